@@ -0,0 +1,27 @@
+#!/bin/bash
+
+# Prints the release feed that `igor upgrade` reads: the version and, per platform, the URL and the SHA-256 checksum
+# of the binary. The binaries are named after their platform, like igor-x86_64-linux.
+#
+# Usage: release-feed.sh <version> <download-url> <binary>...
+
+set -e
+
+VERSION="$1"
+DOWNLOAD_URL="$2"
+shift 2
+
+echo '{'
+echo "  \"version\": \"${VERSION}\","
+echo '  "assets": {'
+SEPARATOR=''
+for BINARY in "$@"
+do
+  NAME="$(basename "${BINARY}")"
+  SHA256="$(sha256sum "${BINARY}" | cut -d ' ' -f 1)"
+  printf '%s    "%s": { "url": "%s/%s", "sha256": "%s" }' "${SEPARATOR}" "${NAME#igor-}" "${DOWNLOAD_URL}" "${NAME}" "${SHA256}"
+  SEPARATOR=$',\n'
+done
+echo
+echo '  }'
+echo '}'
@@ -0,0 +1,77 @@
+use std::path::Path;
+use anyhow::Result;
+use serde::Serialize;
+use crate::report::Report;
+
+/// A prop whose value ended up in a target file, along with the configuration file that provided the value.
+#[derive(Serialize, Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct PropRead {
+    pub niche: String,
+    pub target: String,
+    pub prop: String,
+    pub value: String,
+    pub source: String,
+}
+
+#[derive(Serialize, Debug)]
+struct Audit<'a> {
+    read: &'a [PropRead],
+}
+
+/// Writes the props that the niches of a run recorded to a TOML file, sorted by niche, target file and prop.
+pub fn write(audit_file: &Path, report: &Report) -> Result<()> {
+    let mut reads: Vec<PropRead> = report.niches().iter()
+        .flat_map(|niche_report| niche_report.prop_reads().iter().cloned())
+        .collect();
+    std::fs::write(audit_file, to_toml(&mut reads)?)?;
+    Ok(())
+}
+
+fn to_toml(reads: &mut [PropRead]) -> Result<String> {
+    reads.sort();
+    Ok(toml::to_string(&Audit { read: reads })?)
+}
+
+#[cfg(test)]
+mod test {
+    use indoc::indoc;
+    use super::*;
+
+    #[test]
+    fn audit_to_toml() -> Result<()> {
+        // Given
+        let read = |target: &str, prop: &str, value: &str, source: &str| PropRead {
+            niche: "example".to_string(),
+            target: target.to_string(),
+            prop: prop.to_string(),
+            value: value.to_string(),
+            source: source.to_string(),
+        };
+        let mut reads = vec![
+            read("workshop/mirror.yaml", "frame", "silver", "project:yeth-marthter/example/invar/workshop/mirror+config-@.yaml.toml"),
+            read("workshop/clock.yaml", "sweeper", "Lu Tse", "thundercloud:thundercloud.toml"),
+        ];
+
+        // When
+        let body = to_toml(&mut reads)?;
+
+        // Then
+        let expected = indoc! {r#"
+            [[read]]
+            niche = "example"
+            target = "workshop/clock.yaml"
+            prop = "sweeper"
+            value = "Lu Tse"
+            source = "thundercloud:thundercloud.toml"
+
+            [[read]]
+            niche = "example"
+            target = "workshop/mirror.yaml"
+            prop = "frame"
+            value = "silver"
+            source = "project:yeth-marthter/example/invar/workshop/mirror+config-@.yaml.toml"
+        "#};
+        assert_eq!(body, expected);
+        Ok(())
+    }
+}
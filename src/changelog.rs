@@ -0,0 +1,128 @@
+use anyhow::Result;
+use log::{debug, info};
+use crate::config_model::{GitRemoteConfig, ProjectConfig, UseThundercloudConfig};
+use crate::file_system::{FileSystem, PathType};
+use crate::git;
+use crate::hooks::AllowedCommands;
+use crate::niche::get_thundercloud_directory;
+use crate::path::AbsolutePath;
+use crate::report::Report;
+use crate::run_options::RunOptions;
+use crate::vendor::write_content;
+
+/// The file (relative to the project root) that changelog entries are appended to, unless the project configures another.
+pub const DEFAULT_CHANGELOG_FILE: &str = ".igor/CHANGELOG.md";
+
+/// Describes the thundercloud of a niche for the changelog: the fetch URL and revision of its git remote (with the
+/// commit that is checked out, if the thundercloud is a git clone), the URL and checksum of its download, or else its
+/// directory (relative to the project root, if it is inside the project).
+pub async fn describe_thundercloud<UT: UseThundercloudConfig, FS: FileSystem>(project_root: &AbsolutePath, use_thundercloud: &UT, allowed_commands: &AllowedCommands, fs: &FS) -> Option<String> {
+    if let Some(url) = use_thundercloud.url() {
+        return Some(format!("{url} (sha256 {})", use_thundercloud.sha256().unwrap_or_default()));
+    }
+    let directory = get_thundercloud_directory(project_root, use_thundercloud).ok().flatten();
+    let Some(git_remote) = use_thundercloud.git_remote() else {
+        return directory.map(|directory| {
+            let relative = directory.strip_prefix(project_root.as_path()).unwrap_or(directory.as_path());
+            relative.to_string_lossy().to_string()
+        });
+    };
+    let mut description = format!("{} {}", git_remote.fetch_url(), git_remote.revision());
+    if let Some(directory) = directory {
+        if fs.path_type(&AbsolutePath::new(".git", &directory)).await == PathType::Directory {
+            match git::head_revision(allowed_commands, &directory).await {
+                Ok(commit) => description = format!("{description} ({commit})"),
+                Err(err) => debug!("No commit for thundercloud: {directory:?}: {err:#}"),
+            }
+        }
+    }
+    Some(description)
+}
+
+/// Appends a summary of the run to the changelog of the project: the thunderclouds that were applied and the files that
+/// were changed, per niche. Runs that did not change any files are not recorded, and neither are runs of projects without
+/// a `changelog-file`, unless the run enables the changelog.
+pub async fn append_entry<PC: ProjectConfig, FS: FileSystem>(project_root: &AbsolutePath, project_config: &PC, run_options: &RunOptions, report: &Report, fs: &FS) -> Result<()> {
+    let changelog_file = match project_config.changelog_file() {
+        Some(changelog_file) => changelog_file,
+        None if run_options.changelog() => DEFAULT_CHANGELOG_FILE,
+        None => return Ok(()),
+    };
+    let timestamp = run_options.run_time().format("%Y-%m-%dT%H:%M:%S%:z").to_string();
+    let Some(entry) = changelog_entry(report, &timestamp) else { return Ok(()) };
+    let changelog_path = AbsolutePath::new(changelog_file, project_root);
+    info!("Append to changelog: {changelog_path:?}");
+    let mut content = if fs.path_type(&changelog_path).await == PathType::File {
+        fs.get_content(changelog_path.clone()).await?
+    } else {
+        "# Changelog of generated files\n".to_string()
+    };
+    if !content.ends_with('\n') {
+        content.push('\n');
+    }
+    content.push('\n');
+    content.push_str(&entry);
+    write_content(&changelog_path, &content, fs).await
+}
+
+fn changelog_entry(report: &Report, timestamp: &str) -> Option<String> {
+    let mut entry = format!("## {timestamp}\n");
+    let mut changed = false;
+    for niche_report in report.niches() {
+        let mut written: Vec<&str> = niche_report.written().collect();
+        if written.is_empty() {
+            continue;
+        }
+        changed = true;
+        written.sort();
+        entry.push_str(&format!("\n### {}\n\n", niche_report.name()));
+        if let Some(thundercloud) = niche_report.thundercloud() {
+            entry.push_str(&format!("Thundercloud: {thundercloud}\n\n"));
+        }
+        for path in written {
+            entry.push_str(&format!("- {path}\n"));
+        }
+    }
+    changed.then_some(entry)
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+    use indoc::indoc;
+    use crate::path::test_utils::to_absolute_path;
+    use crate::report::{FileAction, NicheReporter, Reporter};
+    use crate::NicheName;
+    use super::*;
+
+    #[test]
+    fn entry_per_niche() {
+        // Given
+        let reporter = Reporter::default();
+        let niche_reporter = NicheReporter::default();
+        niche_reporter.thundercloud("https://github.com/rustigaan/example-thundercloud.git main".to_string());
+        niche_reporter.file(&AbsolutePath::root(), &to_absolute_path("/workshop/mirror.yaml"), FileAction::Written);
+        niche_reporter.file(&AbsolutePath::root(), &to_absolute_path("/workshop/clock.yaml"), FileAction::Written);
+        niche_reporter.file(&AbsolutePath::root(), &to_absolute_path("/workshop/bench.yaml"), FileAction::Skipped);
+        reporter.add_niche(&NicheName::new("example"), niche_reporter, Duration::from_millis(42), &Ok(()));
+        reporter.add_niche(&NicheName::new("idle"), NicheReporter::default(), Duration::from_millis(7), &Ok(()));
+
+        // When
+        let entry = changelog_entry(&reporter.report(), "2024-05-25T12:00:00+02:00");
+        let unchanged = changelog_entry(&Report::default(), "2024-05-25T12:00:00+02:00");
+
+        // Then
+        let expected = indoc! {"
+            ## 2024-05-25T12:00:00+02:00
+
+            ### example
+
+            Thundercloud: https://github.com/rustigaan/example-thundercloud.git main
+
+            - workshop/clock.yaml
+            - workshop/mirror.yaml
+        "};
+        assert_eq!(entry.as_deref(), Some(expected));
+        assert_eq!(unchanged, None);
+    }
+}
@@ -0,0 +1,238 @@
+use std::path::PathBuf;
+use anyhow::Result;
+use crate::config_model::{NicheTriggers, ProjectConfig, PsychotropicConfig};
+use crate::file_system::{FileSystem, PathType};
+use crate::manifest::{get_manifest_path, Manifest};
+use crate::path::AbsolutePath;
+use crate::thundercloud::BASELINE_DIRECTORY;
+use crate::{get_project_config, get_project_root, NicheName};
+
+pub async fn clean<FS: FileSystem>(project_root_option: Option<PathBuf>, force: bool, fs: &FS) -> Result<()> {
+    let project_root = get_project_root(project_root_option)?;
+    for path in clean_niches(&project_root, force, fs).await? {
+        println!("Removed {path}");
+    }
+    Ok(())
+}
+
+/// Removes the files that Igor generated for all niches of the project, according to their manifests. The baseline of
+/// a niche (see the `merge` setting) goes as well, once none of its generated files remain.
+async fn clean_niches<FS: FileSystem>(project_root: &AbsolutePath, force: bool, fs: &FS) -> Result<Vec<String>> {
+    let project_config = get_project_config(project_root, fs).await?;
+    let niches_directory = project_config.niches_directory();
+    let psychotropic = project_config.psychotropic()?;
+    let mut removed = Vec::new();
+    for niche_triggers in psychotropic.values() {
+        let niche_name = NicheName::new(niche_triggers.name());
+        let manifest_path = get_manifest_path(project_root, &niches_directory, &niche_name);
+        let mut manifest = Manifest::load(&manifest_path, fs).await?;
+        removed.extend(manifest.clean(project_root, force, fs).await?);
+        manifest.save(&manifest_path, fs).await?;
+        let baseline_directory = AbsolutePath::try_new(manifest_path.with_file_name(BASELINE_DIRECTORY))?;
+        if manifest.is_empty() && fs.path_type(&baseline_directory).await == PathType::Directory {
+            fs.remove_dir_all(&baseline_directory).await?;
+            removed.push(baseline_directory.strip_prefix(project_root.as_path())?.to_string_lossy().to_string());
+        }
+    }
+    Ok(removed)
+}
+
+#[cfg(test)]
+mod test {
+    use indoc::indoc;
+    use test_log::test;
+    use crate::config_model::WriteMode;
+    use crate::file_system::{fixture, PathType, TargetFile};
+    use crate::path::test_utils::to_absolute_path;
+    use crate::{application, FunctionRegistry, RunOptions};
+    use super::*;
+
+    #[test(tokio::test)]
+    async fn clean_after_generation() -> Result<()> {
+        // Given
+        let toml_data = indoc! {r#"
+            "CargoCult.toml" = '''
+            [[psychotropic.cues]]
+            name = "example"
+            use-thundercloud = { directory = "{{PROJECT}}/example-thundercloud", invar-defaults = { merge = true } }
+            '''
+
+            [yeth-marthter.example.invar]
+
+            [example-thundercloud]
+            "thundercloud.toml" = """
+            [niche]
+            name = "example"
+            """
+
+            [example-thundercloud.cumulus.workshop]
+            "clock+option-@.yaml" = '''
+            sweeper: Lu Tse
+            '''
+            "mirror+option-@.yaml" = '''
+            frame: wood
+            '''
+        "#};
+        let fs = fixture::from_toml(toml_data)?;
+        let project_root = AbsolutePath::root();
+        application(Some(project_root.to_path_buf()), None, false, &RunOptions::default(), &FunctionRegistry::default(), &fs).await?;
+        let mut mirror = fs.open_target(to_absolute_path("/workshop/mirror.yaml"), WriteMode::Overwrite, false).await?.unwrap();
+        mirror.write_line("frame: silver").await?;
+        mirror.close().await?;
+
+        // When
+        let removed = clean_niches(&project_root, false, &fs).await?;
+
+        // Then
+        assert_eq!(removed, vec!["workshop/clock.yaml".to_string()]);
+        assert_eq!(fs.path_type(&to_absolute_path("/workshop/clock.yaml")).await, PathType::Missing);
+        assert_eq!(fs.get_content(to_absolute_path("/workshop/mirror.yaml")).await?, "frame: silver\n");
+        assert_eq!(fs.path_type(&to_absolute_path("/yeth-marthter/example/baseline/workshop/mirror.yaml")).await, PathType::File);
+
+        // When
+        let removed = clean_niches(&project_root, true, &fs).await?;
+
+        // Then
+        assert_eq!(removed, vec!["workshop/mirror.yaml".to_string(), "yeth-marthter/example/baseline".to_string()]);
+        assert_eq!(fs.path_type(&to_absolute_path("/yeth-marthter/example/manifest.toml")).await, PathType::Missing);
+        assert_eq!(fs.path_type(&to_absolute_path("/yeth-marthter/example/baseline")).await, PathType::Missing);
+        Ok(())
+    }
+
+    #[test(tokio::test)]
+    async fn clean_after_append() -> Result<()> {
+        // Given
+        let toml_data = indoc! {r#"
+            "CargoCult.toml" = '''
+            [[psychotropic.cues]]
+            name = "example"
+            use-thundercloud = { directory = "{{PROJECT}}/example-thundercloud" }
+            '''
+            ".gitignore" = '''
+            node_modules/
+            '''
+
+            [yeth-marthter.example.invar]
+
+            [example-thundercloud]
+            "thundercloud.toml" = """
+            [niche]
+            name = "example"
+            """
+
+            [example-thundercloud.cumulus]
+            "dot_gitignore+append-@" = '''
+            /target
+            '''
+
+            [example-thundercloud.cumulus.workshop]
+            "hosts+append-@" = '''
+            127.0.0.1 workshop
+            '''
+        "#};
+        let fs = fixture::from_toml(toml_data)?;
+        let project_root = AbsolutePath::root();
+        application(Some(project_root.to_path_buf()), None, false, &RunOptions::default(), &FunctionRegistry::default(), &fs).await?;
+
+        // When
+        let removed = clean_niches(&project_root, false, &fs).await?;
+
+        // Then
+        assert_eq!(removed, vec!["workshop/hosts".to_string()]);
+        assert_eq!(fs.get_content(to_absolute_path("/.gitignore")).await?, "node_modules/\n/target\n");
+        assert_eq!(fs.path_type(&to_absolute_path("/workshop/hosts")).await, PathType::Missing);
+        Ok(())
+    }
+
+    #[test(tokio::test)]
+    async fn clean_after_write_mode_append() -> Result<()> {
+        // Given
+        let toml_data = indoc! {r#"
+            "CargoCult.toml" = '''
+            [[psychotropic.cues]]
+            name = "example"
+            use-thundercloud = { directory = "{{PROJECT}}/example-thundercloud", invar-defaults = { write-mode = "Append" } }
+            '''
+
+            [yeth-marthter.example.invar]
+
+            [example-thundercloud]
+            "thundercloud.toml" = """
+            [niche]
+            name = "example"
+            """
+
+            [example-thundercloud.cumulus.workshop]
+            "log+option-@.txt" = '''
+            Clock wound
+            '''
+            "journal+option-@.txt" = '''
+            Mirror polished
+            '''
+
+            [workshop]
+            "log.txt" = '''
+            Glass delivered
+            '''
+        "#};
+        let fs = fixture::from_toml(toml_data)?;
+        let project_root = AbsolutePath::root();
+        application(Some(project_root.to_path_buf()), None, false, &RunOptions::default(), &FunctionRegistry::default(), &fs).await?;
+
+        // When
+        let removed = clean_niches(&project_root, false, &fs).await?;
+
+        // Then
+        assert_eq!(removed, vec!["workshop/journal.txt".to_string()]);
+        assert_eq!(fs.get_content(to_absolute_path("/workshop/log.txt")).await?, "Glass delivered\nClock wound\n");
+        Ok(())
+    }
+
+    #[test(tokio::test)]
+    async fn clean_symlinks() -> Result<()> {
+        // Given
+        let toml_data = indoc! {r#"
+            "CargoCult.toml" = '''
+            [[psychotropic.cues]]
+            name = "example"
+            use-thundercloud = { directory = "{{PROJECT}}/example-thundercloud" }
+            '''
+
+            [yeth-marthter.example.invar]
+
+            [example-thundercloud]
+            "thundercloud.toml" = """
+            [niche]
+            name = "example"
+            """
+
+            [example-thundercloud.cumulus]
+            "current+symlink" = '''
+            releases/v1
+            '''
+            "previous+symlink" = '''
+            releases/v0
+            '''
+
+            [example-thundercloud.cumulus.releases.v1]
+            "clock+option-@.yaml" = '''
+            sweeper: Lu Tse
+            '''
+        "#};
+        let fs = fixture::from_toml(toml_data)?;
+        let project_root = AbsolutePath::root();
+        application(Some(project_root.to_path_buf()), None, false, &RunOptions::default(), &FunctionRegistry::default(), &fs).await?;
+        let manifest = fs.get_content(to_absolute_path("/yeth-marthter/example/manifest.toml")).await?;
+
+        // When
+        let removed = clean_niches(&project_root, false, &fs).await?;
+
+        // Then
+        assert!(manifest.contains(r#"current = "symlink:releases/v1""#), "{manifest}");
+        assert_eq!(removed, vec!["current".to_string(), "previous".to_string(), "releases/v1/clock.yaml".to_string()]);
+        assert_eq!(fs.path_type(&to_absolute_path("/current")).await, PathType::Missing);
+        assert!(fs.symlink_metadata(&to_absolute_path("/previous")).await.is_err());
+        assert_eq!(fs.path_type(&to_absolute_path("/releases/v1")).await, PathType::Directory);
+        Ok(())
+    }
+}
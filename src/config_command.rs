@@ -0,0 +1,282 @@
+use std::path::PathBuf;
+use anyhow::{anyhow, bail, Result};
+use serde::Deserialize;
+use toml_edit::{Array, InlineTable, Item, Table, Value};
+use crate::config_model::document::ConfigDocument;
+use crate::config_model::{project_config, ProjectConfig};
+use crate::file_system::{ConfigFormat, FileSystem, PathType};
+use crate::path::AbsolutePath;
+use crate::niche::substitute_project_directories;
+use crate::vendor::write_content;
+use crate::get_project_root;
+
+/// The cues that a thundercloud suggests for the niches that use it, in `suggested-cues.toml` next to
+/// `thundercloud.toml`. They are copied into the project configuration when a niche is added.
+#[derive(Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+struct SuggestedCues {
+    #[serde(default)]
+    wait_for: Vec<String>,
+    #[serde(default)]
+    features: Vec<String>,
+}
+
+pub async fn config_get<FS: FileSystem>(project_root_option: Option<PathBuf>, key: String, fs: &FS) -> Result<()> {
+    let project_root = get_project_root(project_root_option)?;
+    println!("{}", get_setting(&project_root, &key, fs).await?);
+    Ok(())
+}
+
+pub async fn config_set<FS: FileSystem>(project_root_option: Option<PathBuf>, key: String, value: String, fs: &FS) -> Result<()> {
+    let project_root = get_project_root(project_root_option)?;
+    set_setting(&project_root, &key, &value, fs).await
+}
+
+pub async fn config_add_niche<FS: FileSystem>(project_root_option: Option<PathBuf>, name: String, directory: String, fs: &FS) -> Result<()> {
+    let project_root = get_project_root(project_root_option)?;
+    add_niche(&project_root, &name, &directory, fs).await
+}
+
+/// A component of a key like `psychotropic.cues[example].features`: the name of an item in a table, or an element of
+/// an array, selected by its index or by its `name`.
+#[derive(Debug, Clone, PartialEq)]
+enum KeyComponent {
+    Name(String),
+    Element(String),
+}
+
+fn parse_key(key: &str) -> Result<Vec<KeyComponent>> {
+    let mut components = Vec::new();
+    let mut rest = key;
+    while !rest.is_empty() {
+        if let Some(quoted) = rest.strip_prefix('"') {
+            let end = quoted.find('"').ok_or_else(|| anyhow!("Unterminated quote in key: {key:?}"))?;
+            components.push(KeyComponent::Name(quoted[..end].to_string()));
+            rest = &quoted[end + 1..];
+        } else if let Some(selector) = rest.strip_prefix('[') {
+            let end = selector.find(']').ok_or_else(|| anyhow!("Unterminated bracket in key: {key:?}"))?;
+            components.push(KeyComponent::Element(selector[..end].trim_matches('"').to_string()));
+            rest = &selector[end + 1..];
+        } else {
+            let end = rest.find(['.', '[']).unwrap_or(rest.len());
+            if end == 0 {
+                bail!("Empty component in key: {key:?}");
+            }
+            components.push(KeyComponent::Name(rest[..end].to_string()));
+            rest = &rest[end..];
+        }
+        if let Some(next) = rest.strip_prefix('.') {
+            if next.is_empty() {
+                bail!("Key ends with a dot: {key:?}");
+            }
+            rest = next;
+        } else if !rest.is_empty() && !rest.starts_with('[') {
+            bail!("Invalid key: {key:?}");
+        }
+    }
+    if components.is_empty() {
+        bail!("Empty key");
+    }
+    Ok(components)
+}
+
+/// Converts the components of a key into a path in the document. Elements that are selected by name become the index of
+/// the element with that `name`.
+fn resolve_path(document: &ConfigDocument, components: &[KeyComponent]) -> Result<Vec<String>> {
+    let mut path: Vec<String> = Vec::new();
+    for component in components {
+        match component {
+            KeyComponent::Name(name) => path.push(name.clone()),
+            KeyComponent::Element(index) if index.parse::<usize>().is_ok() => path.push(index.clone()),
+            KeyComponent::Element(name) => {
+                let path_refs: Vec<&str> = path.iter().map(String::as_str).collect();
+                let index = (0..).map_while(|index| document.get(&[path_refs.as_slice(), &[&index.to_string()]].concat()))
+                    .position(|element| element.get("name").and_then(Item::as_str) == Some(name.as_str()))
+                    .ok_or_else(|| anyhow!("No element with name {name:?} in {}", path.join(".")))?;
+                path.push(index.to_string());
+            },
+        }
+    }
+    Ok(path)
+}
+
+async fn load_document<FS: FileSystem>(project_config_path: &AbsolutePath, fs: &FS) -> Result<ConfigDocument> {
+    let source = if fs.path_type(project_config_path).await == PathType::File {
+        fs.get_content(project_config_path.clone()).await?
+    } else {
+        String::new()
+    };
+    ConfigDocument::parse(&source).map_err(|error| anyhow!("{}:{error}", project_config_path.to_string_lossy()))
+}
+
+/// Returns the setting of `CargoCult.toml` at the key. Strings are returned as is, other values and tables as TOML.
+async fn get_setting<FS: FileSystem>(project_root: &AbsolutePath, key: &str, fs: &FS) -> Result<String> {
+    let project_config_path = AbsolutePath::new("CargoCult.toml", project_root);
+    let document = load_document(&project_config_path, fs).await?;
+    let path = resolve_path(&document, &parse_key(key)?)?;
+    let path: Vec<&str> = path.iter().map(String::as_str).collect();
+    let item = document.get(&path).ok_or_else(|| anyhow!("No setting: {key}"))?;
+    let setting = match item {
+        Item::Value(Value::String(string)) => string.value().clone(),
+        Item::Value(value) => value.clone().decorated("", "").to_string(),
+        item => item.to_string().trim_end().to_string(),
+    };
+    Ok(setting)
+}
+
+/// Changes the setting of `CargoCult.toml` at the key, leaving the layout and comments of the rest of the file alone.
+/// The value is parsed as TOML (like `["glass"]` or `true`); anything else is a string. The result must still be a valid
+/// project configuration.
+async fn set_setting<FS: FileSystem>(project_root: &AbsolutePath, key: &str, value: &str, fs: &FS) -> Result<()> {
+    let project_config_path = AbsolutePath::new("CargoCult.toml", project_root);
+    let mut document = load_document(&project_config_path, fs).await?;
+    let path = resolve_path(&document, &parse_key(key)?)?;
+    let path: Vec<&str> = path.iter().map(String::as_str).collect();
+    let value = value.parse::<Value>().unwrap_or_else(|_| Value::from(value));
+    document.set(&path, Item::Value(value))?;
+    let source = document.to_string();
+    project_config::from_str(&source, ConfigFormat::TOML)
+        .and_then(|project_config| project_config.psychotropic().map(|_| ()))
+        .map_err(|error| anyhow!("Setting {key} would make the configuration invalid: {error}"))?;
+    write_content(&project_config_path, &source, fs).await
+}
+
+/// Adds a niche that uses the thundercloud in the directory to the end of the cues in `CargoCult.toml`, with the niches to
+/// wait for and the features that the thundercloud suggests (if any).
+async fn add_niche<FS: FileSystem>(project_root: &AbsolutePath, name: &str, directory: &str, fs: &FS) -> Result<()> {
+    let project_config_path = AbsolutePath::new("CargoCult.toml", project_root);
+    let mut document = load_document(&project_config_path, fs).await?;
+    if resolve_path(&document, &parse_key(&format!("psychotropic.cues[{name:?}]"))?).is_ok() {
+        bail!("Niche already exists: {name}");
+    }
+    let thundercloud_directory = AbsolutePath::new(substitute_project_directories(directory, project_root), project_root);
+    let suggested_cues_path = AbsolutePath::new("suggested-cues.toml", &thundercloud_directory);
+    let suggested_cues: SuggestedCues = if fs.path_type(&suggested_cues_path).await == PathType::File {
+        toml::from_str(&fs.get_content(suggested_cues_path.clone()).await?)
+            .map_err(|error| anyhow!("{}: {error}", suggested_cues_path.to_string_lossy()))?
+    } else {
+        SuggestedCues::default()
+    };
+    let mut use_thundercloud = InlineTable::new();
+    use_thundercloud.insert("directory", Value::from(directory));
+    if !suggested_cues.features.is_empty() {
+        use_thundercloud.insert("features", Value::Array(suggested_cues.features.iter().collect::<Array>()));
+    }
+    let mut cue = Table::new();
+    cue.insert("name", Item::Value(Value::from(name)));
+    if !suggested_cues.wait_for.is_empty() {
+        cue.insert("wait-for", Item::Value(Value::Array(suggested_cues.wait_for.iter().collect::<Array>())));
+    }
+    cue.insert("use-thundercloud", Item::Value(Value::InlineTable(use_thundercloud)));
+    document.push(&["psychotropic", "cues"], cue)?;
+    let source = document.to_string();
+    project_config::from_str(&source, ConfigFormat::TOML)
+        .and_then(|project_config| project_config.psychotropic().map(|_| ()))
+        .map_err(|error| anyhow!("Adding niche {name} would make the configuration invalid: {error}"))?;
+    write_content(&project_config_path, &source, fs).await
+}
+
+#[cfg(test)]
+mod test {
+    use indoc::indoc;
+    use test_log::test;
+    use crate::file_system::fixture;
+    use crate::path::test_utils::to_absolute_path;
+    use super::*;
+
+    #[test]
+    fn parse_keys() -> Result<()> {
+        use KeyComponent::*;
+        assert_eq!(parse_key("psychotropic.cues[example].features")?, vec![
+            Name("psychotropic".to_string()), Name("cues".to_string()), Element("example".to_string()), Name("features".to_string())
+        ]);
+        assert_eq!(parse_key(r#"props."glass.spring"[0]"#)?, vec![
+            Name("props".to_string()), Name("glass.spring".to_string()), Element("0".to_string())
+        ]);
+        assert!(parse_key("psychotropic..cues").is_err());
+        assert!(parse_key("psychotropic.").is_err());
+        Ok(())
+    }
+
+    #[test(tokio::test)]
+    async fn get_and_set_settings() -> Result<()> {
+        // Given
+        let toml_data = indoc! {r#"
+            "CargoCult.toml" = '''
+            niches-directory = "yeth-marthter"
+
+            [[psychotropic.cues]]
+            name = "example"   # Clock parts
+            use-thundercloud = { directory = "{{PROJECT}}/example-thundercloud", features = ["glass"] }
+
+            [[psychotropic.cues]]
+            name = "other"
+            '''
+        "#};
+        let fs = fixture::from_toml(toml_data)?;
+        let project_root = AbsolutePath::root();
+
+        // When
+        set_setting(&project_root, "psychotropic.cues[example].use-thundercloud.features", r#"["glass", "bash_config"]"#, &fs).await?;
+        set_setting(&project_root, "psychotropic.cues[1].wait-for", r#"["example"]"#, &fs).await?;
+        set_setting(&project_root, "niches-directory", "igor-niches", &fs).await?;
+        let invalid = set_setting(&project_root, "psychotropic.cues[other].wait-for", "example", &fs).await;
+        let unknown = set_setting(&project_root, "psychotropic.cues[missing].name", "missing", &fs).await;
+
+        // Then
+        assert_eq!(get_setting(&project_root, "psychotropic.cues[example].use-thundercloud.features", &fs).await?, r#"["glass", "bash_config"]"#);
+        assert_eq!(get_setting(&project_root, "niches-directory", &fs).await?, "igor-niches");
+        assert!(invalid.is_err());
+        assert_eq!(unknown.unwrap_err().to_string(), r#"No element with name "missing" in psychotropic.cues"#);
+        let expected = indoc! {r#"
+            niches-directory = "igor-niches"
+
+            [[psychotropic.cues]]
+            name = "example"   # Clock parts
+            use-thundercloud = { directory = "{{PROJECT}}/example-thundercloud", features = ["glass", "bash_config"] }
+
+            [[psychotropic.cues]]
+            name = "other"
+            wait-for = ["example"]
+        "#};
+        assert_eq!(fs.get_content(to_absolute_path("/CargoCult.toml")).await?, expected);
+        Ok(())
+    }
+
+    #[test(tokio::test)]
+    async fn add_niche_with_suggested_cues() -> Result<()> {
+        // Given
+        let toml_data = indoc! {r#"
+            "CargoCult.toml" = '''
+            [[psychotropic.cues]]
+            name = "default-settings"   # Comes first
+            '''
+
+            [mongo-thundercloud]
+            "suggested-cues.toml" = '''
+            wait-for = ["default-settings"]
+            features = ["replica-set"]
+            '''
+        "#};
+        let fs = fixture::from_toml(toml_data)?;
+        let project_root = AbsolutePath::root();
+
+        // When
+        add_niche(&project_root, "mongo-db", "{{PROJECT}}/mongo-thundercloud", &fs).await?;
+        let again = add_niche(&project_root, "mongo-db", "{{PROJECT}}/mongo-thundercloud", &fs).await;
+
+        // Then
+        assert_eq!(again.unwrap_err().to_string(), "Niche already exists: mongo-db");
+        let expected = indoc! {r#"
+            [[psychotropic.cues]]
+            name = "default-settings"   # Comes first
+
+            [[psychotropic.cues]]
+            name = "mongo-db"
+            wait-for = ["default-settings"]
+            use-thundercloud = { directory = "{{PROJECT}}/mongo-thundercloud", features = ["replica-set"] }
+        "#};
+        assert_eq!(fs.get_content(to_absolute_path("/CargoCult.toml")).await?, expected);
+        Ok(())
+    }
+}
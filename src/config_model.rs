@@ -1,7 +1,7 @@
 #![allow(dead_code)]
 
 pub mod invar_config;
-pub use invar_config::{InvarConfig, WriteMode};
+pub use invar_config::{Anchor, AnchorPosition, CombineFragments, FragmentOrder, InvarConfig, LineEndings, PlaceholderDelimiters, TemplateEngine, WriteMode};
 mod invar_config_data;
 
 pub mod niche_description;
@@ -9,7 +9,7 @@ pub use niche_description::NicheDescription;
 mod niche_description_data;
 
 pub mod thundercloud_config;
-pub use thundercloud_config::ThundercloudConfig;
+pub use thundercloud_config::{PropDeclaration, ThundercloudConfig, UnknownBolts};
 mod thundercloud_config_data;
 
 pub mod niche_config;
@@ -20,7 +20,7 @@ mod thunder_config;
 pub use thunder_config::ThunderConfig;
 mod thunder_config_data;
 
-mod use_thundercloud_config;
+pub mod use_thundercloud_config;
 pub use use_thundercloud_config::{UseThundercloudConfig,OnIncoming};
 mod use_thundercloud_config_data;
 
@@ -29,13 +29,25 @@ pub use git_remote_config::GitRemoteConfig;
 mod git_remote_config_data;
 
 pub mod psychotropic;
-pub use psychotropic::{NicheTriggers, PsychotropicConfig};
+pub use psychotropic::{NicheTriggers, PsychotropicConfig, SharedTargetRole};
 mod psychotropic_data;
 
+mod notify_config;
+pub use notify_config::NotifyConfig;
+mod notify_config_data;
+
+mod scheduler_config;
+pub use scheduler_config::SchedulerConfig;
+mod scheduler_config_data;
+
 pub mod project_config;
-pub use project_config::ProjectConfig;
+pub use project_config::{MissingNiche, NetworkAccess, ProjectConfig};
 mod project_config_data;
 
+pub mod schema;
+
+pub mod document;
+
 use anyhow::Result;
 use std::borrow::Cow;
 use std::fmt::Debug;
@@ -0,0 +1,271 @@
+use std::fmt;
+use std::path::Path;
+use anyhow::{anyhow, bail, Result};
+use toml_edit::{DocumentMut, ImDocument, Item};
+
+/// A position in a configuration file. Lines and columns start at 1.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Position {
+    line: usize,
+    column: usize,
+}
+
+impl Position {
+    /// Converts a byte offset in the source into a position.
+    pub fn at(source: &str, offset: usize) -> Self {
+        let offset = offset.min(source.len());
+        let before = &source[..offset];
+        let line = before.matches('\n').count() + 1;
+        let line_start = before.rfind('\n').map(|index| index + 1).unwrap_or(0);
+        let column = before[line_start..].chars().count() + 1;
+        Position { line, column }
+    }
+
+    pub fn line(&self) -> usize {
+        self.line
+    }
+
+    pub fn column(&self) -> usize {
+        self.column
+    }
+}
+
+impl fmt::Display for Position {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.line, self.column)
+    }
+}
+
+/// A TOML configuration file that remembers where its items are, so that findings can point at exact lines and columns,
+/// and edits leave the layout and comments of the rest of the file untouched.
+///
+/// Paths are lists of keys. A component that is a number selects an element of an array or an array of tables, like
+/// `["psychotropic", "cues", "0", "name"]`. Positions always refer to the source as it was parsed.
+#[derive(Debug, Clone)]
+pub struct ConfigDocument {
+    spanned: ImDocument<String>,
+    document: DocumentMut,
+}
+
+impl ConfigDocument {
+    pub fn parse(source: &str) -> Result<Self> {
+        let spanned = ImDocument::parse(source.to_string())
+            .map_err(|error| {
+                let position = error.span().map(|span| Position::at(source, span.start));
+                match position {
+                    Some(position) => anyhow!("{position}: {}", error.message().trim()),
+                    None => anyhow!("{}", error.message().trim()),
+                }
+            })?;
+        let document = spanned.clone().into_mut();
+        Ok(ConfigDocument { spanned, document })
+    }
+
+    /// Returns the position of the key at the end of the path, or the position of the value if it has no key (like an
+    /// element of an array).
+    pub fn position(&self, path: &[&str]) -> Option<Position> {
+        let (last, parents) = path.split_last()?;
+        let parent = parents.iter().try_fold(self.spanned.as_item(), |item, component| get(item, component))?;
+        let span = match parent.as_table_like().and_then(|table| table.get_key_value(last)) {
+            Some((key, item)) => key.span().or_else(|| item.span()),
+            None => get(parent, last)?.span(),
+        }?;
+        Some(Position::at(self.spanned.raw(), span.start))
+    }
+
+    pub fn get(&self, path: &[&str]) -> Option<&Item> {
+        path.iter().try_fold(self.document.as_item(), |item, component| get(item, component))
+    }
+
+    /// Sets the item at the end of the path. Missing tables along the path are created. A value that replaces another
+    /// value keeps its surrounding whitespace and comments.
+    pub fn set(&mut self, path: &[&str], value: Item) -> Result<()> {
+        let Some((last, parents)) = path.split_last() else { bail!("Cannot replace the whole document") };
+        let mut item = self.document.as_item_mut();
+        for component in parents {
+            item = get_or_insert_table(item, component).ok_or_else(|| anyhow!("Not a table or array: {:?}", component))?;
+        }
+        if item.is_table_like() {
+            let table = item.as_table_like_mut().ok_or_else(|| anyhow!("Not a table: {:?}", parents))?;
+            match table.get_mut(last) {
+                Some(target) => replace(target, value),
+                None => {
+                    table.insert(last, value);
+                }
+            }
+            return Ok(());
+        }
+        let target = component_index(last).and_then(|index| item.get_mut(index))
+            .ok_or_else(|| anyhow!("No such element: {:?}", path))?;
+        replace(target, value);
+        Ok(())
+    }
+
+    /// Appends a table to the array of tables at the end of the path. A missing array (and missing tables along the path)
+    /// are created.
+    pub fn push(&mut self, path: &[&str], table: toml_edit::Table) -> Result<()> {
+        let Some((last, parents)) = path.split_last() else { bail!("Cannot replace the whole document") };
+        let mut item = self.document.as_item_mut();
+        for component in parents {
+            item = get_or_insert_table(item, component).ok_or_else(|| anyhow!("Not a table or array: {:?}", component))?;
+        }
+        let table_like = item.as_table_like_mut().ok_or_else(|| anyhow!("Not a table: {:?}", parents))?;
+        if table_like.get(last).is_none() {
+            table_like.insert(last, Item::ArrayOfTables(toml_edit::ArrayOfTables::new()));
+        }
+        let array = table_like.get_mut(last).and_then(Item::as_array_of_tables_mut)
+            .ok_or_else(|| anyhow!("Not an array of tables: {:?}", path))?;
+        array.push(table);
+        Ok(())
+    }
+
+    /// Removes the item at the end of the path from its table.
+    pub fn remove(&mut self, path: &[&str]) -> Option<Item> {
+        let (last, parents) = path.split_last()?;
+        let mut item = self.document.as_item_mut();
+        for component in parents {
+            item = get_mut(item, component)?;
+        }
+        item.as_table_like_mut()?.remove(last)
+    }
+}
+
+impl fmt::Display for ConfigDocument {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.document)
+    }
+}
+
+/// Adds the path of the configuration file, and the line and column of the offending item, to an error that occurred
+/// while loading a TOML configuration file. Other errors are returned as is.
+pub fn locate_error(path: &Path, source: &str, error: anyhow::Error) -> anyhow::Error {
+    let Some(toml_error) = error.downcast_ref::<toml::de::Error>() else { return error };
+    let Some(span) = toml_error.span() else { return anyhow!("{}: {}", path.display(), toml_error.message().trim()) };
+    anyhow!("{}:{}: {}", path.display(), Position::at(source, span.start), toml_error.message().trim())
+}
+
+fn replace(target: &mut Item, mut value: Item) {
+    if let (Some(old_value), Some(new_value)) = (target.as_value(), value.as_value_mut()) {
+        *new_value.decor_mut() = old_value.decor().clone();
+    }
+    *target = value;
+}
+
+fn component_index(component: &str) -> Option<usize> {
+    component.parse().ok()
+}
+
+fn get<'a>(item: &'a Item, component: &str) -> Option<&'a Item> {
+    if item.is_table_like() {
+        item.get(component)
+    } else {
+        item.get(component_index(component)?)
+    }
+}
+
+fn get_mut<'a>(item: &'a mut Item, component: &str) -> Option<&'a mut Item> {
+    if item.is_table_like() {
+        item.get_mut(component)
+    } else {
+        item.get_mut(component_index(component)?)
+    }
+}
+
+fn get_or_insert_table<'a>(item: &'a mut Item, component: &str) -> Option<&'a mut Item> {
+    if !item.is_table_like() {
+        return item.get_mut(component_index(component)?);
+    }
+    let table = item.as_table_like_mut()?;
+    if table.get(component).is_none() {
+        table.insert(component, Item::Table(toml_edit::Table::new()));
+    }
+    table.get_mut(component)
+}
+
+#[cfg(test)]
+mod test {
+    use indoc::indoc;
+    use toml_edit::value;
+    use super::*;
+
+    const SOURCE: &str = indoc! {r#"
+        niches-directory = "yeth-marthter"
+
+        [[psychotropic.cues]]
+        name = "example"
+        use-thundercloud = { directory = "{{PROJECT}}/example-thundercloud", features = ["glass", "bash_config"] }
+
+        [[psychotropic.cues]]
+        name = "other"   # Waits for the example
+        wait-for = ["example"]
+    "#};
+
+    #[test]
+    fn positions() -> Result<()> {
+        // Given
+        let document = ConfigDocument::parse(SOURCE)?;
+
+        // When
+        let niches_directory = document.position(&["niches-directory"]);
+        let second_name = document.position(&["psychotropic", "cues", "1", "name"]);
+        let feature = document.position(&["psychotropic", "cues", "0", "use-thundercloud", "features", "1"]);
+        let missing = document.position(&["psychotropic", "cues", "2", "name"]);
+
+        // Then
+        assert_eq!(niches_directory, Some(Position { line: 1, column: 1 }));
+        assert_eq!(second_name, Some(Position { line: 8, column: 1 }));
+        assert_eq!(feature.map(|position| position.to_string()), Some("5:91".to_string()));
+        assert_eq!(missing, None);
+        Ok(())
+    }
+
+    #[test]
+    fn surgical_edits() -> Result<()> {
+        // Given
+        let mut document = ConfigDocument::parse(SOURCE)?;
+
+        // When
+        document.set(&["psychotropic", "cues", "1", "name"], value("renamed"))?;
+        document.set(&["scheduler", "jobs"], value(2))?;
+        let removed = document.remove(&["niches-directory"]);
+
+        // Then
+        assert!(removed.is_some());
+        let expected = indoc! {r#"
+
+            [[psychotropic.cues]]
+            name = "example"
+            use-thundercloud = { directory = "{{PROJECT}}/example-thundercloud", features = ["glass", "bash_config"] }
+
+            [[psychotropic.cues]]
+            name = "renamed"   # Waits for the example
+            wait-for = ["example"]
+
+            [scheduler]
+            jobs = 2
+        "#};
+        assert_eq!(document.to_string(), expected);
+        assert_eq!(document.get(&["scheduler", "jobs"]).and_then(Item::as_integer), Some(2));
+        Ok(())
+    }
+
+    #[test]
+    fn parse_error_position() {
+        let result = ConfigDocument::parse("[niche]\nname = \n");
+        let Err(error) = result else { panic!("Expected a parse error") };
+        assert!(error.to_string().starts_with("2:8: "), "Actual error: {error}");
+    }
+
+    #[test]
+    fn locate_deserialization_error() {
+        // Given
+        let source = "niches-directory = \"yeth-marthter\"\n\n[scheduler]\njobs = \"many\"\n";
+        let error = crate::config_model::project_config::from_str(source, crate::file_system::ConfigFormat::TOML).err().unwrap();
+
+        // When
+        let located = locate_error(Path::new("CargoCult.toml"), source, error);
+
+        // Then
+        assert!(located.to_string().starts_with("CargoCult.toml:4:8: "), "Actual error: {located}");
+    }
+}
@@ -1,7 +1,10 @@
 use super::GitRemoteConfig;
+use toml::Table;
+use crate::interpolate::interpolate;
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
-#[derive(Deserialize,Serialize,Debug,Clone)]
+#[derive(Deserialize,Serialize,JsonSchema,Debug,Clone)]
 #[serde(rename_all = "kebab-case")]
 pub struct GitRemoteConfigData {
     fetch_url: String,
@@ -24,4 +27,8 @@ impl GitRemoteConfigData {
             revision: revision.into(),
         }
     }
+
+    pub fn interpolated(&self, props: &Table) -> Self {
+        GitRemoteConfigData::new(interpolate(&self.fetch_url, props), interpolate(&self.revision, props))
+    }
 }
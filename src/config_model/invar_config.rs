@@ -2,17 +2,255 @@ use crate::config_model::invar_config_data::InvarConfigData;
 
 use anyhow::Result;
 use std::borrow::Cow;
+use std::collections::BTreeMap;
 use std::fmt::Debug;
 use ahash::AHashMap;
+use clap::ValueEnum;
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
-use toml::Table;
+use toml::{Table, Value};
 use crate::file_system::ConfigFormat;
 
-#[derive(Deserialize,Serialize,Debug,Clone,Copy,Eq, PartialEq)]
+#[derive(Deserialize,Serialize,JsonSchema,ValueEnum,Debug,Clone,Copy,Eq, PartialEq)]
+#[value(rename_all = "PascalCase")]
 pub enum WriteMode {
     Overwrite,
     WriteNew,
-    Ignore
+    Ignore,
+    /// Adds the generated content to the end of an existing file (or creates the file), for log-like or accumulating
+    /// files.
+    Append
+}
+
+/// The line endings of generated files.
+#[derive(Deserialize,Serialize,JsonSchema,Debug,Clone,Copy,Eq,PartialEq)]
+pub enum LineEndings {
+    #[serde(rename = "LF")]
+    Lf,
+    #[serde(rename = "CRLF")]
+    Crlf,
+}
+
+impl std::fmt::Display for LineEndings {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LineEndings::Lf => write!(f, "LF"),
+            LineEndings::Crlf => write!(f, "CRLF"),
+        }
+    }
+}
+
+/// The template engine that renders options: Igor's own line-based placeholders, fragments and directives, or Tera
+/// (if Igor is built with feature `tera`).
+#[derive(Deserialize,Serialize,JsonSchema,Debug,Clone,Copy,Eq,PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum TemplateEngine {
+    Igor,
+    Tera,
+}
+
+impl std::fmt::Display for TemplateEngine {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TemplateEngine::Igor => write!(f, "igor"),
+            TemplateEngine::Tera => write!(f, "tera"),
+        }
+    }
+}
+
+/// What to do when several fragments fill the same placeholder: let fragments in the invar replace fragments in the
+/// cumulus, or include all of them, those of the cumulus first.
+#[derive(Deserialize,Serialize,JsonSchema,Debug,Clone,Copy,Default,Eq,PartialEq)]
+pub enum CombineFragments {
+    #[default]
+    Replace,
+    Concatenate,
+}
+
+impl std::fmt::Display for CombineFragments {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{self:?}")
+    }
+}
+
+/// Where a fragment goes relative to the line that matches its anchor.
+#[derive(Deserialize,Serialize,JsonSchema,Debug,Clone,Copy,Default,Eq,PartialEq)]
+pub enum AnchorPosition {
+    Before,
+    #[default]
+    After,
+}
+
+impl std::fmt::Display for AnchorPosition {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{self:?}")
+    }
+}
+
+/// A regular expression that locates the line next to which a fragment is inserted, for target files that cannot carry
+/// fragment markers. Only the first line that matches counts.
+#[derive(Deserialize,Serialize,JsonSchema,Debug,Clone,Eq,PartialEq)]
+pub struct Anchor {
+    pattern: String,
+    #[serde(default)]
+    position: AnchorPosition,
+}
+
+impl Anchor {
+    pub fn new<S: Into<String>>(pattern: S, position: AnchorPosition) -> Self {
+        Anchor { pattern: pattern.into(), position }
+    }
+    pub fn pattern(&self) -> &str {
+        &self.pattern
+    }
+    pub fn position(&self) -> AnchorPosition {
+        self.position
+    }
+
+    fn to_value(&self) -> Value {
+        let mut table = Table::new();
+        table.insert("pattern".to_string(), Value::String(self.pattern.clone()));
+        table.insert("position".to_string(), Value::String(self.position.to_string()));
+        Value::Table(table)
+    }
+}
+
+/// Settings for the target files that match a glob pattern, so that a single configuration covers many files. The
+/// pattern is relative to the directory of the configuration file that contains the rule (or to the project root for
+/// invar defaults).
+#[derive(Deserialize,Serialize,JsonSchema,Debug,Clone,Eq,PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub struct InvarRule {
+    glob: String,
+    write_mode: Option<WriteMode>,
+    interpolate: Option<bool>,
+    executable: Option<bool>,
+}
+
+impl InvarRule {
+    pub fn glob(&self) -> &str {
+        &self.glob
+    }
+    pub fn write_mode(&self) -> Option<WriteMode> {
+        self.write_mode
+    }
+    pub fn interpolate(&self) -> Option<bool> {
+        self.interpolate
+    }
+    pub fn executable(&self) -> Option<bool> {
+        self.executable
+    }
+    /// The same rule for a pattern that is relative to a parent directory.
+    pub fn in_directory(&self, directory: &str) -> Self {
+        if directory.is_empty() {
+            return self.clone();
+        }
+        InvarRule { glob: format!("{}/{}", directory.trim_end_matches('/'), self.glob), ..self.clone() }
+    }
+
+    fn to_value(&self) -> Value {
+        let mut table = Table::new();
+        table.insert("glob".to_string(), Value::String(self.glob.clone()));
+        if let Some(write_mode) = self.write_mode {
+            table.insert("write-mode".to_string(), Value::String(format!("{write_mode:?}")));
+        }
+        if let Some(interpolate) = self.interpolate {
+            table.insert("interpolate".to_string(), Value::Boolean(interpolate));
+        }
+        if let Some(executable) = self.executable {
+            table.insert("executable".to_string(), Value::Boolean(executable));
+        }
+        Value::Table(table)
+    }
+}
+
+/// The position of a fragment among the fragments that fill the same placeholder: a number (lower numbers come first,
+/// fragments without an order have 0), `first` or `last`.
+#[derive(Deserialize,Serialize,JsonSchema,Debug,Clone,Copy,Eq,PartialEq)]
+#[serde(untagged)]
+pub enum FragmentOrder {
+    Position(i64),
+    Named(NamedFragmentOrder),
+}
+
+#[derive(Deserialize,Serialize,JsonSchema,Debug,Clone,Copy,Eq,PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum NamedFragmentOrder {
+    First,
+    Last,
+}
+
+impl FragmentOrder {
+    /// Fragments are sorted by this key: `first` before all numbers and `last` after all numbers.
+    pub fn sort_key(&self) -> (i8, i64) {
+        match self {
+            FragmentOrder::Named(NamedFragmentOrder::First) => (-1, 0),
+            FragmentOrder::Position(position) => (0, *position),
+            FragmentOrder::Named(NamedFragmentOrder::Last) => (1, 0),
+        }
+    }
+
+    fn to_value(self) -> Value {
+        match self {
+            FragmentOrder::Position(position) => Value::Integer(position),
+            order => Value::String(order.to_string()),
+        }
+    }
+}
+
+impl Default for FragmentOrder {
+    fn default() -> Self {
+        FragmentOrder::Position(0)
+    }
+}
+
+impl std::str::FromStr for FragmentOrder {
+    type Err = anyhow::Error;
+
+    fn from_str(order: &str) -> Result<Self> {
+        match order {
+            "first" => Ok(FragmentOrder::Named(NamedFragmentOrder::First)),
+            "last" => Ok(FragmentOrder::Named(NamedFragmentOrder::Last)),
+            _ => order.parse().map(FragmentOrder::Position).map_err(|_| anyhow::anyhow!("Invalid fragment order: {order:?}")),
+        }
+    }
+}
+
+impl std::fmt::Display for FragmentOrder {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FragmentOrder::Position(position) => write!(f, "{position}"),
+            FragmentOrder::Named(NamedFragmentOrder::First) => write!(f, "first"),
+            FragmentOrder::Named(NamedFragmentOrder::Last) => write!(f, "last"),
+        }
+    }
+}
+
+#[derive(Deserialize,Serialize,JsonSchema,Debug,Clone,Eq,PartialEq,Hash)]
+pub struct PlaceholderDelimiters {
+    open: String,
+    close: String,
+}
+
+impl PlaceholderDelimiters {
+    pub fn new<S: Into<String>>(open: S, close: S) -> Self {
+        PlaceholderDelimiters { open: open.into(), close: close.into() }
+    }
+    pub fn open(&self) -> &str {
+        &self.open
+    }
+    pub fn close(&self) -> &str {
+        &self.close
+    }
+}
+
+/// Lines of options and fragments that start with this prefix (after leading whitespace) are left out of generated files.
+pub const DEFAULT_TEMPLATE_COMMENT_PREFIX: &str = "#igor#";
+
+impl Default for PlaceholderDelimiters {
+    fn default() -> Self {
+        PlaceholderDelimiters::new("{{", "}}")
+    }
 }
 
 pub trait InvarConfig : Default + Clone + Debug + Send + Sync + Sized {
@@ -26,17 +264,153 @@ pub trait InvarConfig : Default + Clone + Debug + Send + Sync + Sized {
     fn with_interpolate(&self, interpolate: bool) -> Cow<Self>;
     fn interpolate(&self) -> bool;
     fn interpolate_option(&self) -> Option<bool>;
+    fn with_interpolate_strict_option(&self, interpolate_strict: Option<bool>) -> Cow<'_, Self>;
+    /// Whether generation fails on placeholders without a value, instead of leaving them as they are.
+    fn interpolate_strict(&self) -> bool;
+    fn interpolate_strict_option(&self) -> Option<bool>;
     fn with_props_option(&self, props: Option<Table>) -> Cow<Self>;
     fn with_props(&self, props: Table) -> Cow<Self>;
     fn props(&self) -> Cow<Table>;
     fn props_option(&self) -> &Option<Table>;
+    fn with_props_exec_option(&self, props_exec: Option<Table>) -> Cow<'_, Self>;
+    /// Shell commands by prop name. Their output becomes the value of the prop.
+    fn props_exec(&self) -> Cow<'_, Table>;
+    fn props_exec_option(&self) -> &Option<Table>;
     fn string_props(&self) -> AHashMap<String,String>;
+    fn with_placeholder_delimiters_option(&self, placeholder_delimiters: Option<PlaceholderDelimiters>) -> Cow<'_, Self>;
+    fn placeholder_delimiters(&self) -> Cow<'_, PlaceholderDelimiters>;
+    fn placeholder_delimiters_option(&self) -> Option<PlaceholderDelimiters>;
+    fn with_executable_option(&self, executable: Option<bool>) -> Cow<'_, Self>;
+    /// Whether generated files are executable. If unset, they are executable if the file of the option is.
+    fn executable_option(&self) -> Option<bool>;
+    fn with_stable_random_option(&self, stable_random: Option<bool>) -> Cow<'_, Self>;
+    /// Whether random placeholders (like `{{uuid}}`) get the same values in every run, based on a seed of the niche.
+    fn stable_random(&self) -> bool;
+    fn stable_random_option(&self) -> Option<bool>;
+    fn with_template_comment_prefix_option(&self, template_comment_prefix: Option<String>) -> Cow<'_, Self>;
+    /// Lines of options and fragments that start with this prefix are template comments. An empty prefix disables them.
+    fn template_comment_prefix(&self) -> &str;
+    fn template_comment_prefix_option(&self) -> Option<String>;
+    fn with_line_endings_option(&self, line_endings: Option<LineEndings>) -> Cow<'_, Self>;
+    /// The line endings of generated files, regardless of the line endings of options and fragments.
+    fn line_endings(&self) -> LineEndings;
+    fn line_endings_option(&self) -> Option<LineEndings>;
+    fn with_engine_option(&self, engine: Option<TemplateEngine>) -> Cow<'_, Self>;
+    fn engine(&self) -> TemplateEngine;
+    fn engine_option(&self) -> Option<TemplateEngine>;
+    fn with_combine_fragments_option(&self, combine_fragments: Option<CombineFragments>) -> Cow<'_, Self>;
+    /// What to do when the cumulus and the invar (or several files) provide fragments for the same placeholder.
+    fn combine_fragments(&self) -> CombineFragments;
+    fn combine_fragments_option(&self) -> Option<CombineFragments>;
+    fn with_fragment_order_option(&self, fragment_order: Option<BTreeMap<String, FragmentOrder>>) -> Cow<'_, Self>;
+    /// The order of fragments by file name (optionally prefixed with `thundercloud:` or `project:`), on top of the order in their names.
+    fn fragment_order(&self) -> BTreeMap<String, FragmentOrder>;
+    fn fragment_order_option(&self) -> Option<BTreeMap<String, FragmentOrder>>;
+    fn with_keep_markers_option(&self, keep_markers: Option<bool>) -> Cow<'_, Self>;
+    /// Whether the `==== BEGIN/END FRAGMENT ====` marker lines are kept in generated files (default `true`).
+    fn keep_markers(&self) -> bool;
+    fn keep_markers_option(&self) -> Option<bool>;
+    fn with_anchors_option(&self, anchors: Option<BTreeMap<String, Anchor>>) -> Cow<'_, Self>;
+    /// Anchors by placeholder (like `@-deps`) for fragments that are inserted next to a matching line instead of at a placeholder.
+    fn anchors(&self) -> BTreeMap<String, Anchor>;
+    fn anchors_option(&self) -> Option<BTreeMap<String, Anchor>>;
+    fn with_marker_syntax_option(&self, marker_syntax: Option<String>) -> Cow<'_, Self>;
+    /// The syntax of fragment markers, like `<!-- ... -->`, where `...` stands for `BEGIN FRAGMENT feature-qualifier`.
+    fn marker_syntax_option(&self) -> Option<String>;
+    fn with_backup_option(&self, backup: Option<bool>) -> Cow<'_, Self>;
+    /// Whether an existing target file is copied to `<name>.bak` before it is overwritten.
+    fn backup(&self) -> bool;
+    fn backup_option(&self) -> Option<bool>;
+    fn with_merge_option(&self, merge: Option<bool>) -> Cow<'_, Self>;
+    /// Whether changes in an existing target file since it was last generated are merged with the changes in the
+    /// generated content, instead of being overwritten.
+    fn merge(&self) -> bool;
+    fn merge_option(&self) -> Option<bool>;
+    fn with_mode_option(&self, mode: Option<String>) -> Cow<'_, Self>;
+    /// The permission bits of target files, from an octal number like `"0600"`. They take precedence over `executable`.
+    fn mode(&self) -> Result<Option<u32>>;
+    fn mode_option(&self) -> Option<String>;
+    /// Adds rules after the rules that are already there. Rules that are already there are not added again.
+    fn with_rules_option(&self, rules: Option<Vec<InvarRule>>) -> Cow<'_, Self>;
+    /// Settings for the target files that match a glob pattern. Later rules win over earlier rules.
+    fn rules(&self) -> Vec<InvarRule>;
+    fn rules_option(&self) -> Option<Vec<InvarRule>>;
+    /// The same configuration with the patterns of its rules relative to a parent directory.
+    fn with_rules_in_directory(&self, directory: &str) -> Cow<'_, Self>;
+    /// Settings that apply to target files with a given extension (without the dot), on top of these settings.
+    fn by_extension(&self) -> Cow<'_, BTreeMap<String, Self>>;
 }
 
 pub fn from_str(body: &str, config_format: ConfigFormat) -> Result<impl InvarConfig> {
     InvarConfigData::from_str(body, config_format)
 }
 
+pub fn empty() -> impl InvarConfig {
+    InvarConfigData::new()
+}
+
+/// Renders the effective values of an invar configuration (including defaults) as a TOML table.
+pub fn to_table<IC: InvarConfig>(invar_config: &IC) -> Table {
+    let mut table = Table::new();
+    table.insert("write-mode".to_string(), Value::String(format!("{:?}", invar_config.write_mode())));
+    table.insert("interpolate".to_string(), Value::Boolean(invar_config.interpolate()));
+    table.insert("props".to_string(), Value::Table(invar_config.props().into_owned()));
+    let placeholder_delimiters = invar_config.placeholder_delimiters();
+    let mut delimiters_table = Table::new();
+    delimiters_table.insert("open".to_string(), Value::String(placeholder_delimiters.open().to_string()));
+    delimiters_table.insert("close".to_string(), Value::String(placeholder_delimiters.close().to_string()));
+    table.insert("placeholder-delimiters".to_string(), Value::Table(delimiters_table));
+    if let Some(interpolate_strict) = invar_config.interpolate_strict_option() {
+        table.insert("interpolate-strict".to_string(), Value::Boolean(interpolate_strict));
+    }
+    if let Some(executable) = invar_config.executable_option() {
+        table.insert("executable".to_string(), Value::Boolean(executable));
+    }
+    if let Some(stable_random) = invar_config.stable_random_option() {
+        table.insert("stable-random".to_string(), Value::Boolean(stable_random));
+    }
+    if let Some(template_comment_prefix) = invar_config.template_comment_prefix_option() {
+        table.insert("template-comment-prefix".to_string(), Value::String(template_comment_prefix));
+    }
+    if let Some(line_endings) = invar_config.line_endings_option() {
+        table.insert("line-endings".to_string(), Value::String(line_endings.to_string()));
+    }
+    if let Some(props_exec) = invar_config.props_exec_option() {
+        table.insert("props-exec".to_string(), Value::Table(props_exec.clone()));
+    }
+    if let Some(engine) = invar_config.engine_option() {
+        table.insert("engine".to_string(), Value::String(engine.to_string()));
+    }
+    if let Some(combine_fragments) = invar_config.combine_fragments_option() {
+        table.insert("combine-fragments".to_string(), Value::String(combine_fragments.to_string()));
+    }
+    if let Some(fragment_order) = invar_config.fragment_order_option() {
+        table.insert("fragment-order".to_string(), Value::Table(fragment_order.iter().map(|(name, order)| (name.clone(), order.to_value())).collect()));
+    }
+    if let Some(keep_markers) = invar_config.keep_markers_option() {
+        table.insert("keep-markers".to_string(), Value::Boolean(keep_markers));
+    }
+    if let Some(anchors) = invar_config.anchors_option() {
+        table.insert("anchors".to_string(), Value::Table(anchors.iter().map(|(placeholder, anchor)| (placeholder.clone(), anchor.to_value())).collect()));
+    }
+    if let Some(marker_syntax) = invar_config.marker_syntax_option() {
+        table.insert("marker-syntax".to_string(), Value::String(marker_syntax));
+    }
+    if let Some(backup) = invar_config.backup_option() {
+        table.insert("backup".to_string(), Value::Boolean(backup));
+    }
+    if let Some(merge) = invar_config.merge_option() {
+        table.insert("merge".to_string(), Value::Boolean(merge));
+    }
+    if let Some(mode) = invar_config.mode_option() {
+        table.insert("mode".to_string(), Value::String(mode));
+    }
+    if let Some(rules) = invar_config.rules_option() {
+        table.insert("rules".to_string(), Value::Array(rules.iter().map(InvarRule::to_value).collect()));
+    }
+    table
+}
+
 pub fn invar_config_or_default<IC: InvarConfig + Default>(option: &Option<IC>) -> Cow<IC> {
     if let Some(invar_defaults) = option {
         Cow::Borrowed(invar_defaults)
@@ -58,4 +432,13 @@ mod test {
         assert_eq!(invar_config.props(), Cow::Owned(Table::new())); // Default value
         Ok(())
     }
+
+    #[test]
+    fn invar_config_to_table() -> Result<()> {
+        let toml_source = "interpolate = false\n[props]\nsweeper = \"Lu Tse\"\n";
+        let invar_config = from_str(toml_source, ConfigFormat::TOML)?;
+        let expected = "interpolate = false\nwrite-mode = \"Overwrite\"\n\n[placeholder-delimiters]\nclose = \"}}\"\nopen = \"{{\"\n\n[props]\nsweeper = \"Lu Tse\"\n";
+        assert_eq!(toml::to_string(&to_table(&invar_config))?, expected);
+        Ok(())
+    }
 }
@@ -1,23 +1,50 @@
 use crate::config_model::invar_config::*;
 use std::borrow::Cow;
+use std::collections::BTreeMap;
 use ahash::AHashMap;
-use anyhow::Result;
+use anyhow::{anyhow, Result};
 use log::debug;
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use toml::{Table, Value};
 use crate::file_system::ConfigFormat;
+use crate::interpolate::scalar_to_string;
 
-#[derive(Deserialize,Serialize,Debug,Clone)]
+#[derive(Deserialize,Serialize,JsonSchema,Debug,Clone)]
 #[serde(rename_all = "kebab-case")]
 pub struct InvarConfigData {
     write_mode: Option<WriteMode>,
     interpolate: Option<bool>,
+    interpolate_strict: Option<bool>,
+    #[schemars(with = "Option<serde_json::Map<String, serde_json::Value>>")]
     props: Option<Table>,
+    #[schemars(with = "Option<std::collections::BTreeMap<String, String>>")]
+    props_exec: Option<Table>,
+    placeholder_delimiters: Option<PlaceholderDelimiters>,
+    executable: Option<bool>,
+    stable_random: Option<bool>,
+    template_comment_prefix: Option<String>,
+    line_endings: Option<LineEndings>,
+    engine: Option<TemplateEngine>,
+    combine_fragments: Option<CombineFragments>,
+    fragment_order: Option<BTreeMap<String, FragmentOrder>>,
+    keep_markers: Option<bool>,
+    anchors: Option<BTreeMap<String, Anchor>>,
+    marker_syntax: Option<String>,
+    backup: Option<bool>,
+    merge: Option<bool>,
+    mode: Option<String>,
+    rules: Option<Vec<InvarRule>>,
+    by_extension: Option<BTreeMap<String, InvarConfigData>>,
 }
 
 impl InvarConfigData {
     pub fn new() -> InvarConfigData {
-        InvarConfigData { write_mode: None, interpolate: None, props: Some(Table::new()) }
+        InvarConfigData { props: Some(Table::new()), ..InvarConfigData::none() }
+    }
+
+    fn none() -> InvarConfigData {
+        InvarConfigData { write_mode: None, interpolate: None, interpolate_strict: None, props: None, props_exec: None, placeholder_delimiters: None, executable: None, stable_random: None, template_comment_prefix: None, line_endings: None, engine: None, combine_fragments: None, fragment_order: None, keep_markers: None, anchors: None, marker_syntax: None, backup: None, merge: None, mode: None, rules: None, by_extension: None }
     }
 }
 
@@ -62,17 +89,53 @@ impl InvarConfig for InvarConfigData {
         debug!("Write mode: {:?} -> {:?} ({:?})", self.write_mode, &write_mode, dirty);
         let (interpolate, dirty) = merge_property(self.interpolate, invar_config.interpolate_option(), dirty);
         debug!("Interpolate: {:?} -> {:?} ({:?})", self.interpolate, &interpolate, dirty);
+        let (interpolate_strict, dirty) = merge_property(self.interpolate_strict, invar_config.interpolate_strict_option(), dirty);
+        debug!("Interpolate strict: {:?} -> {:?} ({:?})", self.interpolate_strict, &interpolate_strict, dirty);
         let (props, dirty) = merge_props(&self.props, &invar_config.props_option(), dirty);
         debug!("Props ({:?})", dirty);
+        let (props_exec, dirty) = merge_props_exec(&self.props_exec, invar_config.props_exec_option(), dirty);
+        debug!("Props exec ({:?})", dirty);
+        let (placeholder_delimiters, dirty) = merge_cloned_property(&self.placeholder_delimiters, invar_config.placeholder_delimiters_option(), dirty);
+        debug!("Placeholder delimiters: {:?} -> {:?} ({:?})", self.placeholder_delimiters, &placeholder_delimiters, dirty);
+        let (executable, dirty) = merge_property(self.executable, invar_config.executable_option(), dirty);
+        debug!("Executable: {:?} -> {:?} ({:?})", self.executable, &executable, dirty);
+        let (stable_random, dirty) = merge_property(self.stable_random, invar_config.stable_random_option(), dirty);
+        debug!("Stable random: {:?} -> {:?} ({:?})", self.stable_random, &stable_random, dirty);
+        let (template_comment_prefix, dirty) = merge_cloned_property(&self.template_comment_prefix, invar_config.template_comment_prefix_option(), dirty);
+        debug!("Template comment prefix: {:?} -> {:?} ({:?})", self.template_comment_prefix, &template_comment_prefix, dirty);
+        let (line_endings, dirty) = merge_property(self.line_endings, invar_config.line_endings_option(), dirty);
+        debug!("Line endings: {:?} -> {:?} ({:?})", self.line_endings, &line_endings, dirty);
+        let (engine, dirty) = merge_property(self.engine, invar_config.engine_option(), dirty);
+        debug!("Engine: {:?} -> {:?} ({:?})", self.engine, &engine, dirty);
+        let (combine_fragments, dirty) = merge_property(self.combine_fragments, invar_config.combine_fragments_option(), dirty);
+        debug!("Combine fragments: {:?} -> {:?} ({:?})", self.combine_fragments, &combine_fragments, dirty);
+        let (fragment_order, dirty) = merge_cloned_property(&self.fragment_order, invar_config.fragment_order_option(), dirty);
+        debug!("Fragment order: {:?} -> {:?} ({:?})", self.fragment_order, &fragment_order, dirty);
+        let (keep_markers, dirty) = merge_property(self.keep_markers, invar_config.keep_markers_option(), dirty);
+        debug!("Keep markers: {:?} -> {:?} ({:?})", self.keep_markers, &keep_markers, dirty);
+        let (anchors, dirty) = merge_cloned_property(&self.anchors, invar_config.anchors_option(), dirty);
+        debug!("Anchors: {:?} -> {:?} ({:?})", self.anchors, &anchors, dirty);
+        let (marker_syntax, dirty) = merge_cloned_property(&self.marker_syntax, invar_config.marker_syntax_option(), dirty);
+        debug!("Marker syntax: {:?} -> {:?} ({:?})", self.marker_syntax, &marker_syntax, dirty);
+        let (backup, dirty) = merge_property(self.backup, invar_config.backup_option(), dirty);
+        debug!("Backup: {:?} -> {:?} ({:?})", self.backup, &backup, dirty);
+        let (merge, dirty) = merge_property(self.merge, invar_config.merge_option(), dirty);
+        debug!("Merge: {:?} -> {:?} ({:?})", self.merge, &merge, dirty);
+        let (mode, dirty) = merge_cloned_property(&self.mode, invar_config.mode_option(), dirty);
+        debug!("Mode: {:?} -> {:?} ({:?})", self.mode, &mode, dirty);
+        let (rules, dirty) = merge_rules(&self.rules, invar_config.rules_option(), dirty);
+        debug!("Rules: {:?} -> {:?} ({:?})", self.rules, &rules, dirty);
+        let (by_extension, dirty) = merge_by_extension(&self.by_extension, invar_config.by_extension().as_ref(), dirty);
+        debug!("By extension ({:?})", dirty);
         if dirty {
-            Cow::Owned(InvarConfigData { write_mode, interpolate, props: Some(props.into_owned()) })
+            Cow::Owned(InvarConfigData { write_mode, interpolate, interpolate_strict, props: Some(props.into_owned()), props_exec, placeholder_delimiters, executable, stable_random, template_comment_prefix, line_endings, engine, combine_fragments, fragment_order, keep_markers, anchors, marker_syntax, backup, merge, mode, rules, by_extension })
         } else {
             Cow::Borrowed(self)
         }
     }
 
     fn with_write_mode_option(&self, write_mode: Option<WriteMode>) -> Cow<Self> {
-        let invar_config = InvarConfigData { write_mode, interpolate: None, props: None };
+        let invar_config = InvarConfigData { write_mode, ..InvarConfigData::none() };
         self.with_invar_config(invar_config)
     }
 
@@ -89,7 +152,7 @@ impl InvarConfig for InvarConfigData {
     }
 
     fn with_interpolate_option(&self, interpolate: Option<bool>) -> Cow<Self> {
-        let invar_config = InvarConfigData { write_mode: None, interpolate, props: None };
+        let invar_config = InvarConfigData { interpolate, ..InvarConfigData::none() };
         self.with_invar_config(invar_config)
     }
 
@@ -105,8 +168,21 @@ impl InvarConfig for InvarConfigData {
         self.interpolate
     }
 
+    fn with_interpolate_strict_option(&self, interpolate_strict: Option<bool>) -> Cow<'_, Self> {
+        let invar_config = InvarConfigData { interpolate_strict, ..InvarConfigData::none() };
+        self.with_invar_config(invar_config)
+    }
+
+    fn interpolate_strict(&self) -> bool {
+        self.interpolate_strict.unwrap_or(false)
+    }
+
+    fn interpolate_strict_option(&self) -> Option<bool> {
+        self.interpolate_strict
+    }
+
     fn with_props_option(&self, props: Option<Table>) -> Cow<Self> {
-        let invar_config = InvarConfigData { write_mode: None, interpolate: None, props };
+        let invar_config = InvarConfigData { props, ..InvarConfigData::none() };
         self.with_invar_config(invar_config)
     }
 
@@ -122,9 +198,227 @@ impl InvarConfig for InvarConfigData {
         &self.props
     }
 
+    fn with_props_exec_option(&self, props_exec: Option<Table>) -> Cow<'_, Self> {
+        let invar_config = InvarConfigData { props_exec, ..InvarConfigData::none() };
+        self.with_invar_config(invar_config)
+    }
+
+    fn props_exec(&self) -> Cow<'_, Table> {
+        self.props_exec.as_ref().map(Cow::Borrowed).unwrap_or_default()
+    }
+
+    fn props_exec_option(&self) -> &Option<Table> {
+        &self.props_exec
+    }
+
     fn string_props(&self) -> AHashMap<String,String> {
         to_string_map(self.props().as_ref())
     }
+
+    fn with_placeholder_delimiters_option(&self, placeholder_delimiters: Option<PlaceholderDelimiters>) -> Cow<'_, Self> {
+        let invar_config = InvarConfigData { placeholder_delimiters, ..InvarConfigData::none() };
+        self.with_invar_config(invar_config)
+    }
+
+    fn placeholder_delimiters(&self) -> Cow<'_, PlaceholderDelimiters> {
+        self.placeholder_delimiters.as_ref().map(Cow::Borrowed).unwrap_or_default()
+    }
+
+    fn placeholder_delimiters_option(&self) -> Option<PlaceholderDelimiters> {
+        self.placeholder_delimiters.clone()
+    }
+
+    fn with_executable_option(&self, executable: Option<bool>) -> Cow<'_, Self> {
+        let invar_config = InvarConfigData { executable, ..InvarConfigData::none() };
+        self.with_invar_config(invar_config)
+    }
+
+    fn executable_option(&self) -> Option<bool> {
+        self.executable
+    }
+
+    fn with_stable_random_option(&self, stable_random: Option<bool>) -> Cow<'_, Self> {
+        let invar_config = InvarConfigData { stable_random, ..InvarConfigData::none() };
+        self.with_invar_config(invar_config)
+    }
+
+    fn stable_random(&self) -> bool {
+        self.stable_random.unwrap_or(false)
+    }
+
+    fn stable_random_option(&self) -> Option<bool> {
+        self.stable_random
+    }
+
+    fn with_template_comment_prefix_option(&self, template_comment_prefix: Option<String>) -> Cow<'_, Self> {
+        let invar_config = InvarConfigData { template_comment_prefix, ..InvarConfigData::none() };
+        self.with_invar_config(invar_config)
+    }
+
+    fn template_comment_prefix(&self) -> &str {
+        self.template_comment_prefix.as_deref().unwrap_or(DEFAULT_TEMPLATE_COMMENT_PREFIX)
+    }
+
+    fn template_comment_prefix_option(&self) -> Option<String> {
+        self.template_comment_prefix.clone()
+    }
+
+    fn with_line_endings_option(&self, line_endings: Option<LineEndings>) -> Cow<'_, Self> {
+        let invar_config = InvarConfigData { line_endings, ..InvarConfigData::none() };
+        self.with_invar_config(invar_config)
+    }
+
+    fn line_endings(&self) -> LineEndings {
+        self.line_endings.unwrap_or(LineEndings::Lf)
+    }
+
+    fn line_endings_option(&self) -> Option<LineEndings> {
+        self.line_endings
+    }
+
+    fn with_engine_option(&self, engine: Option<TemplateEngine>) -> Cow<'_, Self> {
+        let invar_config = InvarConfigData { engine, ..InvarConfigData::none() };
+        self.with_invar_config(invar_config)
+    }
+
+    fn engine(&self) -> TemplateEngine {
+        self.engine.unwrap_or(TemplateEngine::Igor)
+    }
+
+    fn engine_option(&self) -> Option<TemplateEngine> {
+        self.engine
+    }
+
+    fn with_combine_fragments_option(&self, combine_fragments: Option<CombineFragments>) -> Cow<'_, Self> {
+        let invar_config = InvarConfigData { combine_fragments, ..InvarConfigData::none() };
+        self.with_invar_config(invar_config)
+    }
+
+    fn combine_fragments(&self) -> CombineFragments {
+        self.combine_fragments.unwrap_or_default()
+    }
+
+    fn combine_fragments_option(&self) -> Option<CombineFragments> {
+        self.combine_fragments
+    }
+
+    fn with_fragment_order_option(&self, fragment_order: Option<BTreeMap<String, FragmentOrder>>) -> Cow<'_, Self> {
+        let invar_config = InvarConfigData { fragment_order, ..InvarConfigData::none() };
+        self.with_invar_config(invar_config)
+    }
+
+    fn fragment_order(&self) -> BTreeMap<String, FragmentOrder> {
+        self.fragment_order.clone().unwrap_or_default()
+    }
+
+    fn fragment_order_option(&self) -> Option<BTreeMap<String, FragmentOrder>> {
+        self.fragment_order.clone()
+    }
+
+    fn with_keep_markers_option(&self, keep_markers: Option<bool>) -> Cow<'_, Self> {
+        let invar_config = InvarConfigData { keep_markers, ..InvarConfigData::none() };
+        self.with_invar_config(invar_config)
+    }
+
+    fn keep_markers(&self) -> bool {
+        self.keep_markers.unwrap_or(true)
+    }
+
+    fn keep_markers_option(&self) -> Option<bool> {
+        self.keep_markers
+    }
+
+    fn with_anchors_option(&self, anchors: Option<BTreeMap<String, Anchor>>) -> Cow<'_, Self> {
+        let invar_config = InvarConfigData { anchors, ..InvarConfigData::none() };
+        self.with_invar_config(invar_config)
+    }
+
+    fn anchors(&self) -> BTreeMap<String, Anchor> {
+        self.anchors.clone().unwrap_or_default()
+    }
+
+    fn anchors_option(&self) -> Option<BTreeMap<String, Anchor>> {
+        self.anchors.clone()
+    }
+
+    fn with_marker_syntax_option(&self, marker_syntax: Option<String>) -> Cow<'_, Self> {
+        let invar_config = InvarConfigData { marker_syntax, ..InvarConfigData::none() };
+        self.with_invar_config(invar_config)
+    }
+
+    fn marker_syntax_option(&self) -> Option<String> {
+        self.marker_syntax.clone()
+    }
+
+    fn with_backup_option(&self, backup: Option<bool>) -> Cow<'_, Self> {
+        let invar_config = InvarConfigData { backup, ..InvarConfigData::none() };
+        self.with_invar_config(invar_config)
+    }
+
+    fn backup(&self) -> bool {
+        self.backup.unwrap_or(false)
+    }
+
+    fn backup_option(&self) -> Option<bool> {
+        self.backup
+    }
+
+    fn with_merge_option(&self, merge: Option<bool>) -> Cow<'_, Self> {
+        let invar_config = InvarConfigData { merge, ..InvarConfigData::none() };
+        self.with_invar_config(invar_config)
+    }
+
+    fn merge(&self) -> bool {
+        self.merge.unwrap_or(false)
+    }
+
+    fn merge_option(&self) -> Option<bool> {
+        self.merge
+    }
+
+    fn with_mode_option(&self, mode: Option<String>) -> Cow<'_, Self> {
+        let invar_config = InvarConfigData { mode, ..InvarConfigData::none() };
+        self.with_invar_config(invar_config)
+    }
+
+    fn mode(&self) -> Result<Option<u32>> {
+        let Some(mode) = &self.mode else { return Ok(None) };
+        match u32::from_str_radix(mode.trim_start_matches("0o"), 8) {
+            Ok(bits) if bits <= 0o7777 => Ok(Some(bits)),
+            _ => Err(anyhow!("Invalid mode: {mode:?}")),
+        }
+    }
+
+    fn mode_option(&self) -> Option<String> {
+        self.mode.clone()
+    }
+
+    fn with_rules_option(&self, rules: Option<Vec<InvarRule>>) -> Cow<'_, Self> {
+        let invar_config = InvarConfigData { rules, ..InvarConfigData::none() };
+        self.with_invar_config(invar_config)
+    }
+
+    fn rules(&self) -> Vec<InvarRule> {
+        self.rules.clone().unwrap_or_default()
+    }
+
+    fn rules_option(&self) -> Option<Vec<InvarRule>> {
+        self.rules.clone()
+    }
+
+    fn with_rules_in_directory(&self, directory: &str) -> Cow<'_, Self> {
+        match &self.rules {
+            Some(rules) if !directory.is_empty() => {
+                let rules = rules.iter().map(|rule| rule.in_directory(directory)).collect();
+                Cow::Owned(InvarConfigData { rules: Some(rules), ..self.clone() })
+            },
+            _ => Cow::Borrowed(self),
+        }
+    }
+
+    fn by_extension(&self) -> Cow<'_, BTreeMap<String, Self>> {
+        self.by_extension.as_ref().map(Cow::Borrowed).unwrap_or_default()
+    }
 }
 
 fn merge_property<T: Copy + Eq>(current_value_option: Option<T>, new_value_option: Option<T>, dirty: bool) -> (Option<T>, bool) {
@@ -140,18 +434,51 @@ fn merge_property<T: Copy + Eq>(current_value_option: Option<T>, new_value_optio
     }
 }
 
+fn merge_cloned_property<T: Clone + Eq>(current_value_option: &Option<T>, new_value_option: Option<T>, dirty: bool) -> (Option<T>, bool) {
+    match new_value_option {
+        Some(new_value) if current_value_option.as_ref() != Some(&new_value) => (Some(new_value), true),
+        _ => (current_value_option.clone(), dirty)
+    }
+}
+
+fn merge_rules(current_option: &Option<Vec<InvarRule>>, new_option: Option<Vec<InvarRule>>, dirty: bool) -> (Option<Vec<InvarRule>>, bool) {
+    let Some(new_rules) = new_option else { return (current_option.clone(), dirty) };
+    let mut result = current_option.clone().unwrap_or_default();
+    let mut dirty = dirty;
+    for rule in new_rules {
+        if !result.contains(&rule) {
+            result.push(rule);
+            dirty = true;
+        }
+    }
+    (Some(result), dirty)
+}
+
+fn merge_by_extension<I: InvarConfig>(current_option: &Option<BTreeMap<String, InvarConfigData>>, new_by_extension: &BTreeMap<String, I>, dirty: bool) -> (Option<BTreeMap<String, InvarConfigData>>, bool) {
+    if new_by_extension.is_empty() {
+        return (current_option.clone(), dirty);
+    }
+    let mut result = current_option.clone().unwrap_or_default();
+    let mut dirty = dirty;
+    for (extension, new_invar_config) in new_by_extension {
+        let current = result.remove(extension).unwrap_or_else(InvarConfigData::none);
+        let merged = current.with_invar_config(new_invar_config.clone());
+        dirty = dirty || matches!(merged, Cow::Owned(_));
+        result.insert(extension.clone(), merged.into_owned());
+    }
+    (Some(result), dirty)
+}
+
 fn merge_props<'a>(current_props_option: &'a Option<Table>, new_props_option: &'a Option<Table>, dirty: bool) -> (Cow<'a, Table>, bool) {
     if let Some(current_props) = current_props_option {
         if let Some(new_props) = new_props_option {
-            for (k, v) in new_props {
-                if current_props.get(k) != Some(v) {
-                    let mut result = current_props.clone();
-                    let new_props = new_props.clone();
-                    result.extend(new_props);
-                    return (Cow::Owned(result), true)
-                }
+            if is_contained_in(new_props, current_props) {
+                (Cow::Borrowed(current_props), dirty)
+            } else {
+                let mut result = current_props.clone();
+                deep_merge(&mut result, new_props);
+                (Cow::Owned(result), true)
             }
-            (Cow::Borrowed(current_props), dirty)
         } else {
             (Cow::Borrowed(current_props), dirty)
         }
@@ -162,15 +489,54 @@ fn merge_props<'a>(current_props_option: &'a Option<Table>, new_props_option: &'
     }
 }
 
+/// Commands for props are merged key by key. Unlike props, they stay unset if neither side has any.
+fn merge_props_exec(current_option: &Option<Table>, new_option: &Option<Table>, dirty: bool) -> (Option<Table>, bool) {
+    match (current_option, new_option) {
+        (Some(current), Some(new)) if !is_contained_in(new, current) => {
+            let mut result = current.clone();
+            result.extend(new.iter().map(|(key, value)| (key.clone(), value.clone())));
+            (Some(result), true)
+        },
+        (None, Some(new)) => (Some(new.clone()), true),
+        _ => (current_option.clone(), dirty),
+    }
+}
+
+/// Merges nested tables key by key. Other values (including arrays) in `new_props` replace the current value.
+fn deep_merge(current_props: &mut Table, new_props: &Table) {
+    for (key, new_value) in new_props {
+        match (current_props.get_mut(key), new_value) {
+            (Some(Value::Table(current_table)), Value::Table(new_table)) => deep_merge(current_table, new_table),
+            _ => { current_props.insert(key.clone(), new_value.clone()); },
+        }
+    }
+}
+
+fn is_contained_in(new_props: &Table, current_props: &Table) -> bool {
+    new_props.iter().all(|(key, new_value)| {
+        match (current_props.get(key), new_value) {
+            (Some(Value::Table(current_table)), Value::Table(new_table)) => is_contained_in(new_table, current_table),
+            (current_value, _) => current_value == Some(new_value),
+        }
+    })
+}
+
+/// Flattens the props to a map from names to text. Props in nested tables get dotted names like `server.host`.
 fn to_string_map(props: &Table) -> AHashMap<String,String> {
-    props.iter().map(to_strings).filter(Option::is_some).map(Option::unwrap).collect()
+    let mut string_map = AHashMap::new();
+    insert_strings(&mut string_map, "", props);
+    string_map
 }
 
-fn to_strings(entry: (&String, &Value)) -> Option<(String, String)> {
-    if let (key, Value::String(val)) = entry {
-        Some((key.to_owned(), val.to_owned()))
-    } else {
-        None
+fn insert_strings(string_map: &mut AHashMap<String,String>, prefix: &str, props: &Table) {
+    for (key, value) in props {
+        let name = format!("{prefix}{key}");
+        match value {
+            Value::Table(table) => insert_strings(string_map, &format!("{name}."), table),
+            _ => if let Some(value) = scalar_to_string(value) {
+                string_map.insert(name, value.into_owned());
+            },
+        }
     }
 }
 
@@ -179,6 +545,7 @@ mod test {
     use super::*;
     use super::super::serde_test_utils::insert_entry;
     use crate::config_model::WriteMode::*;
+    use indoc::indoc;
     use test_log::test;
 
     // Write mode
@@ -425,12 +792,110 @@ mod test {
         assert_eq!(updated.props_option(), &Some(updated_mapping));
     }
 
+    #[test]
+    fn with_props_deep_merges_nested_tables() {
+        // Given
+        let old_mapping: Table = toml::from_str(indoc! {r#"
+            [clock]
+            sweeper = "Lu Tse"
+            springs = ["glass", "steel"]
+            [clock.location]
+            valley = "Oi Dong"
+        "#}).unwrap();
+        let invar_config = new_invar_config().with_props(old_mapping).into_owned();
+        let new_mapping: Table = toml::from_str(indoc! {r#"
+            [clock]
+            springs = ["glass"]
+            [clock.location]
+            city = "Ankh-Morpork"
+        "#}).unwrap();
+
+        // When
+        let updated = invar_config.with_props(new_mapping);
+
+        // Then
+        assert_owned(&updated);
+        let expected: Table = toml::from_str(indoc! {r#"
+            [clock]
+            sweeper = "Lu Tse"
+            springs = ["glass"]
+            [clock.location]
+            valley = "Oi Dong"
+            city = "Ankh-Morpork"
+        "#}).unwrap();
+        assert_eq!(updated.props_option(), &Some(expected));
+    }
+
+    #[test]
+    fn with_props_nested_same() {
+        // Given
+        let old_mapping: Table = toml::from_str(indoc! {r#"
+            [clock]
+            sweeper = "Lu Tse"
+            [clock.location]
+            valley = "Oi Dong"
+        "#}).unwrap();
+        let invar_config = new_invar_config().with_props(old_mapping.clone()).into_owned();
+        let new_mapping: Table = toml::from_str(indoc! {r#"
+            [clock.location]
+            valley = "Oi Dong"
+        "#}).unwrap();
+
+        // When
+        let updated = invar_config.with_props(new_mapping);
+
+        // Then
+        assert_borrowed(&updated);
+        assert_eq!(updated.props_option(), &Some(old_mapping));
+    }
+
+    // Placeholder delimiters
+
+    #[test]
+    fn with_placeholder_delimiters_from_none_to_something() {
+        let invar_config = new_invar_config();
+        assert_eq!(invar_config.placeholder_delimiters_option(), None);
+        assert_eq!(invar_config.placeholder_delimiters().as_ref(), &PlaceholderDelimiters::default());
+        let updated = invar_config.with_placeholder_delimiters_option(Some(PlaceholderDelimiters::new("<%", "%>")));
+        assert_owned(&updated);
+        assert_eq!(updated.placeholder_delimiters().as_ref(), &PlaceholderDelimiters::new("<%", "%>"));
+    }
+
+    #[test]
+    fn with_placeholder_delimiters_from_something_to_same_or_none() {
+        let invar_config = new_invar_config().with_placeholder_delimiters_option(Some(PlaceholderDelimiters::new("<%", "%>"))).into_owned();
+        let same = invar_config.with_placeholder_delimiters_option(Some(PlaceholderDelimiters::new("<%", "%>")));
+        assert_borrowed(&same);
+        let none = invar_config.with_placeholder_delimiters_option(None);
+        assert_borrowed(&none);
+        assert_eq!(none.placeholder_delimiters_option(), Some(PlaceholderDelimiters::new("<%", "%>"))); // Old value unchanged
+    }
+
+    #[test]
+    fn placeholder_delimiters_from_str() -> Result<()> {
+        let toml_source = indoc! {r#"
+            [placeholder-delimiters]
+            open = "@{"
+            close = "}@"
+        "#};
+        let invar_config = InvarConfigData::from_str(toml_source, ConfigFormat::TOML)?;
+        assert_eq!(invar_config.placeholder_delimiters().as_ref(), &PlaceholderDelimiters::new("@{", "}@"));
+        Ok(())
+    }
+
     #[test]
     fn string_props() {
         // Given
         let mut mapping = Table::new();
         insert_entry(&mut mapping, "foo", "bar");
         insert_entry(&mut mapping, "food", "baz");
+        mapping.insert("port".to_string(), Value::Integer(8080));
+        mapping.insert("debug".to_string(), Value::Boolean(true));
+        mapping.insert("ratio".to_string(), Value::Float(1.5));
+        mapping.insert("hosts".to_string(), Value::Array(vec![Value::String("igor".to_string())]));
+        let mut server = Table::new();
+        insert_entry(&mut server, "host", "localhost");
+        mapping.insert("server".to_string(), Value::Table(server));
         let invar_config = new_invar_config().with_props(mapping).into_owned();
 
         // When
@@ -440,13 +905,17 @@ mod test {
         let mut expected = AHashMap::new();
         expected.insert("foo".to_string(), "bar".to_string());
         expected.insert("food".to_string(), "baz".to_string());
+        expected.insert("port".to_string(), "8080".to_string());
+        expected.insert("debug".to_string(), "true".to_string());
+        expected.insert("ratio".to_string(), "1.5".to_string());
+        expected.insert("server.host".to_string(), "localhost".to_string());
         assert_eq!(string_props, expected);
     }
 
     // Utility functions
 
     fn empty_invar_config() -> impl InvarConfig {
-        InvarConfigData { write_mode: None, interpolate: None, props: None }
+        InvarConfigData::none()
     }
 
     fn new_invar_config() -> impl InvarConfig {
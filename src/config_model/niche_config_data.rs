@@ -1,10 +1,11 @@
 use anyhow::Result;
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use crate::file_system::ConfigFormat;
 use super::{UseThundercloudConfig, NicheConfig};
 use super::use_thundercloud_config_data::UseThundercloudConfigData;
 
-#[derive(Deserialize,Serialize,Debug)]
+#[derive(Deserialize,Serialize,JsonSchema,Debug)]
 #[serde(rename_all = "kebab-case")]
 pub struct NicheConfigData {
     use_thundercloud: UseThundercloudConfigData,
@@ -1,7 +1,8 @@
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use crate::config_model::niche_description::*;
 
-#[derive(Deserialize,Serialize,Debug)]
+#[derive(Deserialize,Serialize,JsonSchema,Debug)]
 pub struct NicheDescriptionData {
     name: String,
     description: Option<String>,
@@ -0,0 +1,29 @@
+use std::fmt::Debug;
+
+/// How Igor reports the result of a run when it completes: by posting a JSON summary to a webhook and/or with a desktop
+/// notification.
+pub trait NotifyConfig: Clone + Debug + Default {
+    fn url(&self) -> Option<&str>;
+    fn desktop(&self) -> bool;
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use super::super::notify_config_data::NotifyConfigData;
+
+    #[test]
+    fn getters() {
+        // Given
+        let notify_config_data = NotifyConfigData::new(Some("https://ci.example.com/hooks/igor".to_string()), Some(true));
+
+        // When
+        let notify_config = notify_config_data;
+
+        // Then
+        assert_eq!(notify_config.url(), Some("https://ci.example.com/hooks/igor"));
+        assert!(notify_config.desktop());
+        assert_eq!(NotifyConfigData::default().url(), None);
+        assert!(!NotifyConfigData::default().desktop());
+    }
+}
@@ -0,0 +1,29 @@
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use super::NotifyConfig;
+
+#[derive(Deserialize,Serialize,JsonSchema,Debug,Clone,Default)]
+#[serde(rename_all = "kebab-case")]
+pub struct NotifyConfigData {
+    url: Option<String>,
+    desktop: Option<bool>,
+}
+
+impl NotifyConfig for NotifyConfigData {
+    fn url(&self) -> Option<&str> {
+        self.url.as_deref()
+    }
+
+    fn desktop(&self) -> bool {
+        self.desktop.unwrap_or(false)
+    }
+}
+
+impl NotifyConfigData {
+    pub fn new(url: Option<String>, desktop: Option<bool>) -> Self {
+        NotifyConfigData {
+            url,
+            desktop,
+        }
+    }
+}
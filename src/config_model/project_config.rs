@@ -1,20 +1,175 @@
 use std::borrow::Cow;
 use anyhow::Result;
 use std::fmt::Debug;
-use crate::config_model::InvarConfig;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use crate::config_model::{InvarConfig, NotifyConfig, SchedulerConfig};
 use crate::config_model::project_config_data::ProjectConfigData;
 use crate::config_model::psychotropic::PsychotropicConfig;
 use crate::file_system::ConfigFormat;
 use crate::path::RelativePath;
 
+/// Whether Igor may access the network, for instance to fetch thunderclouds from their git remote.
+#[derive(Deserialize,Serialize,JsonSchema,Debug,Clone,Copy,Default,Eq,PartialEq)]
+pub enum NetworkAccess {
+    #[default]
+    Allowed,
+    Forbidden
+}
+
+/// What Igor does with a psychotropic cue for a niche that has neither a thundercloud configuration nor a directory.
+#[derive(Deserialize,Serialize,JsonSchema,Debug,Clone,Copy,Default,Eq,PartialEq)]
+pub enum MissingNiche {
+    /// Record a warning in the report of the run and carry on
+    #[default]
+    Warn,
+    /// Fail the niche
+    Fail
+}
+
 pub trait ProjectConfig: Debug + Sized {
     type InvarConfigImpl : InvarConfig;
+    type SchedulerConfigImpl : SchedulerConfig;
+    type NotifyConfigImpl : NotifyConfig;
     fn from_str(toml_data: &str, config_format: ConfigFormat) -> anyhow::Result<Self>;
     fn niches_directory(&self) -> RelativePath;
     fn psychotropic(&self) -> Result<impl PsychotropicConfig>;
     fn invar_defaults(&self) -> Cow<Self::InvarConfigImpl>;
+    fn scheduler(&self) -> Cow<'_, Self::SchedulerConfigImpl>;
+    /// Names (or glob patterns) of the environment variables that placeholders like `{{env.HOME}}` may read.
+    fn environment_allowlist(&self) -> &[String];
+    fn network(&self) -> NetworkAccess;
+    fn missing_niche(&self) -> MissingNiche;
+    /// The directory that thunderclouds with a git remote, but without a directory are cloned into.
+    fn thundercloud_cache(&self) -> Option<&str>;
+    /// The file (relative to the project root) that a summary of every run that changed files is appended to.
+    fn changelog_file(&self) -> Option<&str>;
+    /// The programs that Igor may run as subprocesses (like the commands of `props-exec` and git). Without an allowlist,
+    /// all programs are allowed.
+    fn allowed_hook_commands(&self) -> Option<&[String]>;
+    fn notify(&self) -> Cow<'_, Self::NotifyConfigImpl>;
+    /// The oldest version of Igor (like `0.2.0`) that may apply the niches of the project.
+    fn requires_igor(&self) -> Option<&str>;
+    /// The release feed that `igor upgrade` checks, instead of the feed of the official releases. Its binaries are
+    /// verified with the key that Igor was built with.
+    fn release_feed(&self) -> Option<&str>;
 }
 
 pub fn from_str(data: &str, config_format: ConfigFormat) -> Result<impl ProjectConfig> {
     ProjectConfigData::from_str(data, config_format)
 }
+
+#[cfg(test)]
+mod test {
+    use indoc::indoc;
+    use crate::config_model::SchedulerConfig;
+    use super::*;
+
+    #[test]
+    fn scheduler_jobs() -> Result<()> {
+        // Given
+        let toml_data = indoc! {r#"
+            [scheduler]
+            jobs = 2
+        "#};
+
+        // When
+        let project_config = from_str(toml_data, ConfigFormat::TOML)?;
+
+        // Then
+        assert_eq!(project_config.scheduler().jobs(), Some(2));
+        Ok(())
+    }
+
+    #[test]
+    fn environment_allowlist() -> Result<()> {
+        // Given
+        let toml_data = indoc! {r#"
+            environment-allowlist = ["CARGO_MANIFEST_DIR"]
+
+            [invar-defaults.props]
+            sweeper = "Lu Tse"
+        "#};
+
+        // When
+        let project_config = from_str(toml_data, ConfigFormat::TOML)?;
+
+        // Then
+        let props = project_config.invar_defaults().props().into_owned();
+        assert_eq!(props.get("sweeper").and_then(toml::Value::as_str), Some("Lu Tse"));
+        let environment = props.get("env").and_then(toml::Value::as_table).unwrap();
+        assert_eq!(environment.keys().collect::<Vec<_>>(), vec!["CARGO_MANIFEST_DIR"]);
+        Ok(())
+    }
+
+    #[test]
+    fn notify() -> Result<()> {
+        // Given
+        let toml_data = indoc! {r#"
+            [notify]
+            url = "https://ci.example.com/hooks/igor"
+            desktop = true
+        "#};
+
+        // When
+        let project_config = from_str(toml_data, ConfigFormat::TOML)?;
+
+        // Then
+        assert_eq!(project_config.notify().url(), Some("https://ci.example.com/hooks/igor"));
+        assert!(project_config.notify().desktop());
+        Ok(())
+    }
+
+    #[test]
+    fn network_access() -> Result<()> {
+        // When
+        let forbidden = from_str(r#"network = "Forbidden""#, ConfigFormat::TOML)?;
+        let default = from_str("", ConfigFormat::TOML)?;
+
+        // Then
+        assert_eq!(forbidden.network(), NetworkAccess::Forbidden);
+        assert_eq!(default.network(), NetworkAccess::Allowed);
+        Ok(())
+    }
+
+    #[test]
+    fn missing_niche_policy() -> Result<()> {
+        // When
+        let fail = from_str(r#"missing-niche = "Fail""#, ConfigFormat::TOML)?;
+        let default = from_str("", ConfigFormat::TOML)?;
+
+        // Then
+        assert_eq!(fail.missing_niche(), MissingNiche::Fail);
+        assert_eq!(default.missing_niche(), MissingNiche::Warn);
+        Ok(())
+    }
+
+    #[test]
+    fn release_settings() -> Result<()> {
+        // Given
+        let toml_data = indoc! {r#"
+            requires-igor = "0.2.0"
+            release-feed = "https://mirror.example.com/igor/release.json"
+        "#};
+
+        // When
+        let project_config = from_str(toml_data, ConfigFormat::TOML)?;
+        let default = from_str("", ConfigFormat::TOML)?;
+
+        // Then
+        assert_eq!(project_config.requires_igor(), Some("0.2.0"));
+        assert_eq!(project_config.release_feed(), Some("https://mirror.example.com/igor/release.json"));
+        assert_eq!(default.requires_igor(), None);
+        Ok(())
+    }
+
+    #[test]
+    fn scheduler_default() -> Result<()> {
+        // When
+        let project_config = from_str("", ConfigFormat::TOML)?;
+
+        // Then
+        assert_eq!(project_config.scheduler().jobs(), None);
+        Ok(())
+    }
+}
@@ -1,25 +1,42 @@
 use std::borrow::Cow;
 use anyhow::Result;
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use crate::config_model::invar_config::invar_config_or_default;
+use crate::config_model::InvarConfig;
 use crate::config_model::invar_config_data::InvarConfigData;
-use crate::config_model::project_config::ProjectConfig;
+use crate::config_model::project_config::{MissingNiche, NetworkAccess, ProjectConfig};
 use crate::config_model::psychotropic::PsychotropicConfig;
 use crate::config_model::psychotropic_data;
 use crate::config_model::psychotropic_data::{data_to_index, PsychotropicConfigData};
+use crate::config_model::notify_config_data::NotifyConfigData;
+use crate::config_model::scheduler_config_data::SchedulerConfigData;
 use crate::file_system::ConfigFormat;
+use crate::interpolate::environment_props;
 use crate::path::RelativePath;
 
-#[derive(Deserialize, Serialize, Debug,Default)]
+#[derive(Deserialize, Serialize, JsonSchema, Debug,Default)]
 #[serde(rename_all = "kebab-case")]
 pub struct ProjectConfigData {
     niches_directory: Option<String>,
     psychotropic: Option<PsychotropicConfigData>,
     invar_defaults: Option<InvarConfigData>,
+    scheduler: Option<SchedulerConfigData>,
+    environment_allowlist: Option<Vec<String>>,
+    network: Option<NetworkAccess>,
+    missing_niche: Option<MissingNiche>,
+    thundercloud_cache: Option<String>,
+    changelog_file: Option<String>,
+    allowed_hook_commands: Option<Vec<String>>,
+    notify: Option<NotifyConfigData>,
+    requires_igor: Option<String>,
+    release_feed: Option<String>,
 }
 
 impl ProjectConfig for ProjectConfigData {
     type InvarConfigImpl = InvarConfigData;
+    type SchedulerConfigImpl = SchedulerConfigData;
+    type NotifyConfigImpl = NotifyConfigData;
 
     fn from_str(data: &str, config_format: ConfigFormat) -> Result<Self> {
         let project_config: ProjectConfigData = match config_format {
@@ -48,7 +65,62 @@ impl ProjectConfig for ProjectConfigData {
         }
     }
 
+    /// The invar defaults of the project, with the allowed environment variables as props in table `env`.
     fn invar_defaults(&self) -> Cow<Self::InvarConfigImpl> {
-        invar_config_or_default(&self.invar_defaults)
+        let invar_defaults = invar_config_or_default(&self.invar_defaults);
+        let environment = environment_props(self.environment_allowlist());
+        if environment.is_empty() {
+            invar_defaults
+        } else {
+            Cow::Owned(invar_defaults.with_props(environment).into_owned())
+        }
+    }
+
+    fn scheduler(&self) -> Cow<'_, Self::SchedulerConfigImpl> {
+        if let Some(scheduler) = &self.scheduler {
+            Cow::Borrowed(scheduler)
+        } else {
+            Cow::Owned(SchedulerConfigData::default())
+        }
+    }
+
+    fn environment_allowlist(&self) -> &[String] {
+        self.environment_allowlist.as_deref().unwrap_or_default()
+    }
+
+    fn network(&self) -> NetworkAccess {
+        self.network.unwrap_or_default()
+    }
+
+    fn missing_niche(&self) -> MissingNiche {
+        self.missing_niche.unwrap_or_default()
+    }
+
+    fn thundercloud_cache(&self) -> Option<&str> {
+        self.thundercloud_cache.as_deref()
+    }
+
+    fn changelog_file(&self) -> Option<&str> {
+        self.changelog_file.as_deref()
+    }
+
+    fn allowed_hook_commands(&self) -> Option<&[String]> {
+        self.allowed_hook_commands.as_deref()
+    }
+
+    fn notify(&self) -> Cow<'_, Self::NotifyConfigImpl> {
+        if let Some(notify) = &self.notify {
+            Cow::Borrowed(notify)
+        } else {
+            Cow::Owned(NotifyConfigData::default())
+        }
+    }
+
+    fn requires_igor(&self) -> Option<&str> {
+        self.requires_igor.as_deref()
+    }
+
+    fn release_feed(&self) -> Option<&str> {
+        self.release_feed.as_deref()
     }
 }
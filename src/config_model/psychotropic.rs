@@ -1,3 +1,4 @@
+use std::collections::BTreeMap;
 use std::fmt::Debug;
 use ahash::AHashSet;
 use anyhow::Result;
@@ -8,6 +9,15 @@ use super::psychotropic_data::{empty, PsychotropicConfigIndex};
 use crate::file_system::{ConfigFormat, FileSystem, PathType};
 use crate::path::AbsolutePath;
 
+/// The part that a niche plays in generating a target file that several niches share. The first niche that declares the
+/// target file in the psychotropic configuration owns it: it generates the file from its option. The other niches
+/// contribute fragments to the file that the niche before them left behind.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SharedTargetRole {
+    Owner,
+    Contributor,
+}
+
 pub trait NicheTriggers: Clone + Debug {
     type UseThundercloudConfigImpl: UseThundercloudConfig + for<'a> Deserialize<'a>;
     fn name(&self) -> String;
@@ -15,6 +25,8 @@ pub trait NicheTriggers: Clone + Debug {
     fn use_thundercloud_path(&self) -> Option<AbsolutePath>;
     fn wait_for(&self) -> &[String];
     fn triggers(&self) -> &[String];
+    /// The shared target files of the niche (relative to the project root), with the role of the niche for each.
+    fn shared_targets(&self) -> &BTreeMap<String, SharedTargetRole>;
 }
 
 pub trait PsychotropicConfig: Debug + Sized + Send {
@@ -91,6 +103,39 @@ mod test {
         assert!(result.is_err(), "An assumed precursor should not appear again");
     }
 
+    #[test]
+    fn shared_targets() -> Result<()> {
+        // Given
+        let toml = indoc! {r#"
+            [[cues]]
+            name = "example"
+            shared-targets = ["workshop/clock.yaml"]
+
+            [[cues]]
+            name = "glass"
+            shared-targets = ["workshop/clock.yaml"]
+
+            [[cues]]
+            name = "spring"
+            wait-for = ["example"]
+            shared-targets = ["workshop/clock.yaml"]
+        "#};
+
+        // When
+        let result = from_str(toml, ConfigFormat::TOML)?;
+
+        // Then
+        let role = |niche: &str| result.get(niche).unwrap().shared_targets().get("workshop/clock.yaml").copied();
+        assert_eq!(role("example"), Some(SharedTargetRole::Owner));
+        assert_eq!(role("glass"), Some(SharedTargetRole::Contributor));
+        assert_eq!(role("spring"), Some(SharedTargetRole::Contributor));
+        assert_eq!(result.get("glass").unwrap().wait_for(), vec!["example"]);
+        assert_eq!(result.get("spring").unwrap().wait_for(), vec!["example", "glass"]);
+        assert_eq!(result.get("example").unwrap().triggers(), vec!["glass", "spring"]);
+
+        Ok(())
+    }
+
     #[test(tokio::test)]
     async fn from_source_file() -> Result<()> {
         // Given
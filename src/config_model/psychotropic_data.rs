@@ -1,27 +1,31 @@
+use std::collections::BTreeMap;
 use std::mem::swap;
 use anyhow::{anyhow, Result};
 use ahash::{AHashMap, AHashSet};
 use log::debug;
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use crate::config_model::use_thundercloud_config_data::UseThundercloudConfigData;
 use crate::file_system::ConfigFormat;
 use crate::path::AbsolutePath;
-use super::psychotropic::{NicheTriggers, PsychotropicConfig};
+use super::psychotropic::{NicheTriggers, PsychotropicConfig, SharedTargetRole};
 
-#[derive(Deserialize,Serialize,Debug,Clone)]
+#[derive(Deserialize,Serialize,JsonSchema,Debug,Clone)]
 #[serde(untagged)]
 enum UseThundercloudSpec {
     ProjectPath(String),
-    Inline(UseThundercloudConfigData),
+    Inline(Box<UseThundercloudConfigData>),
 }
 
-#[derive(Deserialize,Serialize,Debug,Clone)]
+#[derive(Deserialize,Serialize,JsonSchema,Debug,Clone)]
 #[serde(rename_all = "kebab-case")]
 pub struct NicheCueData {
     name: String,
     use_thundercloud: Option<UseThundercloudSpec>,
     #[serde(default)]
     wait_for: Vec<String>,
+    #[serde(default)]
+    shared_targets: Vec<String>,
 }
 
 impl NicheCueData {
@@ -34,7 +38,7 @@ impl NicheCueData {
     }
 }
 
-#[derive(Deserialize,Serialize,Debug)]
+#[derive(Deserialize,Serialize,JsonSchema,Debug)]
 pub struct PsychotropicConfigData {
     cues: Vec<NicheCueData>
 }
@@ -43,6 +47,7 @@ pub struct PsychotropicConfigData {
 pub struct NicheTriggersData {
     niche_cue: NicheCueData,
     triggers: Vec<String>,
+    shared_targets: BTreeMap<String, SharedTargetRole>,
 }
 
 impl NicheTriggers for NicheTriggersData {
@@ -76,6 +81,10 @@ impl NicheTriggers for NicheTriggersData {
     fn triggers(&self) -> &[String] {
         &self.triggers
     }
+
+    fn shared_targets(&self) -> &BTreeMap<String, SharedTargetRole> {
+        &self.shared_targets
+    }
 }
 
 impl NicheTriggersData {
@@ -83,6 +92,7 @@ impl NicheTriggersData {
         NicheTriggersData {
             niche_cue,
             triggers: Vec::new(),
+            shared_targets: BTreeMap::new(),
         }
     }
 }
@@ -156,7 +166,8 @@ pub fn data_to_index(data: &PsychotropicConfigData) -> Result<PsychotropicConfig
     let mut in_block = None;
     barriers.insert(current_barrier.clone());
     let mut index: AHashMap<String, NicheTriggersData> = AHashMap::new();
-    for cue in &data.cues {
+    let (cues, shared_targets) = share_targets(&data.cues);
+    for cue in &cues {
         let cue_name = cue.name();
         if let Some(_) = cue_name.strip_prefix("#") {
             if barriers.contains(&cue_name) {
@@ -168,7 +179,7 @@ pub fn data_to_index(data: &PsychotropicConfigData) -> Result<PsychotropicConfig
                 let previous_barrier_name = name.clone();
                 let mut wait_for = Vec::new();
                 swap(&mut wait_for, &mut current_barrier_wait_for);
-                let barrier_cue = NicheCueData { name, wait_for, use_thundercloud: None };
+                let barrier_cue = NicheCueData { name, wait_for, use_thundercloud: None, shared_targets: Vec::new() };
                 index.insert(previous_barrier_name, NicheTriggersData::new(barrier_cue));
             } else {
                 current_barrier = cue_name.clone();
@@ -192,7 +203,7 @@ pub fn data_to_index(data: &PsychotropicConfigData) -> Result<PsychotropicConfig
             if let Some(niche_trigger) = index.get_mut(dep) {
                 niche_trigger.triggers.push(cue.name())
             } else {
-                let trivial = NicheCueData { name: dep.clone(), wait_for: Vec::new(), use_thundercloud: None };
+                let trivial = NicheCueData { name: dep.clone(), wait_for: Vec::new(), use_thundercloud: None, shared_targets: Vec::new() };
                 let mut niche_trigger = NicheTriggersData::new(trivial);
                 niche_trigger.triggers.push(cue.name());
                 index.insert(dep.clone(), niche_trigger);
@@ -200,9 +211,38 @@ pub fn data_to_index(data: &PsychotropicConfigData) -> Result<PsychotropicConfig
         }
         index.insert(cue.name().to_string(), NicheTriggersData::new(cue.clone()));
     }
+    for (niche, roles) in shared_targets {
+        if let Some(niche_triggers) = index.get_mut(&niche) {
+            niche_triggers.shared_targets = roles;
+        }
+    }
     Ok(PsychotropicConfigIndex(index))
 }
 
+/// Assigns the roles of the niches that share target files, and makes every niche that contributes to a shared target
+/// file wait for the niche that declares the target file before it.
+fn share_targets(cues: &[NicheCueData]) -> (Vec<NicheCueData>, AHashMap<String, BTreeMap<String, SharedTargetRole>>) {
+    let mut previous_niches: AHashMap<&str, &str> = AHashMap::new();
+    let mut roles: AHashMap<String, BTreeMap<String, SharedTargetRole>> = AHashMap::new();
+    let mut result = Vec::new();
+    for original in cues {
+        let mut cue = original.clone();
+        for target in &original.shared_targets {
+            let niche_roles = roles.entry(original.name.clone()).or_default();
+            if let Some(previous) = previous_niches.insert(target, &original.name) {
+                niche_roles.insert(target.clone(), SharedTargetRole::Contributor);
+                if !cue.wait_for.iter().any(|dep| dep == previous) {
+                    cue.wait_for.push(previous.to_string());
+                }
+            } else {
+                niche_roles.insert(target.clone(), SharedTargetRole::Owner);
+            }
+        }
+        result.push(cue);
+    }
+    (result, roles)
+}
+
 pub fn empty() -> PsychotropicConfigIndex {
     PsychotropicConfigIndex(AHashMap::new())
 }
\ No newline at end of file
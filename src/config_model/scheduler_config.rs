@@ -0,0 +1,27 @@
+use std::fmt::Debug;
+
+pub trait SchedulerConfig: Clone + Debug + Default {
+    fn jobs(&self) -> Option<usize>;
+    fn memory_ceiling_mib(&self) -> Option<usize>;
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use super::super::scheduler_config_data::SchedulerConfigData;
+
+    #[test]
+    fn getters() {
+        // Given
+        let scheduler_config_data = SchedulerConfigData::new(Some(3), Some(512));
+
+        // When
+        let scheduler_config = scheduler_config_data;
+
+        // Then
+        assert_eq!(scheduler_config.jobs(), Some(3));
+        assert_eq!(scheduler_config.memory_ceiling_mib(), Some(512));
+        assert_eq!(SchedulerConfigData::default().jobs(), None);
+        assert_eq!(SchedulerConfigData::default().memory_ceiling_mib(), None);
+    }
+}
@@ -0,0 +1,29 @@
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use super::SchedulerConfig;
+
+#[derive(Deserialize,Serialize,JsonSchema,Debug,Clone,Default)]
+#[serde(rename_all = "kebab-case")]
+pub struct SchedulerConfigData {
+    jobs: Option<usize>,
+    memory_ceiling_mib: Option<usize>,
+}
+
+impl SchedulerConfig for SchedulerConfigData {
+    fn jobs(&self) -> Option<usize> {
+        self.jobs
+    }
+
+    fn memory_ceiling_mib(&self) -> Option<usize> {
+        self.memory_ceiling_mib
+    }
+}
+
+impl SchedulerConfigData {
+    pub fn new(jobs: Option<usize>, memory_ceiling_mib: Option<usize>) -> Self {
+        SchedulerConfigData {
+            jobs,
+            memory_ceiling_mib,
+        }
+    }
+}
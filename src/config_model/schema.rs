@@ -0,0 +1,63 @@
+use anyhow::Result;
+use clap::ValueEnum;
+use schemars::schema_for;
+use super::invar_config_data::InvarConfigData;
+use super::project_config_data::ProjectConfigData;
+use super::psychotropic_data::PsychotropicConfigData;
+use super::thundercloud_config_data::ThundercloudConfigData;
+
+/// The configuration files that Igor reads
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SchemaKind {
+    /// `CargoCult.toml`
+    Project,
+    /// `thundercloud.toml` of a thundercloud
+    Thundercloud,
+    /// `+config` files and `invar-defaults`
+    Invar,
+    /// The `psychotropic` table of `CargoCult.toml`
+    Psychotropic,
+}
+
+/// Renders the JSON Schema of a kind of configuration file.
+pub fn json_schema(kind: SchemaKind) -> Result<String> {
+    let schema = match kind {
+        SchemaKind::Project => schema_for!(ProjectConfigData),
+        SchemaKind::Thundercloud => schema_for!(ThundercloudConfigData),
+        SchemaKind::Invar => schema_for!(InvarConfigData),
+        SchemaKind::Psychotropic => schema_for!(PsychotropicConfigData),
+    };
+    Ok(serde_json::to_string_pretty(&schema)?)
+}
+
+#[cfg(test)]
+mod test {
+    use serde_json::Value;
+    use super::*;
+
+    #[test]
+    fn project_schema() -> Result<()> {
+        // When
+        let schema: Value = serde_json::from_str(&json_schema(SchemaKind::Project)?)?;
+
+        // Then
+        let properties = &schema["properties"];
+        for key in ["niches-directory", "psychotropic", "invar-defaults", "scheduler", "environment-allowlist", "network", "missing-niche", "thundercloud-cache", "changelog-file", "allowed-hook-commands", "notify", "requires-igor", "release-feed"] {
+            assert!(properties.get(key).is_some(), "Missing property {key:?}: {schema}");
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn invar_schema() -> Result<()> {
+        // When
+        let schema: Value = serde_json::from_str(&json_schema(SchemaKind::Invar)?)?;
+
+        // Then
+        let properties = &schema["properties"];
+        for key in ["write-mode", "interpolate", "interpolate-strict", "props", "props-exec", "placeholder-delimiters", "executable", "stable-random", "template-comment-prefix", "line-endings", "engine", "combine-fragments", "fragment-order", "keep-markers", "anchors", "marker-syntax", "backup", "merge", "mode", "rules", "by-extension"] {
+            assert!(properties.get(key).is_some(), "Missing property {key:?}: {schema}");
+        }
+        Ok(())
+    }
+}
@@ -1,7 +1,77 @@
 use super::*;
 
 use crate::config_model::thundercloud_config_data::ThundercloudConfigData;
+use std::collections::BTreeMap;
+use std::fmt::{self, Display};
 use crate::file_system::ConfigFormat;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use toml::Value;
+
+/// What to do with files in a thundercloud that look like bolts, but whose bolt type is unknown (like `clock+opton.yaml`).
+#[derive(Deserialize,Serialize,JsonSchema,Debug,Clone,Copy,Default,Eq,PartialEq)]
+pub enum UnknownBolts {
+    Error,
+    #[default]
+    Warn,
+    Ignore,
+}
+
+/// The type of the value of a prop that a thundercloud expects.
+#[derive(Deserialize,Serialize,JsonSchema,Debug,Clone,Copy,Eq,PartialEq)]
+pub enum PropType {
+    String,
+    Integer,
+    Float,
+    Boolean,
+    Array,
+    Table,
+}
+
+impl PropType {
+    /// Integers are accepted where floats are expected.
+    pub fn matches(&self, value: &Value) -> bool {
+        matches!((self, value),
+            (PropType::String, Value::String(_)) |
+            (PropType::Integer, Value::Integer(_)) |
+            (PropType::Float, Value::Float(_) | Value::Integer(_)) |
+            (PropType::Boolean, Value::Boolean(_)) |
+            (PropType::Array, Value::Array(_)) |
+            (PropType::Table, Value::Table(_)))
+    }
+}
+
+impl Display for PropType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{self:?}")
+    }
+}
+
+/// A prop that a thundercloud expects, as declared in a `[[props]]` table of `thundercloud.toml`.
+#[derive(Deserialize,Serialize,JsonSchema,Debug,Clone,Eq,PartialEq)]
+pub struct PropDeclaration {
+    name: String,
+    #[serde(rename = "type")]
+    prop_type: Option<PropType>,
+    #[serde(default)]
+    required: bool,
+    description: Option<String>,
+}
+
+impl PropDeclaration {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+    pub fn prop_type(&self) -> Option<PropType> {
+        self.prop_type
+    }
+    pub fn required(&self) -> bool {
+        self.required
+    }
+    pub fn description(&self) -> Option<&str> {
+        self.description.as_deref()
+    }
+}
 
 pub fn from_str(body: &str, config_format: ConfigFormat) -> Result<impl ThundercloudConfig> {
     ThundercloudConfigData::from_str(body, config_format)
@@ -12,6 +82,10 @@ pub trait ThundercloudConfig : Debug + Sized {
     fn from_str(toml_data: &str, config_format: ConfigFormat) -> Result<Self>;
     fn niche(&self) -> &impl NicheDescription;
     fn invar_defaults(&self) -> Cow<Self::InvarConfigImpl>;
+    fn placeholder_delimiters_by_extension(&self) -> Cow<'_, BTreeMap<String, PlaceholderDelimiters>>;
+    fn unknown_bolts(&self) -> UnknownBolts;
+    /// The props that the thundercloud expects.
+    fn prop_declarations(&self) -> &[PropDeclaration];
 }
 
 #[cfg(test)]
@@ -84,6 +158,64 @@ mod test {
 
         let mapping = Table::new();
         assert_eq!(invar_defaults.props().as_ref(), &mapping);
+        assert!(thundercloud_config.placeholder_delimiters_by_extension().is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_placeholder_delimiters_by_extension() -> Result<()> {
+        // Given
+        let toml = indoc! {r#"
+            [niche]
+            name = "example"
+
+            [placeholder-delimiters-by-extension]
+            hbs = { open = "<%", close = "%>" }
+            j2 = { open = "@{", close = "}@" }
+        "#};
+
+        // When
+        let thundercloud_config = from_str(toml, ConfigFormat::TOML)?;
+
+        // Then
+        let by_extension = thundercloud_config.placeholder_delimiters_by_extension();
+        assert_eq!(by_extension.get("hbs"), Some(&PlaceholderDelimiters::new("<%", "%>")));
+        assert_eq!(by_extension.get("j2"), Some(&PlaceholderDelimiters::new("@{", "}@")));
+        assert_eq!(by_extension.get("yaml"), None);
+        Ok(())
+    }
+
+    #[test]
+    fn test_prop_declarations() -> Result<()> {
+        // Given
+        let toml = indoc! {r#"
+            [niche]
+            name = "example"
+
+            [[props]]
+            name = "sweeper"
+            type = "String"
+            required = true
+            description = "Who keeps the clock clean"
+
+            [[props]]
+            name = "hours"
+        "#};
+
+        // When
+        let thundercloud_config = from_str(toml, ConfigFormat::TOML)?;
+
+        // Then
+        let declarations = thundercloud_config.prop_declarations();
+        assert_eq!(declarations.len(), 2);
+        assert_eq!(declarations[0].name(), "sweeper");
+        assert_eq!(declarations[0].prop_type(), Some(PropType::String));
+        assert!(declarations[0].required());
+        assert_eq!(declarations[0].description(), Some("Who keeps the clock clean"));
+        assert_eq!(declarations[1].prop_type(), None);
+        assert!(!declarations[1].required());
+        assert!(PropType::Float.matches(&Value::Integer(7)));
+        assert!(!PropType::Integer.matches(&Value::String("7".to_string())));
         Ok(())
     }
 }
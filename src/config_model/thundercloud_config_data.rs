@@ -1,16 +1,21 @@
 use std::borrow::Cow;
+use std::collections::BTreeMap;
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use anyhow::Result;
 use super::invar_config_data::InvarConfigData;
-use crate::config_model::{NicheDescription, ThundercloudConfig};
+use crate::config_model::{NicheDescription, PlaceholderDelimiters, PropDeclaration, ThundercloudConfig, UnknownBolts};
 use crate::config_model::niche_description::NicheDescriptionData;
 use crate::file_system::ConfigFormat;
 
-#[derive(Deserialize,Serialize,Debug)]
+#[derive(Deserialize,Serialize,JsonSchema,Debug)]
 #[serde(rename_all = "kebab-case")]
 pub struct ThundercloudConfigData {
     niche: NicheDescriptionData,
-    invar_defaults: Option<InvarConfigData>
+    invar_defaults: Option<InvarConfigData>,
+    placeholder_delimiters_by_extension: Option<BTreeMap<String, PlaceholderDelimiters>>,
+    unknown_bolts: Option<UnknownBolts>,
+    props: Option<Vec<PropDeclaration>>,
 }
 
 impl ThundercloudConfig for ThundercloudConfigData {
@@ -46,4 +51,16 @@ impl ThundercloudConfig for ThundercloudConfigData {
         }
         result
     }
+
+    fn placeholder_delimiters_by_extension(&self) -> Cow<'_, BTreeMap<String, PlaceholderDelimiters>> {
+        self.placeholder_delimiters_by_extension.as_ref().map(Cow::Borrowed).unwrap_or_default()
+    }
+
+    fn unknown_bolts(&self) -> UnknownBolts {
+        self.unknown_bolts.unwrap_or_default()
+    }
+
+    fn prop_declarations(&self) -> &[PropDeclaration] {
+        self.props.as_deref().unwrap_or_default()
+    }
 }
@@ -1,11 +1,16 @@
 use std::borrow::Cow;
+use std::collections::BTreeMap;
 use std::fmt::Debug;
+use anyhow::Result;
+use toml::Table;
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
-use crate::config_model::{GitRemoteConfig, InvarConfig, ThunderConfig};
+use crate::config_model::{GitRemoteConfig, InvarConfig, SharedTargetRole, ThunderConfig};
+use crate::config_model::use_thundercloud_config_data::UseThundercloudConfigData;
 use crate::file_system::FileSystem;
 use crate::path::AbsolutePath;
 
-#[derive(Deserialize,Serialize,Debug,Clone,Eq, PartialEq)]
+#[derive(Deserialize,Serialize,JsonSchema,Debug,Clone,Eq, PartialEq)]
 pub enum OnIncoming {
     Update,
     Ignore,
@@ -13,24 +18,44 @@ pub enum OnIncoming {
     Fail
 }
 
+pub fn from_str(body: &str) -> Result<impl UseThundercloudConfig> {
+    Ok(toml::from_str::<UseThundercloudConfigData>(body)?)
+}
+
 pub trait UseThundercloudConfig : Debug + Clone + Send + Sync {
     type InvarConfigImpl : InvarConfig;
     type GitRemoteConfigImpl : GitRemoteConfig;
     fn directory(&self) -> Option<&str>;
+    /// A tar file (optionally gzipped) or zip file that contains the thundercloud, instead of a directory.
+    fn archive(&self) -> Option<&str>;
+    /// The URL of a tarball (or zip file) with the thundercloud, that `igor fetch` downloads into the cache directory.
+    fn url(&self) -> Option<&str>;
+    /// The SHA-256 checksum (in hexadecimal) that the download from the URL must have.
+    fn sha256(&self) -> Option<&str>;
     fn on_incoming(&self) -> &OnIncoming;
     fn features(&self) -> &[String];
+    /// Whether it is an error if several options with the same precedence provide the same target file.
+    fn strict_options(&self) -> bool;
+    /// Whether files that the niche generated before, but no longer generates, are removed (unless they were modified).
+    fn prune_orphans(&self) -> bool;
     fn invar_defaults(&self) -> Cow<Self::InvarConfigImpl>;
     fn git_remote(&self) -> Option<&Self::GitRemoteConfigImpl>;
+    /// The shared target files of the niche, as declared in the psychotropic configuration.
+    fn shared_targets(&self) -> &BTreeMap<String, SharedTargetRole>;
+    fn with_shared_targets(&self, shared_targets: BTreeMap<String, SharedTargetRole>) -> Self;
+    /// The directory that the thundercloud is cloned into if it has a git remote, but no directory.
+    fn cache_directory(&self) -> Option<&AbsolutePath>;
+    fn with_cache_directory(&self, cache_directory: AbsolutePath) -> Self;
+    /// Replaces the placeholders in the directory, archive, URL, fetch URL and revision by the values of the props.
+    fn with_interpolated_settings(&self, props: &Table) -> Self;
     fn new_thunder_config<IC: InvarConfig, TFS: FileSystem, PFS: FileSystem>(&self, default_invar_config: IC, thundercloud_fs: TFS, thundercloud_directory: AbsolutePath, project_fs: PFS, invar: AbsolutePath, project_root: AbsolutePath) -> impl ThunderConfig;
 }
 
 #[cfg(test)]
 pub mod test {
     use super::*;
-    use anyhow::Result;
     use indoc::indoc;
     use crate::config_model::invar_config;
-    use crate::config_model::use_thundercloud_config_data::UseThundercloudConfigData;
     use crate::file_system::fixture;
     use crate::file_system::ConfigFormat::TOML;
     use crate::path::AbsolutePath;
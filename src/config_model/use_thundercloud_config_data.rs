@@ -1,22 +1,35 @@
-use super::{UseThundercloudConfig, OnIncoming, InvarConfig, ThunderConfig};
+use super::{UseThundercloudConfig, OnIncoming, InvarConfig, SharedTargetRole, ThunderConfig};
 use super::git_remote_config_data::GitRemoteConfigData;
 use super::invar_config_data::InvarConfigData;
 use std::borrow::Cow;
+use std::collections::BTreeMap;
 use once_cell::sync::Lazy;
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
+use toml::Table;
 use crate::config_model::invar_config::invar_config_or_default;
 use crate::config_model::thunder_config_data::ThunderConfigData;
 use crate::file_system::FileSystem;
+use crate::interpolate::interpolate;
 use crate::path::AbsolutePath;
 
-#[derive(Deserialize,Serialize,Debug,Clone)]
+#[derive(Deserialize,Serialize,JsonSchema,Debug,Clone)]
 #[serde(rename_all = "kebab-case")]
 pub struct UseThundercloudConfigData {
     directory: Option<String>,
+    archive: Option<String>,
+    url: Option<String>,
+    sha256: Option<String>,
     git_remote: Option<GitRemoteConfigData>,
     on_incoming: Option<OnIncoming>,
     features: Option<Vec<String>>,
+    strict_options: Option<bool>,
+    prune_orphans: Option<bool>,
     invar_defaults: Option<InvarConfigData>,
+    #[serde(skip)]
+    shared_targets: BTreeMap<String, SharedTargetRole>,
+    #[serde(skip)]
+    cache_directory: Option<AbsolutePath>,
 }
 
 static UPDATE: Lazy<OnIncoming> = Lazy::new(|| OnIncoming::Update);
@@ -29,18 +42,52 @@ impl UseThundercloudConfig for UseThundercloudConfigData {
     fn directory(&self) -> Option<&str> {
         self.directory.as_ref().map(String::as_ref)
     }
+    fn archive(&self) -> Option<&str> {
+        self.archive.as_deref()
+    }
+    fn url(&self) -> Option<&str> {
+        self.url.as_deref()
+    }
+    fn sha256(&self) -> Option<&str> {
+        self.sha256.as_deref()
+    }
     fn on_incoming(&self) -> &OnIncoming {
         &self.on_incoming.as_ref().unwrap_or(&UPDATE)
     }
     fn features(&self) -> &[String] {
         &self.features.as_deref().unwrap_or(&EMPTY_VEC)
     }
+    fn strict_options(&self) -> bool {
+        self.strict_options.unwrap_or(false)
+    }
+    fn prune_orphans(&self) -> bool {
+        self.prune_orphans.unwrap_or(false)
+    }
     fn invar_defaults(&self) -> Cow<Self::InvarConfigImpl> {
         invar_config_or_default(&self.invar_defaults)
     }
     fn git_remote(&self) -> Option<&Self::GitRemoteConfigImpl> {
         self.git_remote.as_ref()
     }
+    fn shared_targets(&self) -> &BTreeMap<String, SharedTargetRole> {
+        &self.shared_targets
+    }
+    fn with_shared_targets(&self, shared_targets: BTreeMap<String, SharedTargetRole>) -> Self {
+        UseThundercloudConfigData { shared_targets, ..self.clone() }
+    }
+    fn cache_directory(&self) -> Option<&AbsolutePath> {
+        self.cache_directory.as_ref()
+    }
+    fn with_cache_directory(&self, cache_directory: AbsolutePath) -> Self {
+        UseThundercloudConfigData { cache_directory: Some(cache_directory), ..self.clone() }
+    }
+    fn with_interpolated_settings(&self, props: &Table) -> Self {
+        let directory = self.directory.as_ref().map(|directory| interpolate(directory, props).into_owned());
+        let archive = self.archive.as_ref().map(|archive| interpolate(archive, props).into_owned());
+        let url = self.url.as_ref().map(|url| interpolate(url, props).into_owned());
+        let git_remote = self.git_remote.as_ref().map(|git_remote| git_remote.interpolated(props));
+        UseThundercloudConfigData { directory, archive, url, git_remote, ..self.clone() }
+    }
     fn new_thunder_config<IC: InvarConfig, TFS: FileSystem, PFS: FileSystem>(&self, default_invar_config: IC, thundercloud_fs: TFS, thundercloud_directory: AbsolutePath, project_fs: PFS, invar: AbsolutePath, project_root: AbsolutePath) -> impl ThunderConfig {
         ThunderConfigData::new(
             self.clone(),
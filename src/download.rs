@@ -0,0 +1,42 @@
+use anyhow::{bail, Result};
+use log::debug;
+use sha2::{Digest, Sha256};
+use tokio::process::Command;
+use crate::hooks::AllowedCommands;
+
+/// Downloads the content at a URL with `curl`. Fails if the server responds with an error, or if `curl` is not one of
+/// the allowed commands.
+pub async fn curl(url: &str, allowed_commands: &AllowedCommands) -> Result<Vec<u8>> {
+    debug!("Download: {url:?}");
+    allowed_commands.check_program("curl")?;
+    let output = Command::new("curl")
+        .args(["--silent", "--show-error", "--fail", "--location", url])
+        .output()
+        .await?;
+    if !output.status.success() {
+        bail!("curl {url}: {}", String::from_utf8_lossy(&output.stderr).trim());
+    }
+    Ok(output.stdout)
+}
+
+/// Fails unless the SHA-256 checksum of the content is the expected checksum (in hexadecimal, in either case).
+pub fn verify_checksum(content: &[u8], expected: &str) -> Result<()> {
+    let actual: String = Sha256::digest(content).iter().map(|byte| format!("{byte:02x}")).collect();
+    if !actual.eq_ignore_ascii_case(expected.trim()) {
+        bail!("Checksum mismatch: expected {}, got {actual}", expected.trim());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn checksum() {
+        let sha256 = "773079AD807A9694223D69EA5C9A05B0E98A74044A0E5D72AD0FCFCD0B72F20B\n";
+        assert!(verify_checksum(b"Igor", sha256).is_ok());
+        let mismatch = verify_checksum(b"Egor", sha256);
+        assert!(mismatch.unwrap_err().to_string().starts_with("Checksum mismatch: expected 773079AD"));
+    }
+}
@@ -0,0 +1,163 @@
+use std::path::PathBuf;
+use anyhow::Result;
+use toml::Table;
+use crate::config_model::{NicheTriggers, ProjectConfig, PsychotropicConfig};
+use crate::file_system::FileSystem;
+use crate::path::AbsolutePath;
+use crate::run_options::RunOptions;
+use crate::niche::UseThundercloudDefaults;
+use crate::{get_project_config, get_project_root, get_use_thundercloud, niche, NicheName};
+
+pub async fn show_effective_config<FS: FileSystem>(project_root_option: Option<PathBuf>, path: PathBuf, run_options: &RunOptions, fs: &FS) -> Result<()> {
+    let project_root = get_project_root(project_root_option)?;
+    let target = AbsolutePath::new(path, &project_root);
+    for (niche_name, (source, config)) in get_effective_configs(&project_root, &target, run_options, fs).await? {
+        println!("{}", render(&project_root, &niche_name, &target, &source, config)?);
+    }
+    Ok(())
+}
+
+async fn get_effective_configs<FS: FileSystem>(project_root: &AbsolutePath, target: &AbsolutePath, run_options: &RunOptions, fs: &FS) -> Result<Vec<(NicheName, (AbsolutePath, Table))>> {
+    let project_config = get_project_config(project_root, fs).await?;
+    let niches_directory = project_config.niches_directory();
+    let psychotropic = project_config.psychotropic()?;
+    let use_thundercloud_defaults = UseThundercloudDefaults::new(project_root, &project_config);
+    let mut effective_configs = Vec::new();
+    for niche_triggers in psychotropic.values() {
+        let niche_name = NicheName::new(niche_triggers.name());
+        let Some(use_thundercloud) = get_use_thundercloud(Some(&niche_triggers), &use_thundercloud_defaults, fs).await? else { continue };
+        let effective_config = niche::effective_config(project_root.clone(), niches_directory.clone(), niche_name.clone(), use_thundercloud, project_config.invar_defaults().into_owned(), run_options, fs.clone(), target).await?;
+        if let Some(effective_config) = effective_config {
+            effective_configs.push((niche_name, effective_config));
+        }
+    }
+    Ok(effective_configs)
+}
+
+fn render(project_root: &AbsolutePath, niche_name: &NicheName, target: &AbsolutePath, source: &AbsolutePath, config: Table) -> Result<String> {
+    let display = |path: &AbsolutePath| path.strip_prefix(project_root.as_path()).unwrap_or(path.as_path()).to_string_lossy().to_string();
+    let mut header = format!("# Niche {:?}: {:?}", niche_name.to_str(), display(target));
+    if source != target {
+        header.push_str(&format!(" (inherited from directory {:?})", display(source)));
+    }
+    Ok(format!("{header}\n{}", toml::to_string(&config)?))
+}
+
+#[cfg(test)]
+mod test {
+    use indoc::indoc;
+    use test_log::test;
+    use crate::file_system::fixture;
+    use crate::path::test_utils::to_absolute_path;
+    use super::*;
+
+    #[test(tokio::test)]
+    async fn effective_config_of_file_and_directory() -> Result<()> {
+        // Given
+        let toml_data = indoc! {r#"
+            "CargoCult.toml" = '''
+            [[psychotropic.cues]]
+            name = "example"
+            use-thundercloud = { directory = "{{PROJECT}}/example-thundercloud" }
+
+            [invar-defaults.props]
+            sweeper = "Lu Tse"
+            '''
+
+            [yeth-marthter.example.invar.workshop]
+            "dot_+config.toml" = '''
+            write-mode = "WriteNew"
+            '''
+
+            [example-thundercloud]
+            "thundercloud.toml" = """
+            [niche]
+            name = "example"
+            """
+
+            [example-thundercloud.cumulus.workshop]
+            "clock+option-@.yaml" = '''
+            sweeper: "{{sweeper}}"
+            '''
+            "clock+config-@.yaml.toml" = '''
+            interpolate = false
+            '''
+
+            [example-thundercloud.cumulus.workshop.drawer]
+            "spring+option-@.yaml" = '''
+            material: glass
+            '''
+        "#};
+        let fs = fixture::from_toml(toml_data)?;
+        let project_root = AbsolutePath::root();
+
+        // When
+        let clock_configs = get_effective_configs(&project_root, &to_absolute_path("/workshop/clock.yaml"), &RunOptions::default(), &fs).await?;
+        let spring_configs = get_effective_configs(&project_root, &to_absolute_path("/workshop/drawer/spring.yaml"), &RunOptions::default(), &fs).await?;
+
+        // Then
+        let [(niche_name, (source, config))] = clock_configs.as_slice() else { panic!("Expected one niche: {clock_configs:?}") };
+        let rendered = render(&project_root, niche_name, &to_absolute_path("/workshop/clock.yaml"), source, config.clone())?;
+        let expected = indoc! {r#"
+            # Niche "example": "workshop/clock.yaml"
+            interpolate = false
+            write-mode = "WriteNew"
+
+            [placeholder-delimiters]
+            close = "}}"
+            open = "{{"
+
+            [props]
+            sweeper = "Lu Tse"
+        "#};
+        assert_eq!(rendered, expected);
+
+        let [(_, (source, config))] = spring_configs.as_slice() else { panic!("Expected one niche: {spring_configs:?}") };
+        assert_eq!(source, &to_absolute_path("/workshop/drawer/spring.yaml"));
+        assert_eq!(config.get("write-mode").and_then(toml::Value::as_str), Some("WriteNew"));
+        assert_eq!(config.get("interpolate").and_then(toml::Value::as_bool), Some(true));
+        Ok(())
+    }
+
+    #[test(tokio::test)]
+    async fn effective_config_inherited_from_directory() -> Result<()> {
+        // Given
+        let toml_data = indoc! {r#"
+            "CargoCult.toml" = '''
+            [[psychotropic.cues]]
+            name = "example"
+            use-thundercloud = { directory = "{{PROJECT}}/example-thundercloud" }
+            '''
+
+            [yeth-marthter.example.invar.workshop]
+            "dot_+config.toml" = '''
+            write-mode = "Ignore"
+            '''
+
+            [example-thundercloud]
+            "thundercloud.toml" = """
+            [niche]
+            name = "example"
+            """
+
+            [example-thundercloud.cumulus.workshop]
+            "clock+option-@.yaml" = '''
+            sweeper: Lu Tse
+            '''
+        "#};
+        let fs = fixture::from_toml(toml_data)?;
+        let project_root = AbsolutePath::root();
+        let target = to_absolute_path("/workshop/mirror.yaml");
+
+        // When
+        let configs = get_effective_configs(&project_root, &target, &RunOptions::default(), &fs).await?;
+
+        // Then
+        let [(niche_name, (source, config))] = configs.as_slice() else { panic!("Expected one niche: {configs:?}") };
+        assert_eq!(source, &to_absolute_path("/workshop"));
+        let rendered = render(&project_root, niche_name, &target, source, config.clone())?;
+        assert_eq!(rendered.lines().next(), Some(r#"# Niche "example": "workshop/mirror.yaml" (inherited from directory "workshop")"#));
+        assert_eq!(config.get("write-mode").and_then(toml::Value::as_str), Some("Ignore"));
+        Ok(())
+    }
+}
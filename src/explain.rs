@@ -0,0 +1,126 @@
+use std::path::PathBuf;
+use anyhow::{bail, Result};
+use crate::config_model::{NicheTriggers, ProjectConfig, PsychotropicConfig};
+use crate::file_system::FileSystem;
+use crate::path::AbsolutePath;
+use crate::run_options::RunOptions;
+use crate::thundercloud::Explanation;
+use crate::niche::UseThundercloudDefaults;
+use crate::{get_project_config, get_project_root, get_use_thundercloud, niche, NicheName};
+
+pub async fn explain<FS: FileSystem>(project_root_option: Option<PathBuf>, path: PathBuf, run_options: &RunOptions, fs: &FS) -> Result<()> {
+    let project_root = get_project_root(project_root_option)?;
+    let target = AbsolutePath::new(path, &project_root);
+    let explanations = get_explanations(&project_root, &target, run_options, fs).await?;
+    if explanations.is_empty() {
+        bail!("No niche produces {:?}", target);
+    }
+    for (niche_name, explanation) in explanations {
+        println!("{}", render(&project_root, &niche_name, &target, &explanation)?);
+    }
+    Ok(())
+}
+
+async fn get_explanations<FS: FileSystem>(project_root: &AbsolutePath, target: &AbsolutePath, run_options: &RunOptions, fs: &FS) -> Result<Vec<(NicheName, Explanation)>> {
+    let project_config = get_project_config(project_root, fs).await?;
+    let niches_directory = project_config.niches_directory();
+    let psychotropic = project_config.psychotropic()?;
+    let use_thundercloud_defaults = UseThundercloudDefaults::new(project_root, &project_config);
+    let mut explanations = Vec::new();
+    for niche_triggers in psychotropic.values() {
+        let niche_name = NicheName::new(niche_triggers.name());
+        let Some(use_thundercloud) = get_use_thundercloud(Some(&niche_triggers), &use_thundercloud_defaults, fs).await? else { continue };
+        let explanation = niche::explain(project_root.clone(), niches_directory.clone(), niche_name.clone(), use_thundercloud, project_config.invar_defaults().into_owned(), run_options, fs.clone(), target).await?;
+        if let Some(explanation) = explanation {
+            explanations.push((niche_name, explanation));
+        }
+    }
+    Ok(explanations)
+}
+
+fn render(project_root: &AbsolutePath, niche_name: &NicheName, target: &AbsolutePath, explanation: &Explanation) -> Result<String> {
+    let display = target.strip_prefix(project_root.as_path()).unwrap_or(target.as_path()).to_string_lossy().to_string();
+    Ok(format!("# Niche {:?}: {:?}\n{}", niche_name.to_str(), display, toml::to_string(explanation)?))
+}
+
+#[cfg(test)]
+mod test {
+    use indoc::indoc;
+    use test_log::test;
+    use crate::file_system::fixture;
+    use crate::path::test_utils::to_absolute_path;
+    use super::*;
+
+    #[test(tokio::test)]
+    async fn explain_target_file() -> Result<()> {
+        // Given
+        let toml_data = indoc! {r#"
+            "CargoCult.toml" = '''
+            [[psychotropic.cues]]
+            name = "example"
+            use-thundercloud = { directory = "{{PROJECT}}/example-thundercloud", features = ["glass"] }
+            '''
+
+            [yeth-marthter.example.invar.workshop]
+            "dot_+config.toml" = '''
+            write-mode = "WriteNew"
+            '''
+            "clock+fragment-glass.yaml" = '''
+            # ==== BEGIN FRAGMENT glass ====
+            face: glass
+            # ==== END FRAGMENT glass ====
+            '''
+
+            [example-thundercloud]
+            "thundercloud.toml" = """
+            [niche]
+            name = "example"
+            """
+
+            [example-thundercloud.cumulus.workshop]
+            "clock+option-glass.yaml" = '''
+            sweeper: Lu Tse
+            '''
+            "clock+option-@-fallback.yaml" = '''
+            sweeper: nobody
+            '''
+            "clock+config-@.yaml.toml" = '''
+            interpolate = false
+            [props]
+            sweeper = "Lu Tse"
+            '''
+        "#};
+        let fs = fixture::from_toml(toml_data)?;
+        let project_root = AbsolutePath::root();
+        let target = to_absolute_path("/workshop/clock.yaml");
+
+        // When
+        let explanations = get_explanations(&project_root, &target, &RunOptions::default(), &fs).await?;
+        let missing = get_explanations(&project_root, &to_absolute_path("/workshop/mirror.yaml"), &RunOptions::default(), &fs).await?;
+
+        // Then
+        let [(niche_name, explanation)] = explanations.as_slice() else { panic!("Expected one niche: {explanations:?}") };
+        let rendered = render(&project_root, niche_name, &target, explanation)?;
+        let expected = indoc! {r#"
+            # Niche "example": "workshop/clock.yaml"
+            features = ["glass"]
+            option = "thundercloud:cumulus/workshop/clock+option-glass.yaml"
+            fragments = ["project:yeth-marthter/example/invar/workshop/clock+fragment-glass.yaml"]
+            configs = ["project:yeth-marthter/example/invar/workshop/dot_+config.toml", "thundercloud:cumulus/workshop/clock+config-@.yaml.toml"]
+
+            [invar-config]
+            interpolate = false
+            write-mode = "WriteNew"
+
+            [invar-config.placeholder-delimiters]
+            close = "}}"
+            open = "{{"
+
+            [invar-config.props]
+            sweeper = "Lu Tse"
+        "#};
+        assert_eq!(rendered, expected);
+        assert!(missing.is_empty());
+        Ok(())
+    }
+}
@@ -0,0 +1,137 @@
+use std::collections::BTreeSet;
+use std::path::PathBuf;
+use anyhow::Result;
+use crate::config_model::{NicheTriggers, ProjectConfig, PsychotropicConfig, UseThundercloudConfig};
+use crate::file_system::{FileSystem, PathType};
+use crate::niche::{get_thundercloud_directory, thundercloud_file_system, UseThundercloudDefaults};
+use crate::path::AbsolutePath;
+use crate::run_options::RunOptions;
+use crate::{get_project_config, get_project_root, get_use_thundercloud, niche, NicheName};
+
+pub async fn features<FS: FileSystem>(project_root_option: Option<PathBuf>, run_options: &RunOptions, fs: &FS) -> Result<()> {
+    let project_root = get_project_root(project_root_option)?;
+    for usage in feature_usage(&project_root, run_options, fs).await? {
+        print!("{usage}");
+    }
+    Ok(())
+}
+
+/// Describes for every niche of the project (sorted by name) which features are enabled and which features the names of
+/// its bolts mention. Features that are enabled, but never mentioned, usually point to a typo.
+async fn feature_usage<FS: FileSystem>(project_root: &AbsolutePath, run_options: &RunOptions, fs: &FS) -> Result<Vec<String>> {
+    let project_config = get_project_config(project_root, fs).await?;
+    let psychotropic = project_config.psychotropic()?;
+    let use_thundercloud_defaults = UseThundercloudDefaults::new(project_root, &project_config);
+    let mut niches = psychotropic.values();
+    niches.sort_by_key(NicheTriggers::name);
+    let mut usages = Vec::new();
+    for niche_triggers in niches {
+        let name = niche_triggers.name();
+        let Some(use_thundercloud) = get_use_thundercloud(Some(&niche_triggers), &use_thundercloud_defaults, fs).await? else { continue };
+        let available = match get_thundercloud_directory(project_root, &use_thundercloud)? {
+            Some(directory) if fs.path_type(&directory).await != PathType::Missing => {
+                thundercloud_file_system(project_root, &use_thundercloud, fs.clone()).await?.path_type(&directory).await == PathType::Directory
+            },
+            Some(_) => false,
+            None => false,
+        };
+        if !available {
+            usages.push(format!("{name}: thundercloud not available\n"));
+            continue;
+        }
+        let enabled = use_thundercloud.features().to_vec();
+        let niches_directory = project_config.niches_directory();
+        let defined = niche::defined_features(project_root.clone(), niches_directory, NicheName::new(name.clone()), use_thundercloud, project_config.invar_defaults().into_owned(), run_options, fs.clone()).await?
+            .unwrap_or_default();
+        usages.push(render(&name, &enabled, &defined));
+    }
+    Ok(usages)
+}
+
+fn render(name: &str, enabled: &[String], defined: &BTreeSet<String>) -> String {
+    let mut usage = format!("{name}\n");
+    usage.push_str(&format!("  enabled: {}\n", join_or_none(enabled.iter())));
+    usage.push_str(&format!("  defined: {}\n", join_or_none(defined.iter())));
+    let unused: Vec<&String> = defined.iter().filter(|feature| !enabled.contains(feature)).collect();
+    for feature in enabled.iter().filter(|feature| !defined.contains(*feature)) {
+        match unused.iter().find(|candidate| normalize(candidate) == normalize(feature)) {
+            Some(candidate) => usage.push_str(&format!("  ! enabled, but unknown: {feature} (did you mean {candidate}?)\n")),
+            None => usage.push_str(&format!("  ! enabled, but unknown: {feature}\n")),
+        }
+    }
+    if !unused.is_empty() {
+        usage.push_str(&format!("  defined, but unused: {}\n", join_or_none(unused.into_iter())));
+    }
+    usage
+}
+
+fn join_or_none<'a>(features: impl Iterator<Item = &'a String>) -> String {
+    let features: Vec<&str> = features.map(String::as_str).collect();
+    if features.is_empty() {
+        "(none)".to_string()
+    } else {
+        features.join(", ")
+    }
+}
+
+/// Lowercase without separators, so that `bash-config`, `Bash_Config` and `bash_config` look the same.
+fn normalize(feature: &str) -> String {
+    feature.chars().filter(char::is_ascii_alphanumeric).map(|c| c.to_ascii_lowercase()).collect()
+}
+
+#[cfg(test)]
+mod test {
+    use indoc::indoc;
+    use test_log::test;
+    use crate::file_system::fixture;
+    use super::*;
+
+    #[test(tokio::test)]
+    async fn report_unknown_and_unused_features() -> Result<()> {
+        // Given
+        let toml_data = indoc! {r#"
+            "CargoCult.toml" = '''
+            [[psychotropic.cues]]
+            name = "example"
+            use-thundercloud = { directory = "{{PROJECT}}/example-thundercloud", features = ["bash-config", "glass", "brass"] }
+
+            [[psychotropic.cues]]
+            name = "remote"
+            use-thundercloud = { directory = "{{PROJECT}}/remote-thundercloud" }
+            '''
+
+            [yeth-marthter.example.invar.workshop]
+            "mirror+option-silver.yaml" = "frame: silver"
+
+            [example-thundercloud]
+            "thundercloud.toml" = """
+            [niche]
+            name = "example"
+            """
+
+            [example-thundercloud.cumulus.workshop]
+            "clock+option-glass.yaml" = "sweeper: Lu Tse"
+            "clock+option-@.yaml" = "sweeper: nobody"
+            "clock+fragment-bash_config-alarm.yaml" = "alarm: true"
+            "clock+config-wood.toml" = ""
+        "#};
+        let fs = fixture::from_toml(toml_data)?;
+
+        // When
+        let usages = feature_usage(&AbsolutePath::root(), &RunOptions::default(), &fs).await?;
+
+        // Then
+        assert_eq!(usages, vec![
+            indoc! {"
+                example
+                  enabled: bash-config, glass, brass
+                  defined: bash_config, glass, silver, wood
+                  ! enabled, but unknown: bash-config (did you mean bash_config?)
+                  ! enabled, but unknown: brass
+                  defined, but unused: bash_config, silver, wood
+            "}.to_string(),
+            "remote: thundercloud not available\n".to_string(),
+        ]);
+        Ok(())
+    }
+}
@@ -0,0 +1,201 @@
+use std::io::IsTerminal;
+use std::path::PathBuf;
+use anyhow::{anyhow, bail, Result};
+use log::{debug, info, warn};
+use crate::config_model::{GitRemoteConfig, NetworkAccess, NicheTriggers, ProjectConfig, PsychotropicConfig, UseThundercloudConfig};
+use crate::file_system::{unpack, FileSystem, PathType};
+use crate::niche::{get_thundercloud_directory, UseThundercloudDefaults};
+use crate::git::GitProgress;
+use crate::hooks::AllowedCommands;
+use crate::path::AbsolutePath;
+use crate::run_options::RunOptions;
+use crate::{download, get_project_config, get_project_root, git};
+
+pub async fn fetch<FS: FileSystem>(project_root_option: Option<PathBuf>, run_options: &RunOptions, fs: &FS) -> Result<()> {
+    let project_root = get_project_root(project_root_option)?;
+    for (niche, commit) in fetch_thunderclouds(&project_root, run_options, fs).await? {
+        println!("Fetched {niche}: {commit}");
+    }
+    Ok(())
+}
+
+/// Clones or updates the thunderclouds of all niches that have a git remote, and checks out their revisions, and
+/// downloads the thunderclouds of all niches that have a URL. Returns the names of the niches with the commits that were
+/// checked out (or the checksums of the downloads). Fails before fetching anything if a niche has a git remote or a URL,
+/// but network access is forbidden (by the project configuration or by `--offline`).
+pub(crate) async fn fetch_thunderclouds<FS: FileSystem>(project_root: &AbsolutePath, run_options: &RunOptions, fs: &FS) -> Result<Vec<(String, String)>> {
+    let project_config = get_project_config(project_root, fs).await?;
+    let run_options = run_options.clone().with_allowed_hook_commands(&project_config);
+    let allowed_commands = run_options.allowed_commands();
+    let network = if run_options.offline() { NetworkAccess::Forbidden } else { project_config.network() };
+    let psychotropic = project_config.psychotropic()?;
+    if network == NetworkAccess::Forbidden {
+        for niche_triggers in psychotropic.values() {
+            let Some(use_thundercloud) = niche_triggers.use_thundercloud() else { continue };
+            if let Some(git_remote) = use_thundercloud.git_remote() {
+                bail!("Network access is forbidden, but niche {:?} has git remote: {:?}", niche_triggers.name(), git_remote.fetch_url());
+            }
+            if let Some(url) = use_thundercloud.url() {
+                bail!("Network access is forbidden, but niche {:?} has URL: {:?}", niche_triggers.name(), url);
+            }
+        }
+    }
+    let use_thundercloud_defaults = UseThundercloudDefaults::new(project_root, &project_config);
+    let mut fetched = Vec::new();
+    for niche_triggers in psychotropic.values() {
+        let Some(use_thundercloud) = niche_triggers.use_thundercloud() else { continue };
+        let use_thundercloud = use_thundercloud_defaults.apply(use_thundercloud);
+        if let Some(url) = use_thundercloud.url() {
+            let Some(directory) = get_thundercloud_directory(project_root, &use_thundercloud)? else {
+                warn!("Niche has a URL, but no thundercloud directory: {:?}", niche_triggers.name());
+                continue;
+            };
+            let sha256 = use_thundercloud.sha256().unwrap_or_default();
+            download_thundercloud(url, sha256, &directory, allowed_commands, fs).await?;
+            fetched.push((niche_triggers.name(), sha256.to_string()));
+            continue;
+        }
+        let Some(git_remote) = use_thundercloud.git_remote() else { continue };
+        let Some(directory) = get_thundercloud_directory(project_root, &use_thundercloud)? else {
+            warn!("Niche has a git remote, but no thundercloud directory: {:?}", niche_triggers.name());
+            continue;
+        };
+        let niche = niche_triggers.name();
+        let on_progress = |progress: GitProgress| report_progress(&niche, &progress);
+        let commit = git::fetch_revision(allowed_commands, &directory, git_remote.fetch_url(), git_remote.revision(), &on_progress).await?;
+        fetched.push((niche, commit));
+    }
+    Ok(fetched)
+}
+
+/// Downloads a tarball (or zip file) with a thundercloud and unpacks it, unless the directory exists already. Because the
+/// directory is named after the checksum, an existing directory has the right content.
+pub(crate) async fn download_thundercloud<FS: FileSystem>(url: &str, sha256: &str, directory: &AbsolutePath, allowed_commands: &AllowedCommands, fs: &FS) -> Result<()> {
+    if fs.path_type(directory).await == PathType::Directory {
+        debug!("Already downloaded: {url:?}: {directory:?}");
+        return Ok(());
+    }
+    info!("Download: {url:?} ⇒ {directory:?}");
+    let tarball = download::curl(url, allowed_commands).await?;
+    install_download(tarball, url, sha256, directory, fs).await
+}
+
+/// Verifies the checksum of a download and unpacks it in a staging directory next to the thundercloud directory, which
+/// is then renamed, so that the thundercloud directory is never half-unpacked. Niches that download the same
+/// thundercloud at the same time each get their own staging directory; the first one to finish wins.
+async fn install_download<FS: FileSystem>(tarball: Vec<u8>, url: &str, sha256: &str, directory: &AbsolutePath, fs: &FS) -> Result<()> {
+    download::verify_checksum(&tarball, sha256).map_err(|error| anyhow!("{url}: {error}"))?;
+    let mut staging = directory.as_os_str().to_owned();
+    staging.push(format!(".new-{:08x}", fastrand::u32(..)));
+    let staging = AbsolutePath::try_new(PathBuf::from(staging))?;
+    unpack(tarball, &staging, fs).await?;
+    if fs.path_type(directory).await == PathType::Directory {
+        debug!("Downloaded by another niche: {url:?}: {directory:?}");
+        return fs.remove_dir_all(&staging).await;
+    }
+    fs.rename(&staging, directory).await
+}
+
+/// Shows the progress of a clone or fetch on the line of the niche when standard error is a terminal, so that a slow
+/// network does not look like a stuck run, and logs it otherwise.
+fn report_progress(niche: &str, progress: &GitProgress) {
+    if std::io::stderr().is_terminal() {
+        let end = if progress.percent == 100 { "\n" } else { "" };
+        eprint!("\r{niche}: {}: {}%{end}", progress.phase, progress.percent);
+    } else {
+        debug!("Fetch {niche}: {}: {}%", progress.phase, progress.percent);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use indoc::indoc;
+    use test_log::test;
+    use sha2::{Digest, Sha256};
+    use tokio_stream::StreamExt;
+    use crate::file_system::fixture;
+    use crate::path::test_utils::to_absolute_path;
+    use super::*;
+
+    #[test(tokio::test)]
+    async fn network_forbidden() -> Result<()> {
+        // Given
+        let toml_data = indoc! {r#"
+            "CargoCult.toml" = '''
+            network = "Forbidden"
+
+            [[psychotropic.cues]]
+            name = "example"
+            use-thundercloud = { directory = "/example-thundercloud", git-remote = { fetch-url = "https://github.com/rustigaan/example-thundercloud.git", revision = "main" } }
+
+            [[psychotropic.cues]]
+            name = "vendored"
+            use-thundercloud = { directory = "/vendored-thundercloud" }
+            '''
+        "#};
+        let fs = fixture::from_toml(toml_data)?;
+
+        // When
+        let result = fetch_thunderclouds(&AbsolutePath::root(), &RunOptions::default(), &fs).await;
+
+        // Then
+        let Err(error) = result else { panic!("Expected a policy error") };
+        assert!(error.to_string().starts_with("Network access is forbidden, but niche \"example\" has git remote"), "Actual error: {error}");
+        Ok(())
+    }
+
+    #[test(tokio::test)]
+    async fn offline_without_git_remotes() -> Result<()> {
+        // Given
+        let toml_data = indoc! {r#"
+            "CargoCult.toml" = '''
+            [[psychotropic.cues]]
+            name = "vendored"
+            use-thundercloud = { directory = "/vendored-thundercloud" }
+            '''
+        "#};
+        let fs = fixture::from_toml(toml_data)?;
+
+        // When
+        let fetched = fetch_thunderclouds(&AbsolutePath::root(), &RunOptions::default().with_offline(true), &fs).await?;
+
+        // Then
+        assert!(fetched.is_empty());
+        Ok(())
+    }
+
+    #[test(tokio::test)]
+    async fn install_verified_download() -> Result<()> {
+        // Given
+        let fs = fixture::from_toml("")?;
+        let mut builder = tar::Builder::new(flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default()));
+        for (path, content, mode) in [
+            ("example-thundercloud-1.0/thundercloud.toml", "[niche]\nname = \"example\"\n", 0o644),
+            ("example-thundercloud-1.0/cumulus/workshop/wind+option-@.sh", "#!/bin/sh\n", 0o755),
+        ] {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(content.len() as u64);
+            header.set_mode(mode);
+            builder.append_data(&mut header, path, content.as_bytes())?;
+        }
+        let tarball = builder.into_inner()?.finish()?;
+        let sha256 = format!("{:x}", Sha256::digest(&tarball));
+        let url = "https://example.com/example-thundercloud-1.0.tar.gz";
+        let directory = to_absolute_path("/cache/example-thundercloud-1.0");
+
+        // When
+        let mismatch = install_download(tarball.clone(), url, &"0".repeat(64), &directory, &fs).await;
+        let missing_after_mismatch = fs.path_type(&directory).await == PathType::Missing;
+        install_download(tarball.clone(), url, &sha256, &directory, &fs).await?;
+        install_download(tarball, url, &sha256, &directory, &fs).await?;
+
+        // Then
+        assert!(mismatch.unwrap_err().to_string().starts_with("https://example.com/example-thundercloud-1.0.tar.gz: Checksum mismatch"));
+        assert!(missing_after_mismatch);
+        assert_eq!(fs.get_content(to_absolute_path("/cache/example-thundercloud-1.0/thundercloud.toml")).await?, "[niche]\nname = \"example\"\n");
+        fixture::assert_executable(&fs, "/cache/example-thundercloud-1.0/cumulus/workshop/wind+option-@.sh").await?;
+        let cache_entries: Vec<_> = fs.read_dir(&to_absolute_path("/cache")).await?.collect().await;
+        assert_eq!(cache_entries.len(), 1, "Staging directories should be gone");
+        Ok(())
+    }
+}
@@ -1,25 +1,119 @@
 use std::ffi::OsString;
-use anyhow::{anyhow, Result};
-use std::fmt::Debug;
+use anyhow::{anyhow, bail, Result};
+use std::fmt::{self, Debug};
+use std::io::ErrorKind;
 use std::future::Future;
-use std::path::PathBuf;
+use std::path::{Component, Path, PathBuf};
+use std::time::SystemTime;
+use sha2::{Digest, Sha256};
 use tokio::sync::mpsc::Sender;
 use tokio_stream::Stream;
-use crate::config_model::WriteMode;
+use crate::config_model::{LineEndings, WriteMode};
+use crate::memory::MemoryGuard;
 use crate::path::AbsolutePath;
 
 mod real;
+pub use real::{real_file_system_with_max_line_length, DEFAULT_MAX_LINE_LENGTH};
+mod redirect;
+use redirect::RedirectedFileSystem;
+mod archive;
+pub use archive::{unpack, ArchiveFileSystem};
+#[cfg(test)]
 pub use real::real_file_system;
 
-#[cfg(test)]
+#[cfg(any(test, feature = "fixture"))]
 pub mod fixture;
 
+#[cfg(any(test, feature = "testkit"))]
+pub mod conformance;
+
+/// The type of a path. Only `symlink_metadata` reports `Symlink`: the other methods follow symbolic links.
 #[derive(Eq, PartialEq, Debug, Copy, Clone)]
-pub enum PathType { Missing, File, Directory, Other }
+pub enum PathType { Missing, File, Directory, Symlink, Other }
 
 #[derive(Debug, Copy, Clone)]
 pub enum ConfigFormat { TOML, YAML }
 
+/// The type, permission bits, length and modification time of a path. The mode has the usual Unix meaning (like
+/// `0o644`). Comparing the length and modification time of a file with those of an earlier run is a cheap way to tell
+/// that it did not change since.
+#[derive(Eq, PartialEq, Debug, Copy, Clone)]
+pub struct Metadata {
+    path_type: PathType,
+    mode: u32,
+    len: u64,
+    modified: Option<SystemTime>,
+}
+
+impl Metadata {
+    pub fn new(path_type: PathType, mode: u32, len: u64, modified: Option<SystemTime>) -> Self {
+        Metadata { path_type, mode, len, modified }
+    }
+
+    pub fn path_type(&self) -> PathType {
+        self.path_type
+    }
+
+    pub fn mode(&self) -> u32 {
+        self.mode
+    }
+
+    /// The length of a file in bytes.
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// The time of the last change to the content, if the platform keeps track of it.
+    pub fn modified(&self) -> Option<SystemTime> {
+        self.modified
+    }
+
+    /// A file is executable if anyone may execute it.
+    pub fn is_executable(&self) -> bool {
+        self.path_type == PathType::File && self.mode & 0o111 != 0
+    }
+}
+
+/// An error that makes further writes pointless, like a full disk. The file that was being written may be truncated.
+#[derive(Debug)]
+pub struct FatalWriteError {
+    path: AbsolutePath,
+    source: std::io::Error,
+}
+
+impl FatalWriteError {
+    /// Turns an I/O error that occurred while writing a file into an error, which is a `FatalWriteError` if the error
+    /// is fatal.
+    pub fn wrap(path: &AbsolutePath, error: std::io::Error) -> anyhow::Error {
+        match error.kind() {
+            ErrorKind::StorageFull | ErrorKind::QuotaExceeded | ErrorKind::FileTooLarge | ErrorKind::ReadOnlyFilesystem =>
+                FatalWriteError { path: path.clone(), source: error }.into(),
+            _ => anyhow!("Error writing {:?}: {:?}", path, error),
+        }
+    }
+
+    /// Finds a fatal write error in the chain of causes of an error.
+    pub fn find(error: &anyhow::Error) -> Option<&FatalWriteError> {
+        error.chain().find_map(|cause| cause.downcast_ref::<FatalWriteError>())
+    }
+}
+
+impl fmt::Display for FatalWriteError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Fatal error writing {}: {}", self.path.to_string_lossy(), self.source)
+    }
+}
+
+impl std::error::Error for FatalWriteError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&self.source)
+    }
+}
+
 #[derive(Debug, Clone)]
 struct ReadOnlyFileSystem<FS: FileSystem>(FS);
 
@@ -31,28 +125,101 @@ pub trait DirEntry: Debug + Send + Sync {
 
 pub trait TargetFile: Send + Sync {
     fn write_line<S: Into<String> + Debug + Send>(&self, line: S) -> impl Future<Output = Result<()>> + Send;
+    /// Writes raw content. The chunk does not have to end at a line boundary.
+    fn write_chunk(&self, chunk: Vec<u8>) -> impl Future<Output = Result<()>> + Send;
     fn close(&mut self) -> impl Future<Output=Result<()>> + Send;
 }
 
 pub trait SourceFile: Send + Sync {
+    /// Returns the next line without its line ending. Fails if the line is longer than the file system allows.
     fn next_line(&mut self) -> impl Future<Output = Result<Option<String>>> + Send;
+    /// Returns the next piece of raw content, regardless of line boundaries and line length.
+    fn next_chunk(&mut self) -> impl Future<Output = Result<Option<Vec<u8>>>> + Send;
 }
 
 pub trait FileSystem: Debug + Send + Sync + Sized + Clone {
     type DirEntryItem: DirEntry;
+    /// Lists the entries of a directory, in no particular order. Fails with `Not found: ...` if the directory does not
+    /// exist and with `Not a directory: ...` if the path is not a directory.
     fn read_dir(&self, directory: &AbsolutePath) -> impl Future<Output = Result<impl Stream<Item = Result<Self::DirEntryItem>> + Send + Sync + Unpin>> + Send;
+    /// Returns the type of the path that a path refers to, after following symbolic links. A dangling symbolic link is
+    /// `Missing`.
     fn path_type(&self, path: &AbsolutePath) -> impl Future<Output = PathType> + Send;
-    fn open_target(&self, file_path: AbsolutePath, write_mode: WriteMode) -> impl Future<Output = Result<Option<impl TargetFile>>> + Send;
+    fn metadata(&self, path: &AbsolutePath) -> impl Future<Output = Result<Metadata>> + Send;
+    /// Like `metadata`, but a symbolic link is not followed: it has path type `Symlink`.
+    fn symlink_metadata(&self, path: &AbsolutePath) -> impl Future<Output = Result<Metadata>> + Send;
+    /// Returns the path without symbolic links (and without `.` and `..` components) that a path refers to. Fails if the
+    /// path does not exist.
+    fn canonicalize(&self, path: &AbsolutePath) -> impl Future<Output = Result<AbsolutePath>> + Send;
+    /// Opens a file for writing, unless the write mode says otherwise. `Append` writes after the existing content. If
+    /// `executable` is set, then anyone that may read the file may also execute it; otherwise nobody may execute it
+    /// (except after appending to a file that was executable already). Without Unix permission bits, `executable` has
+    /// no effect.
+    fn open_target(&self, file_path: AbsolutePath, write_mode: WriteMode, executable: bool) -> impl Future<Output = Result<Option<impl TargetFile>>> + Send;
     fn open_source(&self, file_path: AbsolutePath) -> impl Future<Output = Result<impl SourceFile>> + Send;
+    fn remove_file(&self, file_path: &AbsolutePath) -> impl Future<Output = Result<()>> + Send;
+    /// Removes a directory with everything in it. Symbolic links in the directory are removed, not followed. Fails if
+    /// the path is not a directory.
+    fn remove_dir_all(&self, directory: &AbsolutePath) -> impl Future<Output = Result<()>> + Send;
+    /// Moves a file, directory or symbolic link to another path in the same file system, replacing a file (but not a
+    /// directory) that is there already. The parent directory of the new path must exist. Writing a file next to its
+    /// destination and renaming it makes the write atomic.
+    fn rename(&self, from: &AbsolutePath, to: &AbsolutePath) -> impl Future<Output = Result<()>> + Send;
+    /// Sets the permission bits of a file (like `0o600`).
+    fn set_mode(&self, file_path: &AbsolutePath, mode: u32) -> impl Future<Output = Result<()>> + Send;
+    /// Creates a symbolic link that points to `link_target` (which is usually relative to the directory of the link),
+    /// unless the write mode says otherwise. `Overwrite` replaces an existing file or link, but not a directory; `Append`
+    /// leaves it alone, like `WriteNew`. Missing parent directories are created. Returns whether the link was created.
+    fn create_symlink(&self, link_path: &AbsolutePath, link_target: &Path, write_mode: WriteMode) -> impl Future<Output = Result<bool>> + Send;
+    /// Returns the path that a symbolic link points to. Fails if the path is not a symbolic link.
+    fn read_link(&self, link_path: &AbsolutePath) -> impl Future<Output = Result<PathBuf>> + Send;
     fn get_content(&self, file_path: AbsolutePath) -> impl Future<Output = Result<String>> + Send {
         async {
             let source_file = self.open_source(file_path).await?;
             source_file_to_string(source_file).await
         }
     }
+    /// Returns the content of a file byte for byte, without splitting it into lines.
+    fn read_bytes(&self, file_path: AbsolutePath) -> impl Future<Output = Result<Vec<u8>>> + Send {
+        async {
+            let mut source_file = self.open_source(file_path).await?;
+            let mut content = Vec::new();
+            while let Some(chunk) = source_file.next_chunk().await? {
+                content.extend(chunk);
+            }
+            Ok(content)
+        }
+    }
+    /// Writes the content to a file byte for byte, unless the write mode says otherwise. Returns whether the file was
+    /// written.
+    fn write_bytes(&self, file_path: AbsolutePath, content: &[u8], write_mode: WriteMode, executable: bool) -> impl Future<Output = Result<bool>> + Send {
+        async move {
+            let Some(mut target_file) = self.open_target(file_path, write_mode, executable).await? else { return Ok(false) };
+            let written = target_file.write_chunk(content.to_vec()).await;
+            target_file.close().await.and(written)?;
+            Ok(true)
+        }
+    }
+    /// Returns the SHA-256 hash of the content of a file, as a hexadecimal string.
+    fn content_hash(&self, file_path: AbsolutePath) -> impl Future<Output = Result<String>> + Send {
+        async {
+            let mut source_file = self.open_source(file_path).await?;
+            let mut hasher = Sha256::new();
+            while let Some(chunk) = source_file.next_chunk().await? {
+                hasher.update(chunk);
+            }
+            Ok(format!("{:x}", hasher.finalize()))
+        }
+    }
     fn read_only(self) -> impl FileSystem {
         ReadOnlyFileSystem(self)
     }
+    /// Writes the files below the project root to the output directory instead, preserving their relative paths.
+    fn redirect_writes(self, project_root: AbsolutePath, output_directory: AbsolutePath) -> impl FileSystem + 'static
+    where Self: 'static
+    {
+        RedirectedFileSystem::new(self, project_root, output_directory)
+    }
 }
 
 #[allow(dead_code)]
@@ -63,6 +230,10 @@ impl TargetFile for DummyTarget {
         Err(anyhow!("Trying to write a line to a dummy target"))
     }
 
+    async fn write_chunk(&self, _chunk: Vec<u8>) -> Result<()> {
+        Err(anyhow!("Trying to write a chunk to a dummy target"))
+    }
+
     async fn close(&mut self) -> Result<()> {
         Ok(())
     }
@@ -79,7 +250,19 @@ impl<FS: FileSystem> FileSystem for ReadOnlyFileSystem<FS> {
         self.0.path_type(path).await
     }
 
-    async fn open_target(&self, _file_path: AbsolutePath, _write_mode: WriteMode) -> Result<Option<impl TargetFile>> {
+    fn metadata(&self, path: &AbsolutePath) -> impl Future<Output=Result<Metadata>> + Send {
+        self.0.metadata(path)
+    }
+
+    fn symlink_metadata(&self, path: &AbsolutePath) -> impl Future<Output=Result<Metadata>> + Send {
+        self.0.symlink_metadata(path)
+    }
+
+    fn canonicalize(&self, path: &AbsolutePath) -> impl Future<Output=Result<AbsolutePath>> + Send {
+        self.0.canonicalize(path)
+    }
+
+    async fn open_target(&self, _file_path: AbsolutePath, _write_mode: WriteMode, _executable: bool) -> Result<Option<impl TargetFile>> {
         Ok(None::<DummyTarget>)
     }
 
@@ -87,6 +270,30 @@ impl<FS: FileSystem> FileSystem for ReadOnlyFileSystem<FS> {
         self.0.open_source(file_path)
     }
 
+    async fn remove_file(&self, file_path: &AbsolutePath) -> Result<()> {
+        Err(anyhow!("Trying to remove a file from a read-only file system: {:?}", file_path))
+    }
+
+    async fn remove_dir_all(&self, directory: &AbsolutePath) -> Result<()> {
+        Err(anyhow!("Trying to remove a directory from a read-only file system: {:?}", directory))
+    }
+
+    async fn rename(&self, from: &AbsolutePath, _to: &AbsolutePath) -> Result<()> {
+        Err(anyhow!("Trying to rename a file on a read-only file system: {:?}", from))
+    }
+
+    async fn set_mode(&self, file_path: &AbsolutePath, _mode: u32) -> Result<()> {
+        Err(anyhow!("Trying to change the mode of a file on a read-only file system: {:?}", file_path))
+    }
+
+    async fn create_symlink(&self, _link_path: &AbsolutePath, _link_target: &Path, _write_mode: WriteMode) -> Result<bool> {
+        Ok(false)
+    }
+
+    fn read_link(&self, link_path: &AbsolutePath) -> impl Future<Output=Result<PathBuf>> + Send {
+        self.0.read_link(link_path)
+    }
+
     fn read_only(self) -> impl FileSystem {
         self
     }
@@ -99,4 +306,185 @@ pub async fn source_file_to_string<SF: SourceFile>(mut source_file: SF) -> Resul
     }
     lines.push("".to_string());
     Ok(lines.join("\n"))
-}
\ No newline at end of file
+}
+
+/// A source file with content that is already in memory, for instance because the file is about to be overwritten.
+pub struct LinesSourceFile(std::vec::IntoIter<String>);
+
+impl LinesSourceFile {
+    pub fn new(content: &str) -> Self {
+        LinesSourceFile(content.lines().map(str::to_string).collect::<Vec<_>>().into_iter())
+    }
+}
+
+impl SourceFile for LinesSourceFile {
+    async fn next_line(&mut self) -> Result<Option<String>> {
+        Ok(self.0.next())
+    }
+
+    async fn next_chunk(&mut self) -> Result<Option<Vec<u8>>> {
+        Ok(self.0.next().map(|line| (line + "\n").into_bytes()))
+    }
+}
+
+/// Writes lines to a target file with CRLF line endings instead of LF.
+pub struct CrlfTargetFile<'a, TF: TargetFile>(pub &'a TF);
+
+impl<TF: TargetFile> TargetFile for CrlfTargetFile<'_, TF> {
+    async fn write_line<S: Into<String> + Debug + Send>(&self, line: S) -> Result<()> {
+        self.0.write_line(line.into() + "\r").await
+    }
+
+    async fn write_chunk(&self, chunk: Vec<u8>) -> Result<()> {
+        self.0.write_chunk(chunk).await
+    }
+
+    /// The wrapped target file is closed by its owner.
+    async fn close(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// Collects the lines that are written to it in memory, for instance to drop some of them before they are written to the
+/// actual target file. The lines count toward the memory ceiling of the run.
+#[derive(Debug)]
+pub struct BufferedTargetFile(std::sync::Mutex<Vec<String>>, MemoryGuard);
+
+impl BufferedTargetFile {
+    pub fn new(memory: MemoryGuard) -> Self {
+        BufferedTargetFile(std::sync::Mutex::new(Vec::new()), memory)
+    }
+
+    pub fn into_lines(self) -> Vec<String> {
+        self.0.into_inner().unwrap_or_default()
+    }
+}
+
+impl TargetFile for BufferedTargetFile {
+    async fn write_line<S: Into<String> + Debug + Send>(&self, line: S) -> Result<()> {
+        let line = line.into();
+        self.1.add(line.len());
+        self.0.lock().map_err(|_| anyhow!("Buffer is poisoned"))?.push(line);
+        Ok(())
+    }
+
+    async fn write_chunk(&self, _chunk: Vec<u8>) -> Result<()> {
+        Err(anyhow!("Trying to write a chunk to a buffer of lines"))
+    }
+
+    async fn close(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// Collects the content that is written to it in memory, byte for byte, for instance to compare it with the content of
+/// an existing file. The content counts toward the memory ceiling of the run.
+#[derive(Debug)]
+pub struct MemoryTargetFile(std::sync::Mutex<Vec<u8>>, MemoryGuard);
+
+impl MemoryTargetFile {
+    pub fn new(memory: MemoryGuard) -> Self {
+        MemoryTargetFile(std::sync::Mutex::new(Vec::new()), memory)
+    }
+
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.0.into_inner().unwrap_or_default()
+    }
+}
+
+impl TargetFile for MemoryTargetFile {
+    async fn write_line<S: Into<String> + Debug + Send>(&self, line: S) -> Result<()> {
+        self.write_chunk((line.into() + "\n").into_bytes()).await
+    }
+
+    async fn write_chunk(&self, chunk: Vec<u8>) -> Result<()> {
+        self.1.add(chunk.len());
+        self.0.lock().map_err(|_| anyhow!("Buffer is poisoned"))?.extend(chunk);
+        Ok(())
+    }
+
+    async fn close(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// Detects the line endings of a source file from its first chunk. Returns `None` if the chunk has no line endings.
+pub async fn detect_line_endings<SF: SourceFile>(mut source_file: SF) -> Result<Option<LineEndings>> {
+    let Some(chunk) = source_file.next_chunk().await? else { return Ok(None) };
+    let Some(newline) = chunk.iter().position(|byte| *byte == b'\n') else { return Ok(None) };
+    if newline > 0 && chunk[newline - 1] == b'\r' {
+        Ok(Some(LineEndings::Crlf))
+    } else {
+        Ok(Some(LineEndings::Lf))
+    }
+}
+
+/// Copies the content of a source file to a target file as is, without loading whole lines into memory.
+pub async fn copy_chunks<SF: SourceFile, TF: TargetFile>(mut source_file: SF, target_file: &TF) -> Result<()> {
+    while let Some(chunk) = source_file.next_chunk().await? {
+        target_file.write_chunk(chunk).await?;
+    }
+    Ok(())
+}
+
+/// Like the limit of Linux, to detect loops of symbolic links.
+const MAX_LINK_HOPS: usize = 40;
+
+/// The names in a path, after resolving `.` and `..` components without looking at a file system. In an absolute path,
+/// `..` at the root stays at the root (and the root itself is left out). Returns `None` if a relative path leads above
+/// its start.
+pub(crate) fn normal_components(path: &Path) -> Option<Vec<OsString>> {
+    let mut names = Vec::new();
+    for component in path.components() {
+        match component {
+            Component::Normal(name) => names.push(name.to_os_string()),
+            Component::ParentDir if path.is_absolute() => { names.pop(); },
+            Component::ParentDir => { names.pop()?; },
+            Component::CurDir | Component::RootDir | Component::Prefix(_) => (),
+        }
+    }
+    Some(names)
+}
+
+/// Walks the names in a path one by one for a file system that follows symbolic links itself: at a link, the walk
+/// starts over with the target of the link, followed by the names after the link.
+pub(crate) struct LinkResolver {
+    names: Vec<OsString>,
+    index: usize,
+    hops: usize,
+}
+
+impl LinkResolver {
+    pub(crate) fn new(names: Vec<OsString>) -> Self {
+        LinkResolver { names, index: 0, hops: 0 }
+    }
+
+    pub(crate) fn next_name(&mut self) -> Option<OsString> {
+        let name = self.names.get(self.index).cloned();
+        self.index += 1;
+        name
+    }
+
+    /// Whether there are names after the name that was returned last.
+    pub(crate) fn has_more(&self) -> bool {
+        self.index < self.names.len()
+    }
+
+    /// Returns the path of the target of a link followed by the names after the link, for [`LinkResolver::restart`].
+    /// Fails after [`MAX_LINK_HOPS`] links, because the links probably loop.
+    pub(crate) fn follow<P: Debug>(&mut self, target: PathBuf, path: &P) -> Result<PathBuf> {
+        self.hops += 1;
+        if self.hops > MAX_LINK_HOPS {
+            bail!("Too many levels of symbolic links: {:?}", path);
+        }
+        let mut link_path = target;
+        link_path.extend(&self.names[self.index.min(self.names.len())..]);
+        Ok(link_path)
+    }
+
+    /// Starts over with the names of the path that a link leads to.
+    pub(crate) fn restart(&mut self, names: Vec<OsString>) {
+        self.names = names;
+        self.index = 0;
+    }
+}
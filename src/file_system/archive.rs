@@ -0,0 +1,558 @@
+use std::collections::BTreeMap;
+use std::io::{Cursor, Read};
+use std::path::Component;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+use anyhow::{anyhow, bail, Result};
+use flate2::read::GzDecoder;
+use log::{debug, warn};
+use tokio_stream::StreamExt;
+use crate::config_model::WriteMode;
+use crate::path::AbsolutePath;
+use super::*;
+
+const DIRECTORY_MODE: u32 = 0o755;
+const FILE_MODE: u32 = 0o644;
+
+/// A read-only view of an archive (a tar file, optionally gzipped, or a zip file) as a directory at the path of the
+/// archive. Other paths are left to the underlying file system.
+#[derive(Debug, Clone)]
+pub struct ArchiveFileSystem<FS: FileSystem> {
+    file_system: FS,
+    archive: Option<Arc<Archive>>,
+}
+
+#[derive(Debug)]
+struct Archive {
+    path: AbsolutePath,
+    /// The entries by their path relative to the root of the archive. The root itself has the empty path.
+    entries: BTreeMap<PathBuf, ArchiveEntry>,
+}
+
+#[derive(Debug)]
+struct ArchiveEntry {
+    content: ArchiveContent,
+    mode: u32,
+    modified: Option<SystemTime>,
+}
+
+#[derive(Debug)]
+enum ArchiveContent {
+    Directory,
+    File(Arc<Vec<u8>>),
+    Symlink(PathBuf),
+}
+
+#[derive(Debug, Clone)]
+pub struct ArchiveDirEntry {
+    path: PathBuf,
+    file_name: OsString,
+    is_dir: bool,
+}
+
+pub enum ArchiveSourceFile<SF: SourceFile> {
+    Archive { bytes: Arc<Vec<u8>>, position: usize, file_path: AbsolutePath },
+    Other(SF),
+}
+
+impl<FS: FileSystem> ArchiveFileSystem<FS> {
+    /// Reads the archive (if any) with the underlying file system. The format of the archive follows from its content.
+    pub async fn open(file_system: FS, archive_path: Option<AbsolutePath>) -> Result<Self> {
+        let Some(archive_path) = archive_path else { return Ok(ArchiveFileSystem { file_system, archive: None }) };
+        let bytes = file_system.read_bytes(archive_path.clone()).await
+            .map_err(|error| anyhow!("Cannot read archive: {:?}: {error:#}", archive_path))?;
+        let entries = tokio::task::spawn_blocking(move || read_entries(&bytes)).await?
+            .map_err(|error| anyhow!("Invalid archive: {:?}: {error:#}", archive_path))?;
+        debug!("Archive {:?}: {} entries", archive_path, entries.len());
+        Ok(ArchiveFileSystem { file_system, archive: Some(Arc::new(Archive { path: archive_path, entries })) })
+    }
+
+    /// Returns the archive and the path relative to its root, if the path is inside the archive.
+    fn in_archive(&self, path: &AbsolutePath) -> Option<(&Archive, PathBuf)> {
+        let archive = self.archive.as_deref()?;
+        let relative = path.strip_prefix(archive.path.as_path()).ok()?;
+        Some((archive, relative.to_path_buf()))
+    }
+}
+
+impl Archive {
+    /// Finds the entry for a path inside the archive, following symbolic links in the directories of the path (and at
+    /// the path itself if `follow_last` is set). Links may not lead outside the archive.
+    fn resolve(&self, relative: &Path, follow_last: bool) -> Result<(PathBuf, &ArchiveEntry)> {
+        self.walk(relative, follow_last)?.map_err(|missing| anyhow!("Not found: {:?}", self.path.join(missing)))
+    }
+
+    /// Walks a path inside the archive like [`Archive::resolve`], but returns the path of the first entry that is
+    /// missing instead of failing. A `..` is resolved after the links before it, like on disk, so that a chain of links
+    /// cannot lead outside the archive either.
+    fn walk(&self, relative: &Path, follow_last: bool) -> Result<std::result::Result<(PathBuf, &ArchiveEntry), PathBuf>> {
+        let names = relative_names(relative).ok_or_else(|| anyhow!("Path leads outside the archive: {:?}", self.path.join(relative)))?;
+        let mut resolver = LinkResolver::new(names);
+        let mut current = PathBuf::new();
+        while let Some(name) = resolver.next_name() {
+            if name == ".." {
+                if !current.pop() {
+                    bail!("Path leads outside the archive: {:?}", self.path.join(relative));
+                }
+                continue;
+            }
+            let parent = current.clone();
+            current.push(name);
+            let Some(entry) = self.entries.get(&current) else { return Ok(Err(current)) };
+            if let ArchiveContent::Symlink(target) = &entry.content {
+                if resolver.has_more() || follow_last {
+                    let link_path = resolver.follow(parent.join(target), &self.path.join(relative))?;
+                    let names = relative_names(&link_path)
+                        .ok_or_else(|| anyhow!("Symbolic link leads outside the archive: {:?}", self.path.join(&current)))?;
+                    resolver.restart(names);
+                    current = PathBuf::new();
+                }
+            } else if resolver.has_more() && !matches!(entry.content, ArchiveContent::Directory) {
+                bail!("Not a directory: {:?}", self.path.join(&current));
+            }
+        }
+        Ok(self.entries.get(&current).map(|entry| (current.clone(), entry)).ok_or(current))
+    }
+}
+
+/// The names in a relative path, with the `..` components left in place, because they can only be resolved after the
+/// links before them. Returns `None` for an absolute path.
+fn relative_names(path: &Path) -> Option<Vec<OsString>> {
+    path.components()
+        .filter(|component| *component != Component::CurDir)
+        .map(|component| match component {
+            Component::Normal(name) => Some(name.to_os_string()),
+            Component::ParentDir => Some(OsString::from("..")),
+            _ => None,
+        })
+        .collect()
+}
+
+impl ArchiveEntry {
+    fn path_type(&self) -> PathType {
+        match self.content {
+            ArchiveContent::Directory => PathType::Directory,
+            ArchiveContent::File(_) => PathType::File,
+            ArchiveContent::Symlink(_) => PathType::Symlink,
+        }
+    }
+
+    fn metadata(&self) -> Metadata {
+        let len = match &self.content {
+            ArchiveContent::Directory => 0,
+            ArchiveContent::File(bytes) => bytes.len(),
+            ArchiveContent::Symlink(target) => target.as_os_str().len(),
+        };
+        Metadata::new(self.path_type(), self.mode, len as u64, self.modified)
+    }
+}
+
+impl DirEntry for ArchiveDirEntry {
+    fn path(&self) -> PathBuf {
+        self.path.clone()
+    }
+
+    fn file_name(&self) -> OsString {
+        self.file_name.clone()
+    }
+
+    async fn is_dir(&self) -> Result<bool> {
+        Ok(self.is_dir)
+    }
+}
+
+impl<SF: SourceFile> SourceFile for ArchiveSourceFile<SF> {
+    async fn next_line(&mut self) -> Result<Option<String>> {
+        let (bytes, position, file_path) = match self {
+            ArchiveSourceFile::Archive { bytes, position, file_path } => (bytes, position, file_path),
+            ArchiveSourceFile::Other(source_file) => return source_file.next_line().await,
+        };
+        let rest = &bytes[*position..];
+        if rest.is_empty() {
+            return Ok(None);
+        }
+        let newline = rest.iter().position(|byte| *byte == b'\n');
+        let mut line = rest[..newline.unwrap_or(rest.len())].to_vec();
+        *position += newline.map_or(rest.len(), |index| index + 1);
+        if line.last() == Some(&b'\r') {
+            line.pop();
+        }
+        let line = String::from_utf8(line)
+            .map_err(|e| anyhow!(format!("Error fetching next line from: {:?}: {:?}", file_path, e)))?;
+        Ok(Some(line))
+    }
+
+    async fn next_chunk(&mut self) -> Result<Option<Vec<u8>>> {
+        match self {
+            ArchiveSourceFile::Archive { bytes, position, .. } => {
+                if *position == bytes.len() {
+                    return Ok(None);
+                }
+                let chunk = bytes[*position..].to_vec();
+                *position = bytes.len();
+                Ok(Some(chunk))
+            },
+            ArchiveSourceFile::Other(source_file) => source_file.next_chunk().await,
+        }
+    }
+}
+
+impl<FS: FileSystem> FileSystem for ArchiveFileSystem<FS> {
+    type DirEntryItem = ArchiveDirEntry;
+
+    async fn read_dir(&self, directory: &AbsolutePath) -> Result<impl Stream<Item=Result<Self::DirEntryItem>> + Send + Sync + Unpin> {
+        let mut entries = Vec::new();
+        if let Some((archive, relative)) = self.in_archive(directory) {
+            let (resolved, entry) = archive.resolve(&relative, true)?;
+            if !matches!(entry.content, ArchiveContent::Directory) {
+                bail!("Not a directory: {:?}", directory);
+            }
+            for (path, entry) in &archive.entries {
+                if path.parent() == Some(resolved.as_path()) {
+                    let file_name = path.file_name().unwrap_or_default().to_os_string();
+                    let is_dir = matches!(entry.content, ArchiveContent::Directory);
+                    entries.push(Ok(ArchiveDirEntry { path: directory.join(&file_name), file_name, is_dir }));
+                }
+            }
+        } else {
+            let mut stream = self.file_system.read_dir(directory).await?;
+            while let Some(entry) = stream.next().await {
+                let entry = entry?;
+                entries.push(Ok(ArchiveDirEntry { path: entry.path(), file_name: entry.file_name(), is_dir: entry.is_dir().await? }));
+            }
+        }
+        Ok(tokio_stream::iter(entries))
+    }
+
+    async fn path_type(&self, path: &AbsolutePath) -> PathType {
+        match self.in_archive(path) {
+            Some((archive, relative)) => archive.resolve(&relative, true).map_or(PathType::Missing, |(_, entry)| entry.path_type()),
+            None => self.file_system.path_type(path).await,
+        }
+    }
+
+    async fn metadata(&self, path: &AbsolutePath) -> Result<Metadata> {
+        match self.in_archive(path) {
+            Some((archive, relative)) => Ok(archive.resolve(&relative, true)?.1.metadata()),
+            None => self.file_system.metadata(path).await,
+        }
+    }
+
+    async fn symlink_metadata(&self, path: &AbsolutePath) -> Result<Metadata> {
+        match self.in_archive(path) {
+            Some((archive, relative)) => Ok(archive.resolve(&relative, false)?.1.metadata()),
+            None => self.file_system.symlink_metadata(path).await,
+        }
+    }
+
+    async fn canonicalize(&self, path: &AbsolutePath) -> Result<AbsolutePath> {
+        match self.in_archive(path) {
+            Some((archive, relative)) => Ok(AbsolutePath::new(archive.resolve(&relative, true)?.0, &archive.path)),
+            None => self.file_system.canonicalize(path).await,
+        }
+    }
+
+    async fn open_target(&self, file_path: AbsolutePath, write_mode: WriteMode, executable: bool) -> Result<Option<impl TargetFile>> {
+        if self.in_archive(&file_path).is_some() {
+            warn!("Cannot write to an archive: {:?}", file_path);
+            return Ok(None);
+        }
+        self.file_system.open_target(file_path, write_mode, executable).await
+    }
+
+    async fn open_source(&self, file_path: AbsolutePath) -> Result<impl SourceFile> {
+        let Some((archive, relative)) = self.in_archive(&file_path) else {
+            return Ok(ArchiveSourceFile::Other(self.file_system.open_source(file_path).await?));
+        };
+        match &archive.resolve(&relative, true)?.1.content {
+            ArchiveContent::File(bytes) => Ok(ArchiveSourceFile::Archive { bytes: bytes.clone(), position: 0, file_path }),
+            _ => Err(anyhow!("Trying to read lines from a directory: {:?}", file_path)),
+        }
+    }
+
+    async fn remove_file(&self, file_path: &AbsolutePath) -> Result<()> {
+        if self.in_archive(file_path).is_some() {
+            bail!("Trying to remove a file from an archive: {:?}", file_path);
+        }
+        self.file_system.remove_file(file_path).await
+    }
+
+    async fn remove_dir_all(&self, directory: &AbsolutePath) -> Result<()> {
+        if self.in_archive(directory).is_some() {
+            bail!("Trying to remove a directory from an archive: {:?}", directory);
+        }
+        self.file_system.remove_dir_all(directory).await
+    }
+
+    async fn rename(&self, from: &AbsolutePath, to: &AbsolutePath) -> Result<()> {
+        if self.in_archive(from).is_some() || self.in_archive(to).is_some() {
+            bail!("Trying to rename a file in an archive: {:?}", from);
+        }
+        self.file_system.rename(from, to).await
+    }
+
+    async fn set_mode(&self, file_path: &AbsolutePath, mode: u32) -> Result<()> {
+        if self.in_archive(file_path).is_some() {
+            bail!("Trying to change the mode of a file in an archive: {:?}", file_path);
+        }
+        self.file_system.set_mode(file_path, mode).await
+    }
+
+    async fn create_symlink(&self, link_path: &AbsolutePath, link_target: &Path, write_mode: WriteMode) -> Result<bool> {
+        if self.in_archive(link_path).is_some() {
+            warn!("Cannot write to an archive: {:?}", link_path);
+            return Ok(false);
+        }
+        self.file_system.create_symlink(link_path, link_target, write_mode).await
+    }
+
+    async fn read_link(&self, link_path: &AbsolutePath) -> Result<PathBuf> {
+        let Some((archive, relative)) = self.in_archive(link_path) else { return self.file_system.read_link(link_path).await };
+        match &archive.resolve(&relative, false)?.1.content {
+            ArchiveContent::Symlink(target) => Ok(target.clone()),
+            _ => Err(anyhow!("Not a symbolic link: {:?}", link_path)),
+        }
+    }
+}
+
+/// Writes the files and symbolic links in an archive (in any of the formats of `ArchiveFileSystem`) to a directory.
+/// Fails before anything is written for symbolic links that lead outside the archive (also through other links), and
+/// for entries behind a symbolic link, so that nothing is ever written through a link.
+pub async fn unpack<FS: FileSystem>(bytes: Vec<u8>, directory: &AbsolutePath, fs: &FS) -> Result<()> {
+    let entries = tokio::task::spawn_blocking(move || read_entries(&bytes)).await?
+        .map_err(|error| anyhow!("Invalid archive for: {:?}: {error:#}", directory))?;
+    let archive = Archive { path: directory.clone(), entries };
+    for (path, entry) in &archive.entries {
+        let is_link = |ancestor: &Path| archive.entries.get(ancestor).is_some_and(|entry| matches!(entry.content, ArchiveContent::Symlink(_)));
+        if let Some(link) = path.ancestors().skip(1).find(|ancestor| is_link(ancestor)) {
+            bail!("Archive entry is behind symbolic link {:?}: {:?}", link, path);
+        }
+        if let ArchiveContent::Symlink(target) = &entry.content {
+            let walked = archive.walk(path, true).map_err(|error| anyhow!("Unsafe symbolic link in archive: {:?} -> {:?}: {error:#}", path, target))?;
+            if let Err(missing) = walked {
+                debug!("Dangling symbolic link in archive: {:?} -> {:?} ({:?} is missing)", path, target, missing);
+            }
+        }
+    }
+    for (path, entry) in archive.entries {
+        let target_path = AbsolutePath::new(&path, directory);
+        match entry.content {
+            ArchiveContent::Directory => (),
+            ArchiveContent::File(bytes) => {
+                fs.write_bytes(target_path, &bytes, WriteMode::Overwrite, entry.mode & 0o111 != 0).await?;
+            },
+            ArchiveContent::Symlink(target) => {
+                fs.create_symlink(&target_path, &target, WriteMode::Overwrite).await?;
+            },
+        }
+    }
+    Ok(())
+}
+
+/// Reads the entries of a zip file, a gzipped tar file or a tar file. If everything in the archive is inside a single
+/// directory (like in the tarballs of GitHub), then that directory is the root.
+fn read_entries(bytes: &[u8]) -> Result<BTreeMap<PathBuf, ArchiveEntry>> {
+    let mut entries = if bytes.starts_with(b"PK\x03\x04") {
+        read_zip_entries(bytes)?
+    } else if bytes.starts_with(&[0x1f, 0x8b]) {
+        read_tar_entries(GzDecoder::new(bytes))?
+    } else {
+        read_tar_entries(bytes)?
+    };
+    let mut top_level = entries.keys().filter_map(|path| path.components().next()).collect::<Vec<_>>();
+    top_level.dedup();
+    if let [single] = top_level.as_slice() {
+        let single = PathBuf::from(single.as_os_str());
+        if entries.get(&single).is_none_or(|entry| matches!(entry.content, ArchiveContent::Directory)) {
+            debug!("Archive root: {:?}", single);
+            entries = entries.into_iter()
+                .filter_map(|(path, entry)| Some((path.strip_prefix(&single).ok()?.to_path_buf(), entry)))
+                .collect();
+        }
+    }
+    // Archives do not always have entries for their directories
+    let directories: Vec<PathBuf> = entries.keys().flat_map(|path| path.ancestors().skip(1)).map(Path::to_path_buf).collect();
+    for directory in directories {
+        entries.entry(directory).or_insert(ArchiveEntry { content: ArchiveContent::Directory, mode: DIRECTORY_MODE, modified: None });
+    }
+    entries.entry(PathBuf::new()).or_insert(ArchiveEntry { content: ArchiveContent::Directory, mode: DIRECTORY_MODE, modified: None });
+    Ok(entries)
+}
+
+fn read_tar_entries(reader: impl Read) -> Result<BTreeMap<PathBuf, ArchiveEntry>> {
+    let mut entries = BTreeMap::new();
+    let mut archive = tar::Archive::new(reader);
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        let raw_path = entry.path()?.to_path_buf();
+        let Some(path) = safe_path(&raw_path)? else { continue };
+        let header = entry.header();
+        let mode = header.mode().map(|mode| mode & 0o7777).unwrap_or(FILE_MODE);
+        let modified = header.mtime().ok().map(|mtime| SystemTime::UNIX_EPOCH + Duration::from_secs(mtime));
+        let content = match header.entry_type() {
+            tar::EntryType::Directory => ArchiveContent::Directory,
+            tar::EntryType::Symlink => ArchiveContent::Symlink(entry.link_name()?.ok_or_else(|| anyhow!("Symbolic link without target: {:?}", raw_path))?.to_path_buf()),
+            tar::EntryType::Regular | tar::EntryType::Continuous => {
+                let mut bytes = Vec::new();
+                entry.read_to_end(&mut bytes)?;
+                ArchiveContent::File(Arc::new(bytes))
+            },
+            entry_type => {
+                warn!("Skip archive entry of type {entry_type:?}: {raw_path:?}");
+                continue;
+            },
+        };
+        entries.insert(path, ArchiveEntry { content, mode, modified });
+    }
+    Ok(entries)
+}
+
+fn read_zip_entries(bytes: &[u8]) -> Result<BTreeMap<PathBuf, ArchiveEntry>> {
+    let mut entries = BTreeMap::new();
+    let mut archive = zip::ZipArchive::new(Cursor::new(bytes))?;
+    for index in 0..archive.len() {
+        let mut file = archive.by_index(index)?;
+        let raw_path = PathBuf::from(file.name());
+        let Some(path) = safe_path(&raw_path)? else { continue };
+        let unix_mode = file.unix_mode();
+        let content = if file.is_dir() {
+            ArchiveContent::Directory
+        } else {
+            let mut bytes = Vec::new();
+            file.read_to_end(&mut bytes)?;
+            // Zip files mark symbolic links with the file type in the Unix mode, and store their target as content
+            if unix_mode.is_some_and(|mode| mode & 0o170000 == 0o120000) {
+                ArchiveContent::Symlink(PathBuf::from(String::from_utf8(bytes)?))
+            } else {
+                ArchiveContent::File(Arc::new(bytes))
+            }
+        };
+        let default_mode = if file.is_dir() { DIRECTORY_MODE } else { FILE_MODE };
+        let mode = unix_mode.map(|mode| mode & 0o7777).unwrap_or(default_mode);
+        entries.insert(path, ArchiveEntry { content, mode, modified: None });
+    }
+    Ok(entries)
+}
+
+/// Returns the path of an archive entry without `.` components, or `None` for the root. Fails for paths that lead
+/// outside the archive.
+fn safe_path(raw_path: &Path) -> Result<Option<PathBuf>> {
+    let path: PathBuf = normal_components(raw_path)
+        .filter(|_| !raw_path.is_absolute() && !raw_path.components().any(|component| component == Component::ParentDir))
+        .ok_or_else(|| anyhow!("Unsafe path in archive: {:?}", raw_path))?
+        .iter()
+        .collect();
+    Ok(Some(path).filter(|path| !path.as_os_str().is_empty()))
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::Write;
+    use std::pin::pin;
+    use test_log::test;
+    use zip::write::SimpleFileOptions;
+    use crate::file_system::fixture;
+    use crate::path::test_utils::to_absolute_path;
+    use super::*;
+
+    #[test(tokio::test)]
+    async fn read_zip_archive() -> Result<()> {
+        // Given
+        let mut zip = zip::ZipWriter::new(Cursor::new(Vec::new()));
+        zip.add_directory("example-thundercloud/cumulus/workshop/", SimpleFileOptions::default())?;
+        zip.start_file("example-thundercloud/cumulus/workshop/clock.yaml", SimpleFileOptions::default().unix_permissions(0o755))?;
+        zip.write_all(b"sweeper: Lu Tse\r\nhours: 10\n")?;
+        zip.add_symlink("example-thundercloud/cumulus/mirror", "workshop", SimpleFileOptions::default())?;
+        let bytes = zip.finish()?.into_inner();
+        let fs = fixture::from_toml("")?;
+        let archive_path = to_absolute_path("/example-thundercloud.zip");
+        fs.write_bytes(archive_path.clone(), &bytes, WriteMode::Overwrite, false).await?;
+
+        // When
+        let archive_fs = ArchiveFileSystem::open(fs, Some(archive_path.clone())).await?;
+
+        // Then
+        let cumulus = AbsolutePath::new("cumulus", &archive_path);
+        let mut names = Vec::new();
+        let mut entries = pin!(archive_fs.read_dir(&cumulus).await?);
+        while let Some(entry) = entries.next().await {
+            names.push(entry?.file_name());
+        }
+        assert_eq!(names, vec![OsString::from("mirror"), OsString::from("workshop")]);
+        let clock = AbsolutePath::new("mirror/clock.yaml", &cumulus);
+        assert_eq!(archive_fs.path_type(&archive_path).await, PathType::Directory);
+        assert_eq!(archive_fs.symlink_metadata(&AbsolutePath::new("mirror", &cumulus)).await?.path_type(), PathType::Symlink);
+        assert_eq!(archive_fs.metadata(&clock).await?.mode() & 0o777, 0o755);
+        assert_eq!(archive_fs.canonicalize(&clock).await?, AbsolutePath::new("workshop/clock.yaml", &cumulus));
+        let mut source_file = archive_fs.open_source(clock.clone()).await?;
+        assert_eq!(source_file.next_line().await?, Some("sweeper: Lu Tse".to_string()));
+        assert_eq!(source_file.next_line().await?, Some("hours: 10".to_string()));
+        assert_eq!(source_file.next_line().await?, None);
+        assert!(archive_fs.open_target(clock, WriteMode::Overwrite, false).await?.is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn reject_unsafe_paths() -> Result<()> {
+        // Given
+        let mut header = tar::Header::new_gnu();
+        header.set_size(4);
+        header.set_mode(0o644);
+        header.set_cksum();
+        let mut builder = tar::Builder::new(Vec::new());
+        builder.append_data(&mut header.clone(), "cumulus/clock.yaml", &b"tick"[..])?;
+        // The builder refuses paths with `..`, so write the name into the header directly
+        header.as_old_mut().name[..13].copy_from_slice(b"../escape.txt");
+        header.set_cksum();
+        builder.append(&header, &b"tock"[..])?;
+        let bytes = builder.into_inner()?;
+
+        // When
+        let result = read_entries(&bytes);
+
+        // Then
+        let Err(error) = result else { panic!("Expected the archive to be rejected") };
+        assert_eq!(error.to_string(), "Unsafe path in archive: \"../escape.txt\"");
+        Ok(())
+    }
+
+    #[test(tokio::test)]
+    async fn reject_chained_links_that_lead_outside() -> Result<()> {
+        // Given
+        let tmp_dir = assert_fs::TempDir::new()?;
+        let staging = |name: &str| AbsolutePath::try_new(tmp_dir.path().join("cache").join(name));
+        let tarball = |links: &[(&str, &str)], files: &[&str]| -> Result<Vec<u8>> {
+            let mut builder = tar::Builder::new(Vec::new());
+            for (path, target) in links {
+                let mut header = tar::Header::new_gnu();
+                header.set_entry_type(tar::EntryType::Symlink);
+                header.set_size(0);
+                builder.append_link(&mut header, path, target)?;
+            }
+            for file in files {
+                let mut header = tar::Header::new_gnu();
+                header.set_size(4);
+                header.set_mode(0o644);
+                header.set_cksum();
+                builder.append_data(&mut header, file, &b"evil"[..])?;
+            }
+            Ok(builder.into_inner()?)
+        };
+        let fs = crate::file_system::real_file_system();
+
+        // When
+        let inside = unpack(tarball(&[("q/r", "..")], &["q/clock.yaml", "thundercloud.toml"])?, &staging("inside")?, &fs).await;
+        let chained = unpack(tarball(&[("q/r", ".."), ("p", "q/r/../..")], &["q/clock.yaml"])?, &staging("chained")?, &fs).await;
+        let behind_link = unpack(tarball(&[("p", "q")], &["p/x/evil"])?, &staging("behind-link")?, &fs).await;
+
+        // Then
+        assert!(inside.is_ok(), "Actual: {inside:?}");
+        let chained_error = chained.unwrap_err().to_string();
+        assert!(chained_error.starts_with(r#"Unsafe symbolic link in archive: "p" -> "q/r/../..": Path leads outside the archive"#), "Actual: {chained_error}");
+        assert_eq!(behind_link.unwrap_err().to_string(), r#"Archive entry is behind symbolic link "p": "p/x""#);
+        assert!(!tmp_dir.path().join("cache/chained").exists());
+        assert!(!tmp_dir.path().join("cache/behind-link").exists());
+        assert!(!tmp_dir.path().join("x").exists());
+        Ok(())
+    }
+}
@@ -0,0 +1,317 @@
+//! Checks that an implementation of `FileSystem` honours the contract that Igor relies on. Call
+//! [`check_file_system`] with an empty directory of the file system under test; every check works in its own
+//! subdirectory.
+
+use std::pin::pin;
+use anyhow::{bail, ensure, Result};
+use tokio_stream::StreamExt;
+use crate::config_model::WriteMode;
+use crate::path::AbsolutePath;
+use super::*;
+
+/// Runs all checks.
+pub async fn check_file_system<FS: FileSystem>(fs: &FS, root: &AbsolutePath) -> Result<()> {
+    check_read_dir(fs, &AbsolutePath::new("read-dir", root)).await?;
+    check_path_type(fs, &AbsolutePath::new("path-type", root)).await?;
+    check_write_modes(fs, &AbsolutePath::new("write-modes", root)).await?;
+    check_bytes(fs, &AbsolutePath::new("bytes", root)).await?;
+    check_metadata(fs, &AbsolutePath::new("metadata", root)).await?;
+    check_remove_file(fs, &AbsolutePath::new("remove-file", root)).await?;
+    check_remove_dir_all(fs, &AbsolutePath::new("remove-dir-all", root)).await?;
+    check_rename(fs, &AbsolutePath::new("rename", root)).await?;
+    check_set_mode(fs, &AbsolutePath::new("set-mode", root)).await?;
+    check_symlinks(fs, &AbsolutePath::new("symlinks", root)).await?;
+    check_follow_symlinks(fs, &AbsolutePath::new("follow-symlinks", root)).await?;
+    Ok(())
+}
+
+/// `read_dir` lists files and subdirectories, and fails for missing directories and for files.
+pub async fn check_read_dir<FS: FileSystem>(fs: &FS, root: &AbsolutePath) -> Result<()> {
+    write_file(fs, &AbsolutePath::new("workshop/clock.yaml", root), &["tick"], false).await?;
+    write_file(fs, &AbsolutePath::new("workshop/mirrors/mirror.yaml", root), &["frame"], false).await?;
+
+    let workshop = AbsolutePath::new("workshop", root);
+    let mut entries = pin!(fs.read_dir(&workshop).await?);
+    let mut names = Vec::new();
+    while let Some(entry) = entries.next().await {
+        let entry = entry?;
+        names.push((entry.file_name(), entry.is_dir().await?));
+    }
+    names.sort();
+    let expected = vec![(OsString::from("clock.yaml"), false), (OsString::from("mirrors"), true)];
+    ensure!(names == expected, "Unexpected entries: {names:?}");
+
+    let missing = AbsolutePath::new("missing", root);
+    expect_error(fs.read_dir(&missing).await.map(|_| ()), "Not found:", "Reading a missing directory")?;
+    let file = AbsolutePath::new("workshop/clock.yaml", root);
+    expect_error(fs.read_dir(&file).await.map(|_| ()), "Not a directory:", "Reading a file as a directory")
+}
+
+/// `path_type` distinguishes missing paths, files and directories.
+pub async fn check_path_type<FS: FileSystem>(fs: &FS, root: &AbsolutePath) -> Result<()> {
+    let file = AbsolutePath::new("workshop/clock.yaml", root);
+    write_file(fs, &file, &["tick"], false).await?;
+
+    for (path, expected) in [(&file, PathType::File), (&AbsolutePath::new("workshop", root), PathType::Directory), (&AbsolutePath::new("missing", root), PathType::Missing)] {
+        let actual = fs.path_type(path).await;
+        ensure!(actual == expected, "Unexpected path type: {path:?}: {actual:?} instead of {expected:?}");
+    }
+    Ok(())
+}
+
+/// `open_target` creates missing parent directories, leaves existing files alone with `WriteNew`, replaces their
+/// content with `Overwrite`, adds to their content with `Append` and never opens anything with `Ignore`.
+pub async fn check_write_modes<FS: FileSystem>(fs: &FS, root: &AbsolutePath) -> Result<()> {
+    let file = AbsolutePath::new("workshop/clock.yaml", root);
+    write_file(fs, &file, &["tick", "tock"], false).await?;
+    expect_content(fs, &file, "tick\ntock\n").await?;
+
+    ensure!(fs.open_target(file.clone(), WriteMode::WriteNew, false).await?.is_none(), "WriteNew opened an existing file: {file:?}");
+    expect_content(fs, &file, "tick\ntock\n").await?;
+
+    let Some(mut target_file) = fs.open_target(file.clone(), WriteMode::Overwrite, false).await? else { bail!("Overwrite did not open: {file:?}") };
+    target_file.write_line("ding").await?;
+    target_file.close().await?;
+    expect_content(fs, &file, "ding\n").await?;
+
+    let Some(mut target_file) = fs.open_target(file.clone(), WriteMode::Append, false).await? else { bail!("Append did not open: {file:?}") };
+    target_file.write_line("dong").await?;
+    target_file.close().await?;
+    expect_content(fs, &file, "ding\ndong\n").await?;
+    let appended = AbsolutePath::new("workshop/log.yaml", root);
+    let Some(mut target_file) = fs.open_target(appended.clone(), WriteMode::Append, false).await? else { bail!("Append did not create: {appended:?}") };
+    target_file.write_line("tick").await?;
+    target_file.close().await?;
+    expect_content(fs, &appended, "tick\n").await?;
+
+    ensure!(fs.open_target(file.clone(), WriteMode::Ignore, false).await?.is_none(), "Ignore opened an existing file: {file:?}");
+    let ignored = AbsolutePath::new("workshop/ignored.yaml", root);
+    ensure!(fs.open_target(ignored.clone(), WriteMode::Ignore, false).await?.is_none(), "Ignore opened a new file: {ignored:?}");
+    ensure!(fs.path_type(&ignored).await == PathType::Missing, "Ignore created a file: {ignored:?}");
+    Ok(())
+}
+
+/// `write_bytes` and `read_bytes` keep content byte for byte, including bytes that are not text and a missing final
+/// newline, and `write_bytes` honors the write mode. Lines of a source file do not include the line ending, also if it
+/// is a carriage return and a newline.
+pub async fn check_bytes<FS: FileSystem>(fs: &FS, root: &AbsolutePath) -> Result<()> {
+    let file = AbsolutePath::new("assets/logo.png", root);
+    let content = b"\x89PNG\r\n\x1a\n\x00\xff\xfe";
+    ensure!(fs.write_bytes(file.clone(), content, WriteMode::WriteNew, false).await?, "WriteNew did not write: {file:?}");
+    let read = fs.read_bytes(file.clone()).await?;
+    ensure!(read == content, "Unexpected bytes: {file:?}: {read:?}");
+
+    ensure!(!fs.write_bytes(file.clone(), b"GIF89a", WriteMode::WriteNew, false).await?, "WriteNew replaced an existing file: {file:?}");
+    ensure!(fs.write_bytes(file.clone(), b"tick", WriteMode::Overwrite, false).await?, "Overwrite did not write: {file:?}");
+    ensure!(fs.write_bytes(file.clone(), b"tock", WriteMode::Append, false).await?, "Append did not write: {file:?}");
+    let read = fs.read_bytes(file.clone()).await?;
+    ensure!(read == b"ticktock", "Unexpected bytes: {file:?}: {read:?}");
+
+    let crlf_file = AbsolutePath::new("assets/clock.yaml", root);
+    fs.write_bytes(crlf_file.clone(), b"tick\r\ntock\r\n", WriteMode::Overwrite, false).await?;
+    let mut source_file = fs.open_source(crlf_file.clone()).await?;
+    let mut lines = Vec::new();
+    while let Some(line) = source_file.next_line().await? {
+        lines.push(line);
+    }
+    ensure!(lines == ["tick", "tock"], "Unexpected lines: {crlf_file:?}: {lines:?}");
+    let read = fs.read_bytes(crlf_file.clone()).await?;
+    ensure!(read == b"tick\r\ntock\r\n", "Unexpected bytes: {crlf_file:?}: {read:?}");
+    Ok(())
+}
+
+/// `metadata` reports the path type, whether files are executable, their length and their modification time, and fails
+/// for missing paths. Overwriting a file makes it executable or not, but appending to it keeps it executable.
+pub async fn check_metadata<FS: FileSystem>(fs: &FS, root: &AbsolutePath) -> Result<()> {
+    let plain = AbsolutePath::new("workshop/clock.yaml", root);
+    let script = AbsolutePath::new("workshop/wind-up.sh", root);
+    write_file(fs, &plain, &["tick"], false).await?;
+    write_file(fs, &script, &["echo tick"], true).await?;
+
+    let plain_metadata = fs.metadata(&plain).await?;
+    ensure!(plain_metadata.path_type() == PathType::File && !plain_metadata.is_executable(), "Unexpected metadata: {plain:?}: {plain_metadata:?}");
+    ensure!(plain_metadata.len() == 5 && plain_metadata.modified().is_some(), "Unexpected length or modification time: {plain:?}: {plain_metadata:?}");
+    let script_metadata = fs.metadata(&script).await?;
+    ensure!(script_metadata.is_executable(), "Not executable: {script:?}: {script_metadata:?}");
+    fs.write_bytes(script.clone(), b"echo tock\n", WriteMode::Append, false).await?;
+    ensure!(fs.metadata(&script).await?.is_executable(), "Not executable after appending: {script:?}");
+    fs.write_bytes(script.clone(), b"echo tick\n", WriteMode::Overwrite, false).await?;
+    ensure!(!fs.metadata(&script).await?.is_executable(), "Executable after overwriting: {script:?}");
+    fs.write_bytes(plain.clone(), b"tock\n", WriteMode::Overwrite, true).await?;
+    ensure!(fs.metadata(&plain).await?.is_executable(), "Not executable after overwriting: {plain:?}");
+    fs.write_bytes(plain.clone(), b"tick\n", WriteMode::Append, false).await?;
+    let appended_metadata = fs.metadata(&plain).await?;
+    ensure!(appended_metadata.len() == 10 && appended_metadata.modified() >= plain_metadata.modified(), "Unexpected length or modification time after appending: {plain:?}: {appended_metadata:?}");
+    let directory_metadata = fs.metadata(&AbsolutePath::new("workshop", root)).await?;
+    ensure!(directory_metadata.path_type() == PathType::Directory && !directory_metadata.is_executable(), "Unexpected metadata of directory: {directory_metadata:?}");
+    ensure!(fs.metadata(&AbsolutePath::new("missing", root)).await.is_err(), "Metadata of a missing path should fail");
+    Ok(())
+}
+
+/// `remove_file` removes files, but fails for missing paths and directories.
+pub async fn check_remove_file<FS: FileSystem>(fs: &FS, root: &AbsolutePath) -> Result<()> {
+    let file = AbsolutePath::new("workshop/clock.yaml", root);
+    write_file(fs, &file, &["tick"], false).await?;
+
+    fs.remove_file(&file).await?;
+    ensure!(fs.path_type(&file).await == PathType::Missing, "File was not removed: {file:?}");
+    ensure!(fs.remove_file(&file).await.is_err(), "Removing a missing file should fail: {file:?}");
+    let directory = AbsolutePath::new("workshop", root);
+    ensure!(fs.remove_file(&directory).await.is_err(), "Removing a directory should fail: {directory:?}");
+    ensure!(fs.path_type(&directory).await == PathType::Directory, "Directory was removed: {directory:?}");
+    Ok(())
+}
+
+/// `remove_dir_all` removes directories with everything in them (without following links), but fails for missing
+/// paths and files.
+pub async fn check_remove_dir_all<FS: FileSystem>(fs: &FS, root: &AbsolutePath) -> Result<()> {
+    let kept = AbsolutePath::new("kept/mirror.yaml", root);
+    write_file(fs, &kept, &["frame"], false).await?;
+    write_file(fs, &AbsolutePath::new("workshop/clock.yaml", root), &["tick"], false).await?;
+    write_file(fs, &AbsolutePath::new("workshop/springs/spring.yaml", root), &["coiled"], false).await?;
+    fs.create_symlink(&AbsolutePath::new("workshop/springs/kept", root), Path::new("../../kept"), WriteMode::WriteNew).await?;
+
+    let directory = AbsolutePath::new("workshop", root);
+    fs.remove_dir_all(&directory).await?;
+    ensure!(fs.path_type(&directory).await == PathType::Missing, "Directory was not removed: {directory:?}");
+    expect_content(fs, &kept, "frame\n").await?;
+    expect_error(fs.remove_dir_all(&directory).await, "Not found:", "Removing a missing directory")?;
+    expect_error(fs.remove_dir_all(&kept).await, "Not a directory:", "Removing a file as a directory")?;
+    ensure!(fs.path_type(&kept).await == PathType::File, "File was removed: {kept:?}");
+    Ok(())
+}
+
+/// `rename` moves files and directories, replaces files, but never replaces a directory.
+pub async fn check_rename<FS: FileSystem>(fs: &FS, root: &AbsolutePath) -> Result<()> {
+    let staging = AbsolutePath::new("workshop/clock.yaml.new", root);
+    let clock = AbsolutePath::new("workshop/clock.yaml", root);
+    write_file(fs, &clock, &["tick"], false).await?;
+    write_file(fs, &staging, &["tock"], true).await?;
+
+    fs.rename(&staging, &clock).await?;
+    ensure!(fs.path_type(&staging).await == PathType::Missing, "File was not moved: {staging:?}");
+    expect_content(fs, &clock, "tock\n").await?;
+    ensure!(fs.metadata(&clock).await?.is_executable(), "Renaming lost the mode: {clock:?}");
+
+    let workshop = AbsolutePath::new("workshop", root);
+    let attic = AbsolutePath::new("attic", root);
+    fs.rename(&workshop, &attic).await?;
+    ensure!(fs.path_type(&workshop).await == PathType::Missing, "Directory was not moved: {workshop:?}");
+    expect_content(fs, &AbsolutePath::new("attic/clock.yaml", root), "tock\n").await?;
+
+    write_file(fs, &clock, &["tick"], false).await?;
+    ensure!(fs.rename(&clock, &attic).await.is_err(), "Replacing a directory should fail: {attic:?}");
+    ensure!(fs.rename(&AbsolutePath::new("missing", root), &clock).await.is_err(), "Renaming a missing path should fail");
+    Ok(())
+}
+
+/// `set_mode` sets the permission bits of files, and fails for missing paths.
+pub async fn check_set_mode<FS: FileSystem>(fs: &FS, root: &AbsolutePath) -> Result<()> {
+    let secret = AbsolutePath::new("workshop/secret.yaml", root);
+    let script = AbsolutePath::new("workshop/wind-up.sh", root);
+    write_file(fs, &secret, &["key: 4288"], false).await?;
+    write_file(fs, &script, &["echo tick"], false).await?;
+
+    fs.set_mode(&secret, 0o600).await?;
+    fs.set_mode(&script, 0o750).await?;
+    let secret_metadata = fs.metadata(&secret).await?;
+    ensure!(secret_metadata.mode() == 0o600, "Unexpected mode: {secret:?}: {:o}", secret_metadata.mode());
+    let script_metadata = fs.metadata(&script).await?;
+    ensure!(script_metadata.mode() == 0o750 && script_metadata.is_executable(), "Unexpected mode: {script:?}: {:o}", script_metadata.mode());
+    ensure!(fs.set_mode(&AbsolutePath::new("missing", root), 0o600).await.is_err(), "Setting the mode of a missing path should fail");
+    Ok(())
+}
+
+/// `create_symlink` creates links (and missing parent directories) that `read_link` reads back, leaves existing links
+/// alone with `WriteNew`, replaces them with `Overwrite`, but never replaces a directory.
+pub async fn check_symlinks<FS: FileSystem>(fs: &FS, root: &AbsolutePath) -> Result<()> {
+    let link = AbsolutePath::new("releases/current", root);
+    ensure!(fs.create_symlink(&link, Path::new("v1"), WriteMode::WriteNew).await?, "WriteNew did not create a link: {link:?}");
+    let target = fs.read_link(&link).await?;
+    ensure!(target == Path::new("v1"), "Unexpected link target: {link:?}: {target:?}");
+
+    ensure!(!fs.create_symlink(&link, Path::new("v2"), WriteMode::WriteNew).await?, "WriteNew replaced an existing link: {link:?}");
+    ensure!(!fs.create_symlink(&link, Path::new("v2"), WriteMode::Ignore).await?, "Ignore replaced an existing link: {link:?}");
+    ensure!(fs.create_symlink(&link, Path::new("v2"), WriteMode::Overwrite).await?, "Overwrite did not replace a link: {link:?}");
+    let target = fs.read_link(&link).await?;
+    ensure!(target == Path::new("v2"), "Unexpected link target after Overwrite: {link:?}: {target:?}");
+
+    let directory = AbsolutePath::new("releases", root);
+    ensure!(fs.create_symlink(&directory, Path::new("elsewhere"), WriteMode::Overwrite).await.is_err(), "Replacing a directory with a link should fail");
+    ensure!(fs.read_link(&directory).await.is_err(), "Reading a directory as a link should fail");
+    Ok(())
+}
+
+/// `path_type`, `read_dir`, `open_source` and `canonicalize` follow symbolic links, `symlink_metadata` does not. Dangling
+/// links and links that point to themselves are `Missing`.
+pub async fn check_follow_symlinks<FS: FileSystem>(fs: &FS, root: &AbsolutePath) -> Result<()> {
+    let file = AbsolutePath::new("releases/v1/clock.yaml", root);
+    write_file(fs, &file, &["tick"], false).await?;
+    let current = AbsolutePath::new("releases/current", root);
+    fs.create_symlink(&current, Path::new("v1"), WriteMode::WriteNew).await?;
+    let dangling = AbsolutePath::new("releases/dangling", root);
+    fs.create_symlink(&dangling, Path::new("v0"), WriteMode::WriteNew).await?;
+    let looping = AbsolutePath::new("releases/looping", root);
+    fs.create_symlink(&looping, Path::new("looping"), WriteMode::WriteNew).await?;
+
+    for (path, expected) in [(&current, PathType::Directory), (&dangling, PathType::Missing), (&looping, PathType::Missing)] {
+        let actual = fs.path_type(path).await;
+        ensure!(actual == expected, "Unexpected path type: {path:?}: {actual:?} instead of {expected:?}");
+        let actual = fs.symlink_metadata(path).await?.path_type();
+        ensure!(actual == PathType::Symlink, "Unexpected path type without following links: {path:?}: {actual:?}");
+    }
+    let mut entries = pin!(fs.read_dir(&current).await?);
+    let mut names = Vec::new();
+    while let Some(entry) = entries.next().await {
+        names.push(entry?.file_name());
+    }
+    ensure!(names == vec![OsString::from("clock.yaml")], "Unexpected entries: {current:?}: {names:?}");
+
+    let via_link = AbsolutePath::new("releases/current/clock.yaml", root);
+    expect_content(fs, &via_link, "tick\n").await?;
+    let canonical = fs.canonicalize(&via_link).await?;
+    let expected = fs.canonicalize(&file).await?;
+    ensure!(canonical == expected && !canonical.to_string_lossy().contains("current"), "Unexpected canonical path: {via_link:?}: {canonical:?}");
+    ensure!(fs.canonicalize(&dangling).await.is_err(), "Resolving a dangling link should fail");
+    Ok(())
+}
+
+async fn write_file<FS: FileSystem>(fs: &FS, path: &AbsolutePath, lines: &[&str], executable: bool) -> Result<()> {
+    let Some(mut target_file) = fs.open_target(path.clone(), WriteMode::WriteNew, executable).await? else { bail!("Could not create: {path:?}") };
+    for line in lines {
+        target_file.write_line(*line).await?;
+    }
+    target_file.close().await
+}
+
+async fn expect_content<FS: FileSystem>(fs: &FS, path: &AbsolutePath, expected: &str) -> Result<()> {
+    let content = fs.get_content(path.clone()).await?;
+    ensure!(content == expected, "Unexpected content: {path:?}: {content:?} instead of {expected:?}");
+    Ok(())
+}
+
+fn expect_error(result: Result<()>, prefix: &str, action: &str) -> Result<()> {
+    let Err(error) = result else { bail!("{action} should fail") };
+    ensure!(error.to_string().starts_with(prefix), "{action} should fail with {prefix:?}: {error:?}");
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use assert_fs::TempDir;
+    use test_log::test;
+    use super::*;
+
+    #[test(tokio::test)]
+    async fn fixture_file_system() -> Result<()> {
+        let fs = fixture::from_toml("")?;
+        check_file_system(&fs, &AbsolutePath::root()).await
+    }
+
+    #[test(tokio::test)]
+    async fn real_file_system() -> Result<()> {
+        let tmp_dir = TempDir::new()?;
+        let fs = super::real_file_system();
+        check_file_system(&fs, &AbsolutePath::try_new(tmp_dir.to_path_buf())?).await
+    }
+}
@@ -1,26 +1,53 @@
+//! An in-memory file system, for tests and for runs that must not touch the disk. It is built from a description in
+//! TOML (with [`from_toml`]) or YAML (with [`from_yaml`]), where a table is a directory and a string is a file:
+//!
+//! ```toml
+//! [workshop]
+//! "clock.yaml" = "sweeper: Lu Tse"
+//! "wind-up.sh" = { content = "echo tick", mode = 0o755 }
+//! ```
+//!
+//! Every line of a file in the description ends with a newline. Files get mode `0o644` and directories `0o755`, unless
+//! a file has a `mode` of its own. Symbolic links can be added with `create_symlink`.
+
 use std::fmt;
 use std::io::{BufRead, BufReader};
-use std::path::Component;
+use std::pin::Pin;
 use std::sync::Arc;
+use std::sync::atomic::{AtomicU32, AtomicU64, Ordering};
+use std::time::{Duration, SystemTime};
 use ahash::AHashMap;
 use anyhow::anyhow;
 use async_stream::stream;
-use log::{debug, trace, warn};
+use log::{debug, trace};
 use serde::{Deserialize, Deserializer, Serialize, Serializer};
 use serde::de::{MapAccess, Visitor};
 use serde::ser::SerializeMap;
 use stringreader::StringReader;
 use tokio::sync::RwLock;
-use tokio::sync::mpsc::{Receiver,channel};
-use crate::config_model::WriteMode::{Ignore, Overwrite};
-use crate::file_system::fixture::FixtureContent::{DirFixtureContent, FileFixtureContent};
+use crate::config_model::WriteMode::{Append, Ignore, Overwrite};
+use crate::file_system::fixture::FixtureContent::{DirFixtureContent, FileFixtureContent, LinkFixtureContent};
 use crate::path::AbsolutePath;
 use super::*;
 
+const FILE_MODE: u32 = 0o644;
+const DIRECTORY_MODE: u32 = 0o755;
+const LINK_MODE: u32 = 0o777;
+
+/// The fixture has a clock of its own, which ticks a second for every change, so that a change is always later than
+/// the one before, however fast the test.
+static CLOCK: AtomicU64 = AtomicU64::new(1);
+
+fn tick() -> u64 {
+    CLOCK.fetch_add(1, Ordering::Relaxed)
+}
+
 #[derive(Debug)]
+#[allow(clippy::enum_variant_names)]
 enum FixtureContent {
     DirFixtureContent { entries: RwLock<AHashMap<OsString, Arc<FixtureEntry>>> },
-    FileFixtureContent { lines: RwLock<Vec<String>>},
+    FileFixtureContent { bytes: RwLock<Vec<u8>> },
+    LinkFixtureContent { target: PathBuf },
 }
 
 #[derive(Clone, Debug)]
@@ -33,11 +60,17 @@ struct FixtureEntry {
     file_name: OsString,
     path: AbsolutePath,
     is_dir: bool,
+    mode: AtomicU32,
+    /// The tick of the fixture clock at the last change of the content
+    modified: AtomicU64,
     content: FixtureContent,
 }
 
+/// Reads a snapshot of the content of a file, taken when the file was opened.
 struct FixtureSourceFile {
-    lines: Receiver<String>,
+    file_path: AbsolutePath,
+    bytes: Vec<u8>,
+    position: usize,
 }
 
 impl DirEntry for Arc<FixtureEntry> {
@@ -56,15 +89,27 @@ impl DirEntry for Arc<FixtureEntry> {
 
 impl TargetFile for Arc<FixtureEntry> {
     async fn write_line<S: Into<String> + Debug + Send>(&self, line: S) -> Result<()> {
-        if let FileFixtureContent { lines, .. } = &self.content {
-            let mut lines = lines.write().await;
-            lines.push(line.into());
+        if let FileFixtureContent { bytes } = &self.content {
+            let mut bytes = bytes.write().await;
+            bytes.extend(line.into().into_bytes());
+            bytes.push(b'\n');
+            self.touch();
             Ok(())
         } else {
             Err(anyhow!("Trying to write a line to a directory: {:?}: {:?}", &line, &self.path))
         }
     }
 
+    async fn write_chunk(&self, chunk: Vec<u8>) -> Result<()> {
+        if let FileFixtureContent { bytes } = &self.content {
+            bytes.write().await.extend(chunk);
+            self.touch();
+            Ok(())
+        } else {
+            Err(anyhow!("Trying to write a chunk to a directory: {:?}", &self.path))
+        }
+    }
+
     async fn close(&mut self) -> Result<()> {
         Ok(())
     }
@@ -72,7 +117,28 @@ impl TargetFile for Arc<FixtureEntry> {
 
 impl SourceFile for FixtureSourceFile {
     async fn next_line(&mut self) -> Result<Option<String>> {
-        Ok(self.lines.recv().await)
+        let rest = &self.bytes[self.position..];
+        if rest.is_empty() {
+            return Ok(None);
+        }
+        let newline = rest.iter().position(|byte| *byte == b'\n');
+        let mut line = rest[..newline.unwrap_or(rest.len())].to_vec();
+        self.position += newline.map_or(rest.len(), |index| index + 1);
+        if line.last() == Some(&b'\r') {
+            line.pop();
+        }
+        let line = String::from_utf8(line)
+            .map_err(|e| anyhow!(format!("Error fetching next line from: {:?}: {:?}", &self.file_path, e)))?;
+        Ok(Some(line))
+    }
+
+    async fn next_chunk(&mut self) -> Result<Option<Vec<u8>>> {
+        if self.position == self.bytes.len() {
+            return Ok(None);
+        }
+        let chunk = self.bytes[self.position..].to_vec();
+        self.position = self.bytes.len();
+        Ok(Some(chunk))
     }
 }
 
@@ -80,8 +146,11 @@ impl FileSystem for FixtureFileSystem {
     type DirEntryItem = Arc<FixtureEntry>;
 
     async fn read_dir(&self, directory: &AbsolutePath) -> Result<impl Stream<Item=Result<Self::DirEntryItem>> + Send + Sync + Unpin> {
+        let dir_entry = self.find_entry(directory, |_,_| Ok(None)).await?;
+        if !dir_entry.is_dir {
+            return Err(anyhow!("Not a directory: {:?}", directory));
+        }
         let entries = stream! {
-            let dir_entry = self.find_entry(directory, |_,_| Ok(None)).await?;
             if let DirFixtureContent { entries, .. } = &dir_entry.content {
                 let entries_content = entries.read().await;
                 for (_entry_name, entry) in entries_content.iter() {
@@ -94,14 +163,24 @@ impl FileSystem for FixtureFileSystem {
 
     async fn path_type(&self, path: &AbsolutePath) -> PathType {
         let Ok(entry) = self.find_entry(path, |_,_| Ok(None)).await else { return PathType::Missing };
-        if entry.is_dir {
-            PathType::Directory
-        } else {
-            PathType::File
-        }
+        entry.path_type()
     }
 
-    async fn open_target(&self, file_path: AbsolutePath, write_mode: WriteMode) -> Result<Option<impl TargetFile>> {
+    async fn metadata(&self, path: &AbsolutePath) -> Result<Metadata> {
+        let entry = self.find_entry(path, |_,_| Ok(None)).await?;
+        Ok(entry.metadata().await)
+    }
+
+    async fn symlink_metadata(&self, path: &AbsolutePath) -> Result<Metadata> {
+        let entry = self.resolve(path, |_,_| Ok(None), false).await?;
+        Ok(entry.metadata().await)
+    }
+
+    async fn canonicalize(&self, path: &AbsolutePath) -> Result<AbsolutePath> {
+        Ok(self.find_entry(path, |_,_| Ok(None)).await?.path.clone())
+    }
+
+    async fn open_target(&self, file_path: AbsolutePath, write_mode: WriteMode, executable: bool) -> Result<Option<impl TargetFile>> {
         if write_mode == Ignore {
             return Ok(None);
         }
@@ -112,29 +191,38 @@ impl FileSystem for FixtureFileSystem {
                 DirFixtureContent { entries, .. } => {
                     let mut entries_content = entries.write().await;
                     if let Some(file_entry) = entries_content.get(&file_name.clone()) {
-                        if write_mode == Overwrite {
-                            if let FileFixtureContent { lines, .. } = &file_entry.content {
-                                {
-                                    let mut lines_content = lines.write().await;
-                                    lines_content.truncate(0)
+                        if write_mode == Overwrite || write_mode == Append {
+                            if let FileFixtureContent { bytes } = &file_entry.content {
+                                if write_mode == Overwrite {
+                                    bytes.write().await.clear();
+                                    file_entry.touch();
+                                }
+                                // Appending to a file never takes away its executable bits
+                                if executable || write_mode == Overwrite {
+                                    file_entry.set_executable(executable);
                                 }
                                 Ok(Some(file_entry.clone()))
-                            } else {
+                            } else if file_entry.is_dir {
                                 Err(anyhow!("Trying to write lines to a directory: {:?}", file_path))
+                            } else {
+                                Err(anyhow!("Trying to write lines to a symbolic link: {:?}", file_path))
                             }
                         } else {
                             Ok(None)
                         }
                     } else {
                         let content = FileFixtureContent{
-                            lines: RwLock::new(Vec::new()),
+                            bytes: RwLock::new(Vec::new()),
                         };
                         let new_dir_entry = Arc::new(FixtureEntry {
                             file_name: file_name.clone(),
-                            path: file_path.clone(),
+                            path: AbsolutePath::new(&file_name, &current.path),
                             is_dir: false,
+                            mode: AtomicU32::new(FILE_MODE),
+                            modified: AtomicU64::new(tick()),
                             content
                         });
+                        new_dir_entry.set_executable(executable);
                         entries_content.insert(file_name, new_dir_entry.clone());
                         Ok(Some(new_dir_entry))
                     }
@@ -152,23 +240,149 @@ impl FileSystem for FixtureFileSystem {
         if file_entry.is_dir().await? {
             Err(anyhow!("Trying to read lines from a directory: {:?}", file_path))
         } else {
-            let (tx, rx) = channel(10);
-            tokio::spawn(send_lines(file_entry.clone(), tx));
-            Ok(FixtureSourceFile { lines: rx })
+            let FileFixtureContent { bytes } = &file_entry.content else { return Err(anyhow!("Not a file: {:?}", file_path)) };
+            let bytes = bytes.read().await.clone();
+            Ok(FixtureSourceFile { file_path, bytes, position: 0 })
+        }
+    }
+
+    async fn remove_file(&self, file_path: &AbsolutePath) -> Result<()> {
+        let (current, file_name) = self.find_parent_and_name(file_path).await?;
+        let DirFixtureContent { entries, .. } = &current.content else { return Err(anyhow!("Not a directory: {:?}", file_path.parent())) };
+        let mut entries_content = entries.write().await;
+        match entries_content.get(&file_name) {
+            Some(entry) if entry.is_dir => Err(anyhow!("Trying to remove a directory: {:?}", file_path)),
+            Some(_) => {
+                entries_content.remove(&file_name);
+                Ok(())
+            },
+            None => Err(anyhow!("Not found: {:?}", file_path)),
+        }
+    }
+
+    async fn remove_dir_all(&self, directory: &AbsolutePath) -> Result<()> {
+        let (parent, file_name) = self.find_parent_and_name(directory).await?;
+        let DirFixtureContent { entries, .. } = &parent.content else { return Err(anyhow!("Not a directory: {:?}", directory.parent())) };
+        let mut entries_content = entries.write().await;
+        match entries_content.get(&file_name) {
+            Some(entry) if entry.is_dir => {
+                entries_content.remove(&file_name);
+                Ok(())
+            },
+            Some(_) => Err(anyhow!("Not a directory: {:?}", directory)),
+            None => Err(anyhow!("Not found: {:?}", directory)),
+        }
+    }
+
+    async fn rename(&self, from: &AbsolutePath, to: &AbsolutePath) -> Result<()> {
+        let (from_parent, from_name) = self.find_parent_and_name(from).await?;
+        let (to_parent, to_name) = self.find_parent_and_name(to).await?;
+        let (DirFixtureContent { entries: from_entries }, DirFixtureContent { entries: to_entries }) = (&from_parent.content, &to_parent.content) else {
+            return Err(anyhow!("Not a directory: {:?} or {:?}", from.parent(), to.parent()));
+        };
+        let entry = from_entries.read().await.get(&from_name).cloned().ok_or_else(|| anyhow!("Not found: {:?}", from))?;
+        if to_entries.read().await.get(&to_name).is_some_and(|existing| existing.is_dir) {
+            return Err(anyhow!("Trying to replace a directory: {:?}", to));
+        }
+        let to_path = AbsolutePath::new(&to_name, &to_parent.path);
+        if entry.is_dir && to_path.starts_with(entry.path.as_path()) {
+            return Err(anyhow!("Trying to move a directory into itself: {:?}", to));
+        }
+        let moved = entry.relocate(to_name.clone(), to_path).await;
+        from_entries.write().await.remove(&from_name);
+        to_entries.write().await.insert(to_name, Arc::new(moved));
+        Ok(())
+    }
+
+    async fn set_mode(&self, file_path: &AbsolutePath, mode: u32) -> Result<()> {
+        let entry = self.find_entry(file_path, |_,_| Ok(None)).await?;
+        entry.mode.store(mode & 0o7777, Ordering::Relaxed);
+        Ok(())
+    }
+
+    async fn create_symlink(&self, link_path: &AbsolutePath, link_target: &Path, write_mode: WriteMode) -> Result<bool> {
+        if write_mode == Ignore {
+            return Ok(false);
+        }
+        let file_name = link_path.file_name().ok_or_else(|| anyhow!("Missing file name: {:?}", link_path))?.to_os_string();
+        let current = self.find_parent_entry(link_path).await?;
+        let DirFixtureContent { entries, .. } = &current.content else { return Err(anyhow!("Not a directory: {:?}", link_path.parent())) };
+        let mut entries_content = entries.write().await;
+        match entries_content.get(&file_name) {
+            Some(_) if write_mode != Overwrite => return Ok(false),
+            Some(entry) if entry.is_dir => return Err(anyhow!("Trying to replace a directory with a symbolic link: {:?}", link_path)),
+            _ => (),
+        }
+        let link_entry = FixtureEntry {
+            file_name: file_name.clone(),
+            path: AbsolutePath::new(&file_name, &current.path),
+            is_dir: false,
+            mode: AtomicU32::new(LINK_MODE),
+            modified: AtomicU64::new(tick()),
+            content: LinkFixtureContent { target: link_target.to_path_buf() },
+        };
+        entries_content.insert(file_name, Arc::new(link_entry));
+        Ok(true)
+    }
+
+    async fn read_link(&self, link_path: &AbsolutePath) -> Result<PathBuf> {
+        let entry = self.resolve(link_path, |_,_| Ok(None), false).await?;
+        match &entry.content {
+            LinkFixtureContent { target } => Ok(target.clone()),
+            _ => Err(anyhow!("Not a symbolic link: {:?}", link_path)),
         }
     }
 }
 
-async fn send_lines(file: Arc<FixtureEntry>, tx: Sender<String>) {
-    if let FileFixtureContent {lines, ..} = &file.content {
-        let lines_read = lines.read().await;
-        for line in lines_read.iter() {
-            if let Err(e) = tx.send(line.to_string()).await {
-                warn!("Error sending line: {:?}", e);
-                break;
-            }
+impl FixtureEntry {
+    fn path_type(&self) -> PathType {
+        match self.content {
+            DirFixtureContent { .. } => PathType::Directory,
+            FileFixtureContent { .. } => PathType::File,
+            LinkFixtureContent { .. } => PathType::Symlink,
         }
     }
+
+    /// Copies the entry (and everything in it) to another path. Entries know their path, so moving an entry means
+    /// replacing it.
+    fn relocate(&self, file_name: OsString, path: AbsolutePath) -> Pin<Box<dyn Future<Output = FixtureEntry> + Send + '_>> {
+        Box::pin(async move {
+            let content = match &self.content {
+                DirFixtureContent { entries } => {
+                    let mut relocated = AHashMap::new();
+                    for (entry_name, entry) in entries.read().await.iter() {
+                        let entry_path = AbsolutePath::new(entry_name, &path);
+                        relocated.insert(entry_name.clone(), Arc::new(entry.relocate(entry_name.clone(), entry_path).await));
+                    }
+                    DirFixtureContent { entries: RwLock::new(relocated) }
+                },
+                FileFixtureContent { bytes } => FileFixtureContent { bytes: RwLock::new(bytes.read().await.clone()) },
+                LinkFixtureContent { target } => LinkFixtureContent { target: target.clone() },
+            };
+            FixtureEntry { file_name, path, is_dir: self.is_dir, mode: AtomicU32::new(self.mode.load(Ordering::Relaxed)), modified: AtomicU64::new(self.modified.load(Ordering::Relaxed)), content }
+        })
+    }
+
+    async fn metadata(&self) -> Metadata {
+        let len = match &self.content {
+            DirFixtureContent { .. } => 0,
+            FileFixtureContent { bytes } => bytes.read().await.len(),
+            LinkFixtureContent { target } => target.as_os_str().len(),
+        };
+        let modified = SystemTime::UNIX_EPOCH + Duration::from_secs(self.modified.load(Ordering::Relaxed));
+        Metadata::new(self.path_type(), self.mode.load(Ordering::Relaxed), len as u64, Some(modified))
+    }
+
+    fn touch(&self) {
+        self.modified.store(tick(), Ordering::Relaxed);
+    }
+
+    /// Makes the file executable for anyone that may read it, or for nobody.
+    fn set_executable(&self, executable: bool) {
+        let mode = self.mode.load(Ordering::Relaxed);
+        let new_mode = if executable { mode | (mode & 0o444) >> 2 } else { mode & !0o111 };
+        self.mode.store(new_mode, Ordering::Relaxed);
+    }
 }
 
 impl FixtureFileSystem {
@@ -183,25 +397,34 @@ impl FixtureFileSystem {
         }
     }
 
+    /// Finds the directory that should contain a path (without creating it) and the name of the path in it.
+    async fn find_parent_and_name(&self, path: &AbsolutePath) -> Result<(Arc<FixtureEntry>, OsString)> {
+        let file_name = path.file_name().ok_or_else(|| anyhow!("Missing file name: {:?}", path))?.to_os_string();
+        let parent = match path.parent() {
+            Some(dir_path) => self.find_entry(&AbsolutePath::try_new(dir_path.to_path_buf())?, |_,_| Ok(None)).await?,
+            None => self.data.clone(),
+        };
+        Ok((parent, file_name))
+    }
+
     async fn find_entry(&self, dir_path: &AbsolutePath, dir_creator: impl DirectoryCreator) -> Result<Arc<FixtureEntry>> {
+        self.resolve(dir_path, dir_creator, true).await
+    }
+
+    /// Finds the entry for a path, following symbolic links in the directories of the path. A symbolic link at the
+    /// path itself is only followed if `follow_last` is set.
+    async fn resolve(&self, dir_path: &AbsolutePath, dir_creator: impl DirectoryCreator, follow_last: bool) -> Result<Arc<FixtureEntry>> {
         let mut current = self.data.clone();
         let mut current_path = PathBuf::from("/");
+        let components = normal_components(dir_path).unwrap_or_default();
+        let mut resolver = LinkResolver::new(components);
 
-        let mut components = dir_path.components().peekable();
-        if let Some(Component::RootDir) = &mut components.peek() {
-            let _root_dir = components.next();
-        }
-
-        for component in components {
-            if let Component::RootDir = component {
-                continue;
-            }
-            debug!("Component: {:?}", &component);
+        while let Some(part) = resolver.next_name() {
+            debug!("Component: {:?}", &part);
             let child_entry;
             if let DirFixtureContent {entries,..} = &current.content {
-                current_path.push(component);
+                current_path.push(&part);
                 debug!("Searching entry in {:?}", &current_path);
-                let part = component.as_os_str().to_os_string();
                 let entry_option = {
                     let entries_content = entries.read().await;
                     entries_content.get(&part).map(Arc::clone)
@@ -222,6 +445,17 @@ impl FixtureFileSystem {
             } else {
                 return Err(anyhow!("Not a directory: {:?}", &current_path))
             }
+            if let LinkFixtureContent { target } = &child_entry.content {
+                if resolver.has_more() || follow_last {
+                    // Start over from the root with the target of the link, followed by the remaining components
+                    let link_path = resolver.follow(AbsolutePath::new(target, &current.path).to_path_buf(), dir_path)?;
+                    debug!("Follow link {:?} to {:?}", &current_path, &link_path);
+                    resolver.restart(normal_components(&link_path).unwrap_or_default());
+                    current = self.data.clone();
+                    current_path = PathBuf::from("/");
+                    continue;
+                }
+            }
             current = child_entry;
         }
         debug!("Found entry: {:?}", &current.path);
@@ -246,6 +480,8 @@ fn create_new_directory(current_path: &PathBuf, part: &OsString) -> Result<Optio
         file_name: part.clone(),
         path: new_entry_path,
         is_dir: true,
+        mode: AtomicU32::new(DIRECTORY_MODE),
+        modified: AtomicU64::new(tick()),
         content: new_dir
     };
     debug!("Created new directory: {:?}", new_dir_entry);
@@ -255,10 +491,19 @@ fn create_new_directory(current_path: &PathBuf, part: &OsString) -> Result<Optio
 #[derive(Deserialize,Serialize,Debug)]
 #[serde(untagged)]
 enum FixtureEnum {
+    ModeFile(FixtureModeFile),
     Dir(FixtureDirectory),
     File(String),
 }
 
+/// A file with explicit permission bits, like `"run.sh" = { content = "...", mode = 0o755 }`.
+#[derive(Deserialize,Serialize,Debug)]
+#[serde(deny_unknown_fields)]
+struct FixtureModeFile {
+    content: String,
+    mode: u32,
+}
+
 #[derive(Debug)] // Serialize and Deserialize implemented below
 struct FixtureDirectory(AHashMap<String,Box<FixtureEnum>>);
 
@@ -303,19 +548,8 @@ impl From<FixtureEnum> for FixtureFileSystem {
 fn convert_enum(parent_path: &AbsolutePath, file_name: &str, data: Box<FixtureEnum>) -> FixtureEntry {
     let this_path = AbsolutePath::new(file_name, &parent_path);
     match *data {
-        FixtureEnum::File(body) => {
-            let body_iter = BufReader::new(StringReader::new(&body)).lines();
-            let mut lines = Vec::new();
-            for line in body_iter {
-                lines.push(line.unwrap())
-            }
-            FixtureEntry {
-                file_name: OsString::from(file_name),
-                path: this_path,
-                is_dir: false,
-                content: FileFixtureContent { lines: RwLock::new(lines) },
-            }
-        },
+        FixtureEnum::File(body) => convert_file(this_path, file_name, &body, FILE_MODE),
+        FixtureEnum::ModeFile(FixtureModeFile { content, mode }) => convert_file(this_path, file_name, &content, mode),
         FixtureEnum::Dir(entries) => {
             let mut content = AHashMap::new();
             for (entry_name, entry) in entries.0 {
@@ -327,18 +561,59 @@ fn convert_enum(parent_path: &AbsolutePath, file_name: &str, data: Box<FixtureEn
                 file_name: OsString::from(file_name),
                 path: this_path.clone(),
                 is_dir: true,
+                mode: AtomicU32::new(DIRECTORY_MODE),
+                modified: AtomicU64::new(tick()),
                 content: DirFixtureContent { entries: RwLock::new(content) },
             }
         },
     }
 }
 
+fn convert_file(this_path: AbsolutePath, file_name: &str, body: &str, mode: u32) -> FixtureEntry {
+    // Every line of the body ends with a newline, like in a text file
+    let mut bytes = Vec::new();
+    for line in BufReader::new(StringReader::new(body)).lines() {
+        bytes.extend(line.unwrap().into_bytes());
+        bytes.push(b'\n');
+    }
+    FixtureEntry {
+        file_name: OsString::from(file_name),
+        path: this_path,
+        is_dir: false,
+        mode: AtomicU32::new(mode),
+        modified: AtomicU64::new(tick()),
+        content: FileFixtureContent { bytes: RwLock::new(bytes) },
+    }
+}
+
+/// Creates a file system from a TOML description, with the root of the description as the root of the file system.
 pub fn from_toml(toml_data: &str) -> Result<impl FileSystem> {
     let data : FixtureEnum = toml::from_str(toml_data)?;
     debug!("File system data: {:?}", data);
     Ok::<FixtureFileSystem, anyhow::Error>(data.into())
 }
 
+/// Creates a file system from a YAML description, with the root of the description as the root of the file system.
+pub fn from_yaml(yaml_data: &str) -> Result<impl FileSystem> {
+    let data : FixtureEnum = serde_yaml::from_str(yaml_data)?;
+    debug!("File system data: {:?}", data);
+    Ok::<FixtureFileSystem, anyhow::Error>(data.into())
+}
+
+/// Fails (like `assert!`) unless the file at the path is executable.
+pub async fn assert_executable<FS: FileSystem>(fs: &FS, path: &str) -> Result<()> {
+    let metadata = fs.metadata(&AbsolutePath::try_from(path)?).await?;
+    assert!(metadata.is_executable(), "Not executable: {path:?}: {:o}", metadata.mode());
+    Ok(())
+}
+
+/// Fails (like `assert!`) if the file at the path is executable.
+pub async fn assert_not_executable<FS: FileSystem>(fs: &FS, path: &str) -> Result<()> {
+    let metadata = fs.metadata(&AbsolutePath::try_from(path)?).await?;
+    assert!(!metadata.is_executable(), "Executable: {path:?}: {:o}", metadata.mode());
+    Ok(())
+}
+
 #[cfg(test)]
 mod test {
     use std::pin::pin;
@@ -488,7 +763,7 @@ mod test {
         let file_path = to_absolute_path(file);
 
         // When
-        let Some(mut target_file) = fs.open_target(file_path.clone(), Overwrite).await? else { bail!("Could not open target") };
+        let Some(mut target_file) = fs.open_target(file_path.clone(), Overwrite, false).await? else { bail!("Could not open target") };
         target_file.write_line("Replacement").await?;
         target_file.close().await?;
 
@@ -509,7 +784,7 @@ mod test {
         let parent = to_absolute_path(parent_str);
 
         // When
-        let result = fs.open_target(parent, Overwrite).await;
+        let result = fs.open_target(parent, Overwrite, false).await;
 
         // Then
         let Err(err) = result else { bail!("Opening directory as target file should not be Ok") };
@@ -527,7 +802,7 @@ mod test {
         let parent = to_absolute_path(parent_str);
 
         // When
-        let target_file_option = fs.open_target(parent, WriteNew).await?;
+        let target_file_option = fs.open_target(parent, WriteNew, false).await?;
 
         // Then
         assert!(target_file_option.is_none());
@@ -541,7 +816,7 @@ mod test {
         let root = AbsolutePath::root();
 
         // When
-        let target_option = fs.open_target(root, Ignore).await?;
+        let target_option = fs.open_target(root, Ignore, false).await?;
 
         // Then
         assert!(target_option.is_none());
@@ -558,7 +833,7 @@ mod test {
         assert!(root.file_name().is_none());
 
         // When
-        let result = fs.open_target(root, WriteNew).await;
+        let result = fs.open_target(root, WriteNew, false).await;
 
         // Then
         let Err(err) = result else { bail!("Opening the root of the file-system should not be Ok") };
@@ -574,7 +849,7 @@ mod test {
         let file_in_file = to_absolute_path("/.profile/file");
 
         // When
-        let result = fs.open_target(file_in_file, WriteNew).await;
+        let result = fs.open_target(file_in_file, WriteNew, false).await;
 
         // Then
         let Err(err) = result else { bail!("Opening a target file in a file should not be Ok") };
@@ -593,7 +868,7 @@ mod test {
         let Err(_) = result else { bail!("Opening a source file in a file should not be Ok") };
 
         // When
-        let result = fs.open_target(new_file.clone(), WriteNew).await;
+        let result = fs.open_target(new_file.clone(), WriteNew, false).await;
 
         // Then
         if let Some(mut target_file) = result? {
@@ -605,6 +880,103 @@ mod test {
         Ok(())
     }
 
+    #[test(tokio::test)]
+    async fn remove_file() -> Result<()> {
+        // Given
+        let fs = create_test_fixture_file_system()?;
+        let file = to_absolute_path("/top-dir/sub-dir/file");
+        let dir = to_absolute_path("/top-dir/sub-dir/empty-dir");
+
+        // When
+        fs.remove_file(&file).await?;
+        let missing_result = fs.remove_file(&file).await;
+        let dir_result = fs.remove_file(&dir).await;
+
+        // Then
+        assert_eq!(fs.path_type(&file).await, PathType::Missing);
+        assert!(missing_result.is_err());
+        assert!(dir_result.is_err());
+        assert_eq!(fs.path_type(&dir).await, PathType::Directory);
+
+        Ok(())
+    }
+
+    #[test(tokio::test)]
+    async fn executable_target() -> Result<()> {
+        // Given
+        let toml_data = indoc! {r#"
+            [bin]
+            "wind-up.sh" = { content = "echo tick", mode = 0o750 }
+            "chime.sh" = "echo dong"
+        "#};
+        let fs = from_toml(toml_data)?;
+
+        // When
+        let Some(mut new_file) = fs.open_target(to_absolute_path("/bin/new.sh"), WriteNew, true).await? else { bail!("Could not open target") };
+        new_file.close().await?;
+        let Some(mut chime) = fs.open_target(to_absolute_path("/bin/chime.sh"), Overwrite, true).await? else { bail!("Could not open target") };
+        chime.close().await?;
+        let Some(mut plain) = fs.open_target(to_absolute_path("/bin/plain.txt"), WriteNew, false).await? else { bail!("Could not open target") };
+        plain.close().await?;
+
+        // Then
+        assert_eq!(fs.metadata(&to_absolute_path("/bin/wind-up.sh")).await?.mode(), 0o750);
+        assert_eq!(fs.metadata(&to_absolute_path("/bin/new.sh")).await?.mode(), 0o755);
+        assert_eq!(fs.metadata(&to_absolute_path("/bin")).await?.path_type(), PathType::Directory);
+        assert_executable(&fs, "/bin/chime.sh").await?;
+        assert_not_executable(&fs, "/bin/plain.txt").await?;
+        assert_not_executable(&fs, "/bin").await?;
+
+        Ok(())
+    }
+
+    #[test(tokio::test)]
+    async fn from_yaml_description() -> Result<()> {
+        // Given
+        let yaml_data = indoc! {r#"
+            workshop:
+              clock.yaml: |
+                sweeper: Lu Tse
+                hours: 10
+              bin:
+                wind-up.sh:
+                  content: echo tick
+                  mode: 0o750
+        "#};
+
+        // When
+        let fs = from_yaml(yaml_data)?;
+
+        // Then
+        assert_eq!(fs.get_content(to_absolute_path("/workshop/clock.yaml")).await?, "sweeper: Lu Tse\nhours: 10\n");
+        assert_eq!(fs.metadata(&to_absolute_path("/workshop/bin/wind-up.sh")).await?.mode(), 0o750);
+        assert_eq!(fs.path_type(&to_absolute_path("/workshop/bin")).await, PathType::Directory);
+        Ok(())
+    }
+
+    #[test(tokio::test)]
+    async fn modification_time() -> Result<()> {
+        // Given
+        let fs = from_toml(indoc! {r#"
+            [workshop]
+            "clock.yaml" = "sweeper: Lu Tse"
+            "mirror.yaml" = "frame: wood"
+        "#})?;
+        let clock_path = to_absolute_path("/workshop/clock.yaml");
+        let before = fs.metadata(&clock_path).await?;
+
+        // When
+        fs.write_bytes(clock_path.clone(), b"sweeper: Lobsang\n", Overwrite, false).await?;
+
+        // Then
+        let after = fs.metadata(&clock_path).await?;
+        assert!(after.modified() > before.modified());
+        assert_eq!((before.len(), after.len()), (16, 17));
+        let mirror = fs.metadata(&to_absolute_path("/workshop/mirror.yaml")).await?;
+        assert!(mirror.modified() < after.modified());
+        Ok(())
+    }
+
     // Implementation details
 
     #[test(tokio::test)]
@@ -614,6 +986,8 @@ mod test {
             file_name: OsString::from("foo"),
             path: to_absolute_path("/foo"),
             is_dir: true,
+            mode: AtomicU32::new(DIRECTORY_MODE),
+            modified: AtomicU64::new(tick()),
             content: DirFixtureContent {
                 entries: RwLock::new(AHashMap::new())
             },
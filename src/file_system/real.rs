@@ -2,8 +2,8 @@ use std::ffi::OsString;
 use std::io::ErrorKind;
 use std::path::Path;
 use anyhow::{Result,anyhow};
-use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader, Lines};
-use tokio::fs::{metadata, DirBuilder, DirEntry as TokioDirEntry, File, OpenOptions};
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::fs::{metadata, symlink_metadata, DirBuilder, DirEntry as TokioDirEntry, File, OpenOptions};
 use tokio::sync::mpsc::{channel, Receiver};
 use tokio::task::JoinHandle;
 use tokio_stream::StreamExt;
@@ -12,18 +12,27 @@ use crate::config_model::WriteMode;
 use crate::path::AbsolutePath;
 use super::*;
 
+/// Lines that are longer than this (in bytes, without the line ending) are rejected, unless the file system is
+/// created with another maximum.
+pub const DEFAULT_MAX_LINE_LENGTH: usize = 1024 * 1024;
+
+const CHUNK_SIZE: usize = 64 * 1024;
+
 #[derive(Debug, Copy, Clone)]
-struct RealFileSystem {}
+struct RealFileSystem {
+    max_line_length: usize,
+}
 
 struct RealTargetFile {
     file_path: AbsolutePath,
-    tx: Option<Sender<String>>,
+    tx: Option<Sender<Vec<u8>>>,
     join_handle: Option<JoinHandle<Result<()>>>
 }
 
 struct RealSourceFile {
     file_path: AbsolutePath,
-    lines: Lines<BufReader<File>>
+    reader: BufReader<File>,
+    max_line_length: usize,
 }
 
 impl DirEntry for TokioDirEntry {
@@ -45,6 +54,11 @@ impl FileSystem for RealFileSystem {
     type DirEntryItem = TokioDirEntry;
 
     async fn read_dir(&self, directory: &AbsolutePath) -> Result<impl Stream<Item = Result<Self::DirEntryItem>> + Send + Sync> {
+        match self.path_type(directory).await {
+            PathType::Directory => (),
+            PathType::Missing => return Err(anyhow!("Not found: {:?}", directory)),
+            _ => return Err(anyhow!("Not a directory: {:?}", directory)),
+        }
         let entries = tokio::fs::read_dir(&directory as &Path).await
             .map_err(|e| anyhow!(format!("error reading {:?}: {:?}", &directory, e)))?;
         Ok(ReadDirStream::new(entries).map(move |item| item.map_err(|e| anyhow!(format!("error traversing {:?}: {:?}", &directory, e)))))
@@ -52,16 +66,28 @@ impl FileSystem for RealFileSystem {
 
     async fn path_type(&self, path: &AbsolutePath) -> PathType {
         let Ok(path_metadata) = metadata(path.as_path()).await else { return PathType::Missing };
-        if path_metadata.is_dir() {
-            return PathType::Directory;
-        }
-        if path_metadata.is_file() {
-            return PathType::File;
-        }
-        PathType::Other
+        to_path_type(&path_metadata)
+    }
+
+    async fn metadata(&self, path: &AbsolutePath) -> Result<Metadata> {
+        let path_metadata = metadata(path.as_path()).await
+            .map_err(|e| anyhow!(format!("Error reading metadata of {:?}: {:?}", path, e)))?;
+        Ok(to_metadata(&path_metadata))
     }
 
-    async fn open_target(&self, target_file: AbsolutePath, write_mode: WriteMode) -> Result<Option<impl TargetFile>> {
+    async fn symlink_metadata(&self, path: &AbsolutePath) -> Result<Metadata> {
+        let path_metadata = symlink_metadata(path.as_path()).await
+            .map_err(|e| anyhow!(format!("Error reading metadata of {:?}: {:?}", path, e)))?;
+        Ok(to_metadata(&path_metadata))
+    }
+
+    async fn canonicalize(&self, path: &AbsolutePath) -> Result<AbsolutePath> {
+        let canonical = tokio::fs::canonicalize(path.as_path()).await
+            .map_err(|e| anyhow!(format!("Error resolving {:?}: {:?}", path, e)))?;
+        AbsolutePath::try_new(canonical)
+    }
+
+    async fn open_target(&self, target_file: AbsolutePath, write_mode: WriteMode, executable: bool) -> Result<Option<impl TargetFile>> {
         let mut open_options = OpenOptions::new().read(false).write(true).to_owned();
         let open_options = match write_mode {
             WriteMode::Ignore => {
@@ -69,13 +95,15 @@ impl FileSystem for RealFileSystem {
             },
             WriteMode::WriteNew => open_options.create_new(true),
             WriteMode::Overwrite => open_options.create(true).truncate(true),
+            WriteMode::Append => open_options.create(true).append(true),
         };
 
         let mut target_dir = target_file.to_path_buf();
         target_dir.pop();
         let mut dir_builder = DirBuilder::new();
         dir_builder.recursive(true);
-        dir_builder.create(target_dir.as_path()).await?;
+        dir_builder.create(target_dir.as_path()).await
+            .map_err(|error| FatalWriteError::wrap(&target_file, error))?;
 
         let result = open_options.open(target_file.as_path()).await;
         let file_option = match result {
@@ -84,13 +112,15 @@ impl FileSystem for RealFileSystem {
                 if let ErrorKind::AlreadyExists = error.kind() {
                     None
                 } else {
-                    return Err(error.into())
+                    return Err(FatalWriteError::wrap(&target_file, error))
                 }
             }
         };
         if let Some(file) = file_option {
+            // Appending to a file never takes away its executable bits
+            let executable = if executable || write_mode != WriteMode::Append { Some(executable) } else { None };
             let (tx, rx) = channel(10);
-            let join_handle = tokio::task::spawn(file_writer(rx, file));
+            let join_handle = tokio::task::spawn(file_writer(rx, file, target_file.clone(), executable));
             Ok(Some(RealTargetFile {
                 file_path: target_file,
                 tx: Some(tx),
@@ -103,27 +133,169 @@ impl FileSystem for RealFileSystem {
 
     async fn open_source(&self, source_path: AbsolutePath) -> Result<impl SourceFile> {
         let file = File::open(source_path.as_path()).await?;
-        let buffered_reader = BufReader::new(file);
-        let lines = buffered_reader.lines();
         Ok(RealSourceFile {
             file_path: source_path.clone(),
-            lines
+            reader: BufReader::new(file),
+            max_line_length: self.max_line_length,
         })
     }
+
+    async fn remove_file(&self, file_path: &AbsolutePath) -> Result<()> {
+        tokio::fs::remove_file(file_path.as_path()).await
+            .map_err(|e| anyhow!(format!("Error removing {:?}: {:?}", file_path, e)))
+    }
+
+    async fn remove_dir_all(&self, directory: &AbsolutePath) -> Result<()> {
+        match symlink_metadata(directory.as_path()).await {
+            Ok(directory_metadata) if directory_metadata.is_dir() => (),
+            Ok(_) => return Err(anyhow!("Not a directory: {:?}", directory)),
+            Err(_) => return Err(anyhow!("Not found: {:?}", directory)),
+        }
+        tokio::fs::remove_dir_all(directory.as_path()).await
+            .map_err(|e| anyhow!(format!("Error removing {:?}: {:?}", directory, e)))
+    }
+
+    async fn rename(&self, from: &AbsolutePath, to: &AbsolutePath) -> Result<()> {
+        if symlink_metadata(to.as_path()).await.is_ok_and(|to_metadata| to_metadata.is_dir()) {
+            return Err(anyhow!("Trying to replace a directory: {:?}", to));
+        }
+        tokio::fs::rename(from.as_path(), to.as_path()).await
+            .map_err(|error| FatalWriteError::wrap(to, error))
+    }
+
+    #[cfg(unix)]
+    async fn set_mode(&self, file_path: &AbsolutePath, mode: u32) -> Result<()> {
+        use std::os::unix::fs::PermissionsExt;
+        tokio::fs::set_permissions(file_path.as_path(), std::fs::Permissions::from_mode(mode)).await
+            .map_err(|e| anyhow!(format!("Error setting mode of {:?}: {:?}", file_path, e)))
+    }
+
+    /// Without Unix permission bits, only the write permission of the owner counts.
+    #[cfg(not(unix))]
+    async fn set_mode(&self, file_path: &AbsolutePath, mode: u32) -> Result<()> {
+        let mut permissions = metadata(file_path.as_path()).await
+            .map_err(|e| anyhow!(format!("Error setting mode of {:?}: {:?}", file_path, e)))?
+            .permissions();
+        permissions.set_readonly(mode & 0o200 == 0);
+        tokio::fs::set_permissions(file_path.as_path(), permissions).await
+            .map_err(|e| anyhow!(format!("Error setting mode of {:?}: {:?}", file_path, e)))
+    }
+
+    async fn create_symlink(&self, link_path: &AbsolutePath, link_target: &Path, write_mode: WriteMode) -> Result<bool> {
+        if write_mode == WriteMode::Ignore {
+            return Ok(false);
+        }
+        if let Ok(existing) = symlink_metadata(link_path.as_path()).await {
+            if write_mode != WriteMode::Overwrite {
+                return Ok(false);
+            }
+            if existing.is_dir() {
+                return Err(anyhow!("Trying to replace a directory with a symbolic link: {:?}", link_path));
+            }
+            tokio::fs::remove_file(link_path.as_path()).await
+                .map_err(|error| FatalWriteError::wrap(link_path, error))?;
+        }
+        if let Some(link_dir) = link_path.parent() {
+            DirBuilder::new().recursive(true).create(link_dir).await
+                .map_err(|error| FatalWriteError::wrap(link_path, error))?;
+        }
+        symlink(link_target, link_path.as_path()).await
+            .map_err(|error| FatalWriteError::wrap(link_path, error))?;
+        Ok(true)
+    }
+
+    async fn read_link(&self, link_path: &AbsolutePath) -> Result<PathBuf> {
+        tokio::fs::read_link(link_path.as_path()).await
+            .map_err(|e| anyhow!(format!("Error reading link {:?}: {:?}", link_path, e)))
+    }
+}
+
+fn to_metadata(path_metadata: &std::fs::Metadata) -> Metadata {
+    Metadata::new(to_path_type(path_metadata), permission_bits(path_metadata), path_metadata.len(), path_metadata.modified().ok())
 }
 
-async fn file_writer(rx: Receiver<String>, mut target: File) -> Result<()> {
+fn to_path_type(path_metadata: &std::fs::Metadata) -> PathType {
+    if path_metadata.is_dir() {
+        return PathType::Directory;
+    }
+    if path_metadata.is_file() {
+        return PathType::File;
+    }
+    if path_metadata.is_symlink() {
+        return PathType::Symlink;
+    }
+    PathType::Other
+}
+
+#[cfg(unix)]
+async fn symlink(link_target: &Path, link_path: &Path) -> std::io::Result<()> {
+    tokio::fs::symlink(link_target, link_path).await
+}
+
+#[cfg(not(unix))]
+async fn symlink(_link_target: &Path, _link_path: &Path) -> std::io::Result<()> {
+    Err(std::io::Error::new(ErrorKind::Unsupported, "Symbolic links are not supported on this platform"))
+}
+
+/// The permission bits of a file. Without Unix permission bits, a file is read-only or not.
+fn permission_bits(path_metadata: &std::fs::Metadata) -> u32 {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        path_metadata.permissions().mode() & 0o7777
+    }
+    #[cfg(not(unix))]
+    {
+        if path_metadata.permissions().readonly() { 0o444 } else { 0o644 }
+    }
+}
+
+/// Writes the chunks to the file and, when they are all written, makes the file executable for anyone that may read
+/// it (`Some(true)`) or for nobody (`Some(false)`).
+async fn file_writer(rx: Receiver<Vec<u8>>, mut target: File, file_path: AbsolutePath, executable: Option<bool>) -> Result<()> {
     let mut rx = rx;
-    while let Some(line) = rx.recv().await {
-        target.write_all(line.as_bytes()).await?;
+    while let Some(content) = rx.recv().await {
+        target.write_all(&content).await.map_err(|error| FatalWriteError::wrap(&file_path, error))?;
+    }
+    target.flush().await.map_err(|error| FatalWriteError::wrap(&file_path, error))?;
+    if let Some(executable) = executable {
+        set_executable(&target, executable).await
+            .map_err(|e| anyhow!(format!("Error setting mode of {:?}: {:?}", &file_path, e)))?;
+    }
+    Ok(())
+}
+
+#[cfg(unix)]
+async fn set_executable(target: &File, executable: bool) -> std::io::Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+    let mut permissions = target.metadata().await?.permissions();
+    let mode = permissions.mode();
+    let new_mode = if executable { mode | (mode & 0o444) >> 2 } else { mode & !0o111 };
+    if new_mode != mode {
+        permissions.set_mode(new_mode);
+        target.set_permissions(permissions).await?;
     }
     Ok(())
 }
 
+/// Without Unix permission bits, whether a file is executable does not depend on its permissions.
+#[cfg(not(unix))]
+async fn set_executable(_target: &File, _executable: bool) -> std::io::Result<()> {
+    Ok(())
+}
+
 impl TargetFile for RealTargetFile {
     async fn write_line<S: Into<String> + Send>(&self, line: S) -> Result<()> {
         if let Some(tx) = &self.tx {
-            tx.send(line.into() + "\n").await.map_err(|e| anyhow!(format!("Error wirting line to {:?}: {:?}", &self.file_path, e)))
+            tx.send((line.into() + "\n").into_bytes()).await.map_err(|e| anyhow!(format!("Error wirting line to {:?}: {:?}", &self.file_path, e)))
+        } else {
+            Err(anyhow!(format!("Target file already closed: {:?}", &self.file_path)))
+        }
+    }
+
+    async fn write_chunk(&self, chunk: Vec<u8>) -> Result<()> {
+        if let Some(tx) = &self.tx {
+            tx.send(chunk).await.map_err(|e| anyhow!(format!("Error writing chunk to {:?}: {:?}", &self.file_path, e)))
         } else {
             Err(anyhow!(format!("Target file already closed: {:?}", &self.file_path)))
         }
@@ -133,7 +305,7 @@ impl TargetFile for RealTargetFile {
         if let Some(join_handle) = &mut self.join_handle.take() {
             let tx = self.tx.take();
             drop(tx);
-            join_handle.await?.map_err(|e| anyhow!(format!("Error closing {:?}: {:?}", &self.file_path, e)))
+            join_handle.await?.map_err(|e| e.context(format!("Error closing {:?}", &self.file_path)))
         } else {
             Err(anyhow!("Closed already: {:?}", &self.file_path))
         }
@@ -142,12 +314,54 @@ impl TargetFile for RealTargetFile {
 
 impl SourceFile for RealSourceFile {
     async fn next_line(&mut self) -> Result<Option<String>> {
-        self.lines.next_line().await.map_err(|e| anyhow!(format!("Error fetching next line from: {:?}: {:?}", &self.file_path, e)))
+        let mut line = Vec::new();
+        loop {
+            let buffer = self.reader.fill_buf().await
+                .map_err(|e| anyhow!(format!("Error fetching next line from: {:?}: {:?}", &self.file_path, e)))?;
+            if buffer.is_empty() {
+                if line.is_empty() {
+                    return Ok(None);
+                }
+                break;
+            }
+            let newline = buffer.iter().position(|byte| *byte == b'\n');
+            let content_length = newline.unwrap_or(buffer.len());
+            if line.len() + content_length > self.max_line_length {
+                return Err(anyhow!("Line longer than {} bytes in: {:?}", self.max_line_length, &self.file_path));
+            }
+            line.extend_from_slice(&buffer[..content_length]);
+            self.reader.consume(newline.map(|index| index + 1).unwrap_or(content_length));
+            if newline.is_some() {
+                break;
+            }
+        }
+        if line.last() == Some(&b'\r') {
+            line.pop();
+        }
+        let line = String::from_utf8(line)
+            .map_err(|e| anyhow!(format!("Error fetching next line from: {:?}: {:?}", &self.file_path, e)))?;
+        Ok(Some(line))
+    }
+
+    async fn next_chunk(&mut self) -> Result<Option<Vec<u8>>> {
+        let mut chunk = vec![0; CHUNK_SIZE];
+        let length = self.reader.read(&mut chunk).await
+            .map_err(|e| anyhow!(format!("Error fetching next chunk from: {:?}: {:?}", &self.file_path, e)))?;
+        if length == 0 {
+            return Ok(None);
+        }
+        chunk.truncate(length);
+        Ok(Some(chunk))
     }
 }
 
+#[cfg(test)]
 pub fn real_file_system() -> impl FileSystem<DirEntryItem = impl DirEntry> {
-    RealFileSystem{}
+    real_file_system_with_max_line_length(DEFAULT_MAX_LINE_LENGTH)
+}
+
+pub fn real_file_system_with_max_line_length(max_line_length: usize) -> impl FileSystem<DirEntryItem = impl DirEntry> {
+    RealFileSystem { max_line_length }
 }
 
 #[cfg(test)]
@@ -210,7 +424,7 @@ mod test {
         let path = AbsolutePath::try_new(tmp_dir.to_path_buf())?;
         let file_path = AbsolutePath::new("content", &path);
 
-        let target_file = fs.open_target(file_path.clone(), WriteMode::Overwrite).await?.unwrap();
+        let target_file = fs.open_target(file_path.clone(), WriteMode::Overwrite, false).await?.unwrap();
         target_file.write_line("First line.").await?;
         target_file.write_line("Second line.").await?;
         let mut target_file_mut = target_file;
@@ -237,7 +451,7 @@ mod test {
         let path = AbsolutePath::try_new(tmp_dir.to_path_buf())?;
         let file_path = AbsolutePath::new("content", &path);
 
-        if let Some(_) = fs.open_target(file_path.clone(), WriteMode::Ignore).await? {
+        if let Some(_) = fs.open_target(file_path.clone(), WriteMode::Ignore, false).await? {
             assert!(false, "Opening an ignored target should return None");
         }
         Ok(())
@@ -252,7 +466,7 @@ mod test {
 
         assert_eq!(fs.path_type(&path).await, PathType::Directory);
         assert_eq!(fs.path_type(&file_path).await, PathType::Missing);
-        if let Some(target_file) = fs.open_target(file_path.clone(), WriteMode::WriteNew).await? {
+        if let Some(target_file) = fs.open_target(file_path.clone(), WriteMode::WriteNew, false).await? {
             target_file.write_line("Some line.").await?;
             let mut target_file_mut = target_file;
             target_file_mut.close().await?;
@@ -260,9 +474,105 @@ mod test {
             assert!(false, "Could not open target file");
         }
         assert_eq!(fs.path_type(&file_path).await, PathType::File);
-        if let Some(_) = fs.open_target(file_path.clone(), WriteMode::WriteNew).await? {
+        if let Some(_) = fs.open_target(file_path.clone(), WriteMode::WriteNew, false).await? {
             assert!(false, "Opening an existing file with WriteNew should not be possible");
         }
         Ok(())
     }
+
+    #[tokio::test]
+    async fn executable() -> Result<()> {
+        let tmp_dir = TempDir::new()?;
+        let fs = real_file_system();
+        let path = AbsolutePath::try_new(tmp_dir.to_path_buf())?;
+        let script_path = AbsolutePath::new("wind-up.sh", &path);
+        let plain_path = AbsolutePath::new("clock.yaml", &path);
+
+        for (file_path, executable) in [(&script_path, true), (&plain_path, false)] {
+            let Some(mut target_file) = fs.open_target(file_path.clone(), WriteMode::Overwrite, executable).await? else { panic!("Could not open target file") };
+            target_file.write_line("tick").await?;
+            target_file.close().await?;
+        }
+
+        let script_mode = fs.metadata(&script_path).await?.mode();
+        let plain_mode = fs.metadata(&plain_path).await?.mode();
+        assert_eq!(script_mode & 0o111, (script_mode & 0o444) >> 2, "Mode of script: {script_mode:o}");
+        assert_eq!(plain_mode & 0o111, 0, "Mode of plain file: {plain_mode:o}");
+        assert_eq!(script_mode & !0o111, plain_mode);
+        assert_eq!(fs.metadata(&path).await?.path_type(), PathType::Directory);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn max_line_length() -> Result<()> {
+        let tmp_dir = TempDir::new()?;
+        let fs = real_file_system_with_max_line_length(8);
+        let path = AbsolutePath::try_new(tmp_dir.to_path_buf())?;
+        let file_path = AbsolutePath::new("minified.js", &path);
+        tokio::fs::write(file_path.as_path(), "short\r\nway too long\n").await?;
+
+        let mut source_file = fs.open_source(file_path).await?;
+        assert_eq!(source_file.next_line().await?, Some("short".to_string()));
+        let Err(error) = source_file.next_line().await else { panic!("Expected the line to be too long") };
+        assert!(error.to_string().starts_with("Line longer than 8 bytes in: "), "Actual error: {error}");
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn copy_raw_chunks() -> Result<()> {
+        let tmp_dir = TempDir::new()?;
+        let fs = real_file_system_with_max_line_length(8);
+        let path = AbsolutePath::try_new(tmp_dir.to_path_buf())?;
+        let source_path = AbsolutePath::new("blob.json", &path);
+        let target_path = AbsolutePath::new("copy.json", &path);
+        let content = format!("{{\"data\":\"{}\"}}", "x".repeat(3 * CHUNK_SIZE));
+        tokio::fs::write(source_path.as_path(), &content).await?;
+
+        let source_file = fs.open_source(source_path).await?;
+        let Some(mut target_file) = fs.open_target(target_path.clone(), WriteMode::Overwrite, false).await? else { panic!("Could not open target file") };
+        copy_chunks(source_file, &target_file).await?;
+        target_file.close().await?;
+
+        assert_eq!(tokio::fs::read_to_string(target_path.as_path()).await?, content);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn crlf_line_endings() -> Result<()> {
+        let tmp_dir = TempDir::new()?;
+        let fs = real_file_system();
+        let path = AbsolutePath::try_new(tmp_dir.to_path_buf())?;
+        let source_path = AbsolutePath::new("source", &path);
+        let target_path = AbsolutePath::new("target", &path);
+        tokio::fs::write(source_path.as_path(), "tick\r\ntock\r\n").await?;
+
+        let detected = detect_line_endings(fs.open_source(source_path.clone()).await?).await?;
+        let Some(mut target_file) = fs.open_target(target_path.clone(), WriteMode::Overwrite, false).await? else { panic!("Could not open target file") };
+        let mut source_file = fs.open_source(source_path).await?;
+        while let Some(line) = source_file.next_line().await? {
+            CrlfTargetFile(&target_file).write_line(line).await?;
+        }
+        target_file.close().await?;
+
+        assert_eq!(detected, Some(LineEndings::Crlf));
+        assert_eq!(tokio::fs::read_to_string(target_path.as_path()).await?, "tick\r\ntock\r\n");
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn fatal_write_error() -> Result<()> {
+        let fs = real_file_system();
+        let full_device = AbsolutePath::try_from("/dev/full")?;
+        if fs.path_type(&full_device).await == PathType::Missing {
+            return Ok(());
+        }
+
+        let Some(mut target_file) = fs.open_target(full_device.clone(), WriteMode::Overwrite, false).await? else { panic!("Could not open target file") };
+        target_file.write_line("tick").await?;
+        let Err(error) = target_file.close().await else { panic!("Expected the device to be full") };
+
+        let fatal_write_error = FatalWriteError::find(&error).expect("Expected a fatal write error");
+        assert!(fatal_write_error.to_string().starts_with("Fatal error writing /dev/full: "), "Actual error: {fatal_write_error}");
+        Ok(())
+    }
 }
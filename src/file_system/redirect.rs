@@ -0,0 +1,219 @@
+use ahash::AHashSet;
+use anyhow::Result;
+use log::{debug, warn};
+use tokio_stream::StreamExt;
+use crate::config_model::WriteMode;
+use crate::path::AbsolutePath;
+use super::*;
+
+/// Writes the files below a directory (the project root) to a parallel directory tree instead (the output directory),
+/// preserving their paths relative to the project root. Reads prefer the files in the output directory, so that a run
+/// sees the files that it wrote before. Paths outside the project root are left alone.
+#[derive(Debug, Clone)]
+pub struct RedirectedFileSystem<FS: FileSystem> {
+    file_system: FS,
+    project_root: AbsolutePath,
+    output_directory: AbsolutePath,
+}
+
+impl<FS: FileSystem> RedirectedFileSystem<FS> {
+    pub fn new(file_system: FS, project_root: AbsolutePath, output_directory: AbsolutePath) -> Self {
+        RedirectedFileSystem { file_system, project_root, output_directory }
+    }
+
+    fn redirect(&self, path: &AbsolutePath) -> Option<AbsolutePath> {
+        if path.starts_with(self.output_directory.as_path()) {
+            return None;
+        }
+        let relative = path.strip_prefix(self.project_root.as_path()).ok()?;
+        Some(AbsolutePath::new(relative, &self.output_directory))
+    }
+
+    /// The path to read from: the redirected path if it exists, the original path otherwise.
+    async fn source_path(&self, path: &AbsolutePath) -> AbsolutePath {
+        match self.redirect(path) {
+            Some(redirected) if self.file_system.symlink_metadata(&redirected).await.is_ok() => redirected,
+            _ => path.clone(),
+        }
+    }
+}
+
+impl<FS: FileSystem> FileSystem for RedirectedFileSystem<FS> {
+    type DirEntryItem = FS::DirEntryItem;
+
+    async fn read_dir(&self, directory: &AbsolutePath) -> Result<impl Stream<Item=Result<Self::DirEntryItem>> + Send + Sync + Unpin> {
+        let mut directories = Vec::new();
+        if let Some(redirected) = self.redirect(directory) {
+            if self.file_system.path_type(&redirected).await == PathType::Directory {
+                directories.push(redirected);
+            }
+        }
+        if directories.is_empty() || self.file_system.path_type(directory).await == PathType::Directory {
+            directories.push(directory.clone());
+        }
+        let mut file_names = AHashSet::new();
+        let mut entries = Vec::new();
+        for directory in directories {
+            let mut stream = self.file_system.read_dir(&directory).await?;
+            while let Some(entry) = stream.next().await {
+                let entry = entry?;
+                if file_names.insert(entry.file_name()) {
+                    entries.push(Ok(entry));
+                }
+            }
+        }
+        Ok(tokio_stream::iter(entries))
+    }
+
+    async fn path_type(&self, path: &AbsolutePath) -> PathType {
+        self.file_system.path_type(&self.source_path(path).await).await
+    }
+
+    async fn metadata(&self, path: &AbsolutePath) -> Result<Metadata> {
+        self.file_system.metadata(&self.source_path(path).await).await
+    }
+
+    async fn symlink_metadata(&self, path: &AbsolutePath) -> Result<Metadata> {
+        self.file_system.symlink_metadata(&self.source_path(path).await).await
+    }
+
+    async fn canonicalize(&self, path: &AbsolutePath) -> Result<AbsolutePath> {
+        self.file_system.canonicalize(&self.source_path(path).await).await
+    }
+
+    async fn open_target(&self, file_path: AbsolutePath, write_mode: WriteMode, executable: bool) -> Result<Option<impl TargetFile>> {
+        if write_mode == WriteMode::WriteNew && self.path_type(&file_path).await != PathType::Missing {
+            return Ok(None);
+        }
+        let target_path = match self.redirect(&file_path) {
+            Some(redirected) => {
+                // Appending starts from the content of the original file
+                if write_mode == WriteMode::Append && self.file_system.path_type(&redirected).await == PathType::Missing && self.file_system.path_type(&file_path).await == PathType::File {
+                    let content = self.file_system.read_bytes(file_path).await?;
+                    self.file_system.write_bytes(redirected.clone(), &content, WriteMode::Overwrite, false).await?;
+                }
+                redirected
+            },
+            None => file_path,
+        };
+        debug!("Redirected target: {:?}", &target_path);
+        self.file_system.open_target(target_path, write_mode, executable).await
+    }
+
+    async fn open_source(&self, file_path: AbsolutePath) -> Result<impl SourceFile> {
+        self.file_system.open_source(self.source_path(&file_path).await).await
+    }
+
+    async fn remove_file(&self, file_path: &AbsolutePath) -> Result<()> {
+        match self.redirect(file_path) {
+            Some(redirected) if self.file_system.path_type(&redirected).await == PathType::File => self.file_system.remove_file(&redirected).await,
+            Some(_) => {
+                warn!("Cannot remove a file outside the output directory: {:?}", file_path);
+                Ok(())
+            },
+            None => self.file_system.remove_file(file_path).await,
+        }
+    }
+
+    async fn remove_dir_all(&self, directory: &AbsolutePath) -> Result<()> {
+        match self.redirect(directory) {
+            Some(redirected) if self.file_system.path_type(&redirected).await == PathType::Directory => self.file_system.remove_dir_all(&redirected).await,
+            Some(_) => {
+                warn!("Cannot remove a directory outside the output directory: {:?}", directory);
+                Ok(())
+            },
+            None => self.file_system.remove_dir_all(directory).await,
+        }
+    }
+
+    /// A file that is not in the output directory yet is copied instead, because the original stays where it is.
+    async fn rename(&self, from: &AbsolutePath, to: &AbsolutePath) -> Result<()> {
+        let to = self.redirect(to).unwrap_or(to.clone());
+        match self.redirect(from) {
+            Some(redirected) if self.file_system.symlink_metadata(&redirected).await.is_ok() => self.file_system.rename(&redirected, &to).await,
+            Some(_) => {
+                warn!("Cannot remove a file outside the output directory: {:?}", from);
+                let content = self.file_system.read_bytes(from.clone()).await?;
+                let executable = self.file_system.metadata(from).await?.is_executable();
+                self.file_system.write_bytes(to, &content, WriteMode::Overwrite, executable).await.map(|_| ())
+            },
+            None => self.file_system.rename(from, &to).await,
+        }
+    }
+
+    async fn set_mode(&self, file_path: &AbsolutePath, mode: u32) -> Result<()> {
+        let Some(redirected) = self.redirect(file_path) else { return self.file_system.set_mode(file_path, mode).await };
+        // The file in the output directory starts from the content of the original file
+        if self.file_system.path_type(&redirected).await == PathType::Missing {
+            let content = self.file_system.read_bytes(file_path.clone()).await?;
+            self.file_system.write_bytes(redirected.clone(), &content, WriteMode::Overwrite, false).await?;
+        }
+        self.file_system.set_mode(&redirected, mode).await
+    }
+
+    async fn create_symlink(&self, link_path: &AbsolutePath, link_target: &Path, write_mode: WriteMode) -> Result<bool> {
+        if write_mode != WriteMode::Overwrite && self.symlink_metadata(link_path).await.is_ok() {
+            return Ok(false);
+        }
+        let redirected = self.redirect(link_path).unwrap_or(link_path.clone());
+        self.file_system.create_symlink(&redirected, link_target, write_mode).await
+    }
+
+    async fn read_link(&self, link_path: &AbsolutePath) -> Result<PathBuf> {
+        self.file_system.read_link(&self.source_path(link_path).await).await
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::pin::pin;
+    use indoc::indoc;
+    use test_log::test;
+    use crate::file_system::fixture;
+    use crate::path::test_utils::to_absolute_path;
+    use super::*;
+
+    #[test(tokio::test)]
+    async fn redirect_writes_to_output_directory() -> Result<()> {
+        // Given
+        let toml_data = indoc! {r#"
+            [project.workshop]
+            "clock.yaml" = "sweeper: Lu Tse"
+            "mirror.yaml" = "frame: wood"
+
+            [thundercloud]
+            "thundercloud.toml" = ""
+        "#};
+        let fs = fixture::from_toml(toml_data)?;
+        let redirected = fs.clone().redirect_writes(to_absolute_path("/project"), to_absolute_path("/review"));
+
+        // When
+        let mut target_file = redirected.open_target(to_absolute_path("/project/workshop/clock.yaml"), WriteMode::Overwrite, false).await?.unwrap();
+        target_file.write_line("sweeper: Lobsang").await?;
+        target_file.close().await?;
+        let write_new = redirected.open_target(to_absolute_path("/project/workshop/mirror.yaml"), WriteMode::WriteNew, false).await?;
+        let mut appended_file = redirected.open_target(to_absolute_path("/project/workshop/mirror.yaml"), WriteMode::Append, false).await?.unwrap();
+        appended_file.write_line("glass: polished").await?;
+        appended_file.close().await?;
+
+        // Then
+        assert!(write_new.is_none());
+        assert_eq!(fs.get_content(to_absolute_path("/project/workshop/clock.yaml")).await?, "sweeper: Lu Tse\n");
+        assert_eq!(fs.get_content(to_absolute_path("/review/workshop/clock.yaml")).await?, "sweeper: Lobsang\n");
+        assert_eq!(redirected.get_content(to_absolute_path("/project/workshop/clock.yaml")).await?, "sweeper: Lobsang\n");
+        assert_eq!(fs.get_content(to_absolute_path("/project/workshop/mirror.yaml")).await?, "frame: wood\n");
+        assert_eq!(redirected.get_content(to_absolute_path("/project/workshop/mirror.yaml")).await?, "frame: wood\nglass: polished\n");
+        assert_eq!(redirected.path_type(&to_absolute_path("/thundercloud/thundercloud.toml")).await, PathType::File);
+        let workshop = to_absolute_path("/project/workshop");
+        let entries = redirected.read_dir(&workshop).await?;
+        let mut entries = pin!(entries);
+        let mut file_names = Vec::new();
+        while let Some(entry) = entries.next().await {
+            file_names.push(entry?.file_name().to_string_lossy().to_string());
+        }
+        file_names.sort();
+        assert_eq!(file_names, vec!["clock.yaml", "mirror.yaml"]);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,124 @@
+use std::borrow::Cow;
+use std::fmt::{self, Debug};
+use std::sync::Arc;
+use ahash::AHashMap;
+use anyhow::{anyhow, bail, Result};
+use log::{debug, warn};
+use once_cell::sync::Lazy;
+use regex::Regex;
+use toml::Table;
+use crate::config_model::PlaceholderDelimiters;
+use crate::interpolate::{lookup, scalar_to_string};
+
+/// A function that can be called from an option or fragment as `{{fn:name(arg, ...)}}`. It gets the values of the
+/// arguments and returns the text that replaces the call.
+pub type Function = Arc<dyn Fn(&[String]) -> Result<String> + Send + Sync>;
+
+/// The functions that interpolation can call, by name.
+#[derive(Clone, Default)]
+pub struct FunctionRegistry(AHashMap<String, Function>);
+
+impl FunctionRegistry {
+    /// Makes a function available to the niches that are processed with this registry, for instance to look up values
+    /// in a secrets manager or to compute checksums. A function that is registered under the same name as an earlier
+    /// one replaces it.
+    pub fn register<F>(&mut self, name: impl Into<String>, function: F)
+    where F: Fn(&[String]) -> Result<String> + Send + Sync + 'static
+    {
+        self.0.insert(name.into(), Arc::new(function));
+    }
+
+    fn get(&self, name: &str) -> Option<&Function> {
+        self.0.get(name)
+    }
+}
+
+impl Debug for FunctionRegistry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut names: Vec<&String> = self.0.keys().collect();
+        names.sort();
+        f.debug_tuple("FunctionRegistry").field(&names).finish()
+    }
+}
+
+static ARGUMENT_REGEX: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r#"^\s*(?:"(?<literal>[^"]*)"|(?<prop>[A-Za-z][-A-Za-z0-9_.]*))\s*$"#).unwrap()
+});
+
+fn call_regex(delimiters: &PlaceholderDelimiters) -> Regex {
+    let open = regex::escape(delimiters.open());
+    let close = regex::escape(delimiters.close());
+    Regex::new(&format!(r"\\{open}|{open}fn:(?<name>[A-Za-z][-A-Za-z0-9_]*)\((?<arguments>[^)]*)\){close}")).unwrap()
+}
+
+/// Replaces the function calls in the source (like `{{fn:sha256(sweeper)}}`) by their results. An argument is either
+/// a string in double quotes or the name of a prop. Calls to unknown functions are left as is, unless `strict` is set.
+/// Opening delimiters in the results are escaped, so that they are not mistaken for placeholders later.
+pub fn call_functions<'a>(source: &'a str, variables: &Table, delimiters: &PlaceholderDelimiters, functions: &FunctionRegistry, strict: bool) -> Result<Cow<'a, str>> {
+    if !source.contains(&format!("{}fn:", delimiters.open())) {
+        return Ok(Cow::Borrowed(source));
+    }
+    let mut result = String::new();
+    let mut last_end = 0;
+    for captures in call_regex(delimiters).captures_iter(source) {
+        let Some(call) = captures.get(0) else { continue };
+        let Some(name) = captures.name("name").map(|m| m.as_str()) else { continue };
+        result.push_str(&source[last_end..call.start()]);
+        last_end = call.end();
+        let Some(function) = functions.get(name) else {
+            if strict {
+                bail!("Unknown function: {name}");
+            }
+            warn!("Unknown function: {name:?}");
+            result.push_str(call.as_str());
+            continue;
+        };
+        let arguments = arguments(&captures["arguments"], variables)?;
+        debug!("Call function: {name}({arguments:?})");
+        let value = function(&arguments).map_err(|error| anyhow!("Function {name} failed: {error}"))?;
+        result.push_str(&value.replace(delimiters.open(), &format!("\\{}", delimiters.open())));
+    }
+    result.push_str(&source[last_end..]);
+    Ok(Cow::Owned(result))
+}
+
+fn arguments(arguments: &str, variables: &Table) -> Result<Vec<String>> {
+    if arguments.trim().is_empty() {
+        return Ok(Vec::new());
+    }
+    arguments.split(',').map(|argument| {
+        let captures = ARGUMENT_REGEX.captures(argument).ok_or_else(|| anyhow!("Invalid argument: {:?}", argument.trim()))?;
+        if let Some(literal) = captures.name("literal") {
+            return Ok(literal.as_str().to_string());
+        }
+        let prop = &captures["prop"];
+        lookup(variables, prop).and_then(scalar_to_string).map(Cow::into_owned)
+            .ok_or_else(|| anyhow!("No value for argument: {prop}"))
+    }).collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn call_registered_functions() -> Result<()> {
+        // Given
+        let mut functions = FunctionRegistry::default();
+        functions.register("join", |arguments| Ok(arguments.join("+")));
+        functions.register("braces", |_| Ok("{{sweeper}}".to_string()));
+        let variables: Table = toml::from_str(r#"sweeper = "Lu Tse""#)?;
+        let delimiters = PlaceholderDelimiters::default();
+
+        // When
+        let result = call_functions(r#"{{fn:join(sweeper, "Lobsang")}} {{fn:join()}} {{fn:braces()}} \{{fn:join()}} {{fn:other(x)}}"#, &variables, &delimiters, &functions, false)?;
+
+        // Then
+        assert_eq!(result, r#"Lu Tse+Lobsang  \{{sweeper}} \{{fn:join()}} {{fn:other(x)}}"#);
+        let error = call_functions("{{fn:join(apprentice)}}", &variables, &delimiters, &functions, false).unwrap_err();
+        assert_eq!(error.to_string(), "No value for argument: apprentice");
+        let error = call_functions("{{fn:other()}}", &variables, &delimiters, &functions, true).unwrap_err();
+        assert_eq!(error.to_string(), "Unknown function: other");
+        Ok(())
+    }
+}
@@ -0,0 +1,275 @@
+use std::borrow::Cow;
+use ahash::AHashMap;
+use anyhow::{anyhow, bail, Result};
+use log::{debug, info};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use toml::Table;
+use crate::config_model::{PlaceholderDelimiters, WriteMode};
+use crate::file_system::{FileSystem, PathType, TargetFile};
+use crate::interpolate::lookup;
+use crate::path::AbsolutePath;
+
+/// The name of the file (next to the manifest of a niche) that holds the seed for stable random values.
+pub const RANDOM_SEED_FILE: &str = "random-seed.toml";
+
+/// The placeholder for a random UUID (unless a prop with the same name exists).
+pub const UUID: &str = "uuid";
+
+const ALPHANUMERIC: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789";
+
+type Seed = [u8; 32];
+
+#[derive(Deserialize, Serialize, Debug)]
+struct SeedFile {
+    seed: String,
+}
+
+/// Produces the values of the placeholders `{{uuid}}` (a random UUID, version 4), `{{random:hex16}}` (16 random
+/// hexadecimal digits) and `{{random:alnum24}}` (24 random letters and digits). Every occurrence gets a new value.
+/// With a seed, the values are stable: they only depend on the seed, the target file, the placeholder and the number of
+/// earlier occurrences of the same placeholder in the target file.
+#[derive(Debug, Default)]
+pub struct RandomValues {
+    seed: Option<Seed>,
+    /// All values are stable, whatever the invar configuration says (see [`reproducible_seed`]).
+    always_stable: bool,
+    target: String,
+    occurrences: AHashMap<String, u64>,
+}
+
+impl RandomValues {
+    pub fn set_seed(&mut self, seed: Seed) {
+        self.seed = Some(seed);
+    }
+
+    /// Makes all values stable with the given seed, including those of target files without `stable-random`.
+    pub fn set_reproducible_seed(&mut self, seed: Seed) {
+        self.seed = Some(seed);
+        self.always_stable = true;
+    }
+
+    /// Starts counting occurrences for another target file.
+    pub fn start_target(&mut self, target: String) {
+        self.target = target;
+        self.occurrences.clear();
+    }
+
+    fn key(&mut self, generator: &str, stable: bool) -> Result<Seed> {
+        if !stable && !self.always_stable {
+            return fresh_seed();
+        }
+        let seed = self.seed.ok_or_else(|| anyhow!("No seed for stable random values"))?;
+        let occurrence = self.occurrences.entry(generator.to_string()).or_default();
+        let key = Sha256::new()
+            .chain_update(seed)
+            .chain_update(self.target.as_bytes()).chain_update([0])
+            .chain_update(generator.as_bytes()).chain_update([0])
+            .chain_update(occurrence.to_be_bytes())
+            .finalize();
+        *occurrence += 1;
+        Ok(key.into())
+    }
+}
+
+fn fresh_seed() -> Result<Seed> {
+    let mut seed = Seed::default();
+    getrandom::getrandom(&mut seed).map_err(|error| anyhow!("Could not get random bytes: {error}"))?;
+    Ok(seed)
+}
+
+/// An endless stream of bytes that is derived from a key.
+struct RandomBytes {
+    key: Seed,
+    block: u64,
+    buffer: Vec<u8>,
+}
+
+impl RandomBytes {
+    fn new(key: Seed) -> Self {
+        RandomBytes { key, block: 0, buffer: Vec::new() }
+    }
+
+    fn next_byte(&mut self) -> u8 {
+        if self.buffer.is_empty() {
+            self.buffer = Sha256::new().chain_update(self.key).chain_update(self.block.to_be_bytes()).finalize().to_vec();
+            self.block += 1;
+        }
+        self.buffer.pop().unwrap_or_default()
+    }
+}
+
+fn generator_regex(delimiters: &PlaceholderDelimiters) -> Regex {
+    let open = regex::escape(delimiters.open());
+    let close = regex::escape(delimiters.close());
+    Regex::new(&format!(r"\\{open}|{open}(?<generator>{UUID}|random:(?<charset>hex|alnum)(?<length>[0-9]+)){close}")).unwrap()
+}
+
+/// Replaces the placeholders for random values in the source by freshly generated values (or stable ones, see
+/// [`RandomValues`]). A `{{uuid}}` placeholder is left alone if a prop `uuid` exists.
+pub fn generate_random_values<'a>(source: &'a str, variables: &Table, delimiters: &PlaceholderDelimiters, random_values: &mut RandomValues, stable: bool) -> Result<Cow<'a, str>> {
+    let open = delimiters.open();
+    if !source.contains(&format!("{open}{UUID}")) && !source.contains(&format!("{open}random:")) {
+        return Ok(Cow::Borrowed(source));
+    }
+    let mut result = String::new();
+    let mut last_end = 0;
+    for captures in generator_regex(delimiters).captures_iter(source) {
+        let Some(placeholder) = captures.get(0) else { continue };
+        let Some(generator) = captures.name("generator").map(|m| m.as_str()) else { continue };
+        if generator == UUID && lookup(variables, UUID).is_some() {
+            continue;
+        }
+        result.push_str(&source[last_end..placeholder.start()]);
+        last_end = placeholder.end();
+        let mut bytes = RandomBytes::new(random_values.key(generator, stable)?);
+        let value = match (captures.name("charset").map(|m| m.as_str()), captures.name("length")) {
+            (Some(charset), Some(length)) => {
+                let length: usize = length.as_str().parse().map_err(|_| anyhow!("Invalid length: {generator}"))?;
+                random_string(&mut bytes, charset, length)?
+            },
+            _ => uuid(&mut bytes),
+        };
+        debug!("Generated value for {generator}");
+        result.push_str(&value);
+    }
+    result.push_str(&source[last_end..]);
+    Ok(Cow::Owned(result))
+}
+
+fn random_string(bytes: &mut RandomBytes, charset: &str, length: usize) -> Result<String> {
+    let mut result = String::with_capacity(length);
+    while result.len() < length {
+        let byte = bytes.next_byte();
+        match charset {
+            "hex" => result.push_str(&format!("{:x}", byte & 0x0f)),
+            // Skip the bytes above the largest multiple of the size of the alphabet, so that every character is equally likely
+            "alnum" if (byte as usize) < 256 - 256 % ALPHANUMERIC.len() => result.push(ALPHANUMERIC[byte as usize % ALPHANUMERIC.len()] as char),
+            "alnum" => (),
+            _ => bail!("Unknown character set: {charset}"),
+        }
+    }
+    Ok(result)
+}
+
+fn uuid(bytes: &mut RandomBytes) -> String {
+    let mut uuid: Vec<u8> = (0..16).map(|_| bytes.next_byte()).collect();
+    uuid[6] = (uuid[6] & 0x0f) | 0x40;
+    uuid[8] = (uuid[8] & 0x3f) | 0x80;
+    let hex: String = uuid.iter().map(|byte| format!("{byte:02x}")).collect();
+    format!("{}-{}-{}-{}-{}", &hex[0..8], &hex[8..12], &hex[12..16], &hex[16..20], &hex[20..32])
+}
+
+/// The seed for the random values of a niche in reproducible mode: it is derived from the seed of the run and the name
+/// of the niche, instead of a random seed or the seed in the seed file of the niche.
+pub fn reproducible_seed(seed: u64, niche: &str) -> Seed {
+    Sha256::new().chain_update(seed.to_be_bytes()).chain_update(niche.as_bytes()).finalize().into()
+}
+
+/// Loads the seed for stable random values of a niche, or creates the seed file if it does not exist yet.
+pub async fn load_or_create_seed<FS: FileSystem>(seed_path: &AbsolutePath, fs: &FS) -> Result<Seed> {
+    if fs.path_type(seed_path).await == PathType::File {
+        let seed_file: SeedFile = toml::from_str(&fs.get_content(seed_path.clone()).await?)
+            .map_err(|error| anyhow!("{}: {error}", seed_path.to_string_lossy()))?;
+        return parse_seed(&seed_file.seed).ok_or_else(|| anyhow!("{}: Seed is not 64 hexadecimal digits", seed_path.to_string_lossy()));
+    }
+    let seed = fresh_seed()?;
+    info!("Create seed for stable random values: {:?}", seed_path);
+    let seed_file = SeedFile { seed: seed.iter().map(|byte| format!("{byte:02x}")).collect() };
+    let mut target_file = fs.open_target(seed_path.clone(), WriteMode::Overwrite, false).await?
+        .ok_or_else(|| anyhow!("Could not write seed: {:?}", seed_path))?;
+    for line in toml::to_string(&seed_file)?.lines() {
+        target_file.write_line(line).await?;
+    }
+    target_file.close().await?;
+    Ok(seed)
+}
+
+fn parse_seed(hex: &str) -> Option<Seed> {
+    if hex.len() != 64 || !hex.is_ascii() {
+        return None;
+    }
+    let mut seed = Seed::default();
+    for (index, byte) in seed.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&hex[2 * index..2 * index + 2], 16).ok()?;
+    }
+    Some(seed)
+}
+
+#[cfg(test)]
+mod test {
+    use test_log::test;
+    use crate::file_system::fixture;
+    use crate::path::test_utils::to_absolute_path;
+    use super::*;
+
+    #[test]
+    fn generate_random_values_with_and_without_seed() -> Result<()> {
+        // Given
+        let source = r"id: {{uuid}} key: {{random:hex16}} password: {{random:alnum24}} other: {{random:hex16}} \{{uuid}}";
+        let delimiters = PlaceholderDelimiters::default();
+        let uuid_regex = Regex::new("^[0-9a-f]{8}-[0-9a-f]{4}-4[0-9a-f]{3}-[89ab][0-9a-f]{3}-[0-9a-f]{12}$")?;
+        let mut random_values = RandomValues::default();
+        random_values.set_seed([7; 32]);
+        random_values.start_target("workshop/clock.yaml".to_string());
+
+        // When
+        let first = generate_random_values(source, &Table::new(), &delimiters, &mut random_values, true)?.into_owned();
+        random_values.start_target("workshop/clock.yaml".to_string());
+        let second = generate_random_values(source, &Table::new(), &delimiters, &mut random_values, true)?.into_owned();
+        let unstable = generate_random_values(source, &Table::new(), &delimiters, &mut random_values, false)?.into_owned();
+        let with_prop = generate_random_values("{{uuid}}", &toml::from_str(r#"uuid = "fixed""#)?, &delimiters, &mut random_values, false)?;
+
+        // Then
+        assert_eq!(first, second);
+        assert_ne!(first, unstable);
+        assert_eq!(with_prop, "{{uuid}}");
+        let words: Vec<&str> = first.split(' ').collect();
+        assert!(uuid_regex.is_match(words[1]), "Not a UUID: {}", words[1]);
+        assert!(words[3].len() == 16 && words[3].chars().all(|c| c.is_ascii_hexdigit()), "Not hex16: {}", words[3]);
+        assert!(words[5].len() == 24 && words[5].chars().all(|c| c.is_ascii_alphanumeric()), "Not alnum24: {}", words[5]);
+        assert_ne!(words[3], words[7]);
+        assert_eq!(words[8], r"\{{uuid}}");
+        Ok(())
+    }
+
+    #[test]
+    fn reproducible_values_without_stable_random() -> Result<()> {
+        // Given
+        let source = "id: {{uuid}} key: {{random:hex16}}";
+        let delimiters = PlaceholderDelimiters::default();
+        let generate = |seed: Seed| -> Result<String> {
+            let mut random_values = RandomValues::default();
+            random_values.set_reproducible_seed(seed);
+            random_values.start_target("workshop/clock.yaml".to_string());
+            Ok(generate_random_values(source, &Table::new(), &delimiters, &mut random_values, false)?.into_owned())
+        };
+
+        // When
+        let first = generate([3; 32])?;
+        let second = generate([3; 32])?;
+        let other = generate([4; 32])?;
+
+        // Then
+        assert_eq!(first, second);
+        assert_ne!(first, other);
+        Ok(())
+    }
+
+    #[test(tokio::test)]
+    async fn create_and_load_seed() -> Result<()> {
+        // Given
+        let fs = fixture::from_toml("[yeth-marthter.example]")?;
+        let seed_path = to_absolute_path("/yeth-marthter/example/random-seed.toml");
+
+        // When
+        let created = load_or_create_seed(&seed_path, &fs).await?;
+        let loaded = load_or_create_seed(&seed_path, &fs).await?;
+
+        // Then
+        assert_eq!(created, loaded);
+        assert!(fs.get_content(seed_path).await?.starts_with("seed = \""));
+        Ok(())
+    }
+}
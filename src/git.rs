@@ -0,0 +1,250 @@
+use std::process::Stdio;
+use anyhow::{anyhow, bail, Result};
+use log::debug;
+use once_cell::sync::Lazy;
+use regex::Regex;
+use tokio::fs::create_dir_all;
+use tokio::io::AsyncReadExt;
+use tokio::process::Command;
+use crate::hooks::AllowedCommands;
+use crate::path::AbsolutePath;
+
+static PROGRESS_REGEX: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"^(remote: )?(?<phase>[A-Za-z][A-Za-z ]*):\s+(?<percent>[0-9]{1,3})%").unwrap()
+});
+
+/// The progress of a phase of a clone or fetch, like `Receiving objects` at 45%, as git reports it with `--progress`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GitProgress {
+    pub phase: String,
+    pub percent: u8,
+}
+
+/// Parses a progress line of git, like `Receiving objects:  45% (450/1000), 1.2 MiB | 600 KiB/s`.
+pub fn parse_progress(line: &str) -> Option<GitProgress> {
+    let captures = PROGRESS_REGEX.captures(line.trim())?;
+    let percent = captures["percent"].parse::<u8>().ok().filter(|percent| *percent <= 100)?;
+    Some(GitProgress { phase: captures["phase"].to_string(), percent })
+}
+
+pub async fn git(allowed_commands: &AllowedCommands, work_dir: &AbsolutePath, args: &[&str]) -> Result<String> {
+    debug!("Git: {:?}: {:?}", work_dir, args);
+    allowed_commands.check_program("git")?;
+    let output = Command::new("git")
+        .current_dir(work_dir.as_path())
+        .args(args)
+        .output()
+        .await?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        bail!("Git command failed: git {}: {}", args.join(" "), stderr.trim());
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// Runs a git command that reports its progress on standard error (like clone or fetch with `--progress`), and passes
+/// every change in progress to `on_progress`. The rest of standard error ends up in the error if the command fails.
+async fn git_with_progress(allowed_commands: &AllowedCommands, work_dir: &AbsolutePath, args: &[&str], on_progress: &(dyn Fn(GitProgress) + Sync)) -> Result<()> {
+    debug!("Git: {:?}: {:?}", work_dir, args);
+    allowed_commands.check_program("git")?;
+    let mut child = Command::new("git")
+        .current_dir(work_dir.as_path())
+        .args(args)
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .spawn()?;
+    let mut stderr = child.stderr.take().ok_or_else(|| anyhow!("No standard error for: git {}", args.join(" ")))?;
+    let mut messages = Vec::new();
+    let mut pending = Vec::new();
+    let mut last: Option<GitProgress> = None;
+    let mut buffer = [0u8; 4096];
+    loop {
+        let count = stderr.read(&mut buffer).await?;
+        pending.extend_from_slice(&buffer[..count]);
+        // Git rewrites a progress line with a carriage return, and ends it with a newline when the phase is done
+        while let Some(end) = pending.iter().position(|byte| *byte == b'\r' || *byte == b'\n') {
+            let line = String::from_utf8_lossy(&pending[..end]).into_owned();
+            pending.drain(..=end);
+            match parse_progress(&line) {
+                Some(progress) if last.as_ref() != Some(&progress) => {
+                    on_progress(progress.clone());
+                    last = Some(progress);
+                },
+                Some(_) => (),
+                None if !line.trim().is_empty() => messages.push(line),
+                None => (),
+            }
+        }
+        if count == 0 {
+            break;
+        }
+    }
+    let status = child.wait().await?;
+    if !status.success() {
+        messages.push(String::from_utf8_lossy(&pending).into_owned());
+        bail!("Git command failed: git {}: {}", args.join(" "), messages.join("\n").trim());
+    }
+    Ok(())
+}
+
+pub async fn is_clean(allowed_commands: &AllowedCommands, work_dir: &AbsolutePath) -> Result<bool> {
+    let status = git(allowed_commands, work_dir, &["status", "--porcelain"]).await?;
+    Ok(status.trim().is_empty())
+}
+
+pub async fn create_branch(allowed_commands: &AllowedCommands, work_dir: &AbsolutePath, branch: &str) -> Result<()> {
+    git(allowed_commands, work_dir, &["checkout", "-b", branch]).await?;
+    Ok(())
+}
+
+/// Returns the name of the branch that is checked out, or `None` if the head is detached.
+pub async fn current_branch(allowed_commands: &AllowedCommands, work_dir: &AbsolutePath) -> Result<Option<String>> {
+    let output = git(allowed_commands, work_dir, &["branch", "--show-current"]).await?;
+    let branch = output.trim();
+    Ok((!branch.is_empty()).then(|| branch.to_string()))
+}
+
+/// Throws away the changes in the working tree (including untracked files, but not ignored files), checks out the
+/// original branch (or commit) again and deletes the branch.
+pub async fn abandon_branch(allowed_commands: &AllowedCommands, work_dir: &AbsolutePath, branch: &str, original: &str) -> Result<()> {
+    git(allowed_commands, work_dir, &["reset", "--quiet", "--hard"]).await?;
+    git(allowed_commands, work_dir, &["clean", "--quiet", "-d", "--force"]).await?;
+    git(allowed_commands, work_dir, &["checkout", "--quiet", original]).await?;
+    git(allowed_commands, work_dir, &["branch", "--quiet", "-D", branch]).await?;
+    Ok(())
+}
+
+pub async fn commit_all(allowed_commands: &AllowedCommands, work_dir: &AbsolutePath, message: &str) -> Result<bool> {
+    git(allowed_commands, work_dir, &["add", "--all"]).await?;
+    let staged = git(allowed_commands, work_dir, &["diff", "--cached", "--name-only"]).await?;
+    if staged.trim().is_empty() {
+        return Ok(false);
+    }
+    git(allowed_commands, work_dir, &["commit", "--quiet", "--message", message]).await?;
+    Ok(true)
+}
+
+pub async fn changed_files(allowed_commands: &AllowedCommands, work_dir: &AbsolutePath, base: &str, head: &str) -> Result<Vec<String>> {
+    let range = format!("{base}..{head}");
+    let output = git(allowed_commands, work_dir, &["diff", "--name-status", &range]).await?;
+    Ok(output.lines().map(ToOwned::to_owned).collect())
+}
+
+pub async fn head_revision(allowed_commands: &AllowedCommands, work_dir: &AbsolutePath) -> Result<String> {
+    let output = git(allowed_commands, work_dir, &["rev-parse", "HEAD"]).await?;
+    Ok(output.trim().to_string())
+}
+
+/// Clones the remote into the directory, or fetches from the remote if the directory is already a clone, and checks out
+/// the revision (a branch, tag or commit) as a detached head. Passes the progress of the clone or fetch to
+/// `on_progress`. Returns the commit that was checked out.
+pub async fn fetch_revision(allowed_commands: &AllowedCommands, directory: &AbsolutePath, fetch_url: &str, revision: &str, on_progress: &(dyn Fn(GitProgress) + Sync)) -> Result<String> {
+    if directory.join(".git").exists() {
+        git_with_progress(allowed_commands, directory, &["fetch", "--progress", "--tags", fetch_url, "+refs/heads/*:refs/remotes/origin/*"], on_progress).await?;
+    } else {
+        let parent = directory.parent().ok_or_else(|| anyhow!("Cannot clone into: {:?}", directory))?;
+        create_dir_all(parent).await?;
+        let parent = AbsolutePath::try_new(parent.to_path_buf())?;
+        git_with_progress(allowed_commands, &parent, &["clone", "--progress", "--no-checkout", fetch_url, &directory.to_string_lossy()], on_progress).await?;
+    }
+    let remote_branch = format!("origin/{revision}^{{commit}}");
+    let commit = match git(allowed_commands, directory, &["rev-parse", "--verify", "--quiet", &remote_branch]).await {
+        Ok(commit) => commit,
+        Err(_) => git(allowed_commands, directory, &["rev-parse", "--verify", "--quiet", &format!("{revision}^{{commit}}")]).await?,
+    };
+    let commit = commit.trim();
+    git(allowed_commands, directory, &["checkout", "--quiet", "--detach", commit]).await?;
+    Ok(commit.to_string())
+}
+
+#[cfg(test)]
+pub mod test_utils {
+    use anyhow::Result;
+    use assert_fs::TempDir;
+    use crate::hooks::AllowedCommands;
+    use crate::path::AbsolutePath;
+    use super::git;
+
+    pub async fn init_repository() -> Result<(TempDir, AbsolutePath)> {
+        let tmp_dir = TempDir::new()?;
+        let work_dir = AbsolutePath::try_new(tmp_dir.to_path_buf())?;
+        let allowed_commands = AllowedCommands::default();
+        git(&allowed_commands, &work_dir, &["init", "--quiet", "--initial-branch", "main"]).await?;
+        git(&allowed_commands, &work_dir, &["config", "user.name", "Igor"]).await?;
+        git(&allowed_commands, &work_dir, &["config", "user.email", "igor@example.com"]).await?;
+        git(&allowed_commands, &work_dir, &["config", "commit.gpgsign", "false"]).await?;
+        Ok((tmp_dir, work_dir))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Mutex;
+    use tokio::fs::write;
+    use super::*;
+    use super::test_utils::init_repository;
+
+    #[tokio::test]
+    async fn commit_and_list_changes() -> Result<()> {
+        // Given
+        let allowed_commands = AllowedCommands::default();
+        let (_tmp_dir, work_dir) = init_repository().await?;
+        write(work_dir.join("README.md"), "Hello\n").await?;
+        assert!(commit_all(&allowed_commands, &work_dir, "Initial").await?);
+        let base = head_revision(&allowed_commands, &work_dir).await?;
+        assert!(is_clean(&allowed_commands, &work_dir).await?);
+
+        // When
+        create_branch(&allowed_commands, &work_dir, "feature").await?;
+        write(work_dir.join("README.md"), "Hello, world\n").await?;
+        assert!(!is_clean(&allowed_commands, &work_dir).await?);
+        assert!(commit_all(&allowed_commands, &work_dir, "Change").await?);
+
+        // Then
+        assert!(!commit_all(&allowed_commands, &work_dir, "Nothing").await?);
+        let changes = changed_files(&allowed_commands, &work_dir, &base, "HEAD").await?;
+        assert_eq!(changes, vec!["M\tREADME.md".to_string()]);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn clone_and_fetch_revision() -> Result<()> {
+        // Given
+        let allowed_commands = AllowedCommands::default();
+        let (_remote_dir, remote) = init_repository().await?;
+        write(remote.join("thundercloud.toml"), "[niche]\nname = \"example\"\n").await?;
+        commit_all(&allowed_commands, &remote, "Initial").await?;
+        let first = head_revision(&allowed_commands, &remote).await?;
+        let (_tmp_dir, work_area) = init_repository().await?;
+        let directory = AbsolutePath::new("clouds/example", &work_area);
+        let fetch_url = format!("file://{}", remote.to_string_lossy());
+        let progress = Mutex::new(Vec::new());
+        let on_progress = |git_progress: GitProgress| progress.lock().unwrap().push(git_progress);
+
+        // When
+        let cloned = fetch_revision(&allowed_commands, &directory, &fetch_url, "main", &on_progress).await?;
+        write(remote.join("README.md"), "Thundercloud\n").await?;
+        commit_all(&allowed_commands, &remote, "Add README").await?;
+        let second = head_revision(&allowed_commands, &remote).await?;
+        let fetched = fetch_revision(&allowed_commands, &directory, &fetch_url, "main", &on_progress).await?;
+        let pinned = fetch_revision(&allowed_commands, &directory, &fetch_url, &first[..7], &on_progress).await?;
+
+        // Then
+        assert_eq!(cloned, first);
+        assert_eq!(fetched, second);
+        assert_eq!(pinned, first);
+        assert!(directory.join("thundercloud.toml").exists());
+        assert!(!directory.join("README.md").exists());
+        let progress = progress.into_inner()?;
+        assert!(progress.iter().any(|git_progress| git_progress.percent == 100), "Progress: {progress:?}");
+        Ok(())
+    }
+
+    #[test]
+    fn parse_progress_lines() {
+        assert_eq!(parse_progress("Receiving objects:  45% (450/1000), 1.20 MiB | 600.00 KiB/s"), Some(GitProgress { phase: "Receiving objects".to_string(), percent: 45 }));
+        assert_eq!(parse_progress("remote: Counting objects: 100% (3/3), done."), Some(GitProgress { phase: "Counting objects".to_string(), percent: 100 }));
+        assert_eq!(parse_progress("Cloning into 'example'..."), None);
+        assert_eq!(parse_progress("Resolving deltas: 999% (1/1)"), None);
+    }
+}
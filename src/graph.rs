@@ -0,0 +1,146 @@
+use std::path::PathBuf;
+use anyhow::Result;
+use clap::ValueEnum;
+use crate::config_model::{NicheTriggers, ProjectConfig, PsychotropicConfig};
+use crate::file_system::FileSystem;
+use crate::{get_project_config, get_project_root};
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GraphFormat {
+    Dot,
+    Mermaid,
+}
+
+/// A niche in the psychotropic dependency graph. Niches without a thundercloud only appear as the target of a
+/// `wait-for`.
+#[derive(Debug, Clone, PartialEq)]
+struct GraphNode {
+    name: String,
+    has_thundercloud: bool,
+    wait_for: Vec<String>,
+}
+
+pub async fn graph<FS: FileSystem>(project_root_option: Option<PathBuf>, format: GraphFormat, fs: &FS) -> Result<()> {
+    let project_root = get_project_root(project_root_option)?;
+    let project_config = get_project_config(&project_root, fs).await?;
+    let nodes = get_nodes(&project_config)?;
+    let output = match format {
+        GraphFormat::Dot => to_dot(&nodes),
+        GraphFormat::Mermaid => to_mermaid(&nodes),
+    };
+    println!("{output}");
+    Ok(())
+}
+
+fn get_nodes<PC: ProjectConfig>(project_config: &PC) -> Result<Vec<GraphNode>> {
+    let psychotropic = project_config.psychotropic()?;
+    let mut nodes: Vec<GraphNode> = psychotropic.values().iter()
+        .map(|triggers| {
+            let mut wait_for = triggers.wait_for().to_vec();
+            wait_for.sort();
+            GraphNode {
+                name: triggers.name(),
+                has_thundercloud: triggers.use_thundercloud().is_some() || triggers.use_thundercloud_path().is_some(),
+                wait_for,
+            }
+        })
+        .collect();
+    nodes.sort_by(|left, right| left.name.cmp(&right.name));
+    Ok(nodes)
+}
+
+fn to_dot(nodes: &[GraphNode]) -> String {
+    let mut lines = Vec::new();
+    lines.push("digraph psychotropic {".to_string());
+    lines.push("    rankdir=LR;".to_string());
+    for node in nodes {
+        let style = if node.has_thundercloud { "" } else { " [style=dashed]" };
+        lines.push(format!("    {:?}{style};", node.name));
+    }
+    for node in nodes {
+        for precursor in &node.wait_for {
+            lines.push(format!("    {:?} -> {:?};", precursor, node.name));
+        }
+    }
+    lines.push("}".to_string());
+    lines.join("\n")
+}
+
+fn to_mermaid(nodes: &[GraphNode]) -> String {
+    let id = |name: &str| nodes.iter().position(|node| node.name == name).map(|index| format!("n{index}")).unwrap_or_default();
+    let mut lines = Vec::new();
+    lines.push("flowchart LR".to_string());
+    for (index, node) in nodes.iter().enumerate() {
+        let (open, close) = if node.has_thundercloud { ("[", "]") } else { ("([", "])") };
+        lines.push(format!("    n{index}{open}\"{}\"{close}", node.name));
+    }
+    for node in nodes {
+        for precursor in &node.wait_for {
+            lines.push(format!("    {} --> {}", id(precursor), id(&node.name)));
+        }
+    }
+    lines.join("\n")
+}
+
+#[cfg(test)]
+mod test {
+    use indoc::indoc;
+    use crate::config_model::project_config;
+    use crate::file_system::ConfigFormat::TOML;
+    use super::*;
+
+    const CARGO_CULT_TOML: &str = indoc! {r#"
+        [[psychotropic.cues]]
+        name = "default-settings"
+        use-thundercloud = { directory = "{{PROJECT}}/default-thundercloud" }
+
+        [[psychotropic.cues]]
+        name = "mongo-db"
+        wait-for = ["default-settings", "network"]
+        use-thundercloud = { directory = "{{PROJECT}}/mongo-thundercloud" }
+    "#};
+
+    #[test]
+    fn graph_to_dot() -> Result<()> {
+        // Given
+        let project_config = project_config::from_str(CARGO_CULT_TOML, TOML)?;
+        let nodes = get_nodes(&project_config)?;
+
+        // When
+        let dot = to_dot(&nodes);
+
+        // Then
+        let expected = indoc! {r#"
+            digraph psychotropic {
+                rankdir=LR;
+                "default-settings";
+                "mongo-db";
+                "network" [style=dashed];
+                "default-settings" -> "mongo-db";
+                "network" -> "mongo-db";
+            }"#};
+        assert_eq!(dot, expected);
+        Ok(())
+    }
+
+    #[test]
+    fn graph_to_mermaid() -> Result<()> {
+        // Given
+        let project_config = project_config::from_str(CARGO_CULT_TOML, TOML)?;
+        let nodes = get_nodes(&project_config)?;
+
+        // When
+        let mermaid = to_mermaid(&nodes);
+
+        // Then
+        let expected = indoc! {r#"
+            flowchart LR
+                n0["default-settings"]
+                n1["mongo-db"]
+                n2(["network"])
+                n0 --> n1
+                n2 --> n1"#};
+        assert_eq!(mermaid, expected);
+        Ok(())
+    }
+}
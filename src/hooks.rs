@@ -0,0 +1,107 @@
+use anyhow::{bail, Result};
+use crate::config_model::ProjectConfig;
+
+/// Characters that let a shell command run other programs than the first word of the command.
+const SHELL_SYNTAX: &[char] = &[';', '&', '|', '`', '$', '(', ')', '<', '>', '\n'];
+
+/// The programs that Igor may start for a project (like `git`, `curl` and the shell commands of `props-exec` sections):
+/// the `allowed-hook-commands` of the project, or any program if the project has no allowlist, or none at all with
+/// `--no-hooks`.
+#[derive(Debug, Clone, Default)]
+pub struct AllowedCommands {
+    allowlist: Option<Vec<String>>,
+    no_hooks: bool,
+}
+
+impl AllowedCommands {
+    pub fn new<PC: ProjectConfig>(project_config: &PC) -> Self {
+        AllowedCommands { allowlist: project_config.allowed_hook_commands().map(<[String]>::to_vec), no_hooks: false }
+    }
+
+    /// Refuses every program, whatever the allowlist says.
+    pub fn with_no_hooks(mut self, no_hooks: bool) -> Self {
+        self.no_hooks = no_hooks;
+        self
+    }
+
+    pub fn no_hooks(&self) -> bool {
+        self.no_hooks
+    }
+
+    /// Fails with `--no-hooks`, or if the project has an allowlist that does not contain the program.
+    pub fn check_program(&self, program: &str) -> Result<()> {
+        if self.no_hooks {
+            bail!("Commands are disabled (--no-hooks): {program:?}");
+        }
+        check_program_against(program, self.allowlist.as_deref())
+    }
+
+    /// Fails with `--no-hooks`, or if the project has an allowlist that does not contain the program of the shell
+    /// command (its first word). With an allowlist, commands that use shell syntax to run other programs are refused,
+    /// because they cannot be checked.
+    pub fn check_shell_command(&self, command: &str) -> Result<()> {
+        if self.no_hooks {
+            bail!("Commands are disabled (--no-hooks): {command:?}");
+        }
+        check_shell_command_against(command, self.allowlist.as_deref())
+    }
+}
+
+fn check_program_against(program: &str, allowed_commands: Option<&[String]>) -> Result<()> {
+    match allowed_commands {
+        Some(allowed_commands) if !allowed_commands.iter().any(|allowed| allowed == program) =>
+            bail!("Command is not in allowed-hook-commands: {program:?}"),
+        _ => Ok(()),
+    }
+}
+
+fn check_shell_command_against(command: &str, allowed_commands: Option<&[String]>) -> Result<()> {
+    if allowed_commands.is_none() {
+        return Ok(());
+    }
+    if command.contains(SHELL_SYNTAX) {
+        bail!("Command uses shell syntax, so it cannot be checked against allowed-hook-commands: {command:?}");
+    }
+    let program = command.split_whitespace().next().unwrap_or_default();
+    check_program_against(program, allowed_commands)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn check_against_allowlist() {
+        // Given
+        let allowed_commands = ["cargo".to_string(), "git".to_string()];
+        let allowlist = Some(allowed_commands.as_slice());
+
+        // When
+        let allowed = check_shell_command_against("cargo metadata --format-version 1", allowlist);
+        let other_program = check_shell_command_against("curl https://example.com", allowlist);
+        let shell_syntax = check_shell_command_against("cargo metadata; rm -rf /", allowlist);
+        let git = check_program_against("git", allowlist);
+
+        // Then
+        assert!(allowed.is_ok());
+        assert_eq!(other_program.unwrap_err().to_string(), r#"Command is not in allowed-hook-commands: "curl""#);
+        assert!(shell_syntax.unwrap_err().to_string().starts_with("Command uses shell syntax"));
+        assert!(git.is_ok());
+        assert!(check_shell_command_against("echo $(whoami)", None).is_ok());
+    }
+
+    #[test]
+    fn no_hooks_refuses_everything() {
+        // Given
+        let allowed_commands = AllowedCommands::default().with_no_hooks(true);
+
+        // When
+        let git = allowed_commands.check_program("git");
+        let shell_command = allowed_commands.check_shell_command("cargo metadata");
+
+        // Then
+        assert_eq!(git.unwrap_err().to_string(), r#"Commands are disabled (--no-hooks): "git""#);
+        assert!(shell_command.is_err());
+        assert!(AllowedCommands::default().check_program("curl").is_ok());
+    }
+}
@@ -0,0 +1,90 @@
+use std::path::PathBuf;
+use anyhow::{anyhow, Result};
+use toml::Table;
+use crate::config_model::{ProjectConfig, PsychotropicConfig};
+use crate::file_system::FileSystem;
+use crate::path::AbsolutePath;
+use crate::run_options::RunOptions;
+use crate::niche::UseThundercloudDefaults;
+use crate::{get_project_config, get_project_root, get_use_thundercloud, niche, NicheName};
+
+pub async fn infer_props<FS: FileSystem>(project_root_option: Option<PathBuf>, niche_name: String, run_options: &RunOptions, fs: &FS) -> Result<()> {
+    let project_root = get_project_root(project_root_option)?;
+    let props = infer_niche_props(&project_root, NicheName::new(niche_name.clone()), run_options, fs).await?;
+    println!("# Props inferred for niche {niche_name:?}");
+    println!("{}", to_props_toml(props)?);
+    Ok(())
+}
+
+async fn infer_niche_props<FS: FileSystem>(project_root: &AbsolutePath, niche_name: NicheName, run_options: &RunOptions, fs: &FS) -> Result<Table> {
+    let project_config = get_project_config(project_root, fs).await?;
+    let psychotropic = project_config.psychotropic()?;
+    let use_thundercloud_defaults = UseThundercloudDefaults::new(project_root, &project_config);
+    let niche_triggers = psychotropic.get(niche_name.to_str());
+    let use_thundercloud = get_use_thundercloud(niche_triggers, &use_thundercloud_defaults, fs).await?
+        .ok_or_else(|| anyhow!("Niche not found: {:?}", niche_name.to_str()))?;
+    let niches_directory = project_config.niches_directory();
+    niche::infer_props(project_root.clone(), niches_directory, niche_name, use_thundercloud, project_config.invar_defaults().into_owned(), run_options, fs.clone()).await
+}
+
+fn to_props_toml(props: Table) -> Result<String> {
+    let mut document = Table::new();
+    document.insert("props".to_string(), toml::Value::Table(props));
+    Ok(toml::to_string(&document)?)
+}
+
+#[cfg(test)]
+mod test {
+    use indoc::indoc;
+    use test_log::test;
+    use toml::Value;
+    use crate::file_system::fixture;
+    use super::*;
+
+    #[test(tokio::test)]
+    async fn infer_from_existing_files() -> Result<()> {
+        // Given
+        let toml_data = indoc! {r#"
+            "CargoCult.toml" = '''
+            [[psychotropic.cues]]
+            name = "example"
+            use-thundercloud = { directory = "{{PROJECT}}/example-thundercloud", features = ["glass"] }
+            '''
+
+            [yeth-marthter.example.invar]
+
+            [workshop]
+            "clock.yaml" = '''
+            ---
+            sweeper: "Lu Tse"
+            apprentice: Lobsang # since Thief of Time
+            '''
+
+            [example-thundercloud]
+            "thundercloud.toml" = """
+            [niche]
+            name = "example"
+            """
+
+            [example-thundercloud.cumulus.workshop]
+            "clock+option-glass.yaml" = '''
+            ---
+            sweeper: "{{sweeper}}"
+            apprentice: {{apprentice}} # since {{novel}}
+            '''
+        "#};
+        let fs = fixture::from_toml(toml_data)?;
+
+        // When
+        let props = infer_niche_props(&AbsolutePath::root(), NicheName::new("example"), &RunOptions::default(), &fs).await?;
+
+        // Then
+        let mut expected = Table::new();
+        expected.insert("sweeper".to_string(), Value::String("Lu Tse".to_string()));
+        expected.insert("apprentice".to_string(), Value::String("Lobsang".to_string()));
+        expected.insert("novel".to_string(), Value::String("Thief of Time".to_string()));
+        assert_eq!(props, expected);
+        assert_eq!(to_props_toml(props)?.lines().next(), Some("[props]"));
+        Ok(())
+    }
+}
@@ -1,28 +1,727 @@
 use std::borrow::Cow;
-use log::debug;
+use std::sync::Mutex;
+use ahash::AHashMap;
+use anyhow::{bail, Result};
+use chrono::{DateTime, FixedOffset, Local};
+use globset::{Glob, GlobSetBuilder};
+use log::{debug, warn};
 use once_cell::sync::{Lazy};
 use regex::Regex;
 use toml::{Table, Value};
+use crate::config_model::PlaceholderDelimiters;
+use crate::path::AbsolutePath;
 
+/// Matches either an escaped opening delimiter (like `\{{`, without capture groups) or a placeholder (with groups
+/// `name`, `filters` and `fallback`).
 static PLACEHOLDER_REGEX: Lazy<Regex> = Lazy::new(|| {
-    Regex::new("[{][{]([A-Za-z][-A-Za-z0-9_.]*)[}][}]").unwrap()
+    Regex::new(r"\\[{][{]|[{][{](?<name>[A-Za-z][-A-Za-z0-9_.]*)(?<filters>(?:[|][A-Za-z]+)*)(?::-(?<fallback>.*?))?[}][}]").unwrap()
 });
+static CUSTOM_PLACEHOLDER_REGEXES: Lazy<Mutex<AHashMap<PlaceholderDelimiters, Regex>>> = Lazy::new(|| {
+    Mutex::new(AHashMap::new())
+});
+
+fn placeholder_regex(delimiters: &PlaceholderDelimiters) -> Regex {
+    if delimiters == &PlaceholderDelimiters::default() {
+        return PLACEHOLDER_REGEX.clone();
+    }
+    let open = regex::escape(delimiters.open());
+    let pattern = format!(r"\\{open}|{open}(?<name>[A-Za-z][-A-Za-z0-9_.]*)(?<filters>(?:[|][A-Za-z]+)*)(?::-(?<fallback>.*?))?{}", regex::escape(delimiters.close()));
+    let Ok(mut regexes) = CUSTOM_PLACEHOLDER_REGEXES.lock() else {
+        return Regex::new(&pattern).unwrap();
+    };
+    regexes.entry(delimiters.clone())
+        .or_insert_with(|| Regex::new(&pattern).unwrap())
+        .clone()
+}
 
 pub fn interpolate<'a>(source: &'a str, variables: &Table) -> Cow<'a, str> {
-    let mut result: Cow<str> = Cow::from(source);
-    if variables.is_empty() {
-        return result;
-    }
-    if let Some(captures) = PLACEHOLDER_REGEX.captures(result.as_ref()) {
-        debug!("Interpolate: capture: {:?}", captures.get(0));
-        if let (Some(match_placeholder), Some(match_name)) = (captures.get(0), captures.get(1)) {
-            debug!("Interpolate: placeholder name: '{}'", match_name.as_str());
-            if let Some(value) = variables.get(match_name.as_str()).and_then(Value::as_str) {
-                debug!("Interpolate: '{}' to '{}' in: {}", match_placeholder.as_str(), value, result);
-                let range = match_placeholder.range();
-                result.to_mut().replace_range(range, value);
+    interpolate_with_delimiters(source, variables, &PlaceholderDelimiters::default())
+}
+
+/// Replaces the placeholders in the source by the values of the variables (or their fallbacks). Placeholders without
+/// a value are left as is. An opening delimiter that is escaped with a backslash (like `\{{PROJECT}}`) becomes a plain
+/// opening delimiter, without substitution.
+pub fn interpolate_with_delimiters<'a>(source: &'a str, variables: &Table, delimiters: &PlaceholderDelimiters) -> Cow<'a, str> {
+    interpolate_escaped(source, variables, delimiters, |value| value)
+}
+
+/// Like [`interpolate_with_delimiters`], but every value is quoted as a single shell word, so that values with shell
+/// syntax (like `x; rm -rf ~`) cannot change the meaning of the shell command that the source is.
+pub fn interpolate_shell_command<'a>(source: &'a str, variables: &Table, delimiters: &PlaceholderDelimiters) -> Cow<'a, str> {
+    interpolate_escaped(source, variables, delimiters, |value| format!("'{}'", value.replace('\'', r"'\''")))
+}
+
+fn interpolate_escaped<'a>(source: &'a str, variables: &Table, delimiters: &PlaceholderDelimiters, escape: impl Fn(String) -> String) -> Cow<'a, str> {
+    let regex = placeholder_regex(delimiters);
+    if !regex.is_match(source) {
+        return Cow::from(source);
+    }
+    let mut result = String::new();
+    let mut last_end = 0;
+    for captures in regex.captures_iter(source) {
+        let Some(placeholder) = captures.get(0) else { continue };
+        result.push_str(&source[last_end..placeholder.start()]);
+        last_end = placeholder.end();
+        let Some(name) = captures.name("name") else {
+            result.push_str(delimiters.open());
+            continue;
+        };
+        debug!("Interpolate: placeholder name: '{}'", name.as_str());
+        let fallback = captures.name("fallback").map(|m| m.as_str());
+        let filters = captures.name("filters").map(|m| m.as_str()).unwrap_or_default();
+        let value = lookup(variables, name.as_str()).and_then(scalar_to_string).or(fallback.map(Cow::Borrowed))
+            .and_then(|value| apply_filters(&value, filters));
+        if let Some(value) = value {
+            let value = escape(value);
+            debug!("Interpolate: '{}' to '{}' in: {}", placeholder.as_str(), value, source);
+            result.push_str(&value);
+        } else {
+            result.push_str(placeholder.as_str());
+        }
+    }
+    result.push_str(&source[last_end..]);
+    Cow::Owned(result)
+}
+
+type Filter = fn(&str) -> String;
+
+/// Filters that convert the value of a placeholder, like `{{project-name|snake}}`. Filters can be chained (like
+/// `{{project-name|snake|upper}}`) and apply to fallbacks too.
+static FILTERS: Lazy<AHashMap<&'static str, Filter>> = Lazy::new(|| {
+    let mut filters: AHashMap<&'static str, Filter> = AHashMap::new();
+    filters.insert("lower", |value| value.to_lowercase());
+    filters.insert("upper", |value| value.to_uppercase());
+    filters.insert("snake", |value| words(value).join("_").to_lowercase());
+    filters.insert("kebab", |value| words(value).join("-").to_lowercase());
+    filters.insert("camel", |value| {
+        let words = words(value);
+        let mut result = words.first().map(|word| word.to_lowercase()).unwrap_or_default();
+        words.iter().skip(1).for_each(|word| result.push_str(&capitalize(word)));
+        result
+    });
+    filters.insert("Pascal", |value| words(value).iter().map(|word| capitalize(word)).collect());
+    filters
+});
+
+/// Applies a chain of filters (like `|snake|upper`) to a value. Returns `None` if a filter is unknown.
+fn apply_filters(value: &str, filters: &str) -> Option<String> {
+    let mut result = value.to_string();
+    for filter_name in filters.split('|').filter(|filter_name| !filter_name.is_empty()) {
+        let Some(filter) = FILTERS.get(filter_name) else {
+            warn!("Unknown filter: {filter_name:?}");
+            return None;
+        };
+        result = filter(&result);
+    }
+    Some(result)
+}
+
+/// Splits a value into words at separators (anything that is not a letter or a digit) and at changes of case, so that
+/// `HTTPServer-config_file` consists of `HTTP`, `Server`, `config` and `file`.
+fn words(value: &str) -> Vec<String> {
+    let chars: Vec<char> = value.chars().collect();
+    let mut words = Vec::new();
+    let mut word = String::new();
+    for (index, &c) in chars.iter().enumerate() {
+        if !c.is_alphanumeric() {
+            if !word.is_empty() {
+                words.push(std::mem::take(&mut word));
+            }
+            continue;
+        }
+        if c.is_uppercase() && !word.is_empty() {
+            let previous = chars[index - 1];
+            let next_is_lowercase = chars.get(index + 1).is_some_and(|next| next.is_lowercase());
+            if previous.is_lowercase() || previous.is_numeric() || (previous.is_uppercase() && next_is_lowercase) {
+                words.push(std::mem::take(&mut word));
             }
         }
+        word.push(c);
+    }
+    if !word.is_empty() {
+        words.push(word);
+    }
+    words
+}
+
+fn capitalize(word: &str) -> String {
+    let mut chars = word.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars.flat_map(char::to_lowercase)).collect(),
+        None => String::new(),
+    }
+}
+
+/// Returns the names of the placeholders in the source that have a value in the variables (in order of appearance,
+/// without duplicates).
+pub fn used_placeholders(source: &str, variables: &Table, delimiters: &PlaceholderDelimiters) -> Vec<String> {
+    let mut used = Vec::new();
+    for captures in placeholder_regex(delimiters).captures_iter(source) {
+        let Some(name) = captures.name("name").map(|m| m.as_str().to_string()) else { continue };
+        if lookup(variables, &name).and_then(scalar_to_string).is_some() && !used.contains(&name) {
+            used.push(name);
+        }
+    }
+    used
+}
+
+/// Returns the names of the placeholders in the source that have no value in the variables and no fallback (in order of
+/// appearance, without duplicates).
+pub fn missing_placeholders(source: &str, variables: &Table, delimiters: &PlaceholderDelimiters) -> Vec<String> {
+    let mut missing = Vec::new();
+    for captures in placeholder_regex(delimiters).captures_iter(source) {
+        let Some(name) = captures.name("name").map(|m| m.as_str().to_string()) else { continue };
+        if captures.name("fallback").is_some() {
+            continue;
+        }
+        if lookup(variables, &name).and_then(scalar_to_string).is_none() && !missing.contains(&name) {
+            missing.push(name);
+        }
+    }
+    missing
+}
+
+/// Renders a scalar property value as text: strings as they are, and numbers, booleans and dates in their TOML
+/// representation (like `8080`, `true` or `1.5`). Tables and arrays have no text representation.
+pub fn scalar_to_string(value: &Value) -> Option<Cow<'_, str>> {
+    match value {
+        Value::String(value) => Some(Cow::Borrowed(value)),
+        Value::Datetime(datetime) => Some(Cow::Owned(datetime.to_string())),
+        Value::Integer(_) | Value::Float(_) | Value::Boolean(_) => Some(Cow::Owned(value.to_string())),
+        Value::Array(_) | Value::Table(_) => None,
+    }
+}
+
+/// Looks up a property by name. If there is no property with that exact name, then a dotted name like
+/// `database.hosts.0` selects a value from nested tables and arrays.
+pub fn lookup<'a>(variables: &'a Table, name: &str) -> Option<&'a Value> {
+    if let Some(value) = variables.get(name) {
+        return Some(value);
+    }
+    let mut components = name.split('.');
+    let mut value = variables.get(components.next()?)?;
+    for component in components {
+        value = match value {
+            Value::Table(table) => table.get(component)?,
+            Value::Array(array) => array.get(component.parse::<usize>().ok()?)?,
+            _ => return None,
+        };
+    }
+    Some(value)
+}
+
+/// Inserts a property by name. A dotted name like `server.host` inserts the value in nested tables, which are created
+/// as needed, so that `{{server.host}}` finds it. If a component of the name already holds a value other than a table,
+/// then the name is used as is.
+pub fn insert_dotted(props: &mut Table, name: &str, value: Value) {
+    let mut table = &mut *props;
+    let mut components: Vec<&str> = name.split('.').collect();
+    let last = components.pop().unwrap_or(name);
+    for component in components {
+        let nested = table.entry(component).or_insert_with(|| Value::Table(Table::new()));
+        let Value::Table(nested) = nested else {
+            props.insert(name.to_string(), value);
+            return;
+        };
+        table = nested;
+    }
+    table.insert(last.to_string(), value);
+}
+
+/// The name of the table of props that holds the allowed environment variables.
+pub const ENVIRONMENT_PROPS: &str = "env";
+
+/// Returns the environment variables whose names match one of the patterns of the allowlist, as a table of props named
+/// `env`, so that `{{env.HOME}}` refers to variable `HOME`. Returns an empty table if the allowlist is empty.
+pub fn environment_props(allowlist: &[String]) -> Table {
+    let mut props = Table::new();
+    if allowlist.is_empty() {
+        return props;
+    }
+    let mut builder = GlobSetBuilder::new();
+    for pattern in allowlist {
+        match Glob::new(pattern) {
+            Ok(glob) => { builder.add(glob); },
+            Err(error) => warn!("Ignore invalid pattern in environment allowlist: {pattern:?}: {error}"),
+        }
+    }
+    let allowed = match builder.build() {
+        Ok(allowed) => allowed,
+        Err(error) => {
+            warn!("Ignore environment allowlist: {error}");
+            return props;
+        }
+    };
+    let mut environment = Table::new();
+    for (name, value) in std::env::vars_os() {
+        let (Some(name), Some(value)) = (name.to_str(), value.to_str()) else { continue };
+        if allowed.is_match(name) {
+            environment.insert(name.to_string(), Value::String(value.to_string()));
+        }
+    }
+    props.insert(ENVIRONMENT_PROPS.to_string(), Value::Table(environment));
+    props
+}
+
+/// The names of the props that describe the context of a niche: the name of the niche, the project root, the directory
+/// that contains the project root, the selected features (separated by commas) and the name of the thundercloud.
+pub const CONTEXT_PROPS: [&str; 5] = ["NICHE", "PROJECT", "WORKSPACE", "FEATURES", "THUNDERCLOUD"];
+
+/// Returns the props that describe the context of a niche, like `{{NICHE}}` and `{{PROJECT}}`.
+pub fn context_props(niche: &str, project_root: &AbsolutePath, features: &[String], thundercloud: &str) -> Table {
+    let workspace = AbsolutePath::new("..", project_root);
+    let values = [
+        niche.to_string(),
+        project_root.to_string_lossy().to_string(),
+        workspace.to_string_lossy().to_string(),
+        features.join(","),
+        thundercloud.to_string(),
+    ];
+    CONTEXT_PROPS.iter().zip(values).map(|(name, value)| (name.to_string(), Value::String(value))).collect()
+}
+
+/// The name of the table of props that holds the date and time of the run.
+pub const NOW_PROPS: &str = "now";
+
+/// Returns the date and time of a run: the pinned time, the time in `SOURCE_DATE_EPOCH` (seconds since the Unix epoch)
+/// or else the current time, in the local time zone.
+pub fn run_time(pinned: Option<&DateTime<FixedOffset>>) -> DateTime<FixedOffset> {
+    if let Some(now) = pinned {
+        return *now;
+    }
+    source_date_epoch().unwrap_or_else(|| Local::now().fixed_offset())
+}
+
+/// Returns the time in `SOURCE_DATE_EPOCH` (seconds since the Unix epoch), if it is set.
+pub fn source_date_epoch() -> Option<DateTime<FixedOffset>> {
+    std::env::var("SOURCE_DATE_EPOCH").ok()
+        .and_then(|seconds| seconds.parse::<i64>().ok())
+        .and_then(|seconds| DateTime::from_timestamp(seconds, 0))
+        .map(|now| now.fixed_offset())
+}
+
+/// The name of the prop that holds the date and time of the run as a single timestamp in UTC, like
+/// `1970-01-01T00:00:00Z` in a run with `--reproducible`.
+pub const TIMESTAMP_PROP: &str = "IGOR_TIMESTAMP";
+
+/// Returns the date and time of the run as a table of props named `now`, like `{{now.iso8601}}` and `{{now.year}}`, and
+/// as `{{IGOR_TIMESTAMP}}`.
+pub fn now_props(now: &DateTime<FixedOffset>) -> Table {
+    let mut time = Table::new();
+    for (name, format) in [("iso8601", "%Y-%m-%dT%H:%M:%S%:z"), ("date", "%Y-%m-%d"), ("time", "%H:%M:%S"), ("year", "%Y"), ("month", "%m"), ("day", "%d"), ("timestamp", "%s")] {
+        time.insert(name.to_string(), Value::String(now.format(format).to_string()));
+    }
+    let mut props = Table::new();
+    props.insert(NOW_PROPS.to_string(), Value::Table(time));
+    props.insert(TIMESTAMP_PROP.to_string(), Value::String(now.to_utc().format("%Y-%m-%dT%H:%M:%SZ").to_string()));
+    props
+}
+
+/// Resolves the placeholders in the string values of the props (also in nested tables and arrays), so that props can
+/// be derived from other props, like `full-name = "{{first}} {{last}}"`. Placeholders without a matching prop are left
+/// as is. Fails if props refer to each other in a cycle.
+pub fn resolve_props(props: &Table, delimiters: &PlaceholderDelimiters) -> Result<Table> {
+    let mut resolver = PropsResolver { props, regex: placeholder_regex(delimiters), resolved: AHashMap::new(), stack: Vec::new() };
+    let mut result = Table::new();
+    for (key, value) in props {
+        result.insert(key.clone(), resolver.resolve_value(key, value)?);
+    }
+    Ok(result)
+}
+
+struct PropsResolver<'a> {
+    props: &'a Table,
+    regex: Regex,
+    resolved: AHashMap<String, String>,
+    stack: Vec<String>,
+}
+
+impl PropsResolver<'_> {
+    fn resolve_value(&mut self, name: &str, value: &Value) -> Result<Value> {
+        let resolved_value = match value {
+            Value::String(body) => Value::String(self.resolve_string(name, body)?),
+            Value::Table(table) => {
+                let mut resolved_table = Table::new();
+                for (key, nested_value) in table {
+                    resolved_table.insert(key.clone(), self.resolve_value(&format!("{name}.{key}"), nested_value)?);
+                }
+                Value::Table(resolved_table)
+            },
+            Value::Array(array) => {
+                let mut resolved_array = Vec::new();
+                for (index, nested_value) in array.iter().enumerate() {
+                    resolved_array.push(self.resolve_value(&format!("{name}.{index}"), nested_value)?);
+                }
+                Value::Array(resolved_array)
+            },
+            _ => value.clone(),
+        };
+        Ok(resolved_value)
+    }
+
+    fn resolve_string(&mut self, name: &str, body: &str) -> Result<String> {
+        if let Some(resolved) = self.resolved.get(name) {
+            return Ok(resolved.clone());
+        }
+        if self.stack.iter().any(|item| item == name) {
+            bail!("Props refer to each other in a cycle: {} -> {name}", self.stack.join(" -> "));
+        }
+        self.stack.push(name.to_string());
+        let mut resolved = String::new();
+        let mut last_end = 0;
+        let regex = self.regex.clone();
+        for captures in regex.captures_iter(body) {
+            let Some(placeholder) = captures.get(0) else { continue };
+            resolved.push_str(&body[last_end..placeholder.start()]);
+            last_end = placeholder.end();
+            let Some(placeholder_name) = captures.name("name") else {
+                resolved.push_str(&placeholder.as_str()[1..]);
+                continue;
+            };
+            let value = match (lookup(self.props, placeholder_name.as_str()), captures.name("fallback")) {
+                (Some(Value::String(raw)), _) => Some(self.resolve_string(placeholder_name.as_str(), raw)?),
+                (Some(other), fallback) => scalar_to_string(other).map(Cow::into_owned).or(fallback.map(|fallback| fallback.as_str().to_string())),
+                (None, Some(fallback)) => Some(fallback.as_str().to_string()),
+                (None, None) => None,
+            };
+            let filters = captures.name("filters").map(|m| m.as_str()).unwrap_or_default();
+            match value.and_then(|value| apply_filters(&value, filters)) {
+                Some(value) => resolved.push_str(&value),
+                None => resolved.push_str(placeholder.as_str()),
+            }
+        }
+        resolved.push_str(&body[last_end..]);
+        self.stack.pop();
+        self.resolved.insert(name.to_string(), resolved.clone());
+        Ok(resolved)
+    }
+}
+
+/// Turns a template line into a regular expression that captures the value at every placeholder
+/// position, together with the names of those placeholders (in order of appearance).
+pub fn placeholder_pattern(template: &str, delimiters: &PlaceholderDelimiters) -> Option<(Regex, Vec<String>)> {
+    let mut names = Vec::new();
+    let mut pattern = "^".to_string();
+    let mut last_end = 0;
+    for captures in placeholder_regex(delimiters).captures_iter(template) {
+        let Some(placeholder) = captures.get(0) else { continue };
+        pattern.push_str(&regex::escape(&template[last_end..placeholder.start()]));
+        last_end = placeholder.end();
+        let Some(name) = captures.name("name") else {
+            pattern.push_str(&regex::escape(delimiters.open()));
+            continue;
+        };
+        if captures.name("filters").is_some_and(|filters| !filters.is_empty()) {
+            // The value of the prop cannot be derived from a filtered value
+            pattern.push_str("(?:.*?)");
+            continue;
+        }
+        pattern.push_str("(.*?)");
+        names.push(name.as_str().to_string());
+    }
+    if names.is_empty() {
+        return None;
+    }
+    pattern.push_str(&regex::escape(&template[last_end..]));
+    pattern.push('$');
+    let regex = Regex::new(&pattern).ok()?;
+    Some((regex, names))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn interpolate_nested_property() {
+        // Given
+        let variables: Table = toml::from_str(indoc::indoc! {r#"
+            "clock.sweeper" = "Lu Tse"
+            [clock]
+            apprentices = ["Lobsang", "Jeremy"]
+            [clock.location]
+            valley = "Oi Dong"
+        "#}).unwrap();
+
+        // When
+        let location = interpolate("valley: {{clock.location.valley}}", &variables);
+        let apprentice = interpolate("apprentice: {{clock.apprentices.1}}", &variables);
+        let sweeper = interpolate("sweeper: {{clock.sweeper}}", &variables);
+        let missing = interpolate("city: {{clock.location.city}}", &variables);
+
+        // Then
+        assert_eq!(location, "valley: Oi Dong");
+        assert_eq!(apprentice, "apprentice: Jeremy");
+        assert_eq!(sweeper, "sweeper: Lu Tse");
+        assert_eq!(missing, "city: {{clock.location.city}}");
+    }
+
+    #[test]
+    fn interpolate_fallback() {
+        // Given
+        let mut variables = Table::new();
+        variables.insert("sweeper".to_string(), Value::String("Lu Tse".to_string()));
+        let delimiters = PlaceholderDelimiters::new("<%", "%>");
+
+        // When
+        let present = interpolate("sweeper: {{sweeper:-nobody}}", &variables);
+        let fallback = interpolate("valley: {{valley:-Oi Dong}}", &variables);
+        let empty = interpolate("apprentice: '{{apprentice:-}}'", &variables);
+        let custom = interpolate_with_delimiters("city: <%city:-Ankh-Morpork%>", &variables, &delimiters);
+        let missing = missing_placeholders("{{valley:-Oi Dong}} {{city}}", &variables, &PlaceholderDelimiters::default());
+
+        // Then
+        assert_eq!(present, "sweeper: Lu Tse");
+        assert_eq!(fallback, "valley: Oi Dong");
+        assert_eq!(empty, "apprentice: ''");
+        assert_eq!(custom, "city: Ankh-Morpork");
+        assert_eq!(missing, vec!["city".to_string()]);
+    }
+
+    #[test]
+    fn interpolate_escaped_placeholders() {
+        // Given
+        let mut variables = Table::new();
+        variables.insert("sweeper".to_string(), Value::String("Lu Tse".to_string()));
+        let delimiters = PlaceholderDelimiters::new("<%", "%>");
+
+        // When
+        let escaped = interpolate(r"root: \{{PROJECT}}, sweeper: {{sweeper}}", &variables);
+        let custom = interpolate_with_delimiters(r"<%sweeper%> \<%sweeper%>", &variables, &delimiters);
+        let without_variables = interpolate(r"\{{sweeper}}", &Table::new());
+        let missing = missing_placeholders(r"\{{PROJECT}} {{valley}}", &variables, &PlaceholderDelimiters::default());
+        let pattern = placeholder_pattern(r"\{{PROJECT}}/{{sweeper}}", &PlaceholderDelimiters::default());
+
+        // Then
+        assert_eq!(escaped, "root: {{PROJECT}}, sweeper: Lu Tse");
+        assert_eq!(custom, "Lu Tse <%sweeper%>");
+        assert_eq!(without_variables, "{{sweeper}}");
+        assert_eq!(missing, vec!["valley".to_string()]);
+        let (regex, names) = pattern.unwrap();
+        assert_eq!(names, vec!["sweeper".to_string()]);
+        assert!(regex.is_match("{{PROJECT}}/Lu Tse"));
+    }
+
+    #[test]
+    fn environment_props_from_allowlist() {
+        // Given
+        let allowlist = vec!["CARGO_MANIFEST_*".to_string(), "[".to_string()];
+        let expected = std::env::var("CARGO_MANIFEST_DIR").unwrap();
+
+        // When
+        let props = environment_props(&allowlist);
+        let manifest_dir = interpolate("{{env.CARGO_MANIFEST_DIR}}", &props);
+        let path = interpolate("{{env.PATH}}", &props);
+
+        // Then
+        assert_eq!(manifest_dir, expected);
+        assert_eq!(path, "{{env.PATH}}");
+        assert!(environment_props(&[]).is_empty());
+    }
+
+    #[test]
+    fn time_props_for_pinned_time() -> Result<()> {
+        // Given
+        let now = DateTime::parse_from_rfc3339("2024-03-07T09:05:02+01:00")?;
+
+        // When
+        let props = now_props(&run_time(Some(&now)));
+        let line = interpolate("Copyright {{now.year}}, generated on {{now.date}} at {{now.time}}", &props);
+
+        // Then
+        assert_eq!(line, "Copyright 2024, generated on 2024-03-07 at 09:05:02");
+        assert_eq!(interpolate("{{now.iso8601}} {{now.month}} {{now.day}}", &props), "2024-03-07T09:05:02+01:00 03 07");
+        assert_eq!(interpolate("{{now.timestamp}}", &props), "1709798702");
+        assert_eq!(interpolate("{{IGOR_TIMESTAMP}}", &props), "2024-03-07T08:05:02Z");
+        Ok(())
+    }
+
+    #[test]
+    fn interpolate_non_string_props() -> Result<()> {
+        // Given
+        let variables: Table = toml::from_str(indoc::indoc! {r#"
+            port = 8080
+            debug = false
+            ratio = 0.75
+            released = 1979-05-27
+            url = "http://{{host:-localhost}}:{{port}}/"
+            [server]
+            workers = 4
+        "#})?;
+
+        // When
+        let line = interpolate("listen {{port}} debug={{debug}} ratio={{ratio}} released={{released}} workers={{server.workers}}", &variables);
+        let resolved = resolve_props(&variables, &PlaceholderDelimiters::default())?;
+
+        // Then
+        assert_eq!(line, "listen 8080 debug=false ratio=0.75 released=1979-05-27 workers=4");
+        assert_eq!(resolved.get("url").and_then(Value::as_str), Some("http://localhost:8080/"));
+        assert_eq!(resolved.get("port"), Some(&Value::Integer(8080)));
+        Ok(())
+    }
+
+    #[test]
+    fn insert_dotted_names() -> Result<()> {
+        // Given
+        let mut props: Table = toml::from_str(indoc::indoc! {r#"
+            port = 8080
+            [server]
+            host = "localhost"
+        "#})?;
+
+        // When
+        insert_dotted(&mut props, "server.tls.enabled", Value::Boolean(true));
+        insert_dotted(&mut props, "database.name", Value::String("clocks".to_string()));
+        insert_dotted(&mut props, "port.internal", Value::Integer(8081));
+
+        // Then
+        let expected: Table = toml::from_str(indoc::indoc! {r#"
+            port = 8080
+            "port.internal" = 8081
+            [server]
+            host = "localhost"
+            tls = { enabled = true }
+            [database]
+            name = "clocks"
+        "#})?;
+        assert_eq!(props, expected);
+        assert_eq!(interpolate("{{server.host}}:{{port}} tls={{server.tls.enabled}} {{database.name}}", &props), "localhost:8080 tls=true clocks");
+        assert_eq!(missing_placeholders("{{port}} {{server.host}} {{server.user}}", &props, &PlaceholderDelimiters::default()), vec!["server.user".to_string()]);
+        Ok(())
+    }
+
+    #[test]
+    fn interpolate_filters() {
+        // Given
+        let mut variables = Table::new();
+        variables.insert("project-name".to_string(), Value::String("HTTPServer-config_file".to_string()));
+
+        // When
+        let snake = interpolate("mod {{project-name|snake}};", &variables);
+        let pascal = interpolate("struct {{project-name|Pascal}};", &variables);
+        let screaming = interpolate("{{project-name|snake|upper}}=1", &variables);
+        let camel_and_kebab = interpolate("{{project-name|camel}} {{project-name|kebab}}", &variables);
+        let fallback = interpolate("{{missing|upper:-glass spring}}", &variables);
+        let unknown = interpolate("{{project-name|shout}}", &variables);
+        let pattern = placeholder_pattern("{{project-name|snake}}: {{sweeper}}", &PlaceholderDelimiters::default());
+
+        // Then
+        assert_eq!(snake, "mod http_server_config_file;");
+        assert_eq!(pascal, "struct HttpServerConfigFile;");
+        assert_eq!(screaming, "HTTP_SERVER_CONFIG_FILE=1");
+        assert_eq!(camel_and_kebab, "httpServerConfigFile http-server-config-file");
+        assert_eq!(fallback, "GLASS SPRING");
+        assert_eq!(unknown, "{{project-name|shout}}");
+        let (regex, names) = pattern.unwrap();
+        assert_eq!(names, vec!["sweeper".to_string()]);
+        assert_eq!(regex.captures("glass_spring: Lu Tse").unwrap().get(1).map(|m| m.as_str()), Some("Lu Tse"));
+    }
+
+    #[test]
+    fn missing_placeholders_in_order() {
+        // Given
+        let mut variables = Table::new();
+        variables.insert("sweeper".to_string(), Value::String("Lu Tse".to_string()));
+        let source = "{{apprentice}} and {{sweeper}}\n{{valley}} of {{apprentice}}\n";
+
+        // When
+        let missing = missing_placeholders(source, &variables, &PlaceholderDelimiters::default());
+
+        // Then
+        assert_eq!(missing, vec!["apprentice".to_string(), "valley".to_string()]);
+    }
+
+    #[test]
+    fn resolve_derived_props() -> Result<()> {
+        // Given
+        let props: Table = toml::from_str(indoc::indoc! {r#"
+            full-name = "{{first}} {{last}}"
+            greeting = "Hello, {{full-name}} of {{clock.valley}}!"
+            first = "Lu"
+            last = "Tse"
+            unknown = "{{nobody}} {{first}}"
+            [clock]
+            valley = "Oi Dong"
+            sweepers = ["{{full-name}}"]
+        "#})?;
+
+        // When
+        let resolved = resolve_props(&props, &PlaceholderDelimiters::default())?;
+
+        // Then
+        assert_eq!(resolved.get("full-name").and_then(Value::as_str), Some("Lu Tse"));
+        assert_eq!(resolved.get("greeting").and_then(Value::as_str), Some("Hello, Lu Tse of Oi Dong!"));
+        assert_eq!(resolved.get("unknown").and_then(Value::as_str), Some("{{nobody}} Lu"));
+        assert_eq!(lookup(&resolved, "clock.sweepers.0").and_then(Value::as_str), Some("Lu Tse"));
+        Ok(())
+    }
+
+    #[test]
+    fn resolve_props_with_cycle() -> Result<()> {
+        // Given
+        let props: Table = toml::from_str(indoc::indoc! {r#"
+            sweeper = "{{apprentice}}"
+            apprentice = "{{master}}"
+            master = "{{sweeper}}"
+        "#})?;
+
+        // When
+        let result = resolve_props(&props, &PlaceholderDelimiters::default());
+
+        // Then
+        let Err(error) = result else { panic!("Expected a cycle") };
+        assert!(error.to_string().starts_with("Props refer to each other in a cycle: "), "Actual error: {error}");
+        Ok(())
+    }
+
+    #[test]
+    fn interpolate_with_custom_delimiters() {
+        // Given
+        let mut variables = Table::new();
+        variables.insert("sweeper".to_string(), Value::String("Lu Tse".to_string()));
+        let delimiters = PlaceholderDelimiters::new("<%", "%>");
+
+        // When
+        let custom = interpolate_with_delimiters("{{name}}: <%sweeper%>", &variables, &delimiters);
+        let default = interpolate("{{sweeper}}: <%sweeper%>", &variables);
+
+        // Then
+        assert_eq!(custom, "{{name}}: Lu Tse");
+        assert_eq!(default, "Lu Tse: <%sweeper%>");
+    }
+
+    #[test]
+    fn pattern_with_custom_delimiters() {
+        // Given
+        let delimiters = PlaceholderDelimiters::new("@{", "}@");
+
+        // When
+        let (regex, names) = placeholder_pattern("{{title}} by @{author}@", &delimiters).unwrap();
+
+        // Then
+        assert_eq!(names, vec!["author".to_string()]);
+        let captures = regex.captures("{{title}} by Terry").unwrap();
+        assert_eq!(captures.get(1).map(|m| m.as_str()), Some("Terry"));
+    }
+
+    #[test]
+    fn pattern_captures_placeholder_values() {
+        // Given
+        let template = "name = \"{{project-name}}\" # by {{author}}";
+
+        // When
+        let (regex, names) = placeholder_pattern(template, &PlaceholderDelimiters::default()).unwrap();
+
+        // Then
+        assert_eq!(names, vec!["project-name".to_string(), "author".to_string()]);
+        let captures = regex.captures("name = \"igor\" # by Jeroen").unwrap();
+        assert_eq!(captures.get(1).unwrap().as_str(), "igor");
+        assert_eq!(captures.get(2).unwrap().as_str(), "Jeroen");
+        assert!(regex.captures("name = \"igor\"").is_none());
+    }
+
+    #[test]
+    fn pattern_without_placeholders() {
+        assert!(placeholder_pattern("Just text", &PlaceholderDelimiters::default()).is_none());
     }
-    result
-}
\ No newline at end of file
+}
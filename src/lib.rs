@@ -1,23 +1,66 @@
 use std::path::PathBuf;
+use std::future::Future;
 use std::sync::Arc;
-use ahash::AHashMap;
-use anyhow::Result;
-use clap::Parser;
+use std::time::Instant;
+use ahash::{AHashMap, AHashSet};
+use anyhow::{anyhow, Result};
+use clap::{Parser, Subcommand};
 use log::{debug, error, info, warn};
 use tokio::sync::mpsc::{channel, Receiver, Sender};
 
+mod audit;
+mod changelog;
+mod clean;
+mod config_command;
 mod config_model;
+mod download;
+mod effective_config;
+mod explain;
+mod features;
+mod fetch;
 mod file_system;
+mod functions;
+mod generators;
+mod git;
+mod graph;
+mod hooks;
+mod infer_props;
 mod interpolate;
+mod list;
+mod manifest;
+mod merge;
+mod memory;
+mod new_thundercloud;
+mod notification;
 mod niche;
+mod patch;
 mod path;
+mod prompt;
+mod props_exec;
+mod report;
+mod run_options;
+mod scheduler_debug;
+mod shuffle;
+mod tera_engine;
 mod thundercloud;
+mod update;
+mod upgrade;
+mod vendor;
+mod watch;
+mod write_mode_override;
 
-use crate::config_model::{project_config, NicheTriggers, PsychotropicConfig};
+use crate::config_model::{document, project_config, MissingNiche, NetworkAccess, NicheTriggers, PsychotropicConfig, SchedulerConfig, UseThundercloudConfig};
 use crate::file_system::{ConfigFormat, FileSystem, PathType};
-use crate::niche::process_niche;
+use crate::niche::{process_niche, UseThundercloudDefaults};
+use crate::config_model::schema::SchemaKind;
+use crate::graph::GraphFormat;
 use crate::path::AbsolutePath;
+use crate::report::{NicheReporter, Report, ReportFormat, Reporter};
 use crate::config_model::project_config::ProjectConfig;
+pub use crate::config_model::WriteMode;
+pub use crate::functions::{Function, FunctionRegistry};
+pub use crate::run_options::RunOptions;
+pub use crate::write_mode_override::WriteModeOverride;
 
 #[derive(Parser,Debug)]
 #[command(version, about, long_about = None)]
@@ -29,14 +72,290 @@ struct Arguments {
     /// Location of the directory that specifies the niches to fill (default: PROJECT_ROOT/yeth-marthter)
     #[arg(short, long, value_name = "DIRECTORY")]
     niches: Option<PathBuf>,
+
+    /// Maximum number of niches that are processed concurrently (default: number of CPUs)
+    #[arg(short, long)]
+    jobs: Option<usize>,
+
+    /// Approximate memory (in MiB) for buffered file contents and bolt indexes: near this ceiling, Igor waits for running niches to finish before it starts more niches
+    #[arg(long, value_name = "MIB")]
+    memory_ceiling: Option<usize>,
+
+    /// Stop starting niches after the first niche that fails
+    #[arg(long)]
+    fail_fast: bool,
+
+    /// Never ask for the values of missing properties (Igor only asks if standard input is a terminal)
+    #[arg(long)]
+    no_input: bool,
+
+    /// Override the write mode of all target files for this run
+    #[arg(long, value_enum, value_name = "WRITE_MODE")]
+    write_mode: Option<WriteMode>,
+
+    /// Keep the configured write mode for target files that match this glob pattern (relative to the project root)
+    #[arg(long, value_name = "GLOB", requires = "write_mode")]
+    write_mode_except: Vec<String>,
+
+    /// Maximum length in bytes of the lines that Igor reads (files that are copied as is may have longer lines)
+    #[arg(long, value_name = "BYTES", default_value_t = file_system::DEFAULT_MAX_LINE_LENGTH)]
+    max_line_length: usize,
+
+    /// Print how the names of the files in cumulus and invar directories are classified as bolts (on standard error)
+    #[arg(long)]
+    explain_names: bool,
+
+    /// Date and time that placeholders like {{now.year}} refer to, in RFC 3339 format like 2024-10-21T12:00:00+02:00 (default: the value of SOURCE_DATE_EPOCH or the current time)
+    #[arg(long, value_name = "TIMESTAMP")]
+    now: Option<String>,
+
+    /// Never access the network: fail instead of fetching thunderclouds from their git remote or URL, and skip webhook notifications
+    #[arg(long)]
+    offline: bool,
+
+    /// Randomize the order of independent niches, directory entries and target files to flush out ordering dependencies (the seed is printed, so that the order can be reproduced)
+    #[arg(long, value_name = "SEED", num_args = 0..=1, require_equals = true, default_missing_value = None)]
+    shuffle: Option<Option<u64>>,
+
+    /// Make the output byte-identical for identical inputs: derive all random values from the seed (default 0), visit niches and files in sorted order and pin the time to the Unix epoch (unless --now or SOURCE_DATE_EPOCH sets it)
+    #[arg(long, value_name = "SEED", num_args = 0..=1, require_equals = true, default_missing_value = "0")]
+    reproducible: Option<u64>,
+
+    /// Record every prop that is interpolated in a target file (with its value and the configuration file that provides it) in a TOML file
+    #[arg(long, value_name = "FILE", global = true)]
+    audit_props: Option<PathBuf>,
+
+    /// Print the state of the scheduler (waiting, ready and running niches) on standard error whenever no niche finishes for this many seconds
+    #[arg(long, value_name = "SECONDS")]
+    debug_scheduler: Option<u64>,
+
+    /// Write the files of the project to this directory instead of the project root (preserving their paths relative to the project root), to review them before they replace the project files
+    #[arg(long, value_name = "DIRECTORY")]
+    output_dir: Option<PathBuf>,
+
+    /// Run the shell commands in the props-exec sections of invar configurations to compute props
+    #[arg(long)]
+    allow_props_exec: bool,
+
+    /// Start no programs at all (like git and curl), and skip the commands of props-exec sections instead of running them (the props that they compute get no value)
+    #[arg(long)]
+    no_hooks: bool,
+
+    /// Append a summary of the run to the changelog of the project (the changelog-file in CargoCult.toml, or .igor/CHANGELOG.md) if files were changed
+    #[arg(long)]
+    changelog: bool,
+
+    /// Print a machine-readable report of the run
+    #[arg(long, value_enum, value_name = "FORMAT")]
+    report: Option<ReportFormat>,
+
+    /// Write the report to a file instead of standard output
+    #[arg(long, value_name = "FILE", requires = "report")]
+    report_file: Option<PathBuf>,
+
+    /// Print the effective configuration of a target file or directory (default: the project root) for every niche, instead of applying the niches
+    #[arg(long, value_name = "PATH", num_args = 0..=1, default_missing_value = ".")]
+    show_effective_config: Option<PathBuf>,
+
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand,Debug)]
+enum Command {
+    /// Regenerate the project on a new git branch, commit the result and print a summary
+    Update {
+        /// Name of the branch to create (default: igor/update-<date>)
+        #[arg(short, long)]
+        branch: Option<String>,
+    },
+    /// Clone or update the thunderclouds that have a git remote and check out their revisions, without applying the niches
+    Fetch,
+    /// Copy the fetched thunderclouds that have a git remote into vendor/thunderclouds and configure the niches to use the copies
+    Vendor,
+    /// Propose props for a niche by matching its templates against existing project files
+    InferProps {
+        /// Name of the niche
+        #[arg(short, long)]
+        niche: String,
+    },
+    /// Keep running and regenerate niches when their thundercloud, invar or the project configuration changes
+    Watch,
+    /// Print the niches with the props that their thunderclouds declare
+    List,
+    /// Print the enabled features of the niches next to the features that their bolts mention, to catch misspelled features
+    Features,
+    /// Print the niches and their wait-for relationships as a graph
+    Graph {
+        /// Output format
+        #[arg(short, long, value_enum, default_value = "dot")]
+        format: GraphFormat,
+    },
+    /// Explain which options, fragments, configuration files and features produce a target file
+    Explain {
+        /// Path of the target file (relative to the project root)
+        target: PathBuf,
+    },
+    /// Print the JSON Schema of a kind of configuration file, for validation and completion in editors
+    Schema {
+        /// Kind of configuration file
+        #[arg(value_enum)]
+        kind: SchemaKind,
+    },
+    /// Create a skeleton thundercloud with example options, fragments, configuration and invar
+    NewThundercloud {
+        /// Directory of the new thundercloud
+        directory: PathBuf,
+        /// Name of the niche (default: the name of the directory)
+        #[arg(long)]
+        name: Option<String>,
+        /// Description of the niche
+        #[arg(long)]
+        description: Option<String>,
+    },
+    /// Remove the files that Igor generated, unless they were modified since
+    Clean {
+        /// Also remove generated files that were modified since
+        #[arg(short, long)]
+        force: bool,
+    },
+    /// Replace this executable with the static binary of the latest release, after verifying its checksum
+    Upgrade {
+        /// URL of the release feed (default: the release-feed in CargoCult.toml, or the latest release of Igor on GitHub)
+        #[arg(long)]
+        feed: Option<String>,
+        /// Minisign public key that the binary must be signed with (default: the key of the official releases; only with --feed)
+        #[arg(long)]
+        public_key: Option<String>,
+        /// Only report whether a newer release is available
+        #[arg(long)]
+        check: bool,
+    },
+    /// Read or change a setting in CargoCult.toml, keeping the layout and comments of the file
+    Config {
+        #[command(subcommand)]
+        action: ConfigAction,
+    },
+}
+
+#[derive(Subcommand,Debug)]
+enum ConfigAction {
+    /// Print a setting, like psychotropic.cues[example].use-thundercloud.features
+    Get {
+        /// Dotted key; select elements of arrays by index or by name, like cues[0] or cues[example]
+        key: String,
+    },
+    /// Change a setting; the value is parsed as TOML (like '["glass"]'), or taken as a string otherwise
+    Set {
+        /// Dotted key; select elements of arrays by index or by name, like cues[0] or cues[example]
+        key: String,
+        /// New value
+        value: String,
+    },
+    /// Add a niche with the wait-for niches and features that its thundercloud suggests in suggested-cues.toml
+    AddNiche {
+        /// Name of the niche
+        name: String,
+        /// Directory of the thundercloud, like '{{PROJECT}}/../example-thundercloud'
+        directory: String,
+    },
 }
 
 pub async fn igor() -> Result<()> {
     info!("Igor started");
     let arguments = Arguments::parse();
+    let mut run_options = RunOptions::default();
+
+    run_options = run_options.with_input(!arguments.no_input);
+    run_options = run_options.with_props_exec(arguments.allow_props_exec);
+    run_options = run_options.with_no_hooks(arguments.no_hooks);
+    run_options = run_options.with_offline(arguments.offline);
+    run_options = run_options.with_changelog(arguments.changelog);
+    run_options = run_options.with_explain_names(arguments.explain_names);
+    if let Some(now) = &arguments.now {
+        let now = chrono::DateTime::parse_from_rfc3339(now).map_err(|error| anyhow::anyhow!("Invalid timestamp: {now:?}: {error}"))?;
+        run_options = run_options.with_now(now);
+    }
+    if let Some(seed_option) = arguments.shuffle {
+        let seed = seed_option.unwrap_or_else(|| fastrand::u64(..));
+        eprintln!("Shuffle with seed: {seed}");
+        run_options = run_options.with_shuffle(seed);
+    }
+    if let Some(seed) = arguments.reproducible {
+        run_options = run_options.with_reproducible(seed);
+    }
+    if let Some(audit_file) = &arguments.audit_props {
+        run_options = run_options.with_audit_file(audit_file.clone());
+    }
+    if let Some(seconds) = arguments.debug_scheduler {
+        run_options = run_options.with_debug_scheduler(seconds);
+    }
+    if let Some(mib) = arguments.memory_ceiling {
+        run_options = run_options.with_memory_ceiling(mib);
+    }
+    if let Some(write_mode) = arguments.write_mode {
+        run_options = run_options.with_write_mode_override(WriteModeOverride::new(write_mode, &arguments.write_mode_except)?);
+    }
+    let fs = file_system::real_file_system_with_max_line_length(arguments.max_line_length);
+    if let Some(path) = arguments.show_effective_config {
+        return effective_config::show_effective_config(arguments.project_root, path, &run_options, &fs).await;
+    }
+    match arguments.command {
+        None => {
+            let project_root = get_project_root(arguments.project_root)?;
+            let report = match arguments.output_dir {
+                Some(output_dir) => {
+                    let output_directory = AbsolutePath::new(output_dir, &AbsolutePath::current_dir()?);
+                    info!("Output directory: {output_directory:?}");
+                    let redirected_fs = fs.clone().redirect_writes(project_root.clone(), output_directory);
+                    process_niches(project_root.clone(), arguments.jobs, None, arguments.fail_fast, &run_options, &FunctionRegistry::default(), &redirected_fs).await?
+                },
+                None => process_niches(project_root.clone(), arguments.jobs, None, arguments.fail_fast, &run_options, &FunctionRegistry::default(), &fs).await?,
+            };
+            notification::notify(&project_root, &run_options, &report, &fs).await;
+            if let Some(format) = arguments.report {
+                report.write(format, arguments.report_file)?;
+            }
+            report.check()
+        },
+        Some(Command::Update { branch }) => update::update(arguments.project_root, arguments.jobs, arguments.fail_fast, branch, &run_options, &fs).await,
+        Some(Command::Fetch) => fetch::fetch(arguments.project_root, &run_options, &fs).await,
+        Some(Command::Vendor) => vendor::vendor(arguments.project_root, &fs).await,
+        Some(Command::InferProps { niche }) => infer_props::infer_props(arguments.project_root, niche, &run_options, &fs).await,
+        Some(Command::Watch) => watch::watch(arguments.project_root, arguments.jobs, &run_options, &fs).await,
+        Some(Command::List) => list::list(arguments.project_root, &fs).await,
+        Some(Command::Features) => features::features(arguments.project_root, &run_options, &fs).await,
+        Some(Command::Graph { format }) => graph::graph(arguments.project_root, format, &fs).await,
+        Some(Command::Explain { target }) => explain::explain(arguments.project_root, target, &run_options, &fs).await,
+        Some(Command::Schema { kind }) => {
+            println!("{}", config_model::schema::json_schema(kind)?);
+            Ok(())
+        },
+        Some(Command::NewThundercloud { directory, name, description }) => new_thundercloud::new_thundercloud(directory, name, description, &fs).await,
+        Some(Command::Clean { force }) => clean::clean(arguments.project_root, force, &fs).await,
+        Some(Command::Upgrade { feed, public_key, check }) => upgrade::upgrade(arguments.project_root, feed, public_key, check, &run_options, &fs).await,
+        Some(Command::Config { action: ConfigAction::Get { key } }) => config_command::config_get(arguments.project_root, key, &fs).await,
+        Some(Command::Config { action: ConfigAction::Set { key, value } }) => config_command::config_set(arguments.project_root, key, value, &fs).await,
+        Some(Command::Config { action: ConfigAction::AddNiche { name, directory } }) => config_command::config_add_niche(arguments.project_root, name, directory, &fs).await,
+    }
+}
 
-    let fs = file_system::real_file_system();
-    application(arguments.project_root, &fs).await
+/// Lets other crates check their own implementations of `FileSystem` against the contract that Igor relies on.
+#[cfg(feature = "testkit")]
+pub mod testkit {
+    pub use crate::config_model::WriteMode;
+    pub use crate::file_system::{DirEntry, FileSystem, Metadata, PathType, SourceFile, TargetFile};
+    pub use crate::file_system::conformance::*;
+    pub use crate::path::AbsolutePath;
+}
+
+/// Lets other crates run Igor (see [`application`]) against an in-memory file system, in tests or in a sandbox.
+#[cfg(feature = "fixture")]
+pub mod fixture {
+    pub use crate::config_model::WriteMode;
+    pub use crate::file_system::{DirEntry, FileSystem, Metadata, PathType, SourceFile, TargetFile};
+    pub use crate::file_system::fixture::{assert_executable, assert_not_executable, from_toml, from_yaml};
+    pub use crate::path::AbsolutePath;
 }
 
 #[derive(Clone,Debug,Hash,PartialEq,Eq)]
@@ -60,18 +379,38 @@ enum NicheStatus {
     AllScheduled(usize),
 }
 
-pub async fn application<FS: FileSystem + 'static>(project_root_option: Option<PathBuf>, fs: &FS) -> Result<()> {
+fn get_project_root(project_root_option: Option<PathBuf>) -> Result<AbsolutePath> {
     let cwd = AbsolutePath::current_dir()?;
     let project_root_path = project_root_option.unwrap_or(PathBuf::from("."));
-    let project_root = AbsolutePath::new(project_root_path, &cwd);
+    Ok(AbsolutePath::new(project_root_path, &cwd))
+}
 
-    let project_config_path = AbsolutePath::new("CargoCult.toml", &project_root);
+async fn get_project_config<FS: FileSystem>(project_root: &AbsolutePath, fs: &FS) -> Result<impl ProjectConfig> {
+    let project_config_path = AbsolutePath::new("CargoCult.toml", project_root);
     let project_config_data = if fs.path_type(&project_config_path).await == PathType::File {
-        fs.get_content(project_config_path).await?
+        fs.get_content(project_config_path.clone()).await?
     } else {
         "".to_string()
     };
-    let project_configuration = project_config::from_str(&project_config_data, ConfigFormat::TOML)?;
+    project_config::from_str(&project_config_data, ConfigFormat::TOML)
+        .map_err(|error| document::locate_error(&project_config_path, &project_config_data, error))
+}
+
+/// Processes all niches of the project with the given options, and with the given functions available to templates.
+/// Fails if any niche failed.
+pub async fn application<FS: FileSystem + 'static>(project_root_option: Option<PathBuf>, jobs_option: Option<usize>, fail_fast: bool, run_options: &RunOptions, functions: &FunctionRegistry, fs: &FS) -> Result<Report> {
+    let project_root = get_project_root(project_root_option)?;
+    let report = process_niches(project_root, jobs_option, None, fail_fast, run_options, functions, fs).await?;
+    report.check()?;
+    Ok(report)
+}
+
+/// Processes the niches of the project in the order that the psychotropic configuration prescribes. If a selection is
+/// given, then niches outside the selection are skipped, but still release the niches that wait for them. With
+/// `fail_fast`, niches that start after a niche failed are skipped as well.
+async fn process_niches<FS: FileSystem + 'static>(project_root: AbsolutePath, jobs_option: Option<usize>, selection: Option<AHashSet<NicheName>>, fail_fast: bool, run_options: &RunOptions, functions: &FunctionRegistry, fs: &FS) -> Result<Report> {
+    let project_configuration = get_project_config(&project_root, fs).await?;
+    upgrade::check_requires_igor(project_configuration.requires_igor())?;
 
     let niches_directory= AbsolutePath::new(project_configuration.niches_directory().as_path(), &project_root);
     info!("Niches configuration directory: {niches_directory:?}");
@@ -79,17 +418,27 @@ pub async fn application<FS: FileSystem + 'static>(project_root_option: Option<P
     let project_config = Arc::new(project_configuration);
     info!("Project configuration: {project_config:?}");
 
+    // All niches (and the changelog) refer to the same date and time, and share the order and the memory of the run
+    let offline = run_options.offline() || project_config.network() == NetworkAccess::Forbidden;
+    let run_options = run_options.for_run().with_allowed_hook_commands(project_config.as_ref()).with_offline(offline);
+    memory::configure_memory_ceiling(run_options.memory(), run_options.memory_ceiling(), project_config.scheduler().memory_ceiling_mib());
+
+    let reporter = Reporter::default();
     let mut handles = Vec::new();
-    let permits = 5;
+    let permits = jobs_option
+        .or(project_config.scheduler().jobs())
+        .unwrap_or_else(default_jobs)
+        .max(1);
+    info!("Maximum number of concurrent niches: {permits}");
     let (tx_work, mut rx_work) = channel(permits);
     let (tx_done, rx_done) = channel(permits);
     let (tx_permit, mut rx_permit) = channel(permits);
-    for _ in 1..permits {
+    for _ in 0..permits {
         tx_permit.send(()).await?;
     }
-    let collector_join_handle = tokio::spawn(collect_done(project_config.clone(), permits, rx_done, tx_work.clone(), tx_permit.clone()));
+    let collector_join_handle = tokio::spawn(collect_done(project_config.clone(), run_options.clone(), permits, rx_done, tx_work.clone(), tx_permit.clone()));
     handles.push(collector_join_handle);
-    let emitter_join_handle = tokio::spawn(emit_niches(project_config.clone(), tx_work.clone()));
+    let emitter_join_handle = tokio::spawn(emit_niches(project_config.clone(), run_options.clone(), tx_work.clone()));
     handles.push(emitter_join_handle);
 
     let mut scheduled_count = None;
@@ -103,8 +452,22 @@ pub async fn application<FS: FileSystem + 'static>(project_root_option: Option<P
                     break;
                 }
                 debug!("Got permit for: {:?}", &niche);
+                memory::wait_for_memory(run_options.memory(), niche.to_str()).await;
                 let niche_fs = fs.clone();
-                let niche_join_handle = tokio::spawn(run_process_niche(project_root.clone(), niche.clone(), niche_fs, project_config.clone(), tx_done.clone()));
+                let aborted = reporter.has_truncated_files();
+                let cancelled = aborted || (fail_fast && reporter.has_failures());
+                if aborted {
+                    error!("Skip niche after a fatal write error: {:?}", &niche);
+                } else if cancelled {
+                    warn!("Skip niche after failure: {:?}", &niche);
+                }
+                let selected = !cancelled && selection.as_ref().map(|selection| selection.contains(&niche)).unwrap_or(true);
+                let in_flight = run_options.memory().start_niche();
+                let niche_future = run_process_niche(project_root.clone(), niche.clone(), selected, niche_fs, project_config.clone(), run_options.clone(), functions.clone(), reporter.clone(), tx_done.clone());
+                let niche_join_handle = tokio::spawn(async move {
+                    let _in_flight = in_flight;
+                    niche_future.await
+                });
                 handles.push(niche_join_handle);
                 started_count += 1;
                 if scheduled_count.map(|scheduled| started_count >= scheduled).unwrap_or(false) {
@@ -128,15 +491,24 @@ pub async fn application<FS: FileSystem + 'static>(project_root_option: Option<P
     for handle in handles {
         match handle.await {
             Err(err) => info!("Error in join: {err:?}"),
-            Ok(Err(err)) => info!("Error while processing niche: {err:?}"),
+            Ok(Err(err)) => error!("Error while processing niche: {err:?}"),
             _ => ()
         }
     }
 
-    Ok(())
+    let report = reporter.report();
+    changelog::append_entry(&project_root, project_config.as_ref(), &run_options, &report, fs).await?;
+    if let Some(audit_file) = run_options.audit_file() {
+        audit::write(audit_file, &report)?;
+    }
+    Ok(report)
+}
+
+fn default_jobs() -> usize {
+    std::thread::available_parallelism().map(usize::from).unwrap_or(1)
 }
 
-async fn collect_done<PC>(project_config: Arc<PC>, max_slack: usize, mut rx_done: Receiver<NicheName>, tx_work: Sender<NicheStatus>, tx_permit: Sender<()>) -> Result<()>
+async fn collect_done<PC>(project_config: Arc<PC>, run_options: RunOptions, max_slack: usize, mut rx_done: Receiver<NicheName>, tx_work: Sender<NicheStatus>, tx_permit: Sender<()>) -> Result<()>
 where PC: ProjectConfig
 {
     let psychotropic_config = project_config.psychotropic()?;
@@ -158,7 +530,20 @@ where PC: ProjectConfig
 
     let mut slack = max_slack;
     let mut ready: Vec<NicheName> = Vec::new();
-    while let Some(niche_path) = rx_done.recv().await {
+    let mut done = AHashSet::new();
+    loop {
+        let received = match run_options.stall_timeout() {
+            Some(stall_timeout) => match tokio::time::timeout(stall_timeout, rx_done.recv()).await {
+                Ok(received) => received,
+                Err(_) => {
+                    eprintln!("{}", scheduler_state(&wait_count, &ready, slack, &done));
+                    continue;
+                }
+            },
+            None => rx_done.recv().await,
+        };
+        let Some(niche_path) = received else { break };
+        done.insert(niche_path.clone());
         debug!("Send permit");
         tx_permit.send(()).await?;
         if let Some(later) = ready.pop() {
@@ -169,7 +554,8 @@ where PC: ProjectConfig
             slack += 1;
         }
         debug!("Notify niches waiting for: {:?}", &niche_path);
-        if let Some(later_list) = waiting.remove(&niche_path) {
+        if let Some(mut later_list) = waiting.remove(&niche_path) {
+            run_options.shuffle(&mut later_list, |niche| niche.to_str().to_string());
             for later in later_list {
                 if let Some(count) = wait_count.get_mut(&later) {
                     if *count == 0 {
@@ -195,12 +581,29 @@ where PC: ProjectConfig
     Ok(())
 }
 
-async fn emit_niches<PC>(project_config: Arc<PC>, tx: Sender<NicheStatus>) -> Result<()>
+fn scheduler_state(wait_count: &AHashMap<NicheName, usize>, ready: &[NicheName], slack: usize, done: &AHashSet<NicheName>) -> scheduler_debug::SchedulerState {
+    let mut state = scheduler_debug::SchedulerState {
+        ready: ready.iter().map(|niche| niche.to_str().to_string()).collect(),
+        outstanding_permits: slack,
+        done: done.len(),
+        ..Default::default()
+    };
+    for (niche, count) in wait_count {
+        if *count > 0 {
+            state.waiting.push((niche.to_str().to_string(), *count));
+        } else if !done.contains(niche) && !ready.contains(niche) {
+            state.running.push(niche.to_str().to_string());
+        }
+    }
+    state.sort()
+}
+
+async fn emit_niches<PC>(project_config: Arc<PC>, run_options: RunOptions, tx: Sender<NicheStatus>) -> Result<()>
 where
     PC: ProjectConfig,
 {
     let mut count = 0;
-    let result = do_emit_independent(&project_config, &tx).await;
+    let result = do_emit_independent(&project_config, &run_options, &tx).await;
     if let Ok(independent) = &result {
         count += independent;
     } else {
@@ -213,11 +616,12 @@ where
     Ok(())
 }
 
-async fn do_emit_independent<PC>(project_config: &Arc<PC>, tx: &Sender<NicheStatus>) -> Result<usize>
+async fn do_emit_independent<PC>(project_config: &Arc<PC>, run_options: &RunOptions, tx: &Sender<NicheStatus>) -> Result<usize>
 where PC: ProjectConfig
 {
     let psychotropic_config = project_config.psychotropic()?;
-    let independent = psychotropic_config.independent();
+    let mut independent: Vec<String> = psychotropic_config.independent().into_iter().collect();
+    run_options.shuffle(&mut independent, String::clone);
     let mut count = 0;
     for niche in independent {
         debug!("Send independent: {:?}", &niche);
@@ -234,28 +638,67 @@ where PC: ProjectConfig
     Ok(count)
 }
 
-async fn run_process_niche<FS: FileSystem, PC: ProjectConfig>(project_root: AbsolutePath, niche: NicheName, niche_fs: FS, project_config: Arc<PC>, tx_done: Sender<NicheName>) -> Result<()> {
+fn get_use_thundercloud<NT: NicheTriggers, FS: FileSystem>(niche_triggers: Option<&NT>, use_thundercloud_defaults: &UseThundercloudDefaults, fs: &FS) -> impl Future<Output = Result<Option<NT::UseThundercloudConfigImpl>>> + use<NT, FS> {
+    let use_thundercloud_inline_option = niche_triggers
+        .map(NicheTriggers::use_thundercloud).flatten().map(Clone::clone);
+    let use_thundercloud_path_option = niche_triggers.map(NicheTriggers::use_thundercloud_path).flatten();
+    let shared_targets = niche_triggers.map(|niche_triggers| niche_triggers.shared_targets().clone()).unwrap_or_default();
+    let use_thundercloud_defaults = use_thundercloud_defaults.clone();
+    let fs = fs.clone();
+    async move {
+        let use_thundercloud_option: Option<NT::UseThundercloudConfigImpl> = if use_thundercloud_inline_option.is_some() {
+            use_thundercloud_inline_option
+        } else if let Some(path) = use_thundercloud_path_option {
+            let content = fs.get_content(path).await?;
+            Some(toml::from_str(&content)?)
+        } else {
+            None
+        };
+        Ok(use_thundercloud_option.map(|use_thundercloud| use_thundercloud_defaults.apply(&use_thundercloud).with_shared_targets(shared_targets)))
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn run_process_niche<FS: FileSystem, PC: ProjectConfig>(project_root: AbsolutePath, niche: NicheName, selected: bool, niche_fs: FS, project_config: Arc<PC>, run_options: RunOptions, functions: FunctionRegistry, reporter: Reporter, tx_done: Sender<NicheName>) -> Result<()> {
+    if !selected {
+        debug!("Skip niche that is not selected: {:?}", &niche);
+        tx_done.send(niche).await?;
+        return Ok(());
+    }
     debug!("Processing niche: {:?}", &niche);
+    let start = Instant::now();
     let psychotropic = project_config.psychotropic()?;
     let niche_triggers = psychotropic
         .get(niche.to_str());
-    let use_thundercloud_inline_option = niche_triggers
-        .map(NicheTriggers::use_thundercloud).flatten().map(Clone::clone);
-    let use_thundercloud_option = if use_thundercloud_inline_option.is_some() {
-        use_thundercloud_inline_option
-    } else if let Some(path) = niche_triggers.map(NicheTriggers::use_thundercloud_path).flatten() {
-        let content = niche_fs.get_content(path).await?;
-        Some(toml::from_str(&content)?)
-    } else {
-        None
-    };
-    let result = if let Some(use_thundercloud) = use_thundercloud_option {
-        let niches_directory = project_config.niches_directory();
-        process_niche(project_root, niches_directory, niche.clone(), use_thundercloud.clone(), project_config.invar_defaults().into_owned(), niche_fs).await
-    } else {
-        warn!("Niche not found: {:?}", &niche);
-        Ok(())
+    let niche_reporter = NicheReporter::default();
+    let use_thundercloud_defaults = UseThundercloudDefaults::new(&project_root, project_config.as_ref());
+    let result = match get_use_thundercloud(niche_triggers, &use_thundercloud_defaults, &niche_fs).await {
+        Ok(Some(use_thundercloud)) => {
+            if let Some(description) = changelog::describe_thundercloud(&project_root, &use_thundercloud, run_options.allowed_commands(), &niche_fs).await {
+                niche_reporter.thundercloud(description);
+            }
+            let niches_directory = project_config.niches_directory();
+            process_niche(project_root, niches_directory, niche.clone(), use_thundercloud.clone(), project_config.invar_defaults().into_owned(), niche_fs, run_options, functions, niche_reporter.clone()).await
+        },
+        Ok(None) => {
+            let niche_directory = AbsolutePath::new(project_config.niches_directory().join(niche.to_str()), &project_root);
+            let warning = if niche_fs.path_type(&niche_directory).await == PathType::Directory {
+                format!("Niche has no use-thundercloud: {}", niche.to_str())
+            } else {
+                format!("Niche has no use-thundercloud and no directory in {}: {}", project_config.niches_directory().to_string_lossy(), niche.to_str())
+            };
+            match project_config.missing_niche() {
+                MissingNiche::Fail => Err(anyhow!(warning)),
+                MissingNiche::Warn => {
+                    warn!("{warning}");
+                    niche_reporter.warning(warning);
+                    Ok(())
+                },
+            }
+        },
+        Err(err) => Err(err),
     };
+    reporter.add_niche(&niche, niche_reporter, start.elapsed(), &result);
     debug!("Send done: {:?}", &niche);
     tx_done.send(niche.clone()).await?;
     debug!("Done sent: {:?}", &niche);
@@ -277,7 +720,7 @@ mod test {
         let fs = create_file_system_fixture()?;
 
         // When
-        application(Some(PathBuf::from("/")), &fs).await?;
+        application(Some(PathBuf::from("/")), None, false, &RunOptions::default(), &FunctionRegistry::default(), &fs).await?;
 
         // Then
         let content = fs.get_content(to_absolute_path("/workshop/clock.yaml")).await?;
@@ -292,6 +735,432 @@ mod test {
         Ok(())
     }
 
+    #[test(tokio::test)]
+    async fn test_application_single_job() -> Result<()> {
+        // Given
+        let fs = create_file_system_fixture()?;
+
+        // When
+        application(Some(PathBuf::from("/")), Some(1), false, &RunOptions::default(), &FunctionRegistry::default(), &fs).await?;
+
+        // Then
+        assert_eq!(fs.path_type(&to_absolute_path("/workshop/clock.yaml")).await, PathType::File);
+
+        Ok(())
+    }
+
+    #[test(tokio::test)]
+    async fn test_application_write_mode_override() -> Result<()> {
+        // Given
+        let fs = create_file_system_fixture()?;
+        let run_options = RunOptions::default().with_write_mode_override(WriteModeOverride::new(WriteMode::Ignore, &[])?);
+
+        // When
+        application(Some(PathBuf::from("/")), None, false, &run_options, &FunctionRegistry::default(), &fs).await?;
+        let ignored = fs.path_type(&to_absolute_path("/workshop/clock.yaml")).await;
+        application(Some(PathBuf::from("/")), None, false, &RunOptions::default(), &FunctionRegistry::default(), &fs).await?;
+        let written = fs.path_type(&to_absolute_path("/workshop/clock.yaml")).await;
+
+        // Then
+        assert_eq!(ignored, PathType::Missing);
+        assert_eq!(written, PathType::File);
+
+        Ok(())
+    }
+
+    #[test(tokio::test)]
+    async fn test_application_pinned_time() -> Result<()> {
+        // Given
+        let toml_data = indoc! {r#"
+            "CargoCult.toml" = '''
+            [[psychotropic.cues]]
+            name = "example"
+            use-thundercloud = { directory = "{{PROJECT}}/example-thundercloud" }
+            '''
+
+            [yeth-marthter.example.invar.workshop]
+            "clock+config.yaml.toml" = 'write-mode = "Overwrite"'
+
+            [example-thundercloud]
+            "thundercloud.toml" = """
+            [niche]
+            name = "example"
+            """
+
+            [example-thundercloud.cumulus.workshop]
+            "clock+option-@.yaml" = "wound: {{now.date}}"
+        "#};
+        let fs = fixture::from_toml(toml_data)?;
+        let first_run = RunOptions::default().with_now(chrono::DateTime::parse_from_rfc3339("2024-03-07T09:05:02+01:00")?);
+        let second_run = RunOptions::default().with_now(chrono::DateTime::parse_from_rfc3339("2025-11-30T23:59:59+01:00")?);
+
+        // When
+        application(Some(PathBuf::from("/")), None, false, &first_run, &FunctionRegistry::default(), &fs).await?;
+        let first_content = fs.get_content(to_absolute_path("/workshop/clock.yaml")).await?;
+        application(Some(PathBuf::from("/")), None, false, &second_run, &FunctionRegistry::default(), &fs).await?;
+        let second_content = fs.get_content(to_absolute_path("/workshop/clock.yaml")).await?;
+
+        // Then
+        assert_eq!(first_content, "wound: 2024-03-07\n");
+        assert_eq!(second_content, "wound: 2025-11-30\n");
+
+        Ok(())
+    }
+
+    #[test(tokio::test)]
+    async fn test_application_reproducible() -> Result<()> {
+        // Given
+        let toml_data = indoc! {r#"
+            "CargoCult.toml" = '''
+            [[psychotropic.cues]]
+            name = "example"
+            use-thundercloud = { directory = "{{PROJECT}}/example-thundercloud" }
+            '''
+
+            [yeth-marthter.example.invar]
+
+            [example-thundercloud]
+            "thundercloud.toml" = """
+            [niche]
+            name = "example"
+            """
+
+            [example-thundercloud.cumulus.workshop]
+            "clock+option-@.yaml" = "id: {{uuid}}\nat: {{IGOR_TIMESTAMP}}"
+        "#};
+        let generate = |seed: u64| async move {
+            let fs = fixture::from_toml(toml_data)?;
+            application(Some(PathBuf::from("/")), None, false, &RunOptions::default().with_reproducible(seed), &FunctionRegistry::default(), &fs).await?;
+            fs.get_content(to_absolute_path("/workshop/clock.yaml")).await
+        };
+
+        // When
+        let first = generate(4297).await?;
+        let second = generate(4297).await?;
+        let other = generate(4298).await?;
+
+        // Then
+        assert_eq!(first, second);
+        assert_ne!(first, other);
+        assert!(first.contains("\nat: 1970-01-01T00:00:00Z"), "Actual: {first}");
+
+        Ok(())
+    }
+
+    #[test(tokio::test)]
+    async fn test_application_audit_props() -> Result<()> {
+        // Given
+        let toml_data = indoc! {r#"
+            "CargoCult.toml" = '''
+            [[psychotropic.cues]]
+            name = "example"
+            use-thundercloud = { directory = "{{PROJECT}}/example-thundercloud" }
+            '''
+
+            [yeth-marthter.example.invar]
+
+            [example-thundercloud]
+            "thundercloud.toml" = """
+            [niche]
+            name = "example"
+
+            [invar-defaults.props]
+            sweeper = "Lu Tse"
+            """
+
+            [example-thundercloud.cumulus.workshop]
+            "clock+option-@.yaml" = "sweeper: {{sweeper}}"
+        "#};
+        let fs = fixture::from_toml(toml_data)?;
+        let tmp_dir = assert_fs::TempDir::new()?;
+        let audit_file = tmp_dir.path().join("audit.toml");
+
+        // When
+        application(Some(PathBuf::from("/")), None, false, &RunOptions::default().with_audit_file(audit_file.clone()), &FunctionRegistry::default(), &fs).await?;
+
+        // Then
+        let expected = indoc! {r#"
+            [[read]]
+            niche = "example"
+            target = "workshop/clock.yaml"
+            prop = "sweeper"
+            value = "Lu Tse"
+            source = "thundercloud:thundercloud.toml"
+        "#};
+        assert_eq!(std::fs::read_to_string(audit_file)?, expected);
+
+        Ok(())
+    }
+
+    #[test(tokio::test)]
+    async fn test_application_report() -> Result<()> {
+        // Given
+        let fs = create_file_system_fixture()?;
+
+        // When
+        let report = application(Some(PathBuf::from("/")), None, false, &RunOptions::default(), &FunctionRegistry::default(), &fs).await?;
+
+        // Then
+        let json = serde_json::to_value(report)?;
+        let niches = json["niches"].as_array().unwrap();
+        assert_eq!(niches.len(), 3);
+        let example = niches.iter().find(|niche| niche["name"] == "example").unwrap();
+        assert_eq!(example["files"], serde_json::json!([{ "path": "workshop/clock.yaml", "action": "written" }]));
+        assert_eq!(example["error"], serde_json::Value::Null);
+
+        Ok(())
+    }
+
+    #[test(tokio::test)]
+    async fn test_application_failure() -> Result<()> {
+        // Given
+        let toml_data = indoc! {r#"
+            "CargoCult.toml" = '''
+            [[psychotropic.cues]]
+            name = "broken"
+            use-thundercloud = { directory = "{{PROJECT}}/missing-thundercloud" }
+
+            [[psychotropic.cues]]
+            name = "example"
+            use-thundercloud = { directory = "{{PROJECT}}/example-thundercloud", features = ["glass"] }
+            wait-for = ["broken"]
+            '''
+
+            [yeth-marthter.example.invar]
+
+            [example-thundercloud]
+            "thundercloud.toml" = """
+            [niche]
+            name = "example"
+            """
+
+            [example-thundercloud.cumulus.workshop]
+            "clock+option-glass.yaml" = "sweeper: Lu Tse"
+        "#};
+        let fs = fixture::from_toml(toml_data)?;
+        let fail_fast_fs = fixture::from_toml(toml_data)?;
+
+        // When
+        let result = application(Some(PathBuf::from("/")), Some(1), false, &RunOptions::default(), &FunctionRegistry::default(), &fs).await;
+        let fail_fast_result = application(Some(PathBuf::from("/")), Some(1), true, &RunOptions::default(), &FunctionRegistry::default(), &fail_fast_fs).await;
+
+        // Then
+        assert_eq!(result.err().map(|err| err.to_string()), Some("Failed niches: broken".to_string()));
+        assert_eq!(fs.path_type(&to_absolute_path("/workshop/clock.yaml")).await, PathType::File);
+        assert!(fail_fast_result.is_err());
+        assert_eq!(fail_fast_fs.path_type(&to_absolute_path("/workshop/clock.yaml")).await, PathType::Missing);
+
+        Ok(())
+    }
+
+    #[test(tokio::test)]
+    async fn test_application_missing_niche() -> Result<()> {
+        // Given
+        let toml_data = indoc! {r#"
+            "CargoCult.toml" = '''
+            [[psychotropic.cues]]
+            name = "ghost"
+            '''
+        "#};
+        let fs = fixture::from_toml(toml_data)?;
+        let fail_fs = fixture::from_toml(&toml_data.replace("'''\n[[psychotropic", "'''\nmissing-niche = \"Fail\"\n\n[[psychotropic"))?;
+
+        // When
+        let report = application(Some(PathBuf::from("/")), None, false, &RunOptions::default(), &FunctionRegistry::default(), &fs).await?;
+        let fail_result = application(Some(PathBuf::from("/")), None, false, &RunOptions::default(), &FunctionRegistry::default(), &fail_fs).await;
+
+        // Then
+        assert_eq!(report.niches()[0].warnings(), ["Niche has no use-thundercloud and no directory in yeth-marthter: ghost"]);
+        assert_eq!(fail_result.err().map(|err| err.to_string()), Some("Failed niches: ghost".to_string()));
+
+        Ok(())
+    }
+
+    #[test(tokio::test)]
+    async fn test_application_props_exec_with_niche_props() -> Result<()> {
+        // Given
+        let toml_data = indoc! {r#"
+            "CargoCult.toml" = '''
+            [invar-defaults.props]
+            master = "Ronnie"
+
+            [invar-defaults.props-exec]
+            apprentice = "echo Lobsang of {{master}} in {{NICHE}} and $IGOR_NICHE"
+
+            [[psychotropic.cues]]
+            name = "example"
+            use-thundercloud = { directory = "{{PROJECT}}/example-thundercloud", invar-defaults = { props = { master = "Lu-Tse" } } }
+            '''
+
+            [yeth-marthter.example.invar]
+
+            [example-thundercloud]
+            "thundercloud.toml" = """
+            [niche]
+            name = "example"
+
+            [invar-defaults.props]
+            master = "Nobody"
+            """
+
+            [example-thundercloud.cumulus.workshop]
+            "clock+option-@.yaml" = "apprentice: {{apprentice}}"
+        "#};
+        let fs = fixture::from_toml(toml_data)?;
+
+        // When
+        application(Some(PathBuf::from("/")), None, false, &RunOptions::default().with_props_exec(true), &FunctionRegistry::default(), &fs).await?;
+
+        // Then
+        let content = fs.get_content(to_absolute_path("/workshop/clock.yaml")).await?;
+        assert_eq!(content, "apprentice: Lobsang of Lu-Tse in example and example\n");
+
+        Ok(())
+    }
+
+    #[test(tokio::test)]
+    async fn test_application_changelog() -> Result<()> {
+        // Given
+        let toml_data = indoc! {r#"
+            "CargoCult.toml" = '''
+            changelog-file = "docs/GENERATED.md"
+
+            [[psychotropic.cues]]
+            name = "example"
+            use-thundercloud = { directory = "{{PROJECT}}/example-thundercloud", features = ["glass"] }
+            '''
+
+            [yeth-marthter.example.invar]
+
+            [example-thundercloud]
+            "thundercloud.toml" = """
+            [niche]
+            name = "example"
+            """
+
+            [example-thundercloud.cumulus.workshop]
+            "clock+option-glass.yaml" = "sweeper: Lu Tse"
+        "#};
+        let fs = fixture::from_toml(toml_data)?;
+
+        // When
+        application(Some(PathBuf::from("/")), None, false, &RunOptions::default(), &FunctionRegistry::default(), &fs).await?;
+        application(Some(PathBuf::from("/")), None, false, &RunOptions::default(), &FunctionRegistry::default(), &fs).await?;
+
+        // Then
+        let changelog = fs.get_content(to_absolute_path("/docs/GENERATED.md")).await?;
+        assert!(changelog.starts_with("# Changelog of generated files\n\n## "), "Actual: {changelog}");
+        assert!(changelog.contains("\n### example\n\nThundercloud: example-thundercloud\n\n- workshop/clock.yaml\n"), "Actual: {changelog}");
+        // The second run leaves the unchanged file alone, so it is not recorded
+        assert_eq!(changelog.matches("\n## ").count(), 1, "Actual: {changelog}");
+
+        Ok(())
+    }
+
+    #[test(tokio::test)]
+    async fn test_application_changelog_enabled_by_run_options() -> Result<()> {
+        // Given
+        let toml_data = indoc! {r#"
+            "CargoCult.toml" = '''
+            [[psychotropic.cues]]
+            name = "example"
+            use-thundercloud = { directory = "{{PROJECT}}/example-thundercloud" }
+            '''
+
+            [yeth-marthter.example.invar]
+
+            [example-thundercloud]
+            "thundercloud.toml" = """
+            [niche]
+            name = "example"
+            """
+
+            [example-thundercloud.cumulus.workshop]
+            "clock.yaml" = "sweeper: Lu Tse"
+        "#};
+        let fs = fixture::from_toml(toml_data)?;
+        let run_options = RunOptions::default().with_changelog(true);
+
+        // When
+        application(Some(PathBuf::from("/")), None, false, &run_options, &FunctionRegistry::default(), &fs).await?;
+
+        // Then
+        let changelog = fs.get_content(to_absolute_path("/.igor/CHANGELOG.md")).await?;
+        assert!(changelog.contains("\n### example\n\nThundercloud: example-thundercloud\n\n- workshop/clock.yaml\n"), "Actual: {changelog}");
+
+        Ok(())
+    }
+
+    #[test(tokio::test)]
+    async fn test_application_requires_newer_igor() -> Result<()> {
+        // Given
+        let toml_data = indoc! {r#"
+            [project]
+            "CargoCult.toml" = '''
+            requires-igor = "99.0"
+            '''
+        "#};
+        let fs = fixture::from_toml(toml_data)?;
+
+        // When
+        let result = application(Some(PathBuf::from("/project")), None, false, &RunOptions::default(), &FunctionRegistry::default(), &fs).await;
+
+        // Then
+        let Err(error) = result else { panic!("Expected the run to fail") };
+        assert!(error.to_string().starts_with("The project requires Igor 99.0 or newer"), "Actual: {error}");
+        Ok(())
+    }
+
+    #[test(tokio::test)]
+    async fn test_application_thundercloud_cache() -> Result<()> {
+        // Given
+        let toml_data = indoc! {r#"
+            [project]
+            "CargoCult.toml" = '''
+            thundercloud-cache = "cache"
+
+            [[psychotropic.cues]]
+            name = "example"
+            use-thundercloud = { git-remote = { fetch-url = "https://github.com/rustigaan/example-thundercloud.git", revision = "main" } }
+
+            [[psychotropic.cues]]
+            name = "local"
+            use-thundercloud = { directory = "thunderclouds/local" }
+            '''
+
+            [project.yeth-marthter.example.invar]
+            [project.yeth-marthter.local.invar]
+
+            [project.cache.example-thundercloud-5bb4b95445dd]
+            "thundercloud.toml" = """
+            [niche]
+            name = "example"
+            """
+
+            [project.cache.example-thundercloud-5bb4b95445dd.cumulus.workshop]
+            "clock+option-@.yaml" = "sweeper: Lu Tse"
+
+            [project.thunderclouds.local]
+            "thundercloud.toml" = """
+            [niche]
+            name = "local"
+            """
+
+            [project.thunderclouds.local.cumulus.workshop]
+            "mirror+option-@.yaml" = "frame: wood"
+        "#};
+        let fs = fixture::from_toml(toml_data)?;
+
+        // When
+        application(Some(PathBuf::from("/project")), None, false, &RunOptions::default(), &FunctionRegistry::default(), &fs).await?;
+
+        // Then
+        assert_eq!(fs.get_content(to_absolute_path("/project/workshop/clock.yaml")).await?, "sweeper: Lu Tse\n");
+        assert_eq!(fs.get_content(to_absolute_path("/project/workshop/mirror.yaml")).await?, "frame: wood\n");
+
+        Ok(())
+    }
+
     fn create_file_system_fixture() -> Result<impl FileSystem> {
         let toml_data = indoc! {r#"
             "CargoCult.toml" = '''
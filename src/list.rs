@@ -0,0 +1,121 @@
+use std::path::PathBuf;
+use anyhow::Result;
+use crate::config_model::{NicheDescription, NicheTriggers, PropDeclaration, ProjectConfig, PsychotropicConfig, ThundercloudConfig};
+use crate::file_system::{FileSystem, PathType};
+use crate::niche::{get_thundercloud_directory, thundercloud_file_system, UseThundercloudDefaults};
+use crate::path::AbsolutePath;
+use crate::{get_project_config, get_project_root, get_use_thundercloud, thundercloud};
+
+pub async fn list<FS: FileSystem>(project_root_option: Option<PathBuf>, fs: &FS) -> Result<()> {
+    let project_root = get_project_root(project_root_option)?;
+    for listing in list_niches(&project_root, fs).await? {
+        print!("{listing}");
+    }
+    Ok(())
+}
+
+/// Describes every niche of the project (sorted by name) with the props that its thundercloud declares. Thunderclouds
+/// that are not available (for instance because they were not fetched yet) are listed without props.
+async fn list_niches<FS: FileSystem>(project_root: &AbsolutePath, fs: &FS) -> Result<Vec<String>> {
+    let project_config = get_project_config(project_root, fs).await?;
+    let psychotropic = project_config.psychotropic()?;
+    let use_thundercloud_defaults = UseThundercloudDefaults::new(project_root, &project_config);
+    let mut niches = psychotropic.values();
+    niches.sort_by_key(NicheTriggers::name);
+    let mut listings = Vec::new();
+    for niche_triggers in niches {
+        let name = niche_triggers.name();
+        let Some(use_thundercloud) = get_use_thundercloud(Some(&niche_triggers), &use_thundercloud_defaults, fs).await? else { continue };
+        let directory = match get_thundercloud_directory(project_root, &use_thundercloud)? {
+            Some(directory) if fs.path_type(&directory).await != PathType::Missing => directory,
+            _ => {
+                listings.push(format!("{name}: thundercloud not available\n"));
+                continue;
+            },
+        };
+        let thundercloud_fs = thundercloud_file_system(project_root, &use_thundercloud, fs.clone()).await?;
+        if thundercloud_fs.path_type(&directory).await != PathType::Directory {
+            listings.push(format!("{name}: thundercloud not available\n"));
+            continue;
+        }
+        let config = thundercloud::get_config(&directory, thundercloud_fs).await?;
+        listings.push(render(&name, config.niche().description(), config.prop_declarations()));
+    }
+    Ok(listings)
+}
+
+fn render(name: &str, description: Option<&str>, declarations: &[PropDeclaration]) -> String {
+    let mut listing = match description {
+        Some(description) => format!("{name}: {description}\n"),
+        None => format!("{name}\n"),
+    };
+    for declaration in declarations {
+        let mut qualifiers = Vec::new();
+        if let Some(prop_type) = declaration.prop_type() {
+            qualifiers.push(prop_type.to_string());
+        }
+        if declaration.required() {
+            qualifiers.push("required".to_string());
+        }
+        listing.push_str(&format!("  {}", declaration.name()));
+        if !qualifiers.is_empty() {
+            listing.push_str(&format!(" ({})", qualifiers.join(", ")));
+        }
+        if let Some(description) = declaration.description() {
+            listing.push_str(&format!(": {description}"));
+        }
+        listing.push('\n');
+    }
+    listing
+}
+
+#[cfg(test)]
+mod test {
+    use indoc::indoc;
+    use test_log::test;
+    use crate::file_system::fixture;
+    use super::*;
+
+    #[test(tokio::test)]
+    async fn list_niches_with_props() -> Result<()> {
+        // Given
+        let toml_data = indoc! {r#"
+            "CargoCult.toml" = '''
+            [[psychotropic.cues]]
+            name = "example"
+            use-thundercloud = { directory = "{{PROJECT}}/example-thundercloud" }
+
+            [[psychotropic.cues]]
+            name = "remote"
+            use-thundercloud = { directory = "{{PROJECT}}/remote-thundercloud" }
+            '''
+
+            [example-thundercloud]
+            "thundercloud.toml" = """
+            [niche]
+            name = "example"
+            description = "Clock parts"
+
+            [[props]]
+            name = "sweeper"
+            type = "String"
+            required = true
+            description = "Who keeps the clock clean"
+
+            [[props]]
+            name = "hours"
+            """
+        "#};
+        let fs = fixture::from_toml(toml_data)?;
+
+        // When
+        let listings = list_niches(&AbsolutePath::root(), &fs).await?;
+
+        // Then
+        assert_eq!(listings, vec![
+            "example: Clock parts\n  sweeper (String, required): Who keeps the clock clean\n  hours\n".to_string(),
+            "remote: thundercloud not available\n".to_string(),
+        ]);
+        Ok(())
+    }
+}
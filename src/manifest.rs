@@ -0,0 +1,242 @@
+use std::collections::{BTreeMap, BTreeSet};
+use anyhow::{anyhow, Result};
+use log::{debug, info, warn};
+use serde::{Deserialize, Serialize};
+use crate::config_model::WriteMode;
+use crate::file_system::{FileSystem, PathType, TargetFile};
+use crate::path::{AbsolutePath, RelativePath};
+use crate::NicheName;
+
+/// Records the files that Igor generated for a niche, along with a hash of their content, so that they can be removed
+/// later, unless they were modified since. A symbolic link is recorded with the path that it points to instead, so that
+/// links to directories and dangling links can be recorded as well.
+#[derive(Deserialize, Serialize, Debug, Clone, Default, PartialEq)]
+pub struct Manifest {
+    #[serde(default)]
+    files: BTreeMap<String, String>,
+}
+
+impl Manifest {
+    pub async fn load<FS: FileSystem>(manifest_path: &AbsolutePath, fs: &FS) -> Result<Manifest> {
+        if fs.path_type(manifest_path).await != PathType::File {
+            return Ok(Manifest::default());
+        }
+        let body = fs.get_content(manifest_path.clone()).await?;
+        Ok(toml::from_str(&body)?)
+    }
+
+    pub async fn save<FS: FileSystem>(&self, manifest_path: &AbsolutePath, fs: &FS) -> Result<()> {
+        if self.files.is_empty() {
+            if fs.path_type(manifest_path).await == PathType::File {
+                fs.remove_file(manifest_path).await?;
+            }
+            return Ok(());
+        }
+        // Write next to the manifest and rename, so that an interrupted run never leaves half a manifest
+        let body = toml::to_string(self)?;
+        let staging_path = AbsolutePath::try_new(manifest_path.with_extension("toml.new"))?;
+        let mut target_file = fs.open_target(staging_path.clone(), WriteMode::Overwrite, false).await?
+            .ok_or_else(|| anyhow!("Could not write manifest: {:?}", manifest_path))?;
+        for line in body.lines() {
+            target_file.write_line(line).await?;
+        }
+        target_file.close().await?;
+        fs.rename(&staging_path, manifest_path).await
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.files.is_empty()
+    }
+
+    /// Records the current content of a generated file (or the current target of a generated symbolic link). The path
+    /// is relative to the project root.
+    pub async fn add<FS: FileSystem>(&mut self, project_root: &AbsolutePath, path: &str, fs: &FS) -> Result<()> {
+        let content_hash = entry_hash(&AbsolutePath::new(path, project_root), fs).await?;
+        self.files.insert(path.to_string(), content_hash);
+        Ok(())
+    }
+
+    /// Handles the generated files that are not among the target files of this run (like the target of an option that
+    /// was renamed). Orphans that are gone are forgotten. With `prune`, orphans that were not modified since they were
+    /// generated are removed as well. Returns the paths of the removed orphans and of the remaining orphans.
+    pub async fn prune_orphans<FS: FileSystem>(&mut self, project_root: &AbsolutePath, targets: &BTreeSet<String>, prune: bool, fs: &FS) -> Result<(Vec<String>, Vec<String>)> {
+        let mut removed = Vec::new();
+        let mut remaining = Vec::new();
+        let orphans: Vec<String> = self.files.keys().filter(|path| !targets.contains(*path)).cloned().collect();
+        for path in orphans {
+            let target = AbsolutePath::new(path.as_str(), project_root);
+            if !exists(&target, fs).await {
+                debug!("Orphan is gone: {:?}", &target);
+                self.files.remove(&path);
+            } else if prune && !self.is_modified(&path, &target, fs).await? {
+                info!("Remove orphan: {:?}", &target);
+                fs.remove_file(&target).await?;
+                self.files.remove(&path);
+                removed.push(path);
+            } else {
+                remaining.push(path);
+            }
+        }
+        Ok((removed, remaining))
+    }
+
+    /// Removes the generated files that were not modified since they were generated (or all generated files if
+    /// `force` is set) and returns their paths. Modified files remain in the manifest.
+    pub async fn clean<FS: FileSystem>(&mut self, project_root: &AbsolutePath, force: bool, fs: &FS) -> Result<Vec<String>> {
+        let mut removed = Vec::new();
+        let mut remaining = BTreeMap::new();
+        for (path, file_hash) in &self.files {
+            let target = AbsolutePath::new(path.as_str(), project_root);
+            if !exists(&target, fs).await {
+                debug!("Generated file is gone: {:?}", &target);
+                continue;
+            }
+            if !force && self.is_modified(path, &target, fs).await? {
+                warn!("Keep modified file: {:?}", &target);
+                remaining.insert(path.clone(), file_hash.clone());
+                continue;
+            }
+            info!("Remove: {:?}", &target);
+            fs.remove_file(&target).await?;
+            removed.push(path.clone());
+        }
+        self.files = remaining;
+        Ok(removed)
+    }
+
+    async fn is_modified<FS: FileSystem>(&self, path: &str, target: &AbsolutePath, fs: &FS) -> Result<bool> {
+        let content_hash = entry_hash(target, fs).await?;
+        Ok(self.files.get(path) != Some(&content_hash))
+    }
+}
+
+/// Whether a generated file or symbolic link (even one that points to a directory or nowhere) is still there.
+async fn exists<FS: FileSystem>(target: &AbsolutePath, fs: &FS) -> bool {
+    fs.symlink_metadata(target).await
+        .is_ok_and(|metadata| matches!(metadata.path_type(), PathType::File | PathType::Symlink))
+}
+
+/// Returns the hash of the content of a file, or `symlink:` followed by the path that a symbolic link points to. A link
+/// is not followed, because it may point to a directory or to nothing at all.
+async fn entry_hash<FS: FileSystem>(target: &AbsolutePath, fs: &FS) -> Result<String> {
+    if fs.symlink_metadata(target).await?.path_type() == PathType::Symlink {
+        let link_target = fs.read_link(target).await?;
+        return Ok(format!("symlink:{}", link_target.to_string_lossy()));
+    }
+    fs.content_hash(target.clone()).await
+}
+
+pub fn get_manifest_path(project_root: &AbsolutePath, niches_directory: &RelativePath, niche: &NicheName) -> AbsolutePath {
+    let absolute_niches_directory = AbsolutePath::new(niches_directory.as_path(), project_root);
+    let mut manifest_path = AbsolutePath::new(niche.to_str(), &absolute_niches_directory);
+    manifest_path.push("manifest.toml");
+    manifest_path
+}
+
+#[cfg(test)]
+mod test {
+    use indoc::indoc;
+    use sha2::{Digest, Sha256};
+    use test_log::test;
+    use crate::file_system::fixture;
+    use crate::path::test_utils::to_absolute_path;
+    use super::*;
+
+    fn hash(content: &str) -> String {
+        format!("{:x}", Sha256::digest(content))
+    }
+
+    #[test(tokio::test)]
+    async fn add_save_and_clean() -> Result<()> {
+        // Given
+        let toml_data = indoc! {r#"
+            [workshop]
+            "clock.yaml" = "sweeper: Lu Tse"
+            "mirror.yaml" = "frame: wood"
+            "spring.yaml" = "material: glass"
+        "#};
+        let fs = fixture::from_toml(toml_data)?;
+        let project_root = AbsolutePath::root();
+        let manifest_path = to_absolute_path("/yeth-marthter/example/manifest.toml");
+        let mut manifest = Manifest::default();
+        for path in ["workshop/clock.yaml", "workshop/mirror.yaml", "workshop/spring.yaml"] {
+            manifest.add(&project_root, path, &fs).await?;
+        }
+        manifest.save(&manifest_path, &fs).await?;
+        let mut mirror = fs.open_target(to_absolute_path("/workshop/mirror.yaml"), WriteMode::Overwrite, false).await?.unwrap();
+        mirror.write_line("frame: silver").await?;
+        mirror.close().await?;
+        fs.remove_file(&to_absolute_path("/workshop/spring.yaml")).await?;
+
+        // When
+        let mut manifest = Manifest::load(&manifest_path, &fs).await?;
+        let removed = manifest.clean(&project_root, false, &fs).await?;
+        manifest.save(&manifest_path, &fs).await?;
+
+        // Then
+        assert_eq!(removed, vec!["workshop/clock.yaml".to_string()]);
+        assert_eq!(fs.path_type(&to_absolute_path("/workshop/clock.yaml")).await, PathType::Missing);
+        assert_eq!(fs.path_type(&to_absolute_path("/workshop/mirror.yaml")).await, PathType::File);
+        let manifest = Manifest::load(&manifest_path, &fs).await?;
+        assert_eq!(manifest.files.keys().collect::<Vec<_>>(), vec!["workshop/mirror.yaml"]);
+
+        Ok(())
+    }
+
+    #[test(tokio::test)]
+    async fn clean_with_force() -> Result<()> {
+        // Given
+        let toml_data = indoc! {r#"
+            [workshop]
+            "mirror.yaml" = "frame: silver"
+        "#};
+        let fs = fixture::from_toml(toml_data)?;
+        let project_root = AbsolutePath::root();
+        let manifest_path = to_absolute_path("/yeth-marthter/example/manifest.toml");
+        let mut manifest = Manifest::default();
+        manifest.files.insert("workshop/mirror.yaml".to_string(), hash("frame: wood\n"));
+        manifest.save(&manifest_path, &fs).await?;
+
+        // When
+        let removed = manifest.clean(&project_root, true, &fs).await?;
+        manifest.save(&manifest_path, &fs).await?;
+
+        // Then
+        assert_eq!(removed, vec!["workshop/mirror.yaml".to_string()]);
+        assert_eq!(fs.path_type(&to_absolute_path("/workshop/mirror.yaml")).await, PathType::Missing);
+        assert_eq!(fs.path_type(&manifest_path).await, PathType::Missing);
+
+        Ok(())
+    }
+
+    #[test(tokio::test)]
+    async fn prune_orphans() -> Result<()> {
+        // Given
+        let toml_data = indoc! {r#"
+            [workshop]
+            "clock.yaml" = "sweeper: Lu Tse"
+            "old-clock.yaml" = "sweeper: Lu Tse"
+            "old-mirror.yaml" = "frame: silver"
+        "#};
+        let fs = fixture::from_toml(toml_data)?;
+        let project_root = AbsolutePath::root();
+        let mut manifest = Manifest::default();
+        manifest.files.insert("workshop/clock.yaml".to_string(), hash("sweeper: Lu Tse\n"));
+        manifest.files.insert("workshop/old-clock.yaml".to_string(), hash("sweeper: Lu Tse\n"));
+        manifest.files.insert("workshop/old-mirror.yaml".to_string(), hash("frame: wood\n"));
+        manifest.files.insert("workshop/old-spring.yaml".to_string(), hash("material: glass\n"));
+        let targets = BTreeSet::from(["workshop/clock.yaml".to_string()]);
+
+        // When
+        let reported = manifest.clone().prune_orphans(&project_root, &targets, false, &fs).await?;
+        let pruned = manifest.prune_orphans(&project_root, &targets, true, &fs).await?;
+
+        // Then
+        assert_eq!(reported, (vec![], vec!["workshop/old-clock.yaml".to_string(), "workshop/old-mirror.yaml".to_string()]));
+        assert_eq!(pruned, (vec!["workshop/old-clock.yaml".to_string()], vec!["workshop/old-mirror.yaml".to_string()]));
+        assert_eq!(fs.path_type(&to_absolute_path("/workshop/old-clock.yaml")).await, PathType::Missing);
+        assert_eq!(fs.path_type(&to_absolute_path("/workshop/old-mirror.yaml")).await, PathType::File);
+        assert_eq!(manifest.files.keys().collect::<Vec<_>>(), vec!["workshop/clock.yaml", "workshop/old-mirror.yaml"]);
+        Ok(())
+    }
+}
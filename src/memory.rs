@@ -0,0 +1,142 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use log::{debug, info};
+
+/// Igor stops starting niches when the tracked memory reaches this percentage of the ceiling.
+const NEAR_CEILING_PERCENT: usize = 90;
+
+/// Time between two checks whether a niche that waits for memory may start.
+const POLL_INTERVAL: Duration = Duration::from_millis(20);
+
+const BYTES_PER_MIB: usize = 1024 * 1024;
+
+/// Approximate bookkeeping of the memory that buffered file contents and bolt indexes use in a run, along with the number
+/// of niches that are in flight. Only the memory that is tracked explicitly counts, so the actual use of the process is
+/// higher.
+#[derive(Debug, Default)]
+pub struct MemoryBudget {
+    used: AtomicUsize,
+    /// Zero means that there is no ceiling.
+    ceiling: AtomicUsize,
+    niches: AtomicUsize,
+}
+
+impl MemoryBudget {
+    pub fn set_ceiling(&self, ceiling: Option<usize>) {
+        self.ceiling.store(ceiling.unwrap_or(0), Ordering::SeqCst);
+    }
+
+    pub fn used(&self) -> usize {
+        self.used.load(Ordering::SeqCst)
+    }
+
+    /// Counts the bytes as used until the returned guard is dropped.
+    pub fn track(self: &Arc<Self>, bytes: usize) -> MemoryGuard {
+        self.used.fetch_add(bytes, Ordering::SeqCst);
+        MemoryGuard { budget: self.clone(), bytes: AtomicUsize::new(bytes) }
+    }
+
+    /// Counts a niche as in flight until the returned guard is dropped.
+    pub fn start_niche(self: &Arc<Self>) -> NicheGuard {
+        self.niches.fetch_add(1, Ordering::SeqCst);
+        NicheGuard { budget: self.clone() }
+    }
+
+    pub fn is_near_ceiling(&self) -> bool {
+        let ceiling = self.ceiling.load(Ordering::SeqCst);
+        ceiling > 0 && self.used().saturating_mul(100) >= ceiling.saturating_mul(NEAR_CEILING_PERCENT)
+    }
+
+    /// A niche may start if the memory is not near the ceiling, or if no other niche is in flight (so that a run
+    /// always makes progress, even if a single niche exceeds the ceiling).
+    pub fn may_start_niche(&self) -> bool {
+        !self.is_near_ceiling() || self.niches.load(Ordering::SeqCst) == 0
+    }
+}
+
+/// Memory that is counted as used. It is released when the guard is dropped.
+#[derive(Debug)]
+pub struct MemoryGuard {
+    budget: Arc<MemoryBudget>,
+    bytes: AtomicUsize,
+}
+
+impl MemoryGuard {
+    /// Counts more bytes as used, for buffers that grow.
+    pub fn add(&self, bytes: usize) {
+        self.bytes.fetch_add(bytes, Ordering::SeqCst);
+        self.budget.used.fetch_add(bytes, Ordering::SeqCst);
+    }
+}
+
+impl Drop for MemoryGuard {
+    fn drop(&mut self) {
+        self.budget.used.fetch_sub(*self.bytes.get_mut(), Ordering::SeqCst);
+    }
+}
+
+/// A niche that is in flight. It is no longer counted when the guard is dropped.
+#[derive(Debug)]
+pub struct NicheGuard {
+    budget: Arc<MemoryBudget>,
+}
+
+impl Drop for NicheGuard {
+    fn drop(&mut self) {
+        self.budget.niches.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// Sets the memory ceiling of a run to the override or else the configured value (both in MiB).
+pub fn configure_memory_ceiling(budget: &MemoryBudget, override_mib: Option<usize>, configured_mib: Option<usize>) {
+    let mib = override_mib.or(configured_mib);
+    if let Some(mib) = mib {
+        info!("Memory ceiling: {mib} MiB");
+    }
+    budget.set_ceiling(mib.map(|mib| mib.saturating_mul(BYTES_PER_MIB)));
+}
+
+/// Waits until the memory that is in use leaves room to start another niche.
+pub async fn wait_for_memory(budget: &MemoryBudget, niche: &str) {
+    if budget.may_start_niche() {
+        return;
+    }
+    info!("Memory ceiling nearly reached ({} bytes in use): niche waits for other niches to finish: {niche:?}", budget.used());
+    while !budget.may_start_niche() {
+        tokio::time::sleep(POLL_INTERVAL).await;
+    }
+    debug!("Memory available for niche: {niche:?}");
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn throttle_near_ceiling() {
+        // Given
+        let budget = Arc::new(MemoryBudget::default());
+        budget.set_ceiling(Some(1000));
+        let niche = budget.start_niche();
+
+        // When
+        let buffer = budget.track(500);
+        buffer.add(400);
+
+        // Then
+        assert_eq!(budget.used(), 900);
+        assert!(budget.is_near_ceiling());
+        assert!(!budget.may_start_niche());
+        drop(niche);
+        assert!(budget.may_start_niche());
+        let niche = budget.start_niche();
+        drop(buffer);
+        assert_eq!(budget.used(), 0);
+        assert!(budget.may_start_niche());
+        budget.set_ceiling(None);
+        let _large = budget.track(usize::MAX / 200);
+        assert!(!budget.is_near_ceiling());
+        drop(niche);
+    }
+}
@@ -0,0 +1,141 @@
+/// The result of a three-way merge: the merged content and the number of conflicts in it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Merge {
+    pub content: String,
+    pub conflicts: usize,
+}
+
+/// Merges the changes from `base` to `ours` and from `base` to `theirs`, line by line (like `diff3`). Where both sides
+/// changed the same lines differently, the merged content gets both versions between conflict markers (`<<<<<<<`,
+/// `=======` and `>>>>>>>`).
+pub fn merge(base: &str, ours: &str, theirs: &str, our_label: &str, their_label: &str) -> Merge {
+    let base: Vec<&str> = base.lines().collect();
+    let ours: Vec<&str> = ours.lines().collect();
+    let theirs: Vec<&str> = theirs.lines().collect();
+    let our_matches = matching_lines(&base, &ours);
+    let their_matches = matching_lines(&base, &theirs);
+    let mut content = String::new();
+    let mut push_lines = |lines: &[&str]| lines.iter().for_each(|line| {
+        content.push_str(line);
+        content.push('\n');
+    });
+    let mut conflicts = 0;
+    let (mut base_cursor, mut our_cursor, mut their_cursor) = (0, 0, 0);
+    // Lines of the base that both sides kept are stable: the chunks between them are merged
+    let stable = (0..base.len())
+        .filter_map(|index| Some((index, our_matches[index]?, their_matches[index]?)))
+        .chain([(base.len(), ours.len(), theirs.len())]);
+    for (base_index, our_index, their_index) in stable {
+        let base_chunk = &base[base_cursor..base_index];
+        let our_chunk = &ours[our_cursor..our_index];
+        let their_chunk = &theirs[their_cursor..their_index];
+        if our_chunk == base_chunk || our_chunk == their_chunk {
+            push_lines(their_chunk);
+        } else if their_chunk == base_chunk {
+            push_lines(our_chunk);
+        } else {
+            conflicts += 1;
+            push_lines(&[format!("<<<<<<< {our_label}").as_str()]);
+            push_lines(our_chunk);
+            push_lines(&["======="]);
+            push_lines(their_chunk);
+            push_lines(&[format!(">>>>>>> {their_label}").as_str()]);
+        }
+        if base_index < base.len() {
+            push_lines(&base[base_index..=base_index]);
+        }
+        (base_cursor, our_cursor, their_cursor) = (base_index + 1, our_index + 1, their_index + 1);
+    }
+    Merge { content, conflicts }
+}
+
+/// For each line of `base`, the index of the same line in `other` according to a longest common subsequence, if any.
+/// The common prefix and suffix are matched up front, to keep the table small when few lines changed.
+fn matching_lines(base: &[&str], other: &[&str]) -> Vec<Option<usize>> {
+    let mut matches = vec![None; base.len()];
+    let prefix = base.iter().zip(other).take_while(|(left, right)| left == right).count();
+    let suffix = base[prefix..].iter().rev().zip(other[prefix..].iter().rev()).take_while(|(left, right)| left == right).count();
+    for (index, line_match) in matches.iter_mut().enumerate().take(prefix) {
+        *line_match = Some(index);
+    }
+    for offset in 1..=suffix {
+        matches[base.len() - offset] = Some(other.len() - offset);
+    }
+    let base_middle = &base[prefix..base.len() - suffix];
+    let other_middle = &other[prefix..other.len() - suffix];
+    let (rows, columns) = (base_middle.len(), other_middle.len());
+    // lengths[i][j] is the length of the longest common subsequence of base_middle[i..] and other_middle[j..]
+    let mut lengths = vec![vec![0u32; columns + 1]; rows + 1];
+    for i in (0..rows).rev() {
+        for j in (0..columns).rev() {
+            lengths[i][j] = if base_middle[i] == other_middle[j] {
+                lengths[i + 1][j + 1] + 1
+            } else {
+                lengths[i + 1][j].max(lengths[i][j + 1])
+            };
+        }
+    }
+    let (mut i, mut j) = (0, 0);
+    while i < rows && j < columns {
+        if base_middle[i] == other_middle[j] {
+            matches[prefix + i] = Some(prefix + j);
+            i += 1;
+            j += 1;
+        } else if lengths[i + 1][j] >= lengths[i][j + 1] {
+            i += 1;
+        } else {
+            j += 1;
+        }
+    }
+    matches
+}
+
+#[cfg(test)]
+mod test {
+    use indoc::indoc;
+    use super::*;
+
+    #[test]
+    fn merge_independent_changes() {
+        // Given
+        let base = "sweeper: Lu Tse\nclock:\n  hours: 10\n  spring: coiled\nmirror: polished\n";
+        let ours = "sweeper: Lu Tse\napprentice: Lobsang\nclock:\n  hours: 10\n  spring: coiled\nmirror: polished\n";
+        let theirs = "sweeper: Lu Tse\nclock:\n  hours: 12\n  spring: coiled\n";
+
+        // When
+        let merge = merge(base, ours, theirs, "project", "thundercloud");
+
+        // Then
+        let expected = indoc! {"
+            sweeper: Lu Tse
+            apprentice: Lobsang
+            clock:
+              hours: 12
+              spring: coiled
+        "};
+        assert_eq!(merge, Merge { content: expected.to_string(), conflicts: 0 });
+    }
+
+    #[test]
+    fn merge_conflict() {
+        // Given
+        let base = "clock:\n  hours: 10\n  spring: coiled\n";
+        let ours = "clock:\n  hours: 24\n  spring: coiled\n";
+        let theirs = "clock:\n  hours: 12\n  spring: coiled\n";
+
+        // When
+        let merge = merge(base, ours, theirs, "project", "thundercloud");
+
+        // Then
+        let expected = indoc! {"
+            clock:
+            <<<<<<< project
+              hours: 24
+            =======
+              hours: 12
+            >>>>>>> thundercloud
+              spring: coiled
+        "};
+        assert_eq!(merge, Merge { content: expected.to_string(), conflicts: 1 });
+    }
+}
@@ -0,0 +1,153 @@
+use std::path::PathBuf;
+use anyhow::{anyhow, Result};
+use indoc::indoc;
+use log::info;
+use toml::{Table, Value};
+use crate::config_model::WriteMode;
+use crate::file_system::{FileSystem, TargetFile};
+use crate::path::AbsolutePath;
+
+const EXAMPLE_OPTION: &str = indoc! {r#"
+    ---
+    sweeper: "{{sweeper}}"
+    parts:
+      - pendulum
+    # ==== BEGIN FRAGMENT glass-spring ====
+      - steel spring
+    # ==== END FRAGMENT glass-spring ====
+"#};
+
+const EXAMPLE_FRAGMENT: &str = indoc! {r#"
+    # ==== BEGIN FRAGMENT glass-spring ====
+      - glass spring
+    # ==== END FRAGMENT glass-spring ====
+"#};
+
+const EXAMPLE_CONFIG: &str = indoc! {r#"
+    write-mode = "WriteNew"
+"#};
+
+const EXAMPLE_INVAR_CONFIG: &str = indoc! {r#"
+    [props]
+    sweeper = "Lobsang"
+"#};
+
+const EXAMPLE_INVAR_README: &str = indoc! {r#"
+    # Example invar
+
+    Projects that use this thundercloud can override its files, fragments and configuration in the invar directory of
+    their niche (`yeth-marthter/NICHE/invar`). Copy the files in this directory to get started.
+"#};
+
+/// Creates a skeleton thundercloud in the given directory. Existing files are left alone.
+pub async fn new_thundercloud<FS: FileSystem>(directory: PathBuf, name_option: Option<String>, description_option: Option<String>, fs: &FS) -> Result<()> {
+    let directory = AbsolutePath::new(directory, &AbsolutePath::current_dir()?);
+    for (path, created) in scaffold(&directory, name_option, description_option, fs).await? {
+        let state = if created { "Created" } else { "Exists " };
+        println!("{state} {}", path.to_string_lossy());
+    }
+    Ok(())
+}
+
+async fn scaffold<FS: FileSystem>(directory: &AbsolutePath, name_option: Option<String>, description_option: Option<String>, fs: &FS) -> Result<Vec<(AbsolutePath, bool)>> {
+    let name = match name_option {
+        Some(name) => name,
+        None => directory.file_name().map(|name| name.to_string_lossy().to_string())
+            .ok_or_else(|| anyhow!("Cannot derive a niche name from: {:?}", directory))?,
+    };
+    let description = description_option.unwrap_or_else(|| format!("Thundercloud for niche {name:?}"));
+    let files = [
+        ("thundercloud.toml", thundercloud_toml(&name, &description)?),
+        ("cumulus/workshop/clock+option-@.yaml", EXAMPLE_OPTION.to_string()),
+        ("cumulus/workshop/clock+fragment-glass-spring.yaml", EXAMPLE_FRAGMENT.to_string()),
+        ("cumulus/workshop/clock+config-@.yaml.toml", EXAMPLE_CONFIG.to_string()),
+        ("example-invar/README.md", EXAMPLE_INVAR_README.to_string()),
+        ("example-invar/workshop/clock+config-@.yaml.toml", EXAMPLE_INVAR_CONFIG.to_string()),
+    ];
+    let mut result = Vec::new();
+    for (path, content) in files {
+        let path = AbsolutePath::new(path, directory);
+        let created = write_new(&path, &content, fs).await?;
+        result.push((path, created));
+    }
+    Ok(result)
+}
+
+fn thundercloud_toml(name: &str, description: &str) -> Result<String> {
+    let mut niche = Table::new();
+    niche.insert("name".to_string(), Value::String(name.to_string()));
+    niche.insert("description".to_string(), Value::String(description.to_string()));
+    let mut props = Table::new();
+    props.insert("sweeper".to_string(), Value::String("Lu Tse".to_string()));
+    let mut invar_defaults = Table::new();
+    invar_defaults.insert("props".to_string(), Value::Table(props));
+    let mut document = Table::new();
+    document.insert("niche".to_string(), Value::Table(niche));
+    document.insert("invar-defaults".to_string(), Value::Table(invar_defaults));
+    Ok(toml::to_string(&document)?)
+}
+
+async fn write_new<FS: FileSystem>(path: &AbsolutePath, content: &str, fs: &FS) -> Result<bool> {
+    let Some(mut target_file) = fs.open_target(path.clone(), WriteMode::WriteNew, false).await? else {
+        info!("Exists: {:?}", path);
+        return Ok(false);
+    };
+    for line in content.lines() {
+        target_file.write_line(line).await?;
+    }
+    target_file.close().await?;
+    Ok(true)
+}
+
+#[cfg(test)]
+mod test {
+    use test_log::test;
+    use crate::config_model::{thundercloud_config, NicheDescription, ThundercloudConfig};
+    use crate::file_system::{fixture, ConfigFormat};
+    use crate::path::test_utils::to_absolute_path;
+    use super::*;
+
+    #[test(tokio::test)]
+    async fn scaffold_thundercloud() -> Result<()> {
+        // Given
+        let toml_data = indoc! {r#"
+            [clock-thundercloud]
+            "thundercloud.toml" = '''
+            [niche]
+            name = "hand-crafted"
+            '''
+        "#};
+        let fs = fixture::from_toml(toml_data)?;
+        let directory = to_absolute_path("/clock-thundercloud");
+
+        // When
+        let result = scaffold(&directory, None, None, &fs).await?;
+
+        // Then
+        let created: Vec<_> = result.iter().filter(|(_, created)| *created).map(|(path, _)| path.clone()).collect();
+        assert_eq!(created.len(), 5);
+        assert!(created.contains(&to_absolute_path("/clock-thundercloud/cumulus/workshop/clock+option-@.yaml")));
+        let existing = fs.get_content(to_absolute_path("/clock-thundercloud/thundercloud.toml")).await?;
+        assert!(existing.contains("hand-crafted"));
+        Ok(())
+    }
+
+    #[test(tokio::test)]
+    async fn scaffold_thundercloud_config() -> Result<()> {
+        // Given
+        let fs = fixture::from_toml("")?;
+        let directory = to_absolute_path("/clock-thundercloud");
+
+        // When
+        scaffold(&directory, Some("clock".to_string()), None, &fs).await?;
+
+        // Then
+        let body = fs.get_content(to_absolute_path("/clock-thundercloud/thundercloud.toml")).await?;
+        let config = thundercloud_config::from_str(&body, ConfigFormat::TOML)?;
+        assert_eq!(config.niche().name(), "clock");
+        assert_eq!(config.niche().description(), Some("Thundercloud for niche \"clock\""));
+        let fragment = fs.get_content(to_absolute_path("/clock-thundercloud/cumulus/workshop/clock+fragment-glass-spring.yaml")).await?;
+        assert_eq!(fragment, EXAMPLE_FRAGMENT);
+        Ok(())
+    }
+}
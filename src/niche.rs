@@ -1,52 +1,269 @@
-use anyhow::Result;
-use log::{debug, info};
+use std::collections::BTreeSet;
+use std::path::PathBuf;
+use anyhow::{bail, Result};
+use log::{debug, info, warn};
 use toml::{Table, Value};
-use crate::config_model::{InvarConfig, UseThundercloudConfig};
-use crate::file_system::FileSystem;
-use crate::{interpolate, NicheName};
+use sha2::{Digest, Sha256};
+use crate::config_model::{invar_config, GitRemoteConfig, InvarConfig, ProjectConfig, ThunderConfig, UseThundercloudConfig};
+use crate::file_system::{ArchiveFileSystem, FileSystem, PathType};
+use crate::functions::FunctionRegistry;
+use crate::run_options::RunOptions;
+use crate::{fetch, interpolate, prompt, NicheName};
 use crate::thundercloud;
+use crate::thundercloud::Explanation;
 use crate::path::{AbsolutePath, RelativePath};
+use crate::manifest::{get_manifest_path, Manifest};
+use crate::report::{FileAction, NicheReporter};
 
-pub async fn process_niche<UT: UseThundercloudConfig, FS: FileSystem, IC: InvarConfig>(project_root: AbsolutePath, niches_directory: RelativePath, niche: NicheName, use_thundercloud: UT, invar_config_default: IC, fs: FS) -> Result<()> {
-    if let Some(directory) = use_thundercloud.directory() {
-        info!("Directory: {directory:?}");
+#[allow(clippy::too_many_arguments)]
+pub async fn process_niche<UT: UseThundercloudConfig, FS: FileSystem, IC: InvarConfig>(project_root: AbsolutePath, niches_directory: RelativePath, niche: NicheName, use_thundercloud: UT, invar_config_default: IC, fs: FS, run_options: RunOptions, functions: FunctionRegistry, niche_reporter: NicheReporter) -> Result<()> {
+    let manifest_path = get_manifest_path(&project_root, &niches_directory, &niche);
+    let answers_path = prompt::get_answers_path(&project_root, &niches_directory, &niche);
+    download_missing_thundercloud(&project_root, &use_thundercloud, &run_options, &fs).await?;
+    let mut answers = prompt::load_answers(&answers_path, &fs).await?;
+    if prompt::is_input_enabled(&run_options) {
+        let new_answers = ask_missing_props(project_root.clone(), niches_directory.clone(), niche.clone(), &use_thundercloud, with_answers(answers.clone(), invar_config_default.clone()), &run_options, fs.clone()).await?;
+        if !new_answers.is_empty() {
+            prompt::save_answers(&answers_path, new_answers.clone(), &fs).await?;
+            answers.extend(new_answers);
+        }
+    }
+    let invar_config_default = with_answers(answers, invar_config_default);
+    let Some(thunder_config) = get_thunder_config(project_root.clone(), niches_directory, niche, &use_thundercloud, invar_config_default, fs.clone()).await? else { return Ok(()) };
+    thundercloud::process_niche(thunder_config, run_options, functions, niche_reporter.clone()).await?;
+    update_manifest(&project_root, &manifest_path, use_thundercloud.prune_orphans(), &niche_reporter, &fs).await
+}
+
+/// Downloads the thundercloud of a niche that has a URL, unless it was downloaded before (by `igor fetch` or by an earlier
+/// run). Fails if the thundercloud is missing, but network access is forbidden.
+async fn download_missing_thundercloud<UT: UseThundercloudConfig, FS: FileSystem>(project_root: &AbsolutePath, use_thundercloud: &UT, run_options: &RunOptions, fs: &FS) -> Result<()> {
+    let Some(url) = use_thundercloud.url() else { return Ok(()) };
+    let Some(directory) = get_thundercloud_directory(project_root, use_thundercloud)? else { return Ok(()) };
+    if fs.path_type(&directory).await == PathType::Directory {
+        return Ok(());
+    }
+    if run_options.offline() {
+        bail!("Thundercloud was not downloaded and network access is forbidden (run igor fetch first): {url:?}");
+    }
+    fetch::download_thundercloud(url, use_thundercloud.sha256().unwrap_or_default(), &directory, run_options.allowed_commands(), fs).await
+}
+
+/// Answers have the lowest precedence: they only provide values for props that were missing when the answers were given.
+fn with_answers<IC: InvarConfig>(answers: Table, invar_config_default: IC) -> impl InvarConfig {
+    invar_config::empty().with_props(answers).with_invar_config(invar_config_default).into_owned()
+}
+
+async fn ask_missing_props<UT: UseThundercloudConfig, FS: FileSystem, IC: InvarConfig>(project_root: AbsolutePath, niches_directory: RelativePath, niche: NicheName, use_thundercloud: &UT, invar_config_default: IC, run_options: &RunOptions, fs: FS) -> Result<Table> {
+    let Some(thunder_config) = get_thunder_config(project_root, niches_directory, niche.clone(), use_thundercloud, invar_config_default, fs).await? else { return Ok(Table::new()) };
+    let missing = thundercloud::missing_props(thunder_config, run_options).await?;
+    if missing.is_empty() {
+        return Ok(Table::new());
+    }
+    tokio::task::spawn_blocking(move || prompt::ask_missing_props(&niche, &missing)).await?
+}
 
-        let work_area = AbsolutePath::new("..", &project_root);
-        let absolute_niches_directory = AbsolutePath::new(niches_directory.as_path(), &project_root);
-        let niche_directory = AbsolutePath::new(niche.to_str(), &absolute_niches_directory);
+/// Records the generated files in the manifest of the niche, and reports (or prunes) the files that the niche generated
+/// before, but no longer generates.
+async fn update_manifest<FS: FileSystem>(project_root: &AbsolutePath, manifest_path: &AbsolutePath, prune_orphans: bool, niche_reporter: &NicheReporter, fs: &FS) -> Result<()> {
+    let mut manifest = Manifest::load(manifest_path, fs).await?;
+    let loaded = manifest.clone();
+    for path in niche_reporter.generated() {
+        manifest.add(project_root, &path, fs).await?;
+    }
+    let (removed, remaining) = manifest.prune_orphans(project_root, &niche_reporter.targets(), prune_orphans, fs).await?;
+    for path in removed {
+        niche_reporter.file(project_root, &AbsolutePath::new(path.as_str(), project_root), FileAction::Removed);
+    }
+    for path in remaining {
+        warn!("File is no longer generated: {path:?}");
+        niche_reporter.warning(format!("File is no longer generated: {path}"));
+    }
+    if manifest == loaded {
+        return Ok(());
+    }
+    manifest.save(manifest_path, fs).await
+}
+
+pub async fn infer_props<UT: UseThundercloudConfig, FS: FileSystem, IC: InvarConfig>(project_root: AbsolutePath, niches_directory: RelativePath, niche: NicheName, use_thundercloud: UT, invar_config_default: IC, run_options: &RunOptions, fs: FS) -> Result<Table> {
+    if let Some(thunder_config) = get_thunder_config(project_root, niches_directory, niche, &use_thundercloud, invar_config_default, fs).await? {
+        thundercloud::infer_props(thunder_config, run_options).await
+    } else {
+        Ok(Table::new())
+    }
+}
+
+pub async fn defined_features<UT: UseThundercloudConfig, FS: FileSystem, IC: InvarConfig>(project_root: AbsolutePath, niches_directory: RelativePath, niche: NicheName, use_thundercloud: UT, invar_config_default: IC, run_options: &RunOptions, fs: FS) -> Result<Option<BTreeSet<String>>> {
+    if let Some(thunder_config) = get_thunder_config(project_root, niches_directory, niche, &use_thundercloud, invar_config_default, fs).await? {
+        thundercloud::defined_features(thunder_config, run_options).await.map(Some)
+    } else {
+        Ok(None)
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+pub async fn effective_config<UT: UseThundercloudConfig, FS: FileSystem, IC: InvarConfig>(project_root: AbsolutePath, niches_directory: RelativePath, niche: NicheName, use_thundercloud: UT, invar_config_default: IC, run_options: &RunOptions, fs: FS, target: &AbsolutePath) -> Result<Option<(AbsolutePath, Table)>> {
+    if let Some(thunder_config) = get_thunder_config(project_root, niches_directory, niche, &use_thundercloud, invar_config_default, fs).await? {
+        thundercloud::effective_config(thunder_config, run_options, target).await
+    } else {
+        Ok(None)
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+pub async fn explain<UT: UseThundercloudConfig, FS: FileSystem, IC: InvarConfig>(project_root: AbsolutePath, niches_directory: RelativePath, niche: NicheName, use_thundercloud: UT, invar_config_default: IC, run_options: &RunOptions, fs: FS, target: &AbsolutePath) -> Result<Option<Explanation>> {
+    if let Some(thunder_config) = get_thunder_config(project_root, niches_directory, niche, &use_thundercloud, invar_config_default, fs).await? {
+        thundercloud::explain(thunder_config, run_options, target).await
+    } else {
+        Ok(None)
+    }
+}
+
+async fn get_thunder_config<UT: UseThundercloudConfig, FS: FileSystem, IC: InvarConfig>(project_root: AbsolutePath, niches_directory: RelativePath, niche: NicheName, use_thundercloud: &UT, invar_config_default: IC, fs: FS) -> Result<Option<impl ThunderConfig + use<'_, UT, FS, IC>>> {
+    let Some(thundercloud_directory) = get_thundercloud_directory(&project_root, use_thundercloud)? else { return Ok(None) };
+    if let Some(url) = use_thundercloud.url() {
+        if fs.path_type(&thundercloud_directory).await != PathType::Directory {
+            bail!("Thundercloud was not downloaded (run igor fetch first): {url:?}");
+        }
+    }
+    let invar = get_invar_directory(&project_root, &niches_directory, &niche);
+    let thundercloud_fs = thundercloud_file_system(&project_root, use_thundercloud, fs.clone()).await?;
+    let thunder_config = use_thundercloud.new_thunder_config(
+        invar_config_default,
+        thundercloud_fs,
+        thundercloud_directory,
+        fs,
+        invar,
+        project_root,
+    );
+    debug!("Thunder_config: {thunder_config:?}");
+    Ok(Some(thunder_config))
+}
+
+/// Returns the file system to read the thundercloud of a niche from: a read-only view of the project file system, where
+/// the archive of the thundercloud (if any) appears as a directory.
+pub async fn thundercloud_file_system<UT: UseThundercloudConfig, FS: FileSystem>(project_root: &AbsolutePath, use_thundercloud: &UT, fs: FS) -> Result<ArchiveFileSystem<impl FileSystem>> {
+    let archive = use_thundercloud.archive().map(|archive| AbsolutePath::new(substitute_project_directories(archive, project_root), project_root));
+    ArchiveFileSystem::open(fs.read_only(), archive).await
+}
+
+/// Returns the directory of the thundercloud of a niche. A relative directory is relative to the project root. A
+/// thundercloud with a git remote, but without a directory, lives in a subdirectory of the cache directory that is
+/// named after its fetch URL. The directory of a thundercloud in an archive is the path of the archive. A thundercloud
+/// that is downloaded from a URL is unpacked in a subdirectory of the cache directory that is named after the URL and
+/// the checksum.
+pub fn get_thundercloud_directory<UT: UseThundercloudConfig>(project_root: &AbsolutePath, use_thundercloud: &UT) -> Result<Option<AbsolutePath>> {
+    if let Some(url) = use_thundercloud.url() {
+        if use_thundercloud.directory().is_some() || use_thundercloud.archive().is_some() {
+            bail!("Thundercloud has a URL and also a directory or an archive: {url:?}");
+        }
+        let Some(sha256) = use_thundercloud.sha256() else { bail!("Thundercloud has a URL, but no sha256: {url:?}") };
+        let Some(cache_directory) = use_thundercloud.cache_directory() else { return Ok(None) };
+        let directory = AbsolutePath::new(download_directory_name(url, sha256), cache_directory);
+        info!("Download directory: {directory:?}");
+        return Ok(Some(directory));
+    }
+    if let Some(archive) = use_thundercloud.archive() {
+        if use_thundercloud.directory().is_some() {
+            bail!("Thundercloud has both a directory and an archive: {archive:?}");
+        }
+        info!("Archive: {archive:?}");
+        return Ok(Some(AbsolutePath::new(substitute_project_directories(archive, project_root), project_root)));
+    }
+    let Some(directory) = use_thundercloud.directory() else {
+        let (Some(git_remote), Some(cache_directory)) = (use_thundercloud.git_remote(), use_thundercloud.cache_directory()) else { return Ok(None) };
+        let directory = AbsolutePath::new(clone_directory_name(git_remote.fetch_url()), cache_directory);
+        info!("Clone directory: {directory:?}");
+        return Ok(Some(directory));
+    };
+    info!("Directory: {directory:?}");
+    Ok(Some(AbsolutePath::new(substitute_project_directories(directory, project_root), project_root)))
+}
 
-        let mut substitutions = Table::new();
-        substitutions.insert("WORKSPACE".to_string(), Value::String(work_area.to_string_lossy().to_string()));
-        substitutions.insert("PROJECT".to_string(), Value::String(project_root.to_string_lossy().to_string()));
-        let directory = interpolate::interpolate(directory, &substitutions);
+/// The settings of a project that apply to the use-thundercloud configurations of all its niches.
+#[derive(Debug, Clone)]
+pub struct UseThundercloudDefaults {
+    props: Table,
+    cache_directory: AbsolutePath,
+}
 
-        let current_dir = AbsolutePath::current_dir()?;
-        let thundercloud_directory = AbsolutePath::new(directory.to_string(), &current_dir);
+impl UseThundercloudDefaults {
+    pub fn new<PC: ProjectConfig>(project_root: &AbsolutePath, project_config: &PC) -> Self {
+        let mut props = project_config.invar_defaults().props().into_owned();
+        props.extend(project_directories(project_root));
+        UseThundercloudDefaults { props, cache_directory: get_cache_directory(project_root, project_config) }
+    }
 
-        let mut invar = niche_directory.clone();
-        invar.push("invar");
-        let thunder_config = use_thundercloud.new_thunder_config(
-            invar_config_default,
-            fs.clone().read_only(),
-            thundercloud_directory,
-            fs,
-            invar,
-            project_root,
-        );
-        debug!("Thunder_config: {thunder_config:?}");
+    /// Replaces the placeholders in the directory, fetch URL and revision of a use-thundercloud configuration by the
+    /// props in the invar defaults of the project (and `PROJECT` and `WORKSPACE`), and sets the cache directory of the
+    /// project.
+    pub fn apply<UT: UseThundercloudConfig>(&self, use_thundercloud: &UT) -> UT {
+        use_thundercloud.with_interpolated_settings(&self.props).with_cache_directory(self.cache_directory.clone())
+    }
+}
 
-        thundercloud::process_niche(thunder_config).await?;
+/// Returns the directory that thunderclouds with a git remote, but without a directory are cloned into: the
+/// `thundercloud-cache` of the project (relative to the project root), or `igor/thunderclouds` in the cache directory
+/// of the user.
+pub fn get_cache_directory<PC: ProjectConfig>(project_root: &AbsolutePath, project_config: &PC) -> AbsolutePath {
+    if let Some(directory) = project_config.thundercloud_cache() {
+        return AbsolutePath::new(substitute_project_directories(directory, project_root), project_root);
+    }
+    let user_cache = std::env::var_os("XDG_CACHE_HOME").map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".cache")));
+    match user_cache {
+        Some(user_cache) => AbsolutePath::new(user_cache.join("igor/thunderclouds"), project_root),
+        None => AbsolutePath::new(".igor/thunderclouds", project_root),
     }
+}
+
+fn project_directories(project_root: &AbsolutePath) -> Table {
+    let work_area = AbsolutePath::new("..", project_root);
 
-    Ok(())
+    let mut substitutions = Table::new();
+    substitutions.insert("WORKSPACE".to_string(), Value::String(work_area.to_string_lossy().to_string()));
+    substitutions.insert("PROJECT".to_string(), Value::String(project_root.to_string_lossy().to_string()));
+    substitutions
+}
+
+pub fn substitute_project_directories(directory: &str, project_root: &AbsolutePath) -> String {
+    interpolate::interpolate(directory, &project_directories(project_root)).to_string()
+}
+
+/// Names the clone of a git remote after the last component of its URL, with a hash of the whole URL to keep clones of
+/// different remotes apart, like `example-thundercloud-0123456789ab`.
+fn clone_directory_name(fetch_url: &str) -> String {
+    let name = fetch_url.trim_end_matches('/').rsplit(['/', ':']).next().unwrap_or_default().trim_end_matches(".git");
+    let name: String = name.chars().map(|c| if c.is_ascii_alphanumeric() || c == '-' || c == '_' { c } else { '_' }).collect();
+    let hash: String = Sha256::digest(fetch_url.as_bytes()).iter().take(6).map(|byte| format!("{byte:02x}")).collect();
+    format!("{name}-{hash}")
+}
+
+/// Names the unpacked download of a tarball after the file name in its URL (without extensions like `.tar.gz`), with
+/// the start of its checksum, so that a new checksum gets a fresh directory, like `example-thundercloud-1.0-0123456789ab`.
+fn download_directory_name(url: &str, sha256: &str) -> String {
+    let file_name = url.split(['?', '#']).next().unwrap_or_default().trim_end_matches('/').rsplit('/').next().unwrap_or_default();
+    let name = [".tar.gz", ".tgz", ".tar", ".zip"].iter()
+        .find_map(|extension| file_name.strip_suffix(extension))
+        .unwrap_or(file_name);
+    let name: String = name.chars().map(|c| if c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '.' { c } else { '_' }).collect();
+    let checksum: String = sha256.trim().to_ascii_lowercase().chars().take(12).collect();
+    format!("{name}-{checksum}")
+}
+
+pub fn get_invar_directory(project_root: &AbsolutePath, niches_directory: &RelativePath, niche: &NicheName) -> AbsolutePath {
+    let absolute_niches_directory = AbsolutePath::new(niches_directory.as_path(), project_root);
+    let mut invar = AbsolutePath::new(niche.to_str(), &absolute_niches_directory);
+    invar.push("invar");
+    invar
 }
 
 #[cfg(test)]
 mod test {
+    use assert_fs::TempDir;
     use indoc::indoc;
     use log::trace;
     use test_log::test;
-    use crate::config_model::{invar_config, project_config, NicheTriggers, ProjectConfig, PsychotropicConfig};
+    use crate::config_model::{invar_config, project_config, NicheTriggers, ProjectConfig, PsychotropicConfig, WriteMode};
     use crate::file_system::{fixture, FileSystem};
     use crate::file_system::ConfigFormat::TOML;
     use crate::path::test_utils::to_absolute_path;
@@ -70,7 +287,7 @@ mod test {
         let default_invar_config = invar_config::from_str("", TOML)?;
 
         // When
-        process_niche(project_root, niches_directory, niche.clone(), use_thundercloud.clone(), default_invar_config, fs.clone()).await?;
+        process_niche(project_root, niches_directory, niche.clone(), use_thundercloud.clone(), default_invar_config, fs.clone(), RunOptions::default(), FunctionRegistry::default(), NicheReporter::default()).await?;
 
         // Then
         let content = fs.get_content(to_absolute_path("/workshop/clock.yaml")).await?;
@@ -85,6 +302,192 @@ mod test {
         Ok(())
     }
 
+    #[test(tokio::test)]
+    async fn answers_provide_missing_props() -> Result<()> {
+        // Given
+        let toml_data = indoc! {r#"
+            "CargoCult.toml" = """
+            [[psychotropic.cues]]
+            name = "example"
+            use-thundercloud = { directory = "{{PROJECT}}/example-thundercloud", features = ["glass"] }
+            """
+
+            [yeth-marthter.example]
+            "answers.toml" = """
+            [props]
+            sweeper = "Answered"
+            apprentice = "Lobsang"
+            """
+
+            [yeth-marthter.example.invar]
+
+            [example-thundercloud]
+            "thundercloud.toml" = """
+            [niche]
+            name = "example"
+            """
+
+            [example-thundercloud.cumulus.workshop]
+            "clock+option-glass.yaml" = '''
+            sweeper: {{sweeper}}
+            apprentice: {{apprentice}}
+            valley: {{valley}}
+            '''
+        "#};
+        let fs = fixture::from_toml(toml_data)?;
+        let project_root = AbsolutePath::root();
+        let niche = NicheName::new("example");
+        let niches_directory = RelativePath::from("yeth-marthter");
+        let project_config = project_config::from_str(&fs.get_content(to_absolute_path("/CargoCult.toml")).await?, TOML)?;
+        let psychotropic = project_config.psychotropic()?;
+        let use_thundercloud = psychotropic.get(niche.to_str()).and_then(NicheTriggers::use_thundercloud).unwrap().clone();
+        let default_invar_config = invar_config::from_str("[props]\nsweeper = \"Lu Tse\"", TOML)?;
+        let thunder_config = get_thunder_config(project_root.clone(), niches_directory.clone(), niche.clone(), &use_thundercloud, default_invar_config.clone(), fs.clone()).await?.unwrap();
+
+        // When
+        let missing = thundercloud::missing_props(thunder_config, &RunOptions::default()).await?;
+        process_niche(project_root, niches_directory, niche, use_thundercloud, default_invar_config, fs.clone(), RunOptions::default(), FunctionRegistry::default(), NicheReporter::default()).await?;
+
+        // Then
+        assert_eq!(missing, vec!["apprentice".to_string(), "valley".to_string()]);
+        let content = fs.get_content(to_absolute_path("/workshop/clock.yaml")).await?;
+        assert_eq!(&content, "sweeper: Lu Tse\napprentice: Lobsang\nvalley: {{valley}}\n");
+
+        Ok(())
+    }
+
+    #[test(tokio::test)]
+    async fn thundercloud_from_url() -> Result<()> {
+        // Given
+        let mut builder = tar::Builder::new(flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default()));
+        for (path, content) in [
+            ("example-thundercloud-1.0/thundercloud.toml", "[niche]\nname = \"example\"\n"),
+            ("example-thundercloud-1.0/cumulus/workshop/clock+option-@.yaml", "sweeper: Lu Tse\n"),
+        ] {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(content.len() as u64);
+            header.set_mode(0o644);
+            builder.append_data(&mut header, path, content.as_bytes())?;
+        }
+        let tarball = builder.into_inner()?.finish()?;
+        let server = TempDir::new()?;
+        let tarball_path = server.path().join("example-thundercloud-1.0.tar.gz");
+        std::fs::write(&tarball_path, &tarball)?;
+        let url = format!("file://{}", tarball_path.to_string_lossy());
+        let sha256 = format!("{:x}", Sha256::digest(&tarball));
+        let cargo_cult_toml_data = format!(indoc! {r#"
+            thundercloud-cache = "/cache"
+
+            [[psychotropic.cues]]
+            name = "example"
+            use-thundercloud = {{ url = "{}", sha256 = "{}" }}
+        "#}, url, sha256);
+        let fs = fixture::from_toml("[yeth-marthter.example.invar]")?;
+        let project_root = AbsolutePath::root();
+        let project_config = project_config::from_str(&cargo_cult_toml_data, TOML)?;
+        let psychotropic = project_config.psychotropic()?;
+        let use_thundercloud = psychotropic.get("example").and_then(NicheTriggers::use_thundercloud).unwrap();
+        let use_thundercloud = UseThundercloudDefaults::new(&project_root, &project_config).apply(use_thundercloud);
+        let default_invar_config = invar_config::from_str("", TOML)?;
+        let process = |run_options: RunOptions| process_niche(project_root.clone(), RelativePath::from("yeth-marthter"), NicheName::new("example"), use_thundercloud.clone(), default_invar_config.clone(), fs.clone(), run_options, FunctionRegistry::default(), NicheReporter::default());
+
+        // When
+        let offline = process(RunOptions::default().with_offline(true)).await;
+        process(RunOptions::default()).await?;
+
+        // Then
+        assert!(offline.unwrap_err().to_string().starts_with("Thundercloud was not downloaded and network access is forbidden (run igor fetch first)"));
+        assert_eq!(fs.get_content(to_absolute_path("/workshop/clock.yaml")).await?, "sweeper: Lu Tse\n");
+        let directory = get_thundercloud_directory(&project_root, &use_thundercloud)?.unwrap();
+        assert_eq!(fs.path_type(&directory).await, PathType::Directory);
+        Ok(())
+    }
+
+    #[test(tokio::test)]
+    async fn thundercloud_in_archive() -> Result<()> {
+        // Given
+        let toml_data = indoc! {r#"
+            "CargoCult.toml" = """
+            [[psychotropic.cues]]
+            name = "example"
+            use-thundercloud = { archive = "{{PROJECT}}/example-thundercloud.tar.gz" }
+            """
+
+            [yeth-marthter.example.invar]
+        "#};
+        let fs = fixture::from_toml(toml_data)?;
+        let mut builder = tar::Builder::new(flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default()));
+        for (path, content, mode) in [
+            ("example-thundercloud-1.0/thundercloud.toml", "[niche]\nname = \"example\"\n", 0o644),
+            ("example-thundercloud-1.0/cumulus/workshop/clock+option-@.yaml", "sweeper: Lu Tse\n", 0o644),
+            ("example-thundercloud-1.0/cumulus/workshop/wind+option-@.sh", "#!/bin/sh\n", 0o755),
+        ] {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(content.len() as u64);
+            header.set_mode(mode);
+            builder.append_data(&mut header, path, content.as_bytes())?;
+        }
+        let archive = builder.into_inner()?.finish()?;
+        fs.write_bytes(to_absolute_path("/example-thundercloud.tar.gz"), &archive, WriteMode::Overwrite, false).await?;
+        let project_root = AbsolutePath::root();
+        let project_config = project_config::from_str(&fs.get_content(to_absolute_path("/CargoCult.toml")).await?, TOML)?;
+        let psychotropic = project_config.psychotropic()?;
+        let use_thundercloud = psychotropic.get("example").and_then(NicheTriggers::use_thundercloud).unwrap().clone();
+        let default_invar_config = invar_config::from_str("", TOML)?;
+
+        // When
+        process_niche(project_root.clone(), RelativePath::from("yeth-marthter"), NicheName::new("example"), use_thundercloud.clone(), default_invar_config, fs.clone(), RunOptions::default(), FunctionRegistry::default(), NicheReporter::default()).await?;
+
+        // Then
+        assert_eq!(get_thundercloud_directory(&project_root, &use_thundercloud)?, Some(to_absolute_path("/example-thundercloud.tar.gz")));
+        assert_eq!(fs.get_content(to_absolute_path("/workshop/clock.yaml")).await?, "sweeper: Lu Tse\n");
+        fixture::assert_executable(&fs, "/workshop/wind.sh").await?;
+        Ok(())
+    }
+
+    #[test]
+    fn use_thundercloud_defaults_interpolate_settings() -> Result<()> {
+        // Given
+        let cargo_cult_toml_data = indoc! {r#"
+            thundercloud-cache = "/cache"
+
+            [invar-defaults.props]
+            GIT_HOST = "github.com"
+            BRANCH = "main"
+
+            [[psychotropic.cues]]
+            name = "example"
+            use-thundercloud = { git-remote = { fetch-url = "https://{{GIT_HOST}}/rustigaan/example-thundercloud.git", revision = "{{BRANCH}}" } }
+
+            [[psychotropic.cues]]
+            name = "local"
+            use-thundercloud = { directory = "{{PROJECT}}/{{BRANCH}}-thundercloud" }
+
+            [[psychotropic.cues]]
+            name = "download"
+            use-thundercloud = { url = "https://{{GIT_HOST}}/rustigaan/example-thundercloud/archive/refs/tags/v1.0.tar.gz", sha256 = "5BB4B95445DD0123456789ABCDEF" }
+        "#};
+        let project_root = to_absolute_path("/project");
+        let project_config = project_config::from_str(cargo_cult_toml_data, TOML)?;
+        let psychotropic = project_config.psychotropic()?;
+        let use_thundercloud_defaults = UseThundercloudDefaults::new(&project_root, &project_config);
+
+        // When
+        let example = use_thundercloud_defaults.apply(psychotropic.get("example").and_then(NicheTriggers::use_thundercloud).unwrap());
+        let local = use_thundercloud_defaults.apply(psychotropic.get("local").and_then(NicheTriggers::use_thundercloud).unwrap());
+        let download = use_thundercloud_defaults.apply(psychotropic.get("download").and_then(NicheTriggers::use_thundercloud).unwrap());
+
+        // Then
+        let git_remote = example.git_remote().unwrap();
+        assert_eq!(git_remote.fetch_url(), "https://github.com/rustigaan/example-thundercloud.git");
+        assert_eq!(git_remote.revision(), "main");
+        assert_eq!(get_thundercloud_directory(&project_root, &example)?, Some(to_absolute_path("/cache/example-thundercloud-5bb4b95445dd")));
+        assert_eq!(get_thundercloud_directory(&project_root, &local)?, Some(to_absolute_path("/project/main-thundercloud")));
+        assert_eq!(download.url(), Some("https://github.com/rustigaan/example-thundercloud/archive/refs/tags/v1.0.tar.gz"));
+        assert_eq!(get_thundercloud_directory(&project_root, &download)?, Some(to_absolute_path("/cache/v1.0-5bb4b95445dd")));
+        Ok(())
+    }
+
     fn create_file_system_fixture() -> Result<impl FileSystem> {
         let toml_data = indoc! {r#"
             "CargoCult.toml" = """
@@ -0,0 +1,98 @@
+use std::process::Stdio;
+use anyhow::{bail, Result};
+use log::{debug, warn};
+use serde_json::json;
+use tokio::io::AsyncWriteExt;
+use tokio::process::Command;
+use crate::config_model::{NetworkAccess, NotifyConfig, ProjectConfig};
+use crate::file_system::FileSystem;
+use crate::hooks::AllowedCommands;
+use crate::path::AbsolutePath;
+use crate::report::Report;
+use crate::run_options::RunOptions;
+use crate::get_project_config;
+
+/// Reports the result of a run as configured in the `[notify]` table of `CargoCult.toml`: posts a JSON summary to the
+/// webhook `url` and/or shows a desktop notification. Notifications that fail (or that need a program that is not one of
+/// the allowed commands) are logged, but do not fail the run.
+pub async fn notify<FS: FileSystem>(project_root: &AbsolutePath, run_options: &RunOptions, report: &Report, fs: &FS) {
+    let project_config = match get_project_config(project_root, fs).await {
+        Ok(project_config) => project_config,
+        Err(err) => {
+            warn!("Cannot read the notification configuration: {err:#}");
+            return;
+        }
+    };
+    let run_options = run_options.clone().with_allowed_hook_commands(&project_config);
+    let allowed_commands = run_options.allowed_commands();
+    let notify_config = project_config.notify();
+    if let Some(url) = notify_config.url() {
+        if run_options.offline() || project_config.network() == NetworkAccess::Forbidden {
+            warn!("Network access is forbidden: skip webhook notification: {url:?}");
+        } else if let Err(err) = post_webhook(url, report, allowed_commands).await {
+            warn!("Webhook notification failed: {err:#}");
+        }
+    }
+    if notify_config.desktop() {
+        if let Err(err) = show_desktop_notification(&report.summary(), allowed_commands).await {
+            warn!("Desktop notification failed: {err:#}");
+        }
+    }
+}
+
+fn webhook_payload(report: &Report) -> serde_json::Value {
+    json!({
+        "text": report.summary(),
+        "report": report,
+    })
+}
+
+async fn post_webhook(url: &str, report: &Report, allowed_commands: &AllowedCommands) -> Result<()> {
+    debug!("Post webhook: {url:?}");
+    allowed_commands.check_program("curl")?;
+    let body = serde_json::to_vec(&webhook_payload(report))?;
+    let mut child = Command::new("curl")
+        .args(["--silent", "--show-error", "--fail", "--request", "POST", "--header", "Content-Type: application/json", "--data-binary", "@-", url])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .spawn()?;
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin.write_all(&body).await?;
+    }
+    let output = child.wait_with_output().await?;
+    if !output.status.success() {
+        bail!("curl {url}: {}", String::from_utf8_lossy(&output.stderr).trim());
+    }
+    Ok(())
+}
+
+async fn show_desktop_notification(summary: &str, allowed_commands: &AllowedCommands) -> Result<()> {
+    let output = if cfg!(target_os = "macos") {
+        allowed_commands.check_program("osascript")?;
+        let script = format!("display notification {summary:?} with title \"Igor\"");
+        Command::new("osascript").args(["-e", &script]).output().await?
+    } else {
+        allowed_commands.check_program("notify-send")?;
+        Command::new("notify-send").args(["Igor", summary]).output().await?
+    };
+    if !output.status.success() {
+        bail!("{}", String::from_utf8_lossy(&output.stderr).trim());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn payload() {
+        // When
+        let payload = webhook_payload(&Report::default());
+
+        // Then
+        assert_eq!(payload["text"], "Igor: 0 niches, 0 failed, 0 files changed");
+        assert_eq!(payload["report"]["niches"], json!([]));
+    }
+}
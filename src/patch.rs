@@ -0,0 +1,149 @@
+use anyhow::{anyhow, bail, Result};
+use once_cell::sync::Lazy;
+use regex::Regex;
+
+static HUNK_REGEX: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"^@@ -(?<old_start>[0-9]+)(,(?<old_count>[0-9]+))? [+](?<new_start>[0-9]+)(,(?<new_count>[0-9]+))? @@").unwrap()
+});
+
+/// A change to a contiguous range of lines: the lines that must be there (context and removed lines) and the lines that
+/// replace them (context and added lines).
+#[derive(Debug, Clone, PartialEq)]
+struct Hunk {
+    header: String,
+    old_start: usize,
+    old_lines: Vec<String>,
+    new_lines: Vec<String>,
+}
+
+/// Applies a unified diff (as produced by `diff -u` or `git diff`) to the lines of `content`. File headers are ignored,
+/// because the diff applies to a single file. Hunks may be off by some lines, as long as their context and removed
+/// lines match exactly. Fails if a hunk does not apply.
+pub fn apply(content: &str, diff: &str) -> Result<String> {
+    let hunks = parse(diff)?;
+    if hunks.is_empty() {
+        bail!("Patch has no hunks");
+    }
+    let lines: Vec<&str> = content.lines().collect();
+    let mut result: Vec<&str> = Vec::new();
+    let mut cursor = 0;
+    let mut offset: isize = 0;
+    for hunk in &hunks {
+        // A hunk without old lines inserts its lines after line `old_start`
+        let base = if hunk.old_lines.is_empty() { hunk.old_start } else { hunk.old_start.saturating_sub(1) };
+        let expected = base.saturating_add_signed(offset).max(cursor);
+        let position = find_hunk(&lines, &hunk.old_lines, expected, cursor)
+            .ok_or_else(|| anyhow!("Patch does not apply: hunk {:?}", hunk.header))?;
+        result.extend(&lines[cursor..position]);
+        result.extend(hunk.new_lines.iter().map(String::as_str));
+        cursor = position + hunk.old_lines.len();
+        offset = position as isize - base as isize;
+    }
+    result.extend(&lines[cursor..]);
+    Ok(result.iter().map(|line| format!("{line}\n")).collect())
+}
+
+/// Finds the position of the lines of a hunk closest to the expected position, but not before `cursor`.
+fn find_hunk(lines: &[&str], old_lines: &[String], expected: usize, cursor: usize) -> Option<usize> {
+    let last = lines.len().checked_sub(old_lines.len())?;
+    let expected = expected.min(last);
+    let matches = |position: usize| lines[position..position + old_lines.len()].iter().zip(old_lines).all(|(line, old_line)| line == old_line);
+    (0..=lines.len())
+        .flat_map(|distance| [expected.checked_add(distance), expected.checked_sub(distance).filter(|_| distance > 0)])
+        .flatten()
+        .filter(|position| *position >= cursor && *position <= last)
+        .find(|position| matches(*position))
+}
+
+fn parse(diff: &str) -> Result<Vec<Hunk>> {
+    let mut hunks = Vec::new();
+    let mut diff_lines = diff.lines();
+    while let Some(line) = diff_lines.next() {
+        let Some(captures) = HUNK_REGEX.captures(line) else { continue };
+        let number = |name: &str| captures.name(name).map_or(Ok(1), |m| m.as_str().parse::<usize>());
+        let (old_start, mut old_count, mut new_count) = (number("old_start")?, number("old_count")?, number("new_count")?);
+        let mut hunk = Hunk { header: line.to_string(), old_start, old_lines: Vec::new(), new_lines: Vec::new() };
+        while old_count > 0 || new_count > 0 {
+            let hunk_line = diff_lines.next().ok_or_else(|| anyhow!("Patch ends in the middle of hunk {:?}", hunk.header))?;
+            // Some editors strip the single space of empty context lines
+            let (kind, text) = hunk_line.split_at(hunk_line.len().min(1));
+            match kind {
+                " " | "" if old_count > 0 && new_count > 0 => {
+                    hunk.old_lines.push(text.to_string());
+                    hunk.new_lines.push(text.to_string());
+                    old_count -= 1;
+                    new_count -= 1;
+                },
+                "-" if old_count > 0 => {
+                    hunk.old_lines.push(text.to_string());
+                    old_count -= 1;
+                },
+                "+" if new_count > 0 => {
+                    hunk.new_lines.push(text.to_string());
+                    new_count -= 1;
+                },
+                "\\" => (),
+                _ => bail!("Unexpected line in hunk {:?}: {:?}", hunk.header, hunk_line),
+            }
+        }
+        hunks.push(hunk);
+    }
+    Ok(hunks)
+}
+
+#[cfg(test)]
+mod test {
+    use indoc::indoc;
+    use super::*;
+
+    #[test]
+    fn apply_with_offset() -> Result<()> {
+        // Given
+        let content = indoc! {"
+            sweeper: Lu-Tse
+            clock:
+              hours: 10
+              spring: coiled
+            mirror: polished
+        "};
+        let diff = indoc! {"
+            --- a/clock.yaml
+            +++ b/clock.yaml
+            @@ -1,3 +1,4 @@
+             clock:
+            -  hours: 10
+            +  hours: 12
+            +  minutes: 60
+               spring: coiled
+        "};
+
+        // When
+        let patched = apply(content, diff)?;
+
+        // Then
+        let expected = indoc! {"
+            sweeper: Lu-Tse
+            clock:
+              hours: 12
+              minutes: 60
+              spring: coiled
+            mirror: polished
+        "};
+        assert_eq!(patched, expected);
+        Ok(())
+    }
+
+    #[test]
+    fn apply_mismatch() {
+        // Given
+        let content = "clock:\n  hours: 10\n";
+        let diff = "@@ -1,2 +1,2 @@\n clock:\n-  hours: 24\n+  hours: 12\n";
+
+        // When
+        let result = apply(content, diff);
+
+        // Then
+        let Err(error) = result else { panic!("Expected the patch to fail") };
+        assert_eq!(error.to_string(), "Patch does not apply: hunk \"@@ -1,2 +1,2 @@\"");
+    }
+}
@@ -0,0 +1,127 @@
+use std::io::{BufRead, IsTerminal, Write};
+use std::sync::Mutex;
+use anyhow::{anyhow, Result};
+use once_cell::sync::Lazy;
+use toml::{Table, Value};
+use crate::config_model::WriteMode;
+use crate::file_system::{FileSystem, PathType, TargetFile};
+use crate::interpolate::insert_dotted;
+use crate::path::{AbsolutePath, RelativePath};
+use crate::run_options::RunOptions;
+use crate::NicheName;
+
+static PROMPT_LOCK: Lazy<Mutex<()>> = Lazy::new(|| Mutex::new(()));
+
+/// Input is only possible if the run enables it and standard input is a terminal.
+pub fn is_input_enabled(run_options: &RunOptions) -> bool {
+    run_options.input() && std::io::stdin().is_terminal()
+}
+
+/// Asks on the terminal for the values of missing properties of a niche. Niches that are processed concurrently take
+/// turns.
+pub fn ask_missing_props(niche: &NicheName, names: &[String]) -> Result<Table> {
+    let _guard = PROMPT_LOCK.lock().map_err(|_| anyhow!("Prompt lock poisoned"))?;
+    ask(niche, names, std::io::stdin().lock(), std::io::stderr())
+}
+
+/// Asks for the value of every name. Names without an answer are left out.
+fn ask<R: BufRead, W: Write>(niche: &NicheName, names: &[String], mut input: R, mut output: W) -> Result<Table> {
+    let mut answers = Table::new();
+    for name in names {
+        write!(output, "Niche {:?}: value for {:?}: ", niche.to_str(), name)?;
+        output.flush()?;
+        let mut line = String::new();
+        if input.read_line(&mut line)? == 0 {
+            break;
+        }
+        let answer = line.trim();
+        if !answer.is_empty() {
+            insert_dotted(&mut answers, name, Value::String(answer.to_string()));
+        }
+    }
+    Ok(answers)
+}
+
+pub fn get_answers_path(project_root: &AbsolutePath, niches_directory: &RelativePath, niche: &NicheName) -> AbsolutePath {
+    let absolute_niches_directory = AbsolutePath::new(niches_directory.as_path(), project_root);
+    let mut answers_path = AbsolutePath::new(niche.to_str(), &absolute_niches_directory);
+    answers_path.push("answers.toml");
+    answers_path
+}
+
+/// Loads the props that were given as answers earlier.
+pub async fn load_answers<FS: FileSystem>(answers_path: &AbsolutePath, fs: &FS) -> Result<Table> {
+    if fs.path_type(answers_path).await != PathType::File {
+        return Ok(Table::new());
+    }
+    let body = fs.get_content(answers_path.clone()).await?;
+    let mut table: Table = toml::from_str(&body)?;
+    match table.remove("props") {
+        Some(Value::Table(props)) => Ok(props),
+        Some(_) => Err(anyhow!("Props should be a table: {:?}", answers_path)),
+        None => Ok(Table::new()),
+    }
+}
+
+/// Adds answers to the props that were given as answers earlier.
+pub async fn save_answers<FS: FileSystem>(answers_path: &AbsolutePath, answers: Table, fs: &FS) -> Result<()> {
+    let mut props = load_answers(answers_path, fs).await?;
+    props.extend(answers);
+    let mut table = Table::new();
+    table.insert("props".to_string(), Value::Table(props));
+    let body = toml::to_string(&table)?;
+    let mut target_file = fs.open_target(answers_path.clone(), WriteMode::Overwrite, false).await?
+        .ok_or_else(|| anyhow!("Could not write answers: {:?}", answers_path))?;
+    for line in body.lines() {
+        target_file.write_line(line).await?;
+    }
+    target_file.close().await
+}
+
+#[cfg(test)]
+mod test {
+    use test_log::test;
+    use crate::file_system::fixture;
+    use crate::path::test_utils::to_absolute_path;
+    use super::*;
+
+    #[test]
+    fn ask_for_missing_props() -> Result<()> {
+        // Given
+        let names = vec!["sweeper".to_string(), "apprentice".to_string(), "valley".to_string(), "monastery.abbot".to_string()];
+        let input = "Lu Tse\n\n  Oi Dong  \nRebert Rebert\n".as_bytes();
+        let mut output = Vec::new();
+
+        // When
+        let answers = ask(&NicheName::new("example"), &names, input, &mut output)?;
+
+        // Then
+        assert_eq!(answers.get("sweeper").and_then(Value::as_str), Some("Lu Tse"));
+        assert_eq!(answers.get("apprentice"), None);
+        assert_eq!(answers.get("valley").and_then(Value::as_str), Some("Oi Dong"));
+        assert_eq!(answers.get("monastery").and_then(|monastery| monastery.get("abbot")).and_then(Value::as_str), Some("Rebert Rebert"));
+        assert!(String::from_utf8(output)?.starts_with("Niche \"example\": value for \"sweeper\": "));
+        Ok(())
+    }
+
+    #[test(tokio::test)]
+    async fn save_and_load_answers() -> Result<()> {
+        // Given
+        let fs = fixture::from_toml("")?;
+        let answers_path = to_absolute_path("/yeth-marthter/example/answers.toml");
+        let mut first = Table::new();
+        first.insert("sweeper".to_string(), Value::String("Lu Tse".to_string()));
+        let mut second = Table::new();
+        second.insert("valley".to_string(), Value::String("Oi Dong".to_string()));
+
+        // When
+        save_answers(&answers_path, first, &fs).await?;
+        save_answers(&answers_path, second, &fs).await?;
+        let props = load_answers(&answers_path, &fs).await?;
+
+        // Then
+        assert_eq!(props.get("sweeper").and_then(Value::as_str), Some("Lu Tse"));
+        assert_eq!(props.get("valley").and_then(Value::as_str), Some("Oi Dong"));
+        Ok(())
+    }
+}
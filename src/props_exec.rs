@@ -0,0 +1,107 @@
+use anyhow::{anyhow, bail, Result};
+use log::{debug, info, warn};
+use tokio::process::Command;
+use toml::{Table, Value};
+use crate::config_model::PlaceholderDelimiters;
+use crate::interpolate::{interpolate_shell_command, CONTEXT_PROPS, TIMESTAMP_PROP};
+use crate::path::AbsolutePath;
+use crate::run_options::RunOptions;
+
+/// Runs the shell command of every prop in a `props-exec` section (in the given directory) and returns the props with
+/// the output of their commands as value (without the final line ending). A command that fails is an error.
+///
+/// Placeholders in the commands are interpolated with the given props (the effective props of the niche) when the
+/// command runs, and the context props like `NICHE` are also passed as environment variables like `IGOR_NICHE` (and the
+/// time of the run as `IGOR_TIMESTAMP`). Every interpolated value is quoted as a single shell word, because props may
+/// come from elsewhere (like a thundercloud). The commands only run if the run allows them (see
+/// [`RunOptions::with_props_exec`]).
+pub async fn exec_props(props_exec: &Table, niche_props: &Table, delimiters: &PlaceholderDelimiters, directory: &AbsolutePath, run_options: &RunOptions) -> Result<Table> {
+    if props_exec.is_empty() {
+        return Ok(Table::new());
+    }
+    if run_options.no_hooks() {
+        let names: Vec<&str> = props_exec.keys().map(String::as_str).collect();
+        warn!("Skip the commands of props (--no-hooks): {}", names.join(", "));
+        return Ok(Table::new());
+    }
+    if !run_options.props_exec() {
+        let names: Vec<&str> = props_exec.keys().map(String::as_str).collect();
+        bail!("Props would be computed by shell commands: {} (use --allow-props-exec to run them)", names.join(", "));
+    }
+    let mut props = Table::new();
+    for (name, command) in props_exec {
+        let Value::String(command) = command else { bail!("Command of prop {name} is not a string") };
+        let command = interpolate_shell_command(command, niche_props, delimiters);
+        let command = command.as_ref();
+        run_options.allowed_commands().check_shell_command(command).map_err(|error| anyhow!("Command of prop {name}: {error}"))?;
+        info!("Exec prop {name}: {command}");
+        let environment = CONTEXT_PROPS.iter()
+            .filter_map(|context_prop| niche_props.get(*context_prop).and_then(Value::as_str).map(|value| (format!("IGOR_{context_prop}"), value)))
+            .chain(niche_props.get(TIMESTAMP_PROP).and_then(Value::as_str).map(|value| (TIMESTAMP_PROP.to_string(), value)));
+        let output = Command::new("sh").arg("-c").arg(command).envs(environment).current_dir(directory.as_path()).output().await
+            .map_err(|error| anyhow!("Could not run command of prop {name}: {command:?}: {error}"))?;
+        if !output.status.success() {
+            bail!("Command of prop {name} failed ({}): {command:?}: {}", output.status, String::from_utf8_lossy(&output.stderr).trim_end());
+        }
+        let value = String::from_utf8(output.stdout).map_err(|_| anyhow!("Output of command of prop {name} is not UTF-8: {command:?}"))?;
+        let value = value.strip_suffix('\n').map(|value| value.strip_suffix('\r').unwrap_or(value)).unwrap_or(&value).to_string();
+        debug!("Prop {name} = {value:?}");
+        props.insert(name.clone(), Value::String(value));
+    }
+    Ok(props)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[tokio::test]
+    async fn exec_props_when_allowed() -> Result<()> {
+        // Given
+        let props_exec: Table = toml::from_str(r#"
+            greeting = "echo Hello, $(echo World)"
+            lines = "printf 'tick\ntock\n'"
+        "#)?;
+        let directory = AbsolutePath::current_dir()?;
+
+        let allowed = RunOptions::default().with_props_exec(true);
+
+        // When
+        let props = exec_props(&props_exec, &Table::new(), &PlaceholderDelimiters::default(), &directory, &allowed).await?;
+        let failed = exec_props(&toml::from_str(r#"broken = "echo oops >&2; exit 3""#)?, &Table::new(), &PlaceholderDelimiters::default(), &directory, &allowed).await;
+        let not_allowed = exec_props(&props_exec, &Table::new(), &PlaceholderDelimiters::default(), &directory, &RunOptions::default()).await;
+        let skipped = exec_props(&props_exec, &Table::new(), &PlaceholderDelimiters::default(), &directory, &allowed.clone().with_no_hooks(true)).await?;
+
+        // Then
+        assert_eq!(props.get("greeting"), Some(&Value::String("Hello, World".to_string())));
+        assert_eq!(props.get("lines"), Some(&Value::String("tick\ntock".to_string())));
+        let error = failed.unwrap_err().to_string();
+        assert!(error.starts_with("Command of prop broken failed") && error.ends_with("oops"), "Actual error: {error}");
+        assert!(not_allowed.unwrap_err().to_string().ends_with("(use --allow-props-exec to run them)"));
+        assert!(skipped.is_empty());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn exec_props_with_shell_syntax_in_props() -> Result<()> {
+        // Given
+        let props_exec: Table = toml::from_str(r#"
+            motto = "echo {{motto}}"
+            owner = "printf '%s|' {{owner}}"
+        "#)?;
+        let niche_props: Table = toml::from_str(r#"
+            motto = "x; echo injected $(echo too) `echo also`"
+            owner = "Lu-Tse's  clock"
+        "#)?;
+        let directory = AbsolutePath::current_dir()?;
+        let allowed = RunOptions::default().with_props_exec(true);
+
+        // When
+        let props = exec_props(&props_exec, &niche_props, &PlaceholderDelimiters::default(), &directory, &allowed).await?;
+
+        // Then
+        assert_eq!(props.get("motto"), Some(&Value::String("x; echo injected $(echo too) `echo also`".to_string())));
+        assert_eq!(props.get("owner"), Some(&Value::String("Lu-Tse's  clock|".to_string())));
+        Ok(())
+    }
+}
@@ -0,0 +1,337 @@
+use std::collections::BTreeSet;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use anyhow::{bail, Result};
+use clap::ValueEnum;
+use serde::Serialize;
+use crate::audit::PropRead;
+use crate::path::AbsolutePath;
+use crate::NicheName;
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReportFormat {
+    Json,
+}
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum FileAction {
+    Written,
+    Skipped,
+    /// The target file exists with the generated content already, so it was left alone
+    Unchanged,
+    /// Changes in the target file since it was last generated were merged with the generated content
+    Merged,
+    /// Changes in the target file since it was last generated overlap with changes in the generated content, so the
+    /// target file has conflict markers (or, if it is not text, was left alone)
+    Conflict,
+    Ignored,
+    /// Lines were appended to a file that the niche did not create, so the file is not recorded as generated
+    Appended,
+    /// The niche filled the placeholders of a file that another niche generated
+    Contributed,
+    /// The niche generated the file before, but no longer does, so it was removed
+    Removed,
+    /// Writing the file failed with a fatal error (like a full disk), so the file may be truncated
+    MayBeTruncated,
+}
+
+impl FileAction {
+    /// Whether the action changed the content of the file.
+    fn is_write(self) -> bool {
+        matches!(self, FileAction::Written | FileAction::Appended | FileAction::Contributed)
+    }
+
+    /// Whether the file has the content that the niche generated for it, so that it can be removed when it is cleaned.
+    fn is_generated(self) -> bool {
+        matches!(self, FileAction::Written | FileAction::Unchanged)
+    }
+}
+
+#[derive(Serialize, Debug, Clone, Default, PartialEq)]
+pub struct Report {
+    niches: Vec<NicheReport>,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub struct NicheReport {
+    name: String,
+    duration_ms: u128,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    thundercloud: Option<String>,
+    files: Vec<FileReport>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    warnings: Vec<String>,
+    error: Option<String>,
+    #[serde(skip)]
+    prop_reads: Vec<PropRead>,
+}
+
+impl NicheReport {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn thundercloud(&self) -> Option<&str> {
+        self.thundercloud.as_deref()
+    }
+
+    pub fn warnings(&self) -> &[String] {
+        &self.warnings
+    }
+
+    /// The props that were interpolated in the target files of the niche, if the run audits props.
+    pub fn prop_reads(&self) -> &[PropRead] {
+        &self.prop_reads
+    }
+
+    /// The paths (relative to the project root) of the files that were written (or appended or contributed to).
+    pub fn written(&self) -> impl Iterator<Item = &str> {
+        self.files.iter()
+            .filter(|file_report| file_report.action.is_write())
+            .map(|file_report| file_report.path.as_str())
+    }
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct FileReport {
+    path: String,
+    action: FileAction,
+}
+
+impl Report {
+    pub fn write(&self, format: ReportFormat, report_file: Option<PathBuf>) -> Result<()> {
+        let body = match format {
+            ReportFormat::Json => serde_json::to_string_pretty(self)?,
+        };
+        if let Some(report_file) = report_file {
+            std::fs::write(report_file, body + "\n")?;
+        } else {
+            println!("{body}");
+        }
+        Ok(())
+    }
+
+    /// Fails if any niche failed, so that the process exits with a non-zero exit code.
+    pub fn check(&self) -> Result<()> {
+        let failed: Vec<&str> = self.niches.iter()
+            .filter(|niche_report| niche_report.error.is_some())
+            .map(|niche_report| niche_report.name.as_str())
+            .collect();
+        if !failed.is_empty() {
+            let truncated = self.truncated_files();
+            if !truncated.is_empty() {
+                bail!("Failed niches: {}; these files may be truncated: {}", failed.join(", "), truncated.join(", "));
+            }
+            bail!("Failed niches: {}", failed.join(", "));
+        }
+        Ok(())
+    }
+
+    /// Summarizes the result of the run in one line, like "Igor: 3 niches, 1 failed (example), 5 files changed".
+    pub fn summary(&self) -> String {
+        let failed: Vec<&str> = self.niches.iter()
+            .filter(|niche_report| niche_report.error.is_some())
+            .map(|niche_report| niche_report.name.as_str())
+            .collect();
+        let changed = self.niches.iter()
+            .flat_map(|niche_report| niche_report.files.iter())
+            .filter(|file_report| file_report.action.is_write())
+            .count();
+        let failed = if failed.is_empty() { "0 failed".to_string() } else { format!("{} failed ({})", failed.len(), failed.join(", ")) };
+        format!("Igor: {} niches, {failed}, {changed} files changed", self.niches.len())
+    }
+
+    /// The niches of the run, in the order that they finished.
+    pub fn niches(&self) -> &[NicheReport] {
+        &self.niches
+    }
+
+    fn truncated_files(&self) -> Vec<&str> {
+        self.niches.iter()
+            .flat_map(|niche_report| niche_report.files.iter())
+            .filter(|file_report| file_report.action == FileAction::MayBeTruncated)
+            .map(|file_report| file_report.path.as_str())
+            .collect()
+    }
+}
+
+/// Collects the reports of all niches of a run. Clones share the same report.
+#[derive(Debug, Clone, Default)]
+pub struct Reporter(Arc<Mutex<Report>>);
+
+impl Reporter {
+    pub fn add_niche(&self, niche: &NicheName, niche_reporter: NicheReporter, duration: Duration, result: &Result<()>) {
+        let files = niche_reporter.files.lock().map(|files| files.clone()).unwrap_or_default();
+        let thundercloud = niche_reporter.thundercloud.lock().map(|thundercloud| thundercloud.clone()).unwrap_or_default();
+        let warnings = niche_reporter.warnings.lock().map(|warnings| warnings.clone()).unwrap_or_default();
+        let prop_reads = niche_reporter.prop_reads.lock().map(|prop_reads| prop_reads.clone()).unwrap_or_default();
+        let niche_report = NicheReport {
+            name: niche.to_str().to_string(),
+            duration_ms: duration.as_millis(),
+            thundercloud,
+            files,
+            warnings,
+            error: result.as_ref().err().map(|err| format!("{err:#}")),
+            prop_reads,
+        };
+        if let Ok(mut report) = self.0.lock() {
+            report.niches.push(niche_report);
+        }
+    }
+
+    pub fn has_failures(&self) -> bool {
+        self.0.lock().map(|report| report.niches.iter().any(|niche_report| niche_report.error.is_some())).unwrap_or(false)
+    }
+
+    /// A file may be truncated after a fatal write error (like a full disk). The run should stop.
+    pub fn has_truncated_files(&self) -> bool {
+        self.0.lock().map(|report| !report.truncated_files().is_empty()).unwrap_or(false)
+    }
+
+    pub fn report(&self) -> Report {
+        self.0.lock().map(|report| report.clone()).unwrap_or_default()
+    }
+}
+
+/// Collects what happened to the target files of a single niche. Clones share the same list of files.
+#[derive(Debug, Clone, Default)]
+pub struct NicheReporter {
+    files: Arc<Mutex<Vec<FileReport>>>,
+    thundercloud: Arc<Mutex<Option<String>>>,
+    warnings: Arc<Mutex<Vec<String>>>,
+    prop_reads: Arc<Mutex<Vec<PropRead>>>,
+}
+
+impl NicheReporter {
+    /// Records which thundercloud (and which revision of it) the niche applied.
+    pub fn thundercloud(&self, description: String) {
+        if let Ok(mut thundercloud) = self.thundercloud.lock() {
+            *thundercloud = Some(description);
+        }
+    }
+
+    /// Records a problem that did not fail the niche, but that the user should look into.
+    pub fn warning(&self, warning: String) {
+        if let Ok(mut warnings) = self.warnings.lock() {
+            warnings.push(warning);
+        }
+    }
+
+    /// Records the props that were interpolated in a target file, for the audit of the run.
+    pub fn prop_reads(&self, reads: impl IntoIterator<Item=PropRead>) {
+        if let Ok(mut prop_reads) = self.prop_reads.lock() {
+            prop_reads.extend(reads);
+        }
+    }
+
+    /// Records what happened to a target file. A file that is recorded again keeps its first position, but gets the new
+    /// action. A file that was generated and then appended to remains generated.
+    pub fn file(&self, project_root: &AbsolutePath, target: &AbsolutePath, action: FileAction) {
+        let path = target.strip_prefix(project_root.as_path()).unwrap_or(target.as_path());
+        let file_report = FileReport { path: path.to_string_lossy().to_string(), action };
+        if let Ok(mut files) = self.files.lock() {
+            match files.iter_mut().find(|existing| existing.path == file_report.path) {
+                Some(existing) if existing.action.is_generated() && action == FileAction::Appended => {},
+                Some(existing) => existing.action = file_report.action,
+                None => files.push(file_report),
+            }
+        }
+    }
+
+    /// Returns the paths (relative to the project root) of the files that have the content that was generated for them.
+    pub fn generated(&self) -> Vec<String> {
+        let Ok(files) = self.files.lock() else { return Vec::new() };
+        files.iter()
+            .filter(|file_report| file_report.action.is_generated())
+            .map(|file_report| file_report.path.clone())
+            .collect()
+    }
+
+    /// Returns the paths (relative to the project root) of all target files of the niche, whatever happened to them.
+    pub fn targets(&self) -> BTreeSet<String> {
+        let Ok(files) = self.files.lock() else { return BTreeSet::new() };
+        files.iter().map(|file_report| file_report.path.clone()).collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use anyhow::anyhow;
+    use crate::path::test_utils::to_absolute_path;
+    use super::*;
+
+    #[test]
+    fn report_to_json() -> Result<()> {
+        // Given
+        let reporter = Reporter::default();
+        let niche_reporter = NicheReporter::default();
+        niche_reporter.file(&AbsolutePath::root(), &to_absolute_path("/workshop/clock.yaml"), FileAction::Written);
+        niche_reporter.file(&AbsolutePath::root(), &to_absolute_path("/workshop/mirror.yaml"), FileAction::Skipped);
+        reporter.add_niche(&NicheName::new("example"), niche_reporter, Duration::from_millis(42), &Ok(()));
+        reporter.add_niche(&NicheName::new("broken"), NicheReporter::default(), Duration::from_millis(7), &Err(anyhow!("No thundercloud")));
+
+        // When
+        let json = serde_json::to_value(reporter.report())?;
+
+        // Then
+        let expected = serde_json::json!({
+            "niches": [
+                {
+                    "name": "example",
+                    "duration-ms": 42,
+                    "files": [
+                        { "path": "workshop/clock.yaml", "action": "written" },
+                        { "path": "workshop/mirror.yaml", "action": "skipped" }
+                    ],
+                    "error": null
+                },
+                {
+                    "name": "broken",
+                    "duration-ms": 7,
+                    "files": [],
+                    "error": "No thundercloud"
+                }
+            ]
+        });
+        assert_eq!(json, expected);
+        Ok(())
+    }
+
+    #[test]
+    fn check_truncated_files() {
+        // Given
+        let reporter = Reporter::default();
+        let niche_reporter = NicheReporter::default();
+        niche_reporter.file(&AbsolutePath::root(), &to_absolute_path("/workshop/clock.yaml"), FileAction::MayBeTruncated);
+        reporter.add_niche(&NicheName::new("example"), niche_reporter, Duration::from_millis(42), &Err(anyhow!("No space left on device")));
+
+        // When
+        let result = reporter.report().check();
+
+        // Then
+        assert!(reporter.has_truncated_files());
+        let Err(error) = result else { panic!("Expected the check to fail") };
+        assert_eq!(error.to_string(), "Failed niches: example; these files may be truncated: workshop/clock.yaml");
+    }
+
+    #[test]
+    fn summary() {
+        // Given
+        let reporter = Reporter::default();
+        let niche_reporter = NicheReporter::default();
+        niche_reporter.file(&AbsolutePath::root(), &to_absolute_path("/workshop/clock.yaml"), FileAction::Written);
+        niche_reporter.file(&AbsolutePath::root(), &to_absolute_path("/workshop/mirror.yaml"), FileAction::Skipped);
+        reporter.add_niche(&NicheName::new("example"), niche_reporter, Duration::from_millis(42), &Ok(()));
+        reporter.add_niche(&NicheName::new("broken"), NicheReporter::default(), Duration::from_millis(7), &Err(anyhow!("Spring snapped")));
+
+        // When
+        let summary = reporter.report().summary();
+
+        // Then
+        assert_eq!(summary, "Igor: 2 niches, 1 failed (broken), 1 files changed");
+        assert_eq!(Report::default().summary(), "Igor: 0 niches, 0 failed, 0 files changed");
+    }
+}
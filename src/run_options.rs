@@ -0,0 +1,212 @@
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+use chrono::{DateTime, FixedOffset};
+use crate::config_model::ProjectConfig;
+use crate::hooks::AllowedCommands;
+use crate::interpolate;
+use crate::memory::MemoryBudget;
+use crate::shuffle::Shuffle;
+use crate::write_mode_override::WriteModeOverride;
+
+/// The options of a single run that change how niches are processed, usually taken from the command line. Every run of
+/// [`crate::application`] gets its own options, so that runs in the same process do not influence each other.
+#[derive(Debug, Clone, Default)]
+pub struct RunOptions {
+    write_mode_override: Option<WriteModeOverride>,
+    now: Option<DateTime<FixedOffset>>,
+    reproducible: Option<u64>,
+    shuffle_seed: Option<u64>,
+    audit_file: Option<PathBuf>,
+    explain_names: bool,
+    memory_ceiling: Option<usize>,
+    stall_timeout: Option<Duration>,
+    input: bool,
+    props_exec: bool,
+    changelog: bool,
+    offline: bool,
+    /// The programs that the run may start (see [`RunOptions::with_no_hooks`] and
+    /// [`RunOptions::with_allowed_hook_commands`]).
+    allowed_commands: AllowedCommands,
+    /// The order of the run that is in progress (see [`RunOptions::for_run`]).
+    shuffle: Shuffle,
+    /// The memory that the run in progress uses.
+    memory: Arc<MemoryBudget>,
+}
+
+impl RunOptions {
+    /// Replaces the configured write mode of the target files (see [`WriteModeOverride`]).
+    pub fn with_write_mode_override(mut self, write_mode_override: WriteModeOverride) -> Self {
+        self.write_mode_override = Some(write_mode_override);
+        self
+    }
+
+    pub fn write_mode_override(&self) -> Option<&WriteModeOverride> {
+        self.write_mode_override.as_ref()
+    }
+
+    /// Pins the date and time that placeholders like `{{now.year}}` refer to, for reproducible results.
+    pub fn with_now(mut self, now: DateTime<FixedOffset>) -> Self {
+        self.now = Some(now);
+        self
+    }
+
+    pub fn now(&self) -> Option<&DateTime<FixedOffset>> {
+        self.now.as_ref()
+    }
+
+    /// Makes the random values reproducible: every niche gets a seed that is derived from the given seed and the name of
+    /// the niche, whether or not the target files ask for stable random values. Niches, directory entries and target
+    /// files are visited in sorted order, and the time of the run is the Unix epoch unless it is pinned (or set by
+    /// `SOURCE_DATE_EPOCH`).
+    pub fn with_reproducible(mut self, seed: u64) -> Self {
+        self.reproducible = Some(seed);
+        self
+    }
+
+    pub fn reproducible(&self) -> Option<u64> {
+        self.reproducible
+    }
+
+    /// Randomizes the order in which independent niches are scheduled, directory entries are visited and target files
+    /// are generated, to flush out hidden ordering dependencies. The same seed gives the same order (as far as
+    /// concurrency allows).
+    pub fn with_shuffle(mut self, seed: u64) -> Self {
+        self.shuffle_seed = Some(seed);
+        self
+    }
+
+    /// Records every prop that is interpolated in a target file, and writes them to the given TOML file at the end of
+    /// the run.
+    pub fn with_audit_file(mut self, audit_file: PathBuf) -> Self {
+        self.audit_file = Some(audit_file);
+        self
+    }
+
+    pub fn audit_file(&self) -> Option<&Path> {
+        self.audit_file.as_deref()
+    }
+
+    /// Prints how the names of files in cumulus and invar directories are classified (on standard error), to debug
+    /// surprising bolts.
+    pub fn with_explain_names(mut self, explain_names: bool) -> Self {
+        self.explain_names = explain_names;
+        self
+    }
+
+    pub fn explain_names(&self) -> bool {
+        self.explain_names
+    }
+
+    /// Overrides the memory ceiling from `CargoCult.toml` (in MiB).
+    pub fn with_memory_ceiling(mut self, mib: usize) -> Self {
+        self.memory_ceiling = Some(mib);
+        self
+    }
+
+    pub fn memory_ceiling(&self) -> Option<usize> {
+        self.memory_ceiling
+    }
+
+    /// Makes the scheduler print its state on standard error whenever no niche finishes for the given number of seconds
+    /// (at least one), to diagnose wait-for relationships that never resolve.
+    pub fn with_debug_scheduler(mut self, seconds: u64) -> Self {
+        self.stall_timeout = Some(Duration::from_secs(seconds.max(1)));
+        self
+    }
+
+    pub fn stall_timeout(&self) -> Option<Duration> {
+        self.stall_timeout
+    }
+
+    /// Allows Igor to ask for the values of missing properties on the terminal. Input is disabled by default.
+    pub fn with_input(mut self, input: bool) -> Self {
+        self.input = input;
+        self
+    }
+
+    pub fn input(&self) -> bool {
+        self.input
+    }
+
+    /// Allows niches to run the shell commands of their `props-exec` sections. Running commands is not allowed by
+    /// default, because thunderclouds come from elsewhere.
+    pub fn with_props_exec(mut self, allowed: bool) -> Self {
+        self.props_exec = allowed;
+        self
+    }
+
+    pub fn props_exec(&self) -> bool {
+        self.props_exec
+    }
+
+    /// Makes Igor append an entry to the changelog after every run that changed files, also if the project does not
+    /// configure a `changelog-file`.
+    pub fn with_changelog(mut self, changelog: bool) -> Self {
+        self.changelog = changelog;
+        self
+    }
+
+    pub fn changelog(&self) -> bool {
+        self.changelog
+    }
+
+    /// Refuses to start any program, like `git` or `curl`, and skips the commands of `props-exec` sections instead of
+    /// running them (the props that they compute get no value).
+    pub fn with_no_hooks(mut self, no_hooks: bool) -> Self {
+        self.allowed_commands = self.allowed_commands.with_no_hooks(no_hooks);
+        self
+    }
+
+    pub fn no_hooks(&self) -> bool {
+        self.allowed_commands.no_hooks()
+    }
+
+    /// Forbids network access, so that thunderclouds that were not downloaded yet make their niches fail instead.
+    pub fn with_offline(mut self, offline: bool) -> Self {
+        self.offline = offline;
+        self
+    }
+
+    pub fn offline(&self) -> bool {
+        self.offline
+    }
+
+    /// Starts a run with these options: takes the date and time of the run (unless it is pinned), seeds the order of
+    /// the run and starts the bookkeeping of its memory, so that all niches of the run share them.
+    pub(crate) fn for_run(&self) -> RunOptions {
+        let shuffle = match (self.shuffle_seed, self.reproducible) {
+            (Some(seed), _) => Shuffle::with_seed(seed),
+            (None, Some(_)) => Shuffle::sorted(),
+            (None, None) => Shuffle::default(),
+        };
+        RunOptions { now: Some(self.run_time()), shuffle, memory: Arc::default(), ..self.clone() }
+    }
+
+    /// The date and time of the run: the pinned time, or else the time in `SOURCE_DATE_EPOCH`, or else the Unix epoch
+    /// for a reproducible run and the current time for other runs.
+    pub(crate) fn run_time(&self) -> DateTime<FixedOffset> {
+        match self.reproducible {
+            Some(_) if self.now.is_none() => interpolate::source_date_epoch().unwrap_or(DateTime::UNIX_EPOCH.fixed_offset()),
+            _ => interpolate::run_time(self.now.as_ref()),
+        }
+    }
+
+    /// Restricts the programs that the run may start to the `allowed-hook-commands` of the project of the run.
+    pub(crate) fn with_allowed_hook_commands<PC: ProjectConfig>(mut self, project_config: &PC) -> Self {
+        self.allowed_commands = AllowedCommands::new(project_config).with_no_hooks(self.no_hooks());
+        self
+    }
+
+    pub(crate) fn allowed_commands(&self) -> &AllowedCommands {
+        &self.allowed_commands
+    }
+
+    pub(crate) fn memory(&self) -> &Arc<MemoryBudget> {
+        &self.memory
+    }
+
+    pub(crate) fn shuffle<T, K: Ord>(&self, items: &mut [T], key: impl FnMut(&T) -> K) {
+        self.shuffle.shuffle(items, key);
+    }
+}
@@ -0,0 +1,68 @@
+use std::fmt::{Display, Formatter};
+
+/// Snapshot of the scheduler state. The lists are sorted, so that the output is stable.
+#[derive(Debug, Default)]
+pub struct SchedulerState {
+    /// Niches that still wait for other niches, with the number of niches that they wait for.
+    pub waiting: Vec<(String, usize)>,
+    /// Niches that no longer wait, but have no permit yet.
+    pub ready: Vec<String>,
+    /// Permits that are not used by a running niche.
+    pub outstanding_permits: usize,
+    /// Niches that were started (or are about to start) and have not finished yet.
+    pub running: Vec<String>,
+    /// Number of niches that finished.
+    pub done: usize,
+}
+
+impl SchedulerState {
+    pub fn sort(mut self) -> Self {
+        self.waiting.sort();
+        self.ready.sort();
+        self.running.sort();
+        self
+    }
+}
+
+impl Display for SchedulerState {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "Scheduler made no progress:")?;
+        let waiting: Vec<String> = self.waiting.iter().map(|(niche, count)| format!("{niche} ({count})")).collect();
+        writeln!(f, "  Waiting: {}", waiting.join(", "))?;
+        writeln!(f, "  Ready: {}", self.ready.join(", "))?;
+        writeln!(f, "  Outstanding permits: {}", self.outstanding_permits)?;
+        writeln!(f, "  Running: {}", self.running.join(", "))?;
+        write!(f, "  Done: {}", self.done)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use indoc::indoc;
+    use super::*;
+
+    #[test]
+    fn display_state() {
+        // Given
+        let state = SchedulerState {
+            waiting: vec![("mirror".to_string(), 2), ("clock".to_string(), 1)],
+            ready: vec!["spring".to_string()],
+            outstanding_permits: 0,
+            running: vec!["pendulum".to_string(), "glass".to_string()],
+            done: 3,
+        };
+
+        // When
+        let output = state.sort().to_string();
+
+        // Then
+        let expected = indoc! {"
+            Scheduler made no progress:
+              Waiting: clock (1), mirror (2)
+              Ready: spring
+              Outstanding permits: 0
+              Running: glass, pendulum
+              Done: 3"};
+        assert_eq!(output, expected);
+    }
+}
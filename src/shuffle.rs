@@ -0,0 +1,66 @@
+use std::sync::{Arc, Mutex};
+use fastrand::Rng;
+
+/// The order in which a run schedules independent niches, visits directory entries and generates target files. By
+/// default, that is the order in which the configuration and the file system list them.
+#[derive(Debug, Clone, Default)]
+pub struct Shuffle {
+    rng: Option<Arc<Mutex<Rng>>>,
+    sorted: bool,
+}
+
+impl Shuffle {
+    /// Randomizes the order, to flush out hidden ordering dependencies. The same seed gives the same order (as far as
+    /// concurrency allows).
+    pub fn with_seed(seed: u64) -> Self {
+        Shuffle { rng: Some(Arc::new(Mutex::new(Rng::with_seed(seed)))), sorted: false }
+    }
+
+    /// Makes the order independent of the order in which the file system lists niches, directory entries and target
+    /// files, by sorting them. For reproducible runs.
+    pub fn sorted() -> Self {
+        Shuffle { rng: None, sorted: true }
+    }
+
+    /// Shuffles the items if shuffling is enabled, sorts them if sorted order is enabled, and leaves them alone otherwise.
+    /// The items are sorted by the key before shuffling, so that the result does not depend on their original order.
+    pub fn shuffle<T, K: Ord>(&self, items: &mut [T], key: impl FnMut(&T) -> K) {
+        let Some(rng) = &self.rng else {
+            if self.sorted {
+                items.sort_by_key(key);
+            }
+            return;
+        };
+        let Ok(mut rng) = rng.lock() else { return };
+        shuffle_with(&mut rng, items, key);
+    }
+}
+
+fn shuffle_with<T, K: Ord>(rng: &mut Rng, items: &mut [T], key: impl FnMut(&T) -> K) {
+    items.sort_by_key(key);
+    rng.shuffle(items);
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn shuffle_with_seed() {
+        // Given
+        let original: Vec<u32> = (0..20).collect();
+        let mut reversed: Vec<u32> = original.iter().rev().copied().collect();
+        let mut first = original.clone();
+
+        // When
+        shuffle_with(&mut Rng::with_seed(4279), &mut first, |item| *item);
+        shuffle_with(&mut Rng::with_seed(4279), &mut reversed, |item| *item);
+
+        // Then
+        assert_ne!(first, original);
+        assert_eq!(first, reversed);
+        let mut sorted = first.clone();
+        sorted.sort();
+        assert_eq!(sorted, original);
+    }
+}
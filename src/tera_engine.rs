@@ -0,0 +1,49 @@
+use anyhow::Result;
+use toml::Table;
+
+/// Renders a whole option with Tera, with the props as context. Tera's own syntax applies: `{{ sweeper }}`,
+/// `{% if glass %}`, `{% for part in parts %}` and filters like `{{ name | upper }}`.
+#[cfg(feature = "tera")]
+pub fn render(source: &str, template: &str, props: &Table) -> Result<String> {
+    let context = tera::Context::from_serialize(props)?;
+    tera::Tera::one_off(template, &context, false)
+        .map_err(|error| anyhow::Error::new(error).context(format!("{source}: Tera template failed")))
+}
+
+#[cfg(not(feature = "tera"))]
+pub fn render(source: &str, _template: &str, _props: &Table) -> Result<String> {
+    anyhow::bail!("{source}: Template engine tera is not available: Igor was built without feature \"tera\"")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[cfg(feature = "tera")]
+    #[test]
+    fn render_props() -> Result<()> {
+        // Given
+        let props: Table = toml::from_str("sweeper = \"Lu Tse\"\nparts = [\"pendulum\", \"spring\"]\nglass = true\n")?;
+        let template = "sweeper: {{ sweeper | upper }}\n{% for part in parts %}- {{ part }}{% if glass %} (glass){% endif %}\n{% endfor %}";
+
+        // When
+        let rendered = render("clock+option-@.yaml", template, &props)?;
+
+        // Then
+        assert_eq!(rendered, "sweeper: LU TSE\n- pendulum (glass)\n- spring (glass)\n");
+        let error = render("clock+option-@.yaml", "{% if %}", &props).unwrap_err();
+        assert!(format!("{error:#}").starts_with("clock+option-@.yaml: Tera template failed: "), "Actual error: {error:#}");
+        Ok(())
+    }
+
+    #[cfg(not(feature = "tera"))]
+    #[test]
+    fn render_without_feature() {
+        // When
+        let result = render("clock+option-@.yaml", "{{ sweeper }}", &Table::new());
+
+        // Then
+        let error = result.unwrap_err();
+        assert!(error.to_string().contains("without feature \"tera\""), "Actual error: {error}");
+    }
+}
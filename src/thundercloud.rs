@@ -1,67 +1,233 @@
 use ahash::{AHashMap, AHashSet};
 use anyhow::{anyhow, bail, Result};
 use std::borrow::Cow;
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, BTreeSet};
 use std::hash::Hash;
 use std::ops::Add;
 use std::path::Path;
 use std::pin::pin;
+use std::sync::{Mutex, MutexGuard};
+use globset::Glob;
 use log::{debug, info, trace, warn};
 use once_cell::sync::Lazy;
 use regex::{Captures, Regex};
+use serde::Serialize;
+use toml::{Table, Value};
 use tokio_stream::StreamExt;
-use crate::config_model::{invar_config, InvarConfig, NicheDescription, thundercloud_config, ThundercloudConfig, ThunderConfig, WriteMode};
+use crate::config_model::{document, invar_config, Anchor, AnchorPosition, CombineFragments, FragmentOrder, InvarConfig, LineEndings, NicheDescription, PlaceholderDelimiters, PropDeclaration, SharedTargetRole, TemplateEngine, thundercloud_config, ThundercloudConfig, ThunderConfig, UnknownBolts, WriteMode};
 use crate::path::{AbsolutePath, RelativePath, SingleComponent};
 use crate::thundercloud::Thumbs::{FromBothCumulusAndInvar, FromCumulus, FromInvar};
 use crate::config_model::UseThundercloudConfig;
-use crate::file_system::{source_file_to_string, ConfigFormat, DirEntry, FileSystem, PathType, SourceFile, TargetFile};
+use crate::file_system::{copy_chunks, detect_line_endings, source_file_to_string, BufferedTargetFile, ConfigFormat, CrlfTargetFile, DirEntry, FatalWriteError, FileSystem, LinesSourceFile, MemoryTargetFile, PathType, SourceFile, TargetFile};
 use crate::thundercloud::DirectoryContext::{Project, ThunderCloud};
+use crate::report::{FileAction, NicheReporter};
+use crate::audit::PropRead;
+use crate::functions::{self, FunctionRegistry};
+use crate::generators::{self, RandomValues};
+use crate::merge;
+use crate::patch;
+use crate::tera_engine;
+use crate::run_options::RunOptions;
+use crate::write_mode_override::WriteModeOverride;
+use crate::interpolate::{CONTEXT_PROPS, ENVIRONMENT_PROPS, NOW_PROPS, TIMESTAMP_PROP};
 
-pub async fn process_niche<T: ThunderConfig>(thunder_config: T) -> Result<()> {
-    let generation_context = GenerationContext(thunder_config);
+pub async fn process_niche<T: ThunderConfig>(thunder_config: T, run_options: RunOptions, functions: FunctionRegistry, niche_reporter: NicheReporter) -> Result<()> {
+    let generation_context = GenerationContext::new(thunder_config, niche_reporter).with_run_options(run_options).with_functions(functions);
     process_niche_in_context(&generation_context).await
 }
 
 async fn process_niche_in_context<T: ThunderConfig>(generation_context: &GenerationContext<T>) -> Result<()> {
-    let thundercloud_fs = generation_context.0.thundercloud_file_system();
-    let thundercloud_directory = generation_context.0.thundercloud_directory();
-    let cumulus = generation_context.0.cumulus();
-    let invar = generation_context.0.invar();
-    let project_root = generation_context.0.project_root();
+    let mut plan = plan_niche_in_context(generation_context).await?;
+    let _plan_memory = generation_context.run_options.memory().track(plan.approximate_size());
+    generation_context.prepare_random_values(&plan).await?;
+    generation_context.run_options.shuffle(&mut plan.targets, |target_plan| target_plan.target.to_path_buf());
+    for plan in plan.targets {
+        if generation_context.run_options.audit_file().is_some() {
+            generation_context.reporter.prop_reads(generation_context.prop_reads(&plan).await?);
+        }
+        let role = generation_context.shared_target_role(&plan.target);
+        generation_context.generate_file(&plan.target, plan.option, plan.fragments, plan.patches, &plan.invar_config, role).await?;
+        generation_context.append_to_target(&plan.target, &plan.appends, &plan.invar_config).await?;
+    }
+    Ok(())
+}
+
+/// Collects the features that the names of the bolts of a niche mention, in the thundercloud as well as in the invar.
+pub async fn defined_features<T: ThunderConfig>(thunder_config: T, run_options: &RunOptions) -> Result<BTreeSet<String>> {
+    let generation_context = GenerationContext::new(thunder_config, NicheReporter::default()).with_run_options(run_options.clone());
+    let plan = plan_niche_in_context(&generation_context).await?;
+    Ok(plan.defined_features)
+}
+
+/// Proposes values for the placeholders of a niche by matching the templates of the thundercloud
+/// against the files that already exist in the project.
+pub async fn infer_props<T: ThunderConfig>(thunder_config: T, run_options: &RunOptions) -> Result<Table> {
+    let generation_context = GenerationContext::new(thunder_config, NicheReporter::default()).with_run_options(run_options.clone());
+    let plan = plan_niche_in_context(&generation_context).await?;
+    let mut props = Table::new();
+    for plan in plan.targets {
+        generation_context.infer_target_props(plan, &mut props).await?;
+    }
+    Ok(props)
+}
+
+/// Collects the names of the placeholders in the selected options and fragments of a niche that have no value (in order
+/// of appearance, without duplicates).
+pub async fn missing_props<T: ThunderConfig>(thunder_config: T, run_options: &RunOptions) -> Result<Vec<String>> {
+    let generation_context = GenerationContext::new(thunder_config, NicheReporter::default()).with_run_options(run_options.clone());
+    let plan = plan_niche_in_context(&generation_context).await?;
+    let mut missing = Vec::new();
+    for target_plan in plan.targets {
+        let Some(option) = &target_plan.option else { continue };
+        let invar_config = &target_plan.invar_config;
+        if !invar_config.interpolate() || invar_config.write_mode() == WriteMode::Ignore {
+            continue;
+        }
+        for bolt in std::iter::once(option).chain(target_plan.fragments.iter()) {
+            let content = generation_context.get_bolt_content(bolt).await?;
+            for name in crate::interpolate::missing_placeholders(&content, invar_config.props().as_ref(), invar_config.placeholder_delimiters().as_ref()) {
+                if name != generators::UUID && !missing.contains(&name) {
+                    missing.push(name);
+                }
+            }
+        }
+    }
+    Ok(missing)
+}
+
+/// Computes the effective invar configuration of a target file or directory of a niche after all merges. Returns the
+/// target file or the closest target directory that the configuration belongs to.
+pub async fn effective_config<T: ThunderConfig>(thunder_config: T, run_options: &RunOptions, target: &AbsolutePath) -> Result<Option<(AbsolutePath, Table)>> {
+    let generation_context = GenerationContext::new(thunder_config, NicheReporter::default()).with_run_options(run_options.clone());
+    let plan = plan_niche_in_context(&generation_context).await?;
+    if let Some(target_plan) = plan.targets.iter().find(|target_plan| &target_plan.target == target) {
+        return Ok(Some((target_plan.target.clone(), describe_invar_config(&target_plan.invar_config))));
+    }
+    let directory_plan = plan.directories.iter()
+        .filter(|directory_plan| target.starts_with(directory_plan.target.as_path()))
+        .max_by_key(|directory_plan| directory_plan.target.components().count());
+    Ok(directory_plan.map(|directory_plan| (directory_plan.target.clone(), invar_config::to_table(&directory_plan.invar_config))))
+}
+
+/// Describes how a target file of a niche is produced: the selected features, the option and fragments that make up its
+/// content, the patches and appends that change it, the configuration files that apply to it (in order), and the resulting invar
+/// configuration.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub struct Explanation {
+    features: Vec<String>,
+    option: Option<String>,
+    fragments: Vec<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    patches: Vec<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    appends: Vec<String>,
+    configs: Vec<String>,
+    invar_config: Table,
+}
+
+/// Explains which bolts of a niche produce the given target file. Returns `None` if the niche does not produce the
+/// target file.
+pub async fn explain<T: ThunderConfig>(thunder_config: T, run_options: &RunOptions, target: &AbsolutePath) -> Result<Option<Explanation>> {
+    let generation_context = GenerationContext::new(thunder_config, NicheReporter::default()).with_run_options(run_options.clone());
+    let plan = plan_niche_in_context(&generation_context).await?;
+    let Some(target_plan) = plan.targets.into_iter().find(|target_plan| &target_plan.target == target) else { return Ok(None) };
+    let explanation = Explanation {
+        features: generation_context.thunder_config.use_thundercloud().features().to_vec(),
+        option: target_plan.option.as_ref().map(|bolt| generation_context.describe_bolt(bolt)),
+        fragments: target_plan.fragments.iter().map(|bolt| generation_context.describe_bolt(bolt)).collect(),
+        patches: target_plan.patches.iter().map(|bolt| generation_context.describe_bolt(bolt)).collect(),
+        appends: target_plan.appends.iter().map(|bolt| generation_context.describe_bolt(bolt)).collect(),
+        configs: target_plan.configs.iter().map(|bolt| generation_context.describe_bolt(bolt)).collect(),
+        invar_config: describe_invar_config(&target_plan.invar_config),
+    };
+    Ok(Some(explanation))
+}
+
+/// Converts the invar configuration of a target file to a table for display. The date and time of the run and the props
+/// that describe the context of the niche are left out, because they are the same for every target file.
+fn describe_invar_config<IC: InvarConfig>(invar_config: &IC) -> Table {
+    let mut table = invar_config::to_table(invar_config);
+    if let Some(Value::Table(props)) = table.get_mut("props") {
+        props.remove(NOW_PROPS);
+        props.remove(TIMESTAMP_PROP);
+        for name in CONTEXT_PROPS {
+            props.remove(name);
+        }
+    }
+    table
+}
+
+async fn plan_niche_in_context<T: ThunderConfig>(generation_context: &GenerationContext<T>) -> Result<NichePlan<impl InvarConfig>> {
+    let thundercloud_fs = generation_context.thunder_config.thundercloud_file_system();
+    let thundercloud_directory = generation_context.thunder_config.thundercloud_directory();
+    let cumulus = generation_context.thunder_config.cumulus();
+    let invar = generation_context.thunder_config.invar();
+    let project_root = generation_context.thunder_config.project_root();
     info!("Apply: {:?} ⊕ {:?} ⇒ {:?}", cumulus, invar, project_root);
     let config = get_config(thundercloud_directory, thundercloud_fs).await?;
     let niche = config.niche();
     info!("Thundercloud: {:?}: {:?}", niche.name(), niche.description().unwrap_or(&"-".to_string()));
-    debug!("Use thundercloud: {:?}", generation_context.0.use_thundercloud());
-    let current_directory = RelativePath::from(".");
-    let invar_config = config.invar_defaults();
-    let invar_defaults = generation_context.0.default_invar_config().clone();
-    let invar_config = invar_config.with_invar_config(invar_defaults);
+    let thundercloud_name = niche.name().to_string();
+    debug!("Use thundercloud: {:?}", generation_context.thunder_config.use_thundercloud());
+    let invar_defaults = generation_context.thunder_config.default_invar_config().clone();
+    let use_thundercloud_invar_defaults = generation_context.thunder_config.use_thundercloud().invar_defaults().into_owned();
+    let invar_config = invar_config::empty()
+        .with_invar_config(config.invar_defaults().into_owned())
+        .with_invar_config(invar_defaults)
+        .with_invar_config(use_thundercloud_invar_defaults)
+        .into_owned();
+    let niche_name = generation_context.thunder_config.invar().parent().and_then(Path::file_name).map(|name| name.to_string_lossy().to_string()).unwrap_or_default();
+    let context_props = crate::interpolate::context_props(&niche_name, project_root, generation_context.thunder_config.use_thundercloud().features(), &thundercloud_name);
+    // The commands of props see the props of the niche (so project-level commands can refer to niche-level props)
+    let now_props = crate::interpolate::now_props(&generation_context.run_options.run_time());
+    let mut command_props = invar_config.props().into_owned();
+    command_props.extend(now_props.clone());
+    command_props.extend(context_props.clone());
+    let exec_props = crate::props_exec::exec_props(invar_config.props_exec().as_ref(), &command_props, invar_config.placeholder_delimiters().as_ref(), project_root, &generation_context.run_options).await?;
+    let invar_config = invar_config.with_props(exec_props).into_owned();
     debug!("String properties: {:?}", invar_config.string_props());
-    generation_context.visit_subtree(&current_directory, FromBothCumulusAndInvar, invar_config.as_ref()).await?;
-    Ok(())
+    let mut plan = generation_context.plan_niche(&invar_config).await?;
+    plan.check_unknown_bolts(config.unknown_bolts(), &niche_name)?;
+    plan.combine_fragments();
+    let mut reserved_props = now_props;
+    reserved_props.extend(context_props);
+    if let Some(environment) = generation_context.thunder_config.default_invar_config().props().get(ENVIRONMENT_PROPS) {
+        reserved_props.insert(ENVIRONMENT_PROPS.to_string(), environment.clone());
+    }
+    plan.apply_reserved_props(&reserved_props);
+    plan.apply_placeholder_delimiters_by_extension(&config.placeholder_delimiters_by_extension(), &invar_config.placeholder_delimiters_option());
+    if let Some(write_mode_override) = generation_context.run_options.write_mode_override() {
+        plan.apply_write_mode_override(write_mode_override, project_root);
+    }
+    plan.resolve_props()?;
+    plan.check_props(config.prop_declarations(), &niche_name)?;
+    Ok(plan)
 }
 
-async fn get_config<FS: FileSystem>(thundercloud_directory: &AbsolutePath, fs: FS) -> Result<impl ThundercloudConfig> {
+pub async fn get_config<FS: FileSystem>(thundercloud_directory: &AbsolutePath, fs: FS) -> Result<impl ThundercloudConfig> {
     debug!("Get config: {:?}", thundercloud_directory);
-    let source_file;
+    let config_path;
     let config_format;
     let config_toml = AbsolutePath::new("thundercloud.toml", &thundercloud_directory);
     if fs.path_type(&config_toml).await == PathType::File {
-        source_file = fs.open_source(config_toml).await?;
+        config_path = config_toml;
         config_format = ConfigFormat::TOML;
     } else {
-        let config_yaml = AbsolutePath::new("thundercloud.yaml", &thundercloud_directory);
-        source_file = fs.open_source(config_yaml).await?;
+        config_path = AbsolutePath::new("thundercloud.yaml", &thundercloud_directory);
         config_format = ConfigFormat::YAML;
     }
+    let source_file = fs.open_source(config_path.clone()).await?;
     let body = source_file_to_string(source_file).await?;
-    let config = thundercloud_config::from_str(&body, config_format)?;
+    let config = thundercloud_config::from_str(&body, config_format)
+        .map_err(|error| document::locate_error(&config_path, &body, error))?;
 
     debug!("Thundercloud configuration: {config:?}");
     Ok(config)
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
 enum DirectoryContext { ThunderCloud, Project }
 
 #[derive(Debug, Clone)]
@@ -79,16 +245,35 @@ struct Bolt {
     kind: BoltKind,
 }
 
+/// The option, the fragments, the patches and the appends for a target file.
+type SelectedBolts = (Option<Bolt>, Vec<Bolt>, Vec<Bolt>, Vec<Bolt>);
+
 #[derive(Debug, Clone)]
 enum BoltKind {
-    Option,
+    Option {
+        fallback: bool
+    },
     Fragment {
-        qualifier: Option<String>
+        qualifier: Option<String>,
+        order: Option<FragmentOrder>,
     },
     Config {
         format: ConfigFormat
     },
+    /// A snippet that options and fragments include with `==== INCLUDE path ====`. It is not a target on its own.
+    Include,
+    /// A unified diff that is applied to the generated content of the target file, after the option and fragments.
+    Patch,
+    /// Lines that are appended to the target file, whether it was generated from an option or not.
+    Append,
+    /// Suppresses the target file (named `+delete` or `+skip`), so that a project can opt out of a file of the cumulus.
+    Tombstone,
+    /// Generates a symbolic link instead of a file. The first line of the bolt is the target of the link.
+    Symlink,
+    /// An option that is copied to the target file byte for byte (like an image or a keystore), without interpolation.
+    Binary,
     Unknown {
+        bolt_type: String,
         qualifier: Option<String>
     },
 }
@@ -96,15 +281,25 @@ enum BoltKind {
 impl Bolt {
     fn kind_name(&self) -> &'static str {
         match self.kind {
-            BoltKind::Option => "option",
+            BoltKind::Option { .. } => "option",
             BoltKind::Config { .. } => "config",
             BoltKind::Fragment { .. } => "fragment",
+            BoltKind::Include => "include",
+            BoltKind::Patch => "patch",
+            BoltKind::Append => "append",
+            BoltKind::Tombstone => "tombstone",
+            BoltKind::Symlink => "symlink",
+            BoltKind::Binary => "binary",
             BoltKind::Unknown { .. } => "unknown",
         }
     }
     fn base_name(&self) -> String {
         self.base_name.clone()
     }
+    /// Approximate number of bytes that the bolt occupies in the index of a niche.
+    fn approximate_size(&self) -> usize {
+        size_of::<Bolt>() + self.base_name.len() + self.extension.len() + self.feature_name.len() + self.source.path.as_os_str().len()
+    }
     fn extension(&self) -> String {
         self.extension.clone()
     }
@@ -119,6 +314,25 @@ impl Bolt {
         &self.source.path
     }
     fn context(&self) -> DirectoryContext { self.source.context }
+    /// The order of a fragment among the fragments for the same placeholder: the order that the invar configuration
+    /// specifies for its file name, otherwise the order in its name.
+    fn fragment_order<IC: InvarConfig>(&self, invar_config: &IC) -> FragmentOrder {
+        let configured = invar_config.fragment_order();
+        let file_name = self.source().file_name().map(|file_name| file_name.to_string_lossy().to_string()).unwrap_or_default();
+        let context = match self.context() {
+            ThunderCloud => "thundercloud",
+            Project => "project",
+        };
+        let order_in_name = match &self.kind {
+            BoltKind::Fragment { order, .. } => *order,
+            _ => None,
+        };
+        configured.get(&format!("{context}:{file_name}"))
+            .or_else(|| configured.get(&file_name))
+            .copied()
+            .or(order_in_name)
+            .unwrap_or_default()
+    }
     fn qualifier(&self) -> Option<String> {
         match &self.kind {
             BoltKind::Fragment { qualifier, .. } => qualifier.clone(),
@@ -132,10 +346,10 @@ static CONFIG_REGEX: Lazy<Regex> = Lazy::new(|| {
     Regex::new("^(?<base>.*)[+]config(-(?<feature>[a-z0-9_]+|@))?(?<extension>[.][^.]*)?[.](?<format>toml|yaml)$").unwrap()
 });
 static BOLT_REGEX_WITH_DOT: Lazy<Regex> = Lazy::new(|| {
-    Regex::new("^(?<base>.*)[+](?<bolt_type>[a-z0-9_]+)(-(?<feature>[a-z0-9_]+|@)(-(?<qualifier>[a-z0-9_]+))?)?(?<extension>[.][^.]*)$").unwrap()
+    Regex::new("^(?<base>.*)[+](?<bolt_type>[a-z0-9_]+)(-(?<feature>[a-z0-9_]+|@)(-(?<qualifier>[a-z0-9_]+))?)?(~(?<order>-?[0-9]+|first|last))?(?<extension>[.][^.]*)$").unwrap()
 });
 static BOLT_REGEX_WITHOUT_DOT: Lazy<Regex> = Lazy::new(|| {
-    Regex::new("^(?<base>[^.]+)[+](?<bolt_type>[a-z0-9_]+)(-(?<feature>[a-z0-9_]+|@)(-(?<qualifier>[a-z0-9_]+))?)?$").unwrap()
+    Regex::new("^(?<base>[^.]+)[+](?<bolt_type>[a-z0-9_]+)(-(?<feature>[a-z0-9_]+|@)(-(?<qualifier>[a-z0-9_]+))?)?(~(?<order>-?[0-9]+|first|last))?$").unwrap()
 });
 static PLAIN_FILE_REGEX_WITH_DOT: Lazy<Regex> = Lazy::new(|| {
     Regex::new("^(?<base>.*)(?<extension>[.][^.]*)").unwrap()
@@ -144,10 +358,76 @@ static ILLEGAL_FILE_REGEX: Lazy<Regex> = Lazy::new(|| {
     Regex::new("^([.][.]?)?$").unwrap()
 });
 
+static CONDITION_REGEX: Lazy<Regex> = Lazy::new(|| {
+    Regex::new("==== (?<directive>IF|ELSE|ENDIF)(?: (?<negate>!)?(?<condition>[A-Za-z_][-A-Za-z0-9_.]*))? ====").unwrap()
+});
+
+static LOOP_REGEX: Lazy<Regex> = Lazy::new(|| {
+    Regex::new("==== (?<directive>FOR|ENDFOR)(?: (?<item>[A-Za-z_][A-Za-z0-9_]*) IN (?<list>[A-Za-z_][-A-Za-z0-9_.]*))? ====").unwrap()
+});
+
+static INCLUDE_REGEX: Lazy<Regex> = Lazy::new(|| {
+    Regex::new("==== INCLUDE (?<path>[^ ]+) ====").unwrap()
+});
+
+static KEEP_REGEX: Lazy<Regex> = Lazy::new(|| {
+    Regex::new("==== (?:(?<bracket>BEGIN|END) )?KEEP(?: (?<name>[A-Za-z0-9_-]+))? ====").unwrap()
+});
+
+/// Included files can include other files, but not deeper than this (which usually means that they include each other).
+const MAX_INCLUDE_DEPTH: usize = 16;
+
+/// The directory (next to the invar of a niche) that holds the content that was last generated for target files with
+/// `merge = true`.
+pub const BASELINE_DIRECTORY: &str = "baseline";
+
+/// Fragments with these qualifiers go to the top or the bottom of the target file if the option has no placeholder for them.
+const HEAD_ANCHOR: &str = "head";
+const TAIL_ANCHOR: &str = "tail";
+
+const FRAGMENT_MARKER: &str = "(?<bracket>(BEGIN|END) )?FRAGMENT (?<feature>[a-z0-9_]+|@)(-(?<qualifier>[a-z0-9_]+))?";
+
 static FRAGMENT_REGEX: Lazy<Regex> = Lazy::new(|| {
-    Regex::new("==== (?<bracket>(BEGIN|END) )?FRAGMENT (?<feature>[a-z0-9_]+|@)(-(?<qualifier>[a-z0-9_]+))? ====").unwrap()
+    Regex::new(&format!("==== {FRAGMENT_MARKER} ====")).unwrap()
 });
 
+/// Recognizes the fragment markers of a target file. By default, a marker like `==== BEGIN FRAGMENT glass-spring ====`
+/// can appear anywhere in a line. Setting `marker-syntax` (for instance `<!-- ... -->` for HTML files) makes the marker
+/// the only content of the line, between the text before and after the `...`.
+#[derive(Debug, Clone)]
+struct FragmentMarkers(Regex);
+
+impl Default for FragmentMarkers {
+    fn default() -> Self {
+        FragmentMarkers(FRAGMENT_REGEX.clone())
+    }
+}
+
+impl FragmentMarkers {
+    fn new<IC: InvarConfig>(invar_config: &IC) -> Result<Self> {
+        let Some(marker_syntax) = invar_config.marker_syntax_option() else { return Ok(FragmentMarkers::default()) };
+        let Some((before, after)) = marker_syntax.split_once("...") else {
+            bail!("Marker syntax should contain '...' where the marker goes: {marker_syntax:?}");
+        };
+        let pattern = format!(r"^\s*{}\s*{FRAGMENT_MARKER}\s*{}\s*$", regex::escape(before.trim()), regex::escape(after.trim()));
+        Ok(FragmentMarkers(Regex::new(&pattern)?))
+    }
+
+    fn captures<'h>(&self, line: &'h str) -> Option<Captures<'h>> {
+        self.0.captures(line)
+    }
+
+    fn is_match(&self, line: &str) -> bool {
+        self.0.is_match(line)
+    }
+
+    fn is_bracket(&self, line: &str, bracket: &str) -> bool {
+        self.captures(line)
+            .and_then(|captures| captures.name("bracket").map(|m| m.as_str() == bracket))
+            .unwrap_or(false)
+    }
+}
+
 #[derive(Clone, Copy)]
 enum Thumbs {
     FromCumulus,
@@ -209,61 +489,318 @@ impl<FS: FileSystem> DirectoryLocation for CumulusDirectoryLocation<FS> {
     }
 }
 
-struct GenerationContext<TC: ThunderConfig>(TC);
+struct GenerationContext<TC: ThunderConfig> {
+    thunder_config: TC,
+    reporter: NicheReporter,
+    run_options: RunOptions,
+    functions: FunctionRegistry,
+    random: Mutex<RandomValues>,
+}
+
+/// The target file that a niche generates, along with the bolts and configuration that produce it
+#[derive(Debug, Clone)]
+struct TargetPlan<IC: InvarConfig> {
+    target: AbsolutePath,
+    option: Option<Bolt>,
+    fragments: Vec<Bolt>,
+    patches: Vec<Bolt>,
+    appends: Vec<Bolt>,
+    configs: Vec<Bolt>,
+    invar_config: IC,
+}
+
+/// A target directory of a niche, along with the configuration that its files and subdirectories inherit
+#[derive(Debug, Clone)]
+struct DirectoryPlan<IC: InvarConfig> {
+    target: AbsolutePath,
+    invar_config: IC,
+}
+
+#[derive(Debug, Clone)]
+struct NichePlan<IC: InvarConfig> {
+    directories: Vec<DirectoryPlan<IC>>,
+    targets: Vec<TargetPlan<IC>>,
+    /// The number of files for each unknown bolt type. They are left out of generation.
+    unknown_bolt_types: BTreeMap<String, usize>,
+    /// The features that the names of the bolts mention, whether they are selected or not.
+    defined_features: BTreeSet<String>,
+}
+
+impl<IC: InvarConfig> NichePlan<IC> {
+    /// Approximate number of bytes that the plan occupies, for the memory ceiling of the run. The invar configurations
+    /// are only counted by their inline size.
+    fn approximate_size(&self) -> usize {
+        let targets: usize = self.targets.iter()
+            .map(|target_plan| {
+                let bolts: usize = target_plan.option.iter().chain(&target_plan.fragments).chain(&target_plan.patches).chain(&target_plan.appends).chain(&target_plan.configs).map(Bolt::approximate_size).sum();
+                size_of::<TargetPlan<IC>>() + target_plan.target.as_os_str().len() + bolts
+            })
+            .sum();
+        targets + self.directories.len() * size_of::<DirectoryPlan<IC>>()
+    }
+
+    fn check_unknown_bolts(&self, unknown_bolts: UnknownBolts, niche_name: &str) -> Result<()> {
+        if self.unknown_bolt_types.is_empty() || unknown_bolts == UnknownBolts::Ignore {
+            return Ok(());
+        }
+        let bolt_types: Vec<String> = self.unknown_bolt_types.iter()
+            .map(|(bolt_type, count)| format!("{bolt_type} ({count})"))
+            .collect();
+        if unknown_bolts == UnknownBolts::Error {
+            bail!("Unknown bolt types in niche {niche_name}: {}", bolt_types.join(", "));
+        }
+        warn!("Unknown bolt types in niche {niche_name} (files are left out): {}", bolt_types.join(", "));
+        Ok(())
+    }
+
+    /// Decides per target file which fragments fill a placeholder that several fragments provide: with `Replace`, the
+    /// fragments in the invar replace those in the cumulus; with `Concatenate`, all of them are included. Fragments are
+    /// sorted by their order, then those of the cumulus first, and otherwise in the order of their paths.
+    fn combine_fragments(&mut self) {
+        for target_plan in &mut self.targets {
+            let invar_config = &target_plan.invar_config;
+            target_plan.fragments.sort_by_cached_key(|bolt| (bolt.fragment_order(invar_config).sort_key(), bolt.context(), bolt.source().to_path_buf()));
+            if target_plan.invar_config.combine_fragments() == CombineFragments::Replace {
+                let invar_fragments: AHashSet<(String, Option<String>)> = target_plan.fragments.iter()
+                    .filter(|bolt| bolt.context() == Project)
+                    .map(|bolt| (bolt.feature_name(), bolt.qualifier()))
+                    .collect();
+                target_plan.fragments.retain(|bolt| bolt.context() == Project || !invar_fragments.contains(&(bolt.feature_name(), bolt.qualifier())));
+            }
+        }
+    }
+
+    /// Applies the placeholder delimiters that the thundercloud specifies for the extension of a target file, unless the
+    /// configuration of the target file (or its directory) overrides the placeholder delimiters of the niche.
+    fn apply_placeholder_delimiters_by_extension(&mut self, placeholder_delimiters_by_extension: &BTreeMap<String, PlaceholderDelimiters>, niche_placeholder_delimiters: &Option<PlaceholderDelimiters>) {
+        for target_plan in &mut self.targets {
+            if &target_plan.invar_config.placeholder_delimiters_option() != niche_placeholder_delimiters {
+                continue;
+            }
+            let extension = target_plan.target.extension().map(|extension| extension.to_string_lossy().to_string());
+            let Some(placeholder_delimiters) = extension.and_then(|extension| placeholder_delimiters_by_extension.get(&extension)) else { continue };
+            target_plan.invar_config = target_plan.invar_config.with_placeholder_delimiters_option(Some(placeholder_delimiters.clone())).into_owned();
+        }
+    }
+
+    /// Applies the props that Igor provides (like the allowed environment variables in table `env`) to every target
+    /// file, so that the configuration of the niche or of target files cannot override them.
+    fn apply_reserved_props(&mut self, reserved_props: &Table) {
+        for target_plan in &mut self.targets {
+            target_plan.invar_config = target_plan.invar_config.with_props(reserved_props.clone()).into_owned();
+        }
+    }
+
+    /// Resolves the placeholders in the props of every target file, so that props can be derived from other props.
+    fn resolve_props(&mut self) -> Result<()> {
+        for target_plan in &mut self.targets {
+            let invar_config = &target_plan.invar_config;
+            let props = crate::interpolate::resolve_props(&invar_config.props(), &invar_config.placeholder_delimiters())
+                .map_err(|error| anyhow!("{error}: {:?}", target_plan.target))?;
+            target_plan.invar_config = invar_config.with_props(props).into_owned();
+        }
+        Ok(())
+    }
+
+    /// Checks the props of every target file against the props that the thundercloud declares, and fails with a list
+    /// of the required props that are missing and the props whose values have the wrong type.
+    fn check_props(&self, declarations: &[PropDeclaration], niche_name: &str) -> Result<()> {
+        let mut problems = BTreeSet::new();
+        for target_plan in &self.targets {
+            let props = target_plan.invar_config.props();
+            for declaration in declarations {
+                match crate::interpolate::lookup(&props, declaration.name()) {
+                    None if declaration.required() => {
+                        let description = declaration.description().map(|description| format!(" ({description})")).unwrap_or_default();
+                        problems.insert(format!("missing {}{description}", declaration.name()));
+                    },
+                    Some(value) => {
+                        let Some(prop_type) = declaration.prop_type() else { continue };
+                        if !prop_type.matches(value) {
+                            problems.insert(format!("{} should be {prop_type}, but is {}", declaration.name(), value.type_str()));
+                        }
+                    },
+                    None => (),
+                }
+            }
+        }
+        if !problems.is_empty() {
+            bail!("Invalid props for niche {niche_name}: {}", problems.into_iter().collect::<Vec<_>>().join("; "));
+        }
+        Ok(())
+    }
+
+    /// Applies the write mode that was given on the command line to all target files, except the exceptions.
+    fn apply_write_mode_override(&mut self, write_mode_override: &WriteModeOverride, project_root: &AbsolutePath) {
+        for target_plan in &mut self.targets {
+            let Some(write_mode) = write_mode_override.write_mode_for(&target_plan.target, project_root) else { continue };
+            target_plan.invar_config = target_plan.invar_config.with_write_mode(write_mode).into_owned();
+        }
+    }
+}
 
 impl<TC: ThunderConfig> GenerationContext<TC> {
-    async fn visit_subtree<IC>(&self, directory: &RelativePath, thumbs: Thumbs, invar_config: &IC) -> Result<()>
+    fn new(thunder_config: TC, niche_reporter: NicheReporter) -> Self {
+        GenerationContext { thunder_config, reporter: niche_reporter, run_options: RunOptions::default(), functions: FunctionRegistry::default(), random: Mutex::new(RandomValues::default()) }
+    }
+
+    fn with_run_options(self, run_options: RunOptions) -> Self {
+        GenerationContext { run_options, ..self }
+    }
+
+    /// Makes the given functions available to the options and fragments of the niche.
+    fn with_functions(self, functions: FunctionRegistry) -> Self {
+        GenerationContext { functions, ..self }
+    }
+
+    /// Loads (or creates) the seed of the niche if any target file asks for stable random values. In reproducible mode,
+    /// the seed is derived from the seed of the run instead, for all target files.
+    async fn prepare_random_values<IC: InvarConfig>(&self, plan: &NichePlan<IC>) -> Result<()> {
+        let Some(niche_directory) = self.thunder_config.invar().parent() else { return Ok(()) };
+        let niche_name = niche_directory.file_name().map(|name| name.to_string_lossy().to_string()).unwrap_or_default();
+        if let Some(seed) = self.run_options.reproducible() {
+            self.random_values()?.set_reproducible_seed(generators::reproducible_seed(seed, &niche_name));
+            return Ok(());
+        }
+        if !plan.targets.iter().any(|target_plan| target_plan.invar_config.stable_random()) {
+            return Ok(());
+        }
+        let seed_path = AbsolutePath::try_new(niche_directory.join(generators::RANDOM_SEED_FILE))?;
+        let seed = generators::load_or_create_seed(&seed_path, &self.thunder_config.project_file_system()).await?;
+        self.random_values()?.set_seed(seed);
+        Ok(())
+    }
+
+    fn random_values(&self) -> Result<MutexGuard<'_, RandomValues>> {
+        self.random.lock().map_err(|_| anyhow!("Random values are not available"))
+    }
+
+    /// Interpolates a line that ends up in the target file, including calls to registered functions. With
+    /// `interpolate-strict`, placeholders without a value and unknown functions are an error that points at the line in
+    /// the source file.
+    fn interpolate_line<IC: InvarConfig>(&self, line: &str, invar_config: &IC, location: impl Fn() -> String) -> Result<String> {
+        let line = generators::generate_random_values(line, invar_config.props().as_ref(), invar_config.placeholder_delimiters().as_ref(), &mut *self.random_values()?, invar_config.stable_random())
+            .map_err(|error| anyhow!("{}: {error}", location()))?;
+        let line = line.as_ref();
+        let strict = invar_config.interpolate_strict();
+        if strict {
+            let missing = crate::interpolate::missing_placeholders(line, invar_config.props().as_ref(), invar_config.placeholder_delimiters().as_ref());
+            if !missing.is_empty() {
+                bail!("{}: No value for placeholders: {}", location(), missing.join(", "));
+            }
+        }
+        let line = functions::call_functions(line, invar_config.props().as_ref(), invar_config.placeholder_delimiters().as_ref(), &self.functions, strict)
+            .map_err(|error| anyhow!("{}: {error}", location()))?;
+        Ok(interpolate(&line, invar_config))
+    }
+
+    async fn plan_niche<IC>(&self, invar_config: &IC) -> Result<NichePlan<IC>>
+    where IC: InvarConfig
+    {
+        let current_directory = RelativePath::from(".");
+        let mut plan = NichePlan { directories: Vec::new(), targets: Vec::new(), unknown_bolt_types: BTreeMap::new(), defined_features: BTreeSet::new() };
+        // The invar defaults for an extension are a layer between the configuration of the niche and the configuration
+        // bolts of the directories and files
+        let extension_configs: BTreeMap<String, IC> = invar_config.by_extension().iter()
+            .map(|(extension, defaults)| (extension.clone(), invar_config.with_invar_config(defaults.clone()).into_owned()))
+            .collect();
+        self.plan_subtree(&current_directory, FromBothCumulusAndInvar, invar_config, &[], &extension_configs, &mut plan).await?;
+        Ok(plan)
+    }
+
+    async fn plan_subtree<IC>(&self, directory: &RelativePath, thumbs: Thumbs, invar_config: &IC, configs: &[Bolt], extension_configs: &BTreeMap<String, IC>, plan: &mut NichePlan<IC>) -> Result<()>
     where IC: InvarConfig
     {
-        let cumulus_directory_location = CumulusDirectoryLocation(self.0.thundercloud_file_system().clone());
+        let props = invar_config.props();
+        let cumulus_directory_location = CumulusDirectoryLocation(self.thunder_config.thundercloud_file_system().clone());
         let (cumulus_bolts, cumulus_subdirectories) =
-            self.try_visit_directory(thumbs.visit_cumulus(), &cumulus_directory_location, directory).await?;
-        let invar_directory_location = InvarDirectoryLocation(self.0.project_file_system().clone());
+            self.try_visit_directory(thumbs.visit_cumulus(), &cumulus_directory_location, directory, &props).await?;
+        let invar_directory_location = InvarDirectoryLocation(self.thunder_config.project_file_system().clone());
         let (invar_bolts, invar_subdirectories) =
-            self.try_visit_directory(thumbs.visit_invar(), &invar_directory_location, directory).await?;
+            self.try_visit_directory(thumbs.visit_invar(), &invar_directory_location, directory, &props).await?;
 
-        let bolts = combine(cumulus_bolts, invar_bolts);
+        let mut bolts = combine(cumulus_bolts, invar_bolts);
         for (key, bolt_lists) in &bolts {
             debug!("Bolts entry: {:?}: {:?}", key, bolt_lists);
+            for bolt in bolt_lists.0.iter().chain(bolt_lists.1.iter()) {
+                if let BoltKind::Unknown { bolt_type, .. } = &bolt.kind {
+                    *plan.unknown_bolt_types.entry(bolt_type.clone()).or_default() += 1;
+                }
+                if bolt.feature_name != "@" {
+                    plan.defined_features.insert(bolt.feature_name());
+                }
+            }
+        }
+
+        let mut directory_config = Cow::Borrowed(invar_config);
+        let mut directory_configs = configs.to_vec();
+        if let Some(dir_bolts) = bolts.remove(".") {
+            let half_config = self.update_invar_config(invar_config, &dir_bolts.0).await?;
+            directory_config = Cow::Owned(self.update_invar_config(half_config.as_ref(), &dir_bolts.1).await?.into_owned());
+            directory_configs.extend(config_bolts(&dir_bolts.0).chain(config_bolts(&dir_bolts.1)).cloned());
         }
+        let target = directory.relative_to(self.thunder_config.project_root());
+        plan.directories.push(DirectoryPlan { target, invar_config: directory_config.clone().into_owned() });
 
-        self.generate_files(&directory, bolts, invar_config).await?;
+        self.plan_files(&directory, bolts, directory_config.as_ref(), &directory_configs, extension_configs, plan).await?;
 
-        self.visit_subdirectories(directory, cumulus_subdirectories, invar_subdirectories, invar_config).await?;
+        self.plan_subdirectories(directory, cumulus_subdirectories, invar_subdirectories, directory_config.as_ref(), &directory_configs, extension_configs, plan).await?;
 
         Ok(())
     }
 
-    async fn generate_files<IC>(&self, directory: &RelativePath, bolts: AHashMap<String, (Vec<Bolt>, Vec<Bolt>)>, invar_config: &IC) -> Result<()>
+    async fn plan_files<IC>(&self, directory: &RelativePath, bolts: AHashMap<String, (Vec<Bolt>, Vec<Bolt>)>, use_config: &IC, directory_configs: &[Bolt], extension_configs: &BTreeMap<String, IC>, plan: &mut NichePlan<IC>) -> Result<()>
     where IC: InvarConfig
     {
-        let mut bolts = bolts;
-        let mut use_config = Cow::Borrowed(invar_config);
-        if let Some(dir_bolts) = bolts.remove(".") {
-            let (_, dir_bolt_list) = self.combine_and_filter_bolt_lists(&dir_bolts.0, &dir_bolts.1);
-            use_config = self.update_invar_config(invar_config, &dir_bolt_list).await?;
-        }
-        let bolts = bolts;
-
-        let target_directory = directory.relative_to(self.0.project_root());
-        debug!("Generate files in {:?} with config {:?}", &target_directory, &use_config);
+        let target_directory = directory.relative_to(self.thunder_config.project_root());
+        debug!("Plan files in {:?} with config {:?}", &target_directory, &use_config);
         for (name, bolt_lists) in &bolts {
             if ILLEGAL_FILE_REGEX.is_match(name) {
                 warn!("Target filename is not legal: {name:?}");
                 continue;
             }
-            let target_file = RelativePath::from(name as &str).relative_to(&target_directory);
-            let half_config = self.update_invar_config(use_config.as_ref(), &bolt_lists.0).await?;
+            let target = RelativePath::from(name as &str).relative_to(&target_directory);
+            let mut tombstones = bolt_lists.0.iter().chain(bolt_lists.1.iter())
+                .filter(|bolt| matches!(bolt.kind, BoltKind::Tombstone) && self.is_feature_selected(&bolt.feature_name));
+            if let Some(tombstone) = tombstones.next() {
+                debug!("Skip (tombstone): {:?}: {:?}", &target, tombstone.source());
+                continue;
+            }
+            let extension = target.extension().map(|extension| extension.to_string_lossy().to_string());
+            let directory_config = match extension.and_then(|extension| extension_configs.get(&extension)) {
+                Some(extension_config) => self.update_invar_config(extension_config, &directory_configs.to_vec()).await?,
+                None => Cow::Borrowed(use_config),
+            };
+            let ruled_config = apply_rules(directory_config.as_ref(), &target, self.thunder_config.project_root())?;
+            let half_config = self.update_invar_config(ruled_config.as_ref(), &bolt_lists.0).await?;
             let whole_config = self.update_invar_config(half_config.as_ref(), &bolt_lists.1).await?;
-            let (option, bolts) = self.combine_and_filter_bolt_lists(&bolt_lists.0, &bolt_lists.1);
-            self.generate_file(&target_file, option, bolts, whole_config.as_ref()).await?;
+            let (option, fragments, patches, appends) = self.combine_and_filter_bolt_lists(&bolt_lists.0, &bolt_lists.1)?;
+            let mut configs = directory_configs.to_vec();
+            configs.extend(config_bolts(&bolt_lists.0).chain(config_bolts(&bolt_lists.1)).cloned());
+            plan.targets.push(TargetPlan {
+                target,
+                option,
+                fragments,
+                patches,
+                appends,
+                configs,
+                invar_config: whole_config.into_owned(),
+            });
         }
         Ok(())
     }
 
-    async fn generate_file<IC>(&self, target_path: &AbsolutePath, option: Option<Bolt>, bolts: Vec<Bolt>, invar_config: &IC) -> Result<()>
+    async fn generate_file<IC>(&self, target_path: &AbsolutePath, option: Option<Bolt>, bolts: Vec<Bolt>, patches: Vec<Bolt>, invar_config: &IC, role: Option<SharedTargetRole>) -> Result<()>
     where IC: InvarConfig
     {
+        let relative_target = target_path.strip_prefix(self.thunder_config.project_root().as_path()).unwrap_or(target_path.as_path());
+        self.random_values()?.start_target(relative_target.to_string_lossy().to_string());
+        if role == Some(SharedTargetRole::Contributor) {
+            if !patches.is_empty() {
+                warn!("Ignore patches of niche that contributes to a shared target: {:?}", target_path);
+            }
+            return self.contribute_to_shared_target(target_path, option, bolts, invar_config).await;
+        }
         let option =
             if let Some(option) = option {
                 option
@@ -272,637 +809,4363 @@ impl<TC: ThunderConfig> GenerationContext<TC> {
                 return Ok(())
             }
             ;
+        let project_root = self.thunder_config.project_root();
         if invar_config.write_mode() == WriteMode::Ignore {
             debug!("Ignore: {:?}: {:?}: {:?}", target_path, &bolts, &invar_config);
+            self.reporter.file(project_root, target_path, FileAction::Ignored);
             return Ok(())
         }
-        let file_system = self.0.project_file_system();
-        if let Some(target_file) = file_system.open_target(target_path.clone(), invar_config.write_mode()).await? {
-            let source = option.source();
-            match option.context() {
-                ThunderCloud => {
-                    let fs = self.0.thundercloud_file_system();
-                    let source_file = fs.open_source(source.clone()).await?;
-                    self.generate_option(option, bolts, invar_config, source_file, &target_file).await?
-                },
-                Project => {
-                    let fs = self.0.project_file_system();
-                    let source_file = fs.open_source(source.clone()).await?;
-                    self.generate_option(option, bolts, invar_config, source_file, &target_file).await?
-                }
+        if let BoltKind::Symlink = option.kind {
+            if invar_config.backup() && invar_config.write_mode() == WriteMode::Overwrite {
+                self.backup_target(target_path).await?;
+            }
+            return self.generate_symlink(target_path, &option, invar_config).await;
+        }
+        let source = option.source();
+        let source_metadata = match option.context() {
+            ThunderCloud => self.thunder_config.thundercloud_file_system().metadata(source).await?,
+            Project => self.thunder_config.project_file_system().metadata(source).await?,
+        };
+        if let BoltKind::Binary = option.kind {
+            if !bolts.is_empty() || !patches.is_empty() {
+                warn!("Ignore fragments and patches of binary option: {:?}", source);
             }
-            let mut target_file_mut = target_file;
-            target_file_mut.close().await?;
+            let executable = invar_config.executable_option().unwrap_or(source_metadata.is_executable());
+            return self.generate_binary(target_path, &option, invar_config, executable).await;
+        }
+        // An option for Tera (or an option with patches) is rendered up front, so that a broken template (or a patch that
+        // does not apply) leaves an existing target file alone.
+        let rendered = match invar_config.engine() {
+            TemplateEngine::Tera => Some(self.render_with_tera(&option, &bolts, invar_config).await?),
+            TemplateEngine::Igor if !patches.is_empty() => Some(self.render_with_igor(option.clone(), bolts.clone(), invar_config, role.is_some()).await?),
+            TemplateEngine::Igor => None,
+        };
+        let rendered = match rendered {
+            Some(content) if !patches.is_empty() => Some(self.apply_patches(content, &patches).await?),
+            rendered => rendered,
+        };
+        let _rendered_memory = self.run_options.memory().track(rendered.as_ref().map_or(0, String::len));
+        // Without interpolation or fragments, the option is copied as is, so that huge single-line files (like minified
+        // JavaScript) need not fit in a line.
+        let copy_raw = !invar_config.interpolate() && bolts.is_empty() && rendered.is_none();
+        let executable = invar_config.executable_option().unwrap_or(source_metadata.is_executable());
+        let file_system = self.thunder_config.project_file_system();
+        let overwrite_existing = invar_config.write_mode() == WriteMode::Overwrite && file_system.path_type(target_path).await == PathType::File;
+        let merge = invar_config.write_mode() == WriteMode::Overwrite && invar_config.merge();
+        let existing = if overwrite_existing && !copy_raw {
+            file_system.get_content(target_path.clone()).await?
+        } else {
+            String::new()
+        };
+        let _existing_memory = self.run_options.memory().track(existing.len());
+        let kept = keep_regions(&existing, target_path);
+        if overwrite_existing || merge {
+            // The content is generated in memory first, so that an existing file with the same content is left alone
+            let buffer = MemoryTargetFile::new(self.run_options.memory().track(0));
+            let keep_target_file = KeepRegionsTargetFile::new(&buffer, kept);
+            match rendered {
+                Some(content) => write_content(&content, invar_config.line_endings(), &keep_target_file).await?,
+                None => self.write_target(option, bolts, invar_config, copy_raw, role.is_some(), &keep_target_file).await?,
+            };
+            drop(keep_target_file);
+            let action = if merge {
+                self.merge_with_baseline(target_path, &buffer.into_bytes(), invar_config, executable).await?
+            } else {
+                self.overwrite_if_changed(target_path, &buffer.into_bytes(), invar_config, executable).await?
+            };
+            return self.record_target(target_path, action, invar_config).await;
+        }
+        let appending = invar_config.write_mode() == WriteMode::Append && file_system.path_type(target_path).await == PathType::File;
+        let target_file_option = file_system.open_target(target_path.clone(), invar_config.write_mode(), executable).await
+            .map_err(|error| self.note_fatal_write_error(target_path, error))?;
+        if let Some(mut target_file) = target_file_option {
+            let keep_target_file = KeepRegionsTargetFile::new(&target_file, kept);
+            let generated = match rendered {
+                Some(content) => write_content(&content, invar_config.line_endings(), &keep_target_file).await,
+                None => self.write_target(option, bolts, invar_config, copy_raw, role.is_some(), &keep_target_file).await,
+            };
+            let closed = target_file.close().await;
+            closed.and(generated).map_err(|error| self.note_fatal_write_error(target_path, error))?;
+            let action = if appending { FileAction::Appended } else { FileAction::Written };
+            self.record_target(target_path, action, invar_config).await?;
         } else {
             debug!("Skip (target exists): {:?}: {:?}: {:?}", target_path, &bolts, &invar_config);
+            self.reporter.file(project_root, target_path, FileAction::Skipped);
         }
         Ok(())
     }
 
-    async fn generate_option<IC, SF, TF>(&self, option: Bolt, fragments: Vec<Bolt>, invar_config: &IC, mut source_file: SF, target_file: &TF) -> Result<()>
+    async fn write_target<IC, TF>(&self, option: Bolt, bolts: Vec<Bolt>, invar_config: &IC, copy_raw: bool, keep_placeholders: bool, target_file: &TF) -> Result<()>
     where
         IC: InvarConfig,
-        SF: SourceFile,
         TF: TargetFile
     {
-        debug!("Generating option: {:?}: {:?}: {:?}", &option, &fragments, invar_config);
-        while let Some(line) = source_file.next_line().await? {
-            let line = interpolate(&line, invar_config);
-            if let Some(captures) = FRAGMENT_REGEX.captures(&line) {
-                let feature = captures.name("feature").map(|m| m.as_str().to_string()).unwrap_or("@".to_string());
-                let qualifier = captures.name("qualifier").map(|m| m.as_str().to_string()).unwrap_or("".to_string());
-                debug!("Found fragment: {:?}: {:?}", &feature, &qualifier);
-                if let Some(bracket) = captures.name("bracket") {
-                    if bracket.as_str() == "BEGIN " {
-                        skip_to_end_of_fragment(&mut source_file, &feature, &qualifier).await?;
-                    }
+        debug!("Generating option: {:?}: {:?}: {:?}", &option, &bolts, invar_config);
+        self.check_line_endings(&option, &bolts, invar_config.line_endings(), copy_raw).await?;
+        let source = option.source().clone();
+        match option.context() {
+            ThunderCloud => {
+                let fs = self.thunder_config.thundercloud_file_system();
+                let source_file = fs.open_source(source.clone()).await?;
+                if copy_raw {
+                    copy_chunks(source_file, target_file).await
+                } else {
+                    let lines = NumberedLines::new(source_file, source).with_comment_prefix(invar_config.template_comment_prefix());
+                    self.generate_with_line_endings(bolts, invar_config, lines, keep_placeholders, target_file).await
+                }
+            },
+            Project => {
+                let fs = self.thunder_config.project_file_system();
+                let source_file = fs.open_source(source.clone()).await?;
+                if copy_raw {
+                    copy_chunks(source_file, target_file).await
+                } else {
+                    let lines = NumberedLines::new(source_file, source).with_comment_prefix(invar_config.template_comment_prefix());
+                    self.generate_with_line_endings(bolts, invar_config, lines, keep_placeholders, target_file).await
                 }
-                self.find_and_include_fragment(&feature, &qualifier, target_file, &fragments, invar_config).await?;
-                continue;
             }
-            send_to_writer(&line, target_file).await?;
         }
-        Ok(())
     }
 
-    async fn find_and_include_fragment<IC, TF>(&self, feature: &str, qualifier: &str, target_file: &TF, fragments: &Vec<Bolt>, invar_config: &IC) -> Result<()>
-    where
-        IC: InvarConfig,
-        TF: TargetFile
-    {
-        for bolt in fragments {
-            if let BoltKind::Fragment { qualifier: fragment_qualifier, .. } = &bolt.kind {
-                let fragment_qualifier = fragment_qualifier.as_ref().map(ToOwned::to_owned).unwrap_or("".to_string());
-                if bolt.feature_name == feature && fragment_qualifier == qualifier {
-                    debug!("Found fragment to include: {:?}", bolt);
-                    let source = bolt.source();
-                    match bolt.context() {
-                        ThunderCloud => {
-                            let fs = self.0.thundercloud_file_system();
-                            let source_file = fs.open_source(source.clone()).await?;
-                            self.include_fragment(source_file, feature, qualifier, target_file, fragments, invar_config).await?;
-                        },
-                        Project => {
-                            let fs = self.0.project_file_system();
-                            let source_file = fs.open_source(source.clone()).await?;
-                            self.include_fragment(source_file, feature, qualifier, target_file, fragments, invar_config).await?;
-                        }
-                    }
-                    break;
-                }
-            }
+    /// Renders the whole option with Tera. Fragments do not apply to options for Tera.
+    async fn render_with_tera<IC: InvarConfig>(&self, option: &Bolt, fragments: &[Bolt], invar_config: &IC) -> Result<String> {
+        if !fragments.is_empty() {
+            warn!("Ignore fragments of option for template engine tera: {:?}", option.source());
+        }
+        let template = self.get_bolt_content(option).await?;
+        tera_engine::render(&option.source().to_string_lossy(), &template, invar_config.props().as_ref())
+    }
+
+    /// Creates a symbolic link at the target path to the (interpolated) first line of a symlink bolt.
+    async fn generate_symlink<IC: InvarConfig>(&self, target_path: &AbsolutePath, bolt: &Bolt, invar_config: &IC) -> Result<()> {
+        let content = self.get_bolt_content(bolt).await?;
+        let link_target = content.lines().next().map(str::trim).unwrap_or_default();
+        let link_target = if invar_config.interpolate() {
+            self.interpolate_line(link_target, invar_config, || format!("{}:1", bolt.source().to_string_lossy()))?
+        } else {
+            link_target.to_string()
+        };
+        if link_target.is_empty() {
+            bail!("Symbolic link without target: {:?}", bolt.source());
         }
+        let project_root = self.thunder_config.project_root();
+        let created = self.thunder_config.project_file_system().create_symlink(target_path, Path::new(&link_target), invar_config.write_mode()).await
+            .map_err(|error| self.note_fatal_write_error(target_path, error))?;
+        let action = if created { FileAction::Written } else { FileAction::Skipped };
+        self.reporter.file(project_root, target_path, action);
         Ok(())
     }
 
-    async fn include_fragment<SF, TF, IC>(&self, mut source_file: SF, feature: &str, qualifier: &str, target_file: &TF, fragments: &Vec<Bolt>, invar_config: &IC) -> Result<()>
-    where
-        SF: SourceFile,
-        TF: TargetFile,
-        IC: InvarConfig
-    {
-        while let Some(line) = source_file.next_line().await? {
-            let line = interpolate(&line, invar_config);
-            if let Some(captures) = FRAGMENT_REGEX.captures(&line) {
-                let placeholder_feature = captures.name("feature").map(|m| m.as_str().to_string()).unwrap_or("@".to_string());
-                let placeholder_qualifier = captures.name("qualifier").map(|m| m.as_str().to_string()).unwrap_or("".to_string());
-                debug!("Found placeholder: {:?}: {:?}", &feature, &qualifier);
-                if let Some(bracket) = captures.name("bracket") {
-                    if bracket.as_str() == "BEGIN " && placeholder_feature == feature && placeholder_qualifier == qualifier {
-                        send_to_writer(&line, target_file).await?;
-                        self.copy_to_end_of_fragment(&mut source_file, &feature, &qualifier, target_file, fragments, invar_config).await?;
-                    }
-                }
-                return Ok(());
-            }
+    /// Copies an existing target file to `<name>.bak` next to it, before it is overwritten. An existing backup is replaced.
+    async fn backup_target(&self, target_path: &AbsolutePath) -> Result<()> {
+        let file_system = self.thunder_config.project_file_system();
+        if file_system.path_type(target_path).await != PathType::File {
+            return Ok(());
         }
-        debug!("Include fragment: placeholder not found");
+        let mut backup_path = target_path.as_os_str().to_owned();
+        backup_path.push(".bak");
+        let backup_path = AbsolutePath::try_new(backup_path.into())?;
+        debug!("Backup: {:?} ⇒ {:?}", target_path, &backup_path);
+        let content = file_system.read_bytes(target_path.clone()).await?;
+        let executable = file_system.metadata(target_path).await?.is_executable();
+        file_system.write_bytes(backup_path.clone(), &content, WriteMode::Overwrite, executable).await
+            .map_err(|error| self.note_fatal_write_error(&backup_path, error))?;
         Ok(())
     }
 
-    async fn copy_to_end_of_fragment<SF, TF, IC>(&self, lines: &mut SF, feature: &str, qualifier: &str, target_file: &TF, fragments: &Vec<Bolt>, invar_config: &IC) -> Result<()>
-    where
-        SF: SourceFile,
-        TF: TargetFile,
-        IC: InvarConfig
-    {
-        while let Some(fragment_line) = lines.next_line().await? {
-            let line = interpolate(&fragment_line, invar_config);
-            if let Some(captures) = FRAGMENT_REGEX.captures(&line) {
-                debug!("Found inner fragment: {:?}", &captures);
-                if is_matching_end(&captures, feature, qualifier) {
-                    send_to_writer(&line, target_file).await?;
-                    break;
-                } else {
-                    if let Some(bracket) = captures.name("bracket") {
-                        if bracket.as_str() == "BEGIN " {
-                            skip_to_end_of_fragment(lines, &feature, &qualifier).await?;
-                        }
-                    }
-                    Box::pin(self.find_and_include_fragment(&feature, &qualifier, target_file, fragments, invar_config)).await?;
-                    continue;
-                }
+    /// Copies a binary option to the target path byte for byte.
+    async fn generate_binary<IC: InvarConfig>(&self, target_path: &AbsolutePath, bolt: &Bolt, invar_config: &IC, executable: bool) -> Result<()> {
+        let content = match bolt.context() {
+            ThunderCloud => self.thunder_config.thundercloud_file_system().read_bytes(bolt.source().clone()).await?,
+            Project => self.thunder_config.project_file_system().read_bytes(bolt.source().clone()).await?,
+        };
+        let _content_memory = self.run_options.memory().track(content.len());
+        let file_system = self.thunder_config.project_file_system();
+        let action = if invar_config.write_mode() == WriteMode::Overwrite && file_system.path_type(target_path).await == PathType::File {
+            self.overwrite_if_changed(target_path, &content, invar_config, executable).await?
+        } else {
+            let appending = invar_config.write_mode() == WriteMode::Append && file_system.path_type(target_path).await == PathType::File;
+            let written = file_system.write_bytes(target_path.clone(), &content, invar_config.write_mode(), executable).await
+                .map_err(|error| self.note_fatal_write_error(target_path, error))?;
+            match (written, appending) {
+                (false, _) => FileAction::Skipped,
+                (true, true) => FileAction::Appended,
+                (true, false) => FileAction::Written,
+            }
+        };
+        self.record_target(target_path, action, invar_config).await
+    }
+
+    /// Records what happened to a target file. A target file that has generated content gets the permission bits of
+    /// the `mode` setting, if any.
+    async fn record_target<IC: InvarConfig>(&self, target_path: &AbsolutePath, action: FileAction, invar_config: &IC) -> Result<()> {
+        let project_root = self.thunder_config.project_root();
+        let generated = matches!(action, FileAction::Written | FileAction::Unchanged | FileAction::Merged | FileAction::Conflict);
+        if let Some(mode) = invar_config.mode()?.filter(|_| generated) {
+            let file_system = self.thunder_config.project_file_system();
+            if file_system.metadata(target_path).await?.mode() != mode {
+                debug!("Set mode: {:?}: {:o}", target_path, mode);
+                file_system.set_mode(target_path, mode).await?;
             }
-            send_to_writer(&line, target_file).await?;
         }
+        self.reporter.file(project_root, target_path, action);
         Ok(())
     }
 
-    async fn update_invar_config<'a, IC>(&self, invar_config: &'a IC, bolts: &Vec<Bolt>) -> Result<Cow<'a, IC>>
-    where
-        IC: InvarConfig,
-    {
-        let mut use_config = Cow::Borrowed(invar_config);
-        for bolt in bolts {
-            debug!("Bolt kind: {:?}", bolt.kind_name());
-            if let BoltKind::Config { format } = bolt.kind {
-                let thundercloud_fs = self.0.thundercloud_file_system();
-                let project_fs = self.0.project_file_system();
-                debug!("Bolt context: {:?}", bolt.context());
-                let bolt_invar_config_body = match bolt.context() {
-                    ThunderCloud => thundercloud_fs.get_content(bolt.source().clone()).await?,
-                    Project => project_fs.get_content(bolt.source().clone()).await?,
-                };
-                let bolt_invar_config = get_invar_config(&bolt_invar_config_body, format)?;
-                debug!("Apply bolt configuration: {:?}: {:?} += {:?}", bolt.target_name(), invar_config, &bolt_invar_config);
-                let new_use_config = use_config.to_owned().with_invar_config(bolt_invar_config).into_owned();
-                use_config = Cow::Owned(new_use_config);
+    /// Overwrites an existing target file with the generated content, unless the file has that content already. Leaving
+    /// it alone preserves its modification time, so that build tools do not rebuild what depends on it.
+    async fn overwrite_if_changed<IC: InvarConfig>(&self, target_path: &AbsolutePath, content: &[u8], invar_config: &IC, executable: bool) -> Result<FileAction> {
+        let file_system = self.thunder_config.project_file_system();
+        let existing = file_system.read_bytes(target_path.clone()).await?;
+        let _existing_memory = self.run_options.memory().track(existing.len());
+        if existing == content {
+            debug!("Skip (unchanged): {:?}", target_path);
+            let mode = file_system.metadata(target_path).await?.mode();
+            let new_mode = if executable { mode | (mode & 0o444) >> 2 } else { mode & !0o111 };
+            if new_mode != mode {
+                file_system.set_mode(target_path, new_mode).await?;
             }
+            return Ok(FileAction::Unchanged);
         }
-        debug!("Updated invar config: {:?}", use_config);
-        Ok(use_config)
+        if invar_config.backup() {
+            self.backup_target(target_path).await?;
+        }
+        file_system.write_bytes(target_path.clone(), content, WriteMode::Overwrite, executable).await
+            .map_err(|error| self.note_fatal_write_error(target_path, error))?;
+        Ok(FileAction::Written)
     }
 
-    fn combine_and_filter_bolt_lists(&self, cumulus_bolts_list: &Vec<Bolt>, invar_bolts_list: &Vec<Bolt>) -> (Option<Bolt>, Vec<Bolt>) {
-        let combined = combine_bolt_lists(cumulus_bolts_list, invar_bolts_list);
-        self.filter_options(&combined)
-    }
+    /// Writes the generated content to the target path, taking changes in the target file since it was last generated
+    /// into account. The content that was last generated (the baseline) is kept in the directory of the niche. If both
+    /// the target file and the generated content changed since, the changes are merged (with conflict markers where
+    /// they overlap). If only the target file changed, it is left alone.
+    async fn merge_with_baseline<IC: InvarConfig>(&self, target_path: &AbsolutePath, content: &[u8], invar_config: &IC, executable: bool) -> Result<FileAction> {
+        let file_system = self.thunder_config.project_file_system();
+        let baseline_path = self.baseline_path(target_path)?;
+        let existing = if file_system.path_type(target_path).await == PathType::File {
+            Some(file_system.read_bytes(target_path.clone()).await?)
+        } else {
+            None
+        };
+        let baseline = if file_system.path_type(&baseline_path).await == PathType::File {
+            Some(file_system.read_bytes(baseline_path.clone()).await?)
+        } else {
+            None
+        };
+        let action = match (&existing, &baseline) {
+            (None, _) => {
+                file_system.write_bytes(target_path.clone(), content, WriteMode::Overwrite, executable).await
+                    .map_err(|error| self.note_fatal_write_error(target_path, error))?;
+                FileAction::Written
+            },
+            (Some(existing), Some(baseline)) if existing != baseline && existing != content => {
+                if baseline == content {
+                    debug!("Keep changes (generated content is the same): {:?}", target_path);
+                    FileAction::Skipped
+                } else {
+                    self.merge_changes(target_path, existing, baseline, content, invar_config, executable).await?
+                }
+            },
+            _ => self.overwrite_if_changed(target_path, content, invar_config, executable).await?,
+        };
+        if baseline.as_deref() != Some(content) {
+            file_system.write_bytes(baseline_path.clone(), content, WriteMode::Overwrite, false).await
+                .map_err(|error| self.note_fatal_write_error(&baseline_path, error))?;
+        }
+        Ok(action)
+    }
 
-    fn filter_options(&self, bolt_list: &Vec<Bolt>) -> (Option<Bolt>, Vec<Bolt>) {
-        let mut features = AHashSet::new();
-        features.insert("@");
-        for feature in self.0.use_thundercloud().features() {
-            features.insert(feature);
+    async fn merge_changes<IC: InvarConfig>(&self, target_path: &AbsolutePath, existing: &[u8], baseline: &[u8], content: &[u8], invar_config: &IC, executable: bool) -> Result<FileAction> {
+        let project_root = self.thunder_config.project_root();
+        let relative_target = target_path.strip_prefix(project_root.as_path()).unwrap_or(target_path.as_path()).to_string_lossy().to_string();
+        let (Ok(existing), Ok(baseline), Ok(content)) = (std::str::from_utf8(existing), std::str::from_utf8(baseline), std::str::from_utf8(content)) else {
+            self.reporter.warning(format!("Cannot merge changes in binary file: {relative_target}"));
+            return Ok(FileAction::Conflict);
+        };
+        let merged = merge::merge(baseline, existing, content, "project", "thundercloud");
+        self.overwrite_if_changed(target_path, merged.content.as_bytes(), invar_config, executable).await?;
+        if merged.conflicts > 0 {
+            warn!("Merge conflicts: {:?}: {}", target_path, merged.conflicts);
+            self.reporter.warning(format!("Merge conflicts in {relative_target}: {}", merged.conflicts));
+            return Ok(FileAction::Conflict);
         }
-        let mut options = Vec::new();
-        let mut fragments = Vec::new();
-        for bolt in bolt_list {
-            if features.contains(&bolt.feature_name() as &str) {
-                if let BoltKind::Option = bolt.kind {
-                    options.push(bolt.clone());
-                } else if let BoltKind::Fragment { .. } = bolt.kind {
-                    fragments.push(bolt.clone())
-                }
+        Ok(FileAction::Merged)
+    }
+
+    /// The file that holds the content that was last generated for a target file: the same path relative to the
+    /// `baseline` directory of the niche.
+    fn baseline_path(&self, target_path: &AbsolutePath) -> Result<AbsolutePath> {
+        let niche_directory = self.thunder_config.invar().parent().ok_or_else(|| anyhow!("Invar has no parent: {:?}", self.thunder_config.invar()))?;
+        let relative_target = target_path.strip_prefix(self.thunder_config.project_root().as_path())?;
+        AbsolutePath::try_new(niche_directory.join(BASELINE_DIRECTORY).join(relative_target))
+    }
+
+    /// Generates the option with its fragments in memory, with LF line endings, so that patches can be applied to it.
+    async fn render_with_igor<IC: InvarConfig>(&self, option: Bolt, fragments: Vec<Bolt>, invar_config: &IC, keep_placeholders: bool) -> Result<String> {
+        let buffer = BufferedTargetFile::new(self.run_options.memory().track(0));
+        self.write_target(option, fragments, invar_config, false, keep_placeholders, &buffer).await?;
+        Ok(buffer.into_lines().iter().map(|line| format!("{}\n", line.strip_suffix('\r').unwrap_or(line))).collect())
+    }
+
+    /// Applies the patches to the generated content, in order.
+    async fn apply_patches(&self, content: String, patches: &[Bolt]) -> Result<String> {
+        let mut content = content;
+        for bolt in patches {
+            let diff = self.get_bolt_content(bolt).await?;
+            content = patch::apply(&content, &diff).map_err(|error| anyhow!("{:?}: {error}", bolt.source()))?;
+        }
+        Ok(content)
+    }
+
+    /// Warns about options and fragments whose line endings differ from the line endings of the target file. Igor
+    /// normalizes the line endings of generated files, except for options that are copied as is.
+    async fn check_line_endings(&self, option: &Bolt, fragments: &[Bolt], line_endings: LineEndings, copy_raw: bool) -> Result<()> {
+        for bolt in std::iter::once(option).chain(fragments) {
+            let source = bolt.source().clone();
+            let detected = match bolt.context() {
+                ThunderCloud => detect_line_endings(self.thunder_config.thundercloud_file_system().open_source(source).await?).await?,
+                Project => detect_line_endings(self.thunder_config.project_file_system().open_source(source).await?).await?,
+            };
+            match detected {
+                Some(detected) if detected != line_endings && copy_raw => warn!("Copy {detected} line endings as is (target file should have {line_endings}): {:?}", bolt.source()),
+                Some(detected) if detected != line_endings => warn!("Convert {detected} line endings to {line_endings}: {:?}", bolt.source()),
+                _ => (),
             }
         }
-        let first_option = if options.is_empty() {
-            None
-        } else {
-            Some(options.remove(0))
-        };
-        (first_option, fragments)
+        Ok(())
     }
 
-    async fn visit_subdirectories<IC>(&self, directory: &RelativePath, cumulus_subdirectories: AHashSet<SingleComponent>, invar_subdirectories: AHashSet<SingleComponent>, invar_config: &IC) -> Result<()>
+    async fn generate_with_line_endings<IC, SF, TF>(&self, fragments: Vec<Bolt>, invar_config: &IC, source_file: NumberedLines<SF>, keep_placeholders: bool, target_file: &TF) -> Result<()>
     where
-        TC: ThunderConfig,
-        IC: InvarConfig
+        IC: InvarConfig,
+        SF: SourceFile,
+        TF: TargetFile
     {
-        let mut invar_subdirectories = invar_subdirectories;
-        for path in cumulus_subdirectories {
-            let subdirectory_thumbs = if let Some(_) = invar_subdirectories.get(&path) {
-                invar_subdirectories.remove(&path);
-                FromBothCumulusAndInvar
-            } else {
-                FromCumulus
-            };
-            let mut subdirectory = directory.clone();
-            let path: RelativePath = path.try_into()?;
-            subdirectory.push(path);
-            Box::pin(self.visit_subtree(&subdirectory, subdirectory_thumbs, invar_config)).await?;
+        let markers = FragmentMarkers::new(invar_config).map_err(|error| anyhow!("{:?}: {error}", &source_file.source))?;
+        let source_file = source_file.with_markers(markers.clone());
+        match (invar_config.line_endings(), invar_config.keep_markers()) {
+            (LineEndings::Lf, true) => self.generate_option(fragments, invar_config, source_file, keep_placeholders, target_file).await,
+            (LineEndings::Crlf, true) => self.generate_option(fragments, invar_config, source_file, keep_placeholders, &CrlfTargetFile(target_file)).await,
+            (LineEndings::Lf, false) => self.generate_option(fragments, invar_config, source_file, keep_placeholders, &StripMarkersTargetFile(target_file, markers)).await,
+            (LineEndings::Crlf, false) => self.generate_option(fragments, invar_config, source_file, keep_placeholders, &StripMarkersTargetFile(&CrlfTargetFile(target_file), markers)).await,
         }
-        for path in invar_subdirectories {
-            let mut subdirectory = directory.clone();
-            let path: RelativePath = path.try_into()?;
-            subdirectory.push(path);
-            Box::pin(self.visit_subtree(&subdirectory, FromInvar, invar_config)).await?;
+    }
+
+    /// Appends the lines of the appends to the target file, or creates the target file if it does not exist. Lines that
+    /// are not empty and that the target file already has are skipped, so that the lines are not added again on every
+    /// run.
+    async fn append_to_target<IC>(&self, target_path: &AbsolutePath, appends: &[Bolt], invar_config: &IC) -> Result<()>
+    where IC: InvarConfig
+    {
+        if appends.is_empty() {
+            return Ok(());
+        }
+        let project_root = self.thunder_config.project_root();
+        if invar_config.write_mode() == WriteMode::Ignore {
+            debug!("Ignore appends: {:?}: {:?}", target_path, appends);
+            self.reporter.file(project_root, target_path, FileAction::Ignored);
+            return Ok(());
+        }
+        let file_system = self.thunder_config.project_file_system();
+        let exists = file_system.path_type(target_path).await == PathType::File;
+        let existing = if exists { file_system.get_content(target_path.clone()).await? } else { String::new() };
+        let _existing_memory = self.run_options.memory().track(existing.len());
+        let mut present: AHashSet<String> = existing.lines().map(|line| line.trim_end_matches('\r').to_string()).collect();
+        let mut appended = Vec::new();
+        for bolt in appends {
+            let content = self.get_bolt_content(bolt).await?;
+            for (index, line) in content.lines().enumerate() {
+                let line = line.trim_end_matches('\r');
+                let line = if invar_config.interpolate() {
+                    self.interpolate_line(line, invar_config, || format!("{}:{}", bolt.source().to_string_lossy(), index + 1))?
+                } else {
+                    line.to_string()
+                };
+                if line.trim().is_empty() || present.insert(line.clone()) {
+                    appended.push(line);
+                }
+            }
         }
+        if appended.iter().all(|line| line.trim().is_empty()) {
+            debug!("Skip (nothing to append): {:?}", target_path);
+            return Ok(());
+        }
+        let executable = if exists { file_system.metadata(target_path).await?.is_executable() } else { invar_config.executable_option().unwrap_or(false) };
+        let mut target_file = file_system.open_target(target_path.clone(), WriteMode::Overwrite, executable).await
+            .map_err(|error| self.note_fatal_write_error(target_path, error))?
+            .ok_or_else(|| anyhow!("Could not append to target: {:?}", target_path))?;
+        let written = async {
+            for line in existing.lines() {
+                target_file.write_line(line).await?;
+            }
+            write_content(&appended.join("\n"), invar_config.line_endings(), &target_file).await
+        }.await;
+        let closed = target_file.close().await;
+        closed.and(written).map_err(|error| self.note_fatal_write_error(target_path, error))?;
+        // A file that existed before belongs to someone else, so it must not be removed when the niche is cleaned
+        let action = if exists { FileAction::Appended } else { FileAction::Written };
+        self.reporter.file(project_root, target_path, action);
         Ok(())
     }
 
-    async fn try_visit_directory<DL>(&self, exists: bool, directory_location: &DL, directory: &RelativePath) -> Result<(AHashMap<String, Vec<Bolt>>, AHashSet<SingleComponent>)>
-    where DL: DirectoryLocation
+    /// Reports the target file as possibly truncated if the error is a fatal write error (like a full disk).
+    fn note_fatal_write_error(&self, target_path: &AbsolutePath, error: anyhow::Error) -> anyhow::Error {
+        if FatalWriteError::find(&error).is_some() {
+            self.reporter.file(self.thunder_config.project_root(), target_path, FileAction::MayBeTruncated);
+        }
+        error
+    }
+
+    /// Fills the placeholders of a shared target file that a niche before this one generated with the fragments of this
+    /// niche. The option of this niche (if any) is ignored.
+    async fn contribute_to_shared_target<IC>(&self, target_path: &AbsolutePath, option: Option<Bolt>, fragments: Vec<Bolt>, invar_config: &IC) -> Result<()>
+    where IC: InvarConfig
     {
-        if exists {
-            let source_root = directory_location.directory(&self.0);
-            let in_cumulus = directory.clone().relative_to(source_root);
-            self.visit_directory(directory_location, &in_cumulus).await
-        } else {
-            Ok(void_subtree())
+        let project_root = self.thunder_config.project_root();
+        if let Some(option) = option {
+            warn!("Ignore option of niche that contributes to a shared target: {:?}: {:?}", target_path, option.source());
+        }
+        if fragments.is_empty() {
+            debug!("Skip (no fragments for shared target): {:?}", target_path);
+            return Ok(());
         }
+        if invar_config.write_mode() == WriteMode::Ignore {
+            self.reporter.file(project_root, target_path, FileAction::Ignored);
+            return Ok(());
+        }
+        let file_system = self.thunder_config.project_file_system();
+        if file_system.path_type(target_path).await != PathType::File {
+            warn!("Skip contribution to shared target that does not exist: {:?}", target_path);
+            self.reporter.file(project_root, target_path, FileAction::Skipped);
+            return Ok(());
+        }
+        let executable = file_system.metadata(target_path).await?.is_executable();
+        let content = file_system.get_content(target_path.clone()).await?;
+        let _content_memory = self.run_options.memory().track(content.len());
+        let mut target_file = file_system.open_target(target_path.clone(), WriteMode::Overwrite, executable).await
+            .map_err(|error| self.note_fatal_write_error(target_path, error))?
+            .ok_or_else(|| anyhow!("Could not write shared target: {:?}", target_path))?;
+        let source_file = NumberedLines::new(LinesSourceFile::new(&content), target_path.clone());
+        let generated = self.generate_with_line_endings(fragments, invar_config, source_file, true, &target_file).await;
+        let closed = target_file.close().await;
+        closed.and(generated).map_err(|error| self.note_fatal_write_error(target_path, error))?;
+        self.reporter.file(project_root, target_path, FileAction::Contributed);
+        Ok(())
     }
 
-    async fn visit_directory<DL>(&self, directory_location: &DL, directory: &AbsolutePath) -> Result<(AHashMap<String, Vec<Bolt>>, AHashSet<SingleComponent>)>
-    where DL: DirectoryLocation
+    /// The role of the niche for a target file that it shares with other niches, if any.
+    fn shared_target_role(&self, target: &AbsolutePath) -> Option<SharedTargetRole> {
+        let relative = target.strip_prefix(self.thunder_config.project_root().as_path()).ok()?;
+        self.thunder_config.use_thundercloud().shared_targets().get(relative.to_string_lossy().as_ref()).copied()
+    }
+
+    /// Generates a target file from the lines of an option, with fragments spliced in at their placeholders, at the top
+    /// or bottom of the file (qualifier `head` or `tail`) or next to the first line that matches their anchor. With
+    /// `keep_placeholders`, placeholders without a matching fragment are copied as they are (instead of dropped), so
+    /// that other niches can fill them later.
+    async fn generate_option<IC, SF, TF>(&self, fragments: Vec<Bolt>, invar_config: &IC, source_file: NumberedLines<SF>, keep_placeholders: bool, target_file: &TF) -> Result<()>
+    where
+        IC: InvarConfig,
+        SF: SourceFile,
+        TF: TargetFile
     {
-        trace!("Visit directory: {:?} ⇒ {:?} [{:?}]", &directory, self.0.project_root(), self.0.invar());
-        let mut bolts = AHashMap::new();
-        let mut subdirectories = AHashSet::new();
-        let file_system = directory_location.file_system();
-        let entries = file_system.read_dir(directory).await
-            .map_err(|e| anyhow!(format!("error reading {:?}: {:?}", &directory, e)))?;
-        let mut entries = pin!(entries);
-        while let Some(entry) = entries.next().await {
-            let entry = entry?;
-            trace!("Visit entry: {entry:?}");
-            if entry.is_dir().await? {
-                if let Some(component) = entry.path().components().last() {
-                    let component = SingleComponent::try_new(Path::new(component.as_os_str()))?;
-                    subdirectories.insert(component);
+        let anchored = |anchor: &str| -> BTreeSet<String> {
+            fragments.iter().filter(|bolt| bolt.qualifier().as_deref() == Some(anchor)).map(Bolt::feature_name).collect()
+        };
+        let (heads, tails) = (anchored(HEAD_ANCHOR), anchored(TAIL_ANCHOR));
+        let anchors: Vec<(String, Anchor)> = invar_config.anchors().into_iter()
+            .filter(|(placeholder, _)| {
+                let (feature, qualifier) = placeholder.split_once('-').unwrap_or((placeholder, ""));
+                find_fragment(feature, qualifier, &fragments).is_some()
+            })
+            .collect();
+        if heads.is_empty() && tails.is_empty() && anchors.is_empty() {
+            self.generate_lines(&fragments, invar_config, source_file, keep_placeholders, target_file).await?;
+            return Ok(());
+        }
+        let source = source_file.source.clone();
+        let buffer = BufferedTargetFile::new(self.run_options.memory().track(0));
+        let placeholders = self.generate_lines(&fragments, invar_config, source_file, keep_placeholders, &buffer).await?;
+        for feature in heads.iter().filter(|feature| !placeholders.contains(&(feature.to_string(), HEAD_ANCHOR.to_string()))) {
+            self.find_and_include_fragment(feature, HEAD_ANCHOR, target_file, &fragments, invar_config).await?;
+        }
+        let mut pending = Vec::new();
+        for (placeholder, anchor) in anchors {
+            let (feature, qualifier) = placeholder.split_once('-').unwrap_or((&placeholder, ""));
+            if placeholders.contains(&(feature.to_string(), qualifier.to_string())) {
+                continue;
+            }
+            let regex = Regex::new(anchor.pattern()).map_err(|error| anyhow!("Invalid anchor for fragment {placeholder}: {:?}: {error}", &source))?;
+            let lines = self.anchored_fragment_lines(feature, qualifier, &fragments, invar_config).await?;
+            pending.push((placeholder, regex, anchor.position(), lines));
+        }
+        for line in buffer.into_lines() {
+            let (matched, unmatched) = pending.into_iter().partition::<Vec<_>, _>(|(_, regex, _, _)| regex.is_match(&line));
+            pending = unmatched;
+            for (_, _, _, lines) in matched.iter().filter(|(_, _, position, _)| *position == AnchorPosition::Before) {
+                for fragment_line in lines {
+                    send_to_writer(fragment_line, target_file).await?;
                 }
-            } else {
-                let file_name = entry.file_name().to_string_lossy().into_owned();
-                let source_path = RelativePath::from(file_name.as_str()).relative_to(directory);
-                let source = FileLocation { path: source_path, context: directory_location.context() };
-                let bolt;
-                if let Some(captures) = CONFIG_REGEX.captures(&file_name) {
-                    bolt = config_captures_to_bolt(captures, source)?;
-                } else if let Some(captures) = BOLT_REGEX_WITH_DOT.captures(&file_name) {
-                    debug!("Bolt regex with dot: {:?}", &file_name);
-                    bolt = captures_to_bolt(captures, source)?;
-                } else if let Some(captures) = BOLT_REGEX_WITHOUT_DOT.captures(&file_name) {
-                    debug!("Bolt regex without dot: {:?}", &file_name);
-                    bolt = captures_to_bolt(captures, source)?;
-                } else if let Some(captures) = PLAIN_FILE_REGEX_WITH_DOT.captures(&file_name) {
-                    debug!("Plain file regex with dot: {:?}", &file_name);
-                    let (base_name, extension) =
-                        if let (Some(b), Some(e)) = (captures.name("base"), captures.name("extension")) {
-                            (b.as_str(), e.as_str())
-                        } else {
-                            (&*file_name, "")
-                        };
-                    bolt = Bolt{
-                        base_name: base_name.to_string(),
-                        extension: extension.to_string(),
-                        feature_name: "@".to_string(),
-                        source,
-                        kind: BoltKind::Option
-                    }
-                } else {
-                    debug!("Unrecognized file name: {:?}", &file_name);
-                    bolt = Bolt{
-                        base_name: file_name.to_string(),
-                        extension: "".to_string(),
-                        feature_name: "@".to_string(),
-                        source,
-                        kind: BoltKind::Option
-                    }
+            }
+            send_to_writer(&line, target_file).await?;
+            for (_, _, _, lines) in matched.iter().filter(|(_, _, position, _)| *position == AnchorPosition::After) {
+                for fragment_line in lines {
+                    send_to_writer(fragment_line, target_file).await?;
                 }
-                debug!("Bolt: {bolt:?}");
-                add(&mut bolts, &bolt.target_name(), bolt);
             }
         }
-        for (target_name, bolts) in &bolts {
-            let mut qualifiers = Vec::new();
-            for bolt in bolts {
-                let qualifier = match &bolt.kind {
-                    BoltKind::Fragment { qualifier, .. } => qualifier,
-                    BoltKind::Unknown { qualifier, .. } => qualifier,
-                    _ => &None
-                };
-                if let Some(qualifier) = qualifier {
-                    qualifiers.push(qualifier.to_owned());
+        for (placeholder, regex, _, _) in pending {
+            warn!("No line matches the anchor of fragment {placeholder}: {:?}: {:?}", &source, regex.as_str());
+        }
+        for feature in tails.iter().filter(|feature| !placeholders.contains(&(feature.to_string(), TAIL_ANCHOR.to_string()))) {
+            self.find_and_include_fragment(feature, TAIL_ANCHOR, target_file, &fragments, invar_config).await?;
+        }
+        Ok(())
+    }
+
+    /// Generates the lines of the option with the fragments filled in. Returns the placeholders that the option has
+    /// (as pairs of feature and qualifier).
+    async fn generate_lines<IC, SF, TF>(&self, fragments: &Vec<Bolt>, invar_config: &IC, mut source_file: NumberedLines<SF>, keep_placeholders: bool, target_file: &TF) -> Result<AHashSet<(String, String)>>
+    where
+        IC: InvarConfig,
+        SF: SourceFile,
+        TF: TargetFile
+    {
+        let mut placeholders = AHashSet::new();
+        let mut conditions = Conditions::default();
+        while let Some(line) = source_file.next_line().await? {
+            if let Some(captures) = LOOP_REGEX.captures(&line) {
+                let location = source_file.location();
+                let body = loop_body(&captures, &mut source_file).await.map_err(|error| anyhow!("{location}: {error}"))?;
+                if conditions.is_active() {
+                    let mut lines = Vec::new();
+                    self.expand_loop(&captures, &body, invar_config, &source_file, &mut lines)
+                        .map_err(|error| error.context(location))?;
+                    for line in lines {
+                        send_to_writer(&line, target_file).await?;
+                    }
                 }
+                continue;
             }
-            debug!("Found bolts: {:?}: {:?}: {:?}: {:?}", &directory, target_name, bolts, qualifiers);
+            if let Some(captures) = CONDITION_REGEX.captures(&line) {
+                conditions.apply(&captures, |condition| self.is_condition_met(condition, invar_config))
+                    .map_err(|error| anyhow!("{}: {error}", source_file.location()))?;
+                continue;
+            }
+            if !conditions.is_active() {
+                continue;
+            }
+            let line = self.interpolate_line(&line, invar_config, || source_file.location())?;
+            if let Some(captures) = INCLUDE_REGEX.captures(&line) {
+                self.include_file(&captures["path"], invar_config, target_file, 0).await
+                    .map_err(|error| anyhow!("{}: {error}", source_file.location()))?;
+                continue;
+            }
+            if let Some(captures) = source_file.markers.captures(&line) {
+                let feature = captures.name("feature").map(|m| m.as_str().to_string()).unwrap_or("@".to_string());
+                let qualifier = captures.name("qualifier").map(|m| m.as_str().to_string()).unwrap_or("".to_string());
+                debug!("Found fragment: {:?}: {:?}", &feature, &qualifier);
+                placeholders.insert((feature.clone(), qualifier.clone()));
+                if keep_placeholders && find_fragment(&feature, &qualifier, fragments).is_none() {
+                    send_to_writer(&line, target_file).await?;
+                    if captures.name("bracket").map(|bracket| bracket.as_str()) == Some("BEGIN ") {
+                        self.copy_to_end_of_fragment(&mut source_file, &feature, &qualifier, target_file, fragments, invar_config).await?;
+                    }
+                    continue;
+                }
+                if let Some(bracket) = captures.name("bracket") {
+                    if bracket.as_str() == "BEGIN " {
+                        skip_to_end_of_fragment(&mut source_file, &feature, &qualifier, invar_config).await?;
+                    }
+                }
+                self.find_and_include_fragment(&feature, &qualifier, target_file, fragments, invar_config).await?;
+                continue;
+            }
+            send_to_writer(&line, target_file).await?;
         }
-        Ok((bolts, subdirectories))
+        if conditions.is_nested() {
+            bail!("{}: IF without ENDIF", source_file.location());
+        }
+        Ok(placeholders)
     }
-}
 
-async fn skip_to_end_of_fragment<SF>(lines: &mut SF, feature: &str, qualifier: &str) -> Result<()>
-where
-    SF: SourceFile
-{
-    while let Some(fragment_line) = lines.next_line().await? {
-        if let Some(captures) = FRAGMENT_REGEX.captures(&fragment_line) {
-            debug!("Found inner fragment: {:?}", &captures);
-            if is_matching_end(&captures, feature, qualifier) {
-                break;
-            }
+    /// Repeats the body of a `==== FOR item IN list ====` loop for every element of the array prop `list`, with prop
+    /// `item` set to the element. The body can contain conditional blocks and other loops.
+    fn expand_loop<IC, SF>(&self, captures: &Captures, body: &[(usize, String)], invar_config: &IC, source_file: &NumberedLines<SF>, output: &mut Vec<String>) -> Result<()>
+    where
+        IC: InvarConfig,
+        SF: SourceFile
+    {
+        let (item, list) = (&captures["item"], &captures["list"]);
+        let props = invar_config.props();
+        let elements = match crate::interpolate::lookup(props.as_ref(), list) {
+            Some(Value::Array(elements)) => elements.clone(),
+            Some(_) => bail!("Prop of loop is not an array: {list}"),
+            None if invar_config.interpolate_strict() => bail!("No value for loop prop: {list}"),
+            None => Vec::new(),
+        };
+        debug!("Loop: {:?} in {:?}: {} elements", item, list, elements.len());
+        for element in elements {
+            let mut item_props = Table::new();
+            item_props.insert(item.to_string(), element);
+            let item_config = invar_config.with_props(item_props);
+            self.expand_block(body, item_config.as_ref(), source_file, output)?;
         }
+        Ok(())
     }
-    Ok(())
-}
 
-fn is_matching_end(captures: &Captures, feature: &str, qualifier: &str) -> bool {
-    if let Some(inner_bracket) = captures.name("bracket") {
-        if inner_bracket.as_str() == "END " {
-            let inner_feature = captures.name("feature").map(|m| m.as_str().to_string()).unwrap_or("@".to_string());
-            if inner_feature != feature {
-                return false;
+    fn expand_block<IC, SF>(&self, body: &[(usize, String)], invar_config: &IC, source_file: &NumberedLines<SF>, output: &mut Vec<String>) -> Result<()>
+    where
+        IC: InvarConfig,
+        SF: SourceFile
+    {
+        let mut conditions = Conditions::default();
+        let mut index = 0;
+        while let Some((line_number, line)) = body.get(index) {
+            index += 1;
+            let location = || source_file.location_of(*line_number);
+            if let Some(captures) = LOOP_REGEX.captures(line) {
+                let end = index + matching_end_of_loop(&body[index..]).ok_or_else(|| anyhow!("{}: FOR without ENDFOR", location()))?;
+                if conditions.is_active() {
+                    self.expand_loop(&captures, &body[index..end], invar_config, source_file, output)
+                        .map_err(|error| error.context(location()))?;
+                }
+                index = end + 1;
+                continue;
             }
-            let inner_qualifier = captures.name("qualifier").map(|m| m.as_str().to_string()).unwrap_or("".to_string());
-            if inner_qualifier != qualifier {
-                return false;
+            if let Some(captures) = CONDITION_REGEX.captures(line) {
+                conditions.apply(&captures, |condition| self.is_condition_met(condition, invar_config))
+                    .map_err(|error| anyhow!("{}: {error}", location()))?;
+                continue;
+            }
+            if conditions.is_active() {
+                output.push(self.interpolate_line(line, invar_config, location)?);
             }
-            debug!("Found end of fragment: {:?}", &captures);
-            return true;
         }
+        if conditions.is_nested() {
+            bail!("{}: IF without ENDIF in loop", source_file.location());
+        }
+        Ok(())
     }
 
-    false
-}
-
-async fn send_to_writer<TF: TargetFile>(line: &str, target_file: &TF) -> Result<()> {
-    debug!("Send to writer: {:?}", &line);
-    target_file.write_line(line).await?;
-    Ok(())
-}
-
-fn interpolate<IC: InvarConfig>(line: &str, invar_config: &IC) -> String {
-    crate::interpolate::interpolate(line, invar_config.props().as_ref()).into_owned()
-}
-
-fn void_subtree() -> (AHashMap<String, Vec<Bolt>>, AHashSet<SingleComponent>) {
-    (AHashMap::new(), AHashSet::new())
-}
-
-fn get_invar_config(body: &str, config_format: ConfigFormat) -> Result<impl InvarConfig> {
-    let config = invar_config::from_str(body, config_format)?;
-    debug!("Invar configuration: {config:?}");
-    Ok(config)
-}
-
-fn combine(cumulus_bolts: AHashMap<String, Vec<Bolt>>, invar_bolts: AHashMap<String, Vec<Bolt>>) -> AHashMap<String, (Vec<Bolt>, Vec<Bolt>)> {
-    let cumulus_keys: AHashSet<String> = cumulus_bolts.iter().map(|(k, _)| k).map(ToOwned::to_owned).collect();
-    let invar_keys: AHashSet<String> = invar_bolts.iter().map(|(k, _)| k).map(ToOwned::to_owned).collect();
-    let keys = cumulus_keys.union(&invar_keys);
-    keys.map(
-        |k: &String|
-            (k.to_owned(),
-             (
-                 cumulus_bolts.get(k).map(ToOwned::to_owned).unwrap_or_else(Vec::new),
-                 invar_bolts.get(k).map(ToOwned::to_owned).unwrap_or_else(Vec::new)
-             )
-            )
-    ).collect()
-}
-
-fn combine_bolt_lists(cumulus_bolts_list: &Vec<Bolt>, invar_bolts_list: &Vec<Bolt>) -> Vec<Bolt> {
-    let mut result = invar_bolts_list.clone();
-    let mut invar_fragments = AHashSet::new();
-    for invar_bolt in invar_bolts_list {
-        if let BoltKind::Fragment { .. } = invar_bolt.kind {
-            invar_fragments.insert((invar_bolt.feature_name(), invar_bolt.qualifier()));
+    /// A condition holds if it names a selected feature or a prop that is `true` (or `"true"`).
+    fn is_condition_met<IC: InvarConfig>(&self, condition: &str, invar_config: &IC) -> bool {
+        if self.thunder_config.use_thundercloud().features().iter().any(|feature| feature == condition) {
+            return true;
+        }
+        match crate::interpolate::lookup(invar_config.props().as_ref(), condition) {
+            Some(Value::Boolean(value)) => *value,
+            Some(Value::String(value)) => value == "true",
+            _ => false,
         }
     }
-    for cumulus_bolt in cumulus_bolts_list {
-        if let BoltKind::Fragment { .. } = cumulus_bolt.kind {
-            if invar_fragments.contains(&(cumulus_bolt.feature_name(), cumulus_bolt.qualifier())) {
-                continue;
+
+    async fn find_and_include_fragment<IC, TF>(&self, feature: &str, qualifier: &str, target_file: &TF, fragments: &Vec<Bolt>, invar_config: &IC) -> Result<()>
+    where
+        IC: InvarConfig,
+        TF: TargetFile
+    {
+        let matching: Vec<&Bolt> = match invar_config.combine_fragments() {
+            CombineFragments::Replace => find_fragment(feature, qualifier, fragments).into_iter().collect(),
+            CombineFragments::Concatenate => find_fragments(feature, qualifier, fragments),
+        };
+        if let [bolt] = matching.as_slice() {
+            return self.include_fragment_bolt(bolt, feature, qualifier, target_file, fragments, invar_config).await;
+        }
+        // Concatenated fragments share the BEGIN marker of the first fragment and the END marker of the last one
+        let markers = FragmentMarkers::new(invar_config)?;
+        let last = matching.len().saturating_sub(1);
+        for (index, bolt) in matching.into_iter().enumerate() {
+            let buffer = BufferedTargetFile::new(self.run_options.memory().track(0));
+            self.include_fragment_bolt(bolt, feature, qualifier, &buffer, fragments, invar_config).await?;
+            let mut lines = buffer.into_lines();
+            if index < last && lines.last().is_some_and(|line| markers.is_bracket(line, "END ")) {
+                lines.pop();
+            }
+            let skip = usize::from(index > 0 && lines.first().is_some_and(|line| markers.is_bracket(line, "BEGIN ")));
+            for line in lines.into_iter().skip(skip) {
+                send_to_writer(&line, target_file).await?;
             }
         }
-        result.push(cumulus_bolt.clone());
+        Ok(())
     }
-    result
-}
 
-fn captures_to_bolt(captures: Captures, source: FileLocation) -> Result<Bolt> {
-    let extension = captures.name("extension").map(|m|m.as_str().to_string()).unwrap_or("".to_string());
-    let feature_name = captures.name("feature").map(|m|m.as_str().to_string()).unwrap_or("@".to_string());
-    let qualifier = captures.name("qualifier").map(|m|m.as_str().to_string());
-    if let (Some(base_name_orig), Some(bolt_type)) = (captures.name("base"), captures.name("bolt_type")) {
-        let base_name = to_base_name(base_name_orig.as_str());
-        let bolt_type = bolt_type.as_str();
-        let bolt =
-            if bolt_type == "option" {
-                Bolt{ base_name, extension, feature_name, source, kind: BoltKind::Option}
-            } else if bolt_type == "fragment" {
-                Bolt{ base_name, extension, feature_name, source, kind: BoltKind::Fragment { qualifier } }
-            } else {
-                Bolt{ base_name, extension, feature_name, source, kind: BoltKind::Unknown { qualifier } }
+    /// The lines of the fragments for a placeholder without their outer fragment markers, to insert them at an anchor.
+    async fn anchored_fragment_lines<IC>(&self, feature: &str, qualifier: &str, fragments: &Vec<Bolt>, invar_config: &IC) -> Result<Vec<String>>
+    where IC: InvarConfig
+    {
+        let buffer = BufferedTargetFile::new(self.run_options.memory().track(0));
+        self.find_and_include_fragment(feature, qualifier, &buffer, fragments, invar_config).await?;
+        let mut lines = buffer.into_lines();
+        let markers = FragmentMarkers::new(invar_config)?;
+        if lines.last().is_some_and(|line| markers.is_bracket(line, "END ")) {
+            lines.pop();
+        }
+        if lines.first().is_some_and(|line| markers.is_bracket(line, "BEGIN ")) {
+            lines.remove(0);
+        }
+        Ok(lines)
+    }
+
+    async fn include_fragment_bolt<IC, TF>(&self, bolt: &Bolt, feature: &str, qualifier: &str, target_file: &TF, fragments: &Vec<Bolt>, invar_config: &IC) -> Result<()>
+    where
+        IC: InvarConfig,
+        TF: TargetFile
+    {
+        debug!("Found fragment to include: {:?}", bolt);
+        let source = bolt.source();
+        let markers = FragmentMarkers::new(invar_config)?;
+        match bolt.context() {
+            ThunderCloud => {
+                let fs = self.thunder_config.thundercloud_file_system();
+                let source_file = NumberedLines::new(fs.open_source(source.clone()).await?, source.clone())
+                    .with_comment_prefix(invar_config.template_comment_prefix())
+                    .with_markers(markers);
+                self.include_fragment(source_file, feature, qualifier, target_file, fragments, invar_config).await
+            },
+            Project => {
+                let fs = self.thunder_config.project_file_system();
+                let source_file = NumberedLines::new(fs.open_source(source.clone()).await?, source.clone())
+                    .with_comment_prefix(invar_config.template_comment_prefix())
+                    .with_markers(markers);
+                self.include_fragment(source_file, feature, qualifier, target_file, fragments, invar_config).await
+            }
+        }
+    }
+
+    async fn include_fragment<SF, TF, IC>(&self, mut source_file: NumberedLines<SF>, feature: &str, qualifier: &str, target_file: &TF, fragments: &Vec<Bolt>, invar_config: &IC) -> Result<()>
+    where
+        SF: SourceFile,
+        TF: TargetFile,
+        IC: InvarConfig
+    {
+        while let Some(line) = source_file.next_line().await? {
+            let line = interpolate(&line, invar_config);
+            if let Some(captures) = source_file.markers.captures(&line) {
+                let placeholder_feature = captures.name("feature").map(|m| m.as_str().to_string()).unwrap_or("@".to_string());
+                let placeholder_qualifier = captures.name("qualifier").map(|m| m.as_str().to_string()).unwrap_or("".to_string());
+                debug!("Found placeholder: {:?}: {:?}", &feature, &qualifier);
+                if let Some(bracket) = captures.name("bracket") {
+                    if bracket.as_str() == "BEGIN " && placeholder_feature == feature && placeholder_qualifier == qualifier {
+                        send_to_writer(&line, target_file).await?;
+                        self.copy_to_end_of_fragment(&mut source_file, &feature, &qualifier, target_file, fragments, invar_config).await?;
+                    }
+                }
+                return Ok(());
+            }
+        }
+        debug!("Include fragment: placeholder not found");
+        Ok(())
+    }
+
+    async fn copy_to_end_of_fragment<SF, TF, IC>(&self, lines: &mut NumberedLines<SF>, feature: &str, qualifier: &str, target_file: &TF, fragments: &Vec<Bolt>, invar_config: &IC) -> Result<()>
+    where
+        SF: SourceFile,
+        TF: TargetFile,
+        IC: InvarConfig
+    {
+        while let Some(fragment_line) = lines.next_line().await? {
+            let line = self.interpolate_line(&fragment_line, invar_config, || lines.location())?;
+            if let Some(captures) = INCLUDE_REGEX.captures(&line) {
+                self.include_file(&captures["path"], invar_config, target_file, 0).await
+                    .map_err(|error| anyhow!("{}: {error}", lines.location()))?;
+                continue;
+            }
+            if let Some(captures) = lines.markers.captures(&line) {
+                debug!("Found inner fragment: {:?}", &captures);
+                if is_matching_end(&captures, feature, qualifier) {
+                    send_to_writer(&line, target_file).await?;
+                    break;
+                } else {
+                    if let Some(bracket) = captures.name("bracket") {
+                        if bracket.as_str() == "BEGIN " {
+                            skip_to_end_of_fragment(lines, &feature, &qualifier, invar_config).await?;
+                        }
+                    }
+                    Box::pin(self.find_and_include_fragment(&feature, &qualifier, target_file, fragments, invar_config)).await?;
+                    continue;
+                }
+            }
+            send_to_writer(&line, target_file).await?;
+        }
+        Ok(())
+    }
+
+    /// Writes the lines of the file that a `==== INCLUDE path ====` directive names, with interpolation applied. The path
+    /// is relative to the invar directory of the niche or, if the invar has no such file, to the cumulus of the
+    /// thundercloud.
+    async fn include_file<IC, TF>(&self, path: &str, invar_config: &IC, target_file: &TF, depth: usize) -> Result<()>
+    where
+        IC: InvarConfig,
+        TF: TargetFile
+    {
+        if depth >= MAX_INCLUDE_DEPTH {
+            bail!("Includes nested too deeply: {path}");
+        }
+        if Path::new(path).components().any(|component| !matches!(component, std::path::Component::Normal(_))) {
+            bail!("Include path must stay inside the invar or cumulus: {path}");
+        }
+        let invar_path = AbsolutePath::new(path, self.thunder_config.invar());
+        let cumulus_path = AbsolutePath::new(path, self.thunder_config.cumulus());
+        let (source, content) = if self.thunder_config.project_file_system().path_type(&invar_path).await == PathType::File {
+            (invar_path.clone(), self.thunder_config.project_file_system().get_content(invar_path).await?)
+        } else if self.thunder_config.thundercloud_file_system().path_type(&cumulus_path).await == PathType::File {
+            (cumulus_path.clone(), self.thunder_config.thundercloud_file_system().get_content(cumulus_path).await?)
+        } else {
+            bail!("Included file not found in invar or cumulus: {path}");
+        };
+        debug!("Include: {:?}", &source);
+        let mut lines = NumberedLines::new(LinesSourceFile::new(&content), source)
+            .with_comment_prefix(invar_config.template_comment_prefix());
+        while let Some(line) = lines.next_line().await? {
+            let line = self.interpolate_line(&line, invar_config, || lines.location())?;
+            if let Some(captures) = INCLUDE_REGEX.captures(&line) {
+                Box::pin(self.include_file(&captures["path"], invar_config, target_file, depth + 1)).await
+                    .map_err(|error| anyhow!("{}: {error}", lines.location()))?;
+                continue;
+            }
+            send_to_writer(&line, target_file).await?;
+        }
+        Ok(())
+    }
+
+    async fn infer_target_props<IC>(&self, plan: TargetPlan<IC>, props: &mut Table) -> Result<()>
+    where IC: InvarConfig
+    {
+        let Some(option) = plan.option else { return Ok(()) };
+        if !plan.invar_config.interpolate() {
+            return Ok(());
+        }
+        let project_fs = self.thunder_config.project_file_system();
+        if project_fs.path_type(&plan.target).await != PathType::File {
+            debug!("Nothing to infer from (target does not exist): {:?}", &plan.target);
+            return Ok(());
+        }
+        let existing = project_fs.get_content(plan.target.clone()).await?;
+        let existing_lines: Vec<&str> = existing.lines().collect();
+        let mut templates = vec![self.get_bolt_content(&option).await?];
+        for fragment in &plan.fragments {
+            templates.push(self.get_bolt_content(fragment).await?);
+        }
+        for template in &templates {
+            for template_line in template.lines() {
+                let Some((pattern, names)) = crate::interpolate::placeholder_pattern(template_line, plan.invar_config.placeholder_delimiters().as_ref()) else { continue };
+                let Some(captures) = existing_lines.iter().find_map(|line| pattern.captures(line)) else { continue };
+                for (index, name) in names.iter().enumerate() {
+                    let Some(value) = captures.get(index + 1) else { continue };
+                    let value = Value::String(value.as_str().to_string());
+                    match crate::interpolate::lookup(props, name) {
+                        Some(known) if known != &value => warn!("Conflicting values for {:?}: {:?} vs {:?} (in {:?})", name, known, &value, &plan.target),
+                        Some(_) => (),
+                        None => {
+                            debug!("Inferred {:?} = {:?} from {:?}", name, &value, &plan.target);
+                            crate::interpolate::insert_dotted(props, name, value);
+                        }
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Describes the source of a bolt relative to the thundercloud directory or the project root.
+    fn describe_bolt(&self, bolt: &Bolt) -> String {
+        let (name, base) = match bolt.context() {
+            ThunderCloud => ("thundercloud", self.thunder_config.thundercloud_directory()),
+            Project => ("project", self.thunder_config.project_root()),
+        };
+        let path = bolt.source().strip_prefix(base.as_path()).unwrap_or(bolt.source().as_path());
+        format!("{name}:{}", path.to_string_lossy())
+    }
+
+    /// Returns the props that are interpolated in a target file, along with the configuration file that provides them.
+    async fn prop_reads<IC: InvarConfig>(&self, plan: &TargetPlan<IC>) -> Result<Vec<PropRead>> {
+        let Some(option) = &plan.option else { return Ok(Vec::new()) };
+        let invar_config = &plan.invar_config;
+        if !invar_config.interpolate() || invar_config.write_mode() == WriteMode::Ignore {
+            return Ok(Vec::new());
+        }
+        let props = invar_config.props();
+        let mut names: Vec<String> = Vec::new();
+        for bolt in std::iter::once(option).chain(&plan.fragments) {
+            let content = self.get_bolt_content(bolt).await?;
+            for name in crate::interpolate::used_placeholders(&content, &props, &invar_config.placeholder_delimiters()) {
+                if !names.contains(&name) {
+                    names.push(name);
+                }
+            }
+        }
+        if names.is_empty() {
+            return Ok(Vec::new());
+        }
+        let thundercloud_config = get_config(self.thunder_config.thundercloud_directory(), self.thunder_config.thundercloud_file_system().clone()).await?;
+        let sources = self.prop_sources(plan, &thundercloud_config).await?;
+        let project_root = self.thunder_config.project_root();
+        let target = plan.target.strip_prefix(project_root.as_path()).unwrap_or(plan.target.as_path()).to_string_lossy().to_string();
+        let reads = names.into_iter().map(|prop| {
+            let value = crate::interpolate::lookup(&props, &prop).and_then(crate::interpolate::scalar_to_string).unwrap_or_default().into_owned();
+            let reserved = prop.split('.').next().filter(|name| [NOW_PROPS, TIMESTAMP_PROP, ENVIRONMENT_PROPS].contains(name) || CONTEXT_PROPS.contains(name));
+            let source = match reserved {
+                Some(name) => format!("igor:{name}"),
+                None => sources.iter()
+                    .find(|(_, source_props)| crate::interpolate::lookup(source_props, &prop).is_some())
+                    .map(|(source, _)| source.clone())
+                    .unwrap_or_else(|| "unknown".to_string()),
             };
-        Ok(bolt)
-    } else {
-        bail!("Internal error")
+            PropRead { niche: thundercloud_config.niche().name().to_string(), target: target.clone(), prop, value, source }
+        });
+        Ok(reads.collect())
     }
-}
-fn config_captures_to_bolt(captures: Captures, source: FileLocation) -> Result<Bolt> {
-    let extension = captures.name("extension").map(|m|m.as_str().to_string()).unwrap_or("".to_string());
-    let feature_name = captures.name("feature").map(|m|m.as_str().to_string()).unwrap_or("@".to_string());
-    if let (Some(base_name_orig), Some(format_match)) = (captures.name("base"), captures.name("format")) {
-        let base_name = to_base_name(base_name_orig.as_str());
-        let format_str = format_match.as_str();
-        let format =
-            if format_str == "toml" { ConfigFormat::TOML }
-            else if format_str == "yaml" { ConfigFormat::YAML }
-            else { bail!("Unknown config file format: {:?}", format_match) }
-        ;
-        let config =
-            Bolt{
-                base_name: base_name.to_string(),
-                extension: extension.to_string(),
-                feature_name: feature_name.to_string(),
-                source,
-                kind: BoltKind::Config { format },
+
+    /// Returns the configuration files that provide props for a target file with their props, from highest to lowest
+    /// precedence.
+    async fn prop_sources<IC: InvarConfig, TCC: ThundercloudConfig>(&self, plan: &TargetPlan<IC>, thundercloud_config: &TCC) -> Result<Vec<(String, Table)>> {
+        let mut sources = Vec::new();
+        for bolt in plan.configs.iter().rev() {
+            let BoltKind::Config { format } = bolt.kind else { continue };
+            let bolt_invar_config = get_invar_config(&self.get_bolt_content(bolt).await?, format)?;
+            sources.push((self.describe_bolt(bolt), bolt_invar_config.props().into_owned()));
+        }
+        let extension = plan.target.extension().map(|extension| extension.to_string_lossy().to_string()).unwrap_or_default();
+        let project_config = "project:CargoCult.toml".to_string();
+        sources.push((project_config.clone(), props_for_extension(self.thunder_config.use_thundercloud().invar_defaults().as_ref(), &extension)));
+        let default_props = props_for_extension(self.thunder_config.default_invar_config(), &extension);
+        let project_root = self.thunder_config.project_root();
+        if let Some(answers_path) = self.thunder_config.invar().parent().and_then(|niche_directory| AbsolutePath::try_new(niche_directory.join("answers.toml")).ok()) {
+            let answers: Table = crate::prompt::load_answers(&answers_path, &self.thunder_config.project_file_system()).await?.into_iter()
+                .filter(|(key, value)| default_props.get(key) == Some(value))
+                .collect();
+            let display = answers_path.strip_prefix(project_root.as_path()).unwrap_or(answers_path.as_path()).to_string_lossy().to_string();
+            sources.push((format!("project:{display}"), answers));
+        }
+        sources.push((project_config, default_props));
+        sources.push(("thundercloud:thundercloud.toml".to_string(), props_for_extension(thundercloud_config.invar_defaults().as_ref(), &extension)));
+        Ok(sources)
+    }
+
+    async fn get_bolt_content(&self, bolt: &Bolt) -> Result<String> {
+        match bolt.context() {
+            ThunderCloud => self.thunder_config.thundercloud_file_system().get_content(bolt.source().clone()).await,
+            Project => self.thunder_config.project_file_system().get_content(bolt.source().clone()).await,
+        }
+    }
+
+    async fn update_invar_config<'a, IC>(&self, invar_config: &'a IC, bolts: &Vec<Bolt>) -> Result<Cow<'a, IC>>
+    where
+        IC: InvarConfig,
+    {
+        let mut use_config = Cow::Borrowed(invar_config);
+        for bolt in bolts {
+            debug!("Bolt kind: {:?}", bolt.kind_name());
+            if let BoltKind::Config { format } = bolt.kind {
+                let thundercloud_fs = self.thunder_config.thundercloud_file_system();
+                let project_fs = self.thunder_config.project_file_system();
+                debug!("Bolt context: {:?}", bolt.context());
+                let bolt_invar_config_body = match bolt.context() {
+                    ThunderCloud => thundercloud_fs.get_content(bolt.source().clone()).await?,
+                    Project => project_fs.get_content(bolt.source().clone()).await?,
+                };
+                let bolt_invar_config = get_invar_config(&bolt_invar_config_body, format)
+                    .map_err(|error| document::locate_error(bolt.source(), &bolt_invar_config_body, error))?;
+                let root = match bolt.context() {
+                    ThunderCloud => self.thunder_config.cumulus(),
+                    Project => self.thunder_config.invar(),
+                };
+                let directory = bolt.source().parent().and_then(|parent| parent.strip_prefix(root.as_path()).ok()).map(|directory| directory.to_string_lossy().to_string()).unwrap_or_default();
+                let bolt_invar_config = bolt_invar_config.with_rules_in_directory(&directory).into_owned();
+                debug!("Apply bolt configuration: {:?}: {:?} += {:?}", bolt.target_name(), invar_config, &bolt_invar_config);
+                let new_use_config = use_config.to_owned().with_invar_config(bolt_invar_config).into_owned();
+                use_config = Cow::Owned(new_use_config);
             }
-        ;
-        Ok(config)
-    } else {
-        bail!("Internal error")
+        }
+        debug!("Updated invar config: {:?}", use_config);
+        Ok(use_config)
     }
-}
 
-fn to_base_name(base_name_orig: &str) -> String {
-    let base_name = base_name_orig.to_string();
-    let base_name = base_name.strip_prefix("dot_")
-        .map(|stripped| ".".to_string() + stripped)
-        .unwrap_or(base_name);
-    base_name.strip_prefix("x_").unwrap_or(&base_name).to_string()
-}
+    fn is_feature_selected(&self, feature: &str) -> bool {
+        feature == "@" || self.thunder_config.use_thundercloud().features().iter().any(|selected| selected == feature)
+    }
+
+    fn combine_and_filter_bolt_lists(&self, cumulus_bolts_list: &[Bolt], invar_bolts_list: &[Bolt]) -> Result<SelectedBolts> {
+        let combined = combine_bolt_lists(cumulus_bolts_list, invar_bolts_list);
+        self.filter_options(&combined)
+    }
+
+    /// Selects the option, the fragments, the patches and the appends of the selected features. Patches and appends from
+    /// the cumulus come before those from the invar.
+    fn filter_options(&self, bolt_list: &Vec<Bolt>) -> Result<SelectedBolts> {
+        let mut features = AHashSet::new();
+        features.insert("@");
+        for feature in self.thunder_config.use_thundercloud().features() {
+            features.insert(feature);
+        }
+        let mut options = Vec::new();
+        let mut fallbacks = Vec::new();
+        let mut fragments = Vec::new();
+        let mut patches = Vec::new();
+        let mut appends = Vec::new();
+        for bolt in bolt_list {
+            if features.contains(&bolt.feature_name() as &str) {
+                if let BoltKind::Option { fallback } = bolt.kind {
+                    if fallback {
+                        fallbacks.push(bolt.clone());
+                    } else {
+                        options.push(bolt.clone());
+                    }
+                } else if let BoltKind::Symlink | BoltKind::Binary = bolt.kind {
+                    options.push(bolt.clone());
+                } else if let BoltKind::Fragment { .. } = bolt.kind {
+                    fragments.push(bolt.clone())
+                } else if let BoltKind::Patch = bolt.kind {
+                    patches.push(bolt.clone())
+                } else if let BoltKind::Append = bolt.kind {
+                    appends.push(bolt.clone())
+                }
+            }
+        }
+        let first_option = match self.select_option(options)? {
+            Some(option) => Some(option),
+            None => self.select_option(fallbacks)?,
+        };
+        patches.sort_by_cached_key(|bolt| (bolt.context(), bolt.source().to_path_buf()));
+        appends.sort_by_cached_key(|bolt| (bolt.context(), bolt.source().to_path_buf()));
+        Ok((first_option, fragments, patches, appends))
+    }
+
+    /// Selects the option with the highest precedence: options in the invar come before options in the cumulus, then
+    /// options follow the order of the selected features, with options without a feature (`@`) last, unless `@` is
+    /// selected explicitly. Fails if two options have the same precedence and the niche has strict options.
+    fn select_option(&self, options: Vec<Bolt>) -> Result<Option<Bolt>> {
+        let features = self.thunder_config.use_thundercloud().features();
+        let precedence = |bolt: &Bolt| {
+            let feature_rank = features.iter().position(|feature| *feature == bolt.feature_name).unwrap_or(features.len());
+            (Reverse(bolt.context()), feature_rank)
+        };
+        let mut options = options;
+        options.sort_by_cached_key(|bolt| (precedence(bolt), bolt.source().to_path_buf()));
+        if let [first, second, ..] = options.as_slice() {
+            if precedence(first) == precedence(second) {
+                if self.thunder_config.use_thundercloud().strict_options() {
+                    bail!("Ambiguous options for {:?}: {:?} and {:?}", first.target_name(), first.source().to_string_lossy(), second.source().to_string_lossy());
+                }
+                warn!("Ambiguous options for {:?}: select {:?} over {:?}", first.target_name(), first.source().to_string_lossy(), second.source().to_string_lossy());
+            }
+        }
+        Ok(options.into_iter().next())
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    async fn plan_subdirectories<IC>(&self, directory: &RelativePath, cumulus_subdirectories: AHashSet<SingleComponent>, invar_subdirectories: AHashSet<SingleComponent>, invar_config: &IC, configs: &[Bolt], extension_configs: &BTreeMap<String, IC>, plan: &mut NichePlan<IC>) -> Result<()>
+    where
+        TC: ThunderConfig,
+        IC: InvarConfig
+    {
+        let mut invar_subdirectories = invar_subdirectories;
+        for path in cumulus_subdirectories {
+            let subdirectory_thumbs = if let Some(_) = invar_subdirectories.get(&path) {
+                invar_subdirectories.remove(&path);
+                FromBothCumulusAndInvar
+            } else {
+                FromCumulus
+            };
+            let mut subdirectory = directory.clone();
+            let path: RelativePath = path.try_into()?;
+            subdirectory.push(path);
+            Box::pin(self.plan_subtree(&subdirectory, subdirectory_thumbs, invar_config, configs, extension_configs, plan)).await?;
+        }
+        for path in invar_subdirectories {
+            let mut subdirectory = directory.clone();
+            let path: RelativePath = path.try_into()?;
+            subdirectory.push(path);
+            Box::pin(self.plan_subtree(&subdirectory, FromInvar, invar_config, configs, extension_configs, plan)).await?;
+        }
+        Ok(())
+    }
+
+    async fn try_visit_directory<DL>(&self, exists: bool, directory_location: &DL, directory: &RelativePath, props: &Table) -> Result<(AHashMap<String, Vec<Bolt>>, AHashSet<SingleComponent>)>
+    where DL: DirectoryLocation
+    {
+        if exists {
+            let source_root = directory_location.directory(&self.thunder_config);
+            let in_cumulus = directory.clone().relative_to(source_root);
+            self.visit_directory(directory_location, &in_cumulus, props).await
+        } else {
+            Ok(void_subtree())
+        }
+    }
+
+    /// Collects the bolts and the subdirectories of a directory. Placeholders in the bolt type, feature and qualifier of
+    /// a file name (like `clock+option-{{flavor}}.yaml`) are replaced by the values of the props.
+    async fn visit_directory<DL>(&self, directory_location: &DL, directory: &AbsolutePath, props: &Table) -> Result<(AHashMap<String, Vec<Bolt>>, AHashSet<SingleComponent>)>
+    where DL: DirectoryLocation
+    {
+        trace!("Visit directory: {:?} ⇒ {:?} [{:?}]", &directory, self.thunder_config.project_root(), self.thunder_config.invar());
+        let mut bolts = AHashMap::new();
+        let mut subdirectories = AHashSet::new();
+        let file_system = directory_location.file_system();
+        let entries = file_system.read_dir(directory).await
+            .map_err(|e| anyhow!(format!("error reading {:?}: {:?}", &directory, e)))?;
+        let mut entries = pin!(entries);
+        let mut visited = Vec::new();
+        while let Some(entry) = entries.next().await {
+            visited.push(entry?);
+        }
+        self.run_options.shuffle(&mut visited, DirEntry::file_name);
+        for entry in visited {
+            trace!("Visit entry: {entry:?}");
+            let is_dir = match self.symlink_type(directory_location, directory, &entry).await? {
+                Some(path_type) => path_type == PathType::Directory,
+                None => continue,
+            };
+            if is_dir {
+                if let Some(component) = entry.path().components().last() {
+                    let component = SingleComponent::try_new(Path::new(component.as_os_str()))?;
+                    subdirectories.insert(component);
+                }
+            } else {
+                let file_name = entry.file_name().to_string_lossy().into_owned();
+                let source_path = RelativePath::from(file_name.as_str()).relative_to(directory);
+                let source = FileLocation { path: source_path, context: directory_location.context() };
+                let Some(bolt_name) = interpolate_bolt_name(&file_name, props) else {
+                    warn!("Skip file with placeholders without a value in its name: {:?}", source.path);
+                    continue;
+                };
+                let (name_class, bolt) = classify_file_name(&bolt_name, source)?;
+                if self.run_options.explain_names() {
+                    eprintln!("{}", explain_name(name_class, &bolt));
+                }
+                debug!("Bolt: {bolt:?}");
+                add(&mut bolts, &bolt.target_name(), bolt);
+            }
+        }
+        for (target_name, bolts) in &bolts {
+            let mut qualifiers = Vec::new();
+            for bolt in bolts {
+                let qualifier = match &bolt.kind {
+                    BoltKind::Fragment { qualifier, .. } => qualifier,
+                    BoltKind::Unknown { qualifier, .. } => qualifier,
+                    _ => &None
+                };
+                if let Some(qualifier) = qualifier {
+                    qualifiers.push(qualifier.to_owned());
+                }
+            }
+            debug!("Found bolts: {:?}: {:?}: {:?}: {:?}", &directory, target_name, bolts, qualifiers);
+        }
+        Ok((bolts, subdirectories))
+    }
+
+    /// Returns the type of a directory entry, after following a symbolic link. Returns `None` for entries to skip: a
+    /// dangling link, or a link to a directory that is being visited already (which would recurse forever).
+    async fn symlink_type<DL>(&self, directory_location: &DL, directory: &AbsolutePath, entry: &impl DirEntry) -> Result<Option<PathType>>
+    where DL: DirectoryLocation
+    {
+        if entry.is_dir().await? {
+            return Ok(Some(PathType::Directory));
+        }
+        let file_system = directory_location.file_system();
+        let entry_path = AbsolutePath::new(entry.file_name(), directory);
+        if file_system.symlink_metadata(&entry_path).await?.path_type() != PathType::Symlink {
+            return Ok(Some(PathType::File));
+        }
+        match file_system.path_type(&entry_path).await {
+            PathType::Missing => {
+                warn!("Skip dangling symbolic link: {:?}", entry_path);
+                Ok(None)
+            },
+            PathType::Directory => {
+                let link_target = file_system.canonicalize(&entry_path).await?;
+                let source_root = directory_location.directory(&self.thunder_config);
+                let mut ancestor = Some(directory.as_path());
+                while let Some(ancestor_path) = ancestor.filter(|path| path.starts_with(source_root.as_path())) {
+                    let canonical_ancestor = file_system.canonicalize(&AbsolutePath::try_new(ancestor_path.to_path_buf())?).await?;
+                    if canonical_ancestor.starts_with(link_target.as_path()) {
+                        warn!("Skip symbolic link to a directory that is visited already: {:?}", entry_path);
+                        return Ok(None);
+                    }
+                    ancestor = ancestor_path.parent();
+                }
+                Ok(Some(PathType::Directory))
+            },
+            path_type => Ok(Some(path_type)),
+        }
+    }
+}
+
+async fn skip_to_end_of_fragment<SF, IC>(lines: &mut NumberedLines<SF>, feature: &str, qualifier: &str, invar_config: &IC) -> Result<()>
+where
+    SF: SourceFile,
+    IC: InvarConfig
+{
+    while let Some(fragment_line) = lines.next_line().await? {
+        let fragment_line = interpolate(&fragment_line, invar_config);
+        if let Some(captures) = lines.markers.captures(&fragment_line) {
+            debug!("Found inner fragment: {:?}", &captures);
+            if is_matching_end(&captures, feature, qualifier) {
+                break;
+            }
+        }
+    }
+    Ok(())
+}
+
+fn find_fragment<'a>(feature: &str, qualifier: &str, fragments: &'a [Bolt]) -> Option<&'a Bolt> {
+    fragments.iter().find(|bolt| is_fragment_for(bolt, feature, qualifier))
+}
+
+fn find_fragments<'a>(feature: &str, qualifier: &str, fragments: &'a [Bolt]) -> Vec<&'a Bolt> {
+    fragments.iter().filter(|bolt| is_fragment_for(bolt, feature, qualifier)).collect()
+}
+
+fn is_fragment_for(bolt: &Bolt, feature: &str, qualifier: &str) -> bool {
+    let BoltKind::Fragment { qualifier: fragment_qualifier, .. } = &bolt.kind else { return false };
+    bolt.feature_name == feature && fragment_qualifier.as_deref().unwrap_or("") == qualifier
+}
+
+fn is_matching_end(captures: &Captures, feature: &str, qualifier: &str) -> bool {
+    if let Some(inner_bracket) = captures.name("bracket") {
+        if inner_bracket.as_str() == "END " {
+            let inner_feature = captures.name("feature").map(|m| m.as_str().to_string()).unwrap_or("@".to_string());
+            if inner_feature != feature {
+                return false;
+            }
+            let inner_qualifier = captures.name("qualifier").map(|m| m.as_str().to_string()).unwrap_or("".to_string());
+            if inner_qualifier != qualifier {
+                return false;
+            }
+            debug!("Found end of fragment: {:?}", &captures);
+            return true;
+        }
+    }
+
+    false
+}
+
+async fn write_content<TF: TargetFile>(content: &str, line_endings: LineEndings, target_file: &TF) -> Result<()> {
+    for line in content.lines() {
+        match line_endings {
+            LineEndings::Lf => send_to_writer(line, target_file).await?,
+            LineEndings::Crlf => send_to_writer(line, &CrlfTargetFile(target_file)).await?,
+        }
+    }
+    Ok(())
+}
+
+async fn send_to_writer<TF: TargetFile>(line: &str, target_file: &TF) -> Result<()> {
+    debug!("Send to writer: {:?}", &line);
+    target_file.write_line(line).await?;
+    Ok(())
+}
+
+/// The props of an invar configuration, including the props that it specifies for the given extension.
+fn props_for_extension<IC: InvarConfig>(invar_config: &IC, extension: &str) -> Table {
+    let mut props = invar_config.props().into_owned();
+    if let Some(defaults) = invar_config.by_extension().get(extension) {
+        props.extend(defaults.props().into_owned());
+    }
+    props
+}
+
+fn interpolate<IC: InvarConfig>(line: &str, invar_config: &IC) -> String {
+    crate::interpolate::interpolate_with_delimiters(line, invar_config.props().as_ref(), invar_config.placeholder_delimiters().as_ref()).into_owned()
+}
+
+/// Reads the lines of a loop up to the matching `==== ENDFOR ====`, along with their line numbers.
+async fn loop_body<SF: SourceFile>(captures: &Captures<'_>, lines: &mut NumberedLines<SF>) -> Result<Vec<(usize, String)>> {
+    if &captures["directive"] == "ENDFOR" {
+        bail!("ENDFOR without FOR");
+    }
+    if captures.name("list").is_none() {
+        bail!("Expected FOR item IN list");
+    }
+    let mut body = Vec::new();
+    let mut depth = 0;
+    while let Some(line) = lines.next_line().await? {
+        if let Some(captures) = LOOP_REGEX.captures(&line) {
+            if &captures["directive"] == "FOR" {
+                depth += 1;
+            } else if depth == 0 {
+                return Ok(body);
+            } else {
+                depth -= 1;
+            }
+        }
+        body.push((lines.line_number, line));
+    }
+    bail!("FOR without ENDFOR")
+}
+
+/// Returns the index of the `==== ENDFOR ====` line that ends the loop that the lines are the body of.
+fn matching_end_of_loop(lines: &[(usize, String)]) -> Option<usize> {
+    let mut depth = 0;
+    for (index, (_, line)) in lines.iter().enumerate() {
+        let Some(captures) = LOOP_REGEX.captures(line) else { continue };
+        if &captures["directive"] == "FOR" {
+            depth += 1;
+        } else if depth == 0 {
+            return Some(index);
+        } else {
+            depth -= 1;
+        }
+    }
+    None
+}
+
+/// Keeps track of the nested `==== IF condition ====` blocks of an option file.
+#[derive(Default)]
+struct Conditions(Vec<ConditionalBlock>);
+
+struct ConditionalBlock {
+    enclosing_active: bool,
+    condition: bool,
+    in_else: bool,
+}
+
+impl Conditions {
+    /// Whether lines at the current position end up in the target file.
+    fn is_active(&self) -> bool {
+        self.0.last().map(|block| block.enclosing_active && block.condition != block.in_else).unwrap_or(true)
+    }
+
+    fn is_nested(&self) -> bool {
+        !self.0.is_empty()
+    }
+
+    fn apply(&mut self, captures: &Captures, is_met: impl FnOnce(&str) -> bool) -> Result<()> {
+        let condition = captures.name("condition").map(|m| m.as_str());
+        match (&captures["directive"], condition) {
+            ("IF", Some(condition)) => {
+                let enclosing_active = self.is_active();
+                let condition = enclosing_active && is_met(condition) != captures.name("negate").is_some();
+                debug!("Conditional block: {:?}: {:?}", &captures["condition"], condition);
+                self.0.push(ConditionalBlock { enclosing_active, condition, in_else: false });
+            },
+            ("IF", None) => bail!("IF without condition"),
+            ("ELSE", None) => {
+                match self.0.last_mut() {
+                    Some(block) if !block.in_else => block.in_else = true,
+                    Some(_) => bail!("Second ELSE for the same IF"),
+                    None => bail!("ELSE without IF"),
+                }
+            },
+            ("ENDIF", None) => {
+                if self.0.pop().is_none() {
+                    bail!("ENDIF without IF");
+                }
+            },
+            (directive, _) => bail!("Unexpected condition after {directive}"),
+        }
+        Ok(())
+    }
+}
+
+/// A line that begins or ends a kept region: `==== BEGIN KEEP name ====` (or `==== KEEP name ====`) and
+/// `==== END KEEP name ====` (or `==== END KEEP ====`, which ends the region that is open).
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum KeepMarker {
+    Begin(String),
+    End(Option<String>),
+}
+
+impl KeepMarker {
+    fn parse(line: &str) -> Option<KeepMarker> {
+        let captures = KEEP_REGEX.captures(line)?;
+        let name = captures.name("name").map(|m| m.as_str().to_string());
+        match captures.name("bracket").map(|m| m.as_str()) {
+            Some("END") => Some(KeepMarker::End(name)),
+            _ => name.map(KeepMarker::Begin),
+        }
+    }
+
+    fn ends(&self, region: &str) -> bool {
+        matches!(self, KeepMarker::End(name) if name.as_deref().is_none_or(|name| name == region))
+    }
+}
+
+/// The lines between `==== BEGIN KEEP name ====` and `==== END KEEP name ====` in the existing content of a target file,
+/// by name. Regions without an end are left out.
+fn keep_regions(content: &str, target_path: &AbsolutePath) -> AHashMap<String, Vec<String>> {
+    let mut regions = AHashMap::new();
+    let mut current: Option<(String, Vec<String>)> = None;
+    for line in content.lines() {
+        let marker = KeepMarker::parse(line);
+        match (current.take(), marker) {
+            (None, Some(KeepMarker::Begin(name))) => current = Some((name, Vec::new())),
+            (Some((current_name, lines)), Some(marker)) if marker.ends(&current_name) => {
+                regions.entry(current_name).or_insert(lines);
+            },
+            (Some((current_name, mut lines)), _) => {
+                lines.push(line.trim_end_matches('\r').to_string());
+                current = Some((current_name, lines));
+            },
+            (None, _) => (),
+        }
+    }
+    if let Some((name, _)) = current {
+        warn!("Region without END KEEP is not kept: {name}: {:?}", target_path);
+    }
+    regions
+}
+
+/// Replaces the body of every `==== BEGIN KEEP name ====` region that it writes by the body of the region with the same
+/// name in the previous version of the target file, so that lines that a user added there survive a regeneration.
+struct KeepRegionsTargetFile<'a, TF: TargetFile> {
+    target_file: &'a TF,
+    kept: AHashMap<String, Vec<String>>,
+    /// The name of the kept region whose lines from the option are being dropped.
+    skipping: Mutex<Option<String>>,
+}
+
+impl<'a, TF: TargetFile> KeepRegionsTargetFile<'a, TF> {
+    fn new(target_file: &'a TF, kept: AHashMap<String, Vec<String>>) -> Self {
+        KeepRegionsTargetFile { target_file, kept, skipping: Mutex::new(None) }
+    }
+
+    fn skipping(&self) -> Result<MutexGuard<'_, Option<String>>> {
+        self.skipping.lock().map_err(|_| anyhow!("Kept regions are not available"))
+    }
+}
+
+impl<TF: TargetFile> TargetFile for KeepRegionsTargetFile<'_, TF> {
+    async fn write_line<S: Into<String> + std::fmt::Debug + Send>(&self, line: S) -> Result<()> {
+        let line = line.into();
+        if self.kept.is_empty() {
+            return self.target_file.write_line(line).await;
+        }
+        let marker = KeepMarker::parse(&line);
+        let skipping = self.skipping()?.clone();
+        if let Some(skipping) = skipping {
+            if marker.is_some_and(|marker| marker.ends(&skipping)) {
+                *self.skipping()? = None;
+                return self.target_file.write_line(line).await;
+            }
+            return Ok(());
+        }
+        let Some(KeepMarker::Begin(name)) = marker else { return self.target_file.write_line(line).await };
+        let Some(kept_lines) = self.kept.get(&name) else { return self.target_file.write_line(line).await };
+        let line_ending = if line.ends_with('\r') { "\r" } else { "" };
+        self.target_file.write_line(line.clone()).await?;
+        for kept_line in kept_lines {
+            self.target_file.write_line(format!("{kept_line}{line_ending}")).await?;
+        }
+        *self.skipping()? = Some(name);
+        Ok(())
+    }
+
+    async fn write_chunk(&self, chunk: Vec<u8>) -> Result<()> {
+        self.target_file.write_chunk(chunk).await
+    }
+
+    /// The wrapped target file is closed by its owner.
+    async fn close(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// Drops the fragment marker lines that are written to it, for target files with `keep-markers = false`.
+struct StripMarkersTargetFile<'a, TF: TargetFile>(&'a TF, FragmentMarkers);
+
+impl<TF: TargetFile> TargetFile for StripMarkersTargetFile<'_, TF> {
+    async fn write_line<S: Into<String> + std::fmt::Debug + Send>(&self, line: S) -> Result<()> {
+        let line = line.into();
+        if self.1.is_match(&line) {
+            return Ok(());
+        }
+        self.0.write_line(line).await
+    }
+
+    async fn write_chunk(&self, chunk: Vec<u8>) -> Result<()> {
+        self.0.write_chunk(chunk).await
+    }
+
+    /// The wrapped target file is closed by its owner.
+    async fn close(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// Reads the lines of a source file and keeps track of the line number, for error messages.
+struct NumberedLines<SF> {
+    source_file: SF,
+    source: AbsolutePath,
+    line_number: usize,
+    comment_prefix: String,
+    markers: FragmentMarkers,
+}
+
+impl<SF: SourceFile> NumberedLines<SF> {
+    fn new(source_file: SF, source: AbsolutePath) -> Self {
+        NumberedLines { source_file, source, line_number: 0, comment_prefix: String::new(), markers: FragmentMarkers::default() }
+    }
+
+    /// Skips the lines that start with the prefix (after leading whitespace). An empty prefix skips nothing.
+    fn with_comment_prefix(self, comment_prefix: &str) -> Self {
+        NumberedLines { comment_prefix: comment_prefix.to_string(), ..self }
+    }
+
+    fn with_markers(self, markers: FragmentMarkers) -> Self {
+        NumberedLines { markers, ..self }
+    }
+
+    async fn next_line(&mut self) -> Result<Option<String>> {
+        loop {
+            let line = self.source_file.next_line().await?;
+            let Some(line) = line else { return Ok(None) };
+            self.line_number += 1;
+            if self.comment_prefix.is_empty() || !line.trim_start().starts_with(&self.comment_prefix) {
+                return Ok(Some(line));
+            }
+        }
+    }
+
+    /// The path of the source file and the number of the line that was read last, like `/path/to/file:42`.
+    fn location(&self) -> String {
+        self.location_of(self.line_number)
+    }
+
+    fn location_of(&self, line_number: usize) -> String {
+        format!("{}:{}", self.source.to_string_lossy(), line_number)
+    }
+}
+
+fn void_subtree() -> (AHashMap<String, Vec<Bolt>>, AHashSet<SingleComponent>) {
+    (AHashMap::new(), AHashSet::new())
+}
+
+fn get_invar_config(body: &str, config_format: ConfigFormat) -> Result<impl InvarConfig> {
+    let config = invar_config::from_str(body, config_format)?;
+    debug!("Invar configuration: {config:?}");
+    Ok(config)
+}
+
+fn config_bolts(bolts: &[Bolt]) -> impl Iterator<Item = &Bolt> {
+    bolts.iter().filter(|bolt| matches!(bolt.kind, BoltKind::Config { .. }))
+}
+
+fn combine(cumulus_bolts: AHashMap<String, Vec<Bolt>>, invar_bolts: AHashMap<String, Vec<Bolt>>) -> AHashMap<String, (Vec<Bolt>, Vec<Bolt>)> {
+    let cumulus_keys: AHashSet<String> = cumulus_bolts.iter().map(|(k, _)| k).map(ToOwned::to_owned).collect();
+    let invar_keys: AHashSet<String> = invar_bolts.iter().map(|(k, _)| k).map(ToOwned::to_owned).collect();
+    let keys = cumulus_keys.union(&invar_keys);
+    keys.map(
+        |k: &String|
+            (k.to_owned(),
+             (
+                 cumulus_bolts.get(k).map(ToOwned::to_owned).unwrap_or_else(Vec::new),
+                 invar_bolts.get(k).map(ToOwned::to_owned).unwrap_or_else(Vec::new)
+             )
+            )
+    ).collect()
+}
+
+/// Puts the bolts of the invar before those of the cumulus, so that options in the invar take precedence. Fragments for
+/// the same placeholder are all kept: which of them end up in the target file depends on the `combine-fragments` setting
+/// of the target file (see `NichePlan::combine_fragments`).
+fn combine_bolt_lists(cumulus_bolts_list: &[Bolt], invar_bolts_list: &[Bolt]) -> Vec<Bolt> {
+    let mut result = invar_bolts_list.to_vec();
+    result.extend(cumulus_bolts_list.iter().cloned());
+    result
+}
+
+/// Applies the settings of the rules whose pattern matches the path of the target file relative to the project root, in
+/// order, so that later rules win.
+fn apply_rules<'a, IC: InvarConfig>(invar_config: &'a IC, target: &AbsolutePath, project_root: &AbsolutePath) -> Result<Cow<'a, IC>> {
+    let relative_target = target.strip_prefix(project_root.as_path()).unwrap_or(target.as_path());
+    let mut result = Cow::Borrowed(invar_config);
+    for rule in invar_config.rules() {
+        let matcher = Glob::new(rule.glob()).map_err(|error| anyhow!("Invalid glob in rule: {:?}: {error}", rule.glob()))?.compile_matcher();
+        if matcher.is_match(relative_target) {
+            debug!("Apply rule: {:?}: {:?}", relative_target, &rule);
+            let updated = result.with_write_mode_option(rule.write_mode()).with_interpolate_option(rule.interpolate()).into_owned()
+                .with_executable_option(rule.executable()).into_owned();
+            result = Cow::Owned(updated);
+        }
+    }
+    Ok(result)
+}
+
+/// Replaces the placeholders after the last `+` of a file name by the values of the props, so that the base name stays
+/// as is. Returns `None` if a placeholder has no value.
+fn interpolate_bolt_name<'a>(file_name: &'a str, props: &Table) -> Option<Cow<'a, str>> {
+    let Some(plus) = file_name.rfind('+') else { return Some(Cow::Borrowed(file_name)) };
+    let suffix = &file_name[plus..];
+    let delimiters = PlaceholderDelimiters::default();
+    if !crate::interpolate::missing_placeholders(suffix, props, &delimiters).is_empty() {
+        return None;
+    }
+    match crate::interpolate::interpolate(suffix, props) {
+        Cow::Borrowed(_) => Some(Cow::Borrowed(file_name)),
+        Cow::Owned(interpolated) => Some(Cow::Owned(format!("{}{interpolated}", &file_name[..plus]))),
+    }
+}
+
+/// The regular expression that classified the name of a file in a cumulus or invar directory
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum NameClass { Config, BoltWithDot, BoltWithoutDot, Plain, Unrecognized }
+
+impl NameClass {
+    fn name(&self) -> &'static str {
+        match self {
+            NameClass::Config => "config",
+            NameClass::BoltWithDot => "bolt-with-dot",
+            NameClass::BoltWithoutDot => "bolt-without-dot",
+            NameClass::Plain => "plain",
+            NameClass::Unrecognized => "unrecognized",
+        }
+    }
+}
+
+fn classify_file_name(file_name: &str, source: FileLocation) -> Result<(NameClass, Bolt)> {
+    if let Some(captures) = CONFIG_REGEX.captures(file_name) {
+        return Ok((NameClass::Config, config_captures_to_bolt(captures, source)?));
+    }
+    if let Some(captures) = BOLT_REGEX_WITH_DOT.captures(file_name) {
+        debug!("Bolt regex with dot: {:?}", file_name);
+        return Ok((NameClass::BoltWithDot, captures_to_bolt(captures, source)?));
+    }
+    if let Some(captures) = BOLT_REGEX_WITHOUT_DOT.captures(file_name) {
+        debug!("Bolt regex without dot: {:?}", file_name);
+        return Ok((NameClass::BoltWithoutDot, captures_to_bolt(captures, source)?));
+    }
+    if let Some(captures) = PLAIN_FILE_REGEX_WITH_DOT.captures(file_name) {
+        debug!("Plain file regex with dot: {:?}", file_name);
+        let (base_name, extension) =
+            if let (Some(b), Some(e)) = (captures.name("base"), captures.name("extension")) {
+                (b.as_str(), e.as_str())
+            } else {
+                (file_name, "")
+            };
+        let bolt = Bolt{
+            base_name: base_name.to_string(),
+            extension: extension.to_string(),
+            feature_name: "@".to_string(),
+            source,
+            kind: BoltKind::Option { fallback: false }
+        };
+        return Ok((NameClass::Plain, bolt));
+    }
+    debug!("Unrecognized file name: {:?}", file_name);
+    let bolt = Bolt{
+        base_name: file_name.to_string(),
+        extension: "".to_string(),
+        feature_name: "@".to_string(),
+        source,
+        kind: BoltKind::Option { fallback: false }
+    };
+    Ok((NameClass::Unrecognized, bolt))
+}
+
+fn explain_name(name_class: NameClass, bolt: &Bolt) -> String {
+    let (kind, qualifier) = match &bolt.kind {
+        BoltKind::Option { fallback: true } => ("option", Some("fallback")),
+        BoltKind::Option { fallback: false } => ("option", None),
+        BoltKind::Fragment { qualifier, .. } => ("fragment", qualifier.as_deref()),
+        BoltKind::Config { .. } => ("config", None),
+        BoltKind::Include => ("include", None),
+        BoltKind::Patch => ("patch", None),
+        BoltKind::Append => ("append", None),
+        BoltKind::Tombstone => ("tombstone", None),
+        BoltKind::Symlink => ("symlink", None),
+        BoltKind::Binary => ("binary", None),
+        BoltKind::Unknown { qualifier, .. } => ("unknown", qualifier.as_deref()),
+    };
+    format!("{}: {}: {kind}: base={:?} extension={:?} feature={:?} qualifier={:?} target={:?}",
+        bolt.source.path.to_string_lossy(), name_class.name(), bolt.base_name, bolt.extension, bolt.feature_name,
+        qualifier.unwrap_or(""), bolt.target_name())
+}
+
+fn captures_to_bolt(captures: Captures, source: FileLocation) -> Result<Bolt> {
+    let extension = captures.name("extension").map(|m|m.as_str().to_string()).unwrap_or("".to_string());
+    let feature_name = captures.name("feature").map(|m|m.as_str().to_string()).unwrap_or("@".to_string());
+    let qualifier = captures.name("qualifier").map(|m|m.as_str().to_string());
+    let order = captures.name("order").map(|m| m.as_str().parse::<FragmentOrder>()).transpose()?;
+    if let (Some(base_name_orig), Some(bolt_type)) = (captures.name("base"), captures.name("bolt_type")) {
+        let base_name = to_base_name(base_name_orig.as_str());
+        let bolt_type = bolt_type.as_str();
+        if order.is_some() && bolt_type != "fragment" {
+            warn!("Ignore order in the name of a {bolt_type} (only fragments have an order): {:?}", source.path);
+        }
+        let bolt =
+            if bolt_type == "option" {
+                let fallback = qualifier.as_deref() == Some("fallback");
+                Bolt{ base_name, extension, feature_name, source, kind: BoltKind::Option { fallback } }
+            } else if bolt_type == "fragment" {
+                Bolt{ base_name, extension, feature_name, source, kind: BoltKind::Fragment { qualifier, order } }
+            } else if bolt_type == "include" {
+                Bolt{ base_name, extension, feature_name, source, kind: BoltKind::Include }
+            } else if bolt_type == "patch" {
+                Bolt{ base_name, extension, feature_name, source, kind: BoltKind::Patch }
+            } else if bolt_type == "append" {
+                Bolt{ base_name, extension, feature_name, source, kind: BoltKind::Append }
+            } else if bolt_type == "delete" || bolt_type == "skip" {
+                Bolt{ base_name, extension, feature_name, source, kind: BoltKind::Tombstone }
+            } else if bolt_type == "symlink" {
+                Bolt{ base_name, extension, feature_name, source, kind: BoltKind::Symlink }
+            } else if bolt_type == "binary" {
+                Bolt{ base_name, extension, feature_name, source, kind: BoltKind::Binary }
+            } else {
+                Bolt{ base_name, extension, feature_name, source, kind: BoltKind::Unknown { bolt_type: bolt_type.to_string(), qualifier } }
+            };
+        Ok(bolt)
+    } else {
+        bail!("Internal error")
+    }
+}
+fn config_captures_to_bolt(captures: Captures, source: FileLocation) -> Result<Bolt> {
+    let extension = captures.name("extension").map(|m|m.as_str().to_string()).unwrap_or("".to_string());
+    let feature_name = captures.name("feature").map(|m|m.as_str().to_string()).unwrap_or("@".to_string());
+    if let (Some(base_name_orig), Some(format_match)) = (captures.name("base"), captures.name("format")) {
+        let base_name = to_base_name(base_name_orig.as_str());
+        let format_str = format_match.as_str();
+        let format =
+            if format_str == "toml" { ConfigFormat::TOML }
+            else if format_str == "yaml" { ConfigFormat::YAML }
+            else { bail!("Unknown config file format: {:?}", format_match) }
+        ;
+        let config =
+            Bolt{
+                base_name: base_name.to_string(),
+                extension: extension.to_string(),
+                feature_name: feature_name.to_string(),
+                source,
+                kind: BoltKind::Config { format },
+            }
+        ;
+        Ok(config)
+    } else {
+        bail!("Internal error")
+    }
+}
+
+fn to_base_name(base_name_orig: &str) -> String {
+    let base_name = base_name_orig.to_string();
+    let base_name = base_name.strip_prefix("dot_")
+        .map(|stripped| ".".to_string() + stripped)
+        .unwrap_or(base_name);
+    base_name.strip_prefix("x_").unwrap_or(&base_name).to_string()
+}
+
+fn add<K,I>(map: &mut AHashMap<K,Vec<I>>, key: &K, item: I)
+where
+    K: PartialEq + Eq + Hash + Clone
+{
+    if let Some(existing_list) = map.get_mut(key) {
+        existing_list.push(item);
+    } else {
+        let mut new_list = Vec::new();
+        new_list.push(item);
+        map.insert(key.clone(), new_list);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use indoc::{formatdoc, indoc};
+    use test_log::test;
+    use crate::config_model::{project_config, NicheTriggers, ProjectConfig, PsychotropicConfig};
+    use crate::file_system::ConfigFormat::TOML;
+    use crate::file_system::fixture;
+    use crate::path::test_utils::to_absolute_path;
+    use super::*;
+
+    #[test]
+    fn classify_file_names() -> Result<()> {
+        // Given
+        let names = [
+            ("clock+config-glass.yaml.toml", NameClass::Config, "config", "clock.yaml"),
+            ("clock+fragment-glass-spring.yaml", NameClass::BoltWithDot, "fragment", "clock.yaml"),
+            ("dot_bashrc+option-@", NameClass::BoltWithoutDot, "option", ".bashrc"),
+            ("README.md", NameClass::Plain, "option", "README.md"),
+            ("Makefile", NameClass::Unrecognized, "option", "Makefile"),
+        ];
+
+        for (file_name, expected_class, expected_kind, expected_target) in names {
+            // When
+            let source = FileLocation { path: AbsolutePath::new(file_name, &to_absolute_path("/cumulus")), context: ThunderCloud };
+            let (name_class, bolt) = classify_file_name(file_name, source)?;
+            let explanation = explain_name(name_class, &bolt);
+
+            // Then
+            assert_eq!(name_class, expected_class, "{file_name}");
+            assert_eq!(bolt.target_name(), expected_target, "{file_name}");
+            assert!(explanation.starts_with(&format!("/cumulus/{file_name}: {}: {expected_kind}: ", expected_class.name())), "{explanation}");
+        }
+        Ok(())
+    }
+
+    #[test(tokio::test)]
+    async fn test_process_complex_niche() -> Result<()> {
+        // Given
+        let thundercloud_toml = indoc! {r#"
+            [example-thundercloud]
+            "thundercloud.toml" = """
+            [niche]
+            name = "example"
+            description = "Example thundercloud for demonstration purposes"
+
+            [invar-defaults]
+            write-mode = "Overwrite"
+            interpolate = true
+
+            [invar-defaults.props]
+            milk-man = "Ronny Soak"
+            alter-ego = "Lobsang"
+            """
+
+            [example-thundercloud.cumulus.workshop]
+            "clock+fragment-glass-spring.yaml" = """
+            ---
+            spring:
+              material: glass
+              delicate: false
+              number-of-coils: 3
+            """
+            "clock+option-glass.yaml" = '''
+            ---
+            sweeper: "{{alter-ego}}"
+            raising:
+              - "steam"
+              - "money"
+            # ==== BEGIN FRAGMENT glass-spring ====
+              - "replaced-by-fragment"
+            # ==== END FRAGMENT glass-spring ====
+              - "to the occasion"
+            '''
+            "clock+config-glass.yaml.toml" = """
+            write-mode = "WriteNew"
+            """
+        "#};
+        let project_toml = indoc! {r#"
+            "CargoCult.toml" = '''
+            [[psychotropic.cues]]
+            name = "example"
+            use-thundercloud = { directory = "{{PROJECT}}/example-thundercloud", on-incoming = "Update", features = ["glass", "bash_config", "kermie"], invar-defaults = { props = { marthter = "Jeremy", buyer = "Myra LeJean", milk-man = "Kaos" } } }
+            '''
+
+            [yeth-marthter.example.invar.workshop]
+            "clock+config-glass.yaml.toml" = """
+            write-mode = "Overwrite"
+
+            [props]
+            sweeper = "Lu Tse"
+            """
+            "clock+fragment-glass-spring.yaml" = '''
+            # ==== BEGIN FRAGMENT glass-spring ====
+            ---
+            spring:
+              material: glass
+              delicate: true
+              number-of-coils: 17
+            raising:
+              - "expectations"
+            # ==== END FRAGMENT glass-spring ====
+            '''
+        "#};
+
+        // When
+        let result_file_path = to_absolute_path("/workshop/clock.yaml");
+        let result_body = test_process_niche(thundercloud_toml, project_toml, result_file_path).await?;
+
+        // Then
+        let expected_result = indoc! {r#"
+            ---
+            sweeper: "Lobsang"
+            raising:
+              - "steam"
+              - "money"
+            # ==== BEGIN FRAGMENT glass-spring ====
+            ---
+            spring:
+              material: glass
+              delicate: true
+              number-of-coils: 17
+            raising:
+              - "expectations"
+            # ==== END FRAGMENT glass-spring ====
+              - "to the occasion"
+        "#};
+        assert_eq!(&result_body, expected_result);
+
+        Ok(())
+    }
+
+    #[test(tokio::test)]
+    async fn test_config_without_extension() -> Result<()> {
+        // Given
+        let thundercloud_toml = indoc! {r#"
+            [example-thundercloud]
+            "thundercloud.toml" = """
+            [niche]
+            name = "example"
+            description = "Example thundercloud for demonstration purposes"
+
+            [invar-defaults.props]
+            alter-ego = "Lobsang"
+            """
+
+            [example-thundercloud.cumulus.workshop]
+            "x_x_x+option-kermie" = '''
+            Miss Piggy
+            Sweeper: {{sweeper}}
+            Alter ego: {{alter-ego}}
+            '''
+        "#};
+        let project_toml = indoc! {r#"
+            "CargoCult.toml" = '''
+            [[psychotropic.cues]]
+            name = "example"
+            use-thundercloud = { directory = "{{PROJECT}}/example-thundercloud", on-incoming = "Update", features = ["glass", "bash_config", "kermie"], invar-defaults = { props = { marthter = "Jeremy", buyer = "Myra LeJean", milk-man = "Kaos" } } }
+            '''
+
+            [yeth-marthter.example.invar.workshop]
+            "x_x_x+config-kermie.toml" = '''
+            [props]
+            sweeper = "Lu Tse"
+            '''
+        "#};
+
+        // When
+        let result_file_path = to_absolute_path("/workshop/x_x");
+        let result_body = test_process_niche(thundercloud_toml, project_toml, result_file_path).await?;
+
+        // Then
+        let expected_result = indoc! {r#"
+            Miss Piggy
+            Sweeper: Lu Tse
+            Alter ego: Lobsang
+        "#};
+        assert_eq!(&result_body, expected_result);
+
+        Ok(())
+    }
+
+    #[test(tokio::test)]
+    async fn test_fallback_option() -> Result<()> {
+        // Given
+        let thundercloud_toml = indoc! {r#"
+            [example-thundercloud]
+            "thundercloud.toml" = """
+            [niche]
+            name = "example"
+            """
+
+            [example-thundercloud.cumulus.workshop]
+            "clock+option-@-fallback.yaml" = '''
+            spring: steel
+            '''
+            "clock+option-glass.yaml" = '''
+            spring: glass
+            '''
+            "mirror+option-@-fallback.yaml" = '''
+            frame: wood
+            '''
+            "mirror+option-silver.yaml" = '''
+            frame: silver
+            '''
+        "#};
+        let project_toml = indoc! {r#"
+            "CargoCult.toml" = '''
+            [[psychotropic.cues]]
+            name = "example"
+            use-thundercloud = { directory = "{{PROJECT}}/example-thundercloud", features = ["glass"] }
+            '''
+
+            [yeth-marthter.example.invar]
+        "#};
+
+        // When
+        let clock_body = test_process_niche(thundercloud_toml, project_toml, to_absolute_path("/workshop/clock.yaml")).await?;
+        let mirror_body = test_process_niche(thundercloud_toml, project_toml, to_absolute_path("/workshop/mirror.yaml")).await?;
+
+        // Then
+        assert_eq!(&clock_body, "spring: glass\n");
+        assert_eq!(&mirror_body, "frame: wood\n");
+
+        Ok(())
+    }
+
+    #[test(tokio::test)]
+    async fn test_nested_props_precedence() -> Result<()> {
+        // Given
+        let thundercloud_toml = indoc! {r#"
+            [example-thundercloud]
+            "thundercloud.toml" = """
+            [niche]
+            name = "example"
+
+            [invar-defaults.props.clock]
+            thundercloud = "thundercloud"
+            project = "thundercloud"
+            niche = "thundercloud"
+            config = "thundercloud"
+            """
+
+            [example-thundercloud.cumulus.workshop]
+            "clock+option-glass.yaml" = '''
+            thundercloud: {{clock.thundercloud}}
+            project: {{clock.project}}
+            niche: {{clock.niche}}
+            config: {{clock.config}}
+            springs: {{clock.springs.1}}
+            '''
+        "#};
+        let project_toml = indoc! {r#"
+            "CargoCult.toml" = '''
+            [[psychotropic.cues]]
+            name = "example"
+            use-thundercloud = { directory = "{{PROJECT}}/example-thundercloud", features = ["glass"], invar-defaults = { props = { clock = { niche = "niche", config = "niche", springs = ["steel", "iron"] } } } }
+
+            [invar-defaults.props.clock]
+            project = "project"
+            niche = "project"
+            config = "project"
+            '''
+
+            [yeth-marthter.example.invar.workshop]
+            "clock+config-glass.yaml.toml" = """
+            [props.clock]
+            config = "config"
+            springs = ["glass", "crystal"]
+            """
+        "#};
+        let thundercloud_fs = fixture::from_toml(thundercloud_toml)?;
+        let project_fs = fixture::from_toml(project_toml)?;
+        let project_config = create_project_config(project_fs.clone()).await?;
+        let niche_triggers = get_niche_triggers(&project_config)?;
+        let use_thundercloud = niche_triggers.use_thundercloud().unwrap();
+        let thunder_config = use_thundercloud.new_thunder_config(project_config.invar_defaults().into_owned(), thundercloud_fs, to_absolute_path("/example-thundercloud"), project_fs.clone(), to_absolute_path("/yeth-marthter/example/invar"), AbsolutePath::root());
+
+        // When
+        process_niche(thunder_config, RunOptions::default(), FunctionRegistry::default(), NicheReporter::default()).await?;
+
+        // Then
+        let result_body = project_fs.get_content(to_absolute_path("/workshop/clock.yaml")).await?;
+        let expected_result = indoc! {r#"
+            thundercloud: thundercloud
+            project: project
+            niche: niche
+            config: config
+            springs: crystal
+        "#};
+        assert_eq!(&result_body, expected_result);
+
+        Ok(())
+    }
+
+    #[test(tokio::test)]
+    async fn test_custom_placeholder_delimiters() -> Result<()> {
+        // Given
+        let thundercloud_toml = indoc! {r#"
+            [example-thundercloud]
+            "thundercloud.toml" = """
+            [niche]
+            name = "example"
+
+            [invar-defaults]
+            placeholder-delimiters = { open = "@{", close = "}@" }
+            props = { sweeper = "Lu Tse" }
+
+            [placeholder-delimiters-by-extension]
+            hbs = { open = "<%", close = "%>" }
+            """
+
+            [example-thundercloud.cumulus.workshop]
+            "clock+option-glass.yaml" = '''
+            sweeper: @{sweeper}@
+            apprentice: {{sweeper}}
+            '''
+            "clock+option-glass.hbs" = '''
+            <p><%sweeper%> {{apprentice}}</p>
+            '''
+            "mirror+option-glass.hbs" = '''
+            <p><%sweeper%> [sweeper]</p>
+            '''
+        "#};
+        let project_toml = indoc! {r#"
+            "CargoCult.toml" = '''
+            [[psychotropic.cues]]
+            name = "example"
+            use-thundercloud = { directory = "{{PROJECT}}/example-thundercloud", features = ["glass"] }
+            '''
+
+            [yeth-marthter.example.invar.workshop]
+            "mirror+config-glass.hbs.toml" = """
+            placeholder-delimiters = { open = "[", close = "]" }
+            """
+        "#};
+
+        // When
+        let yaml_body = test_process_niche(thundercloud_toml, project_toml, to_absolute_path("/workshop/clock.yaml")).await?;
+        let hbs_body = test_process_niche(thundercloud_toml, project_toml, to_absolute_path("/workshop/clock.hbs")).await?;
+        let mirror_body = test_process_niche(thundercloud_toml, project_toml, to_absolute_path("/workshop/mirror.hbs")).await?;
+
+        // Then
+        assert_eq!(&yaml_body, "sweeper: Lu Tse\napprentice: {{sweeper}}\n");
+        assert_eq!(&hbs_body, "<p>Lu Tse {{apprentice}}</p>\n");
+        assert_eq!(&mirror_body, "<p><%sweeper%> Lu Tse</p>\n");
+
+        Ok(())
+    }
+
+    #[test(tokio::test)]
+    async fn test_interpolated_fragment_marker() -> Result<()> {
+        // Given
+        let thundercloud_toml = indoc! {r#"
+            [example-thundercloud]
+            "thundercloud.toml" = """
+            [niche]
+            name = "example"
+            """
+
+            [example-thundercloud.cumulus.workshop]
+            "clock+option-glass.yaml" = '''
+            parts:
+            # ==== BEGIN FRAGMENT glass-{{component}} ====
+              - placeholder
+            # ==== END FRAGMENT glass-{{component}} ====
+              - face
+            '''
+        "#};
+        let project_toml = indoc! {r#"
+            "CargoCult.toml" = '''
+            [[psychotropic.cues]]
+            name = "example"
+            use-thundercloud = { directory = "{{PROJECT}}/example-thundercloud", features = ["glass"], invar-defaults = { props = { component = "pendulum" } } }
+            '''
+
+            [yeth-marthter.example.invar.workshop]
+            "clock+fragment-glass-spring.yaml" = '''
+            # ==== BEGIN FRAGMENT glass-spring ====
+              - spring
+            # ==== END FRAGMENT glass-spring ====
+            '''
+            "clock+fragment-glass-pendulum.yaml" = '''
+            # ==== BEGIN FRAGMENT glass-pendulum ====
+              - pendulum
+            # ==== END FRAGMENT glass-pendulum ====
+            '''
+        "#};
+
+        // When
+        let result_body = test_process_niche(thundercloud_toml, project_toml, to_absolute_path("/workshop/clock.yaml")).await?;
+
+        // Then
+        let expected_result = indoc! {r#"
+            parts:
+            # ==== BEGIN FRAGMENT glass-pendulum ====
+              - pendulum
+            # ==== END FRAGMENT glass-pendulum ====
+              - face
+        "#};
+        assert_eq!(&result_body, expected_result);
+
+        Ok(())
+    }
+
+    #[test(tokio::test)]
+    async fn test_executable_option() -> Result<()> {
+        // Given
+        let thundercloud_toml = indoc! {r#"
+            [example-thundercloud]
+            "thundercloud.toml" = """
+            [niche]
+            name = "example"
+            """
+
+            [example-thundercloud.cumulus.workshop]
+            "wind-up+option-glass.sh" = { content = "echo tick", mode = 0o755 }
+            "clock+option-glass.yaml" = "sweeper: Lu Tse"
+        "#};
+        let project_toml = indoc! {r#"
+            "CargoCult.toml" = '''
+            [[psychotropic.cues]]
+            name = "example"
+            use-thundercloud = { directory = "{{PROJECT}}/example-thundercloud", features = ["glass"] }
+            '''
+
+            [yeth-marthter.example.invar]
+        "#};
+
+        // When
+        let fs = process_test_niche(thundercloud_toml, project_toml).await?;
+
+        // Then
+        fixture::assert_executable(&fs, "/workshop/wind-up.sh").await?;
+        fixture::assert_not_executable(&fs, "/workshop/clock.yaml").await?;
+
+        Ok(())
+    }
+
+    #[test(tokio::test)]
+    async fn test_copy_raw_option() -> Result<()> {
+        // Given
+        let thundercloud_toml = indoc! {r#"
+            [example-thundercloud]
+            "thundercloud.toml" = """
+            [niche]
+            name = "example"
+
+            [invar-defaults.props]
+            sweeper = "Lu Tse"
+            """
+
+            [example-thundercloud.cumulus.workshop]
+            "clock+option-@.yaml" = """
+            sweeper: "{{sweeper}}"
+            # ==== BEGIN FRAGMENT glass-spring ====
+            spring: steel
+            # ==== END FRAGMENT glass-spring ===="""
+            "clock+config-@.yaml.toml" = "interpolate = false"
+        "#};
+        let project_toml = indoc! {r#"
+            "CargoCult.toml" = '''
+            [[psychotropic.cues]]
+            name = "example"
+            use-thundercloud = { directory = "{{PROJECT}}/example-thundercloud" }
+            '''
+
+            [yeth-marthter.example.invar]
+        "#};
+
+        // When
+        let content = test_process_niche(thundercloud_toml, project_toml, to_absolute_path("/workshop/clock.yaml")).await?;
+
+        // Then
+        let expected = indoc! {r#"
+            sweeper: "{{sweeper}}"
+            # ==== BEGIN FRAGMENT glass-spring ====
+            spring: steel
+            # ==== END FRAGMENT glass-spring ====
+        "#};
+        assert_eq!(content, expected);
+
+        Ok(())
+    }
+
+    #[test(tokio::test)]
+    async fn test_invar_defaults_by_extension() -> Result<()> {
+        // Given
+        let thundercloud_toml = indoc! {r#"
+            [example-thundercloud]
+            "thundercloud.toml" = """
+            [niche]
+            name = "example"
+
+            [invar-defaults.props]
+            sweeper = "Lu Tse"
+
+            [invar-defaults.by-extension]
+            sh = { executable = true }
+            md = { interpolate = false }
+            yaml = { props = { sweeper = "Lobsang" } }
+            txt = { write-mode = "Ignore", props = { sweeper = "Lobsang" } }
+            """
+
+            [example-thundercloud.cumulus.workshop]
+            "wind-up+option-@.sh" = "echo tick"
+            "README+option-@.md" = "Swept by {{sweeper}}"
+            "clock+option-@.yaml" = "sweeper: {{sweeper}}"
+            "mirror+option-@.yaml" = "sweeper: {{sweeper}}"
+            "mirror+config-@.yaml.toml" = "props = { sweeper = 'Jeremy' }"
+            "notes+option-@.txt" = "sweeper: {{sweeper}}"
+            "log+option-@.txt" = "sweeper: {{sweeper}}"
+            "log+config-@.txt.toml" = "write-mode = 'Overwrite'\nprops = { sweeper = 'Lu Tse' }"
+
+            [example-thundercloud.cumulus.library]
+            "dot_+config.toml" = "write-mode = 'Overwrite'"
+            "catalogue+option-@.txt" = "sweeper: {{sweeper}}"
+        "#};
+        let project_toml = indoc! {r#"
+            "CargoCult.toml" = '''
+            [[psychotropic.cues]]
+            name = "example"
+            use-thundercloud = { directory = "{{PROJECT}}/example-thundercloud" }
+            '''
+
+            [yeth-marthter.example.invar]
+        "#};
+
+        // When
+        let fs = process_test_niche(thundercloud_toml, project_toml).await?;
+
+        // Then
+        fixture::assert_executable(&fs, "/workshop/wind-up.sh").await?;
+        fixture::assert_not_executable(&fs, "/workshop/clock.yaml").await?;
+        assert_eq!(fs.get_content(to_absolute_path("/workshop/README.md")).await?, "Swept by {{sweeper}}\n");
+        assert_eq!(fs.get_content(to_absolute_path("/workshop/clock.yaml")).await?, "sweeper: Lobsang\n");
+        assert_eq!(fs.get_content(to_absolute_path("/workshop/mirror.yaml")).await?, "sweeper: Jeremy\n");
+        // Configuration bolts override the defaults for the extension, also if they restate the values of the niche
+        assert_eq!(fs.path_type(&to_absolute_path("/workshop/notes.txt")).await, PathType::Missing);
+        assert_eq!(fs.get_content(to_absolute_path("/workshop/log.txt")).await?, "sweeper: Lu Tse\n");
+        assert_eq!(fs.get_content(to_absolute_path("/library/catalogue.txt")).await?, "sweeper: Lobsang\n");
+
+        Ok(())
+    }
+
+    #[test(tokio::test)]
+    async fn test_interpolate_strict() -> Result<()> {
+        // Given
+        let thundercloud_toml = indoc! {r#"
+            [example-thundercloud]
+            "thundercloud.toml" = """
+            [niche]
+            name = "example"
+
+            [invar-defaults]
+            interpolate-strict = true
+
+            [invar-defaults.props]
+            sweeper = "Lu Tse"
+            """
+
+            [example-thundercloud.cumulus.workshop]
+            "clock+option-@.yaml" = """
+            sweeper: "{{sweeper}}"
+            apprentice: "{{apprentice}}"
+            """
+        "#};
+        let project_toml = indoc! {r#"
+            "CargoCult.toml" = '''
+            [[psychotropic.cues]]
+            name = "example"
+            use-thundercloud = { directory = "{{PROJECT}}/example-thundercloud" }
+            '''
+
+            [yeth-marthter.example.invar]
+        "#};
+
+        // When
+        let result = process_test_niche(thundercloud_toml, project_toml).await;
+
+        // Then
+        let Err(error) = result else { panic!("Expected an error") };
+        let message = format!("{error:#}");
+        assert!(message.contains("/example-thundercloud/cumulus/workshop/clock+option-@.yaml:2"), "Actual error: {message}");
+        assert!(message.contains("apprentice"), "Actual error: {message}");
+        assert!(!message.contains("sweeper"), "Actual error: {message}");
+
+        Ok(())
+    }
+
+    #[test(tokio::test)]
+    async fn test_conditional_blocks() -> Result<()> {
+        // Given
+        let thundercloud_toml = indoc! {r#"
+            [example-thundercloud]
+            "thundercloud.toml" = """
+            [niche]
+            name = "example"
+
+            [invar-defaults]
+            interpolate-strict = true
+
+            [invar-defaults.props]
+            chime = true
+            sweeper = "Lu Tse"
+            """
+
+            [example-thundercloud.cumulus.workshop]
+            "clock+option-@.yaml" = """
+            sweeper: "{{sweeper}}"
+            # ==== IF glass ====
+            spring: glass
+            # ==== IF !chime ====
+            sound: "{{tick}}"
+            # ==== ENDIF ====
+            # ==== ELSE ====
+            spring: steel
+            # ==== ENDIF ====
+            # ==== IF pendulum ====
+            pendulum: "{{pendulum}}"
+            # ==== ELSE ====
+            pendulum: none
+            # ==== ENDIF ====
+            """
+        "#};
+        let project_toml = indoc! {r#"
+            "CargoCult.toml" = '''
+            [[psychotropic.cues]]
+            name = "example"
+            use-thundercloud = { directory = "{{PROJECT}}/example-thundercloud", features = ["glass"] }
+            '''
+
+            [yeth-marthter.example.invar]
+        "#};
+
+        // When
+        let content = test_process_niche(thundercloud_toml, project_toml, to_absolute_path("/workshop/clock.yaml")).await?;
+
+        // Then
+        let expected = indoc! {r#"
+            sweeper: "Lu Tse"
+            spring: glass
+            pendulum: none
+        "#};
+        assert_eq!(content, expected);
+
+        Ok(())
+    }
+
+    #[test(tokio::test)]
+    async fn test_unbalanced_conditional_blocks() -> Result<()> {
+        // Given
+        let thundercloud_toml = indoc! {r#"
+            [example-thundercloud]
+            "thundercloud.toml" = """
+            [niche]
+            name = "example"
+            """
+
+            [example-thundercloud.cumulus.workshop]
+            "clock+option-@.yaml" = """
+            # ==== IF glass ====
+            spring: glass
+            # ==== ENDIF ====
+            # ==== ENDIF ====
+            """
+        "#};
+        let project_toml = indoc! {r#"
+            "CargoCult.toml" = '''
+            [[psychotropic.cues]]
+            name = "example"
+            use-thundercloud = { directory = "{{PROJECT}}/example-thundercloud" }
+            '''
+
+            [yeth-marthter.example.invar]
+        "#};
+
+        // When
+        let result = process_test_niche(thundercloud_toml, project_toml).await;
+
+        // Then
+        let Err(error) = result else { panic!("Expected an error") };
+        let message = format!("{error:#}");
+        assert!(message.contains("clock+option-@.yaml:4: ENDIF without IF"), "Actual error: {message}");
+
+        Ok(())
+    }
+
+    #[test(tokio::test)]
+    async fn test_prop_reads() -> Result<()> {
+        // Given
+        let thundercloud_toml = indoc! {r#"
+            [example-thundercloud]
+            "thundercloud.toml" = """
+            [niche]
+            name = "example"
+
+            [invar-defaults.props]
+            sweeper = "Lu Tse"
+            unused = "dust"
+            """
+
+            [example-thundercloud.cumulus.workshop]
+            "clock+option-@.yaml" = """
+            sweeper: "{{sweeper}}"
+            valley: "{{valley}}"
+            apprentice: "{{apprentice}}"
+            year: {{now.year}}
+            missing: "{{missing}}"
+            """
+        "#};
+        let project_toml = indoc! {r#"
+            "CargoCult.toml" = '''
+            [[psychotropic.cues]]
+            name = "example"
+            use-thundercloud = { directory = "{{PROJECT}}/example-thundercloud", invar-defaults = { props = { valley = "Oi Dong" } } }
+            '''
+
+            [yeth-marthter.example.invar.workshop]
+            "clock+config-@.yaml.toml" = "props = { apprentice = 'Lobsang' }"
+        "#};
+        let thundercloud_fs = fixture::from_toml(thundercloud_toml)?;
+        let project_fs = fixture::from_toml(project_toml)?;
+        let project_config = create_project_config(project_fs.clone()).await?;
+        let niche_triggers = get_niche_triggers(&project_config)?;
+        let default_invar_config = niche_triggers.use_thundercloud().unwrap().invar_defaults().into_owned();
+        let thunder_config = niche_triggers.use_thundercloud().unwrap().new_thunder_config(default_invar_config, thundercloud_fs, to_absolute_path("/example-thundercloud"), project_fs, to_absolute_path("/yeth-marthter/example/invar"), AbsolutePath::root());
+        let generation_context = GenerationContext::new(thunder_config, NicheReporter::default());
+        let plan = plan_niche_in_context(&generation_context).await?;
+        let target_plan = plan.targets.iter().find(|target_plan| target_plan.target == to_absolute_path("/workshop/clock.yaml")).unwrap();
+
+        // When
+        let reads = generation_context.prop_reads(target_plan).await?;
+
+        // Then
+        let reads: Vec<_> = reads.iter().map(|read| (read.target.as_str(), read.prop.as_str(), read.source.as_str())).collect();
+        assert_eq!(reads, vec![
+            ("workshop/clock.yaml", "sweeper", "thundercloud:thundercloud.toml"),
+            ("workshop/clock.yaml", "valley", "project:CargoCult.toml"),
+            ("workshop/clock.yaml", "apprentice", "project:yeth-marthter/example/invar/workshop/clock+config-@.yaml.toml"),
+            ("workshop/clock.yaml", "now.year", "igor:now"),
+        ]);
+
+        Ok(())
+    }
+
+    #[test(tokio::test)]
+    async fn test_loops() -> Result<()> {
+        // Given
+        let thundercloud_toml = indoc! {r#"
+            [example-thundercloud]
+            "thundercloud.toml" = """
+            [niche]
+            name = "example"
+
+            [invar-defaults]
+            interpolate-strict = true
+
+            [invar-defaults.props]
+            features = ["macros", "rt"]
+            deps = [
+                { name = "serde", version = "1.0", derive = true },
+                { name = "tokio", version = "1.36", features = ["macros", "rt"] },
+            ]
+            """
+
+            [example-thundercloud.cumulus]
+            "Cargo+option-@.toml" = """
+            [dependencies]
+            # ==== FOR dep IN deps ====
+            # ==== IF dep.derive ====
+            {{dep.name}} = { version = "{{dep.version}}", features = ["derive"] }
+            # ==== ELSE ====
+            {{dep.name}} = "{{dep.version}}"
+            # ==== ENDIF ====
+            # ==== ENDFOR ====
+
+            [features]
+            # ==== FOR feature IN features ====
+            # ==== FOR other IN features ====
+            {{feature}}-{{other}} = []
+            # ==== ENDFOR ====
+            # ==== ENDFOR ====
+            # ==== FOR item IN nothing ====
+            {{item}}
+            # ==== ENDFOR ====
+            """
+        "#};
+        let project_toml = indoc! {r#"
+            "CargoCult.toml" = '''
+            [[psychotropic.cues]]
+            name = "example"
+            use-thundercloud = { directory = "{{PROJECT}}/example-thundercloud", invar-defaults = { props = { nothing = [] } } }
+            '''
+
+            [yeth-marthter.example.invar]
+        "#};
+
+        // When
+        let content = test_process_niche(thundercloud_toml, project_toml, to_absolute_path("/Cargo.toml")).await?;
+
+        // Then
+        let expected = indoc! {r#"
+            [dependencies]
+            serde = { version = "1.0", features = ["derive"] }
+            tokio = "1.36"
+
+            [features]
+            macros-macros = []
+            macros-rt = []
+            rt-macros = []
+            rt-rt = []
+        "#};
+        assert_eq!(content, expected);
+
+        Ok(())
+    }
+
+    #[test(tokio::test)]
+    async fn test_loop_without_end() -> Result<()> {
+        // Given
+        let thundercloud_toml = indoc! {r#"
+            [example-thundercloud]
+            "thundercloud.toml" = """
+            [niche]
+            name = "example"
+
+            [invar-defaults.props]
+            deps = ["serde"]
+            """
+
+            [example-thundercloud.cumulus]
+            "Cargo+option-@.toml" = """
+            [dependencies]
+            # ==== FOR dep IN deps ====
+            {{dep}} = "1"
+            """
+        "#};
+        let project_toml = indoc! {r#"
+            "CargoCult.toml" = '''
+            [[psychotropic.cues]]
+            name = "example"
+            use-thundercloud = { directory = "{{PROJECT}}/example-thundercloud" }
+            '''
+
+            [yeth-marthter.example.invar]
+        "#};
+
+        // When
+        let result = process_test_niche(thundercloud_toml, project_toml).await;
+
+        // Then
+        let Err(error) = result else { panic!("Expected an error") };
+        let message = format!("{error:#}");
+        assert!(message.contains("Cargo+option-@.toml:2: FOR without ENDFOR"), "Actual error: {message}");
+
+        Ok(())
+    }
+
+    #[test(tokio::test)]
+    async fn test_context_props() -> Result<()> {
+        // Given
+        let thundercloud_toml = indoc! {r#"
+            [example-thundercloud]
+            "thundercloud.toml" = """
+            [niche]
+            name = "clock-parts"
+
+            [invar-defaults.props]
+            banner = "Generated by {{THUNDERCLOUD}} for {{NICHE}}"
+            """
+
+            [example-thundercloud.cumulus.workshop]
+            "clock+option-@.yaml" = """
+            banner: "{{banner}}"
+            project: "{{PROJECT}}"
+            features: "{{FEATURES}}"
+            """
+        "#};
+        let project_toml = indoc! {r#"
+            "CargoCult.toml" = '''
+            [[psychotropic.cues]]
+            name = "example"
+            use-thundercloud = { directory = "{{PROJECT}}/example-thundercloud", features = ["glass", "kermie"] }
+            '''
+
+            [yeth-marthter.example.invar]
+        "#};
+
+        // When
+        let content = test_process_niche(thundercloud_toml, project_toml, to_absolute_path("/workshop/clock.yaml")).await?;
+
+        // Then
+        let expected = indoc! {r#"
+            banner: "Generated by clock-parts for example"
+            project: "/"
+            features: "glass,kermie"
+        "#};
+        assert_eq!(content, expected);
+
+        Ok(())
+    }
+
+    #[test(tokio::test)]
+    async fn test_shared_target_owner() -> Result<()> {
+        // Given
+        let thundercloud_toml = indoc! {r#"
+            [example-thundercloud]
+            "thundercloud.toml" = """
+            [niche]
+            name = "example"
+            """
+
+            [example-thundercloud.cumulus.workshop]
+            "clock+option-@.yaml" = """
+            parts:
+              - pendulum
+            # ==== FRAGMENT glass-spring ====
+            # ==== BEGIN FRAGMENT chime ====
+              - bell
+            # ==== END FRAGMENT chime ====
+            """
+            "clock+fragment-chime.yaml" = """
+            # ==== BEGIN FRAGMENT chime ====
+              - gong
+            # ==== END FRAGMENT chime ====
+            """
+        "#};
+        let project_toml = indoc! {r#"
+            "CargoCult.toml" = '''
+            [[psychotropic.cues]]
+            name = "example"
+            use-thundercloud = { directory = "{{PROJECT}}/example-thundercloud", features = ["chime"] }
+            shared-targets = ["workshop/clock.yaml"]
+            '''
+
+            [yeth-marthter.example.invar]
+        "#};
+
+        // When
+        let content = test_process_niche(thundercloud_toml, project_toml, to_absolute_path("/workshop/clock.yaml")).await?;
+
+        // Then
+        let expected = indoc! {r#"
+            parts:
+              - pendulum
+            # ==== FRAGMENT glass-spring ====
+            # ==== BEGIN FRAGMENT chime ====
+              - gong
+            # ==== END FRAGMENT chime ====
+        "#};
+        assert_eq!(content, expected);
+
+        Ok(())
+    }
+
+    #[test(tokio::test)]
+    async fn test_shared_target_contributor() -> Result<()> {
+        // Given
+        let thundercloud_toml = indoc! {r#"
+            [example-thundercloud]
+            "thundercloud.toml" = """
+            [niche]
+            name = "example"
+            """
+
+            [example-thundercloud.cumulus.workshop]
+            "clock+option-@.yaml" = """
+            ignored: true
+            """
+            "clock+fragment-glass-spring.yaml" = """
+            # ==== BEGIN FRAGMENT glass-spring ====
+              - glass spring
+            # ==== END FRAGMENT glass-spring ====
+            """
+        "#};
+        let project_toml = indoc! {r#"
+            "CargoCult.toml" = '''
+            [[psychotropic.cues]]
+            name = "owner"
+            shared-targets = ["workshop/clock.yaml"]
+
+            [[psychotropic.cues]]
+            name = "example"
+            use-thundercloud = { directory = "{{PROJECT}}/example-thundercloud", features = ["glass"] }
+            shared-targets = ["workshop/clock.yaml"]
+            '''
+
+            [yeth-marthter.example.invar]
+
+            [workshop]
+            "clock.yaml" = '''
+            parts:
+              - pendulum
+            # ==== FRAGMENT glass-spring ====
+            # ==== BEGIN FRAGMENT chime ====
+              - bell
+            # ==== END FRAGMENT chime ====
+            '''
+        "#};
+
+        // When
+        let content = test_process_niche(thundercloud_toml, project_toml, to_absolute_path("/workshop/clock.yaml")).await?;
+
+        // Then
+        let expected = indoc! {r#"
+            parts:
+              - pendulum
+            # ==== BEGIN FRAGMENT glass-spring ====
+              - glass spring
+            # ==== END FRAGMENT glass-spring ====
+            # ==== BEGIN FRAGMENT chime ====
+              - bell
+            # ==== END FRAGMENT chime ====
+        "#};
+        assert_eq!(content, expected);
+
+        Ok(())
+    }
+
+    #[test(tokio::test)]
+    async fn test_template_comments() -> Result<()> {
+        // Given
+        let thundercloud_toml = indoc! {r#"
+            [example-thundercloud]
+            "thundercloud.toml" = """
+            [niche]
+            name = "example"
+
+            [invar-defaults.by-extension.rs]
+            template-comment-prefix = "//igor//"
+            """
+
+            [example-thundercloud.cumulus.workshop]
+            "clock+option-@.yaml" = """
+            #igor# The sweeper is configured in the invar
+            sweeper: "Lu Tse"
+            parts:
+              #igor# Fragments add more parts
+            # ==== FRAGMENT glass-spring ====
+            """
+            "clock+fragment-glass-spring.yaml" = """
+            # ==== BEGIN FRAGMENT glass-spring ====
+              #igor# Replaces the steel spring
+              - glass spring
+            # ==== END FRAGMENT glass-spring ====
+            """
+            "main+option-@.rs" = """
+            //igor// Entry point of the clock
+            #igor# is not a comment here
+            fn main() {}
+            """
+        "#};
+        let project_toml = indoc! {r#"
+            "CargoCult.toml" = '''
+            [[psychotropic.cues]]
+            name = "example"
+            use-thundercloud = { directory = "{{PROJECT}}/example-thundercloud", features = ["glass"] }
+            '''
+
+            [yeth-marthter.example.invar]
+        "#};
+
+        // When
+        let fs = process_test_niche(thundercloud_toml, project_toml).await?;
+
+        // Then
+        let expected = indoc! {r#"
+            sweeper: "Lu Tse"
+            parts:
+            # ==== BEGIN FRAGMENT glass-spring ====
+              - glass spring
+            # ==== END FRAGMENT glass-spring ====
+        "#};
+        assert_eq!(fs.get_content(to_absolute_path("/workshop/clock.yaml")).await?, expected);
+        assert_eq!(fs.get_content(to_absolute_path("/workshop/main.rs")).await?, "#igor# is not a comment here\nfn main() {}\n");
+
+        Ok(())
+    }
+
+    #[test(tokio::test)]
+    async fn test_registered_functions() -> Result<()> {
+        // Given
+        let mut functions = FunctionRegistry::default();
+        functions.register("initials", |arguments| {
+            Ok(arguments.iter().flat_map(|argument| argument.split(' ')).filter_map(|word| word.chars().next()).collect())
+        });
+        let thundercloud_toml = indoc! {r#"
+            [example-thundercloud]
+            "thundercloud.toml" = """
+            [niche]
+            name = "example"
+
+            [invar-defaults.props]
+            sweeper = "Lu Tse"
+            """
+
+            [example-thundercloud.cumulus.workshop]
+            "clock+option-@.yaml" = """
+            sweeper: "{{sweeper}}"
+            initials: "{{fn:initials(sweeper, "Lobsang Ludd")}}"
+            """
+        "#};
+        let project_toml = indoc! {r#"
+            "CargoCult.toml" = '''
+            [[psychotropic.cues]]
+            name = "example"
+            use-thundercloud = { directory = "{{PROJECT}}/example-thundercloud" }
+            '''
+
+            [yeth-marthter.example.invar]
+        "#};
+
+        // When
+        let thundercloud_fs = fixture::from_toml(thundercloud_toml)?;
+        let fs = process_test_niche_with(thundercloud_fs, project_toml, RunOptions::default(), functions).await?;
+        let content = fs.get_content(to_absolute_path("/workshop/clock.yaml")).await?;
+
+        // Then
+        let expected = indoc! {r#"
+            sweeper: "Lu Tse"
+            initials: "LTLL"
+        "#};
+        assert_eq!(content, expected);
+
+        Ok(())
+    }
+
+    #[test(tokio::test)]
+    async fn test_line_endings() -> Result<()> {
+        // Given
+        let thundercloud_toml = indoc! {r#"
+            [example-thundercloud]
+            "thundercloud.toml" = """
+            [niche]
+            name = "example"
+
+            [invar-defaults.by-extension.bat]
+            line-endings = "CRLF"
+            """
+
+            [example-thundercloud.cumulus.workshop]
+            "clock+option-@.bat" = """
+            @echo off
+            echo {{sweeper:-Lu Tse}}
+            """
+        "#};
+        let project_toml = indoc! {r#"
+            "CargoCult.toml" = '''
+            [[psychotropic.cues]]
+            name = "example"
+            use-thundercloud = { directory = "{{PROJECT}}/example-thundercloud" }
+            '''
+
+            [yeth-marthter.example.invar]
+        "#};
+
+        // When
+        let fs = process_test_niche(thundercloud_toml, project_toml).await?;
+
+        // Then
+        let content = fs.read_bytes(to_absolute_path("/workshop/clock.bat")).await?;
+        assert_eq!(String::from_utf8(content)?, "@echo off\r\necho Lu Tse\r\n");
+
+        Ok(())
+    }
+
+    #[test(tokio::test)]
+    async fn test_props_exec() -> Result<()> {
+        // Given
+        let thundercloud_toml = indoc! {r#"
+            [example-thundercloud]
+            "thundercloud.toml" = """
+            [niche]
+            name = "example"
+
+            [invar-defaults.props-exec]
+            sweeper = "echo Lu Tse"
+            """
+
+            [example-thundercloud.cumulus.workshop]
+            "clock+option-@.yaml" = """
+            sweeper: "{{sweeper}}"
+            apprentice: "{{apprentice}}"
+            """
+        "#};
+        let project_toml = indoc! {r#"
+            "CargoCult.toml" = '''
+            [[psychotropic.cues]]
+            name = "example"
+            use-thundercloud = { directory = "{{PROJECT}}/example-thundercloud", invar-defaults = { props-exec = { apprentice = "printf 'Lobsang\\n'" } } }
+            '''
+
+            [yeth-marthter.example.invar]
+        "#};
+
+        // When
+        let thundercloud_fs = fixture::from_toml(thundercloud_toml)?;
+        let fs = process_test_niche_with(thundercloud_fs, project_toml, RunOptions::default().with_props_exec(true), FunctionRegistry::default()).await?;
+
+        // Then
+        let content = fs.get_content(to_absolute_path("/workshop/clock.yaml")).await?;
+        assert_eq!(content, "sweeper: \"Lu Tse\"\napprentice: \"Lobsang\"\n");
+        Ok(())
+    }
+
+    #[test(tokio::test)]
+    async fn test_unknown_bolts() -> Result<()> {
+        // Given
+        let thundercloud_toml = |unknown_bolts: &str| formatdoc! {r#"
+            [example-thundercloud]
+            "thundercloud.toml" = """
+            {unknown_bolts}
+            [niche]
+            name = "example"
+            """
+
+            [example-thundercloud.cumulus.workshop]
+            "clock+option-@.yaml" = "sweeper: Lu Tse"
+            "clock+opton-@.yaml" = "sweeper: Lobsang"
+            "mirror+opton.yaml" = "frame: wood"
+            "spring+fragmnt-glass.yaml" = "- glass spring"
+        "#};
+        let project_toml = indoc! {r#"
+            "CargoCult.toml" = '''
+            [[psychotropic.cues]]
+            name = "example"
+            use-thundercloud = { directory = "{{PROJECT}}/example-thundercloud" }
+            '''
+
+            [yeth-marthter.example.invar]
+        "#};
+
+        // When
+        let warned = process_test_niche(&thundercloud_toml(""), project_toml).await?;
+        let failed = process_test_niche(&thundercloud_toml(r#"unknown-bolts = "Error""#), project_toml).await;
+
+        // Then
+        assert_eq!(warned.get_content(to_absolute_path("/workshop/clock.yaml")).await?, "sweeper: Lu Tse\n");
+        assert_eq!(warned.path_type(&to_absolute_path("/workshop/mirror.yaml")).await, PathType::Missing);
+        let error = failed.err().map(|error| format!("{error:#}")).unwrap_or_default();
+        assert!(error.contains("Unknown bolt types in niche example: fragmnt (1), opton (2)"), "Actual error: {error}");
+        Ok(())
+    }
+
+    #[test(tokio::test)]
+    async fn test_random_values() -> Result<()> {
+        // Given
+        let thundercloud_toml = indoc! {r#"
+            [example-thundercloud]
+            "thundercloud.toml" = """
+            [niche]
+            name = "example"
+
+            [invar-defaults]
+            stable-random = true
+            """
+
+            [example-thundercloud.cumulus.workshop]
+            "clock+option-@.yaml" = """
+            id: "{{uuid}}"
+            secret: "{{random:alnum32}}"
+            """
+            "mirror+option-@.yaml" = """
+            id: "{{uuid}}"
+            """
+        "#};
+        let project_toml = indoc! {r#"
+            "CargoCult.toml" = '''
+            [[psychotropic.cues]]
+            name = "example"
+            use-thundercloud = { directory = "{{PROJECT}}/example-thundercloud" }
+            '''
+
+            [yeth-marthter.example]
+            "random-seed.toml" = 'seed = "4288428842884288428842884288428842884288428842884288428842884288"'
+
+            [yeth-marthter.example.invar]
+        "#};
+
+        // When
+        let first = process_test_niche(thundercloud_toml, project_toml).await?;
+        let second = process_test_niche(thundercloud_toml, project_toml).await?;
+
+        // Then
+        let clock = first.get_content(to_absolute_path("/workshop/clock.yaml")).await?;
+        let mirror = first.get_content(to_absolute_path("/workshop/mirror.yaml")).await?;
+        let clock_regex = Regex::new(r#"^id: "[0-9a-f]{8}-[0-9a-f]{4}-4[0-9a-f]{3}-[89ab][0-9a-f]{3}-[0-9a-f]{12}"\nsecret: "[A-Za-z0-9]{32}"\n$"#)?;
+        assert!(clock_regex.is_match(&clock), "Actual: {clock}");
+        assert_ne!(clock.lines().next(), mirror.lines().next());
+        assert_eq!(second.get_content(to_absolute_path("/workshop/clock.yaml")).await?, clock);
+        assert_eq!(second.get_content(to_absolute_path("/workshop/mirror.yaml")).await?, mirror);
+        Ok(())
+    }
+
+    #[test(tokio::test)]
+    async fn test_prop_declarations() -> Result<()> {
+        // Given
+        let thundercloud_toml = indoc! {r#"
+            [example-thundercloud]
+            "thundercloud.toml" = """
+            [niche]
+            name = "example"
+
+            [[props]]
+            name = "sweeper"
+            type = "String"
+            required = true
+            description = "Who keeps the clock clean"
+
+            [[props]]
+            name = "hours"
+            type = "Integer"
+
+            [[props]]
+            name = "clock.maker"
+            required = true
+            """
+
+            [example-thundercloud.cumulus.workshop]
+            "clock+option-@.yaml" = "sweeper: {{sweeper}}"
+        "#};
+        let project_toml = |props: &str| formatdoc! {r#"
+            "CargoCult.toml" = '''
+            [[psychotropic.cues]]
+            name = "example"
+
+            [psychotropic.cues.use-thundercloud]
+            directory = "{{{{PROJECT}}}}/example-thundercloud"
+
+            [psychotropic.cues.use-thundercloud.invar-defaults.props]
+            {props}
+            '''
+
+            [yeth-marthter.example.invar]
+        "#};
+
+        // When
+        let valid = process_test_niche(thundercloud_toml, &project_toml(r#"sweeper = "Lu Tse"
+            hours = 24
+            clock = { maker = "Jeremy" }"#)).await?;
+        let invalid = process_test_niche(thundercloud_toml, &project_toml(r#"hours = "24""#)).await;
+
+        // Then
+        assert_eq!(valid.get_content(to_absolute_path("/workshop/clock.yaml")).await?, "sweeper: Lu Tse\n");
+        let error = invalid.err().map(|error| format!("{error:#}")).unwrap_or_default();
+        assert!(error.contains("Invalid props for niche example: hours should be Integer, but is string; missing clock.maker; missing sweeper (Who keeps the clock clean)"), "Actual error: {error}");
+        Ok(())
+    }
+
+    #[test(tokio::test)]
+    async fn test_concatenate_fragments() -> Result<()> {
+        // Given
+        let thundercloud_toml = indoc! {r#"
+            [example-thundercloud]
+            "thundercloud.toml" = """
+            [niche]
+            name = "example"
+            """
+
+            [example-thundercloud.cumulus.workshop]
+            "clock+option-@.yaml" = '''
+            raising:
+            # ==== BEGIN FRAGMENT @-spring ====
+              - "replaced-by-fragment"
+            # ==== END FRAGMENT @-spring ====
+              - "to the occasion"
+            '''
+            "clock+fragment-@-spring.yaml" = '''
+            # ==== BEGIN FRAGMENT @-spring ====
+              - "steam"
+            # ==== END FRAGMENT @-spring ====
+            '''
+        "#};
+        let project_toml = |combine_fragments: &str| formatdoc! {r#"
+            "CargoCult.toml" = '''
+            [[psychotropic.cues]]
+            name = "example"
+            use-thundercloud = {{ directory = "{{{{PROJECT}}}}/example-thundercloud" }}
+            '''
+
+            [yeth-marthter.example.invar.workshop]
+            "clock+config-@.yaml.toml" = "{combine_fragments}"
+            "clock+fragment-@-spring.yaml" = """
+            # ==== BEGIN FRAGMENT @-spring ====
+              - "expectations"
+            # ==== END FRAGMENT @-spring ====
+            """
+        "#};
+
+        // When
+        let replaced = process_test_niche(thundercloud_toml, &project_toml("")).await?;
+        let concatenated = process_test_niche(thundercloud_toml, &project_toml(r#"combine-fragments = \"Concatenate\""#)).await?;
+
+        // Then
+        let expected_replaced = indoc! {r#"
+            raising:
+            # ==== BEGIN FRAGMENT @-spring ====
+              - "expectations"
+            # ==== END FRAGMENT @-spring ====
+              - "to the occasion"
+        "#};
+        assert_eq!(replaced.get_content(to_absolute_path("/workshop/clock.yaml")).await?, expected_replaced);
+        let expected_concatenated = indoc! {r#"
+            raising:
+            # ==== BEGIN FRAGMENT @-spring ====
+              - "steam"
+              - "expectations"
+            # ==== END FRAGMENT @-spring ====
+              - "to the occasion"
+        "#};
+        assert_eq!(concatenated.get_content(to_absolute_path("/workshop/clock.yaml")).await?, expected_concatenated);
+        Ok(())
+    }
+
+    #[test(tokio::test)]
+    async fn test_fragment_order() -> Result<()> {
+        // Given
+        let thundercloud_toml = indoc! {r#"
+            [example-thundercloud]
+            "thundercloud.toml" = """
+            [niche]
+            name = "example"
+            """
+
+            [example-thundercloud.cumulus.workshop]
+            "clock+option-@.yaml" = '''
+            raising:
+            # ==== BEGIN FRAGMENT @-spring ====
+            # ==== END FRAGMENT @-spring ====
+            '''
+            "clock+fragment-@-spring~last.yaml" = '''
+            # ==== BEGIN FRAGMENT @-spring ====
+              - "steam"
+            # ==== END FRAGMENT @-spring ====
+            '''
+        "#};
+        let project_toml = |fragment_order: &str| formatdoc! {r#"
+            "CargoCult.toml" = '''
+            [[psychotropic.cues]]
+            name = "example"
+            use-thundercloud = {{ directory = "{{{{PROJECT}}}}/example-thundercloud" }}
+            '''
+
+            [yeth-marthter.example.invar.workshop]
+            "clock+config-@.yaml.toml" = """
+            combine-fragments = "Concatenate"
+            {fragment_order}
+            """
+            "clock+fragment-@-spring.yaml" = """
+            # ==== BEGIN FRAGMENT @-spring ====
+              - "expectations"
+            # ==== END FRAGMENT @-spring ====
+            """
+            "clock+fragment-@-spring~-1.yaml" = """
+            # ==== BEGIN FRAGMENT @-spring ====
+              - "hope"
+            # ==== END FRAGMENT @-spring ====
+            """
+        "#};
+
+        // When
+        let by_name = process_test_niche(thundercloud_toml, &project_toml("")).await?;
+        let configured = process_test_niche(thundercloud_toml, &project_toml(r#"fragment-order = { "thundercloud:clock+fragment-@-spring~last.yaml" = "first", "clock+fragment-@-spring.yaml" = -2 }"#)).await?;
+
+        // Then
+        let expected_by_name = indoc! {r#"
+            raising:
+            # ==== BEGIN FRAGMENT @-spring ====
+              - "hope"
+              - "expectations"
+              - "steam"
+            # ==== END FRAGMENT @-spring ====
+        "#};
+        assert_eq!(by_name.get_content(to_absolute_path("/workshop/clock.yaml")).await?, expected_by_name);
+        let expected_configured = indoc! {r#"
+            raising:
+            # ==== BEGIN FRAGMENT @-spring ====
+              - "steam"
+              - "expectations"
+              - "hope"
+            # ==== END FRAGMENT @-spring ====
+        "#};
+        assert_eq!(configured.get_content(to_absolute_path("/workshop/clock.yaml")).await?, expected_configured);
+        Ok(())
+    }
+
+    #[test(tokio::test)]
+    async fn test_strip_markers() -> Result<()> {
+        // Given
+        let thundercloud_toml = indoc! {r#"
+            [example-thundercloud]
+            "thundercloud.toml" = """
+            [niche]
+            name = "example"
+            """
+
+            [example-thundercloud.cumulus.workshop]
+            "clock+option-@.json" = '''
+            {
+              "sweeper": "Lu Tse",
+              ==== BEGIN FRAGMENT @-apprentice ====
+              "apprentice": "nobody",
+              ==== END FRAGMENT @-apprentice ====
+              "ticking": true
+            }
+            '''
+            "clock+fragment-@-apprentice.json" = '''
+            ==== BEGIN FRAGMENT @-apprentice ====
+              "apprentice": "Lobsang",
+            ==== END FRAGMENT @-apprentice ====
+            '''
+        "#};
+        let project_toml = indoc! {r#"
+            "CargoCult.toml" = '''
+            [[psychotropic.cues]]
+            name = "example"
+            use-thundercloud = { directory = "{{PROJECT}}/example-thundercloud" }
+            '''
+
+            [yeth-marthter.example.invar.workshop]
+            "clock+config-@.json.toml" = "keep-markers = false"
+        "#};
+
+        // When
+        let fs = process_test_niche(thundercloud_toml, project_toml).await?;
+
+        // Then
+        let expected = indoc! {r#"
+            {
+              "sweeper": "Lu Tse",
+              "apprentice": "Lobsang",
+              "ticking": true
+            }
+        "#};
+        assert_eq!(fs.get_content(to_absolute_path("/workshop/clock.json")).await?, expected);
+        Ok(())
+    }
+
+    #[test(tokio::test)]
+    async fn test_head_and_tail_fragments() -> Result<()> {
+        // Given
+        let thundercloud_toml = indoc! {r#"
+            [example-thundercloud]
+            "thundercloud.toml" = """
+            [niche]
+            name = "example"
+            """
+
+            [example-thundercloud.cumulus.workshop]
+            "clock+option-@.yaml" = '''
+            sweeper: Lu Tse
+            '''
+            "clock+fragment-@-head.yaml" = '''
+            # ==== BEGIN FRAGMENT @-head ====
+            # Managed by Igor
+            # ==== END FRAGMENT @-head ====
+            '''
+            "mirror+option-@.yaml" = '''
+            # ==== BEGIN FRAGMENT @-tail ====
+            # ==== END FRAGMENT @-tail ====
+            frame: wood
+            '''
+            "mirror+fragment-@-tail.yaml" = '''
+            # ==== BEGIN FRAGMENT @-tail ====
+            # End of mirror
+            # ==== END FRAGMENT @-tail ====
+            '''
+        "#};
+        let project_toml = indoc! {r#"
+            "CargoCult.toml" = '''
+            [[psychotropic.cues]]
+            name = "example"
+            use-thundercloud = { directory = "{{PROJECT}}/example-thundercloud" }
+            '''
+
+            [yeth-marthter.example.invar.workshop]
+            "clock+fragment-@-tail.yaml" = """
+            # ==== BEGIN FRAGMENT @-tail ====
+            # The end
+            # ==== END FRAGMENT @-tail ====
+            """
+        "#};
+
+        // When
+        let fs = process_test_niche(thundercloud_toml, project_toml).await?;
+
+        // Then
+        let expected_clock = indoc! {r#"
+            # ==== BEGIN FRAGMENT @-head ====
+            # Managed by Igor
+            # ==== END FRAGMENT @-head ====
+            sweeper: Lu Tse
+            # ==== BEGIN FRAGMENT @-tail ====
+            # The end
+            # ==== END FRAGMENT @-tail ====
+        "#};
+        assert_eq!(fs.get_content(to_absolute_path("/workshop/clock.yaml")).await?, expected_clock);
+        let expected_mirror = indoc! {r#"
+            # ==== BEGIN FRAGMENT @-tail ====
+            # End of mirror
+            # ==== END FRAGMENT @-tail ====
+            frame: wood
+        "#};
+        assert_eq!(fs.get_content(to_absolute_path("/workshop/mirror.yaml")).await?, expected_mirror);
+        Ok(())
+    }
+
+    #[test(tokio::test)]
+    async fn test_anchored_fragments() -> Result<()> {
+        // Given
+        let thundercloud_toml = indoc! {r#"
+            [example-thundercloud]
+            "thundercloud.toml" = """
+            [niche]
+            name = "example"
+            """
+
+            [example-thundercloud.cumulus]
+            "Cargo+option-@.toml" = '''
+            [package]
+            name = "clock"
+
+            [dependencies]
+            tokio = "1"
+            '''
+        "#};
+        let project_toml = indoc! {r#"
+            "CargoCult.toml" = '''
+            [[psychotropic.cues]]
+            name = "example"
+            use-thundercloud = { directory = "{{PROJECT}}/example-thundercloud" }
+            '''
+
+            [yeth-marthter.example.invar]
+            "Cargo+config-@.toml.toml" = """
+            [anchors."@-deps"]
+            pattern = '^\\[dependencies\\]'
+
+            [anchors."@-edition"]
+            pattern = '^\\[dependencies\\]'
+            position = "Before"
+
+            [anchors."@-unused"]
+            pattern = '^\\[workspace\\]'
+            """
+            "Cargo+fragment-@-deps.toml" = """
+            # ==== BEGIN FRAGMENT @-deps ====
+            regex = "1"
+            # ==== END FRAGMENT @-deps ====
+            """
+            "Cargo+fragment-@-edition.toml" = """
+            # ==== BEGIN FRAGMENT @-edition ====
+            edition = "2021"
+
+            # ==== END FRAGMENT @-edition ====
+            """
+        "#};
+
+        // When
+        let fs = process_test_niche(thundercloud_toml, project_toml).await?;
+
+        // Then
+        let expected = indoc! {r#"
+            [package]
+            name = "clock"
+
+            edition = "2021"
+
+            [dependencies]
+            regex = "1"
+            tokio = "1"
+        "#};
+        assert_eq!(fs.get_content(to_absolute_path("/Cargo.toml")).await?, expected);
+        Ok(())
+    }
+
+    #[test(tokio::test)]
+    async fn test_marker_syntax() -> Result<()> {
+        // Given
+        let thundercloud_toml = indoc! {r#"
+            [example-thundercloud]
+            "thundercloud.toml" = """
+            [niche]
+            name = "example"
+
+            [invar-defaults.by-extension.html]
+            marker-syntax = "<!-- ... -->"
+            """
+
+            [example-thundercloud.cumulus.workshop]
+            "clock+option-@.html" = '''
+            <ul>
+              <li>Lu Tse</li>
+              <!-- FRAGMENT @-apprentice -->
+            </ul>
+            <p>==== FRAGMENT @-apprentice ====</p>
+            '''
+            "clock+fragment-@-apprentice.html" = '''
+            <!-- BEGIN FRAGMENT @-apprentice -->
+              <li>Lobsang</li>
+            <!-- END FRAGMENT @-apprentice -->
+            '''
+        "#};
+        let project_toml = indoc! {r#"
+            "CargoCult.toml" = '''
+            [[psychotropic.cues]]
+            name = "example"
+            use-thundercloud = { directory = "{{PROJECT}}/example-thundercloud" }
+            '''
+
+            [yeth-marthter.example.invar]
+        "#};
+
+        // When
+        let fs = process_test_niche(thundercloud_toml, project_toml).await?;
+
+        // Then
+        let expected = indoc! {r#"
+            <ul>
+              <li>Lu Tse</li>
+            <!-- BEGIN FRAGMENT @-apprentice -->
+              <li>Lobsang</li>
+            <!-- END FRAGMENT @-apprentice -->
+            </ul>
+            <p>==== FRAGMENT @-apprentice ====</p>
+        "#};
+        assert_eq!(fs.get_content(to_absolute_path("/workshop/clock.html")).await?, expected);
+        Ok(())
+    }
+
+    #[test(tokio::test)]
+    async fn test_include_directive() -> Result<()> {
+        // Given
+        let thundercloud_toml = indoc! {r#"
+            [example-thundercloud]
+            "thundercloud.toml" = """
+            [niche]
+            name = "example"
+
+            [invar-defaults.props]
+            owner = "Lu Tse"
+            """
+
+            [example-thundercloud.cumulus.workshop]
+            "clock+option-@.yaml" = '''
+            # ==== INCLUDE snippets/license+include.txt ====
+            sweeper: {{owner}}
+            # ==== FRAGMENT @-apprentice ====
+            '''
+            "clock+fragment-@-apprentice.yaml" = '''
+            # ==== BEGIN FRAGMENT @-apprentice ====
+            # ==== INCLUDE snippets/motto+include.txt ====
+            # ==== END FRAGMENT @-apprentice ====
+            '''
+
+            [example-thundercloud.cumulus.snippets]
+            "license+include.txt" = '''
+            # Copyright {{owner}}
+            # ==== INCLUDE snippets/motto+include.txt ====
+            '''
+            "motto+include.txt" = '# Do not rush'
+        "#};
+        let project_toml = indoc! {r#"
+            "CargoCult.toml" = '''
+            [[psychotropic.cues]]
+            name = "example"
+            use-thundercloud = { directory = "{{PROJECT}}/example-thundercloud" }
+            '''
+
+            [yeth-marthter.example.invar.snippets]
+            "motto+include.txt" = '# Come back yesterday'
+        "#};
+
+        // When
+        let fs = process_test_niche(thundercloud_toml, project_toml).await?;
+
+        // Then
+        let expected = indoc! {r#"
+            # Copyright Lu Tse
+            # Come back yesterday
+            sweeper: Lu Tse
+            # ==== BEGIN FRAGMENT @-apprentice ====
+            # Come back yesterday
+            # ==== END FRAGMENT @-apprentice ====
+        "#};
+        assert_eq!(fs.get_content(to_absolute_path("/workshop/clock.yaml")).await?, expected);
+        assert_eq!(fs.path_type(&to_absolute_path("/snippets")).await, PathType::Missing);
+        Ok(())
+    }
+
+    #[test(tokio::test)]
+    async fn test_keep_regions() -> Result<()> {
+        // Given
+        let thundercloud_toml = indoc! {r#"
+            [example-thundercloud]
+            "thundercloud.toml" = """
+            [niche]
+            name = "example"
+            """
+
+            [example-thundercloud.cumulus.workshop]
+            "clock+option-@.yaml" = '''
+            sweeper: Lu Tse
+            # ==== BEGIN KEEP local ====
+            # Add your own settings here
+            # ==== END KEEP local ====
+            # ==== BEGIN KEEP other ====
+            ticking: true
+            # ==== END KEEP other ====
+            # ==== KEEP bells ====
+            # ==== END KEEP ====
+            '''
+        "#};
+        let project_toml = indoc! {r#"
+            "CargoCult.toml" = '''
+            [[psychotropic.cues]]
+            name = "example"
+            use-thundercloud = { directory = "{{PROJECT}}/example-thundercloud" }
+            '''
+
+            [yeth-marthter.example.invar]
+
+            [workshop]
+            "clock.yaml" = '''
+            sweeper: nobody
+            # ==== BEGIN KEEP local ====
+            apprentice: Lobsang
+            chime: false
+            # ==== END KEEP local ====
+            # ==== KEEP bells ====
+            bells: 12
+            # ==== END KEEP ====
+            '''
+        "#};
+
+        // When
+        let fs = process_test_niche(thundercloud_toml, project_toml).await?;
+
+        // Then
+        let expected = indoc! {r#"
+            sweeper: Lu Tse
+            # ==== BEGIN KEEP local ====
+            apprentice: Lobsang
+            chime: false
+            # ==== END KEEP local ====
+            # ==== BEGIN KEEP other ====
+            ticking: true
+            # ==== END KEEP other ====
+            # ==== KEEP bells ====
+            bells: 12
+            # ==== END KEEP ====
+        "#};
+        assert_eq!(fs.get_content(to_absolute_path("/workshop/clock.yaml")).await?, expected);
+        Ok(())
+    }
+
+    #[test(tokio::test)]
+    async fn test_patch_bolt() -> Result<()> {
+        // Given
+        let thundercloud_toml = indoc! {r#"
+            [example-thundercloud]
+            "thundercloud.toml" = """
+            [niche]
+            name = "example"
+            """
+
+            [example-thundercloud.cumulus.workshop]
+            "clock+option-@.yaml" = '''
+            clock:
+              hours: 10
+              spring: coiled
+            # ==== FRAGMENT @-apprentice ====
+            '''
+            "clock+fragment-@-apprentice.yaml" = '''
+            # ==== BEGIN FRAGMENT @-apprentice ====
+            apprentice: Lobsang
+            # ==== END FRAGMENT @-apprentice ====
+            '''
+        "#};
+        let project_toml = indoc! {r#"
+            "CargoCult.toml" = '''
+            [[psychotropic.cues]]
+            name = "example"
+            use-thundercloud = { directory = "{{PROJECT}}/example-thundercloud" }
+            '''
+
+            [yeth-marthter.example.invar.workshop]
+            "clock+patch-@.yaml" = '''
+            --- a/workshop/clock.yaml
+            +++ b/workshop/clock.yaml
+            @@ -2,3 +2,3 @@
+            -  hours: 10
+            +  hours: 12
+               spring: coiled
+             # ==== BEGIN FRAGMENT @-apprentice ====
+            @@ -4,2 +4,3 @@
+             apprentice: Lobsang
+            +sweeper: Lu Tse
+             # ==== END FRAGMENT @-apprentice ====
+            '''
+        "#};
+
+        // When
+        let fs = process_test_niche(thundercloud_toml, project_toml).await?;
+
+        // Then
+        let expected = indoc! {r#"
+            clock:
+              hours: 12
+              spring: coiled
+            # ==== BEGIN FRAGMENT @-apprentice ====
+            apprentice: Lobsang
+            sweeper: Lu Tse
+            # ==== END FRAGMENT @-apprentice ====
+        "#};
+        assert_eq!(fs.get_content(to_absolute_path("/workshop/clock.yaml")).await?, expected);
+        Ok(())
+    }
+
+    #[test(tokio::test)]
+    async fn test_append_bolt() -> Result<()> {
+        // Given
+        let thundercloud_toml = indoc! {r#"
+            [example-thundercloud]
+            "thundercloud.toml" = """
+            [niche]
+            name = "example"
+
+            [invar-defaults.props]
+            apprentice = "Lobsang"
+            """
+
+            [example-thundercloud.cumulus]
+            "dot_gitignore+append-@" = '''
+            # Clock parts of {{apprentice}}
+            /target
+            *.bak
+            '''
+
+            [example-thundercloud.cumulus.workshop]
+            "hosts+append-@" = '''
+            127.0.0.1 workshop
+            '''
+        "#};
+        let project_toml = indoc! {r#"
+            "CargoCult.toml" = '''
+            [[psychotropic.cues]]
+            name = "example"
+            use-thundercloud = { directory = "{{PROJECT}}/example-thundercloud" }
+            '''
+            ".gitignore" = '''
+            /target
+            .idea
+            '''
+
+            [yeth-marthter.example.invar]
+            "dot_gitignore+append-@" = '''
+            *.swp
+            '''
+        "#};
+
+        // When
+        let fs = process_test_niche(thundercloud_toml, project_toml).await?;
+
+        // Then
+        let expected = indoc! {r#"
+            /target
+            .idea
+            # Clock parts of Lobsang
+            *.bak
+            *.swp
+        "#};
+        assert_eq!(fs.get_content(to_absolute_path("/.gitignore")).await?, expected);
+        assert_eq!(fs.get_content(to_absolute_path("/workshop/hosts")).await?, "127.0.0.1 workshop\n");
+        Ok(())
+    }
+
+    #[test(tokio::test)]
+    async fn test_tombstone_bolt() -> Result<()> {
+        // Given
+        let thundercloud_toml = indoc! {r#"
+            [example-thundercloud]
+            "thundercloud.toml" = """
+            [niche]
+            name = "example"
+            """
+
+            [example-thundercloud.cumulus.workshop]
+            "clock+option-@.yaml" = '''
+            sweeper: Lu Tse
+            '''
+            "mirror+option-@.yaml" = '''
+            polished: true
+            '''
+        "#};
+        let project_toml = indoc! {r#"
+            "CargoCult.toml" = '''
+            [[psychotropic.cues]]
+            name = "example"
+            use-thundercloud = { directory = "{{PROJECT}}/example-thundercloud" }
+            '''
+
+            [yeth-marthter.example.invar.workshop]
+            "clock+skip.yaml" = ""
+            "mirror+delete-brass.yaml" = ""
+        "#};
+
+        // When
+        let fs = process_test_niche(thundercloud_toml, project_toml).await?;
+
+        // Then
+        assert_eq!(fs.path_type(&to_absolute_path("/workshop/clock.yaml")).await, PathType::Missing);
+        assert_eq!(fs.get_content(to_absolute_path("/workshop/mirror.yaml")).await?, "polished: true\n");
+        Ok(())
+    }
+
+    #[test(tokio::test)]
+    async fn test_symlink_bolt() -> Result<()> {
+        // Given
+        let thundercloud_toml = indoc! {r#"
+            [example-thundercloud]
+            "thundercloud.toml" = """
+            [niche]
+            name = "example"
+
+            [invar-defaults.props]
+            release = "v1"
+            """
+
+            [example-thundercloud.cumulus]
+            "current+symlink" = '''
+            releases/{{release}}
+            '''
+
+            [example-thundercloud.cumulus.releases.v1]
+            "clock+option-@.yaml" = '''
+            sweeper: Lu Tse
+            '''
+        "#};
+        let project_toml = indoc! {r#"
+            "CargoCult.toml" = '''
+            [[psychotropic.cues]]
+            name = "example"
+            use-thundercloud = { directory = "{{PROJECT}}/example-thundercloud" }
+            '''
+
+            [yeth-marthter.example.invar]
+        "#};
+
+        // When
+        let fs = process_test_niche(thundercloud_toml, project_toml).await?;
+
+        // Then
+        assert_eq!(fs.read_link(&to_absolute_path("/current")).await?, Path::new("releases/v1"));
+        assert_eq!(fs.get_content(to_absolute_path("/releases/v1/clock.yaml")).await?, "sweeper: Lu Tse\n");
+        Ok(())
+    }
+
+    #[test(tokio::test)]
+    async fn test_binary_bolt() -> Result<()> {
+        // Given
+        let thundercloud_toml = indoc! {r#"
+            [example-thundercloud]
+            "thundercloud.toml" = """
+            [niche]
+            name = "example"
+
+            [invar-defaults.props]
+            release = "v1"
+            """
+
+            [example-thundercloud.cumulus]
+            "keystore+binary.jks" = '''
+            magic {{release}}
+            '''
+        "#};
+        let project_toml = indoc! {r#"
+            "CargoCult.toml" = '''
+            [[psychotropic.cues]]
+            name = "example"
+            use-thundercloud = { directory = "{{PROJECT}}/example-thundercloud" }
+            '''
+
+            [yeth-marthter.example.invar]
+        "#};
+
+        // When
+        let fs = process_test_niche(thundercloud_toml, project_toml).await?;
+
+        // Then
+        assert_eq!(fs.read_bytes(to_absolute_path("/keystore.jks")).await?, b"magic {{release}}\n");
+        Ok(())
+    }
+
+    #[test(tokio::test)]
+    async fn test_option_precedence() -> Result<()> {
+        // Given
+        let thundercloud_toml = indoc! {r#"
+            [example-thundercloud]
+            "thundercloud.toml" = """
+            [niche]
+            name = "example"
+            """
+
+            [example-thundercloud.cumulus]
+            "clock+option.yaml" = "hours: 10"
+            "clock+option-glass.yaml" = "hours: 12"
+            "clock+option-kermie.yaml" = "hours: 24"
+            "mirror+option-glass.yaml" = "frame: glass"
+            "mirror+option-kermie.yaml" = "frame: kermie"
+        "#};
+        let project_toml = indoc! {r#"
+            "CargoCult.toml" = '''
+            [[psychotropic.cues]]
+            name = "example"
+            use-thundercloud = { directory = "{{PROJECT}}/example-thundercloud", features = ["kermie", "glass"] }
+            '''
+
+            [yeth-marthter.example.invar]
+            "mirror+option-glass.yaml" = "frame: invar"
+        "#};
+
+        // When
+        let fs = process_test_niche(thundercloud_toml, project_toml).await?;
+
+        // Then
+        assert_eq!(fs.get_content(to_absolute_path("/clock.yaml")).await?, "hours: 24\n");
+        assert_eq!(fs.get_content(to_absolute_path("/mirror.yaml")).await?, "frame: invar\n");
+        Ok(())
+    }
+
+    #[test(tokio::test)]
+    async fn test_strict_options() -> Result<()> {
+        // Given
+        let thundercloud_toml = indoc! {r#"
+            [example-thundercloud]
+            "thundercloud.toml" = """
+            [niche]
+            name = "example"
+            """
+
+            [example-thundercloud.cumulus]
+            "clock+option.yaml" = "hours: 10"
+            "clock+option-@.yaml" = "hours: 12"
+        "#};
+        let project_toml = indoc! {r#"
+            "CargoCult.toml" = '''
+            [[psychotropic.cues]]
+            name = "example"
+            use-thundercloud = { directory = "{{PROJECT}}/example-thundercloud", strict-options = true }
+            '''
+
+            [yeth-marthter.example.invar]
+        "#};
+
+        // When
+        let result = process_test_niche(thundercloud_toml, project_toml).await;
+
+        // Then
+        let Err(error) = result else { panic!("Expected ambiguous options to fail") };
+        assert_eq!(error.to_string(), "Ambiguous options for \"clock.yaml\": \"/example-thundercloud/cumulus/clock+option-@.yaml\" and \"/example-thundercloud/cumulus/clock+option.yaml\"");
+        Ok(())
+    }
+
+    #[test(tokio::test)]
+    async fn test_interpolated_bolt_names() -> Result<()> {
+        // Given
+        let thundercloud_toml = indoc! {r#"
+            [example-thundercloud]
+            "thundercloud.toml" = """
+            [niche]
+            name = "example"
+            """
+
+            [example-thundercloud.cumulus]
+            "clock+option-glass.yaml" = "hours: 12"
+            "mirror+option-{{frame}}.yaml" = "frame: wood"
+        "#};
+        let project_toml = indoc! {r#"
+            "CargoCult.toml" = '''
+            [[psychotropic.cues]]
+            name = "example"
+            use-thundercloud = { directory = "{{PROJECT}}/example-thundercloud", features = ["glass", "kermie"], invar-defaults = { props = { flavor = "kermie" } } }
+            '''
+
+            [yeth-marthter.example.invar]
+            "clock+option-{{flavor}}.yaml" = "hours: 24"
+        "#};
+
+        // When
+        let fs = process_test_niche(thundercloud_toml, project_toml).await?;
+
+        // Then
+        assert_eq!(fs.get_content(to_absolute_path("/clock.yaml")).await?, "hours: 24\n");
+        assert_eq!(fs.path_type(&to_absolute_path("/mirror.yaml")).await, PathType::Missing);
+        assert_eq!(interpolate_bolt_name("x+y{{z}}+option-{{flavor}}", &toml::toml! { flavor = "glass" }).as_deref(), Some("x+y{{z}}+option-glass"));
+        Ok(())
+    }
+
+    #[test(tokio::test)]
+    async fn test_backup() -> Result<()> {
+        // Given
+        let thundercloud_toml = indoc! {r#"
+            [example-thundercloud]
+            "thundercloud.toml" = """
+            [niche]
+            name = "example"
+            """
+
+            [example-thundercloud.cumulus]
+            "clock+option.yaml" = "hours: 12"
+            "mirror+option.yaml" = "frame: glass"
+        "#};
+        let project_toml = indoc! {r#"
+            "CargoCult.toml" = '''
+            [[psychotropic.cues]]
+            name = "example"
+            use-thundercloud = { directory = "{{PROJECT}}/example-thundercloud", invar-defaults = { backup = true } }
+            '''
+            "clock.yaml" = "hours: 10"
+            "mirror.yaml" = "frame: glass"
 
-fn add<K,I>(map: &mut AHashMap<K,Vec<I>>, key: &K, item: I)
-where
-    K: PartialEq + Eq + Hash + Clone
-{
-    if let Some(existing_list) = map.get_mut(key) {
-        existing_list.push(item);
-    } else {
-        let mut new_list = Vec::new();
-        new_list.push(item);
-        map.insert(key.clone(), new_list);
-    }
-}
+            [yeth-marthter.example.invar]
+        "#};
 
-#[cfg(test)]
-mod test {
-    use indoc::indoc;
-    use test_log::test;
-    use crate::config_model::{project_config, NicheTriggers, ProjectConfig, PsychotropicConfig};
-    use crate::file_system::ConfigFormat::TOML;
-    use crate::file_system::fixture;
-    use crate::path::test_utils::to_absolute_path;
-    use super::*;
+        // When
+        let fs = process_test_niche(thundercloud_toml, project_toml).await?;
+
+        // Then
+        assert_eq!(fs.get_content(to_absolute_path("/clock.yaml")).await?, "hours: 12\n");
+        assert_eq!(fs.get_content(to_absolute_path("/clock.yaml.bak")).await?, "hours: 10\n");
+        assert_eq!(fs.get_content(to_absolute_path("/mirror.yaml")).await?, "frame: glass\n");
+        assert_eq!(fs.path_type(&to_absolute_path("/mirror.yaml.bak")).await, PathType::Missing);
+        Ok(())
+    }
 
     #[test(tokio::test)]
-    async fn test_process_complex_niche() -> Result<()> {
+    async fn test_rules() -> Result<()> {
         // Given
         let thundercloud_toml = indoc! {r#"
             [example-thundercloud]
             "thundercloud.toml" = """
             [niche]
             name = "example"
-            description = "Example thundercloud for demonstration purposes"
 
             [invar-defaults]
-            write-mode = "Overwrite"
-            interpolate = true
+            rules = [ { glob = "docs/**", interpolate = false } ]
 
             [invar-defaults.props]
-            milk-man = "Ronny Soak"
-            alter-ego = "Lobsang"
+            sweeper = "Lu Tse"
             """
 
+            [example-thundercloud.cumulus.docs]
+            "clock+option.md" = "{{sweeper}}"
+
             [example-thundercloud.cumulus.workshop]
-            "clock+fragment-glass-spring.yaml" = """
-            ---
-            spring:
-              material: glass
-              delicate: false
-              number-of-coils: 3
-            """
-            "clock+option-glass.yaml" = '''
-            ---
-            sweeper: "{{alter-ego}}"
-            raising:
-              - "steam"
-              - "money"
-            # ==== BEGIN FRAGMENT glass-spring ====
-              - "replaced-by-fragment"
-            # ==== END FRAGMENT glass-spring ====
-              - "to the occasion"
-            '''
-            "clock+config-glass.yaml.toml" = """
+            "dot_+config.toml" = '''
+            [[rules]]
+            glob = "*.sh"
+            executable = true
+
+            [[rules]]
+            glob = "*.md"
             write-mode = "WriteNew"
-            """
+            '''
+            "wind-up+option.sh" = "echo {{sweeper}}"
+            "notes+option.md" = "generated"
+            "clock+option.md" = "{{sweeper}}"
         "#};
         let project_toml = indoc! {r#"
             "CargoCult.toml" = '''
             [[psychotropic.cues]]
             name = "example"
-            use-thundercloud = { directory = "{{PROJECT}}/example-thundercloud", on-incoming = "Update", features = ["glass", "bash_config", "kermie"], invar-defaults = { props = { marthter = "Jeremy", buyer = "Myra LeJean", milk-man = "Kaos" } } }
+            use-thundercloud = { directory = "{{PROJECT}}/example-thundercloud" }
             '''
 
-            [yeth-marthter.example.invar.workshop]
-            "clock+config-glass.yaml.toml" = """
-            write-mode = "Overwrite"
+            [workshop]
+            "notes.md" = "edited"
 
-            [props]
+            [yeth-marthter.example.invar]
+        "#};
+
+        // When
+        let fs = process_test_niche(thundercloud_toml, project_toml).await?;
+
+        // Then
+        assert_eq!(fs.get_content(to_absolute_path("/docs/clock.md")).await?, "{{sweeper}}\n");
+        assert_eq!(fs.get_content(to_absolute_path("/workshop/clock.md")).await?, "Lu Tse\n");
+        assert_eq!(fs.get_content(to_absolute_path("/workshop/notes.md")).await?, "edited\n");
+        assert_eq!(fs.get_content(to_absolute_path("/workshop/wind-up.sh")).await?, "echo Lu Tse\n");
+        assert!(fs.metadata(&to_absolute_path("/workshop/wind-up.sh")).await?.is_executable());
+        Ok(())
+    }
+
+    #[test(tokio::test)]
+    async fn test_directory_config_inherited_by_subdirectories() -> Result<()> {
+        // Given
+        let thundercloud_toml = indoc! {r#"
+            [example-thundercloud]
+            "thundercloud.toml" = """
+            [niche]
+            name = "example"
+
+            [invar-defaults.props]
             sweeper = "Lu Tse"
             """
-            "clock+fragment-glass-spring.yaml" = '''
-            # ==== BEGIN FRAGMENT glass-spring ====
-            ---
-            spring:
-              material: glass
-              delicate: true
-              number-of-coils: 17
-            raising:
-              - "expectations"
-            # ==== END FRAGMENT glass-spring ====
+
+            [example-thundercloud.cumulus.workshop]
+            "dot_+config.toml" = '''
+            write-mode = "WriteNew"
+            '''
+
+            [example-thundercloud.cumulus.workshop.drawer]
+            "spring+option.yaml" = "material: glass"
+
+            [example-thundercloud.cumulus.workshop.drawer.box]
+            "key+option.yaml" = "owner: {{sweeper}}"
+        "#};
+        let project_toml = indoc! {r#"
+            "CargoCult.toml" = '''
+            [[psychotropic.cues]]
+            name = "example"
+            use-thundercloud = { directory = "{{PROJECT}}/example-thundercloud" }
+            '''
+
+            [workshop.drawer]
+            "spring.yaml" = "material: steel"
+
+            [yeth-marthter.example.invar.workshop.drawer.box]
+            "dot_+config.toml" = '''
+            interpolate = false
             '''
         "#};
 
         // When
-        let result_file_path = to_absolute_path("/workshop/clock.yaml");
-        let result_body = test_process_niche(thundercloud_toml, project_toml, result_file_path).await?;
+        let fs = process_test_niche(thundercloud_toml, project_toml).await?;
 
         // Then
-        let expected_result = indoc! {r#"
-            ---
-            sweeper: "Lobsang"
-            raising:
-              - "steam"
-              - "money"
-            # ==== BEGIN FRAGMENT glass-spring ====
-            ---
-            spring:
-              material: glass
-              delicate: true
-              number-of-coils: 17
-            raising:
-              - "expectations"
-            # ==== END FRAGMENT glass-spring ====
-              - "to the occasion"
+        assert_eq!(fs.get_content(to_absolute_path("/workshop/drawer/spring.yaml")).await?, "material: steel\n");
+        assert_eq!(fs.get_content(to_absolute_path("/workshop/drawer/box/key.yaml")).await?, "owner: {{sweeper}}\n");
+        Ok(())
+    }
+
+    #[test(tokio::test)]
+    async fn test_merge() -> Result<()> {
+        // Given
+        let thundercloud_toml = indoc! {r#"
+            [example-thundercloud]
+            "thundercloud.toml" = """
+            [niche]
+            name = "example"
+            """
+
+            [example-thundercloud.cumulus]
+            "clock+option.yaml" = '''
+            sweeper: Lu Tse
+            clock: ticking
+            hours: 12
+            '''
+            "mirror+option.yaml" = "frame: glass"
+            "spring+option.yaml" = "material: glass"
+            "bell+option.yaml" = "chime: true"
+        "#};
+        let project_toml = indoc! {r#"
+            "CargoCult.toml" = '''
+            [[psychotropic.cues]]
+            name = "example"
+            use-thundercloud = { directory = "{{PROJECT}}/example-thundercloud", invar-defaults = { merge = true } }
+            '''
+            "clock.yaml" = '''
+            sweeper: Lobsang
+            clock: ticking
+            hours: 10
+            '''
+            "mirror.yaml" = "frame: silver"
+            "spring.yaml" = "material: bronze"
+
+            [yeth-marthter.example.invar]
+
+            [yeth-marthter.example.baseline]
+            "clock.yaml" = '''
+            sweeper: Lu Tse
+            clock: ticking
+            hours: 10
+            '''
+            "mirror.yaml" = "frame: wood"
+            "spring.yaml" = "material: glass"
         "#};
-        assert_eq!(&result_body, expected_result);
 
+        // When
+        let fs = process_test_niche(thundercloud_toml, project_toml).await?;
+
+        // Then
+        assert_eq!(fs.get_content(to_absolute_path("/clock.yaml")).await?, "sweeper: Lobsang\nclock: ticking\nhours: 12\n");
+        assert_eq!(fs.get_content(to_absolute_path("/mirror.yaml")).await?, "<<<<<<< project\nframe: silver\n=======\nframe: glass\n>>>>>>> thundercloud\n");
+        assert_eq!(fs.get_content(to_absolute_path("/spring.yaml")).await?, "material: bronze\n");
+        assert_eq!(fs.get_content(to_absolute_path("/bell.yaml")).await?, "chime: true\n");
+        assert_eq!(fs.get_content(to_absolute_path("/yeth-marthter/example/baseline/clock.yaml")).await?, "sweeper: Lu Tse\nclock: ticking\nhours: 12\n");
+        assert_eq!(fs.get_content(to_absolute_path("/yeth-marthter/example/baseline/mirror.yaml")).await?, "frame: glass\n");
+        assert_eq!(fs.get_content(to_absolute_path("/yeth-marthter/example/baseline/bell.yaml")).await?, "chime: true\n");
         Ok(())
     }
 
     #[test(tokio::test)]
-    async fn test_config_without_extension() -> Result<()> {
+    async fn test_mode() -> Result<()> {
         // Given
         let thundercloud_toml = indoc! {r#"
             [example-thundercloud]
             "thundercloud.toml" = """
             [niche]
             name = "example"
-            description = "Example thundercloud for demonstration purposes"
+            """
 
-            [invar-defaults.props]
-            alter-ego = "Lobsang"
+            [example-thundercloud.cumulus]
+            "secret+option.yaml" = "key: 4288"
+            "clock+option.yaml" = "hours: 12"
+            "wind-up+option.sh" = "echo tick"
+            "wind-up+config-@.sh.toml" = 'mode = "0750"'
+        "#};
+        let project_toml = indoc! {r#"
+            "CargoCult.toml" = '''
+            [[psychotropic.cues]]
+            name = "example"
+            use-thundercloud = { directory = "{{PROJECT}}/example-thundercloud", invar-defaults = { mode = "0600" } }
+            '''
+            "clock.yaml" = "hours: 12"
+
+            [yeth-marthter.example.invar]
+        "#};
+
+        // When
+        let fs = process_test_niche(thundercloud_toml, project_toml).await?;
+
+        // Then
+        assert_eq!(fs.metadata(&to_absolute_path("/secret.yaml")).await?.mode(), 0o600);
+        assert_eq!(fs.metadata(&to_absolute_path("/clock.yaml")).await?.mode(), 0o600);
+        assert_eq!(fs.metadata(&to_absolute_path("/wind-up.sh")).await?.mode(), 0o750);
+        Ok(())
+    }
+
+    #[test(tokio::test)]
+    async fn test_executable_targets() -> Result<()> {
+        // Given
+        let thundercloud_toml = indoc! {r#"
+            [example-thundercloud]
+            "thundercloud.toml" = """
+            [niche]
+            name = "example"
+            """
+
+            [example-thundercloud.cumulus]
+            "wind-up+option.sh" = { content = "echo tick", mode = 0o755 }
+            "ring+option.sh" = { content = "echo ding", mode = 0o755 }
+            "clock+option.yaml" = "hours: 12"
+        "#};
+        let project_toml = indoc! {r#"
+            "CargoCult.toml" = '''
+            [[psychotropic.cues]]
+            name = "example"
+            use-thundercloud = { directory = "{{PROJECT}}/example-thundercloud" }
+            '''
+            "ring.sh" = "echo ding"
+            "clock.yaml" = { content = "hours: 10", mode = 0o755 }
+
+            [yeth-marthter.example.invar]
+        "#};
+
+        // When
+        let fs = process_test_niche(thundercloud_toml, project_toml).await?;
+
+        // Then
+        assert_eq!(fs.metadata(&to_absolute_path("/wind-up.sh")).await?.mode(), 0o755);
+        assert_eq!(fs.metadata(&to_absolute_path("/ring.sh")).await?.mode(), 0o755);
+        assert_eq!(fs.metadata(&to_absolute_path("/clock.yaml")).await?.mode(), 0o644);
+        Ok(())
+    }
+
+    #[test(tokio::test)]
+    async fn test_symlinks_in_thundercloud() -> Result<()> {
+        // Given
+        let thundercloud_toml = indoc! {r#"
+            [example-thundercloud]
+            "thundercloud.toml" = """
+            [niche]
+            name = "example"
             """
 
             [example-thundercloud.cumulus.workshop]
-            "x_x_x+option-kermie" = '''
-            Miss Piggy
-            Sweeper: {{sweeper}}
-            Alter ego: {{alter-ego}}
+            "clock+option-@.yaml" = '''
+            sweeper: Lu Tse
             '''
         "#};
         let project_toml = indoc! {r#"
             "CargoCult.toml" = '''
             [[psychotropic.cues]]
             name = "example"
-            use-thundercloud = { directory = "{{PROJECT}}/example-thundercloud", on-incoming = "Update", features = ["glass", "bash_config", "kermie"], invar-defaults = { props = { marthter = "Jeremy", buyer = "Myra LeJean", milk-man = "Kaos" } } }
+            use-thundercloud = { directory = "{{PROJECT}}/example-thundercloud" }
             '''
 
-            [yeth-marthter.example.invar.workshop]
-            "x_x_x+config-kermie.toml" = '''
-            [props]
+            [yeth-marthter.example.invar]
+        "#};
+        let thundercloud_fs = fixture::from_toml(thundercloud_toml)?;
+        let links = [
+            ("mirror", "workshop"),
+            ("clock-copy+option-@.yaml", "workshop/clock+option-@.yaml"),
+            ("dangling+option-@.yaml", "nowhere.yaml"),
+            ("workshop/here", "."),
+            ("workshop/back", ".."),
+        ];
+        for (link, link_target) in links {
+            let link_path = AbsolutePath::new(link, &to_absolute_path("/example-thundercloud/cumulus"));
+            thundercloud_fs.create_symlink(&link_path, Path::new(link_target), WriteMode::WriteNew).await?;
+        }
+
+        // When
+        let fs = process_test_niche_from(thundercloud_fs, project_toml).await?;
+
+        // Then
+        assert_eq!(fs.get_content(to_absolute_path("/workshop/clock.yaml")).await?, "sweeper: Lu Tse\n");
+        assert_eq!(fs.get_content(to_absolute_path("/mirror/clock.yaml")).await?, "sweeper: Lu Tse\n");
+        assert_eq!(fs.get_content(to_absolute_path("/clock-copy.yaml")).await?, "sweeper: Lu Tse\n");
+        assert_eq!(fs.path_type(&to_absolute_path("/dangling.yaml")).await, PathType::Missing);
+        assert_eq!(fs.path_type(&to_absolute_path("/workshop/here")).await, PathType::Missing);
+        assert_eq!(fs.path_type(&to_absolute_path("/mirror/back")).await, PathType::Missing);
+        Ok(())
+    }
+
+    #[cfg(feature = "tera")]
+    #[test(tokio::test)]
+    async fn test_tera_engine() -> Result<()> {
+        // Given
+        let thundercloud_toml = indoc! {r#"
+            [example-thundercloud]
+            "thundercloud.toml" = """
+            [niche]
+            name = "example"
+
+            [invar-defaults.props]
             sweeper = "Lu Tse"
+            parts = ["pendulum", "spring"]
+            """
+
+            [example-thundercloud.cumulus.workshop]
+            "clock+option-@.yaml" = """
+            sweeper: "{{ sweeper }}"
+            parts:
+            {%- for part in parts %}
+              - {{ part | upper }}
+            {%- endfor %}
+            """
+            "clock+config-@.yaml.toml" = """
+            engine = "tera"
+            """
+            "mirror+option-@.yaml" = """
+            sweeper: "{{sweeper}}"
+            """
+        "#};
+        let project_toml = indoc! {r#"
+            "CargoCult.toml" = '''
+            [[psychotropic.cues]]
+            name = "example"
+            use-thundercloud = { directory = "{{PROJECT}}/example-thundercloud" }
             '''
+
+            [yeth-marthter.example.invar]
         "#};
 
         // When
-        let result_file_path = to_absolute_path("/workshop/x_x");
-        let result_body = test_process_niche(thundercloud_toml, project_toml, result_file_path).await?;
+        let fs = process_test_niche(thundercloud_toml, project_toml).await?;
 
         // Then
-        let expected_result = indoc! {r#"
-            Miss Piggy
-            Sweeper: Lu Tse
-            Alter ego: Lobsang
+        let expected = indoc! {r#"
+            sweeper: "Lu Tse"
+            parts:
+              - PENDULUM
+              - SPRING
         "#};
-        assert_eq!(&result_body, expected_result);
+        assert_eq!(fs.get_content(to_absolute_path("/workshop/clock.yaml")).await?, expected);
+        assert_eq!(fs.get_content(to_absolute_path("/workshop/mirror.yaml")).await?, "sweeper: \"Lu Tse\"\n");
 
         Ok(())
     }
 
     async fn test_process_niche(thundercloud_toml: &str, project_toml: &str, result_file_path: AbsolutePath) -> Result<String> {
-        // Given
+        let fs = process_test_niche(thundercloud_toml, project_toml).await?;
+        fs.get_content(result_file_path).await
+    }
+
+    async fn process_test_niche(thundercloud_toml: &str, project_toml: &str) -> Result<impl FileSystem> {
         let thundercloud_fs = fixture::from_toml(thundercloud_toml)?;
+        process_test_niche_from(thundercloud_fs, project_toml).await
+    }
+
+    async fn process_test_niche_from<TFS: FileSystem + 'static>(thundercloud_fs: TFS, project_toml: &str) -> Result<impl FileSystem> {
+        process_test_niche_with(thundercloud_fs, project_toml, RunOptions::default(), FunctionRegistry::default()).await
+    }
+
+    async fn process_test_niche_with<TFS: FileSystem + 'static>(thundercloud_fs: TFS, project_toml: &str, run_options: RunOptions, functions: FunctionRegistry) -> Result<impl FileSystem> {
+        // Given
         let project_fs = fixture::from_toml(project_toml)?;
         let project_config = create_project_config(project_fs.clone()).await?;
         let niche_triggers = get_niche_triggers(&project_config)?;
-        let default_invar_config = niche_triggers.use_thundercloud().unwrap().invar_defaults().into_owned();
+        let use_thundercloud = niche_triggers.use_thundercloud().unwrap().with_shared_targets(niche_triggers.shared_targets().clone());
+        let default_invar_config = use_thundercloud.invar_defaults().into_owned();
         let project_root = AbsolutePath::root();
         let thundercloud_directory = to_absolute_path("/example-thundercloud");
         let invar_directory = to_absolute_path("/yeth-marthter/example/invar");
-        let thunder_config = niche_triggers.use_thundercloud().unwrap().new_thunder_config(default_invar_config, thundercloud_fs.clone(), thundercloud_directory.clone(), project_fs.clone(), invar_directory.clone(), project_root.clone());
-        let generation_context = GenerationContext(thunder_config);
+        let thunder_config = use_thundercloud.new_thunder_config(default_invar_config, thundercloud_fs.clone(), thundercloud_directory.clone(), project_fs.clone(), invar_directory.clone(), project_root.clone());
+        let generation_context = GenerationContext::new(thunder_config, NicheReporter::default()).with_run_options(run_options).with_functions(functions);
 
         // When
         let result = process_niche_in_context(&generation_context).await;
@@ -910,9 +5173,7 @@ mod test {
         // Then
         result?;
 
-        let fs = generation_context.0.project_file_system();
-
-        fs.get_content(result_file_path).await
+        Ok(project_fs)
     }
 
     async fn create_project_config<FS: FileSystem>(fs: FS) -> Result<impl ProjectConfig> {
@@ -0,0 +1,225 @@
+use std::path::PathBuf;
+use anyhow::{bail, Result};
+use chrono::Local;
+use log::info;
+use crate::config_model::{NicheTriggers, ProjectConfig, PsychotropicConfig};
+use crate::file_system::FileSystem;
+use crate::{application, fetch, get_project_config, get_project_root, git, FunctionRegistry, RunOptions};
+
+const COMMIT_MESSAGE: &str = "Update files generated from thunderclouds";
+
+/// Creates a new branch, refreshes the thunderclouds (see `igor fetch`), applies the niches and commits the changes on
+/// the branch. Prints a summary for the description of a pull request. If fetching or applying fails, the changes are
+/// thrown away and the original branch is checked out again, so that the user is not left on a half-updated branch.
+pub async fn update<FS: FileSystem + 'static>(project_root_option: Option<PathBuf>, jobs_option: Option<usize>, fail_fast: bool, branch_option: Option<String>, run_options: &RunOptions, fs: &FS) -> Result<()> {
+    let project_root = get_project_root(project_root_option)?;
+    let git_run_options = run_options.clone().with_allowed_hook_commands(&get_project_config(&project_root, fs).await?);
+    let allowed_commands = git_run_options.allowed_commands();
+    if !git::is_clean(allowed_commands, &project_root).await? {
+        bail!("Working tree has uncommitted changes: commit or stash them before updating: {project_root:?}");
+    }
+    let branch = branch_option.unwrap_or_else(default_branch);
+    let base = git::head_revision(allowed_commands, &project_root).await?;
+    let original = git::current_branch(allowed_commands, &project_root).await?.unwrap_or_else(|| base.clone());
+    info!("Update on branch: {branch:?} (from {original} at {base})");
+    git::create_branch(allowed_commands, &project_root, &branch).await?;
+
+    let updated = async {
+        let fetched = fetch::fetch_thunderclouds(&project_root, run_options, fs).await?;
+        application(Some(project_root.to_path_buf()), jobs_option, fail_fast, run_options, &FunctionRegistry::default(), fs).await?;
+        Ok::<_, anyhow::Error>(fetched)
+    }.await;
+    let fetched = match updated {
+        Ok(fetched) => fetched,
+        Err(error) => {
+            if let Err(abandon_error) = git::abandon_branch(allowed_commands, &project_root, &branch, &original).await {
+                bail!("Update failed: {error:#}; the changes are left uncommitted on branch {branch}, because {original} could not be restored: {abandon_error:#}");
+            }
+            bail!("Update failed, so branch {branch} was removed and {original} is checked out again: {error:#}");
+        }
+    };
+
+    if !git::commit_all(allowed_commands, &project_root, COMMIT_MESSAGE).await? {
+        println!("No changes: nothing to commit on branch {branch}");
+        return Ok(());
+    }
+    let changes = git::changed_files(allowed_commands, &project_root, &base, "HEAD").await?;
+    let project_config = get_project_config(&project_root, fs).await?;
+    let niches = niche_names(&project_config)?;
+    println!("{}", summary(&branch, &niches, &fetched, &changes));
+    Ok(())
+}
+
+fn default_branch() -> String {
+    format!("igor/update-{}", Local::now().format("%Y-%m-%d"))
+}
+
+fn niche_names<PC: ProjectConfig>(project_config: &PC) -> Result<Vec<String>> {
+    let psychotropic = project_config.psychotropic()?;
+    let mut names: Vec<String> = psychotropic.values().iter()
+        .filter(|triggers| triggers.use_thundercloud().is_some() || triggers.use_thundercloud_path().is_some())
+        .map(NicheTriggers::name)
+        .collect();
+    names.sort();
+    Ok(names)
+}
+
+fn summary(branch: &str, niches: &[String], fetched: &[(String, String)], changes: &[String]) -> String {
+    let mut lines = Vec::new();
+    lines.push(format!("## {COMMIT_MESSAGE}"));
+    lines.push("".to_string());
+    lines.push(format!("Generated by `igor update` on branch `{branch}`."));
+    lines.push("".to_string());
+    lines.push("### Niches".to_string());
+    lines.push("".to_string());
+    for niche in niches {
+        match fetched.iter().find(|(fetched_niche, _)| fetched_niche == niche) {
+            Some((_, revision)) => lines.push(format!("- {niche} (thundercloud at `{revision}`)")),
+            None => lines.push(format!("- {niche}")),
+        }
+    }
+    lines.push("".to_string());
+    lines.push(format!("### Changed files ({})", changes.len()));
+    lines.push("".to_string());
+    for change in changes {
+        let (status, path) = change.split_once('\t').unwrap_or(("?", change));
+        lines.push(format!("- `{status}` {path}"));
+    }
+    lines.join("\n")
+}
+
+#[cfg(test)]
+mod test {
+    use assert_fs::TempDir;
+    use indoc::{formatdoc, indoc};
+    use tokio::fs::{create_dir_all, read_to_string, write};
+    use crate::file_system::real_file_system;
+    use crate::git::git;
+    use crate::git::test_utils::init_repository;
+    use crate::hooks::AllowedCommands;
+    use super::*;
+
+    #[test]
+    fn test_summary() {
+        // Given
+        let niches = vec!["example".to_string(), "local".to_string()];
+        let fetched = vec![("example".to_string(), "5bb4b95445dd".to_string())];
+        let changes = vec!["A\tworkshop/clock.yaml".to_string(), "M\tREADME.md".to_string()];
+
+        // When
+        let result = summary("igor/update-2024-10-21", &niches, &fetched, &changes);
+
+        // Then
+        let expected = indoc! {r#"
+            ## Update files generated from thunderclouds
+
+            Generated by `igor update` on branch `igor/update-2024-10-21`.
+
+            ### Niches
+
+            - example (thundercloud at `5bb4b95445dd`)
+            - local
+
+            ### Changed files (2)
+
+            - `A` workshop/clock.yaml
+            - `M` README.md"#};
+        assert_eq!(&result, expected);
+    }
+
+    #[tokio::test]
+    async fn test_update() -> Result<()> {
+        // Given
+        let (_tmp_dir, project_root) = init_repository().await?;
+        let (_remote_dir, remote) = init_repository().await?;
+        let cache_dir = TempDir::new()?;
+        let allowed_commands = AllowedCommands::default();
+        create_dir_all(remote.join("cumulus")).await?;
+        write(remote.join("thundercloud.toml"), "[niche]\nname = \"example\"\n").await?;
+        write(remote.join("cumulus/hello+option-@.txt"), "Hello\n").await?;
+        git::commit_all(&allowed_commands, &remote, "Initial").await?;
+        let cargo_cult_toml = formatdoc! {r#"
+            thundercloud-cache = "{}"
+
+            [[psychotropic.cues]]
+            name = "example"
+            use-thundercloud = {{ git-remote = {{ fetch-url = "file://{}", revision = "main" }} }}
+        "#, cache_dir.path().to_string_lossy(), remote.to_string_lossy()};
+        write(project_root.join("CargoCult.toml"), cargo_cult_toml).await?;
+        create_dir_all(project_root.join("yeth-marthter/example/invar")).await?;
+        write(project_root.join("yeth-marthter/example/invar/hello+config.txt.toml"), "write-mode = \"Overwrite\"\n").await?;
+        git(&allowed_commands, &project_root, &["add", "--all"]).await?;
+        git(&allowed_commands, &project_root, &["commit", "--quiet", "--message", "Initial"]).await?;
+        let fs = real_file_system();
+
+        // When
+        update(Some(project_root.to_path_buf()), Some(1), false, Some("igor/update-test".to_string()), &RunOptions::default(), &fs).await?;
+        let first_content = read_to_string(project_root.join("hello.txt")).await?;
+        write(remote.join("cumulus/hello+option-@.txt"), "Hello, world\n").await?;
+        git::commit_all(&allowed_commands, &remote, "Greet the world").await?;
+        update(Some(project_root.to_path_buf()), Some(1), false, Some("igor/update-again".to_string()), &RunOptions::default(), &fs).await?;
+
+        // Then
+        assert_eq!(&first_content, "Hello\n");
+        let branch = git(&allowed_commands, &project_root, &["branch", "--show-current"]).await?;
+        assert_eq!(branch.trim(), "igor/update-again");
+        assert!(git::is_clean(&allowed_commands, &project_root).await?);
+        let content = read_to_string(project_root.join("hello.txt")).await?;
+        assert_eq!(&content, "Hello, world\n");
+        let message = git(&allowed_commands, &project_root, &["log", "-1", "--format=%s"]).await?;
+        assert_eq!(message.trim(), COMMIT_MESSAGE);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_update_failure() -> Result<()> {
+        // Given
+        let (_tmp_dir, project_root) = init_repository().await?;
+        let cache_dir = TempDir::new()?;
+        let allowed_commands = AllowedCommands::default();
+        let cargo_cult_toml = formatdoc! {r#"
+            thundercloud-cache = "{}"
+
+            [[psychotropic.cues]]
+            name = "example"
+            use-thundercloud = {{ git-remote = {{ fetch-url = "file://{}/missing", revision = "main" }} }}
+        "#, cache_dir.path().to_string_lossy(), cache_dir.path().to_string_lossy()};
+        write(project_root.join("CargoCult.toml"), cargo_cult_toml).await?;
+        git(&allowed_commands, &project_root, &["add", "--all"]).await?;
+        git(&allowed_commands, &project_root, &["commit", "--quiet", "--message", "Initial"]).await?;
+        let fs = real_file_system();
+
+        // When
+        let result = update(Some(project_root.to_path_buf()), Some(1), false, Some("igor/update-test".to_string()), &RunOptions::default(), &fs).await;
+
+        // Then
+        let error = result.unwrap_err().to_string();
+        assert!(error.starts_with("Update failed, so branch igor/update-test was removed and main is checked out again"), "Actual: {error}");
+        let branch = git(&allowed_commands, &project_root, &["branch", "--show-current"]).await?;
+        assert_eq!(branch.trim(), "main");
+        let branches = git(&allowed_commands, &project_root, &["branch", "--list", "igor/*"]).await?;
+        assert_eq!(branches.trim(), "");
+        assert!(git::is_clean(&allowed_commands, &project_root).await?);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_update_without_hooks() -> Result<()> {
+        // Given
+        let (_tmp_dir, project_root) = init_repository().await?;
+        let allowed_commands = AllowedCommands::default();
+        write(project_root.join("CargoCult.toml"), "").await?;
+        git(&allowed_commands, &project_root, &["add", "--all"]).await?;
+        git(&allowed_commands, &project_root, &["commit", "--quiet", "--message", "Initial"]).await?;
+        let fs = real_file_system();
+
+        // When
+        let result = update(Some(project_root.to_path_buf()), Some(1), false, Some("igor/update-test".to_string()), &RunOptions::default().with_no_hooks(true), &fs).await;
+
+        // Then
+        assert_eq!(result.unwrap_err().to_string(), r#"Commands are disabled (--no-hooks): "git""#);
+        let branch = git(&allowed_commands, &project_root, &["branch", "--show-current"]).await?;
+        assert_eq!(branch.trim(), "main");
+        Ok(())
+    }
+}
@@ -0,0 +1,224 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use anyhow::{anyhow, bail, Result};
+use minisign_verify::{PublicKey, Signature};
+use serde::Deserialize;
+use crate::config_model::{NetworkAccess, ProjectConfig};
+use crate::download::{curl, verify_checksum};
+use crate::file_system::FileSystem;
+use crate::run_options::RunOptions;
+use crate::{get_project_config, get_project_root};
+
+/// The release feed that `igor upgrade` checks unless another feed is given. The release workflow
+/// (`.github/workflows/release.yml`) publishes it with every release.
+const DEFAULT_FEED: &str = "https://github.com/rustigaan/igor/releases/latest/download/release.json";
+
+/// The minisign public key that the release workflow signs the binaries of releases with. The workflow builds the
+/// binaries with the key in environment variable `IGOR_RELEASE_PUBLIC_KEY`; other builds have no key unless they set it.
+/// The signature of a binary is published next to it, with the extension `.minisig`.
+const RELEASE_PUBLIC_KEY: Option<&str> = option_env!("IGOR_RELEASE_PUBLIC_KEY");
+
+/// Describes the latest release: its version and a static binary with its SHA-256 checksum per platform (like
+/// `x86_64-linux`).
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+struct ReleaseFeed {
+    version: String,
+    assets: BTreeMap<String, ReleaseAsset>,
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+struct ReleaseAsset {
+    url: String,
+    sha256: String,
+}
+
+/// Replaces the running executable with the static binary of the latest release for this platform, if that release is
+/// newer. With `check`, only reports whether there is a newer release. The binary must have the checksum of the feed and
+/// a valid signature of the public key (given or built in). Fails without contacting the feed
+/// if network access is forbidden (by the project configuration or by `--offline`), and if the release does not meet the
+/// `requires-igor` version of the project.
+pub async fn upgrade<FS: FileSystem>(project_root_option: Option<PathBuf>, feed_option: Option<String>, public_key_option: Option<String>, check: bool, run_options: &RunOptions, fs: &FS) -> Result<()> {
+    let project_root = get_project_root(project_root_option)?;
+    let project_config = get_project_config(&project_root, fs).await?;
+    let run_options = run_options.clone().with_allowed_hook_commands(&project_config);
+    let allowed_commands = run_options.allowed_commands();
+    if run_options.offline() || project_config.network() == NetworkAccess::Forbidden {
+        bail!("Network access is forbidden: cannot check for a newer release");
+    }
+    let configured_feed = feed_option.is_none() && project_config.release_feed().is_some();
+    let feed_url = feed_option.or(project_config.release_feed().map(str::to_string)).unwrap_or_else(|| DEFAULT_FEED.to_string());
+    let feed: ReleaseFeed = serde_json::from_slice(&curl(&feed_url, allowed_commands).await?)
+        .map_err(|error| anyhow!("Invalid release feed: {feed_url}: {error}"))?;
+    if let Some(required) = project_config.requires_igor().filter(|required| !meets(&feed.version, required).unwrap_or(false)) {
+        bail!("The latest release (Igor {}) does not meet requires-igor = {required:?} of the project", feed.version);
+    }
+    let current = env!("CARGO_PKG_VERSION");
+    if !is_newer(&feed.version, current)? {
+        println!("Igor {current} is up to date");
+        return Ok(());
+    }
+    if check {
+        println!("Igor {} is available (this is {current})", feed.version);
+        return Ok(());
+    }
+    let public_key = select_public_key(public_key_option.as_deref(), RELEASE_PUBLIC_KEY, configured_feed)?;
+    let asset = select_asset(&feed, &platform())?;
+    let binary = curl(&asset.url, allowed_commands).await?;
+    verify_checksum(&binary, &asset.sha256).map_err(|error| anyhow!("{}: {error}", asset.url))?;
+    let signature_url = format!("{}.minisig", asset.url);
+    let signature = String::from_utf8(curl(&signature_url, allowed_commands).await?).map_err(|_| anyhow!("Invalid signature: {signature_url}"))?;
+    verify_signature(&binary, &signature, public_key).map_err(|error| anyhow!("{}: {error}", asset.url))?;
+    replace_executable(&std::env::current_exe()?, &binary).await?;
+    println!("Upgraded Igor {current} to {}", feed.version);
+    Ok(())
+}
+
+/// The platform of this executable, like `x86_64-linux` or `aarch64-macos`.
+fn platform() -> String {
+    format!("{}-{}", std::env::consts::ARCH, std::env::consts::OS)
+}
+
+/// Fails unless this version of Igor meets the `requires-igor` version of the project (if any).
+pub fn check_requires_igor(required: Option<&str>) -> Result<()> {
+    let current = env!("CARGO_PKG_VERSION");
+    match required {
+        Some(required) if !meets(current, required)? => bail!("The project requires Igor {required} or newer (this is {current}); run igor upgrade"),
+        _ => Ok(()),
+    }
+}
+
+/// Whether a version is the required version or newer.
+fn meets(version: &str, required: &str) -> Result<bool> {
+    Ok(!is_newer(required, version)?)
+}
+
+/// The public key that was given takes precedence over the key that Igor was built with. The `release-feed` of the
+/// project is only verified with the built-in key, because a project (like a repository that was just cloned) must not
+/// choose both the binary and the key that it is verified with.
+fn select_public_key<'a>(given: Option<&'a str>, built_in: Option<&'a str>, configured_feed: bool) -> Result<&'a str> {
+    match given {
+        Some(_) if configured_feed => bail!("--public-key only applies to a feed that is given with --feed, not to the release-feed in CargoCult.toml"),
+        Some(given) => Ok(given),
+        None => built_in.ok_or_else(|| anyhow!("No public key to verify releases with: use --feed with --public-key, or use a release build of Igor")),
+    }
+}
+
+fn select_asset<'a>(feed: &'a ReleaseFeed, platform: &str) -> Result<&'a ReleaseAsset> {
+    feed.assets.get(platform).ok_or_else(|| anyhow!("Igor {} has no binary for platform {platform}", feed.version))
+}
+
+/// Compares versions like `0.10.2` by their numerical components. A pre-release suffix (like `-rc1`) is ignored.
+fn is_newer(candidate: &str, current: &str) -> Result<bool> {
+    let components = |version: &str| -> Result<Vec<u64>> {
+        let release = version.trim_start_matches('v').split(['-', '+']).next().unwrap_or_default();
+        release.split('.').map(|component| component.parse::<u64>().map_err(|_| anyhow!("Invalid version: {version:?}"))).collect()
+    };
+    Ok(components(candidate)? > components(current)?)
+}
+
+/// Fails unless the minisign signature of the content was made with the secret key of the public key.
+fn verify_signature(content: &[u8], signature: &str, public_key: &str) -> Result<()> {
+    let public_key = PublicKey::from_base64(public_key).map_err(|error| anyhow!("Invalid public key: {error}"))?;
+    let signature = Signature::decode(signature).map_err(|error| anyhow!("Invalid signature: {error}"))?;
+    public_key.verify(content, &signature, false).map_err(|error| anyhow!("Signature mismatch: {error}"))
+}
+
+/// Writes the new binary next to the executable and renames it over the executable, so that the executable is never
+/// half-written.
+async fn replace_executable(executable: &Path, binary: &[u8]) -> Result<()> {
+    let mut staging = executable.as_os_str().to_owned();
+    staging.push(".new");
+    tokio::fs::write(&staging, binary).await?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        tokio::fs::set_permissions(&staging, std::fs::Permissions::from_mode(0o755)).await?;
+    }
+    tokio::fs::rename(&staging, executable).await
+        .map_err(|error| anyhow!("Cannot replace {:?}: {error}", executable))
+}
+
+#[cfg(test)]
+mod test {
+    use serde_json::json;
+    use super::*;
+
+    #[test]
+    fn select_newer_release() -> Result<()> {
+        // Given
+        let feed: ReleaseFeed = serde_json::from_value(json!({
+            "version": "0.10.0",
+            "assets": {
+                "x86_64-linux": { "url": "https://example.com/igor-x86_64-linux", "sha256": "0123" }
+            }
+        }))?;
+
+        // When
+        let asset = select_asset(&feed, "x86_64-linux")?;
+        let missing = select_asset(&feed, "riscv64-linux");
+
+        // Then
+        assert_eq!(asset.url, "https://example.com/igor-x86_64-linux");
+        assert_eq!(missing.unwrap_err().to_string(), "Igor 0.10.0 has no binary for platform riscv64-linux");
+        assert!(is_newer(&feed.version, "0.9.3")?);
+        assert!(!is_newer(&feed.version, "0.10.0")?);
+        assert!(!is_newer("v0.10.0-rc1", "0.10.0")?);
+        assert!(is_newer("nightly", "0.1.0").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn requires_igor() -> Result<()> {
+        // When
+        let older = check_requires_igor(Some("0.0.9"));
+        let newer = check_requires_igor(Some("99.0"));
+
+        // Then
+        assert!(older.is_ok());
+        assert!(check_requires_igor(None).is_ok());
+        assert_eq!(newer.unwrap_err().to_string(), format!("The project requires Igor 99.0 or newer (this is {}); run igor upgrade", env!("CARGO_PKG_VERSION")));
+        assert!(meets("0.10.0", "0.10")?);
+        assert!(!meets("0.9.3", "0.10.0")?);
+        Ok(())
+    }
+
+    #[test]
+    fn public_key_precedence() -> Result<()> {
+        // When
+        let given = select_public_key(Some("given"), Some("built-in"), false)?;
+        let built_in = select_public_key(None, Some("built-in"), false)?;
+        let configured_feed = select_public_key(None, Some("built-in"), true)?;
+        let given_for_configured_feed = select_public_key(Some("given"), Some("built-in"), true);
+        let missing = select_public_key(None, None, false);
+        let missing_for_configured_feed = select_public_key(None, None, true);
+
+        // Then
+        assert_eq!((given, built_in, configured_feed), ("given", "built-in", "built-in"));
+        assert!(given_for_configured_feed.unwrap_err().to_string().starts_with("--public-key only applies to a feed that is given with --feed"));
+        assert!(missing.unwrap_err().to_string().starts_with("No public key to verify releases with"));
+        assert!(missing_for_configured_feed.is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn signature() {
+        // Given
+        let public_key = "RWSMurcnf4ZmqDka82IFjuKOtgUVgiD+92dJLD+q72XCCpIkdA3UTSFA";
+        let signature = concat!(
+            "untrusted comment: signature from minisign secret key\n",
+            "RUSMurcnf4ZmqCy8a4FdQZa+Z9SUSSiXb0Kc0gF2GZFtafaQLwL1ie8mEhguMpNKEgvTqL6j2JdMzSsm73yaqYmm4XgemLPD4AQ=\n",
+            "trusted comment: timestamp:1729468800\tfile:igor\n",
+            "L3IAFi/ckE8F+jvrZtFuCC/exwUUzg6XAqJnuzynijG4nt+VtxfS4Nu4b8foK4Ikp4yFaPQvQqHBKnegNXVnBQ==\n",
+        );
+
+        // When
+        let valid = verify_signature(b"Igor", signature, public_key);
+        let tampered = verify_signature(b"Egor", signature, public_key);
+        let other_key = verify_signature(b"Igor", signature, "RWQ4vLCHQr3VxHwZaQET+rKlGDHZZNdIYYBnaKlugDFGm22eczO7IKD+");
+
+        // Then
+        assert!(valid.is_ok(), "Actual: {valid:?}");
+        assert!(tampered.unwrap_err().to_string().starts_with("Signature mismatch"));
+        assert!(other_key.is_err());
+    }
+}
@@ -0,0 +1,237 @@
+use std::path::PathBuf;
+use std::pin::pin;
+use anyhow::{anyhow, bail, Result};
+use log::{debug, info};
+use tokio_stream::StreamExt;
+use toml_edit::{value, DocumentMut, Item};
+use crate::config_model::document::ConfigDocument;
+use crate::config_model::{use_thundercloud_config, UseThundercloudConfig, WriteMode};
+use crate::file_system::{copy_chunks, DirEntry, FileSystem, PathType, TargetFile};
+use crate::niche::{get_thundercloud_directory, UseThundercloudDefaults};
+use crate::path::AbsolutePath;
+use crate::{get_project_config, get_project_root};
+
+/// The directory (relative to the project root) that holds the vendored thunderclouds.
+pub const VENDOR_DIRECTORY: &str = "vendor/thunderclouds";
+
+pub async fn vendor<FS: FileSystem>(project_root_option: Option<PathBuf>, fs: &FS) -> Result<()> {
+    let project_root = get_project_root(project_root_option)?;
+    for (niche, directory) in vendor_thunderclouds(&project_root, fs).await? {
+        println!("Vendored {niche}: {}", directory.to_string_lossy());
+    }
+    Ok(())
+}
+
+/// Copies the thundercloud of every niche that has a git remote into `vendor/thunderclouds/NICHE` and rewrites its
+/// `use-thundercloud` configuration (in `CargoCult.toml` or in the external file that it refers to) to use the vendored
+/// directory without a git remote. The thunderclouds must have been fetched before. Returns the names of the niches with
+/// their vendored directories.
+async fn vendor_thunderclouds<FS: FileSystem>(project_root: &AbsolutePath, fs: &FS) -> Result<Vec<(String, AbsolutePath)>> {
+    let project_config_path = AbsolutePath::new("CargoCult.toml", project_root);
+    if fs.path_type(&project_config_path).await != PathType::File {
+        return Ok(Vec::new());
+    }
+    let mut project_document = ConfigDocument::parse(&fs.get_content(project_config_path.clone()).await?)
+        .map_err(|error| anyhow!("{}:{error}", project_config_path.to_string_lossy()))?;
+    let use_thundercloud_defaults = UseThundercloudDefaults::new(project_root, &get_project_config(project_root, fs).await?);
+    let mut project_document_changed = false;
+    let mut vendored = Vec::new();
+    for index in 0.. {
+        let cue_path = ["psychotropic", "cues", &index.to_string()];
+        let Some(cue) = project_document.get(&cue_path) else { break };
+        let Some(niche) = cue.get("name").and_then(Item::as_str).map(str::to_string) else { continue };
+        let vendor_directory = AbsolutePath::new(format!("{VENDOR_DIRECTORY}/{niche}"), project_root);
+        let directory_setting = format!("{{{{PROJECT}}}}/{VENDOR_DIRECTORY}/{niche}");
+        match cue.get("use-thundercloud") {
+            Some(item) if item.is_table_like() => {
+                let Ok(table) = item.clone().into_table() else { continue };
+                let Some(use_thundercloud) = git_remote_use_thundercloud(&DocumentMut::from(table).to_string())? else { continue };
+                vendor_thundercloud(project_root, &niche, &use_thundercloud_defaults.apply(&use_thundercloud), &vendor_directory, fs).await?;
+                let use_thundercloud_path = [cue_path.as_slice(), &["use-thundercloud"]].concat();
+                rewrite_use_thundercloud(&mut project_document, &use_thundercloud_path, &directory_setting)?;
+                project_document_changed = true;
+            },
+            Some(item) => {
+                let Some(path) = item.as_str() else { continue };
+                let path = AbsolutePath::new(path, project_root);
+                let source = fs.get_content(path.clone()).await?;
+                let Some(use_thundercloud) = git_remote_use_thundercloud(&source)? else { continue };
+                vendor_thundercloud(project_root, &niche, &use_thundercloud_defaults.apply(&use_thundercloud), &vendor_directory, fs).await?;
+                let mut document = ConfigDocument::parse(&source)
+                    .map_err(|error| anyhow!("{}:{error}", path.to_string_lossy()))?;
+                rewrite_use_thundercloud(&mut document, &[], &directory_setting)?;
+                write_content(&path, &document.to_string(), fs).await?;
+            },
+            None => continue,
+        }
+        vendored.push((niche, vendor_directory));
+    }
+    if project_document_changed {
+        write_content(&project_config_path, &project_document.to_string(), fs).await?;
+    }
+    Ok(vendored)
+}
+
+fn git_remote_use_thundercloud(body: &str) -> Result<Option<impl UseThundercloudConfig>> {
+    let use_thundercloud = use_thundercloud_config::from_str(body)?;
+    Ok(use_thundercloud.git_remote().is_some().then_some(use_thundercloud))
+}
+
+async fn vendor_thundercloud<FS: FileSystem, UT: UseThundercloudConfig>(project_root: &AbsolutePath, niche: &str, use_thundercloud: &UT, vendor_directory: &AbsolutePath, fs: &FS) -> Result<()> {
+    let Some(directory) = get_thundercloud_directory(project_root, use_thundercloud)? else {
+        bail!("Niche has a git remote, but no thundercloud directory: {niche:?}");
+    };
+    if fs.path_type(&directory).await != PathType::Directory {
+        bail!("Thundercloud of niche {niche:?} was not fetched (run igor fetch first): {:?}", directory);
+    }
+    info!("Vendor: {:?} ⇒ {:?}", &directory, vendor_directory);
+    copy_directory(&directory, vendor_directory, fs).await
+}
+
+/// Copies the files in a directory and its subdirectories, except the `.git` directory.
+async fn copy_directory<FS: FileSystem>(source_directory: &AbsolutePath, target_directory: &AbsolutePath, fs: &FS) -> Result<()> {
+    let mut pending = vec![(source_directory.clone(), target_directory.clone())];
+    while let Some((source, target)) = pending.pop() {
+        let entries = fs.read_dir(&source).await?;
+        let mut entries = pin!(entries);
+        while let Some(entry) = entries.next().await {
+            let entry = entry?;
+            let file_name = entry.file_name();
+            if file_name == ".git" {
+                continue;
+            }
+            let source_path = AbsolutePath::new(&file_name, &source);
+            let target_path = AbsolutePath::new(&file_name, &target);
+            if entry.is_dir().await? {
+                pending.push((source_path, target_path));
+                continue;
+            }
+            debug!("Copy: {:?} ⇒ {:?}", &source_path, &target_path);
+            let executable = fs.metadata(&source_path).await?.mode() & 0o111 != 0;
+            let source_file = fs.open_source(source_path).await?;
+            let mut target_file = fs.open_target(target_path.clone(), WriteMode::Overwrite, executable).await?
+                .ok_or_else(|| anyhow!("Could not write: {:?}", &target_path))?;
+            let copied = copy_chunks(source_file, &target_file).await;
+            let closed = target_file.close().await;
+            copied.and(closed)?;
+        }
+    }
+    Ok(())
+}
+
+/// Points the `use-thundercloud` table at the given path to the vendored directory and removes its git remote.
+fn rewrite_use_thundercloud(document: &mut ConfigDocument, use_thundercloud_path: &[&str], directory: &str) -> Result<()> {
+    document.set(&[use_thundercloud_path, &["directory"]].concat(), value(directory))?;
+    document.remove(&[use_thundercloud_path, &["git-remote"]].concat());
+    if let Some(mut inline_table) = document.get(use_thundercloud_path).and_then(Item::as_inline_table).cloned() {
+        inline_table.fmt();
+        document.set(use_thundercloud_path, Item::Value(inline_table.into()))?;
+    }
+    Ok(())
+}
+
+/// Writes a file line by line, replacing the file if it exists.
+pub async fn write_content<FS: FileSystem>(path: &AbsolutePath, content: &str, fs: &FS) -> Result<()> {
+    let mut target_file = fs.open_target(path.clone(), WriteMode::Overwrite, false).await?
+        .ok_or_else(|| anyhow!("Could not write: {:?}", path))?;
+    for line in content.lines() {
+        target_file.write_line(line).await?;
+    }
+    target_file.close().await
+}
+
+#[cfg(test)]
+mod test {
+    use indoc::indoc;
+    use test_log::test;
+    use crate::file_system::fixture;
+    use crate::path::test_utils::to_absolute_path;
+    use super::*;
+
+    #[test(tokio::test)]
+    async fn vendor_inline_and_external() -> Result<()> {
+        // Given
+        let toml_data = indoc! {r#"
+            "CargoCult.toml" = '''
+            [[psychotropic.cues]]
+            name = "example"   # Clock parts
+            use-thundercloud = { directory = "/example-thundercloud", git-remote = { fetch-url = "https://github.com/rustigaan/example-thundercloud.git", revision = "main" } }
+
+            [[psychotropic.cues]]
+            name = "external"
+            use-thundercloud = "yeth-marthter/external/igor-thettingth.toml"
+
+            [[psychotropic.cues]]
+            name = "local"
+            use-thundercloud = { directory = "/local-thundercloud" }
+            '''
+
+            [yeth-marthter.external]
+            "igor-thettingth.toml" = '''
+            directory = "/external-thundercloud"
+            git-remote = { fetch-url = "https://github.com/rustigaan/external-thundercloud.git", revision = "v1" }
+            features = ["glass"]
+            '''
+
+            [example-thundercloud]
+            "thundercloud.toml" = '''
+            [niche]
+            name = "example"
+            '''
+
+            [example-thundercloud.".git"]
+            HEAD = "ref: refs/heads/main"
+
+            [example-thundercloud.cumulus.workshop]
+            "clock+option-@.yaml" = "sweeper: {{sweeper}}"
+
+            [external-thundercloud]
+            "thundercloud.toml" = '''
+            [niche]
+            name = "external"
+            '''
+        "#};
+        let fs = fixture::from_toml(toml_data)?;
+        let project_root = AbsolutePath::root();
+
+        // When
+        let vendored = vendor_thunderclouds(&project_root, &fs).await?;
+
+        // Then
+        let niches: Vec<_> = vendored.iter().map(|(niche, _)| niche.as_str()).collect();
+        assert_eq!(niches, vec!["example", "external"]);
+        let option = fs.get_content(to_absolute_path("/vendor/thunderclouds/example/cumulus/workshop/clock+option-@.yaml")).await?;
+        assert_eq!(option, "sweeper: {{sweeper}}\n");
+        assert_eq!(fs.path_type(&to_absolute_path("/vendor/thunderclouds/example/.git")).await, PathType::Missing);
+        assert_eq!(fs.path_type(&to_absolute_path("/vendor/thunderclouds/external/thundercloud.toml")).await, PathType::File);
+
+        let project_config = fs.get_content(to_absolute_path("/CargoCult.toml")).await?;
+        assert!(project_config.contains(r#"name = "example"   # Clock parts"#));
+        assert!(project_config.contains(r#"use-thundercloud = { directory = "{{PROJECT}}/vendor/thunderclouds/example" }"#), "Actual: {project_config}");
+        assert!(project_config.contains(r#"use-thundercloud = { directory = "/local-thundercloud" }"#));
+        let external = fs.get_content(to_absolute_path("/yeth-marthter/external/igor-thettingth.toml")).await?;
+        assert_eq!(external, "directory = \"{{PROJECT}}/vendor/thunderclouds/external\"\nfeatures = [\"glass\"]\n");
+        Ok(())
+    }
+
+    #[test(tokio::test)]
+    async fn vendor_requires_fetch() -> Result<()> {
+        // Given
+        let toml_data = indoc! {r#"
+            "CargoCult.toml" = '''
+            [[psychotropic.cues]]
+            name = "example"
+            use-thundercloud = { directory = "/example-thundercloud", git-remote = { fetch-url = "https://github.com/rustigaan/example-thundercloud.git", revision = "main" } }
+            '''
+        "#};
+        let fs = fixture::from_toml(toml_data)?;
+
+        // When
+        let result = vendor_thunderclouds(&AbsolutePath::root(), &fs).await;
+
+        // Then
+        let Err(error) = result else { panic!("Expected an error") };
+        assert!(error.to_string().contains("run igor fetch first"), "Actual error: {error}");
+        Ok(())
+    }
+}
@@ -0,0 +1,176 @@
+use std::path::PathBuf;
+use std::time::Duration;
+use ahash::AHashSet;
+use anyhow::Result;
+use log::{debug, info, warn};
+use notify::{Event, RecursiveMode, Watcher};
+use tokio::sync::mpsc::channel;
+use tokio::time::timeout;
+use crate::config_model::{NicheTriggers, ProjectConfig, PsychotropicConfig};
+use crate::file_system::{FileSystem, PathType};
+use crate::path::AbsolutePath;
+use crate::niche::UseThundercloudDefaults;
+use crate::{get_project_config, get_project_root, get_use_thundercloud, niche, notification, process_niches, FunctionRegistry, NicheName, RunOptions};
+
+const QUIET_PERIOD: Duration = Duration::from_millis(300);
+
+/// A path that is watched, along with the niche that must be regenerated when it changes. Changes to paths without a
+/// niche (like `CargoCult.toml`) regenerate all niches.
+#[derive(Debug, Clone, PartialEq)]
+struct WatchedPath {
+    path: AbsolutePath,
+    niche: Option<NicheName>,
+}
+
+pub async fn watch<FS: FileSystem + 'static>(project_root_option: Option<PathBuf>, jobs_option: Option<usize>, run_options: &RunOptions, fs: &FS) -> Result<()> {
+    let project_root = get_project_root(project_root_option)?;
+    let (tx_event, mut rx_event) = channel(64);
+    let mut watcher = notify::recommended_watcher(move |result: notify::Result<Event>| {
+        match result {
+            Ok(event) if !event.kind.is_access() => {
+                let _ = tx_event.blocking_send(event.paths);
+            },
+            Ok(_) => (),
+            Err(err) => warn!("Error while watching: {err:?}"),
+        }
+    })?;
+
+    let mut selection = None;
+    loop {
+        let watched_paths = get_watched_paths(&project_root, fs).await?;
+        for watched_path in &watched_paths {
+            if fs.path_type(&watched_path.path).await != PathType::Missing {
+                debug!("Watch: {:?}", &watched_path);
+                watcher.watch(watched_path.path.as_path(), RecursiveMode::Recursive)?;
+            }
+        }
+
+        if selection.as_ref().map(|niches: &AHashSet<NicheName>| niches.is_empty()) != Some(true) {
+            match process_niches(project_root.clone(), jobs_option, selection, false, run_options, &FunctionRegistry::default(), fs).await {
+                Ok(report) => notification::notify(&project_root, run_options, &report, fs).await,
+                Err(err) => warn!("Error while processing niches: {err:?}"),
+            }
+        }
+        info!("Waiting for changes");
+
+        let Some(mut changed_paths) = rx_event.recv().await else { break };
+        while let Ok(Some(paths)) = timeout(QUIET_PERIOD, rx_event.recv()).await {
+            changed_paths.extend(paths);
+        }
+        debug!("Changed: {:?}", &changed_paths);
+        selection = get_affected_niches(&watched_paths, &changed_paths);
+        info!("Regenerate niches: {:?}", selection.as_ref().map(|niches| niches.iter().map(NicheName::to_str).collect::<Vec<_>>()));
+
+        for watched_path in &watched_paths {
+            let _ = watcher.unwatch(watched_path.path.as_path());
+        }
+    }
+    Ok(())
+}
+
+async fn get_watched_paths<FS: FileSystem>(project_root: &AbsolutePath, fs: &FS) -> Result<Vec<WatchedPath>> {
+    let mut watched_paths = vec![WatchedPath { path: AbsolutePath::new("CargoCult.toml", project_root), niche: None }];
+    let project_config = get_project_config(project_root, fs).await?;
+    let niches_directory = project_config.niches_directory();
+    let psychotropic = project_config.psychotropic()?;
+    let use_thundercloud_defaults = UseThundercloudDefaults::new(project_root, &project_config);
+    for niche_triggers in psychotropic.values() {
+        let niche = NicheName::new(niche_triggers.name());
+        if let Some(path) = niche_triggers.use_thundercloud_path() {
+            watched_paths.push(WatchedPath { path, niche: Some(niche.clone()) });
+        }
+        let Some(use_thundercloud) = get_use_thundercloud(Some(&niche_triggers), &use_thundercloud_defaults, fs).await? else { continue };
+        if let Some(thundercloud_directory) = niche::get_thundercloud_directory(project_root, &use_thundercloud)? {
+            watched_paths.push(WatchedPath { path: thundercloud_directory, niche: Some(niche.clone()) });
+        }
+        let invar_directory = niche::get_invar_directory(project_root, &niches_directory, &niche);
+        watched_paths.push(WatchedPath { path: invar_directory, niche: Some(niche) });
+    }
+    Ok(watched_paths)
+}
+
+/// Returns the niches that are affected by the changed paths, or `None` if all niches are affected.
+fn get_affected_niches(watched_paths: &[WatchedPath], changed_paths: &[PathBuf]) -> Option<AHashSet<NicheName>> {
+    let mut affected = AHashSet::new();
+    for changed_path in changed_paths {
+        for watched_path in watched_paths {
+            if changed_path.starts_with(watched_path.path.as_path()) {
+                if let Some(niche) = &watched_path.niche {
+                    affected.insert(niche.clone());
+                } else {
+                    return None;
+                }
+            }
+        }
+    }
+    Some(affected)
+}
+
+#[cfg(test)]
+mod test {
+    use indoc::indoc;
+    use test_log::test;
+    use crate::file_system::fixture;
+    use crate::path::test_utils::to_absolute_path;
+    use super::*;
+
+    #[test(tokio::test)]
+    async fn watched_paths() -> Result<()> {
+        // Given
+        let fs = create_file_system_fixture()?;
+
+        // When
+        let watched_paths = get_watched_paths(&AbsolutePath::root(), &fs).await?;
+
+        // Then
+        let example = Some(NicheName::new("example"));
+        let expected = vec![
+            WatchedPath { path: to_absolute_path("/CargoCult.toml"), niche: None },
+            WatchedPath { path: to_absolute_path("/yeth-marthter/example/use-thundercloud.toml"), niche: example.clone() },
+            WatchedPath { path: to_absolute_path("/example-thundercloud"), niche: example.clone() },
+            WatchedPath { path: to_absolute_path("/yeth-marthter/example/invar"), niche: example },
+        ];
+        assert_eq!(watched_paths, expected);
+        Ok(())
+    }
+
+    #[test(tokio::test)]
+    async fn affected_niches() -> Result<()> {
+        // Given
+        let fs = create_file_system_fixture()?;
+        let watched_paths = get_watched_paths(&AbsolutePath::root(), &fs).await?;
+
+        // When
+        let template_changed = get_affected_niches(&watched_paths, &[PathBuf::from("/example-thundercloud/cumulus/clock+option-glass.yaml")]);
+        let unrelated_changed = get_affected_niches(&watched_paths, &[PathBuf::from("/workshop/clock.yaml")]);
+        let project_config_changed = get_affected_niches(&watched_paths, &[PathBuf::from("/example-thundercloud/thundercloud.toml"), PathBuf::from("/CargoCult.toml")]);
+
+        // Then
+        assert_eq!(template_changed, Some(AHashSet::from([NicheName::new("example")])));
+        assert_eq!(unrelated_changed, Some(AHashSet::new()));
+        assert_eq!(project_config_changed, None);
+        Ok(())
+    }
+
+    fn create_file_system_fixture() -> Result<impl FileSystem> {
+        let toml_data = indoc! {r#"
+            "CargoCult.toml" = '''
+            [[psychotropic.cues]]
+            name = "example"
+            use-thundercloud = "/yeth-marthter/example/use-thundercloud.toml"
+            '''
+
+            [yeth-marthter.example]
+            "use-thundercloud.toml" = '''
+            directory = "{{PROJECT}}/example-thundercloud"
+            '''
+
+            [example-thundercloud]
+            "thundercloud.toml" = """
+            [niche]
+            name = "example"
+            """
+        "#};
+        fixture::from_toml(toml_data)
+    }
+}
@@ -0,0 +1,54 @@
+use anyhow::Result;
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use crate::config_model::WriteMode;
+use crate::path::AbsolutePath;
+
+/// A write mode that replaces the configured write mode of every target file, except for the files that match one of
+/// the exception patterns. Patterns are matched against paths relative to the project root.
+#[derive(Debug, Clone)]
+pub struct WriteModeOverride {
+    write_mode: WriteMode,
+    exceptions: GlobSet,
+}
+
+impl WriteModeOverride {
+    pub fn new(write_mode: WriteMode, exceptions: &[String]) -> Result<Self> {
+        let mut builder = GlobSetBuilder::new();
+        for exception in exceptions {
+            builder.add(Glob::new(exception)?);
+        }
+        Ok(WriteModeOverride { write_mode, exceptions: builder.build()? })
+    }
+
+    /// Returns the write mode for the target file, or `None` if the target file is an exception.
+    pub fn write_mode_for(&self, target: &AbsolutePath, project_root: &AbsolutePath) -> Option<WriteMode> {
+        let relative_target = target.strip_prefix(project_root.as_path()).unwrap_or(target);
+        if self.exceptions.is_match(relative_target) {
+            None
+        } else {
+            Some(self.write_mode)
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::path::test_utils::to_absolute_path;
+    use super::*;
+
+    #[test]
+    fn write_mode_with_exceptions() -> Result<()> {
+        // Given
+        let write_mode_override = WriteModeOverride::new(WriteMode::Overwrite, &["workshop/*.md".to_string()])?;
+        let project_root = to_absolute_path("/project");
+
+        // When
+        let clock = write_mode_override.write_mode_for(&to_absolute_path("/project/workshop/clock.yaml"), &project_root);
+        let readme = write_mode_override.write_mode_for(&to_absolute_path("/project/workshop/README.md"), &project_root);
+
+        // Then
+        assert_eq!(clock, Some(WriteMode::Overwrite));
+        assert_eq!(readme, None);
+        Ok(())
+    }
+}